@@ -1,11 +1,13 @@
 use std::{io, result, fmt};
 
-use crate::{MAGIC_LEN, ColTy, LitTy, CLit, AggOp, BinOp, CmpOp};
+use crate::{MAGIC_LEN, ColTy, LitTy, CLit, AggOp, BinOp, CmpOp, StrFn, DftFn, MathFn};
 
 #[derive(Debug)]
 pub struct ParserError<'a> {
   pub line: u32,
   pub col: u32,
+  // in bytes, from the start of the sql text
+  pub offset: u32,
   pub kind: ParserErrorKind<'a>,
 }
 
@@ -16,6 +18,16 @@ pub enum ParserErrorKind<'a> {
   InvalidTypeSize(&'a str),
   InvalidInt(&'a str),
   InvalidFloat(&'a str),
+  // a x'...' literal of odd length, or not a utf-8 string
+  InvalidBytes(&'a str),
+  // the unit of `interval n unit` is not day / week / month / year
+  InvalidIntervalUnit(&'a str),
+  // an interval is added to a literal that is not a date (see `date`), or the result is out of range
+  InvalidIntervalDate(&'a str),
+  // a placeholder without bound value
+  InvalidParam(&'a str),
+  // the syntax is only accepted in `Dialect::MySql`
+  MySqlOnly(&'a str),
 }
 
 #[derive(Debug)]
@@ -23,16 +35,24 @@ pub enum Error<'a> {
   ParserErrors(Box<[ParserError<'a>]>),
   InvalidSize { size: usize, expect_multiply_of: usize },
   InvalidMagic([u8; MAGIC_LEN]),
+  // the db file has a different layout, see `FORMAT_VERSION`
+  InvalidFormat { found: u16, expect: u16 },
   NoDbInUse,
-  TableExhausted,
+  DupAttach(&'a str),
+  NoSuchAttach(&'a str),
   ColTooMany(usize),
   // not support table with 0 col
   ColTooFew,
-  ColSizeTooBig(usize),
   TableNameTooLong(&'a str),
   ColNameTooLong(&'a str),
   IndexNameTooLong(&'a str),
+  // prefix index is only for char col, and the prefix should be shorter than the col
+  InvalidIndexPrefix { col: &'a str, prefix: u8 },
+  // the predicate of partial index can only be `col op lit` (lit is not null) or `col is [not] null`, and col can't be varchar
+  InvalidPartialCond(&'a str),
   DupTable(&'a str),
+  // the percent in `tablesample` should be in [0, 100]
+  InvalidSample(f32),
   DupCol(&'a str),
   DupIndex(&'a str),
   // add duplicate constraint on one col in create/alter table
@@ -42,21 +62,62 @@ pub enum Error<'a> {
   NoSuchIndex(&'a str),
   NoSuchForeign(&'a str),
   NoSuchPrimary(&'a str),
+  // select a record by a rid that is not a record of the table, e.g. it is deleted
+  NoSuchRecord { page: u32, slot: u32 },
+  // dump a page out of the file
+  NoSuchPage(u32),
+  // access a page as a type it is not used for, see `Db::page`
+  WrongPageKind { page: u32, expect: &'static str },
+  // use a handle of a table that is dropped, or whose cols are added / dropped after the handle is got, see `TableHandle`
+  StaleHandle(u32),
+  // a row given to `Eval::insert_rows` can't be serialized into the values of a record
+  InvalidRow(String),
+  // reindex a col that has no index
+  NoColIndex(&'a str),
   ForeignOnNotUnique(&'a str),
   // ModifyCol... : delete/update that actually affects data with a foreign link. so there is a concrete val
   // ModifyTable... : drop table/drop col, even no data with foreign link is affected, it is still rejected
   ModifyTableWithForeignLink(&'a str),
   ModifyColWithForeignLink { col: &'a str, val: CLit<'a> },
-  InvalidDate { date: &'a str, reason: chrono::ParseError },
+  // `expect` describes the accepted formats (see `date`)
+  InvalidDate { date: &'a str, reason: chrono::ParseError, expect: &'static str },
   InvalidLike { like: &'a str, reason: Box<regex::Error> },
   InvalidLikeTy(ColTy),
   InvalidLikeTy1(LitTy),
+  // `StrFn` is only available for char and varchar
+  InvalidStrFnTy { func: StrFn, ty: ColTy },
+  // the col in a bitwise condition like `col & mask = lit` should be int
+  InvalidBitTy { op: BinOp, ty: ColTy },
+  // see `DftFn::valid_for`
+  InvalidDftFnTy { func: DftFn, ty: ColTy },
+  // full-text index is only for char and varchar
+  InvalidFullTextTy(ColTy),
+  // `match(col) against (...)` requires a full-text index on col
+  NoFullTextIndex(&'a str),
+  // spatial index and `within` are only for float cols
+  InvalidSpatialTy(ColTy),
   // some operation on Varchar is not supported, e.g., create index (thus primary/foreign/unique are not supported, either)
   UnsupportedVarcharOp(&'a str),
-  // require them to be exactly the same (including BareTy and size, in order to search each other in index page)
+  // require them to be exactly the same (including BareTy and size, in order to search each other in index page), except the size of Char
   IncompatibleForeignTy { foreign: ColTy, own: ColTy },
   ColMismatch { l: ColTy, r: ColTy },
   ColLitMismatch { ty: ColTy, val: CLit<'a> },
+  // the 2 sides of a row-value comparison like `(a, b) < (1, 2)` should have the same number of items, so should the rows of `(values ...)` in from list
+  RowArityMismatch { cols: usize, vals: usize },
+  // a subquery used as a list of values (like in `col > all (select ...)`) should select exactly 1 col
+  SubqueryColNum(usize),
+  // the result of a derived table `(select ...) as table` can't be made into a table, `reason` is the error of making it
+  // e.g. its cols have duplicate names, which can be renamed like `(select ...) as table (col1, col2)`
+  InvalidDerived { table: &'a str, reason: String },
+  // the result of `create table table as select ...` can't be made into a table, `reason` is the error of making it, like `InvalidDerived`
+  InvalidCreateAs { table: &'a str, reason: String },
+  // the partitioning in `create table ... partition by` is invalid, `reason` tells why, e.g. the range bounds are not ascending
+  InvalidPartition { col: &'a str, reason: &'static str },
+  // `alter table drop partition` with a partition number not less than the number of partitions
+  NoSuchPartition { table: &'a str, part: u32 },
+  // add / drop col of a partitioned table (which would rewrite its data pages), or update / add a foreign key to its partition col
+  // (which would move records across partitions)
+  ModifyPartitioned(&'a str),
   // e.g.: insert (1, 2) into (int)
   InsertTooLong { max: usize, actual: usize },
   PutNullOnNotNull,
@@ -67,14 +128,31 @@ pub enum Error<'a> {
   // but for convenience, duplication in add/drop primary also uses this error (so no need to get the dup value)
   PutDupOnPrimary,
   AmbiguousCol(&'a str),
+  // `t.col` when `t` is selected from more than one attached database but not from the one in use, it should be `db.t.col`
+  AmbiguousTable(&'a str),
   // check list always rejects null (because it is meaningless)
   CheckNull(&'a str),
   CheckTooLong(&'a str),
+  // see `MAX_COMMENT`
+  CommentTooLong(&'a str),
+  NoSuchSetting(&'a str),
+  // the db has applied more migrations than given to `Eval::migrate` of the driver, i.e. it is migrated by a newer version of the application
+  SchemaVersionTooNew { version: u32, known: u32 },
+  // see `Setting::parse`
+  InvalidSettingVal { name: &'a str, val: CLit<'a> },
   InvalidAgg { col: ColTy, op: AggOp },
+  // the fraction of `percentile_cont` should be in [0, 1]
+  InvalidPercentile(f32),
   // select agg col together with non-agg col
   MixedSelect,
   IncompatibleBin { op: BinOp, ty: LitTy },
+  // the arguments of `MathFn` should be numbers
+  InvalidMathArg { func: MathFn, ty: LitTy },
+  // the seed of `rand(seed)` should be a number
+  InvalidRandSeed(LitTy),
   IncompatibleCmp { op: CmpOp, l: LitTy, r: LitTy },
+  // the arguments of a function like `coalesce` should have the same type, so should the values in one col of `(values ...)` in from list
+  IncompatibleArgs { func: &'static str, l: LitTy, r: LitTy },
   IncompatibleLogic(LitTy),
   IO(io::Error),
 }
@@ -95,4 +173,83 @@ impl fmt::Debug for ModifyError<'_> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "{:?}; {} column(s) affected", self.1, self.0)
   }
+}
+
+// a piece of the sql text that an error is about, see `Error::span`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Span {
+  // in bytes
+  pub offset: usize,
+  pub len: usize,
+  // starting from 1, `col` counts chars
+  pub line: usize,
+  pub col: usize,
+}
+
+impl Span {
+  // `offset` should be on a char boundary of `code`
+  pub fn at(code: &str, offset: usize, len: usize) -> Span {
+    let before = &code[..offset];
+    let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+    Span { offset, len, line: before.matches('\n').count() + 1, col: before[line_start..].chars().count() + 1 }
+  }
+
+  // the span of `s` if it is a slice of `code`, names and values in a statement borrow the sql text it is parsed from
+  pub fn of(code: &str, s: &str) -> Option<Span> {
+    let (start, p) = (code.as_ptr() as usize, s.as_ptr() as usize);
+    if start <= p && p + s.len() <= start + code.len() { Some(Span::at(code, p - start, s.len())) } else { None }
+  }
+}
+
+impl ParserError<'_> {
+  pub fn span(&self, code: &str) -> Span {
+    use ParserErrorKind::*;
+    match self.kind {
+      InvalidTypeSize(s) | InvalidInt(s) | InvalidFloat(s) | InvalidBytes(s) | InvalidIntervalUnit(s) | InvalidIntervalDate(s) | InvalidParam(s) | MySqlOnly(s) => Span::of(code, s),
+      _ => None,
+    }.unwrap_or_else(|| {
+      let offset = (self.offset as usize).min(code.len());
+      Span::at(code, offset, code[offset..].chars().next().map_or(0, char::len_utf8))
+    })
+  }
+}
+
+impl<'a> Error<'a> {
+  // where this error is in `code`, the sql text that the failed statement is parsed from
+  // a parser error knows its offset, other errors are located by the name / value in them, None if it doesn't come from `code`
+  pub fn span(&self, code: &str) -> Option<Span> {
+    use Error::*;
+    match *self {
+      ParserErrors(ref es) => es.first().map(|e| e.span(code)),
+      DupAttach(s) | NoSuchAttach(s) | TableNameTooLong(s) | ColNameTooLong(s) | IndexNameTooLong(s) | InvalidPartialCond(s) | DupTable(s) |
+      DupCol(s) | DupIndex(s) | DupConstraint(s) | NoSuchTable(s) | NoSuchCol(s) | NoSuchIndex(s) | NoSuchForeign(s) | NoSuchPrimary(s) |
+      NoColIndex(s) | ForeignOnNotUnique(s) | ModifyTableWithForeignLink(s) | NoFullTextIndex(s) | UnsupportedVarcharOp(s) | AmbiguousCol(s) |
+      AmbiguousTable(s) | CheckNull(s) | CheckTooLong(s) | CommentTooLong(s) | NoSuchSetting(s) | InvalidSettingVal { name: s, .. } |
+      InvalidDate { date: s, .. } | InvalidLike { like: s, .. } | InvalidIndexPrefix { col: s, .. } | InvalidDerived { table: s, .. } |
+      InvalidCreateAs { table: s, .. } | InvalidPartition { col: s, .. } | NoSuchPartition { table: s, .. } | ModifyPartitioned(s) => Span::of(code, s),
+      _ => None,
+    }
+  }
+
+  // the error, then the line of `code` it is at with carets under the span (see `span`), like:
+  // error at line 1, col 8: NoSuchCol("x")
+  //   select x from t;
+  //          ^
+  // each error of `ParserErrors` is shown in this way
+  pub fn render(&self, code: &str) -> String {
+    fn show(code: &str, span: Option<Span>, msg: &dyn fmt::Debug) -> String {
+      let span = match span { Some(span) => span, None => return format!("error: {:?}", msg) };
+      let line_start = code[..span.offset].rfind('\n').map(|x| x + 1).unwrap_or(0);
+      let line = code[line_start..].lines().next().unwrap_or("");
+      // a tab is kept so the caret is aligned with the line, the caret covers the span within this line
+      let pad = line.chars().take(span.col - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+      let end = (span.offset + span.len).min(line_start + line.len()).max(span.offset);
+      let width = code[span.offset..end].chars().count();
+      format!("error at line {}, col {}: {:?}\n  {}\n  {}{}", span.line, span.col, msg, line, pad, "^".repeat(width.max(1)))
+    }
+    match self {
+      Error::ParserErrors(es) => es.iter().map(|e| show(code, Some(e.span(code)), &e.kind)).collect::<Vec<_>>().join("\n"),
+      e => show(code, e.span(code), e),
+    }
+  }
 }
\ No newline at end of file
@@ -8,12 +8,18 @@ pub mod errors;
 
 pub use crate::{unsafe_helper::*, errors::*, ty::*};
 
+use std::cell::Cell;
+use chrono::{NaiveDate, NaiveDateTime};
+
 pub const MAGIC_LEN: usize = 18;
 pub const MAGIC: &[u8; MAGIC_LEN] = b"MashPlant-DataBase";
+// the version of the layout of all pages, stored in `DbPage::format`, a db file of another version can't be opened
+// it must be increased whenever the layout of a page changes; files made before it existed have 0 there
+pub const FORMAT_VERSION: u16 = 5;
 pub const LOB_SUFFIX: &str = "lob";
 pub const LOG_MAX_SLOT: usize = 9;
 pub const MAX_PAGE: usize = 1 << (32 - LOG_MAX_SLOT);
-pub const MAX_SLOT: usize = 1 << LOG_MAX_SLOT; // 512 (actually can hold up to MAX_DATA_BYTE / MIN_SLOT_SIZE = 507)
+pub const MAX_SLOT: usize = 1 << LOG_MAX_SLOT; // 512 (actually can hold up to MAX_DATA_BYTE / (MIN_SLOT_SIZE + 1) = 477, see `slot_cap`)
 pub const MAX_SLOT_BS: usize = MAX_SLOT / 32; // 16
 pub const MIN_SLOT_SIZE: usize = PAGE_SIZE / MAX_SLOT; // 16
 pub const MAX_DATA_BYTE: usize = PAGE_SIZE - 12 - MAX_SLOT_BS * 4; // 8116 (12 is the size of all other fields in DataPage)
@@ -25,6 +31,50 @@ pub type IndexSet<K> = indexmap::IndexSet<K, hashbrown::hash_map::DefaultHashBui
 pub type HashMap<K, V> = hashbrown::HashMap<K, V>;
 pub type HashSet<K> = hashbrown::HashSet<K>;
 
+// whether two names of tables or cols are the same, `ignore_case` is `Db::ignore_case` of the database they are in (see `Setting::IgnoreCase`)
+// names are always stored in the case they are created with
+pub fn name_eq(a: &str, b: &str, ignore_case: bool) -> bool { if ignore_case { a.eq_ignore_ascii_case(b) } else { a == b } }
+
+thread_local!(static RANDOM: Cell<Option<u64>> = Cell::new(None));
+
+// the next value in [0, 1) of the random generator (splitmix64) on this thread, used by `random()`
+// it is seeded by the randomly seeded hasher of std on first use, unless `seed_random` is called before, see `Setting::RandomSeed`
+pub fn random() -> f64 {
+  RANDOM.with(|r| {
+    let state = r.get().unwrap_or_else(|| {
+      use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}};
+      RandomState::new().build_hasher().finish()
+    });
+    r.set(Some(state.wrapping_add(0x9E3779B97F4A7C15)));
+    seeded_random(state)
+  })
+}
+
+// None makes the generator seeded randomly again on next use
+pub fn seed_random(seed: Option<u64>) { RANDOM.with(|r| r.set(seed)) }
+
+// a value in [0, 1) that only depends on `seed`, used by `rand(seed)`; `random()` right after `seed_random(Some(seed))` returns the same value
+pub fn seeded_random(seed: u64) -> f64 {
+  let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+  ((z ^ (z >> 31)) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// the formats accepted by `date`, the time (if any) is ignored, since a Date has no time
+const DATE_FORMATS: [&str; 8] = ["%Y-%m-%d", "%Y/%m/%d", "%Y-%m-%d %H:%M:%S", "%Y/%m/%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S",
+  "%Y/%m/%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y/%m/%d %H:%M"];
+pub const DATE_EXPECT: &str = "YYYY-MM-DD or YYYY/MM/DD, optionally followed by ' HH:MM[:SS]' or 'THH:MM:SS'";
+
+pub fn date(date: &str) -> Result<NaiveDate> {
+  let reason = match NaiveDate::parse_from_str(date, DATE_FORMATS[0]) { Ok(d) => return Ok(d), Err(e) => e };
+  if let Ok(d) = NaiveDate::parse_from_str(date, DATE_FORMATS[1]) { return Ok(d); }
+  for f in &DATE_FORMATS[2..] {
+    if let Ok(d) = NaiveDateTime::parse_from_str(date, f) { return Ok(d.date()); }
+  }
+  Err(Error::InvalidDate { date, reason, expect: DATE_EXPECT })
+}
+
 // save some typing
 #[macro_use]
 mod macros {
@@ -1,5 +1,5 @@
-use std::{fmt, cmp::Ordering, mem, marker::PhantomData};
-use chrono::NaiveDate;
+use std::{fmt::{self, Write}, cmp::Ordering, mem, marker::PhantomData};
+use chrono::{NaiveDate, Datelike, Duration};
 use crate::{impossible, varchar, VARCHAR_SLOT_SIZE};
 
 #[repr(u8)]
@@ -39,6 +39,15 @@ impl ColTy {
     }
   }
 
+  // whether a foreign key col of this type can reference a col of type `other`
+  // they should be the same, except that two Char can have different sizes, and their values are compared when checking the foreign key
+  pub fn foreign_compatible(self, other: ColTy) -> bool {
+    match (self, other) {
+      (ColTy::FixTy(l), ColTy::FixTy(r)) => l.ty == r.ty && (l.size == r.size || l.ty == BareTy::Char),
+      _ => false,
+    }
+  }
+
   pub fn align4(self) -> bool {
     use BareTy::*;
     match self {
@@ -92,7 +101,18 @@ impl fmt::Debug for Lit<'_> {
     use Lit::*;
     match *self {
       Null => write!(f, "null"), Bool(x) => write!(f, "{}", x), Number(x) => write!(f, "{}", x),
-      Date(x) => write!(f, "{}", x), Str(x) => write!(f, "'{}'", x)
+      Date(x) => write!(f, "{}", x),
+      Str(x) => { // escaped like a string literal, so it can be parsed back
+        f.write_char('\'')?;
+        for ch in x.chars() {
+          match ch {
+            '\'' => f.write_str("''")?, '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?, '\r' => f.write_str("\\r")?, '\t' => f.write_str("\\t")?,
+            ch => f.write_char(ch)?,
+          }
+        }
+        f.write_char('\'')
+      }
     }
   }
 }
@@ -141,25 +161,211 @@ impl fmt::Debug for CLit<'_> {
 }
 
 // Agg, Sum is available for Int, Float
-// Min, Max, Count is available for all
+// Min, Max, Count, ApproxCountDistinct is available for all
 // CountAll is special, it comes from count(*), so it doesn't have ColRef
+// ApproxCountDistinct estimates the number of distinct not-null values with HyperLogLog, the error is about 1%
+// GroupConcat joins not-null values (as text) with a separator, its separator and order are in `ast::AggArg`
+// Stddev, Variance (of a sample, so null if less than 2 values), Median, PercentileCont are available for Int, Float
+// PercentileCont interpolates between the two nearest values, its fraction is in `ast::AggArg`
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum AggOp { Avg, Sum, Min, Max, Count, CountAll }
+pub enum AggOp { Avg, Sum, Min, Max, Count, CountAll, ApproxCountDistinct, GroupConcat, Stddev, Variance, Median, PercentileCont }
 
 impl AggOp {
   pub fn name(self) -> &'static str {
     use AggOp::*;
-    match self { Avg => "avg", Sum => "sum", Min => "min", Max => "max", Count | CountAll => "count" }
+    match self {
+      Avg => "avg", Sum => "sum", Min => "min", Max => "max", Count | CountAll => "count", ApproxCountDistinct => "approx_count_distinct",
+      GroupConcat => "group_concat", Stddev => "stddev", Variance => "variance", Median => "median", PercentileCont => "percentile_cont",
+    }
+  }
+}
+
+// string functions that can appear in a condition like `lower(col) = 'abc'`, and be used to create an index on `lower(col)`
+// only ASCII letters are converted, so the length of the string is unchanged, which is required by the key in index
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum StrFn { Lower, Upper }
+
+impl StrFn {
+  pub fn name(self) -> &'static str {
+    match self { StrFn::Lower => "lower", StrFn::Upper => "upper" }
+  }
+
+  pub fn apply(self, s: &str) -> String {
+    match self { StrFn::Lower => s.to_ascii_lowercase(), StrFn::Upper => s.to_ascii_uppercase() }
+  }
+
+  pub fn apply_in_place(self, s: &mut [u8]) {
+    match self { StrFn::Lower => s.make_ascii_lowercase(), StrFn::Upper => s.make_ascii_uppercase() }
+  }
+}
+
+// numeric functions in update expressions, the arguments and the result are `Lit::Number`
+// abs, ceil, floor, round and mod of integers are integers, so they can be stored into an int col without loss; sqrt and power may not
+// `Round` takes the number of decimal places (0 if omitted, it can be negative), and rounds half away from zero
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MathFn { Abs, Round, Ceil, Floor, Mod, Power, Sqrt }
+
+impl MathFn {
+  pub fn name(self) -> &'static str {
+    use MathFn::*;
+    match self { Abs => "abs", Round => "round", Ceil => "ceil", Floor => "floor", Mod => "mod", Power => "power", Sqrt => "sqrt" }
+  }
+
+  // `y` is the second argument of `Round`, `Mod` and `Power`, it is ignored by the others
+  // NaN if the result is undefined, like sqrt(-1), mod(1, 0), or round(x, n) with a non-integer n
+  pub fn apply(self, x: f64, y: f64) -> f64 {
+    use MathFn::*;
+    match self {
+      Abs => x.abs(), Ceil => x.ceil(), Floor => x.floor(), Sqrt => x.sqrt(), Mod => x % y, Power => x.powf(y),
+      Round if y.fract() != 0.0 => std::f64::NAN,
+      Round => {
+        // dividing by 10^-y is more accurate than multiplying by 10^y for a negative y
+        let scale = 10f64.powi(y.abs() as i32);
+        let r = if y >= 0.0 { (x * scale).round() / scale } else { (x / scale).round() * scale };
+        // `scale` overflows to inf with too many decimal places (which don't change `x`) or integer places (which make it 0)
+        if r.is_finite() { r } else if y > 0.0 { x } else { 0.0 }
+      }
+    }
+  }
+}
+
+// what happens to the referencing records when a record referenced by a foreign key is deleted (or its referenced col is updated)
+// `Restrict` rejects the change, `Cascade` deletes them too (or updates their foreign key col to the new value), `SetNull` sets their foreign key col to null
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum FkAction { Restrict, Cascade, SetNull }
+
+impl FkAction {
+  // in the order of declaration, so `FkAction::ALL[action as usize] == action`
+  pub const ALL: [FkAction; 3] = [FkAction::Restrict, FkAction::Cascade, FkAction::SetNull];
+
+  pub fn name(self) -> &'static str {
+    match self { FkAction::Restrict => "restrict", FkAction::Cascade => "cascade", FkAction::SetNull => "set null" }
+  }
+}
+
+// a function as the default value of a col, it is evaluated for each inserted record that doesn't specify this col
+// `Now` is `now()` or `current_timestamp`, in a date col it is the same as `CurrentDate`, in a char col it also has the time
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DftFn { CurrentDate, Now, Uuid4 }
+
+impl DftFn {
+  // in the order of declaration, so `DftFn::ALL[f as usize] == f`
+  pub const ALL: [DftFn; 3] = [DftFn::CurrentDate, DftFn::Now, DftFn::Uuid4];
+
+  pub fn name(self) -> &'static str {
+    match self { DftFn::CurrentDate => "current_date", DftFn::Now => "now()", DftFn::Uuid4 => "uuid4()" }
+  }
+
+  // the length of its value in a char col
+  pub fn char_len(self) -> usize {
+    match self { DftFn::CurrentDate => 10, DftFn::Now => 19, DftFn::Uuid4 => 36 }
+  }
+
+  // `CurrentDate` and `Now` can be the default value of a date col, all of them can be the default value of a char col long enough
+  pub fn valid_for(self, ty: ColTy) -> bool {
+    match ty {
+      ColTy::FixTy(FixTy { ty: BareTy::Date, .. }) => self != DftFn::Uuid4,
+      ColTy::FixTy(FixTy { ty: BareTy::Char, size }) => size as usize >= self.char_len(),
+      _ => false,
+    }
+  }
+}
+
+// a runtime setting, `set name = value` (or `pragma name = value`) changes it for this session
+// `set persist name = value` also stores it in the db page, so it is applied whenever the db is used
+// every value is kept as a u32: `SortMemory` in KB, `ScanThreads` as the number of threads, `ForeignKeys` and `IgnoreCase` as 0 / 1,
+// `Dialect` as `Dialect as u32`, `RandomSeed` as the seed + 1 (0 for not seeded), `AutoAnalyze` as 0 / 1
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Setting { SortMemory, ScanThreads, ForeignKeys, Dialect, IgnoreCase, RandomSeed, AutoAnalyze }
+
+pub const SETTING_NUM: usize = 7;
+
+// the syntax accepted by the parser, see `Setting::Dialect`
+// `MySql` also accepts identifiers quoted by backticks, `limit offset, count`, and the function aliases `lcase`, `ucase`, `curdate()` and `uuid()`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Dialect { Default, MySql }
+
+impl Dialect {
+  pub fn name(self) -> &'static str {
+    match self { Dialect::Default => "default", Dialect::MySql => "mysql" }
+  }
+}
+
+impl Default for Dialect { fn default() -> Self { Dialect::Default } }
+
+impl Setting {
+  // in the order of declaration, so `Setting::ALL[s as usize] == s`
+  pub const ALL: [Setting; SETTING_NUM] = [Setting::SortMemory, Setting::ScanThreads, Setting::ForeignKeys, Setting::Dialect, Setting::IgnoreCase,
+    Setting::RandomSeed, Setting::AutoAnalyze];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Setting::SortMemory => "sort_memory", Setting::ScanThreads => "scan_threads", Setting::ForeignKeys => "foreign_keys", Setting::Dialect => "dialect",
+      Setting::IgnoreCase => "ignore_case", Setting::RandomSeed => "random_seed", Setting::AutoAnalyze => "auto_analyze",
+    }
+  }
+
+  // the settings whose value is on / off
+  fn is_bool(self) -> bool { self == Setting::ForeignKeys || self == Setting::IgnoreCase || self == Setting::AutoAnalyze }
+
+  pub fn from_name(name: &str) -> Option<Setting> {
+    Setting::ALL.iter().copied().find(|s| s.name().eq_ignore_ascii_case(name))
+  }
+
+  // None if `val` is not a valid value of this setting
+  // `SortMemory` accepts a number of bytes, or a string like '64MB' (the unit can be B, KB, MB or GB); `ForeignKeys`, `IgnoreCase` and `AutoAnalyze` accept on / off / true / false / 1 / 0
+  // `Dialect` accepts `Dialect::name`; `RandomSeed` accepts a number, or null / 'off' to make `random()` seeded randomly again
+  pub fn parse(self, val: Lit) -> Option<u32> {
+    let int = |x: f64| if x.fract() == 0.0 && (0.0..=std::u32::MAX as f64).contains(&x) { Some(x as u64) } else { None };
+    match (self, val) {
+      (Setting::SortMemory, Lit::Number(x)) => int(x).map(|b| b / 1024),
+      (Setting::SortMemory, Lit::Str(s)) => {
+        let s = s.trim().to_ascii_uppercase();
+        let num_end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let unit = match s[num_end..].trim() { "" | "B" => 1, "KB" => 1 << 10, "MB" => 1 << 20, "GB" => 1 << 30, _ => return None };
+        s[..num_end].parse::<u64>().ok().and_then(|x| x.checked_mul(unit)).map(|b| b / 1024)
+      }
+      (Setting::ScanThreads, Lit::Number(x)) => int(x),
+      (b, Lit::Bool(x)) if b.is_bool() => Some(x as u64),
+      (b, Lit::Number(x)) if b.is_bool() && (x == 0.0 || x == 1.0) => Some(x as u64),
+      (b, Lit::Str(s)) if b.is_bool() => match s.to_ascii_lowercase().as_str() { "on" | "true" => Some(1), "off" | "false" => Some(0), _ => None },
+      (Setting::RandomSeed, Lit::Number(x)) => int(x).map(|x| x + 1),
+      (Setting::RandomSeed, Lit::Null) => Some(0),
+      (Setting::RandomSeed, Lit::Str(s)) if s.eq_ignore_ascii_case("off") => Some(0),
+      (Setting::Dialect, Lit::Str(s)) => [Dialect::Default, Dialect::MySql].iter().find(|d| d.name().eq_ignore_ascii_case(s)).map(|&d| d as u64),
+      _ => None,
+    }.filter(|&x| x != 0 || (self != Setting::SortMemory && self != Setting::ScanThreads)).filter(|&x| x < std::u32::MAX as u64).map(|x| x as u32)
+  }
+
+  pub fn show(self, val: u32) -> String {
+    match self {
+      Setting::SortMemory => if val % 1024 == 0 { format!("{}MB", val / 1024) } else { format!("{}KB", val) },
+      Setting::ScanThreads => val.to_string(),
+      Setting::ForeignKeys | Setting::IgnoreCase | Setting::AutoAnalyze => (if val != 0 { "on" } else { "off" }).to_owned(),
+      Setting::Dialect => (if val == Dialect::MySql as u32 { Dialect::MySql } else { Dialect::Default }).name().to_owned(),
+      Setting::RandomSeed => if val == 0 { "off".to_owned() } else { (val - 1).to_string() },
+    }
   }
 }
 
+// BitAnd, BitOr, BitXor, Shl, Shr only accept integers, see `bit`
 #[derive(Debug, Copy, Clone)]
-pub enum BinOp { Add, Sub, Mul, Div, Mod }
+pub enum BinOp { Add, Sub, Mul, Div, Mod, BitAnd, BitOr, BitXor, Shl, Shr }
 
 impl BinOp {
-  pub fn name(self) -> char {
+  pub fn name(self) -> &'static str {
+    use BinOp::*;
+    match self { Add => "+", Sub => "-", Mul => "*", Div => "/", Mod => "%", BitAnd => "&", BitOr => "|", BitXor => "^", Shl => "<<", Shr => ">>" }
+  }
+
+  // apply a bitwise op on i32, None for an arithmetic op, or a shift amount not in [0, 32)
+  pub fn bit(self, l: i32, r: i32) -> Option<i32> {
     use BinOp::*;
-    match self { Add => '+', Sub => '-', Mul => '*', Div => '/', Mod => '%' }
+    match self {
+      BitAnd => Some(l & r), BitOr => Some(l | r), BitXor => Some(l ^ r),
+      Shl if 0 <= r && r < 32 => Some(l << r), Shr if 0 <= r && r < 32 => Some(l >> r),
+      _ => None,
+    }
   }
 }
 
@@ -167,6 +373,9 @@ impl BinOp {
 pub enum CmpOp { Lt, Le, Ge, Gt, Eq, Ne }
 
 impl CmpOp {
+  // in the order of declaration, so `CmpOp::ALL[op as usize] == op`
+  pub const ALL: [CmpOp; 6] = [CmpOp::Lt, CmpOp::Le, CmpOp::Ge, CmpOp::Gt, CmpOp::Eq, CmpOp::Ne];
+
   pub fn name(self) -> &'static str {
     use CmpOp::*;
     match self { Lt => "<", Le => "<=", Ge => ">=", Gt => ">", Eq => "==", Ne => "!=" }
@@ -177,4 +386,54 @@ impl CmpOp {
     use CmpOp::*;
     match self { Lt => Gt, Le => Ge, Ge => Le, Gt => Lt, Eq => Eq, Ne => Ne }
   }
+
+  // whether `x <op> y` holds, given `x.cmp(y) == ord`
+  pub fn test(self, ord: Ordering) -> bool {
+    use {CmpOp::*, Ordering::*};
+    match self { Lt => ord == Less, Le => ord != Greater, Ge => ord != Less, Gt => ord == Greater, Eq => ord == Equal, Ne => ord != Equal }
+  }
+}
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IntervalUnit { Day, Week, Month, Year }
+
+impl IntervalUnit {
+  pub fn name(self) -> &'static str {
+    use IntervalUnit::*;
+    match self { Day => "day", Week => "week", Month => "month", Year => "year" }
+  }
+
+  // case-insensitive, the plural is also accepted
+  pub fn from_name(s: &str) -> Option<IntervalUnit> {
+    use IntervalUnit::*;
+    let s = s.to_ascii_lowercase();
+    [Day, Week, Month, Year].iter().copied().find(|u| s == u.name() || (s.ends_with('s') && s[..s.len() - 1] == *u.name()))
+  }
+}
+
+// `interval n unit`, n may be negative
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Interval { pub n: i32, pub unit: IntervalUnit }
+
+impl Interval {
+  // None if the result is out of the range of Date
+  // adding months / years keeps the day, unless the month is shorter, e.g. 2020-01-31 + 1 month is 2020-02-29
+  pub fn add(self, d: NaiveDate) -> Option<NaiveDate> {
+    use IntervalUnit::*;
+    let months = match self.unit {
+      Day => return d.checked_add_signed(Duration::days(self.n as i64)),
+      Week => return d.checked_add_signed(Duration::weeks(self.n as i64)),
+      Month => self.n as i64,
+      Year => self.n as i64 * 12,
+    };
+    let months = d.year() as i64 * 12 + d.month0() as i64 + months;
+    let (y, m) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+    if y as i32 as i64 != y { return None; }
+    (1..=d.day()).rev().find_map(|day| NaiveDate::from_ymd_opt(y as i32, m, day))
+  }
+
+  pub fn neg(self) -> Interval { Interval { n: self.n.wrapping_neg(), ..self } }
+}
+
+impl fmt::Debug for Interval {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "interval {} {}", self.n, self.unit.name()) }
 }
\ No newline at end of file
@@ -1,3 +1,5 @@
+use std::mem::size_of;
+
 use common::{*, Error::*};
 use physics::*;
 use crate::{Db, is_null};
@@ -5,27 +7,34 @@ use crate::{Db, is_null};
 impl Db {
   // only alloc one index page for ci, records are not inserted into index (this is done by `index` crate)
   // `index` may be an empty string, this means it is an internal index (no extra operation needed)
+  // varchar col can only have a full-text index, caller should set `ColFlags::FULLTEXT` before calling it
   pub unsafe fn alloc_index<'a>(&mut self, ci: &mut ColInfo, index: &'a str) -> Result<'a, ()> {
-    debug_assert!(!ci.ty.is_varchar());
-    if index.len() > MAX_IDX_NAME { return Err(IndexNameTooLong(index)); }
-    ci.idx_name_len = index.len() as u8;
-    ci.idx_name.as_mut_ptr().copy_from_nonoverlapping(index.as_ptr(), index.len());
+    debug_assert!(!ci.ty.is_varchar() || ci.flags.contains(ColFlags::FULLTEXT));
+    set_idx_name(ci, index)?;
     let (id, ip) = self.alloc_page::<IndexPage>();
     ci.index = id;
-    ip.init(true, ci.ty.size()); // it is the root, but also a leaf
+    ip.init(true, ci.idx_key_size()); // it is the root, but also a leaf
+    Ok(())
+  }
+
+  // like `alloc_index`, but alloc one R-tree page for a spatial index on (`ci`, the `y_id`th col)
+  pub unsafe fn alloc_rtree<'a>(&mut self, ci: &mut ColInfo, y_id: u32, index: &'a str) -> Result<'a, ()> {
+    set_idx_name(ci, index)?;
+    let (id, rp) = self.alloc_page::<RTreePage>();
+    (ci.index = id, ci.idx_spatial = y_id as u8 + 1);
+    rp.init(true);
     Ok(())
   }
 
   pub fn drop_index<'a>(&mut self, index: &'a str, table: Option<&'a str>) -> Result<'a, ()> {
     unsafe {
-      for &tp_id in self.dp().tables() {
+      for tp_id in self.tables() {
         let tp = self.get_page::<TablePage>(tp_id);
         for ci in tp.cols() {
           if ci.idx_name().filter(|&x| !x.is_empty() && x == index).is_some() {
             // `table` is only for error checking
-            match table { Some(t) if t != tp.name() => return Err(NoSuchIndex(index)), _ => {} };
-            self.dealloc_index(ci.index);
-            ci.pr().index = !0;
+            match table { Some(t) if !self.name_eq(t, tp.name()) => return Err(NoSuchIndex(index)), _ => {} };
+            self.clear_index(ci.pr());
             return Ok(());
           }
         }
@@ -34,8 +43,33 @@ impl Db {
     }
   }
 
-  // only deallocate index pages, ColInfo::index is not affected
-  pub unsafe fn dealloc_index(&mut self, root: u32) {
+  // deallocate the index of `ci`, and reset all fields about index in `ci`
+  pub unsafe fn clear_index(&mut self, ci: &mut ColInfo) {
+    self.dealloc_index(ci);
+    (ci.index = !0, ci.idx_prefix = 0, ci.idx_spatial = 0, ci.flags.remove(ColFlags::DESC | ColFlags::LOWER | ColFlags::UPPER | ColFlags::FULLTEXT));
+    self.clear_partial(ci);
+  }
+
+  // remove the predicate of partial index, the check page is deallocated if it only contains the predicate
+  pub unsafe fn clear_partial(&mut self, ci: &mut ColInfo) {
+    if !ci.flags.contains(ColFlags::PARTIAL) { return; }
+    ci.flags.remove(ColFlags::PARTIAL);
+    if (ci.check & 1) == 0 && self.get_page::<CheckPage>(ci.check >> 1).count == 0 {
+      self.dealloc_page(ci.check >> 1);
+      ci.check = !0;
+    }
+  }
+
+  // return the predicate of the partial index on `ci` and the pointer to its val (see `PartialPred`)
+  pub unsafe fn partial_pred<'a>(&self, ci: &ColInfo) -> Option<(&'a mut PartialPred, *const u8)> {
+    if !ci.flags.contains(ColFlags::PARTIAL) { return None; }
+    let cp = self.pr().get_page::<CheckPage>(ci.check >> 1);
+    let p = cp.data.as_mut_ptr().add(ci.partial_off(cp));
+    Some(((p as *mut PartialPred).r(), p.add(size_of::<PartialPred>()) as *const u8))
+  }
+
+  // only deallocate index pages (B+ tree or R-tree), ColInfo::index is not affected
+  pub unsafe fn dealloc_index(&mut self, ci: &ColInfo) {
     unsafe fn dfs(db: &mut Db, page: u32) {
       let ip = db.get_page::<IndexPage>(page);
       let (slot_size, key_size) = (ip.slot_size() as usize, ip.key_size() as usize);
@@ -43,7 +77,12 @@ impl Db {
       if !ip.leaf { for i in 0..ip.count as usize { dfs(db, at_ch!(i)); } }
       db.dealloc_page(page);
     }
-    dfs(self, root);
+    unsafe fn dfs_rtree(db: &mut Db, page: u32) {
+      let rp = db.get_page::<RTreePage>(page);
+      if !rp.leaf { for e in rp.entries() { dfs_rtree(db, e.ptr); } }
+      db.dealloc_page(page);
+    }
+    if ci.idx_spatial != 0 { dfs_rtree(self, ci.index); } else { dfs(self, ci.index); }
   }
 }
 
@@ -51,37 +90,44 @@ impl Db {
   // unfortunately we don't know whether the index introduced by foreign constraint can be dropped or not, so just leave it here
   pub fn drop_foreign<'a>(&mut self, table: &'a str, col: &'a str) -> Result<'a, ()> {
     unsafe {
-      let ci = self.get_tp(table)?.1.get_ci(col)?;
+      let ci = self.get_tp(table)?.1.get_ci(col, self.ignore_case)?;
       if ci.f_table == !0 { return Err(NoSuchForeign(col)); }
-      ci.f_table = !0;
+      (ci.f_table = !0, ci.f_action = 0);
       Ok(())
     }
   }
 
   pub fn rename_table<'a>(&mut self, old: &'a str, new: &'a str) -> Result<'a, ()> {
     unsafe {
-      let tp = self.get_tp(old)?.1;
-      if new.len() > MAX_TABLE_NAME { return Err(TableNameTooLong(new)); }
-      tp.name_len = new.len() as u8;
-      tp.name.as_mut_ptr().copy_from_nonoverlapping(new.as_ptr(), new.len());
+      let (tp_id, mut tp) = self.get_tp(old)?;
+      if new.len() > MAX_NAME { return Err(TableNameTooLong(new)); }
+      let extra = table_pages(tp.col_num as usize, tp.names + long_name_bytes(new, MAX_TABLE_NAME));
+      if extra != tp.extra_pages() { tp = self.move_tp(tp_id, extra).1; } // the new name doesn't fit in the current name pages
+      tp.set_name(new);
       Ok(())
     }
   }
 }
 
 impl Db {
-  pub fn drop_table<'a>(&mut self, table: &'a str) -> Result<'a, ()> {
+  // foreign keys in the table itself never prevent dropping it
+  // if `cascade` is true, foreign keys in other tables referencing it are dropped, otherwise they reject dropping it
+  pub fn drop_table<'a>(&mut self, table: &'a str, cascade: bool) -> Result<'a, ()> {
     unsafe {
-      let dp = self.dp();
-      for (idx, &tp_id) in dp.tables().iter().enumerate() {
+      for tp_id in self.tables() {
         let tp = self.get_page::<TablePage>(tp_id);
-        if tp.name() == table {
-          if self.foreign_links_to(tp_id).next().is_some() { return Err(ModifyTableWithForeignLink(table)); }
-          let tables = dp.tables.as_mut_ptr();
-          tables.add(idx).swap(tables.add(dp.table_num as usize - 1));
-          dp.table_num -= 1;
+        if self.name_eq(tp.name(), table) {
+          let links = self.foreign_links_to(tp_id).filter(|&(tp_id1, _, _)| tp_id1 != tp_id).collect::<Vec<_>>();
+          if !links.is_empty() && !cascade { return Err(ModifyTableWithForeignLink(table)); }
+          // the index on a referencing col is left there, the same as `drop_foreign`
+          for (tp_id1, ci_id1, _) in links {
+            let ci1 = self.get_page::<TablePage>(tp_id1).cols.get_unchecked_mut(ci_id1 as usize);
+            (ci1.f_table = !0, ci1.f_action = 0);
+          }
+          self.remove_table(tp_id);
+          self.counters.tables.remove(&tp_id);
           for ci in tp.cols() {
-            if ci.index != !0 { self.dealloc_index(ci.index); }
+            if ci.index != !0 { self.dealloc_index(ci); }
             if ci.check != !0 { self.dealloc_page(ci.check >> 1); }
           }
           if tp.cols().iter().any(|ci| ci.ty.is_varchar()) {
@@ -93,7 +139,8 @@ impl Db {
               }
             }
           }
-          self.drop_list(tp.first);
+          self.drop_list(tp.first, tp.overflow());
+          (self.clear_stats(tp), self.clear_comments(tp), self.clear_partitions(tp));
           return Ok(());
         }
       }
@@ -101,12 +148,39 @@ impl Db {
     }
   }
 
-  // `pub` for `index` crate's use
-  pub unsafe fn drop_list(&mut self, mut first: u32) {
+  // move the table page `tp_id` to a new place with `extra` pages after it (see `table_pages`), return the new id
+  // the references in the db (the table directory, foreign keys and counters) are updated, but the ids held outside it are not,
+  // e.g., a `TableHandle` / `ColumnHandle` of the table returns `StaleHandle` after it, like after the table is dropped
+  // `pub` for `index` crate's use, i.e., adding a col that doesn't fit in the current col pages or name pages
+  pub unsafe fn move_tp<'a>(&mut self, tp_id: u32, extra: u32) -> (u32, &'a mut TablePage) {
+    let old = self.get_page::<TablePage>(tp_id);
+    let old_extra = old.extra_pages();
+    let (id, tp) = self.alloc_pages::<TablePage>(extra);
+    (tp as *mut TablePage as *mut u8).copy_from_nonoverlapping(old as *const TablePage as *const u8, (old_extra.min(extra) + 1) as usize * PAGE_SIZE);
+    self.replace_table(tp_id, id);
+    for tp_id1 in self.tables() {
+      for ci in self.get_page::<TablePage>(tp_id1).cols() {
+        if ci.f_table == tp_id { ci.pr().f_table = id; }
+      }
+    }
+    if let Some(c) = self.counters.tables.remove(&tp_id) { self.counters.tables.insert(id, c); }
+    self.dealloc_pages(tp_id, old_extra);
+    (id, tp)
+  }
+
+  // `pub` for `index` crate's use, `overflow` is the number of overflow pages after each data page (see `TablePage::overflow`)
+  pub unsafe fn drop_list(&mut self, mut first: u32, overflow: u32) {
     while first != !0 {
       let next = self.get_page::<DataPage>(first).next;
-      self.dealloc_page(first);
+      self.dealloc_pages(first, overflow);
       first = next;
     }
   }
+}
+
+unsafe fn set_idx_name<'a>(ci: &mut ColInfo, index: &'a str) -> Result<'a, ()> {
+  if index.len() > MAX_IDX_NAME { return Err(IndexNameTooLong(index)); }
+  ci.idx_name_len = index.len() as u8;
+  ci.idx_name.as_mut_ptr().copy_from_nonoverlapping(index.as_ptr(), index.len());
+  Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::slice;
+
+use common::{*, BareTy::*};
+use physics::*;
+use crate::{Db, is_null};
+
+// every DataPage has a Bloom filter of (col id, value) for all not-null values in it, so that a scan with `col = lit`
+// can skip the pages whose filter doesn't contain (col id, lit); the filter grows with the number of values in a page (see `bloom_bits`)
+// bits are never cleared when a record is deleted or updated, which only causes false positives,
+// and all filters are rebuilt when `alter table` rewrites the data pages
+
+// FNV-1a, followed by a finalizer to spread the bits, because bit positions are taken from both halves of the hash
+fn hash(ci_id: u32, bytes: &[u8]) -> u64 {
+  let mut h = 0xcbf29ce484222325u64;
+  for &b in ci_id.to_le_bytes().iter().chain(bytes) { h = (h ^ b as u64).wrapping_mul(0x100000001b3); }
+  h = (h ^ (h >> 33)).wrapping_mul(0xff51afd7ed558ccd);
+  h ^ (h >> 33)
+}
+
+impl Db {
+  // the hash of the not-null value at `ptr` of the `ci_id`th col, whose type is `ty`
+  // values that are equal in the sense of predicate have the same hash, so 0.0 and -0.0 are the same here
+  pub unsafe fn bloom_hash(&self, ci_id: u32, ty: ColTy, ptr: *const u8) -> u64 {
+    let f;
+    hash(ci_id, match ty {
+      float!() => {
+        let v = *(ptr as *const f32);
+        f = if v == 0.0 { 0.0f32 } else { v }.to_bits().to_le_bytes();
+        &f
+      }
+      char!() => str_from_db(ptr).as_bytes(),
+      varchar!() => self.varchar(ptr).as_bytes(),
+      _ => slice::from_raw_parts(ptr, ty.size() as usize),
+    })
+  }
+
+  // the hash to look up for `col = val` on the `ci_id`th col, None if `val` can't be converted to the col's format
+  pub unsafe fn bloom_hash_lit(&mut self, ci_id: u32, ty: ColTy, val: CLit) -> Option<u64> {
+    match (ty, val.lit()) {
+      (char!(), Lit::Str(v)) | (varchar!(), Lit::Str(v)) => Some(hash(ci_id, v.as_bytes())),
+      (varchar!(), _) => None,
+      _ => {
+        let buf = Align4U8::new(ty.size() as usize);
+        self.lit2ptr(buf.ptr, ty.fix_ty(), val).ok()?;
+        Some(self.bloom_hash(ci_id, ty, buf.ptr))
+      }
+    }
+  }
+
+  // add all not-null values of record `data` to the filter of `page`, where the record is stored
+  // for varchar cols, `data` should already hold the new VarcharSlot
+  pub unsafe fn bloom_add(&mut self, tp: &TablePage, page: u32, data: *const u8) {
+    let (dp, bits) = (self.get_page::<DataPage>(page), tp.bloom_bits());
+    if bits == 0 { return; }
+    for (ci_id, ci) in tp.cols().iter().enumerate() {
+      if !is_null(data, ci_id as u32) { dp.bloom_set(bits, self.bloom_hash(ci_id as u32, ci.ty, data.add(ci.off as usize))); }
+    }
+  }
+
+  // clear the filters of all data pages in table, and add all records again, which drops the values of deleted / updated records
+  pub unsafe fn bloom_rebuild(&mut self, tp: &TablePage) {
+    let mut page = tp.first;
+    while page != !0 {
+      let dp = self.get_page::<DataPage>(page);
+      dp.bloom_clear(tp.bloom_bits());
+      page = dp.next;
+    }
+    let db = (self as *mut Db).r();
+    for (data, rid) in self.record_iter(tp) { db.bloom_add(tp, rid.page(), data); }
+  }
+}
\ No newline at end of file
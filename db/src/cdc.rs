@@ -0,0 +1,98 @@
+use std::{collections::VecDeque, mem, sync::mpsc::{channel, Sender, Receiver}};
+use chrono::NaiveDate;
+
+use common::*;
+use physics::*;
+use crate::Db;
+
+// a value of a changed record, it is owned, so that it outlives the record
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value { Null, Bool(bool), Number(f64), Date(NaiveDate), Str(String) }
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChangeOp { Insert, Delete, Update }
+
+// a record inserted / deleted / updated by a statement, including the ones changed by the actions of foreign keys
+// every change is visible to others once it is made (a failed statement doesn't roll back the changes before the error), so they are all captured
+#[derive(Debug, Clone)]
+pub struct Change {
+  // the position in the change stream, the first change after `Db::enable_cdc` is 1, and each change is 1 after the previous one
+  // it is 0 if cdc is not enabled (the change is only watched, see `Db::watch_changes`)
+  pub pos: u64,
+  pub table: String,
+  pub op: ChangeOp,
+  // the values of all cols (in the order of the table) before the change, None for insert
+  pub old: Option<Vec<Value>>,
+  // the values of all cols after the change, None for delete
+  pub new: Option<Vec<Value>>,
+}
+
+// the captured changes of a db, they are only kept in memory, and start from position 1 every time cdc is enabled
+pub struct ChangeLog {
+  // the position of the next change
+  next: u64,
+  // at most `cap` most recent changes are kept, older ones are dropped
+  cap: usize,
+  changes: VecDeque<Change>,
+  // every change is also sent to each of them, see `Db::subscribe_changes`; a disconnected one is removed when sending to it fails
+  subscribers: Vec<Sender<Change>>,
+}
+
+impl Db {
+  // start capturing changes, keeping the most recent `cap` of them for `changes_since`; the changes captured before are discarded
+  pub fn enable_cdc(&mut self, cap: usize) {
+    self.cdc = Some(ChangeLog { next: 1, cap: cap.max(1), changes: VecDeque::new(), subscribers: vec![] });
+  }
+
+  pub fn disable_cdc(&mut self) { self.cdc = None; }
+
+  // the kept changes after position `pos` (pass 0 for all of them), in the order they were made
+  // Err(the position of the oldest kept change) if some changes after `pos` were already dropped, then the consumer can't catch up by them
+  // None if cdc is not enabled
+  pub fn changes_since(&self, pos: u64) -> Option<std::result::Result<impl Iterator<Item = &Change>, u64>> {
+    let log = self.cdc.as_ref()?;
+    let first = log.changes.front().map(|c| c.pos).unwrap_or(log.next);
+    Some(if pos + 1 < first { Err(first) } else { Ok(log.changes.iter().skip((pos + 1 - first) as usize)) })
+  }
+
+  // every change made after this is sent to the returned receiver, which can be moved to another thread to tail the changes without polling
+  // None if cdc is not enabled; disabling cdc disconnects all receivers
+  pub fn subscribe_changes(&mut self) -> Option<Receiver<Change>> {
+    let log = self.cdc.as_mut()?;
+    let (tx, rx) = channel();
+    log.subscribers.push(tx);
+    Some(rx)
+  }
+
+  // start / stop collecting the changes for `take_changes`, it is independent of cdc, see `Eval::on_change` of the driver
+  pub fn watch_changes(&mut self, on: bool) {
+    self.watched = if on { Some(self.watched.take().unwrap_or_default()) } else { None };
+  }
+
+  // the watched changes since the last call, in the order they were made
+  pub fn take_changes(&mut self) -> Vec<Change> {
+    self.watched.as_mut().map(|w| mem::replace(w, vec![])).unwrap_or_default()
+  }
+
+  // the values of all cols of the record `data` of `tp`, for `cdc_push`; None if changes are neither captured nor watched
+  pub unsafe fn cdc_record(&self, tp: &TablePage, data: *const u8) -> Option<Vec<Value>> {
+    if self.cdc.is_none() && self.watched.is_none() { return None; }
+    Some(tp.cols().iter().enumerate().map(|(ci_id, ci)| match self.data2lit(data, ci_id as u32, ci).lit() {
+      Lit::Null => Value::Null, Lit::Bool(x) => Value::Bool(x), Lit::Number(x) => Value::Number(x),
+      Lit::Date(x) => Value::Date(x), Lit::Str(s) => Value::Str(s.to_owned()),
+    }).collect())
+  }
+
+  // capture / watch a change of `tp`, `old` and `new` are made by `cdc_record`; nothing is done if changes are neither captured nor watched
+  pub unsafe fn cdc_push(&mut self, tp: &TablePage, op: ChangeOp, old: Option<Vec<Value>>, new: Option<Vec<Value>>) {
+    if self.cdc.is_none() && self.watched.is_none() { return; }
+    let change = Change { pos: self.cdc.as_ref().map(|log| log.next).unwrap_or(0), table: tp.name().to_owned(), op, old, new };
+    if let Some(w) = &mut self.watched { w.push(change.clone()); }
+    if let Some(log) = &mut self.cdc {
+      log.next += 1;
+      log.subscribers.retain(|s| s.send(change.clone()).is_ok());
+      if log.changes.len() == log.cap { log.changes.pop_front(); }
+      log.changes.push_back(change);
+    }
+  }
+}
\ No newline at end of file
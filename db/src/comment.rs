@@ -0,0 +1,62 @@
+use std::iter;
+
+use common::{*, Error::*};
+use physics::*;
+use crate::Db;
+
+impl Db {
+  // the comment of `tp` (if `ci_id` is None) or its `ci_id`th col
+  pub unsafe fn comment<'a>(&self, tp: &TablePage, ci_id: Option<u32>) -> Option<&'a str> {
+    if tp.comment == !0 { return None; }
+    let cp = self.read_page::<CommentPage>(tp.comment);
+    let slot = match ci_id { Some(ci_id) => cp.cols.get_unchecked(ci_id as usize), None => &cp.table };
+    if slot.len == 0 { None } else { Some(str_from_parts(self.read_lob(slot.lob_id), slot.len as usize)) }
+  }
+
+  // set the comment of `table` (if `col` is None) or its col `col`, an empty comment removes the old one
+  pub fn set_comment<'a>(&mut self, table: &'a str, col: Option<&'a str>, comment: &'a str) -> Result<'a, ()> {
+    unsafe {
+      let tp = self.get_tp(table)?.1;
+      let ci_id = if let Some(col) = col { Some(tp.get_ci(col, self.ignore_case)?.idx(&tp.cols)) } else { None };
+      if comment.len() > MAX_COMMENT { return Err(CommentTooLong(comment)); }
+      Ok(self.write_comment(tp, ci_id, comment))
+    }
+  }
+
+  // the same as `set_comment`, but `comment` must not be longer than MAX_COMMENT
+  pub unsafe fn write_comment(&mut self, tp: &mut TablePage, ci_id: Option<u32>, comment: &str) {
+    if tp.comment == !0 {
+      if comment.is_empty() { return; }
+      let (id, cp) = self.alloc_page::<CommentPage>();
+      (cp.init(), tp.comment = id);
+    }
+    let cp = self.get_page::<CommentPage>(tp.comment);
+    let slot = match ci_id { Some(ci_id) => cp.cols.get_unchecked_mut(ci_id as usize), None => &mut cp.table };
+    if slot.len != 0 { (self.dealloc_lob(slot.lob_id, slot.cap as u32), slot.len = 0); }
+    if !comment.is_empty() {
+      let (lob_id, cap, ptr) = self.alloc_lob(comment.len() as u32);
+      ptr.copy_from_nonoverlapping(comment.as_ptr(), comment.len());
+      *slot = VarcharSlot { lob_id, len: comment.len() as u16, cap: cap as u16 };
+    }
+  }
+
+  // the `ci_id`th col of `tp` is to be dropped (`tp.col_num` is not changed yet), the comments of later cols are moved forward
+  pub unsafe fn drop_col_comment(&mut self, tp: &mut TablePage, ci_id: u32) {
+    if tp.comment == !0 { return; }
+    self.write_comment(tp, Some(ci_id), "");
+    let cp = self.get_page::<CommentPage>(tp.comment);
+    let (ci_id, col_num) = (ci_id as usize, tp.col_num as usize);
+    cp.cols.as_mut_ptr().add(ci_id).copy_from(cp.cols.as_ptr().add(ci_id + 1), col_num - ci_id - 1);
+    cp.cols.get_unchecked_mut(col_num - 1).len = 0;
+  }
+
+  // deallocate the comment page of `tp` and all comments in it
+  pub unsafe fn clear_comments(&mut self, tp: &mut TablePage) {
+    if tp.comment == !0 { return; }
+    let cp = self.get_page::<CommentPage>(tp.comment);
+    for slot in iter::once(&cp.table).chain(cp.cols.iter()) {
+      if slot.len != 0 { self.dealloc_lob(slot.lob_id, slot.cap as u32); }
+    }
+    (self.dealloc_page(tp.comment), tp.comment = !0);
+  }
+}
\ No newline at end of file
@@ -0,0 +1,38 @@
+use common::*;
+use physics::*;
+use crate::Db;
+
+// what this session has done to a db, they start from 0 when the db is opened, and are never stored in it
+// they can be queried from the virtual tables `sys.counters` and `sys.table_counters` (see `Eval::refresh_sys`)
+#[derive(Default, Clone)]
+pub struct Counters {
+  pub pages_alloc: u64,
+  pub pages_free: u64,
+  // the scans of a table by index (including full-text / spatial indexes), and without index
+  pub index_scans: u64,
+  pub full_scans: u64,
+  // tp_id => counters of that table
+  pub tables: HashMap<u32, TableCounters>,
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct TableCounters {
+  // records visited by scans, a record rejected by the conditions not served by the scan is also counted
+  pub read: u64,
+  pub inserted: u64,
+  // including the records deleted by `on delete cascade`
+  pub deleted: u64,
+}
+
+impl Db {
+  pub fn table_counters(&mut self, tp_id: u32) -> &mut TableCounters { self.counters.tables.entry(tp_id).or_default() }
+
+  // (name, counters) of all tables, in the order of `Db::tables`
+  pub fn all_table_counters(&mut self) -> Vec<(String, TableCounters)> {
+    unsafe {
+      self.tables().map(|tp_id| {
+        (self.get_page::<TablePage>(tp_id).name().to_owned(), self.counters.tables.get(&tp_id).copied().unwrap_or_default())
+      }).collect()
+    }
+  }
+}
\ No newline at end of file
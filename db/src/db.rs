@@ -1,55 +1,127 @@
-use std::{fs::{File, OpenOptions}, path::Path};
-use memmap::{MmapOptions, MmapMut};
+use std::{fs::{File, OpenOptions}, path::Path, ptr};
 use unchecked_unwrap::UncheckedUnwrap;
 use chrono::NaiveDate;
 
 use physics::*;
 use common::{*, Error::*, BareTy::*};
 use syntax::ast::*;
+use crate::{progress::Progress, counters::Counters, cdc::{ChangeLog, Change}, vfs::{Vfs, IoMode, BLOCK, open_vfs, contiguous}, guard::PageTy};
+
+// the defaults of `Db::sort_budget`, `Db::scan_threads` and `Db::cache_blocks`
+pub const SORT_BUDGET: usize = 64 << 20;
+pub const SCAN_THREADS: usize = 1;
+pub const CACHE_BLOCKS: usize = 256;
 
 pub struct Db {
-  pub(crate) mmap: MmapMut,
-  pub(crate) file: File,
-  pub(crate) lob_mmap: MmapMut,
-  pub(crate) lob_file: File,
+  pub(crate) vfs: Box<dyn Vfs>,
+  pub(crate) lob_vfs: Box<dyn Vfs>,
+  // `Vfs::base` of them, null if the file is not contiguous in memory, then pages / lob slots are found by `Vfs::ptr`
+  pub(crate) base: *mut u8,
+  pub(crate) lob_base: *mut u8,
   pub(crate) pages: u32,
   pub(crate) lob_slots: u32,
+  pub(crate) progress: Option<Progress>,
+  // whether foreign keys are checked (and their actions are taken) when modifying records, see `Setting::ForeignKeys`
+  pub foreign_keys: bool,
+  // whether analyzed tables are analyzed again after too many changes, see `Db::refresh_stats` and `Setting::AutoAnalyze`
+  pub auto_analyze: bool,
+  // the memory budget of sorting in bytes, and the number of threads to scan a large table (1 disables parallel scans)
+  // they belong to the session, the driver sets them on the database in use, see `Setting::SortMemory` and `Setting::ScanThreads`
+  pub sort_budget: usize,
+  pub scan_threads: usize,
+  // whether names of tables and cols are matched case-insensitively, it belongs to the session like above, see `Setting::IgnoreCase`
+  pub ignore_case: bool,
+  // the number of blocks (see `BLOCK`) of each file kept in memory by `trim_cache` with `IoMode::File`
+  pub cache_blocks: usize,
+  pub counters: Counters,
+  // strings made by a query (like the result of `group_concat`), which have nowhere else to live; see `temp_str`
+  pub(crate) temp_strs: Vec<Box<str>>,
+  // the rid of the last record inserted to any table (by insert or insert batch), None if no record is inserted since it is opened
+  pub last_insert: Option<Rid>,
+  // the captured changes, None if change data capture is not enabled, see `Db::enable_cdc`
+  pub(crate) cdc: Option<ChangeLog>,
+  // the changes not taken yet, None if changes are not watched, see `Db::watch_changes`
+  pub(crate) watched: Option<Vec<Change>>,
 }
 
 impl Db {
-  pub fn create<'a>(path: impl AsRef<Path>) -> Result<'a, Db> {
+  pub fn create<'a>(path: impl AsRef<Path>) -> Result<'a, Db> { Db::create_with(path, IoMode::Auto) }
+
+  pub fn open<'a>(path: impl AsRef<Path>) -> Result<'a, Db> { Db::open_with(path, IoMode::Auto) }
+
+  // like `create`, but the files are accessed as `mode`
+  pub fn create_with<'a>(path: impl AsRef<Path>, mode: IoMode) -> Result<'a, Db> {
     unsafe {
-      let opt = OpenOptions::new().read(true).write(true).create(true).append(true).clone();
+      let opt = OpenOptions::new().read(true).write(true).create(true).clone();
       let file = opt.open(path.as_ref())?;
-      file.set_len(PAGE_SIZE as u64)?;
-      // this is 64G, the maximum capacity of this db; mmap will not allocate memory unless accessed
-      let mut mmap = MmapOptions::new().len(PAGE_SIZE * MAX_PAGE).map_mut(&file)?;
-      (mmap.as_mut_ptr() as *mut DbPage).r().init();
+      file.set_len(2 * PAGE_SIZE as u64)?; // the db page and the first `KindPage`
       let lob_file = opt.open(path.as_ref().with_extension(LOB_SUFFIX))?;
       lob_file.set_len(LOB_SLOT_SIZE as u64)?;
-      // lob file can use all the 32 bits addr space, each addr for 32 bytes, in all 128G
-      let mut lob_mmap = MmapOptions::new().len(!0u32 as usize * LOB_SLOT_SIZE).map_mut(&lob_file)?;
-      (lob_mmap.as_mut_ptr() as *mut FreeLobSlot).r().init_nil();
-      Ok(Db { mmap, file, lob_mmap, lob_file, pages: 1, lob_slots: 1 })
+      let mut db = Db::new(file, lob_file, mode)?;
+      (db.dp().init(), db.set_tag(1, TAG_KIND), (db.get_lob(0) as *mut FreeLobSlot).r().init_nil());
+      Ok(db)
     }
   }
 
-  pub fn open<'a>(path: impl AsRef<Path>) -> Result<'a, Db> {
+  // like `open`, but the files are accessed as `mode`
+  pub fn open_with<'a>(path: impl AsRef<Path>, mode: IoMode) -> Result<'a, Db> {
     unsafe {
-      let opt = OpenOptions::new().read(true).write(true).append(true).clone();
+      let opt = OpenOptions::new().read(true).write(true).clone();
       let file = opt.open(path.as_ref())?;
       let size = file.metadata()?.len() as usize;
       if size == 0 || size % PAGE_SIZE != 0 { return Err(InvalidSize { size, expect_multiply_of: PAGE_SIZE }); }
-      let mmap = MmapOptions::new().len(PAGE_SIZE * MAX_PAGE).map_mut(&file)?;
-      let dp = &*(mmap.as_ptr() as *const DbPage);
-      if &dp.magic != MAGIC { return Err(InvalidMagic(dp.magic)); }
       let lob_file = opt.open(path.as_ref().with_extension(LOB_SUFFIX))?;
       let lob_size = lob_file.metadata()?.len() as usize;
       if lob_size == 0 || lob_size % LOB_SLOT_SIZE != 0 { return Err(InvalidSize { size: lob_size, expect_multiply_of: LOB_SLOT_SIZE }); }
-      let lob_mmap = MmapOptions::new().len(!0u32 as usize * LOB_SLOT_SIZE).map_mut(&lob_file)?;
-      Ok(Db { mmap, file, lob_file, lob_mmap, pages: (size / PAGE_SIZE) as u32, lob_slots: (lob_size / LOB_SLOT_SIZE) as u32 })
+      let mut db = Db::new(file, lob_file, mode)?;
+      let magic = db.dp().magic;
+      if &magic != MAGIC { return Err(InvalidMagic(magic)); }
+      let format = db.dp().format;
+      if format != FORMAT_VERSION { return Err(InvalidFormat { found: format, expect: FORMAT_VERSION }); }
+      Ok(db)
     }
   }
+
+  unsafe fn new<'a>(file: File, lob_file: File, mode: IoMode) -> Result<'a, Db> {
+    let (pages, lob_slots) = ((file.metadata()?.len() / PAGE_SIZE as u64) as u32, (lob_file.metadata()?.len() / LOB_SLOT_SIZE as u64) as u32);
+    // the maximum capacity of this db is 64G; lob file can use all the 32 bits addr space, each addr for 32 bytes, in all 128G
+    let mut vfs = open_vfs(file, PAGE_SIZE.checked_mul(MAX_PAGE), mode)?;
+    let mut lob_vfs = open_vfs(lob_file, (!0u32 as usize).checked_mul(LOB_SLOT_SIZE), mode)?;
+    let (base, lob_base) = (vfs.base().unwrap_or(ptr::null_mut()), lob_vfs.base().unwrap_or(ptr::null_mut()));
+    Ok(Db { vfs, lob_vfs, base, lob_base, pages, lob_slots, progress: None, foreign_keys: true, auto_analyze: true,
+      sort_budget: SORT_BUDGET, scan_threads: SCAN_THREADS, ignore_case: false, cache_blocks: CACHE_BLOCKS, counters: Counters::default(), temp_strs: vec![], last_insert: None, cdc: None, watched: None })
+  }
+
+  // `IoMode::Mmap` or `IoMode::File`, how the db file is actually accessed
+  pub fn io_mode(&self) -> IoMode { if self.base.is_null() { IoMode::File } else { IoMode::Mmap } }
+
+  // write the changes to the files; with mmap the os writes them at any time, but with `IoMode::File` only this or dropping the db does
+  pub fn flush<'a>(&mut self) -> Result<'a, ()> { (self.vfs.flush()?, self.lob_vfs.flush()?, Ok(())).2 }
+
+  // like dropping the db, but the errors of writing back the changes are returned instead of only printed (see `FileVfs`)
+  pub fn close<'a>(mut self) -> Result<'a, ()> { self.flush() }
+
+  // the error met when reading the files with `IoMode::File`, the bytes that failed to be read are seen as zeroes (see `FileVfs`)
+  // the driver checks it after each statement, the result of a statement is not trusted if it fails
+  pub fn io_error<'a>(&self) -> Result<'a, ()> { (self.vfs.error()?, self.lob_vfs.error()?, Ok(())).2 }
+
+  // with `IoMode::File`, drop the least recently used blocks of the files beyond `cache_blocks`, writing back the changes in them
+  // `&mut self` guarantees that no query result borrows the pages any more, the driver calls it before each statement
+  pub fn trim_cache<'a>(&mut self) -> Result<'a, ()> { (self.vfs.trim(self.cache_blocks)?, self.lob_vfs.trim(self.cache_blocks)?, Ok(())).2 }
+}
+
+impl Db {
+  // keep `s` until `clear_temp_strs`, so that it can be returned in a query result borrowing `self`
+  pub fn temp_str(&self, s: String) -> &str {
+    unsafe {
+      let temp_strs = &mut self.pr().temp_strs;
+      temp_strs.push(s.into_boxed_str());
+      &*(&**temp_strs.last().unchecked_unwrap() as *const str)
+    }
+  }
+
+  // `&mut self` guarantees that no query result borrows the strings any more
+  pub fn clear_temp_strs(&mut self) { self.temp_strs.clear(); }
 }
 
 impl Db {
@@ -132,7 +204,7 @@ impl Db {
 
   pub unsafe fn varchar<'a>(&self, ptr: *const u8) -> &'a str {
     let v = (ptr as *const VarcharSlot).r();
-    str_from_parts(self.pr().get_lob(v.lob_id), v.len as usize)
+    str_from_parts(self.read_lob(v.lob_id), v.len as usize)
   }
 
   pub unsafe fn free_varchar(&mut self, ptr: *const u8) {
@@ -144,21 +216,26 @@ impl Db {
 impl Db {
   pub unsafe fn dp<'a>(&mut self) -> &'a mut DbPage { self.get_page::<DbPage>(0) }
 
+  // a version for a table that is never given before, see `TablePage::version`
+  pub unsafe fn new_version(&mut self) -> u32 {
+    let dp = self.dp();
+    (dp.last_version += 1, dp.last_version).1
+  }
+
   pub fn create_table<'a>(&mut self, c: &CreateTable<'a>) -> Result<'a, ()> {
     unsafe {
-      let dp = self.dp();
-
       // validate table and cols
-      if dp.table_num == MAX_TABLE as u16 { return Err(TableExhausted); }
-      if c.table.len() > MAX_TABLE_NAME { return Err(TableNameTooLong(c.table)); }
+      if c.table.len() > MAX_NAME { return Err(TableNameTooLong(c.table)); }
       if self.get_tp(c.table).is_ok() { return Err(DupTable(c.table)); }
       if c.cols.len() > MAX_COL { return Err(ColTooMany(c.cols.len())); }
       if c.cols.is_empty() { return Err(ColTooFew); }
       // its V will be used later to validate col cons, only allow one primary / foreign / check for one col
-      let mut cols = IndexMap::default();
+      let (mut cols, ignore_case) = (IndexMap::default(), self.ignore_case);
+      // the name of the declared col that `col` refers to (see `name_eq`), or `col` itself if there isn't one
+      let key = |col: &&'a str| c.cols.iter().find(|cd| name_eq(cd.col, col, ignore_case)).map_or(*col, |cd| cd.col);
       for cd in &c.cols {
-        if cols.insert(cd.col, (false, false, false, false)).is_some() { return Err(DupCol(cd.col)); }
-        if cd.col.len() > MAX_COL_NAME { return Err(ColNameTooLong(cd.col)); }
+        if cols.insert(key(&cd.col), (false, false, false, false)).is_some() { return Err(DupCol(cd.col)); }
+        if cd.col.len() > MAX_NAME { return Err(ColNameTooLong(cd.col)); }
       }
 
       // validate col cons
@@ -166,34 +243,48 @@ impl Db {
       for cons in &c.cons {
         match cons {
           ColCons::Primary(cols1) => for col in cols1 {
-            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(col) { x } else { return Err(NoSuchCol(col)); };
+            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(&key(col)) { x } else { return Err(NoSuchCol(col)); };
             if (has_pfuc.0, has_pfuc.0 = true).0 { return Err(DupConstraint(col)); }
             if c.cols.get_unchecked(idx).ty.is_varchar() { return Err(UnsupportedVarcharOp(col)); }
             primary_cnt += 1;
           }
-          ColCons::Foreign { col, f_table, f_col } => {
-            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(col) { x } else { return Err(NoSuchCol(col)); };
+          ColCons::Foreign { col, f_table, f_col, .. } => {
+            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(&key(col)) { x } else { return Err(NoSuchCol(col)); };
             if (has_pfuc.1, has_pfuc.1 = true).0 { return Err(DupConstraint(col)); }
             let cd = c.cols.get_unchecked(idx);
-            let f_tp = self.get_tp(f_table)?.1;
-            let f_ci = f_tp.get_ci(f_col)?;
-            if !f_ci.unique(f_tp.primary_cols().count()) { return Err(ForeignOnNotUnique(f_col)); }
-            debug_assert!(!f_ci.ty.is_varchar());
-            if f_ci.ty != cd.ty { return Err(IncompatibleForeignTy { foreign: f_ci.ty, own: cd.ty }); }
+            // a self-referencing foreign key, the table doesn't exist yet, so check the referenced col by `c`
+            let f_ty = if name_eq(f_table, c.table, ignore_case) {
+              let f_idx = if let Some(x) = cols.get_full(&key(f_col)) { x.0 } else { return Err(NoSuchCol(f_col)); };
+              let pks = c.cons.iter().flat_map(|cons| if let ColCons::Primary(pks) = cons { &pks[..] } else { &[] }).collect::<Vec<_>>();
+              if !c.cons.iter().any(|cons| match cons { ColCons::Unique(col) => name_eq(col, f_col, ignore_case), _ => false }) && !(pks.len() == 1 && name_eq(pks[0], f_col, ignore_case)) { return Err(ForeignOnNotUnique(f_col)); }
+              c.cols.get_unchecked(f_idx).ty
+            } else {
+              let f_tp = self.get_tp(f_table)?.1;
+              let f_ci = f_tp.get_ci(f_col, ignore_case)?;
+              if !f_ci.unique(f_tp.primary_cols().count()) { return Err(ForeignOnNotUnique(f_col)); }
+              f_ci.ty
+            };
+            if f_ty.is_varchar() { return Err(UnsupportedVarcharOp(f_col)); }
+            if !cd.ty.foreign_compatible(f_ty) { return Err(IncompatibleForeignTy { foreign: f_ty, own: cd.ty }); }
           }
           ColCons::Unique(col) => {
-            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(col) { x } else { return Err(NoSuchCol(col)); };
+            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(&key(col)) { x } else { return Err(NoSuchCol(col)); };
             if (has_pfuc.2, has_pfuc.2 = true).0 { return Err(DupConstraint(col)); }
             if c.cols.get_unchecked(idx).ty.is_varchar() { return Err(UnsupportedVarcharOp(col)); }
           }
+          ColCons::Comment(col, comment) => {
+            if !cols.contains_key(&key(col)) { return Err(NoSuchCol(col)); }
+            if comment.len() > MAX_COMMENT { return Err(CommentTooLong(comment)); }
+          }
           ColCons::Check(col, check) => {
-            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(col) { x } else { return Err(NoSuchCol(col)); };
+            let (idx, _, has_pfuc) = if let Some(x) = cols.get_full_mut(&key(col)) { x } else { return Err(NoSuchCol(col)); };
             if (has_pfuc.3, has_pfuc.3 = true).0 { return Err(DupConstraint(col)); }
             let cd = c.cols.get_unchecked(idx);
             if cd.ty.is_varchar() { return Err(UnsupportedVarcharOp(col)); }
             let sz = cd.ty.size() as usize;
-            // default value will use one slot in check page
-            if sz * (check.len() + (cd.dft.is_some() as usize)) > MAX_CHECK_BYTES { return Err(CheckTooLong(col)); }
+            // default value will use one slot in check page, unless it is a `DftFn`
+            let dft_slot = match cd.dft { Some(Dft::Lit(_)) => 1, _ => 0 };
+            if sz * (check.len() + dft_slot) > MAX_CHECK_BYTES { return Err(CheckTooLong(col)); }
             for &c in check {
               if c.is_null() { return Err(CheckNull(col)); } else { Db::lit2ptr_ck(cd.ty.fix_ty(), c)?; }
             }
@@ -201,51 +292,54 @@ impl Db {
         }
       }
       for cd in &c.cols {
-        if let Some(dft) = cd.dft {
-          if cd.ty.is_varchar() { return Err(UnsupportedVarcharOp(cd.col)); }
+        match cd.dft {
+          Some(_) if cd.ty.is_varchar() => return Err(UnsupportedVarcharOp(cd.col)),
           // you can set default = null to a notnull col, such insertion will be rejected though
-          if !dft.is_null() { Db::lit2ptr_ck(cd.ty.fix_ty(), dft)?; }
+          Some(Dft::Lit(dft)) => if !dft.is_null() { Db::lit2ptr_ck(cd.ty.fix_ty(), dft)?; }
+          Some(Dft::Fn(f)) => if !f.valid_for(cd.ty) { return Err(InvalidDftFnTy { func: f, ty: cd.ty }); }
+          None => {}
         }
       }
 
-      // validate size, the size is calculated in the same way as below
-      let mut size = (c.cols.len() + 31) / 32 * 4; // null bitset
-      for c in &c.cols { size += c.ty.size() as usize; }
-      size = (size + 3) & !3; // it should be 4-aligned to keep the alignment of the next slot
-      if size > MAX_DATA_BYTE { return Err(ColSizeTooBig(size)); }
-
       // now no error can occur, can write to db safely
 
       // handle each col def
-      let (id, tp) = self.alloc_page::<TablePage>();
+      let names = long_name_bytes(c.table, MAX_TABLE_NAME) + c.cols.iter().map(|c| long_name_bytes(c.col, MAX_COL_NAME)).sum::<u32>();
+      let (id, tp) = self.alloc_pages::<TablePage>(table_pages(c.cols.len(), names));
       let mut size = (c.cols.len() as u16 + 31) / 32 * 4; // null bitset
       for (i, c) in c.cols.iter().enumerate() {
         if c.ty.align4() { size = (size + 3) & !3; }
-        tp.cols.get_unchecked_mut(i).init(c.ty, size, c.col, c.notnull);
+        tp.cols.get_unchecked_mut(i).init(c.ty, size, c.notnull);
         size += c.ty.size();
       }
       size = (size + 3) & !3;
-      tp.init(size.max(MIN_SLOT_SIZE as u16), c.cols.len() as u8, c.table);
+      tp.init(size.max(MIN_SLOT_SIZE as u16), c.cols.len() as u8);
+      tp.version = self.new_version();
+      tp.set_name(c.table);
+      for (i, c) in c.cols.iter().enumerate() { tp.set_col_name(i, c.col); }
 
       // handle table cons
       for cons in &c.cons {
         match cons {
           ColCons::Primary(pks) => for col in pks {
-            let ci = tp.cols.get_unchecked_mut(cols.get_full(col).unchecked_unwrap().0);
+            let ci = tp.cols.get_unchecked_mut(cols.get_full(&key(col)).unchecked_unwrap().0);
             ci.flags.set(ColFlags::PRIMARY, true);
           }
-          ColCons::Foreign { col, f_table, f_col } => {
-            let ci = tp.cols.get_unchecked_mut(cols.get_full(col).unchecked_unwrap().0);
-            let (f_tp_id, f_tp) = self.get_tp(f_table).unchecked_unwrap();
-            let f_ci_id = f_tp.get_ci(f_col).unchecked_unwrap().idx(&f_tp.cols);
-            (ci.f_table = f_tp_id, ci.f_col = f_ci_id as u8);
+          ColCons::Foreign { col, f_table, f_col, on_delete, on_update } => {
+            let ci = tp.cols.get_unchecked_mut(cols.get_full(&key(col)).unchecked_unwrap().0);
+            let (f_tp_id, f_ci_id) = if name_eq(f_table, c.table, ignore_case) { (id, cols.get_full(&key(f_col)).unchecked_unwrap().0) } else {
+              let (f_tp_id, f_tp) = self.get_tp(f_table).unchecked_unwrap();
+              (f_tp_id, f_tp.get_ci(f_col, ignore_case).unchecked_unwrap().idx(&f_tp.cols))
+            };
+            (ci.f_table = f_tp_id, ci.f_col = f_ci_id as u8, ci.f_action = *on_delete as u8 | (*on_update as u8) << 2);
           }
           ColCons::Unique(col) => {
-            let ci = tp.cols.get_unchecked_mut(cols.get_full(col).unchecked_unwrap().0);
+            let ci = tp.cols.get_unchecked_mut(cols.get_full(&key(col)).unchecked_unwrap().0);
             ci.flags.set(ColFlags::UNIQUE, true);
           }
+          ColCons::Comment(col, comment) => self.write_comment(tp, Some(cols.get_full(&key(col)).unchecked_unwrap().0 as u32), comment),
           ColCons::Check(col, check) => {
-            let ci = tp.cols.get_unchecked_mut(cols.get_full(col).unchecked_unwrap().0);
+            let ci = tp.cols.get_unchecked_mut(cols.get_full(&key(col)).unchecked_unwrap().0);
             let (id, cp) = self.alloc_page::<CheckPage>();
             ci.check = id << 1;
             cp.count = check.len() as u16;
@@ -257,7 +351,8 @@ impl Db {
         }
       }
       for (idx, col) in c.cols.iter().enumerate() {
-        if let Some(dft) = col.dft {
+        if let Some(Dft::Fn(f)) = col.dft { tp.cols.get_unchecked_mut(idx).dft_fn = f as u8 + 1; }
+        if let Some(Dft::Lit(dft)) = col.dft {
           if !dft.is_null() {
             let ci = tp.cols.get_unchecked_mut(idx);
             let cp = if ci.check == !0 {
@@ -271,8 +366,7 @@ impl Db {
         }
       }
 
-      *dp.tables.get_unchecked_mut(dp.table_num as usize) = id;
-      dp.table_num += 1;
+      self.add_table(id);
       tp.cols().iter().filter(|ci| ci.unique(primary_cnt) || ci.f_table != !0).for_each(|ci| self.alloc_index(ci.pr(), "").unchecked_unwrap());
       Ok(())
     }
@@ -280,75 +374,184 @@ impl Db {
 
   // return all the (tp_id1, ci_id1, ci_id), where tp_id1.ci_id1 has foreign link to tp_id.ci_id
   pub unsafe fn foreign_links_to<'a>(&'a mut self, tp_id: u32) -> impl Iterator<Item=(u32, u8, u8)> + 'a {
-    self.dp().tables().iter().flat_map(move |&tp_id1|
+    self.tables().flat_map(move |tp_id1|
       self.get_page::<TablePage>(tp_id1).cols().iter().enumerate().filter_map(move |(ci_id1, ci1)|
         if ci1.f_table == tp_id { Some((tp_id1, ci_id1 as u8, ci1.f_col)) } else { None }))
   }
+
+  // store `val` of `s` in the db page, the caller should apply it to this session by itself
+  pub fn persist_setting(&mut self, s: Setting, val: u32) {
+    unsafe { *self.dp().settings.get_unchecked_mut(s as usize) = val; }
+  }
+
+  // see `DbPage::schema_version`
+  pub fn schema_version(&self) -> u32 { unsafe { self.pr().dp().schema_version } }
+
+  pub fn set_schema_version(&mut self, version: u32) { unsafe { self.dp().schema_version = version; } }
+
+  // the sizes of the db file and the lob file in bytes
+  pub fn file_sizes(&self) -> (u64, u64) { (self.pages as u64 * PAGE_SIZE as u64, self.lob_slots as u64 * LOB_SLOT_SIZE as u64) }
+
+  // the settings stored by `persist_setting`
+  pub fn persisted_settings(&self) -> Vec<(Setting, u32)> {
+    unsafe { Setting::ALL.iter().map(|&s| (s, *self.pr().dp().settings.get_unchecked(s as usize))).filter(|&(_, val)| val != !0).collect() }
+  }
 }
 
 impl Db {
   pub unsafe fn get_page<'a, P>(&mut self, page: u32) -> &'a mut P {
     debug_assert!(page < self.pages);
-    (self.mmap.get_unchecked_mut(page as usize * PAGE_SIZE).p() as *mut P).r()
+    let off = page as usize * PAGE_SIZE;
+    (if self.base.is_null() { self.vfs.ptr(off, true) } else { self.base.add(off) } as *mut P).r()
+  }
+
+  // like `get_page`, but the page is only read, so with `IoMode::File` its block is not written back for it (see `FileVfs`)
+  pub unsafe fn read_page<'a, P>(&self, page: u32) -> &'a P {
+    debug_assert!(page < self.pages);
+    let off = page as usize * PAGE_SIZE;
+    &*(if self.base.is_null() { self.pr().vfs.ptr(off, false) } else { self.base.add(off) } as *const P)
   }
 
-  // the return P is neither initialized nor zeroed, just keeping the original bytes
+  // the return P is neither initialized nor zeroed, just keeping the original bytes, but its tag is set to `P::TAG` (see `KindPage`)
   // allocation may not always be successful(when 64G is used up), but in most cases this error is not recoverable, so let it crash
-  pub unsafe fn alloc_page<'a, P>(&mut self) -> (u32, &'a mut P) {
+  pub unsafe fn alloc_page<'a, P: PageTy>(&mut self) -> (u32, &'a mut P) {
     let dp = self.dp();
     let free = if dp.first_free != !0 {
       let free = dp.first_free;
       dp.first_free = *self.get_page(free); // [0] stores next free(or none)
-      free
-    } else {
-      self.file.set_len((self.pages as u64 + 1) * PAGE_SIZE as u64).expect("Failed to allocate page. The database may already be in an invalid state.");
-      (self.pages, self.pages += 1).0
-    };
+      (self.counters.pages_alloc += 1, free).1
+    } else { self.append_pages(1) };
+    self.set_tag(free, P::TAG);
     (free, self.get_page(free))
   }
 
   // add `page` to the head of free list
   pub unsafe fn dealloc_page(&mut self, page: u32) {
     debug_assert!(page < self.pages);
+    self.counters.pages_free += 1;
     let dp = self.dp();
     *self.get_page::<u32>(page) = dp.first_free;
     dp.first_free = page;
+    self.set_tag(page, TAG_FREE);
+  }
+
+  // allocate a page followed by `extra` pages, so that a P larger than a page can be accessed in place, like `alloc_page`
+  // e.g., a data page with overflow pages (see `overflow_pages`), or a table page with col pages (see `col_pages`)
+  // free pages are not contiguous in general, so if `extra` != 0, they are all newly appended to the file; the `extra` pages are tagged `TAG_EXTRA`
+  pub unsafe fn alloc_pages<'a, P: PageTy>(&mut self, extra: u32) -> (u32, &'a mut P) {
+    if extra == 0 { return self.alloc_page(); }
+    let id = self.append_pages(extra + 1);
+    self.set_tag(id, P::TAG);
+    for page in id + 1..=id + extra { self.set_tag(page, TAG_EXTRA); }
+    (id, self.get_page(id))
+  }
+
+  // append `n` pages to the file and return the first one, they never cross a block boundary (see `contiguous`) and are never a `KindPage`
+  // the kind pages skipped for it are initialized (a new page is zeroed, so all pages of its group are free), the other pages skipped go to the free list
+  unsafe fn append_pages(&mut self, n: u32) -> u32 {
+    let block = (BLOCK / PAGE_SIZE) as u32;
+    assert!(n <= block, "Failed to allocate {} contiguous pages. The database may already be in an invalid state.", n);
+    let mut id = self.pages;
+    loop {
+      if !contiguous(id as usize * PAGE_SIZE, (id + n) as usize * PAGE_SIZE) { id = (id / block + 1) * block; }
+      else if let Some(kp) = (id..id + n).find(|&p| KindPage::is_kind_page(p)) { id = kp + 1; }
+      else { break; }
+    }
+    self.vfs.grow(self.pages as usize * PAGE_SIZE, (id + n) as usize * PAGE_SIZE).expect("Failed to allocate page. The database may already be in an invalid state.");
+    let old = (self.pages, self.pages = id + n).0;
+    self.counters.pages_alloc += (id + n - old) as u64;
+    for page in old..id {
+      if KindPage::is_kind_page(page) { self.set_tag(page, TAG_KIND); } else { self.dealloc_page(page); }
+    }
+    id
+  }
+
+  // the tag of what `page` is used for, see `KindPage`
+  pub fn page_tag(&self, page: u32) -> u8 {
+    if page == 0 { return TAG_HEADER; }
+    let (kp, idx) = KindPage::of(page);
+    unsafe { *self.read_page::<KindPage>(kp).tags.get_unchecked(idx) }
+  }
+
+  unsafe fn set_tag(&mut self, page: u32, tag: u8) {
+    let (kp, idx) = KindPage::of(page);
+    *self.get_page::<KindPage>(kp).tags.get_unchecked_mut(idx) = tag;
+  }
+
+  // free the pages allocated by `alloc_pages` with the same `extra`, they go to the free list one by one
+  pub unsafe fn dealloc_pages(&mut self, page: u32, extra: u32) {
+    for i in 0..=extra { self.dealloc_page(page + i); }
   }
 
+  pub fn name_eq(&self, a: &str, b: &str) -> bool { name_eq(a, b, self.ignore_case) }
+
   // for convenience, the index of TablePage is returned (because it cannot be obtained by `idx`)
   pub unsafe fn get_tp<'a, 'b>(&mut self, table: &'b str) -> Result<'b, (u32, &'a mut TablePage)> {
-    for &tp_id in self.dp().tables() {
+    for tp_id in self.tables() {
       let tp = self.get_page::<TablePage>(tp_id);
-      if tp.name() == table { return Ok((tp_id, tp)); }
+      if self.name_eq(tp.name(), table) { return Ok((tp_id, tp)); }
     }
     Err(NoSuchTable(table))
   }
 
-  pub unsafe fn alloc_data_slot(&mut self, tp_id: u32) -> Rid {
+  // allocate a slot in a page of partition `part` (see `Db::partition_of`, always 0 if the table is not partitioned)
+  pub unsafe fn alloc_data_slot(&mut self, tp_id: u32, part: u16) -> Rid {
     let tp = self.get_page::<TablePage>(tp_id);
-    if tp.first_free == !0 {
-      let (id, dp) = self.alloc_page::<DataPage>();
-      (dp.init(tp.first), tp.first = id); // push front, so insert order may not be kept
-      tp.first_free = id;
+    let first_free = self.free_list(tp, part);
+    if *first_free == !0 {
+      let (id, dp) = self.alloc_pages::<DataPage>(tp.overflow());
+      (dp.init(tp.first, part, tp.bloom_bits()), tp.first = id); // push front, so insert order may not be kept
+      *first_free = id;
     }
-    let free = tp.first_free;
+    let free = *first_free;
     let dp = self.get_page::<DataPage>(free);
     debug_assert!(dp.count < tp.cap);
     let slot = (0..tp.cap as usize).filter_map(|i| {
       if bsget(dp.used.as_ptr(), i) { None } else { (bsset(dp.used.as_mut_ptr(), i), Some(i)).1 }
     }).next().unchecked_unwrap() as u32;
     dp.count += 1;
-    if dp.count == tp.cap { tp.first_free = dp.next_free; }
+    if dp.count == tp.cap { *first_free = dp.next_free; }
     Rid::new(free, slot)
   }
 
+  // like calling `alloc_data_slot` for each of `parts`, but each page with free slots is filled in one pass over its `used` bitset
+  pub unsafe fn alloc_data_slots(&mut self, tp_id: u32, parts: &[u16]) -> Vec<Rid> {
+    let tp = self.get_page::<TablePage>(tp_id);
+    let mut ret = vec![Rid::new(!0, 0); parts.len()];
+    let mut distinct = parts.to_vec();
+    (distinct.sort_unstable(), distinct.dedup());
+    for part in distinct {
+      let first_free = self.free_list(tp, part);
+      let mut idx = parts.iter().enumerate().filter(|&(_, &p)| p == part).map(|(idx, _)| idx).peekable();
+      while idx.peek().is_some() {
+        if *first_free == !0 {
+          let (id, dp) = self.alloc_pages::<DataPage>(tp.overflow());
+          (dp.init(tp.first, part, tp.bloom_bits()), tp.first = id);
+          *first_free = id;
+        }
+        let free = *first_free;
+        let dp = self.get_page::<DataPage>(free);
+        for i in 0..tp.cap as usize {
+          if idx.peek().is_none() || dp.count == tp.cap { break; }
+          if !bsget(dp.used.as_ptr(), i) {
+            (bsset(dp.used.as_mut_ptr(), i), dp.count += 1);
+            *ret.get_unchecked_mut(idx.next().unchecked_unwrap()) = Rid::new(free, i as u32);
+          }
+        }
+        if dp.count == tp.cap { *first_free = dp.next_free; }
+      }
+    }
+    ret
+  }
+
   pub unsafe fn dealloc_data_slot(&mut self, tp: &mut TablePage, rid: Rid) {
     let (page, slot) = (rid.page(), rid.slot());
     let dp = self.get_page::<DataPage>(page);
     debug_assert!(bsget(dp.used.as_ptr(), slot as usize));
     bsdel(dp.used.as_mut_ptr(), slot as usize);
     if dp.count == tp.cap { // not in free list, add it
-      (dp.next_free = tp.first_free, tp.first_free = page);
+      let first_free = self.free_list(tp, dp.part);
+      (dp.next_free = *first_free, *first_free = page);
     }
     // it is never given back to db, for simplicity (this enables calling `dealloc_data_slot` during iteration)
     dp.count -= 1;
@@ -357,4 +560,9 @@ impl Db {
   pub unsafe fn get_data_slot(&mut self, tp: &TablePage, rid: Rid) -> *mut u8 {
     self.get_page::<DataPage>(rid.page()).data.as_mut_ptr().add((rid.slot() * tp.size as u32) as usize)
   }
+
+  // like `get_data_slot`, but the record is only read (see `read_page`)
+  pub unsafe fn read_data_slot(&self, tp: &TablePage, rid: Rid) -> *const u8 {
+    self.read_page::<DataPage>(rid.page()).data.as_ptr().add((rid.slot() * tp.size as u32) as usize)
+  }
 }
\ No newline at end of file
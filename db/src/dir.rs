@@ -0,0 +1,63 @@
+use std::iter;
+
+use common::*;
+use physics::*;
+use crate::Db;
+
+impl Db {
+  // the ids of all table pages, `DbPage::tables` first, then the `DirPage`s in the chain
+  pub unsafe fn tables<'a>(&self) -> impl Iterator<Item=u32> + 'a {
+    let db = self.p();
+    let dp = db.r().read_page::<DbPage>(0);
+    let n = dp.table_num as usize;
+    let dirs = iter::successors(Some(dp.dir).filter(|&p| p != !0), move |&p| Some(db.r().read_page::<DirPage>(p).next).filter(|&p| p != !0));
+    dp.tables.get_unchecked(..n.min(MAX_TABLE)).iter()
+      .chain(dirs.enumerate().flat_map(move |(k, p)| db.r().read_page::<DirPage>(p).tables.get_unchecked(..(n - MAX_TABLE - k * DIR_TABLES).min(DIR_TABLES))))
+      .copied()
+  }
+
+  // append `tp_id` to the table ids, a new `DirPage` is allocated if the last one is full
+  pub unsafe fn add_table(&mut self, tp_id: u32) {
+    let dp = self.dp();
+    let n = dp.table_num as usize;
+    if n >= MAX_TABLE && (n - MAX_TABLE) % DIR_TABLES == 0 {
+      let (id, dir) = self.alloc_page::<DirPage>();
+      dir.next = !0;
+      *self.dir_link((n - MAX_TABLE) / DIR_TABLES) = id;
+    }
+    *self.table_slot(n) = tp_id;
+    dp.table_num += 1;
+  }
+
+  // remove `tp_id` from the table ids, the last id takes its place; the last `DirPage` is deallocated if it becomes empty
+  pub unsafe fn remove_table(&mut self, tp_id: u32) {
+    let dp = self.dp();
+    let n = dp.table_num as usize;
+    let idx = self.tables().position(|x| x == tp_id).unwrap_or(n - 1); // it is in the table ids in all uses
+    *self.table_slot(idx) = *self.table_slot(n - 1);
+    dp.table_num -= 1;
+    if n - 1 >= MAX_TABLE && (n - 1 - MAX_TABLE) % DIR_TABLES == 0 {
+      let link = self.dir_link((n - 1 - MAX_TABLE) / DIR_TABLES);
+      (self.dealloc_page(*link), *link = !0);
+    }
+  }
+
+  // replace `old` with `new` in the table ids, keeping its position
+  pub unsafe fn replace_table(&mut self, old: u32, new: u32) {
+    if let Some(idx) = self.tables().position(|x| x == old) { *self.table_slot(idx) = new; }
+  }
+
+  // the `idx`-th table id, it should be < `DbPage::table_num`, or be the next one with its page allocated
+  unsafe fn table_slot<'a>(&mut self, idx: usize) -> &'a mut u32 {
+    if idx < MAX_TABLE { return self.dp().tables.get_unchecked_mut(idx); }
+    let (k, i) = ((idx - MAX_TABLE) / DIR_TABLES, (idx - MAX_TABLE) % DIR_TABLES);
+    self.get_page::<DirPage>(*self.dir_link(k)).tables.get_unchecked_mut(i)
+  }
+
+  // the place storing the id of the `k`-th `DirPage`, i.e., `DbPage::dir` or `next` of the previous one
+  unsafe fn dir_link<'a>(&mut self, k: usize) -> &'a mut u32 {
+    let mut link = &mut self.dp().dir;
+    for _ in 0..k { link = &mut self.get_page::<DirPage>(*link).next; }
+    link
+  }
+}
@@ -0,0 +1,188 @@
+use std::fmt::Write;
+use unchecked_unwrap::UncheckedUnwrap;
+
+use common::{*, Error::*};
+use physics::*;
+use crate::Db;
+
+// what a page is used for, see `Db::page_kind`; `start` is the first page of a structure taking several contiguous pages
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PageKind {
+  Header,
+  // a `KindPage`
+  Kind,
+  Free,
+  // the `k`-th page in the chain of `DirPage`
+  Dir { k: u32 },
+  // the table page, or one of the pages after it holding its cols and long names
+  Table { tp_id: u32 },
+  // a data page, or one of its overflow pages
+  Data { tp_id: u32, start: u32 },
+  Stats { tp_id: u32 },
+  Hist { tp_id: u32, ci_id: u32 },
+  Comment { tp_id: u32 },
+  Part { tp_id: u32 },
+  Check { tp_id: u32, ci_id: u32 },
+  // a node of the B+tree / R-tree of the index on a col
+  Index { tp_id: u32, ci_id: u32 },
+  RTree { tp_id: u32, ci_id: u32 },
+  // not used by any structure, e.g., it is leaked
+  Unknown,
+}
+
+// a page id, or "none" for !0
+fn link(page: u32) -> String { if page == !0 { "none".to_owned() } else { page.to_string() } }
+
+impl Db {
+  // find what `page` is used for by walking all structures from the db page, they are assumed to be valid
+  // (a db that may be corrupt should be checked by `index::check_db` first)
+  pub unsafe fn page_kind(&self, page: u32) -> PageKind {
+    use PageKind::*;
+    let db = self.pr();
+    if page == 0 { return Header; }
+    if KindPage::is_kind_page(page) { return Kind; }
+    let mut p = db.dp().first_free;
+    while p != !0 {
+      if p == page { return Free; }
+      p = *db.read_page::<u32>(p);
+    }
+    let (mut p, mut k) = (db.dp().dir, 0);
+    while p != !0 {
+      if p == page { return Dir { k }; }
+      (p = db.read_page::<DirPage>(p).next, k += 1);
+    }
+    for tp_id in db.tables() {
+      let tp = db.read_page::<TablePage>(tp_id);
+      if (tp_id..=tp_id + tp.extra_pages()).contains(&page) { return Table { tp_id }; }
+      let mut p = tp.first;
+      while p != !0 {
+        if (p..=p + tp.overflow()).contains(&page) { return Data { tp_id, start: p }; }
+        p = db.read_page::<DataPage>(p).next;
+      }
+      if tp.stats != !0 {
+        let sp = db.read_page::<StatsPage>(tp.stats);
+        if (tp.stats..=tp.stats + sp.col_pages()).contains(&page) { return Stats { tp_id }; }
+        if let Some(ci_id) = sp.pr().cols_mut().iter().position(|cs| cs.hist == page) { return Hist { tp_id, ci_id: ci_id as u32 }; }
+      }
+      if tp.comment == page { return Comment { tp_id }; }
+      if tp.part == page { return Part { tp_id }; }
+      for (ci_id, ci) in tp.cols().iter().enumerate() {
+        let ci_id = ci_id as u32;
+        if ci.check != !0 && ci.check >> 1 == page { return Check { tp_id, ci_id }; }
+        if ci.index != !0 && self.index_pages(ci).contains(&page) {
+          return if ci.idx_spatial != 0 { RTree { tp_id, ci_id } } else { Index { tp_id, ci_id } };
+        }
+      }
+    }
+    Unknown
+  }
+
+  // all nodes of the index on `ci`, like `dealloc_index`
+  unsafe fn index_pages(&self, ci: &ColInfo) -> Vec<u32> {
+    unsafe fn dfs(db: &mut Db, page: u32, spatial: bool, pages: &mut Vec<u32>) {
+      pages.push(page);
+      if spatial {
+        let rp = db.read_page::<RTreePage>(page);
+        if !rp.leaf { for e in rp.entries() { dfs(db, e.ptr, spatial, pages); } }
+      } else {
+        let ip = db.read_page::<IndexPage>(page);
+        let (slot_size, key_size) = (ip.slot_size() as usize, ip.key_size() as usize);
+        macro_rules! at_ch { ($pos: expr) => { *(ip.data.as_ptr().add($pos * slot_size + key_size) as *const u32) }; }
+        if !ip.leaf { for i in 0..ip.count as usize { dfs(db, at_ch!(i), spatial, pages); } }
+      }
+    }
+    let mut pages = vec![];
+    dfs(self.pr(), ci.index, ci.idx_spatial != 0, &mut pages);
+    pages
+  }
+
+  // describe `page` by what it is used for (see `page_kind`), e.g., the cols of a table page, the used slots and records of a data page,
+  // or the keys and children of an index page, for debugging storage bugs without a hex editor
+  pub fn dump_page<'a>(&self, page: u32) -> Result<'a, String> {
+    use PageKind::*;
+    unsafe {
+      if page >= self.pages { return Err(NoSuchPage(page)); }
+      let (db, mut s) = (self.pr(), String::new());
+      macro_rules! w { ($($arg: tt)*) => { writeln!(s, $($arg)*).unchecked_unwrap() }; }
+      let kind = self.page_kind(page);
+      let table = |tp_id: u32| self.read_page::<TablePage>(tp_id);
+      let col = |tp_id: u32, ci_id: u32| table(tp_id).cols.get_unchecked(ci_id as usize);
+      match kind {
+        Header => {
+          let dp = db.dp();
+          w!("page {}: db page, table count = {}, first free = {}, dir = {}, schema version = {}", page, dp.table_num, link(dp.first_free), link(dp.dir), dp.schema_version);
+          for (idx, &tp_id) in dp.tables.iter().take((dp.table_num as usize).min(MAX_TABLE)).enumerate() { w!("  - table {}: page {}", idx, tp_id); }
+        }
+        Kind => w!("page {}: the tags of pages {} to {}", page, page, page as usize + KIND_SPAN - 1),
+        Free => w!("page {}: free, next free = {}", page, link(*db.read_page::<u32>(page))),
+        Dir { k } => {
+          let dir = db.read_page::<DirPage>(page);
+          let base = MAX_TABLE + k as usize * DIR_TABLES;
+          w!("page {}: table directory {}, next = {}", page, k, link(dir.next));
+          for (idx, &tp_id) in dir.tables.iter().take((db.dp().table_num as usize - base).min(DIR_TABLES)).enumerate() { w!("  - table {}: page {}", base + idx, tp_id); }
+        }
+        Table { tp_id } if tp_id != page => w!("page {}: col page {} of table `{}` (page {})", page, page - tp_id, table(tp_id).name(), tp_id),
+        Table { tp_id } => {
+          let tp = table(tp_id);
+          w!("page {}: table `{}`, record size = {}, slots per page = {}, record count = {}, first = {}, first free = {}, stats = {}, comment = {}, partitions = {}",
+            page, tp.name(), tp.size, tp.cap, tp.count, link(tp.first), link(tp.first_free), link(tp.stats), link(tp.comment), link(tp.part));
+          for (idx, ci) in tp.cols().iter().enumerate() {
+            write!(s, "  - col {}: `{}`: {:?} @ offset +{}, flags = {:?}, index = {}, check = {}", idx, ci.name(), ci.ty, ci.off, ci.flags, link(ci.index),
+              link(if ci.check == !0 { !0 } else { ci.check >> 1 })).unchecked_unwrap();
+            if ci.f_table != !0 { write!(s, ", foreign = page {} col {}", ci.f_table, ci.f_col).unchecked_unwrap(); }
+            s.push('\n');
+          }
+        }
+        Data { tp_id, start } if start != page => w!("page {}: overflow page {} of data page {} of `{}`", page, page - start, start, table(tp_id).name()),
+        Data { tp_id, .. } => {
+          let (tp, dp) = (table(tp_id), db.read_page::<DataPage>(page));
+          w!("page {}: data of `{}`, next = {}, next free = {}, count = {}, partition = {}", page, tp.name(), link(dp.next), link(dp.next_free), dp.count, dp.part);
+          w!("  - used: {}", (0..tp.cap as usize).map(|i| if bsget(dp.used.as_ptr(), i) { '1' } else { '0' }).collect::<String>());
+          for slot in (0..tp.cap as usize).filter(|&i| bsget(dp.used.as_ptr(), i)) {
+            let data = dp.data.as_ptr().add(slot * tp.size as usize);
+            let vals = tp.cols().iter().enumerate().map(|(ci_id, ci)| format!("{:?}", self.data2lit(data, ci_id as u32, ci))).collect::<Vec<_>>();
+            w!("  - slot {}: ({})", slot, vals.join(", "));
+          }
+        }
+        Stats { tp_id } => w!("page {}: statistics of `{}`", page, table(tp_id).name()),
+        Hist { tp_id, ci_id } => {
+          let hp = db.read_page::<HistPage>(page);
+          w!("page {}: histogram of `{}.{}`, buckets = {}, bounds = {:?}", page, table(tp_id).name(), col(tp_id, ci_id).name(), hp.buckets, hp.bounds());
+        }
+        Comment { tp_id } => w!("page {}: comments of `{}`", page, table(tp_id).name()),
+        Part { tp_id } => {
+          let pp = db.read_page::<PartPage>(page);
+          w!("page {}: partitions of `{}` by {} of col {}, count = {}", page, table(tp_id).name(), if pp.hash { "hash" } else { "range" }, pp.ci_id, pp.count);
+          for (idx, &free) in pp.free.iter().take(pp.count as usize).enumerate() { w!("  - partition {}: first free = {}", idx, link(free)); }
+        }
+        Check { tp_id, ci_id } => w!("page {}: checks of `{}.{}`, count = {}", page, table(tp_id).name(), col(tp_id, ci_id).name(), db.read_page::<CheckPage>(page).count),
+        Index { tp_id, ci_id } => {
+          let (ci, ip) = (col(tp_id, ci_id), db.read_page::<IndexPage>(page));
+          let (slot_size, key_size, rid_off) = (ip.slot_size() as usize, ip.key_size() as usize, ip.rid_off as usize);
+          w!("page {}: {} of the index on `{}.{}`, count = {}, cap = {}, key size = {}, next = {}", page, if ip.leaf { "leaf" } else { "inner node" },
+            table(tp_id).name(), ci.name(), ip.count, ip.cap, key_size, link(ip.next));
+          for idx in 0..ip.count as usize {
+            let key = ip.data.as_ptr().add(idx * slot_size);
+            let val = if ci.flags.contains(ColFlags::FULLTEXT) { format!("{:?}", str_from_db(key)) } else { format!("{:?}", self.ptr2lit(key, ci.ty)) };
+            write!(s, "  - slot {}: key = {}, rid = {:?}", idx, val, *(key.add(rid_off) as *const Rid)).unchecked_unwrap();
+            if !ip.leaf { write!(s, ", child = {}", *(key.add(key_size) as *const u32)).unchecked_unwrap(); }
+            s.push('\n');
+          }
+        }
+        RTree { tp_id, ci_id } => {
+          let rp = db.read_page::<RTreePage>(page);
+          w!("page {}: {} of the spatial index on `{}.{}`, count = {}", page, if rp.leaf { "leaf" } else { "inner node" }, table(tp_id).name(), col(tp_id, ci_id).name(), rp.count);
+          for (idx, e) in rp.entries().iter().enumerate() {
+            if rp.leaf {
+              w!("  - entry {}: point = ({}, {}), rid = {:?}", idx, e.rect.min_x, e.rect.min_y, Rid::new(e.ptr >> LOG_MAX_SLOT, e.ptr & (MAX_SLOT as u32 - 1)));
+            } else {
+              w!("  - entry {}: rect = {:?}, child = {}", idx, e.rect, e.ptr);
+            }
+          }
+        }
+        Unknown => w!("page {}: not used by anything", page),
+      }
+      Ok((s.pop(), s).1)
+    }
+  }
+}
@@ -0,0 +1,127 @@
+use std::{marker::PhantomData, ops::{Deref, DerefMut}};
+
+use common::{*, Error::*};
+use physics::*;
+use crate::Db;
+
+// a type of page that can be allocated by `Db::alloc_page` and accessed by `Db::page`, the page is tagged `TAG` (see `KindPage`)
+pub trait PageTy {
+  const NAME: &'static str;
+  const TAG: u8;
+}
+
+macro_rules! page_ty {
+  ($ty: ident, $name: expr, $tag: expr) => {
+    impl PageTy for $ty {
+      const NAME: &'static str = $name;
+      const TAG: u8 = $tag;
+    }
+  };
+}
+
+page_ty!(DbPage, "db page", TAG_HEADER);
+page_ty!(DirPage, "table directory", TAG_DIR);
+// the pages after a table page holding its cols and long names are `TAG_EXTRA`, so are the overflow pages of a data page
+page_ty!(TablePage, "table page", TAG_TABLE);
+page_ty!(DataPage, "data page", TAG_DATA);
+page_ty!(StatsPage, "statistics page", TAG_STATS);
+page_ty!(HistPage, "histogram page", TAG_HIST);
+page_ty!(CommentPage, "comment page", TAG_COMMENT);
+page_ty!(PartPage, "partition page", TAG_PART);
+page_ty!(CheckPage, "check page", TAG_CHECK);
+page_ty!(IndexPage, "index page", TAG_INDEX);
+page_ty!(RTreePage, "spatial index page", TAG_RTREE);
+
+// a page borrowed from a db by `Db::page` / `Db::table`, it can't outlive the db, and the db can't be modified while it is alive
+pub struct PageRef<'a, P> {
+  db: &'a Db,
+  page: u32,
+  _p: PhantomData<&'a P>,
+}
+
+// a page mutably borrowed from a db by `Db::page_mut` / `Db::table_mut`, the db can't be used in other ways while it is alive
+pub struct PageMut<'a, P> {
+  db: &'a mut Db,
+  page: u32,
+  _p: PhantomData<&'a mut P>,
+}
+
+impl<'a, P> PageRef<'a, P> {
+  // the caller guarantees `page` is a `P`
+  pub(crate) fn new(db: &'a Db, page: u32) -> PageRef<'a, P> { PageRef { db, page, _p: PhantomData } }
+
+  pub fn id(&self) -> u32 { self.page }
+}
+
+impl<'a, P> PageMut<'a, P> {
+  pub(crate) fn new(db: &'a mut Db, page: u32) -> PageMut<'a, P> { PageMut { db, page, _p: PhantomData } }
+
+  pub fn id(&self) -> u32 { self.page }
+}
+
+impl<P> Deref for PageRef<'_, P> {
+  type Target = P;
+  fn deref(&self) -> &P { unsafe { self.db.read_page(self.page) } }
+}
+
+// only `deref_mut` accesses the page for writing (see `Db::read_page`)
+impl<P> Deref for PageMut<'_, P> {
+  type Target = P;
+  fn deref(&self) -> &P { unsafe { self.db.read_page(self.page) } }
+}
+
+impl<P> DerefMut for PageMut<'_, P> {
+  fn deref_mut(&mut self) -> &mut P { unsafe { self.db.get_page(self.page) } }
+}
+
+// the accessors of pages that are unsafe because they return unbound lifetimes, here they are bound to the guard
+macro_rules! safe_accessors {
+  ($guard: ident) => {
+    impl $guard<'_, TablePage> {
+      pub fn name(&self) -> &str { unsafe { (**self).name() } }
+      pub fn cols(&self) -> &[ColInfo] { unsafe { (**self).cols() } }
+      // the name of col `ci_id`, None if there is no such col
+      pub fn col_name(&self, ci_id: usize) -> Option<&str> { self.cols().get(ci_id).map(|ci| unsafe { ci.name() }) }
+    }
+
+    impl $guard<'_, StatsPage> {
+      pub fn cols(&self) -> &[ColStats] { unsafe { (**self).pr().cols_mut() } }
+    }
+
+    impl $guard<'_, HistPage> {
+      pub fn bounds(&self) -> &[f64] { unsafe { (**self).bounds() } }
+    }
+  };
+}
+
+safe_accessors!(PageRef);
+safe_accessors!(PageMut);
+
+impl Db {
+  // `page` as a `P`, it is checked to be in the file and tagged as a `P` (see `KindPage`), which takes O(1)
+  pub fn page<'a, 'b, P: PageTy>(&'a self, page: u32) -> Result<'b, PageRef<'a, P>> {
+    self.check_page::<P>(page)?;
+    Ok(PageRef::new(self, page))
+  }
+
+  pub fn page_mut<'a, 'b, P: PageTy>(&'a mut self, page: u32) -> Result<'b, PageMut<'a, P>> {
+    self.check_page::<P>(page)?;
+    Ok(PageMut::new(self, page))
+  }
+
+  // the table page of `table`, only the table directory is searched, so unlike `TableHandle::page` the page kind is not checked again
+  pub fn table<'a, 'b>(&'a self, table: &'b str) -> Result<'b, PageRef<'a, TablePage>> {
+    let tp_id = unsafe { self.pr().get_tp(table)?.0 };
+    Ok(PageRef::new(self, tp_id))
+  }
+
+  pub fn table_mut<'a, 'b>(&'a mut self, table: &'b str) -> Result<'b, PageMut<'a, TablePage>> {
+    let tp_id = unsafe { self.get_tp(table)?.0 };
+    Ok(PageMut::new(self, tp_id))
+  }
+
+  pub(crate) fn check_page<'b, P: PageTy>(&self, page: u32) -> Result<'b, ()> {
+    if page >= self.pages { return Err(NoSuchPage(page)); }
+    if self.page_tag(page) == P::TAG { Ok(()) } else { Err(WrongPageKind { page, expect: P::NAME }) }
+  }
+}
@@ -0,0 +1,103 @@
+use common::{*, Error::*};
+use physics::*;
+use crate::{Db, PageRef, PageMut, RecordIter, is_null};
+
+// a table of a db by the id of its table page, like the ids kept by `Index`; unlike a `&mut TablePage` it doesn't borrow the db,
+// so handles of several tables (or several handles of the same table) can be held together, and the page is resolved on every access,
+// with a lifetime bound to the borrow of the db passed in
+// a handle is only valid for the db it comes from, until the table is dropped (or moved, see `Db::move_tp`) or its cols are added / dropped
+// it keeps the version of the table (see `TablePage::version`), which is checked with the tag of the page (see `KindPage`) on every access in O(1),
+// so an invalid handle gives `StaleHandle`, even if its page is reused by another table
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TableHandle {
+  tp_id: u32,
+  version: u32,
+}
+
+// a col of a table, `ci_id` is its index in the cols of the table, it is valid as long as the handle of its table is
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ColumnHandle {
+  table: TableHandle,
+  ci_id: u32,
+}
+
+impl TableHandle {
+  // the id of the table page
+  pub fn id(self) -> u32 { self.tp_id }
+
+  pub fn page<'b>(self, db: &Db) -> Result<'b, PageRef<TablePage>> { Ok(PageRef::new(db, self.resolve(db)?)) }
+
+  pub fn page_mut<'b>(self, db: &mut Db) -> Result<'b, PageMut<TablePage>> {
+    let tp_id = self.resolve(db)?;
+    Ok(PageMut::new(db, tp_id))
+  }
+
+  fn resolve<'b>(self, db: &Db) -> Result<'b, u32> {
+    let valid = db.check_page::<TablePage>(self.tp_id).is_ok() && unsafe { db.read_page::<TablePage>(self.tp_id).version } == self.version;
+    if valid { Ok(self.tp_id) } else { Err(StaleHandle(self.tp_id)) }
+  }
+
+  pub fn name<'b>(self, db: &Db) -> Result<'b, &str> { Ok(unsafe { db.read_page::<TablePage>(self.resolve(db)?).name() }) }
+
+  pub fn col<'b>(self, db: &Db, col: &'b str) -> Result<'b, ColumnHandle> {
+    match self.page(db)?.cols().iter().position(|ci| db.name_eq(unsafe { ci.name() }, col)) {
+      Some(ci_id) => Ok(ColumnHandle { table: self, ci_id: ci_id as u32 }),
+      None => Err(NoSuchCol(col)),
+    }
+  }
+
+  pub fn cols<'b>(self, db: &Db) -> Result<'b, impl Iterator<Item=ColumnHandle>> {
+    Ok((0..self.page(db)?.col_num as u32).map(move |ci_id| ColumnHandle { table: self, ci_id }))
+  }
+
+  // the records of the table and their rids, like `Db::record_iter`, `db` is borrowed until the iteration ends
+  pub fn records<'a, 'b>(self, db: &'a mut Db) -> Result<'b, RecordIter<'a>> {
+    let tp = unsafe { db.get_page::<TablePage>(self.resolve(db)?) };
+    Ok(unsafe { db.record_iter(tp) })
+  }
+}
+
+impl ColumnHandle {
+  pub fn table(self) -> TableHandle { self.table }
+
+  // the index of the col in the cols of its table
+  pub fn id(self) -> u32 { self.ci_id }
+
+  // the cols are not changed while the version of the table is the same, so `ci_id` is in range if the table handle is valid
+  pub fn info<'b>(self, db: &Db) -> Result<'b, &ColInfo> {
+    let tp_id = self.table.resolve(db)?;
+    Ok(unsafe { db.read_page::<TablePage>(tp_id).cols.get_unchecked(self.ci_id as usize) })
+  }
+
+  pub fn info_mut<'b>(self, db: &mut Db) -> Result<'b, &mut ColInfo> {
+    let tp_id = self.table.resolve(db)?;
+    Ok(unsafe { db.get_page::<TablePage>(tp_id).cols.get_unchecked_mut(self.ci_id as usize) })
+  }
+
+  pub fn name<'b>(self, db: &Db) -> Result<'b, &str> { Ok(unsafe { self.info(db)?.name() }) }
+
+  // the col referenced by this foreign key col, None if it is not a foreign key
+  pub fn foreign<'b>(self, db: &Db) -> Result<'b, Option<ColumnHandle>> {
+    let ci = self.info(db)?;
+    if ci.f_table == !0 { return Ok(None); }
+    let version = unsafe { db.read_page::<TablePage>(ci.f_table).version };
+    Ok(Some(ColumnHandle { table: TableHandle { tp_id: ci.f_table, version }, ci_id: ci.f_col as u32 }))
+  }
+
+  // the value of this col in `data`, a record of the table from `TableHandle::records`, None if it is null
+  pub unsafe fn value<'a, 'b>(self, db: &Db, data: *const u8) -> Result<'b, Option<CLit<'a>>> {
+    let ci = self.info(db)?;
+    Ok(if is_null(data, self.ci_id) { None } else { Some(db.ptr2lit(data.add(ci.off as usize), ci.ty)) })
+  }
+}
+
+impl Db {
+  pub fn table_handle<'b>(&self, table: &'b str) -> Result<'b, TableHandle> {
+    let (tp_id, tp) = unsafe { self.pr().get_tp(table)? };
+    Ok(TableHandle { tp_id, version: tp.version })
+  }
+
+  pub fn table_handles(&self) -> impl Iterator<Item=TableHandle> + '_ {
+    unsafe { self.tables().map(move |tp_id| TableHandle { tp_id, version: self.read_page::<TablePage>(tp_id).version }) }
+  }
+}
@@ -4,7 +4,13 @@ use crate::Db;
 
 impl Db {
   pub unsafe fn record_iter<'a>(&mut self, tp: &TablePage) -> RecordIter<'a> {
-    RecordIter { db: self.pr(), page: tp.first, slot: 0, size: tp.size, cap: tp.cap }
+    self.record_iter_bloom(tp, vec![], !0)
+  }
+
+  // like `record_iter`, but skip the pages whose Bloom filter doesn't contain all of `hashes` (see `Db::bloom_add`),
+  // and the pages of partitions not in the bitset `parts` (see `Db::part_mask`)
+  pub unsafe fn record_iter_bloom<'a>(&mut self, tp: &TablePage, hashes: Vec<u64>, parts: u64) -> RecordIter<'a> {
+    RecordIter { db: self.pr(), page: tp.first, slot: 0, size: tp.size, cap: tp.cap, bits: tp.bloom_bits(), hashes, parts }
   }
 }
 
@@ -14,6 +20,10 @@ pub struct RecordIter<'a> {
   slot: u16,
   size: u16,
   cap: u16,
+  // the bits of Bloom filter (see `bloom_bits`)
+  bits: usize,
+  hashes: Vec<u64>,
+  parts: u64,
 }
 
 impl Iterator for RecordIter<'_> {
@@ -25,10 +35,15 @@ impl Iterator for RecordIter<'_> {
         if self.page == !0 { return None; }
         // now self.page must be a valid data page id
         let dp = self.db.get_page::<DataPage>(self.page);
+        if self.slot == 0 && (self.parts >> dp.part & 1 == 0 || !self.hashes.iter().all(|&h| dp.bloom_test(self.bits, h))) {
+          self.page = dp.next;
+          continue;
+        }
         for i in self.slot as usize..self.cap as usize {
           if bsget(dp.used.as_ptr(), i) {
             self.slot = i as u16 + 1;
             let data = dp.data.as_mut_ptr().add(i * self.size as usize);
+            self.db.progress_tick();
             return Some((data, Rid::new(self.page, i as u32)));
           }
         }
@@ -6,26 +6,74 @@ pub mod iter;
 pub mod alter;
 pub mod show;
 pub mod lob;
+pub mod bloom;
+pub mod stats;
+pub mod progress;
+pub mod comment;
+pub mod counters;
+pub mod partition;
+pub mod cdc;
+pub mod dir;
+pub mod dump;
+pub mod guard;
+pub mod handle;
+pub mod vfs;
 
-pub use crate::{db::*, iter::*, lob::*, show::*};
+pub use crate::{db::*, iter::*, lob::*, show::*, progress::*, counters::*, cdc::*, dump::*, guard::*, handle::*, vfs::*};
+pub use common::{date, DATE_EXPECT};
+pub use physics::Rid;
 
 use regex::Regex;
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}};
 
 use common::{*, Error::*, BareTy::*};
-use chrono::NaiveDate;
+use chrono::{NaiveDate, Local};
 use physics::ColInfo;
 
 // `data` points to the beginning of the whole data slot
 pub unsafe fn is_null(data: *const u8, ci_id: u32) -> bool { bsget(data as *const u32, ci_id as usize) }
 
-pub fn date(date: &str) -> Result<NaiveDate> {
-  NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|reason| InvalidDate { date, reason })
-}
-
 pub fn like2re(like: &str) -> Result<Regex> {
   Regex::new(&escape_re(like)).map_err(|e| InvalidLike { like, reason: box e })
 }
 
+// a value of col type `from` at `ptr`, as a value of col type `to`, they are `foreign_compatible`
+// if they are Char of different sizes, the value is copied to `buf`, and the returned pointer points into it, otherwise it is `ptr` itself
+// None if the value is longer than the size of `to`, so it can't be a value of `to`
+pub unsafe fn coerce(ptr: *const u8, from: ColTy, to: ColTy, buf: &mut Option<Align4U8>) -> Option<*const u8> {
+  if from == to { return Some(ptr); }
+  let (len, size) = (*ptr as usize, to.size() as usize);
+  if len + 1 > size { return None; }
+  let b = Align4U8::new((size + 3) & !3); // index may read the key with padding
+  b.ptr.copy_from_nonoverlapping(ptr, len + 1);
+  let p = b.ptr;
+  (*buf = Some(b), Some(p as *const u8)).1
+}
+
+// write the value of `f` for a new record to `ptr`, the col type `ty` is `DftFn::valid_for`
+pub unsafe fn eval_dft_fn(ptr: *mut u8, ty: FixTy, f: DftFn) {
+  let now = Local::now().naive_local();
+  if ty.ty == Date { return (ptr as *mut NaiveDate).write(now.date()); }
+  let s = match f {
+    DftFn::CurrentDate => now.format("%Y-%m-%d").to_string(),
+    DftFn::Now => now.format("%Y-%m-%d %H:%M:%S").to_string(),
+    DftFn::Uuid4 => uuid4(),
+  };
+  *ptr = s.len() as u8;
+  ptr.add(1).copy_from_nonoverlapping(s.as_ptr(), s.len());
+}
+
+// a random (version 4) uuid, the random bits come from the randomly seeded hasher of std, which is different for each call
+fn uuid4() -> String {
+  let mut r = [0u8; 16];
+  for half in r.chunks_mut(8) {
+    half.copy_from_slice(&RandomState::new().build_hasher().finish().to_le_bytes());
+  }
+  (r[6] = (r[6] & 0x0f) | 0x40, r[8] = (r[8] & 0x3f) | 0x80); // version and variant
+  let hex = r.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+  format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
 pub unsafe fn hash_pks(data: *const u8, pks: &[&ColInfo]) -> u128 {
   const SEED: u128 = 19260817;
   let mut hash = 0u128;
@@ -1,65 +1,79 @@
 use common::*;
 use physics::*;
-use crate::Db;
+use crate::{Db, vfs::{BLOCK, contiguous}};
 
 impl Db {
   pub unsafe fn get_lob(&mut self, id: u32) -> *mut u8 {
-    (self.lob_mmap.as_mut_ptr() as *mut FreeLobSlot).add(id as usize) as *mut u8
+    let off = id as usize * LOB_SLOT_SIZE;
+    if self.lob_base.is_null() { self.lob_vfs.ptr(off, true) } else { self.lob_base.add(off) }
   }
 
+  // like `get_lob`, but the slots are only read, like `Db::read_page`
+  pub unsafe fn read_lob(&self, id: u32) -> *const u8 {
+    let off = id as usize * LOB_SLOT_SIZE;
+    if self.lob_base.is_null() { self.pr().lob_vfs.ptr(off, false) } else { self.lob_base.add(off) }
+  }
+
+  unsafe fn lob_slot<'a>(&mut self, id: u32) -> &'a mut FreeLobSlot { (self.get_lob(id) as *mut FreeLobSlot).r() }
+
   // return (lob id, actual bytes allocated, start addr of lob), lob id can be used for get & dealloc
   pub unsafe fn alloc_lob(&mut self, count: u32) -> (u32, u32, *mut u8) {
     let count = ((count + LOB_SLOT_SIZE as u32 - 1) / LOB_SLOT_SIZE as u32).max(1); // .max(1) to avoid alloc 0 uses the nil node
-    let base = self.lob_mmap.as_mut_ptr() as *mut FreeLobSlot;
-    let mut x = base.r();
+    let (mut x_id, mut x) = (0, self.lob_slot(0));
     while x.count < count {
-      if x.next == 0 { break; } else { x = base.add(x.next as usize).r(); }
+      if x.next == 0 { break; } else { x_id = x.next; x = self.lob_slot(x_id); }
     }
     if x.count >= count {
-      if x.count > count { self.shift_lob_link(x, count); } else {
+      if x.count > count { self.shift_lob_link(x_id, count); } else {
         let (prev, next) = (x.prev, x.next);
-        base.add(prev as usize).r().next = next;
-        base.add(next as usize).r().prev = prev;
+        self.lob_slot(prev).next = next;
+        self.lob_slot(next).prev = prev;
       }
-      (x.p().offset_from(base) as u32, count * 32, x.p() as *mut u8)
+      (x_id, count * 32, x.p() as *mut u8)
     } else { // get out of `while` because of `break`
-      let id = (self.lob_slots, self.lob_slots += count).0;
-      self.lob_file.set_len(self.lob_slots as u64 * LOB_SLOT_SIZE as u64).expect("failed to allocate lob slot. the database may already be in an invalid state.");
-      (id, count * 32, base.add(id as usize) as *mut u8)
+      // the slots never cross a block boundary (see `contiguous`), the ones skipped for it become a free node
+      let (block, old) = ((BLOCK / LOB_SLOT_SIZE) as u32, self.lob_slots);
+      debug_assert!(count <= block);
+      let pad = if contiguous(old as usize * LOB_SLOT_SIZE, (old + count) as usize * LOB_SLOT_SIZE) { 0 } else { block - old % block };
+      let id = (old + pad, self.lob_slots += pad + count).0;
+      self.lob_vfs.grow(old as usize * LOB_SLOT_SIZE, self.lob_slots as usize * LOB_SLOT_SIZE).expect("failed to allocate lob slot. the database may already be in an invalid state.");
+      if pad != 0 { self.dealloc_lob(old, pad * LOB_SLOT_SIZE as u32); }
+      (id, count * 32, self.get_lob(id))
     }
   }
 
   pub unsafe fn dealloc_lob(&mut self, id: u32, count: u32) {
     debug_assert!(count != 0 && count % LOB_SLOT_SIZE as u32 == 0);
     let count = count / LOB_SLOT_SIZE as u32;
-    let base = self.lob_mmap.as_mut_ptr() as *mut FreeLobSlot;
-    let (mut x_id, mut x) = (0, base.r());
+    // a free node is allocated in place as a whole, so 2 nodes are merged only if they are in one block (see `contiguous`)
+    let contiguous = |from: u32, to: u32| contiguous(from as usize * LOB_SLOT_SIZE, to as usize * LOB_SLOT_SIZE);
+    let (mut x_id, mut x) = (0, self.lob_slot(0));
     loop {
-      if x_id + x.count == id {
+      if x_id + x.count == id && contiguous(x_id, id + count) {
         return x.count += count;
-      } else if id + count == x_id {
-        return self.shift_lob_link(x, !count + 1); // !count + 1 == -count
+      } else if id + count == x_id && contiguous(id, x_id + x.count) {
+        return self.shift_lob_link(x_id, !count + 1); // !count + 1 == -count
       } else {
         x_id = x.next;
-        if x_id == 0 { break; } else { x = base.add(x_id as usize).r(); }
+        if x_id == 0 { break; } else { x = self.lob_slot(x_id); }
       }
     }
     // fails to extend any existing nodes, add to back
-    let nil = base.r();
-    let prev = base.add(nil.prev as usize).r();
-    let new = base.add(id as usize).r();
+    let nil = self.lob_slot(0);
+    let prev = self.lob_slot(nil.prev);
+    let new = self.lob_slot(id);
     (prev.next = id, new.prev = nil.prev);
     (new.next = 0, nil.prev = id);
     new.count = count;
   }
 
   // `shift as i32` can be negative
-  unsafe fn shift_lob_link(&mut self, x: &FreeLobSlot, shift: u32) {
-    let base = self.lob_mmap.as_mut_ptr() as *mut FreeLobSlot;
-    let (prev, next, new_x_id) = (x.prev, x.next, (x.p().offset_from(base) as u32).wrapping_add(shift));
-    let new_x = base.add(new_x_id as usize).r();
-    (base.add(prev as usize).r().next = new_x_id, new_x.prev = prev);
-    (base.add(next as usize).r().prev = new_x_id, new_x.next = next);
+  unsafe fn shift_lob_link(&mut self, x_id: u32, shift: u32) {
+    let x = self.lob_slot(x_id);
+    let (prev, next, new_x_id) = (x.prev, x.next, x_id.wrapping_add(shift));
+    let new_x = self.lob_slot(new_x_id);
+    (self.lob_slot(prev).next = new_x_id, new_x.prev = prev);
+    (self.lob_slot(next).prev = new_x_id, new_x.next = next);
     new_x.count = x.count.wrapping_sub(shift);
   }
 }
\ No newline at end of file
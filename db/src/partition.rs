@@ -0,0 +1,135 @@
+use unchecked_unwrap::UncheckedUnwrap;
+use std::cmp::Ordering;
+
+use common::{*, Error::*, CmpOp::*};
+use syntax::ast::*;
+use physics::*;
+use crate::{Db, is_null};
+
+// the bitset of partitions 0..n
+fn first_parts(n: u16) -> u64 { if n as usize >= MAX_PART { !0 } else { (1 << n) - 1 } }
+
+impl Db {
+  // create the table of `c`, whose records are assigned to partitions by `p` (see `PartPage`)
+  pub fn create_partitioned_table<'a>(&mut self, c: &CreateTable<'a>, p: &Partition<'a>) -> Result<'a, ()> {
+    unsafe {
+      let err = |reason| Err(InvalidPartition { col: p.col, reason });
+      let ty = match c.cols.iter().find(|cd| self.name_eq(cd.col, p.col)) { Some(cd) => cd.ty, None => return Err(NoSuchCol(p.col)) };
+      if ty.is_varchar() { return Err(UnsupportedVarcharOp(p.col)); }
+      if c.cons.iter().any(|cons| if let ColCons::Foreign { col, .. } = cons { self.name_eq(col, p.col) } else { false }) {
+        return err("the partition col can't be a foreign key, whose actions may change it");
+      }
+      let count = if p.hash {
+        match p.vals.as_slice() {
+          [n] => match n.lit() {
+            Lit::Number(n) if n.fract() == 0.0 && n >= 1.0 && n <= MAX_PART as f64 => n as u16,
+            _ => return err("the number of hash partitions should be an integer in [1, MAX_PART]"),
+          }
+          _ => return err("hash partitioning takes only the number of partitions"),
+        }
+      } else {
+        if p.vals.len() >= MAX_PART { return err("range partitioning takes at most MAX_PART - 1 bounds"); }
+        p.vals.len() as u16 + 1
+      };
+      let (size, bounds) = (ty.size() as usize, if p.hash { &[][..] } else { &p.vals[..] });
+      if size * bounds.len() > MAX_PART_BYTES { return err("the bounds are too long to fit in a page"); }
+      let buf = Align4U8::new((size * bounds.len()).max(1));
+      for (i, &val) in bounds.iter().enumerate() {
+        if val.is_null() { return err("a bound can't be null"); }
+        self.lit2ptr(buf.ptr.add(i * size), ty.fix_ty(), val)?;
+        if i > 0 && self.ptr2lit(buf.ptr.add((i - 1) * size), ty).cmp(self.ptr2lit(buf.ptr.add(i * size), ty)) != Ordering::Less {
+          return err("the bounds should be strictly ascending");
+        }
+      }
+      self.create_table(c)?;
+      // now no error can occur
+      let tp = self.get_tp(c.table).unchecked_unwrap().1;
+      let ci_id = tp.get_ci(p.col, self.ignore_case).unchecked_unwrap().idx(&tp.cols);
+      let (id, pp) = self.alloc_page::<PartPage>();
+      pp.init(ci_id as u8, p.hash, count);
+      pp.bounds.as_mut_ptr().copy_from_nonoverlapping(buf.ptr, size * bounds.len());
+      tp.part = id;
+      Ok(())
+    }
+  }
+
+  pub unsafe fn part_page<'a>(&self, tp: &TablePage) -> Option<&'a mut PartPage> {
+    if tp.part == !0 { None } else { Some(self.pr().get_page::<PartPage>(tp.part)) }
+  }
+
+  // the id of the partition col of `tp`, None if it is not partitioned
+  pub unsafe fn part_col(&self, tp: &TablePage) -> Option<u32> { self.part_page(tp).map(|pp| pp.ci_id as u32) }
+
+  // the number of partitions of `tp`, 1 if it is not partitioned
+  pub unsafe fn part_count(&self, tp: &TablePage) -> u16 { self.part_page(tp).map_or(1, |pp| pp.count) }
+
+  // the head of the list of pages with free slots of partition `part` in `tp`
+  pub unsafe fn free_list<'a>(&self, tp: &mut TablePage, part: u16) -> &'a mut u32 {
+    match self.part_page(tp) {
+      Some(pp) => pp.free.get_unchecked_mut(part as usize),
+      None => &mut tp.pr().first_free,
+    }
+  }
+
+  // the partition of the record `data` of `tp`, 0 if `tp` is not partitioned
+  pub unsafe fn partition_of(&self, tp: &TablePage, data: *const u8) -> u16 {
+    let pp = match self.part_page(tp) { Some(pp) => pp, None => return 0 };
+    if is_null(data, pp.ci_id as u32) { return 0; }
+    let ci = tp.cols.get_unchecked(pp.ci_id as usize);
+    let ptr = data.add(ci.off as usize);
+    if pp.hash { (self.bloom_hash(pp.ci_id as u32, ci.ty, ptr) % pp.count as u64) as u16 } else { self.range_part(pp, ci.ty, self.ptr2lit(ptr, ci.ty)) }
+  }
+
+  // the number of bounds that are <= `val`
+  unsafe fn range_part(&self, pp: &PartPage, ty: ColTy, val: CLit) -> u16 {
+    let size = ty.size() as usize;
+    (0..pp.count as usize - 1).take_while(|&i| self.ptr2lit(pp.bound(i, size), ty).cmp(val) != Ordering::Greater).count() as u16
+  }
+
+  // the bitset of partitions of `tp` that may contain a record satisfying `col op val`, where col is the partition col
+  pub unsafe fn part_mask(&mut self, tp: &TablePage, op: CmpOp, val: CLit) -> u64 {
+    let pp = match self.part_page(tp) { Some(pp) => pp, None => return !0 };
+    let (all, ci_id) = (first_parts(pp.count), pp.ci_id as u32);
+    let ty = tp.cols.get_unchecked(ci_id as usize).ty;
+    let buf = Align4U8::new(ty.size() as usize);
+    if val.is_null() || self.lit2ptr(buf.ptr, ty.fix_ty(), val).is_err() { return all; }
+    if pp.hash { return if op == Eq { 1 << (self.bloom_hash(ci_id, ty, buf.ptr) % pp.count as u64) } else { all }; }
+    let part = self.range_part(pp, ty, self.ptr2lit(buf.ptr, ty));
+    match op {
+      Eq => 1 << part,
+      Lt | Le => first_parts(part + 1),
+      Gt | Ge => all & !first_parts(part),
+      Ne => all,
+    }
+  }
+
+  // free the data pages of partition `part` of `tp`, all records in them should have been deleted
+  pub unsafe fn drop_partition_pages(&mut self, tp: &mut TablePage, part: u16) {
+    *self.free_list(tp, part) = !0;
+    let (mut prev, mut page) = (None::<&mut DataPage>, tp.first);
+    while page != !0 {
+      let dp = self.get_page::<DataPage>(page);
+      let next = dp.next;
+      if dp.part == part {
+        debug_assert_eq!(dp.count, 0);
+        match prev.as_mut() { Some(prev) => prev.next = next, None => tp.first = next }
+        self.dealloc_pages(page, tp.overflow());
+      } else { prev = Some(dp); }
+      page = next;
+    }
+  }
+
+  // make `to` (which has no record) partitioned in the same way as `from`
+  pub unsafe fn copy_partitions(&mut self, from: &TablePage, to: &mut TablePage) {
+    if let Some(pp) = self.part_page(from) {
+      let (id, new_pp) = self.alloc_page::<PartPage>();
+      new_pp.init(pp.ci_id, pp.hash, pp.count);
+      new_pp.bounds.copy_from_slice(&pp.bounds);
+      to.part = id;
+    }
+  }
+
+  pub unsafe fn clear_partitions(&mut self, tp: &mut TablePage) {
+    if tp.part != !0 { (self.dealloc_page(tp.part), tp.part = !0); }
+  }
+}
\ No newline at end of file
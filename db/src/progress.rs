@@ -0,0 +1,46 @@
+use crate::Db;
+
+// what a long operation is doing when it reports progress
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProgressOp {
+  // visiting records in select / update / delete
+  Scan,
+  // inserting records
+  Insert,
+  // visiting records to build (or rebuild) indexes, including index creation, reindex and altering table
+  IndexBuild,
+}
+
+// a callback invoked every `every` rows, with the operation and the number of rows processed in this statement
+pub struct Progress {
+  every: u64,
+  count: u64,
+  op: ProgressOp,
+  f: Box<dyn FnMut(ProgressOp, u64)>,
+}
+
+impl Db {
+  pub fn set_progress(&mut self, every: u64, f: impl FnMut(ProgressOp, u64) + 'static) {
+    self.progress = Some(Progress { every: every.max(1), count: 0, op: ProgressOp::Scan, f: box f });
+  }
+
+  pub fn clear_progress(&mut self) { self.progress = None; }
+
+  // called at the beginning of a statement (by `Eval::exec`), the count restarts from 0
+  pub fn progress_start(&mut self, op: ProgressOp) {
+    if let Some(p) = &mut self.progress { (p.op = op, p.count = 0); }
+  }
+
+  // called for every row processed
+  pub fn progress_tick(&mut self) { self.progress_add(1); }
+
+  // called for `n` rows processed at once, like a data page in a full scan
+  // the callback is still invoked with every multiple of `every`, as if the rows were processed one by one
+  pub fn progress_add(&mut self, n: u64) {
+    if let Some(p) = &mut self.progress {
+      let old = p.count;
+      p.count += n;
+      for k in old / p.every + 1..=p.count / p.every { (p.f)(p.op, k * p.every); }
+    }
+  }
+}
\ No newline at end of file
@@ -27,8 +27,8 @@ impl Db {
   pub fn show_tables(&self) -> String {
     unsafe {
       let mut s = String::new();
-      for &tp_id in self.pr().dp().tables() {
-        self.show_table_info(self.pr().get_page::<TablePage>(tp_id), &mut s);
+      for tp_id in self.tables() {
+        self.show_table_info(self.read_page::<TablePage>(tp_id), &mut s);
       }
       (s.pop(), s).1
     }
@@ -36,9 +36,22 @@ impl Db {
 
   unsafe fn show_table_info(&self, tp: &TablePage, s: &mut String) {
     writeln!(s, "table `{}`: record count = {}, record size = {}", tp.name(), tp.count, tp.size).unchecked_unwrap();
+    let stats = self.stats(tp);
+    if let Some(sp) = stats { writeln!(s, "  - analyzed: record count = {}", sp.count).unchecked_unwrap(); }
+    if let Some(c) = self.comment(tp, None) { writeln!(s, "  - comment: {:?}", c).unchecked_unwrap(); }
+    if let Some(pp) = self.part_page(tp) {
+      let ci = tp.cols.get_unchecked(pp.ci_id as usize);
+      write!(s, "  - partition by {} (`{}`): {} partitions", if pp.hash { "hash" } else { "range" }, ci.name(), pp.count).unchecked_unwrap();
+      if !pp.hash {
+        let bounds = (0..pp.count as usize - 1).map(|i| format!("{:?}", self.ptr2lit(pp.bound(i, ci.ty.size() as usize), ci.ty))).collect::<Vec<_>>();
+        write!(s, ", bounds = ({})", bounds.join(", ")).unchecked_unwrap();
+      }
+      s.push('\n');
+    }
     for (idx, ci) in tp.cols().iter().enumerate() {
       writeln!(s, "  - col {}: `{}`: {:?} @ offset +{} ", idx, ci.name(), ci.ty, ci.off).unchecked_unwrap();
-      if !ci.flags.is_empty() {
+      if let Some(c) = self.comment(tp, Some(idx as u32)) { writeln!(s, "    - comment: {:?}", c).unchecked_unwrap(); }
+      if ci.flags.intersects(ColFlags::PRIMARY | ColFlags::NOTNULL | ColFlags::UNIQUE) {
         *s += "    - attr: ";
         if ci.flags.contains(ColFlags::PRIMARY) { *s += "primary + "; }
         if ci.flags.contains(ColFlags::NOTNULL) { *s += "notnull + "; }
@@ -46,17 +59,38 @@ impl Db {
         (s.pop(), s.pop(), s.push('\n'));
       }
       if ci.f_table != !0 {
-        let f_tp = self.pr().get_page::<TablePage>(ci.f_table);
+        let f_tp = self.read_page::<TablePage>(ci.f_table);
         let f_ci = f_tp.cols.get_unchecked(ci.f_col as usize);
-        writeln!(s, "    - foreign: `{}.{}`", f_tp.name(), f_ci.name()).unchecked_unwrap();
+        write!(s, "    - foreign: `{}.{}`", f_tp.name(), f_ci.name()).unchecked_unwrap();
+        if ci.on_delete() != FkAction::Restrict { write!(s, " on delete {}", ci.on_delete().name()).unchecked_unwrap(); }
+        if ci.on_update() != FkAction::Restrict { write!(s, " on update {}", ci.on_update().name()).unchecked_unwrap(); }
+        s.push('\n');
       }
       if let Some(idx) = ci.idx_name() {
         *s += "    - index: ";
         if idx.is_empty() { *s += "<internal>"; } else { write!(s, "`{}`", idx).unchecked_unwrap(); }
+        if let Some(f) = ci.idx_fn() { write!(s, " on {}(`{}`)", f.name(), ci.name()).unchecked_unwrap(); }
+        if ci.idx_prefix != 0 { write!(s, " prefix {}", ci.idx_prefix).unchecked_unwrap(); }
+        if ci.flags.contains(ColFlags::DESC) { *s += " desc (stored ascending, scanned backward)"; }
+        if ci.flags.contains(ColFlags::FULLTEXT) { *s += " fulltext"; }
+        if ci.idx_spatial != 0 { write!(s, " spatial on (`{}`, `{}`)", ci.name(), tp.cols.get_unchecked(ci.idx_spatial as usize - 1).name()).unchecked_unwrap(); }
+        if let Some((&mut PartialPred { ci_id, op, .. }, val)) = self.partial_pred(ci) {
+          let p_ci = tp.cols.get_unchecked(ci_id as usize);
+          match op {
+            PARTIAL_NULL | PARTIAL_NOTNULL => write!(s, " where `{}` is {}null", p_ci.name(), if op == PARTIAL_NULL { "" } else { "not " }),
+            _ => write!(s, " where `{}` {} {:?}", p_ci.name(), CmpOp::ALL[op as usize].name(), self.ptr2lit(val, p_ci.ty)),
+          }.unchecked_unwrap();
+        }
+        s.push('\n');
+      }
+      if let Some(sp) = stats {
+        let cs = sp.cols.get_unchecked(idx);
+        write!(s, "    - stats: nulls = {}, distinct = {}", cs.nulls, cs.distinct).unchecked_unwrap();
+        if let Some((min, max)) = self.stats_min_max(ci, cs) { write!(s, ", min = {:?}, max = {:?}", min, max).unchecked_unwrap(); }
         s.push('\n');
       }
       if ci.check != !0 {
-        let cp = self.pr().get_page::<CheckPage>(ci.check >> 1);
+        let cp = self.read_page::<CheckPage>(ci.check >> 1);
         let (count, size) = (cp.count as usize, ci.ty.size() as usize);
         if count != 0 {
           *s += "    - check: ";
@@ -69,6 +103,7 @@ impl Db {
           writeln!(s, "    - default: {:?}", self.ptr2lit(cp.data.as_ptr().add(count * size), ci.ty)).unchecked_unwrap();
         }
       }
+      if let Some(f) = ci.dft_fn() { writeln!(s, "    - default: {}", f.name()).unchecked_unwrap(); }
     }
   }
 }
\ No newline at end of file
@@ -0,0 +1,146 @@
+use unchecked_unwrap::UncheckedUnwrap;
+use std::cmp::Ordering;
+use chrono::Datelike;
+
+use common::{*, BareTy::*};
+use physics::*;
+use crate::{Db, is_null};
+
+// an analyzed table is analyzed again once the records inserted / deleted / updated since then are more than
+// AUTO_ANALYZE_BASE + AUTO_ANALYZE_SCALE * the number of records when it was analyzed
+const AUTO_ANALYZE_BASE: f64 = 50.0;
+const AUTO_ANALYZE_SCALE: f64 = 0.1;
+
+impl Db {
+  // collect statistics of `table` (or all tables if it is None) into stats pages, see `StatsPage`
+  pub fn analyze<'a>(&mut self, table: Option<&'a str>) -> Result<'a, ()> {
+    unsafe {
+      match table {
+        Some(table) => {
+          let tp = self.get_tp(table)?.1;
+          self.analyze_table(tp);
+        }
+        None => for tp_id in self.tables() {
+          let tp = self.get_page::<TablePage>(tp_id);
+          self.analyze_table(tp);
+        }
+      }
+      Ok(())
+    }
+  }
+
+  unsafe fn analyze_table(&mut self, tp: &mut TablePage) {
+    let col_num = tp.col_num as usize;
+    let (mut nulls, mut hashes) = (vec![0; col_num], vec![HashSet::new(); col_num]);
+    let (mut min, mut max, mut nums) = (vec![None; col_num], vec![None; col_num], vec![vec![]; col_num]);
+    let db = (self as *mut Db).r();
+    for (data, _) in self.record_iter(tp) {
+      for (ci_id, ci) in tp.cols().iter().enumerate() {
+        if is_null(data, ci_id as u32) {
+          *nulls.get_unchecked_mut(ci_id) += 1;
+          continue;
+        }
+        let ptr = data.add(ci.off as usize);
+        hashes.get_unchecked_mut(ci_id).insert(db.bloom_hash(ci_id as u32, ci.ty, ptr));
+        let val = db.ptr2lit(ptr, ci.ty);
+        let (lo, hi) = (min.get_unchecked_mut(ci_id), max.get_unchecked_mut(ci_id));
+        if lo.map(|m: CLit| val.cmp(m) == Ordering::Less).unwrap_or(true) { *lo = Some(val); }
+        if hi.map(|m: CLit| val.cmp(m) == Ordering::Greater).unwrap_or(true) { *hi = Some(val); }
+        match val.lit() {
+          Lit::Number(x) => nums.get_unchecked_mut(ci_id).push(x),
+          Lit::Date(d) => nums.get_unchecked_mut(ci_id).push(d.num_days_from_ce() as f64),
+          _ => {}
+        }
+      }
+    }
+    if tp.stats == !0 {
+      let (id, sp) = self.alloc_pages::<StatsPage>(tp.col_pages());
+      (sp.init(tp.col_num as u32), tp.stats = id);
+    }
+    let sp = self.get_page::<StatsPage>(tp.stats);
+    self.clear_hists(sp);
+    (sp.count = tp.count, sp.changes = 0);
+    for (ci_id, ci) in tp.cols().iter().enumerate() {
+      let cs = sp.cols.get_unchecked_mut(ci_id);
+      (cs.nulls = *nulls.get_unchecked(ci_id), cs.distinct = hashes.get_unchecked(ci_id).len() as u32);
+      if let (Some(min), Some(max)) = (*min.get_unchecked(ci_id), *max.get_unchecked(ci_id)) {
+        self.write_stats_val(cs.min.as_mut_ptr(), ci.ty, min);
+        self.write_stats_val(cs.max.as_mut_ptr(), ci.ty, max);
+      }
+      let nums = nums.get_unchecked_mut(ci_id);
+      if !nums.is_empty() {
+        nums.sort_unstable_by(|l, r| l.partial_cmp(r).unchecked_unwrap());
+        let (id, hp) = self.alloc_page::<HistPage>();
+        let (n, buckets) = (nums.len(), nums.len().min(HIST_BUCKETS));
+        hp.buckets = buckets as u32;
+        for i in 0..=buckets { *hp.bounds.get_unchecked_mut(i) = *nums.get_unchecked(i * (n - 1) / buckets); }
+        cs.hist = id;
+      }
+    }
+  }
+
+  // `n` records of `tp` are inserted / deleted / updated, nothing to do if it is not analyzed
+  pub unsafe fn stats_changed(&mut self, tp: &TablePage, n: u32) {
+    if tp.stats == !0 { return; }
+    let sp = self.get_page::<StatsPage>(tp.stats);
+    sp.changes = sp.changes.saturating_add(n);
+  }
+
+  // analyze again the tables with too many changes since they were analyzed, it is called after each insert / delete / update
+  // a table that is never analyzed is left alone, and nothing is done if `auto_analyze` is false
+  pub fn refresh_stats(&mut self) {
+    if !self.auto_analyze { return; }
+    unsafe {
+      for tp_id in self.tables() {
+        let tp = self.get_page::<TablePage>(tp_id);
+        if let Some(sp) = self.stats(tp) {
+          if sp.changes as f64 > AUTO_ANALYZE_BASE + AUTO_ANALYZE_SCALE * sp.count as f64 { self.analyze_table(tp); }
+        }
+      }
+    }
+  }
+
+  // the histogram of a col in stats, None if it has none
+  pub unsafe fn hist<'a>(&self, cs: &ColStats) -> Option<&'a HistPage> {
+    if cs.hist == !0 { None } else { Some(self.pr().get_page::<HistPage>(cs.hist)) }
+  }
+
+  unsafe fn clear_hists(&mut self, sp: &mut StatsPage) {
+    for cs in sp.cols_mut() {
+      if cs.hist != !0 { (self.dealloc_page(cs.hist), cs.hist = !0); }
+    }
+  }
+
+  // write the not-null `val` of a col of type `ty` into `ptr`, which has STATS_VAL_SIZE bytes
+  unsafe fn write_stats_val(&mut self, ptr: *mut u8, ty: ColTy, val: CLit) {
+    match val.lit() {
+      Lit::Str(s) => {
+        let mut end = s.len().min(MAX_STATS_STR);
+        while !s.is_char_boundary(end) { end -= 1; }
+        *ptr = end as u8;
+        ptr.add(1).copy_from_nonoverlapping(s.as_ptr(), end);
+      }
+      _ => self.lit2ptr(ptr, ty.fix_ty(), val).unchecked_unwrap(),
+    }
+  }
+
+  // the statistics of `tp`, None if it is not analyzed
+  pub unsafe fn stats<'a>(&self, tp: &TablePage) -> Option<&'a StatsPage> {
+    if tp.stats == !0 { None } else { Some(self.pr().get_page::<StatsPage>(tp.stats)) }
+  }
+
+  // the min and max of a col in stats, None if it has no not-null value
+  pub unsafe fn stats_min_max<'a>(&self, ci: &ColInfo, cs: &ColStats) -> Option<(CLit<'a>, CLit<'a>)> {
+    if cs.distinct == 0 { return None; }
+    let ty = match ci.ty { char!() | varchar!() => ColTy::FixTy(FixTy { ty: Char, size: MAX_STATS_STR as u8 }), ty => ty };
+    Some((self.ptr2lit(cs.min.as_ptr(), ty), self.ptr2lit(cs.max.as_ptr(), ty)))
+  }
+
+  // discard the statistics of `tp`, they become meaningless when cols are added / dropped
+  pub unsafe fn clear_stats(&mut self, tp: &mut TablePage) {
+    if tp.stats == !0 { return; }
+    let sp = self.get_page::<StatsPage>(tp.stats);
+    self.clear_hists(sp);
+    (self.dealloc_pages(tp.stats, sp.col_pages()), tp.stats = !0);
+  }
+}
\ No newline at end of file
@@ -0,0 +1,181 @@
+use std::{fs::File, io, collections::HashMap};
+use memmap::{MmapOptions, MmapMut};
+use unchecked_unwrap::UncheckedUnwrap;
+
+// how the files of a db are accessed, see `Db::create_with` / `Db::open_with`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IoMode {
+  // `Mmap`, or `File` if the address space for mmap can't be reserved
+  Auto,
+  Mmap,
+  File,
+}
+
+// the files of a db are divided into blocks of BLOCK bytes, and a structure accessed in place (e.g. the pages allocated by `Db::alloc_pages`,
+// or the slots of a lob) never crosses a block boundary (see `contiguous`), so `FileVfs` can keep any subset of blocks in memory
+pub const BLOCK: usize = 1 << 20;
+
+// whether bytes [from, to) of a file are in one block, so a structure there can be allocated in place
+pub fn contiguous(from: usize, to: usize) -> bool { from / BLOCK == (to - 1) / BLOCK }
+
+// where the bytes of a file of a db live in memory, `Db::get_page` and `Db::get_lob` return pointers into it
+pub trait Vfs {
+  // the address of byte 0 if all bytes of the file are contiguous in memory, then `ptr` is not called
+  fn base(&mut self) -> Option<*mut u8>;
+  // the address of byte `off` of the file, the bytes from it to the end of its block are contiguous in memory
+  // `write` tells whether the caller may modify the bytes through it, otherwise it is only for reading
+  fn ptr(&mut self, off: usize, write: bool) -> *mut u8;
+  // extend the file from `from` bytes to `to` bytes, the new bytes are zeroed
+  fn grow(&mut self, from: usize, to: usize) -> io::Result<()>;
+  // write the changes to the file
+  fn flush(&mut self) -> io::Result<()>;
+  // keep at most `blocks` blocks in memory, the pointers returned by `ptr` before are invalid after it (see `Db::trim_cache`)
+  fn trim(&mut self, _blocks: usize) -> io::Result<()> { Ok(()) }
+  // the error that `ptr` met and couldn't return, it is kept, so every call reports it again
+  fn error(&self) -> io::Result<()> { Ok(()) }
+}
+
+// map `len` bytes of `file` (the maximum size it can grow to, None if it doesn't fit in the address space), or read it by `FileVfs`, see `IoMode`
+pub fn open_vfs(file: File, len: Option<usize>, mode: IoMode) -> io::Result<Box<dyn Vfs>> {
+  if mode != IoMode::File {
+    // mmap will not allocate memory unless accessed, but the address space of `len` bytes must be reserved at once
+    match len.map(|len| unsafe { MmapOptions::new().len(len).map_mut(&file) }) {
+      Some(Ok(mmap)) => return Ok(box MmapVfs { len: file.metadata()?.len() as usize, mmap, file }),
+      Some(Err(e)) if mode == IoMode::Mmap => return Err(e),
+      None if mode == IoMode::Mmap => return Err(io::Error::new(io::ErrorKind::Other, "the file is too large to be mapped")),
+      _ => {} // e.g., on 32-bit targets or with a limited address space
+    }
+  }
+  Ok(box FileVfs::new(file)?)
+}
+
+pub struct MmapVfs {
+  mmap: MmapMut,
+  file: File,
+  len: usize,
+}
+
+impl Vfs for MmapVfs {
+  fn base(&mut self) -> Option<*mut u8> { Some(self.mmap.as_mut_ptr()) }
+
+  fn ptr(&mut self, off: usize, _write: bool) -> *mut u8 { unsafe { self.mmap.as_mut_ptr().add(off) } }
+
+  fn grow(&mut self, _from: usize, to: usize) -> io::Result<()> { (self.file.set_len(to as u64)?, self.len = to, Ok(())).2 }
+
+  // the os writes the changes at any time, this only waits for it
+  fn flush(&mut self) -> io::Result<()> { self.mmap.flush_range(0, self.len) }
+}
+
+// a block read into memory by `FileVfs`
+struct Block {
+  data: Box<[u8]>,
+  // whether a pointer into it for writing has been returned since it was read or written, then the bytes may have been modified through the pointer
+  dirty: bool,
+  // the time of the last `ptr` into it, the least recently used blocks are dropped first by `trim`
+  used: u64,
+}
+
+// a block is read by pread when it is first accessed, and written back by pwrite if it is dirty, in `flush` (also when it is dropped),
+// or when it is dropped by `trim`; so the changes are lost if the process crashes before that
+// if a block fails to be read, it is zeroed instead and the error is kept (see `Vfs::error`); then nothing is written back any more,
+// because the changes may be made from the wrong bytes, and `flush` / `trim` report the error
+// it is used when the address space for `MmapVfs` can't be reserved
+// a block is never moved, so the pointers into it are valid until it is dropped by `trim`; the blocks accessed by one statement
+// are all kept until the next `trim`, so a statement scanning a large table may still take a lot of memory
+pub struct FileVfs {
+  file: File,
+  len: usize,
+  // block index -> the block
+  blocks: HashMap<usize, Block>,
+  time: u64,
+  error: Option<io::Error>,
+}
+
+impl FileVfs {
+  pub fn new(file: File) -> io::Result<FileVfs> {
+    let len = file.metadata()?.len() as usize;
+    Ok(FileVfs { file, len, blocks: HashMap::new(), time: 0, error: None })
+  }
+
+  // the bytes of block `idx` that are in the file
+  fn block_len(&self, idx: usize) -> usize { self.len.saturating_sub(idx * BLOCK).min(BLOCK) }
+}
+
+impl Vfs for FileVfs {
+  fn base(&mut self) -> Option<*mut u8> { None }
+
+  fn ptr(&mut self, off: usize, write: bool) -> *mut u8 {
+    let (idx, len) = (off / BLOCK, self.block_len(off / BLOCK));
+    let (file, error, time) = (&self.file, &mut self.error, (self.time += 1, self.time).1);
+    let b = self.blocks.entry(idx).or_insert_with(|| {
+      // the bytes after the end of the file are zeroed, like the ones added by `grow`
+      let mut data = vec![0; BLOCK].into_boxed_slice();
+      if let Err(e) = read_at(file, &mut data[..len], idx * BLOCK) {
+        (data.iter_mut().for_each(|x| *x = 0), error.get_or_insert(e));
+      }
+      Block { data, dirty: false, used: 0 }
+    });
+    (b.dirty |= write, b.used = time);
+    unsafe { b.data.as_mut_ptr().add(off % BLOCK) }
+  }
+
+  // the blocks in memory after the old end already have zeroes there
+  fn grow(&mut self, from: usize, to: usize) -> io::Result<()> {
+    debug_assert_eq!(from, self.len);
+    (self.file.set_len(to as u64)?, self.len = to, Ok(())).2
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.error()?;
+    for (&idx, b) in &mut self.blocks {
+      if b.dirty {
+        write_at(&self.file, &b.data[..self.len.saturating_sub(idx * BLOCK).min(BLOCK)], idx * BLOCK)?;
+        b.dirty = false;
+      }
+    }
+    Ok(())
+  }
+
+  fn trim(&mut self, blocks: usize) -> io::Result<()> {
+    self.error()?;
+    if self.blocks.len() <= blocks { return Ok(()); }
+    let mut lru = self.blocks.iter().map(|(&idx, b)| (b.used, idx)).collect::<Vec<_>>();
+    lru.sort_unstable();
+    for &(_, idx) in &lru[..lru.len() - blocks] {
+      let len = self.block_len(idx);
+      let b = unsafe { self.blocks.get(&idx).unchecked_unwrap() };
+      if b.dirty { write_at(&self.file, &b.data[..len], idx * BLOCK)?; }
+      self.blocks.remove(&idx);
+    }
+    Ok(())
+  }
+
+  fn error(&self) -> io::Result<()> {
+    match &self.error { Some(e) => Err(io::Error::new(e.kind(), e.to_string())), None => Ok(()) }
+  }
+}
+
+// the errors are returned by `Db::close`, the ones here can't be returned, so they are only printed, instead of panicking in a drop
+impl Drop for FileVfs {
+  fn drop(&mut self) {
+    if let Err(e) = self.flush() { eprintln!("failed to write back the database file: {}", e); }
+  }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], off: usize) -> io::Result<()> { std::os::unix::fs::FileExt::read_exact_at(file, buf, off as u64) }
+
+#[cfg(unix)]
+fn write_at(file: &File, buf: &[u8], off: usize) -> io::Result<()> { std::os::unix::fs::FileExt::write_all_at(file, buf, off as u64) }
+
+#[cfg(not(unix))]
+fn read_at(mut file: &File, buf: &mut [u8], off: usize) -> io::Result<()> {
+  use std::io::{Read, Seek, SeekFrom};
+  (file.seek(SeekFrom::Start(off as u64))?, file.read_exact(buf)).1
+}
+
+#[cfg(not(unix))]
+fn write_at(mut file: &File, buf: &[u8], off: usize) -> io::Result<()> {
+  use std::io::{Write, Seek, SeekFrom};
+  (file.seek(SeekFrom::Start(off as u64))?, file.write_all(buf)).1
+}
@@ -1,8 +1,9 @@
 use rustyline::{Editor, Helper, highlight::Highlighter, completion::Completer, hint::Hinter, error::ReadlineError};
 use colored::*;
-use std::{borrow::Cow, str, fs};
+use std::{borrow::Cow, str, fs, time::Duration};
 use typed_arena::Arena;
 
+use common::ModifyError;
 use driver::Eval;
 use syntax::{Lexer, TokenKind};
 
@@ -20,11 +21,11 @@ impl Highlighter for SqlHelper {
       let start = token.col as usize - 1 + ret.len() - line.len();
       let range = start..start + piece.len();
       match token.ty {
-        Lt | Le | Ge | Gt | Eq | Ne | LPar | RPar | Add | Sub | Mul | Div | Mod | Comma | Semicolon => {}
-        Null | True | False | FloatLit | IntLit | StrLit => ret.replace_range(range, &piece.green().to_string()),
+        Lt | Le | Ge | Gt | Eq | Ne | LPar | RPar | Add | Sub | Mul | Div | Mod | BitAnd | BitOr | BitXor | Shl | Shr | BitNot | Comma | Semicolon => {}
+        Null | True | False | FloatLit | IntLit | HexLit | BinLit | BytesLit | StrLit => ret.replace_range(range, &piece.green().to_string()),
         Int | Bool | Char | Varchar | Float | Date => ret.replace_range(range, &piece.cyan().to_string()),
-        Sum | Avg | Min | Max | Count => ret.replace_range(range, &piece.yellow().to_string()),
-        Id1 | Dot => ret.replace_range(range, &piece.purple().to_string()),
+        Sum | Avg | Min | Max | Count | GroupConcat | StringAgg | Stddev | Variance | Median | PercentileCont => ret.replace_range(range, &piece.yellow().to_string()),
+        Id1 | BackQuoted | Quoted | Dot => ret.replace_range(range, &piece.purple().to_string()),
         _Err | _Eof => break ret.into(),
         _ => ret.replace_range(range, &piece.blue().bold().to_string()),
       }
@@ -42,6 +43,12 @@ impl Hinter for SqlHelper {}
 
 impl Helper for SqlHelper {}
 
+// the error with the line of `code` it is at (see `Error::render`)
+fn report(code: &str, e: ModifyError) {
+  eprintln!("{}", e.1.render(code));
+  if e.0 != 0 { eprintln!("{} column(s) affected", e.0); }
+}
+
 fn main() {
   let mut rl = Editor::new();
   rl.set_helper(Some(SqlHelper));
@@ -61,16 +68,50 @@ fn main() {
           const OUTPUT: &str = ".output";
           const READ: &str = ".read";
           const COLOR: &str = ".color";
+          const LOG: &str = ".log";
+          const SLOW_LOG: &str = ".slowlog";
+          const METRICS: &str = ".metrics";
+          const PAGE: &str = ".page";
+          const BTREE: &str = ".btree";
           match cmd {
             OUTPUT => output = words.next().map(|x| x.to_owned()),
             READ => if let Some(file) = words.next() {
               if let Ok(input) = fs::read_to_string(file) {
-                if let Err(e) = e.exec_all(&input, &Arena::default(), |_| {}, |_| {}) { eprintln!("Error: {:?}", e); }
+                if let Err(e) = e.exec_all(&input, &Arena::default(), |_| {}, |_| {}) { report(&input, e); }
               } else { eprintln!("Error: fails to read from {}", file); }
             } else { eprintln!("Usage: {} <file>", READ); }
             COLOR => if let Some(color) = words.next().and_then(|x| x.parse().ok()) {
               rl.set_helper(if color { Some(SqlHelper) } else { None });
             } else { eprintln!("Usage: {} [true|false]", COLOR); }
+            LOG => match words.next() { // without a file, stop logging
+              Some(file) => if let Err(err) = e.set_log_file(file) { eprintln!("Error: fails to open {}: {}", file, err); }
+              None => e.clear_log(),
+            }
+            SLOW_LOG => match (words.next().map(|x| x.parse::<u64>()), words.next()) { // without arguments, stop logging
+              (Some(Ok(ms)), Some(file)) => if let Err(err) = e.set_slow_log_file(Duration::from_millis(ms), file) { eprintln!("Error: fails to open {}: {}", file, err); }
+              (None, _) => e.clear_slow_log(),
+              _ => eprintln!("Usage: {} [<milliseconds> <file>]", SLOW_LOG),
+            }
+            METRICS => match words.next() { // without a file, print them
+              Some(file) => if let Err(err) = fs::write(file, e.metrics_text()) { eprintln!("Error: fails to write {}: {}", file, err); }
+              None => print!("{}", e.metrics_text()),
+            }
+            PAGE => match (words.next().map(|x| x.parse::<u32>()), e.db()) {
+              (Some(Ok(page)), Ok(db)) => match db.dump_page(page) {
+                Ok(dump) => println!("{}", dump),
+                Err(err) => eprintln!("Error: {:?}", err),
+              }
+              (Some(Ok(_)), Err(err)) => eprintln!("Error: {:?}", err),
+              _ => eprintln!("Usage: {} <page id>", PAGE),
+            }
+            BTREE => match (words.next(), words.next(), e.db()) {
+              (Some(table), Some(col), Ok(db)) => match index::check_index_tree(db, table, col) {
+                Ok(dump) => println!("{}", dump),
+                Err(err) => eprintln!("Error: {:?}", err),
+              }
+              (Some(_), Some(_), Err(err)) => eprintln!("Error: {:?}", err),
+              _ => eprintln!("Usage: {} <table> <col>", BTREE),
+            }
             _ => eprintln!("Unknown command: {}", cmd),
           }
         } else {
@@ -81,7 +122,7 @@ fn main() {
               if let Some(output) = &output {
                 if fs::write(output, x).is_err() { eprintln!("Error: fails to write to {}", output); }
               } else { println!("{}", x); }
-            }) { eprintln!("Error: {:?}", e); }
+            }) { report(&cur, e); }
             cur.clear();
           }
         }
@@ -0,0 +1,124 @@
+use std::{fs, process, str, sync::atomic::{AtomicUsize, Ordering::Relaxed}};
+use typed_arena::Arena;
+
+use common::{*, Error::*, BareTy::*};
+use syntax::ast::*;
+use db::Db;
+use crate::{Eval, sys::uses_sys};
+
+// for naming the files of `DERIVED`, like `SYS_ID`
+static DERIVED_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Eval {
+  // rebuild the database `DERIVED` with one table for each derived table in `s` (see `Derived`), including the ones nested in them
+  // the cols have the same types as the result cols, and are nullable
+  // `exec` calls it before a select / explain that has derived tables, `select` and `cursor` see the tables of the last refresh
+  pub fn refresh_derived<'a>(&mut self, s: &Select<'a>) -> Result<'a, ()> {
+    let path = match self.derived.take() {
+      Some((_, path)) => path, // the old db is dropped before the file is created again
+      None => std::env::temp_dir().join(format!("db-derived-{}-{}", process::id(), DERIVED_ID.fetch_add(1, Relaxed))),
+    };
+    self.derived = Some((Db::create(&path)?, path));
+    (self.share_settings(), self.fill_derived(s)).1
+  }
+
+  // the inner derived tables are filled first, so the select of a derived table can use them
+  fn fill_derived<'a>(&mut self, s: &Select<'a>) -> Result<'a, ()> {
+    for d in &s.derived {
+      // the strings may be in the pages of `DERIVED` if `d` selects from another derived table (see `select_rows`)
+      let strs = Arena::new();
+      let (names, tys, vals): (Vec<String>, Vec<ColTy>, Vec<Vec<CLit>>) = match &d.src {
+        DerivedSrc::Select(s) => {
+          self.fill_derived(s)?;
+          self.select_rows(s, &strs)?
+        }
+        DerivedSrc::Values(rows) => {
+          let tys = values_tys(rows)?;
+          ((1..=tys.len()).map(|i| format!("column{}", i)).collect(), tys, rows.clone())
+        }
+      };
+      let db = &mut self.derived.as_mut().unwrap().0;
+      fill_table(db, d.alias, d.cols.as_deref(), names, tys, vals).map_err(|reason| InvalidDerived { table: d.alias, reason })?;
+    }
+    Ok(())
+  }
+
+  // `create table ... as select ...`, the cols have the same types as the result cols, and are nullable
+  pub(crate) fn create_table_as<'a>(&mut self, c: &CreateTableAs<'a>) -> Result<'a, ()> {
+    if !c.select.derived.is_empty() { self.refresh_derived(&c.select)?; }
+    let strs = Arena::new();
+    let (names, tys, vals) = self.select_rows(&c.select, &strs)?;
+    fill_table(self.db()?, c.table, c.cols.as_deref(), names, tys, vals).map_err(|e| InvalidCreateAs { table: c.table, reason: e })
+  }
+
+  // the names, types and rows of the result of `s`
+  // the strings are copied to `strs`, since they may be in the pages of the db that the rows are going to be inserted to
+  fn select_rows<'a, 's>(&mut self, s: &Select<'a>, strs: &'s Arena<u8>) -> Result<'a, (Vec<String>, Vec<ColTy>, Vec<Vec<CLit<'s>>>)> {
+    if uses_sys(&s.tables) { self.refresh_sys()?; }
+    let res = self.select(s)?;
+    let vals = res.data.chunks(res.cols.len().max(1)).map(|row| row.iter().map(|x| CLit::new(match x.lit() {
+      Lit::Null => Lit::Null,
+      Lit::Bool(x) => Lit::Bool(x),
+      Lit::Number(x) => Lit::Number(x),
+      Lit::Date(x) => Lit::Date(x),
+      Lit::Str(x) => Lit::Str(unsafe { str::from_utf8_unchecked(strs.alloc_extend(x.bytes())) }),
+    })).collect()).collect();
+    Ok((res.cols.iter().map(|c| c.name()).collect(), res.cols.iter().map(|c| c.ty()).collect(), vals))
+  }
+
+  pub(crate) fn remove_derived(&mut self) {
+    if let Some((derived, path)) = self.derived.take() {
+      drop(derived);
+      let _ = (fs::remove_file(&path), fs::remove_file(path.with_extension(LOB_SUFFIX)));
+    }
+  }
+}
+
+// create `table` in `db` with nullable cols of `names` (or `cols` if it is Some) and `tys`, and insert `vals` to it
+// the error is formatted, since it may refer to the strings here
+fn fill_table(db: &mut Db, table: &str, cols: Option<&[&str]>, names: Vec<String>, tys: Vec<ColTy>, vals: Vec<Vec<CLit>>) -> std::result::Result<(), String> {
+  let names = match cols {
+    Some(cols) if cols.len() != tys.len() => return Err(format!("{:?}", InsertTooLong { max: tys.len(), actual: cols.len() })),
+    Some(cols) => cols.iter().map(|&c| c.to_owned()).collect(),
+    None => names,
+  };
+  let cols = names.iter().zip(tys).map(|(col, ty)| ColDecl { col: col.as_str(), ty, notnull: false, dft: None }).collect();
+  db.create_table(&CreateTable { table, cols, cons: vec![] }).map_err(|e| format!("{:?}", e))?;
+  if !vals.is_empty() {
+    query::insert(&Insert { table, vals, cols: None }, db).map_err(|e| format!("{:?}", e.1))?;
+  }
+  Ok(())
+}
+
+// the type of each col of `values (...), ...`: int if all numbers in it are integers in the range of int, otherwise float,
+// char as long as the longest string in it (varchar if that is too long for char), and int if it only has null
+fn values_tys<'a>(rows: &[Vec<CLit<'a>>]) -> Result<'a, Vec<ColTy>> {
+  let n = rows.first().map_or(0, |row| row.len());
+  let (mut tys, mut int, mut len) = (vec![LitTy::Null; n], vec![true; n], vec![1; n]);
+  for row in rows {
+    if row.len() != n { return Err(RowArityMismatch { cols: n, vals: row.len() }); }
+    for (i, x) in row.iter().enumerate() {
+      let x = x.lit();
+      match (tys[i], x.ty()) {
+        (_, LitTy::Null) => continue,
+        (LitTy::Null, r) => tys[i] = r,
+        (l, r) if l != r => return Err(IncompatibleArgs { func: "values", l, r }),
+        _ => {}
+      }
+      match x {
+        Lit::Number(x) => int[i] &= x.fract() == 0.0 && x.abs() <= std::i32::MAX as f64,
+        Lit::Str(x) => len[i] = len[i].max(x.len()),
+        _ => {}
+      }
+    }
+  }
+  let fix = |ty, size| ColTy::FixTy(FixTy { ty, size });
+  Ok(tys.into_iter().zip(int).zip(len).map(|((ty, int), len)| match ty {
+    LitTy::Bool => fix(Bool, 0),
+    LitTy::Number => fix(if int { Int } else { Float }, 0),
+    LitTy::Date => fix(Date, 0),
+    LitTy::Str if len <= std::u8::MAX as usize => fix(Char, len as u8),
+    LitTy::Str => ColTy::Varchar(len.min(std::u16::MAX as usize) as u16),
+    LitTy::Null => fix(Int, 0),
+  }).collect())
+}
\ No newline at end of file
@@ -0,0 +1,39 @@
+use db::{Db, Change};
+use crate::Eval;
+
+impl Eval {
+  // `f` is called with every record inserted / deleted / updated by a statement executed by `exec` (or by `insert_batch`),
+  // including the ones changed by the actions of foreign keys and the ones in temporary tables
+  // it is called after the statement, not in the middle of it, and a failed statement still reports the changes made before the error
+  pub fn on_change(&mut self, f: impl FnMut(&Change) + 'static) {
+    self.change_hooks.push(Box::new(f));
+    self.watch_changes();
+  }
+
+  pub fn clear_change_hooks(&mut self) {
+    self.change_hooks.clear();
+    if self.results.is_some() { return; } // changes are still watched for the result cache
+    for db in self.modified_dbs() { db.watch_changes(false); }
+  }
+
+  // the dbs that statements can modify, the attached ones (and the internal ones like `sys`) are read-only
+  fn modified_dbs(&mut self) -> impl Iterator<Item = &mut Db> {
+    self.db.iter_mut().chain(self.temp.iter_mut().map(|(temp, _)| temp))
+  }
+
+  // a db may be opened (or created) by the previous statement, so it is called before every statement
+  // changes are also watched for the result cache, see `enable_result_cache`
+  pub(crate) fn watch_changes(&mut self) {
+    if self.change_hooks.is_empty() && self.results.is_none() { return; }
+    for db in self.modified_dbs() { db.watch_changes(true); }
+  }
+
+  pub(crate) fn fire_change_hooks(&mut self) {
+    if self.change_hooks.is_empty() && self.results.is_none() { return; }
+    let changes = self.modified_dbs().flat_map(|db| db.take_changes()).collect::<Vec<_>>();
+    self.invalidate_results(changes.iter().map(|c| &*c.table));
+    for c in &changes {
+      for f in &mut self.change_hooks { f(c); }
+    }
+  }
+}
\ No newline at end of file
@@ -1,31 +1,168 @@
-use std::{borrow::Cow, fs, path::Path};
+pub mod prepared;
+pub mod log;
+pub mod sys;
+pub mod derived;
+pub mod temp;
+pub mod metrics;
+pub mod hook;
+pub mod results;
+pub mod migrate;
+#[cfg(feature = "with-serde")]
+pub mod typed;
+
+use std::{borrow::Cow, fs, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
 use typed_arena::Arena;
 
 use common::{*, Error::*};
 use syntax::ast::*;
-use db::{Db, show::show_db};
-use query::SelectResult;
+use db::{Db, Rid, Change, ProgressOp, show::show_db};
+use query::{SelectResult, Cursor};
+use crate::{prepared::{Prepared, CACHE_CAP}, log::LogEntry, sys::{SYS, uses_sys}, temp::TEMP, metrics::Metrics, results::{ResultCache, result_key}};
 
 #[derive(Default)]
-pub struct Eval(Option<Db>);
+pub struct Eval {
+  db: Option<Db>,
+  // attached databases, the key is alias; they can only be accessed by select
+  attached: HashMap<String, Db>,
+  // sql text -> its parsed statements, see `exec_cached`
+  cache: HashMap<String, Prepared>,
+  // the number of `exec_cached` calls whose sql text is found / not found in `cache`
+  cache_hits: u64,
+  cache_misses: u64,
+  // the virtual database of counters and the path of its file, see `refresh_sys`
+  sys: Option<(Db, PathBuf)>,
+  // the database of derived tables and the path of its file, see `refresh_derived`
+  derived: Option<(Db, PathBuf)>,
+  // the database of temporary tables and the path of its file, see `create_temp_table`
+  temp: Option<(Db, PathBuf)>,
+  metrics: Metrics,
+  // see `set_log`
+  log: Option<Box<dyn FnMut(&LogEntry)>>,
+  // see `set_slow_log`
+  slow_log: Option<(Duration, Box<dyn FnMut(&LogEntry)>)>,
+  // see `on_change`
+  change_hooks: Vec<Box<dyn FnMut(&Change)>>,
+  // see `enable_result_cache`
+  results: Option<ResultCache>,
+  // the dialect that sql texts are parsed in, see `Setting::Dialect`
+  dialect: Dialect,
+  // see `Setting::IgnoreCase`, it is copied to every open database (see `Db::ignore_case`)
+  ignore_case: bool,
+  // see `Setting::RandomSeed`, the value stored by `Setting::parse`
+  random_seed: u32,
+  // see `Setting::SortMemory` and `Setting::ScanThreads`, None for the default; they are copied to the database in use (see `Db::sort_budget`)
+  sort_budget: Option<usize>,
+  scan_threads: Option<usize>,
+}
 
 impl Eval {
   pub fn exec_all<'a>(&mut self, code: &'a str, alloc: &'a Arena<u8>, input_handler: impl Fn(&Stmt), result_handler: impl Fn(&str)) -> ModifyResult<'a, ()> {
-    for s in &syntax::work(code, alloc)? {
+    for s in &syntax::work_in_dialect(code, alloc, &[], self.dialect)? {
       input_handler(s);
       result_handler(&self.exec(s)?);
     }
     Ok(())
   }
 
+  // like `exec_all`, but a failed statement doesn't stop the ones after it, the output or error of each statement is returned in order
+  // it is still an error if `code` can't be parsed, and then no statement is executed
+  pub fn exec_batch<'a>(&mut self, code: &'a str, alloc: &'a Arena<u8>) -> Result<'a, Vec<ModifyResult<'a, String>>> {
+    let ss = syntax::work_in_dialect(code, alloc, &[], self.dialect)?;
+    Ok(ss.iter().map(|s| self.exec(s).map(|x| x.into_owned())).collect())
+  }
+
+  // like `exec_all`, but the parsed statements of `code` are cached, so executing the same sql text again skips parsing (but not planning)
+  // the error borrows the cache, so the returned lifetime is the same as `self`
+  pub fn exec_cached<'a>(&'a mut self, code: &str, result_handler: impl Fn(&str)) -> ModifyResult<'a, ()> {
+    // an entry parsed in another dialect is parsed again
+    if self.cache.get(code).map_or(true, |p| p.dialect != self.dialect) {
+      if self.cache.len() == CACHE_CAP { self.cache.clear(); }
+      self.cache.insert(code.to_owned(), Prepared::new(code, self.dialect));
+      self.cache_misses += 1;
+    } else { self.cache_hits += 1; }
+    // safe because entries are only removed above, when no statement or error borrows them
+    let p = unsafe { &*(&self.cache[code] as *const Prepared) };
+    for s in p.stmts()? {
+      result_handler(&self.exec(s)?);
+    }
+    Ok(())
+  }
+
+  pub fn cached(&self) -> usize { self.cache.len() }
+
+  // like `exec_all`, but placeholders (`?` or `$n`) in `code` are bound to `params`, they are never parsed as sql
+  // the values are checked against the cols they are compared with / inserted into, just like literals
+  pub fn exec_with_params<'a>(&mut self, code: &'a str, alloc: &'a Arena<u8>, params: &'a [CLit<'a>], result_handler: impl Fn(&str)) -> ModifyResult<'a, ()> {
+    for s in &syntax::work_in_dialect(code, alloc, params, self.dialect)? {
+      result_handler(&self.exec(s)?);
+    }
+    Ok(())
+  }
+
   pub fn exec<'a>(&mut self, sql: &Stmt<'a>) -> ModifyResult<'a, Cow<str>> {
+    let (time, start) = (SystemTime::now(), Instant::now());
+    // results of previous selects can't be alive here, so the strings made for them are freed, and the pages they refer to can be dropped
+    for db in self.db.iter_mut().chain(self.attached.values_mut()).chain(self.temp.iter_mut().map(|(temp, _)| temp)) { (db.clear_temp_strs(), db.trim_cache()?); }
+    // the output is made owned, so that it doesn't borrow `self` below; all outputs except "" are owned anyway
+    self.watch_changes();
+    let res = self.exec_stmt(sql).map(|s| Cow::<str>::Owned(s.into_owned()));
+    // a page that failed to be read (see `Db::io_error`) fails the statement, since it was read as zeros
+    let res = res.and_then(|s| {
+      for db in self.db.iter().chain(self.attached.values()).chain(self.temp.iter().map(|(temp, _)| temp)) { db.io_error()?; }
+      Ok(s)
+    });
+    // statistics are refreshed after the whole statement (even if it fails halfway), not in the middle of it
+    let stmt = if let Stmt::Returning(r) = sql { &*r.stmt } else { sql };
+    if let Stmt::Insert(Insert { table, .. }) | Stmt::Delete(Delete { table, .. }) | Stmt::Update(Update { table, .. }) | Stmt::DropPartition { table, .. } = stmt {
+      if let Ok(db) = self.table_db(table) { db.refresh_stats(); }
+    }
+    (self.fire_change_hooks(), self.clear_results(stmt));
+    let duration = start.elapsed();
+    self.metrics.record(duration, res.is_ok());
+    if self.log.is_none() && self.slow_log.is_none() { return res; }
+    let rows = match sql {
+      Stmt::Insert(_) | Stmt::Delete(_) | Stmt::Update(_) | Stmt::DropPartition { .. } => Some(match &res {
+        Ok(s) => s.split(' ').next().and_then(|n| n.parse().ok()).unwrap_or(0),
+        Err(e) => e.0,
+      }),
+      // one line for each row after the header
+      Stmt::Returning(_) => Some(match &res { Ok(s) => s.matches('\n').count() as u32, Err(e) => e.0 }),
+      _ => None,
+    };
+    let error = res.as_ref().err().map(|e| format!("{:?}", e.1));
+    let mut entry = LogEntry { time, duration, stmt: sql, rows, error, plan: None };
+    if let Some(log) = &mut self.log { log(&entry); }
+    if self.slow_log.as_ref().map(|&(threshold, _)| duration >= threshold).unwrap_or(false) {
+      entry.plan = self.plan(sql);
+      if let Some((_, log)) = &mut self.slow_log { log(&entry); }
+    }
+    res
+  }
+
+  fn exec_stmt<'a>(&mut self, sql: &Stmt<'a>) -> ModifyResult<'a, Cow<str>> {
     fn fmt<'a>(n: u32) -> Cow<'a, str> { Cow::Owned(format!("{} column(s) affected", n)) }
     use Stmt::*;
+    if let Some(db) = &mut self.db { // see `Db::set_progress`
+      let stmt = if let Returning(r) = sql { &*r.stmt } else { sql };
+      db.progress_start(match stmt {
+        Insert(_) => ProgressOp::Insert,
+        CreateIndex(_) | Reindex { .. } | Optimize(_) | AddCol { .. } | DropCol { .. } | AddPrimary { .. } | AddForeign(_) => ProgressOp::IndexBuild,
+        _ => ProgressOp::Scan,
+      });
+    }
     Ok(match sql {
-      Insert(i) => fmt(query::insert(i, self.db()?)?),
-      Delete(d) => fmt(query::delete(d, self.db()?)?),
-      Select(s) => query::select(s, self.db()?)?.csv().into(),
-      Update(u) => fmt(query::update(u, self.db()?)?),
+      Insert(i) => fmt(query::insert(i, self.table_db(i.table)?)?),
+      Delete(d) => fmt(query::delete(d, self.table_db(d.table)?)?),
+      Select(s) => {
+        let key = if self.results.is_some() { result_key(s) } else { None };
+        if let Some(out) = key.as_ref().and_then(|(key, _)| self.cached_result(key)) { return Ok(out.into()); }
+        if uses_sys(&s.tables) { self.refresh_sys()?; }
+        if !s.derived.is_empty() { self.refresh_derived(s)?; }
+        let out = self.select(s)?.csv();
+        if let Some((key, tables)) = key { self.cache_result(key, tables, &out); }
+        out.into()
+      }
+      Update(u) => fmt(query::update(u, self.table_db(u.table)?)?),
       &CreateDb(path) => (Db::create(path), "".into()).1,
       &DropDb(path) => (fs::remove_file(path)?, fs::remove_file(AsRef::<Path>::as_ref(path).with_extension(LOB_SUFFIX))?, "".into()).2,
       &ShowDb(path) => {
@@ -40,26 +177,169 @@ impl Eval {
         }
         s.into()
       }
-      &UseDb(path) => (self.0 = Some(Db::open(path)?), "".into()).1,
+      &UseDb(path) => {
+        // the db used before is closed here, so that an error in writing back its changes is reported
+        if let Some(db) = self.db.replace(Db::open(path)?) { db.close()?; }
+        self.share_settings();
+        // apply the settings persisted in it, they are all valid since they were checked when persisted
+        for (s, val) in self.db()?.persisted_settings() { self.apply(s, val)?; }
+        "".into()
+      }
       CreateTable(c) => (self.db()?.create_table(c)?, "".into()).1,
-      &DropTable(table) => (self.db()?.drop_table(table)?, "".into()).1,
-      &ShowTable(table) => self.db()?.show_table(table)?.into(),
+      CreateTempTable(c) => (self.create_temp_table(c)?, "".into()).1,
+      CreateTableAs(c) => (self.create_table_as(c)?, "".into()).1,
+      &CreateTableLike { table, like } => (index::create_table_like(self.db()?, table, like)?, "".into()).1,
+      CreatePartitioned { create, part } => (self.db()?.create_partitioned_table(create, part)?, "".into()).1,
+      &DropPartition { table, part } => fmt(query::drop_partition(table, part, self.db()?)?),
+      &DropTable { table, cascade } => (self.table_db(table)?.drop_table(table, cascade)?, "".into()).1,
+      &ShowTable(table) => self.table_db(table)?.show_table(table)?.into(),
       ShowTables => self.db()?.show_tables().into(),
       CreateIndex(c) => (index::create_index(self.db()?, c)?, "".into()).1,
       &DropIndex { index, table } => (self.db()?.drop_index(index, table)?, "".into()).1,
       &Rename { old, new } => (self.db()?.rename_table(old, new)?, "".into()).1,
       AddForeign(a) => (index::add_foreign(self.db()?, a)?, "".into()).1,
       &DropForeign { table, col } => (self.db()?.drop_foreign(table, col)?, "".into()).1,
+      CheckForeignKeys => index::check_foreign_keys(self.db()?).into(),
+      CheckDb => index::check_db(self.db()?).into(),
+      RepairDb(None) => index::repair_db(self.db()?).into(),
+      &RepairDb(Some((src, dst))) => {
+        // src is not touched, so nothing is lost if the repair goes wrong
+        let lob = |path: &str| AsRef::<Path>::as_ref(path).with_extension(LOB_SUFFIX);
+        (fs::copy(src, dst)?, fs::copy(lob(src), lob(dst))?);
+        index::repair_db(&mut Db::open(dst)?).into()
+      }
+      &Comment { table, col, comment } => (self.db()?.set_comment(table, col, comment)?, "".into()).1,
       AddPrimary { table, cols } => (index::add_primary(self.db()?, table, cols)?, "".into()).1,
       DropPrimary { table, cols } => (index::drop_primary(self.db()?, table, cols)?, "".into()).1,
       AddCol { table, col } => (index::add_col(self.db()?, table, col)?, "".into()).1,
       &DropCol { table, col } => (index::drop_col(self.db()?, table, col)?, "".into()).1,
+      &Reindex { table, col } => (index::reindex(self.db()?, table, col)?, "".into()).1,
+      &Analyze(table) => (self.db()?.analyze(table)?, "".into()).1,
+      &Optimize(table) => format!("{} page(s) released", index::optimize_table(self.table_db(table)?, table)?).into(),
+      &Attach { path, alias } => {
+        if self.attached.contains_key(alias) || alias == SYS || alias == DERIVED || alias == TEMP { return Err(DupAttach(alias).into()); }
+        (self.attached.insert(alias.to_owned(), Db::open(path)?), self.share_settings(), "".into()).2
+      }
+      &Detach(alias) => (self.attached.remove(alias).ok_or(NoSuchAttach(alias))?.close()?, "".into()).1,
+      Explain(e) => {
+        if uses_sys(&e.select.tables) { self.refresh_sys()?; }
+        if !e.select.derived.is_empty() { self.refresh_derived(&e.select)?; }
+        let s = self.resolve_temp(&e.select);
+        let (s, db) = (s.as_ref().unwrap_or(&e.select), self.db.as_ref().ok_or(NoDbInUse)?);
+        match e.format {
+          ExplainFormat::Text => query::explain_plan(s, db, |alias| self.attached(alias))?.text(),
+          ExplainFormat::Json => query::explain_plan(s, db, |alias| self.attached(alias))?.json(),
+          ExplainFormat::Dot => query::explain_analyze(s, db, |alias| self.attached(alias))?.dot(),
+          ExplainFormat::Tree => query::explain_analyze(s, db, |alias| self.attached(alias))?.tree(),
+        }.into()
+      }
+      &Set { name, val, persist } => (self.set(name, val, persist)?, "".into()).1,
+      &ShowSetting(name) => self.setting(name)?.into(),
+      Returning(r) => match &*r.stmt {
+        Insert(i) => query::insert_returning(i, r.cols.as_deref(), self.table_db(i.table)?)?.csv().into(),
+        Update(u) => query::update_returning(u, r.cols.as_deref(), self.table_db(u.table)?)?.csv().into(),
+        Delete(d) => query::delete_returning(d, r.cols.as_deref(), self.table_db(d.table)?)?.csv().into(),
+        _ => unsafe { impossible!() },
+      },
     })
   }
 
+  fn set<'a>(&mut self, name: &'a str, val: CLit<'a>, persist: bool) -> Result<'a, ()> {
+    let s = Setting::from_name(name).ok_or(NoSuchSetting(name))?;
+    let v = s.parse(val.lit()).ok_or(InvalidSettingVal { name, val })?;
+    if persist { self.db()?.persist_setting(s, v); }
+    self.apply(s, v)
+  }
+
+  // `val` is the same as stored by `Setting::parse`
+  fn apply<'a>(&mut self, s: Setting, val: u32) -> Result<'a, ()> {
+    match s {
+      Setting::SortMemory => (self.sort_budget = Some(val as usize * 1024), self.share_settings()).0,
+      Setting::ScanThreads => (self.scan_threads = Some(val as usize), self.share_settings()).0,
+      // it belongs to the current db, so it is reset when another db is used, unless it is persisted there
+      Setting::ForeignKeys => match &mut self.db { Some(db) => db.foreign_keys = val != 0, None => return Err(NoDbInUse) },
+      Setting::AutoAnalyze => match &mut self.db { Some(db) => db.auto_analyze = val != 0, None => return Err(NoDbInUse) },
+      Setting::Dialect => self.dialect = if val == Dialect::MySql as u32 { Dialect::MySql } else { Dialect::Default },
+      Setting::IgnoreCase => (self.ignore_case = val != 0, self.share_settings()).0,
+      // setting it again restarts the same sequence of `random()`
+      Setting::RandomSeed => (self.random_seed = val, seed_random(val.checked_sub(1).map(|x| x as u64))).0,
+    }
+    Ok(())
+  }
+
+  // the settings of this session that are kept on the database in use, they are copied again when another database is used
+  // `ignore_case` is kept on all open databases, since a statement may refer to tables in any of them
+  fn share_settings(&mut self) {
+    if let Some(db) = &mut self.db {
+      db.sort_budget = self.sort_budget.unwrap_or(db::SORT_BUDGET);
+      db.scan_threads = self.scan_threads.unwrap_or(db::SCAN_THREADS);
+    }
+    let ignore_case = self.ignore_case;
+    let others = self.sys.iter_mut().chain(&mut self.derived).chain(&mut self.temp).map(|(db, _)| db);
+    for db in self.db.iter_mut().chain(self.attached.values_mut()).chain(others) { db.ignore_case = ignore_case; }
+  }
+
+  // like "sort_memory = 64MB"
+  fn setting<'a>(&mut self, name: &'a str) -> Result<'a, String> {
+    let s = Setting::from_name(name).ok_or(NoSuchSetting(name))?;
+    let val = match s {
+      Setting::SortMemory => (self.sort_budget.unwrap_or(db::SORT_BUDGET) / 1024) as u32,
+      Setting::ScanThreads => self.scan_threads.unwrap_or(db::SCAN_THREADS) as u32,
+      Setting::ForeignKeys => self.db()?.foreign_keys as u32,
+      Setting::AutoAnalyze => self.db()?.auto_analyze as u32,
+      Setting::Dialect => self.dialect as u32,
+      Setting::IgnoreCase => self.ignore_case as u32,
+      Setting::RandomSeed => self.random_seed,
+    };
+    Ok(format!("{} = {}", s.name(), s.show(val)))
+  }
+
+  // like `exec(&Stmt::Insert(i))`, but for many rows at once, which is all or nothing (see `query::insert_batch`)
+  pub fn insert_batch<'a>(&mut self, i: &Insert<'a>) -> ModifyResult<'a, u32> {
+    self.watch_changes();
+    let db = self.db()?;
+    db.progress_start(ProgressOp::Insert);
+    let res = query::insert_batch(i, db);
+    db.refresh_stats();
+    (self.fire_change_hooks(), res).1
+  }
+
   pub fn select<'a, 'b>(&'b self, s: &Select<'a>) -> Result<'a, SelectResult<'b>> {
-    query::select(s, self.0.as_ref().ok_or(NoDbInUse)?)
+    let resolved = self.resolve_temp(s);
+    query::select_attached(resolved.as_ref().unwrap_or(s), self.db.as_ref().ok_or(NoDbInUse)?, |alias| self.attached(alias))
+  }
+
+  // like `select`, but rows are produced lazily if possible (see `Cursor`)
+  pub fn cursor<'a: 'b, 'b>(&'b self, s: &Select<'a>) -> Result<'a, Cursor<'b>> {
+    let resolved = self.resolve_temp(s);
+    query::cursor_attached(resolved.as_ref().unwrap_or(s), self.db.as_ref().ok_or(NoDbInUse)?, |alias| self.attached(alias))
+  }
+
+  // the rid of the last record inserted to the database in use, see `Db::last_insert`
+  pub fn last_insert_rid(&self) -> Option<Rid> { self.db.as_ref()?.last_insert }
+
+  // the record `rid` of `table`, like `select * from table` but only this record, e.g. `select_rid(t, last_insert_rid().unwrap())`
+  pub fn select_rid<'a, 'b>(&'b self, table: &'a str, rid: Rid) -> Result<'a, SelectResult<'b>> {
+    query::select_rid(table, rid, self.db.as_ref().ok_or(NoDbInUse)?)
   }
 
-  pub fn db<'a>(&mut self) -> Result<'a, &mut Db> { self.0.as_mut().ok_or(NoDbInUse) }
+  // an attached database, `sys` (see `refresh_sys`), `DERIVED` (see `refresh_derived`) or `TEMP` (see `create_temp_table`)
+  fn attached(&self, alias: &str) -> Option<&Db> {
+    match alias {
+      SYS => self.sys.as_ref().map(|(sys, _)| sys),
+      DERIVED => self.derived.as_ref().map(|(derived, _)| derived),
+      TEMP => self.temp.as_ref().map(|(temp, _)| temp),
+      _ => self.attached.get(alias),
+    }
+  }
+
+  pub fn db<'a>(&mut self) -> Result<'a, &mut Db> { self.db.as_mut().ok_or(NoDbInUse) }
+}
+
+impl Drop for Eval {
+  fn drop(&mut self) {
+    self.remove_sys();
+    self.remove_derived();
+    self.remove_temp();
+  }
 }
\ No newline at end of file
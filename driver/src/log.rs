@@ -0,0 +1,75 @@
+use std::{fs::OpenOptions, io::{self, Write}, path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use syntax::ast::*;
+use crate::Eval;
+
+// a statement executed by `Eval::exec`, passed to the callback set by `Eval::set_log` / `Eval::set_slow_log`
+pub struct LogEntry<'s> {
+  // when the statement started
+  pub time: SystemTime,
+  pub duration: Duration,
+  pub stmt: &'s Stmt<'s>,
+  // the number of records inserted / deleted / updated, None for other statements
+  // if the statement failed, it is the number before the error, and these changes are not rolled back
+  pub rows: Option<u32>,
+  // the error if the statement failed
+  pub error: Option<String>,
+  // how the tables of a select / update / delete are scanned (see `query::explain`), it is only made for the slow log
+  // it is made after the statement, so it may differ from the plan used (e.g. the statement changes the statistics)
+  pub plan: Option<String>,
+}
+
+impl LogEntry<'_> {
+  // a line of tab separated fields: start time (seconds since the unix epoch), duration (microseconds), rows ("-" for None), "ok" or the error, statement
+  pub fn line(&self) -> String {
+    let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let rows = self.rows.map(|x| x.to_string()).unwrap_or_else(|| "-".to_owned());
+    let status = self.error.as_ref().map(|e| format!("error: {}", e)).unwrap_or_else(|| "ok".to_owned());
+    format!("{}.{:03}\t{}\t{}\t{}\t{:?}", time.as_secs(), time.subsec_millis(), self.duration.as_micros(), rows, status, self.stmt).replace('\n', " ")
+  }
+}
+
+impl Eval {
+  // `f` is called after every statement executed by `exec` (including those in `exec_all` / `exec_cached`), whether it succeeds or not
+  pub fn set_log(&mut self, f: impl FnMut(&LogEntry) + 'static) { self.log = Some(Box::new(f)); }
+
+  // like `set_log`, but `LogEntry::line` is appended to the file at `path`
+  // failing to write the log doesn't fail the statement
+  pub fn set_log_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(self.set_log(move |e| { let _ = writeln!(f, "{}", e.line()); }))
+  }
+
+  pub fn clear_log(&mut self) { self.log = None; }
+
+  // `f` is called after every statement executed by `exec` that takes at least `threshold`, with the plan of it (if any)
+  // it is independent of `set_log`, both are called for a slow statement if they are set
+  pub fn set_slow_log(&mut self, threshold: Duration, f: impl FnMut(&LogEntry) + 'static) { self.slow_log = Some((threshold, Box::new(f))); }
+
+  // like `set_slow_log`, but `LogEntry::line` is appended to the file at `path`, followed by the lines of the plan indented by a tab
+  pub fn set_slow_log_file(&mut self, threshold: Duration, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(self.set_slow_log(threshold, move |e| {
+      let _ = writeln!(f, "{}", e.line());
+      for l in e.plan.iter().flat_map(|p| p.lines()) { let _ = writeln!(f, "\t{}", l); }
+    }))
+  }
+
+  pub fn clear_slow_log(&mut self) { self.slow_log = None; }
+
+  // the plan of the scans in `sql`, None if it has no scan, or the plan can't be made (e.g. the table is dropped)
+  pub(crate) fn plan(&self, sql: &Stmt) -> Option<String> {
+    let scan;
+    let s = match *sql {
+      Stmt::Select(ref s) | Stmt::Explain(Explain { select: ref s, .. }) | Stmt::CreateTableAs(CreateTableAs { select: ref s, .. }) => s,
+      Stmt::Update(Update { table, ref where_, .. }) | Stmt::Delete(Delete { table, ref where_ }) => {
+        scan = Select { ops: None, tables: vec![TableRef { db: None, table, sample: None }], where_: where_.clone(), order: None, limit: None, offset: 0, derived: vec![] };
+        &scan
+      }
+      Stmt::Returning(ref r) => return self.plan(&r.stmt),
+      _ => return None,
+    };
+    let resolved = self.resolve_temp(s);
+    query::explain_attached(resolved.as_ref().unwrap_or(s), self.db.as_ref()?, |alias| self.attached(alias)).ok()
+  }
+}
\ No newline at end of file
@@ -0,0 +1,63 @@
+use std::{fmt::Write, time::Duration};
+
+use crate::Eval;
+
+// the upper bounds (in seconds) of the buckets of statement durations, the last bucket (+Inf) is implied
+pub const DURATION_BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+// statements executed by `Eval::exec` in this session
+#[derive(Default)]
+pub struct Metrics {
+  pub ok: u64,
+  pub error: u64,
+  // `buckets[i]` is the number of statements taking at most `DURATION_BUCKETS[i]`, and more than the previous one
+  pub buckets: [u64; 8],
+  pub duration_sum: f64,
+}
+
+impl Metrics {
+  pub(crate) fn record(&mut self, duration: Duration, ok: bool) {
+    if ok { self.ok += 1; } else { self.error += 1; }
+    let secs = duration.as_secs_f64();
+    if let Some(idx) = DURATION_BUCKETS.iter().position(|&b| secs <= b) { self.buckets[idx] += 1; }
+    self.duration_sum += secs;
+  }
+}
+
+impl Eval {
+  pub fn metrics(&self) -> &Metrics { &self.metrics }
+
+  // the metrics of statements, and the counters (see `Counters`) and file sizes of the db in use, in the Prometheus text format
+  // there is no server mode to serve it over http, it can be written to a file for the textfile collector of node exporter
+  pub fn metrics_text(&self) -> String {
+    let m = &self.metrics;
+    let mut s = String::new();
+    let mut metric = |name: &str, ty: &str, help: &str, vals: &[(&str, String)]| {
+      writeln!(s, "# HELP {} {}\n# TYPE {} {}", name, help, name, ty).unwrap();
+      for (labels, val) in vals { writeln!(s, "{}{} {}", name, labels, val).unwrap(); }
+    };
+    metric("db_statements_total", "counter", "Statements executed.",
+           &[("{status=\"ok\"}", m.ok.to_string()), ("{status=\"error\"}", m.error.to_string())]);
+    let count = m.ok + m.error;
+    let mut vals = DURATION_BUCKETS.iter().zip(&m.buckets).scan(0, |acc, (b, &n)| {
+      *acc += n;
+      Some((format!("_bucket{{le=\"{}\"}}", b), acc.to_string()))
+    }).collect::<Vec<_>>();
+    vals.push(("_bucket{le=\"+Inf\"}".to_owned(), count.to_string()));
+    vals.push(("_sum".to_owned(), m.duration_sum.to_string()));
+    vals.push(("_count".to_owned(), count.to_string()));
+    let vals = vals.iter().map(|(suffix, val)| (suffix.as_str(), val.clone())).collect::<Vec<_>>();
+    metric("db_statement_duration_seconds", "histogram", "Durations of statements.", &vals);
+    if let Some(db) = &self.db {
+      let c = &db.counters;
+      metric("db_pages_allocated_total", "counter", "Pages allocated.", &[("", c.pages_alloc.to_string())]);
+      metric("db_pages_freed_total", "counter", "Pages freed.", &[("", c.pages_free.to_string())]);
+      metric("db_scans_total", "counter", "Scans of tables.",
+             &[("{method=\"index\"}", c.index_scans.to_string()), ("{method=\"full\"}", c.full_scans.to_string())]);
+      let (size, lob_size) = db.file_sizes();
+      metric("db_file_size_bytes", "gauge", "Sizes of the database files.",
+             &[("{file=\"db\"}", size.to_string()), ("{file=\"lob\"}", lob_size.to_string())]);
+    }
+    s
+  }
+}
\ No newline at end of file
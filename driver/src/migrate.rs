@@ -0,0 +1,21 @@
+use typed_arena::Arena;
+
+use common::{*, Error::*};
+use crate::Eval;
+
+impl Eval {
+  // bring the db in use up to date with the migrations `scripts`, return the number of them applied by this call
+  // `scripts[i]` is the sql text of the (i + 1)-th migration, and the db records how many of them have been applied (see `Db::schema_version`),
+  // so only the ones after them are applied, in order, and calling it again with the same list (or a longer one) applies nothing else
+  // the version is stored after each script succeeds; if a script fails, its error is returned, and the scripts after it are not applied
+  // the statements of the failed script before the error are not undone, so a script should be safe to run again from its start
+  pub fn migrate<'a>(&mut self, scripts: &[&'a str], alloc: &'a Arena<u8>) -> Result<'a, u32> {
+    let version = self.db()?.schema_version();
+    if version as usize > scripts.len() { return Err(SchemaVersionTooNew { version, known: scripts.len() as u32 }); }
+    for (i, code) in scripts.iter().enumerate().skip(version as usize) {
+      self.exec_all(code, alloc, |_| {}, |_| {}).map_err(|e| e.1)?;
+      self.db()?.set_schema_version(i as u32 + 1);
+    }
+    Ok(scripts.len() as u32 - version)
+  }
+}
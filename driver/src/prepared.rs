@@ -0,0 +1,37 @@
+use std::mem;
+use typed_arena::Arena;
+
+use common::*;
+use syntax::ast::*;
+
+// the max number of sql texts in the cache of `Eval::exec_cached`, the cache is cleared when it is full
+pub const CACHE_CAP: usize = 256;
+
+// parsed statements of a sql text, they borrow from `code` and `alloc`, which are owned here and never move (they are boxed)
+// `stmts` is declared first, so it is dropped before what it borrows from
+// only the parsing is cached: names are bound and the plan is chosen in every execution, so an entry never uses the cols, indexes
+// or statistics of an old schema, and a ddl never makes it invalid; what is derived from the schema is checked where it is kept,
+// e.g. `TableHandle` checks the version of its table (see `TablePage::version`)
+pub struct Prepared {
+  // None if `code` has syntax errors, they are reported again in every execution
+  stmts: Option<Vec<Stmt<'static>>>,
+  alloc: Box<Arena<u8>>,
+  code: Box<str>,
+  // the same text may be parsed differently in another dialect
+  pub(crate) dialect: Dialect,
+}
+
+impl Prepared {
+  pub fn new(code: &str, dialect: Dialect) -> Prepared {
+    let (code, alloc) = (Box::<str>::from(code), Box::new(Arena::default()));
+    let stmts = unsafe { syntax::work_in_dialect(&*(&*code as *const str), &*(&*alloc as *const Arena<u8>), &[], dialect).ok().map(|ss| mem::transmute(ss)) };
+    Prepared { stmts, alloc, code, dialect }
+  }
+
+  pub fn stmts(&self) -> Result<&[Stmt]> {
+    match &self.stmts {
+      Some(ss) => Ok(ss),
+      None => syntax::work_in_dialect(&self.code, &self.alloc, &[], self.dialect).map(|_| &[][..]),
+    }
+  }
+}
\ No newline at end of file
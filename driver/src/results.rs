@@ -0,0 +1,96 @@
+use common::*;
+use syntax::ast::*;
+use crate::{Eval, sys::SYS};
+
+// the outputs of selects executed by `Eval::exec`, see `Eval::enable_result_cache`
+pub struct ResultCache {
+  // the cache is cleared when it has `cap` outputs and another one is kept, like the cache of `exec_cached`
+  cap: usize,
+  // the key of a select (see `result_key`) -> (the tables it reads, its output)
+  entries: HashMap<String, (Vec<String>, String)>,
+  // the number of selects whose output is found / not found in the cache
+  pub hits: u64,
+  pub misses: u64,
+}
+
+impl ResultCache {
+  pub fn len(&self) -> usize { self.entries.len() }
+
+  pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+}
+
+// call `f` with every table in the from list of `s`, including the ones of its subqueries and derived tables
+fn select_tables<'a>(s: &Select<'a>, f: &mut impl FnMut(&TableRef<'a>)) {
+  for t in &s.tables { f(t); }
+  for d in &s.derived {
+    if let DerivedSrc::Select(s) = &d.src { select_tables(s, f); }
+  }
+  for cond in &s.where_ {
+    if let Cond::Quant(.., s) | Cond::Sub(_, _, s) = cond { select_tables(s, f); }
+  }
+}
+
+// the key of `s` in the cache, and the tables it reads (including the ones in its subqueries and derived tables)
+// two selects have the same key if they are parsed into the same statement, so spaces, keyword case and how params are bound don't matter
+// None if it can't be cached: the tables of `sys` change in every statement, and a sampled table doesn't have `tablesample` in the key
+pub(crate) fn result_key(s: &Select) -> Option<(String, Vec<String>)> {
+  let (mut tables, mut ok) = (vec![], true);
+  select_tables(s, &mut |t| {
+    if t.db == Some(SYS) || t.sample.is_some() { ok = false; }
+    if t.db != Some(DERIVED) { tables.push(t.table.to_owned()); }
+  });
+  if ok { Some((format!("{:?}", s), tables)) } else { None }
+}
+
+impl Eval {
+  // keep the outputs of at most `cap` selects executed by `exec` (so also by `exec_cached`, `exec_with_params`, ...), and return the kept output
+  // of an identical select (see `result_key`) without executing it again; the outputs kept before are discarded
+  // a kept output is dropped when a table it reads is changed by a statement (including the actions of foreign keys), and all of them are dropped
+  // by a statement other than select / insert / delete / update / explain / show (e.g. a ddl, `use` or `set`)
+  // changes made directly to a db (e.g. by `Eval::db`) are not noticed
+  pub fn enable_result_cache(&mut self, cap: usize) {
+    self.results = Some(ResultCache { cap: cap.max(1), entries: HashMap::default(), hits: 0, misses: 0 });
+    self.watch_changes();
+  }
+
+  pub fn disable_result_cache(&mut self) {
+    self.results = None;
+    if self.change_hooks.is_empty() { self.clear_change_hooks(); }
+  }
+
+  // None if the cache is not enabled
+  pub fn result_cache(&self) -> Option<&ResultCache> { self.results.as_ref() }
+
+  pub(crate) fn cached_result(&mut self, key: &str) -> Option<String> {
+    let r = self.results.as_mut()?;
+    match r.entries.get(key) {
+      Some((_, out)) => (r.hits += 1, Some(out.clone())).1,
+      None => (r.misses += 1, None).1,
+    }
+  }
+
+  pub(crate) fn cache_result(&mut self, key: String, tables: Vec<String>, out: &str) {
+    if let Some(r) = &mut self.results {
+      if r.entries.len() == r.cap { r.entries.clear(); }
+      r.entries.insert(key, (tables, out.to_owned()));
+    }
+  }
+
+  // drop the outputs that read any of `tables`, which are changed by the last statement
+  pub(crate) fn invalidate_results<'a>(&mut self, tables: impl Iterator<Item = &'a str> + Clone) {
+    let ignore_case = self.ignore_case;
+    if let Some(r) = &mut self.results {
+      r.entries.retain(|_, (read, _)| !read.iter().any(|t| tables.clone().any(|t1| name_eq(t, t1, ignore_case))));
+    }
+  }
+
+  // `stmt` may change the outputs of selects in other ways than changing records (which are watched), so all outputs are dropped
+  pub(crate) fn clear_results(&mut self, stmt: &Stmt) {
+    use Stmt::*;
+    match stmt {
+      Select(_) | Insert(_) | Delete(_) | Update(_) | DropPartition { .. } | Returning(_) | Explain(_) | ShowDb(_) | ShowDbs | ShowTable(_) | ShowTables
+      | ShowSetting(_) | CheckForeignKeys | CheckDb => {}
+      _ => if let Some(r) = &mut self.results { r.entries.clear(); }
+    }
+  }
+}
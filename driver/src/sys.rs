@@ -0,0 +1,56 @@
+use std::{fs, process, path::PathBuf, sync::atomic::{AtomicUsize, Ordering::Relaxed}};
+
+use common::{*, BareTy::*};
+use syntax::ast::*;
+use db::Db;
+use crate::Eval;
+
+// the alias of the virtual database of counters, it can't be used by `attach`
+pub const SYS: &str = "sys";
+// for naming the files of `sys`, so different `Eval`s in one process don't conflict
+static SYS_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn uses_sys(tables: &[TableRef]) -> bool { tables.iter().any(|t| t.db == Some(SYS)) }
+
+impl Eval {
+  // rebuild the virtual tables in `sys` from the counters (see `Counters`) of the db in use:
+  // - `sys.counters (name, value)`: pages allocated / freed, index / full scans, and hits / misses of the statement cache (see `exec_cached`)
+  // - `sys.table_counters (table_name, rows_read, rows_inserted, rows_deleted)`: one row for each table
+  // values are int, so they are clamped to `i32::MAX`
+  // `exec` calls it before a select / explain that uses `sys`, `select` and `cursor` see the values of the last refresh
+  pub fn refresh_sys<'a>(&mut self) -> Result<'a, ()> {
+    let db = self.db()?;
+    let c = db.counters.clone();
+    let tables = db.all_table_counters();
+    let path = match self.sys.take() {
+      Some((_, path)) => path, // the old db is dropped before the file is created again
+      None => std::env::temp_dir().join(format!("db-sys-{}-{}", process::id(), SYS_ID.fetch_add(1, Relaxed))),
+    };
+    let mut sys = Db::create(&path)?;
+    let int = |x: u64| CLit::new(Lit::Number(x.min(std::i32::MAX as u64) as f64));
+    let col = |col, ty| ColDecl { col, ty: ColTy::FixTy(FixTy { ty, size: if ty == Char { 255 } else { 0 } }), notnull: true, dft: None };
+    sys.create_table(&CreateTable { table: "counters", cols: vec![col("name", Char), col("value", Int)], cons: vec![] })?;
+    sys.create_table(&CreateTable {
+      table: "table_counters",
+      cols: vec![col("table_name", Char), col("rows_read", Int), col("rows_inserted", Int), col("rows_deleted", Int)],
+      cons: vec![],
+    })?;
+    let vals = [("pages_allocated", c.pages_alloc), ("pages_freed", c.pages_free), ("index_scans", c.index_scans), ("full_scans", c.full_scans),
+      ("cache_hits", self.cache_hits), ("cache_misses", self.cache_misses)];
+    let vals = vals.iter().map(|&(name, x)| vec![CLit::new(Lit::Str(name)), int(x)]).collect();
+    query::insert(&Insert { table: "counters", vals, cols: None }, &mut sys).map_err(|e| e.1)?;
+    let vals = tables.iter().map(|(name, t)| vec![CLit::new(Lit::Str(name)), int(t.read), int(t.inserted), int(t.deleted)]).collect::<Vec<_>>();
+    if !vals.is_empty() { // it never fails, table names are at most 255 bytes (see `MAX_NAME`)
+      query::insert(&Insert { table: "table_counters", vals, cols: None }, &mut sys).unwrap();
+    }
+    self.sys = Some((sys, path));
+    Ok(self.share_settings())
+  }
+
+  pub(crate) fn remove_sys(&mut self) {
+    if let Some((sys, path)) = self.sys.take() {
+      drop(sys);
+      let _ = (fs::remove_file(&path), fs::remove_file(path.with_extension(LOB_SUFFIX)));
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,51 @@
+use std::{fs, process, sync::atomic::{AtomicUsize, Ordering::Relaxed}};
+
+use common::*;
+use syntax::ast::*;
+use db::Db;
+use crate::Eval;
+
+// the alias of the database of temporary tables, it can't be used by `attach`
+// a temporary table can also be referred to without it, and it hides a table of the same name in the db in use
+pub const TEMP: &str = "temp";
+// for naming the files of `TEMP`, like `SYS_ID`
+static TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Eval {
+  // `create temporary table`, the database `TEMP` is created by the first one, and removed with all its tables when `self` is dropped
+  pub(crate) fn create_temp_table<'a>(&mut self, c: &CreateTable<'a>) -> Result<'a, ()> {
+    if self.temp.is_none() {
+      let path = std::env::temp_dir().join(format!("db-temp-{}-{}", process::id(), TEMP_ID.fetch_add(1, Relaxed)));
+      self.temp = Some((Db::create(&path)?, path));
+      self.share_settings();
+    }
+    self.temp.as_mut().unwrap().0.create_table(c)
+  }
+
+  fn is_temp(&self, table: &str) -> bool {
+    self.temp.as_ref().map_or(false, |(temp, _)| temp.table(table).is_ok())
+  }
+
+  // the database that `table` is in, `TEMP` if it is a temporary table, otherwise the db in use
+  pub(crate) fn table_db<'a>(&mut self, table: &str) -> Result<'a, &mut Db> {
+    if self.is_temp(table) { Ok(&mut self.temp.as_mut().unwrap().0) } else { self.db() }
+  }
+
+  // `s` with the temporary tables in its from list qualified by `TEMP`, None if it has no one
+  pub(crate) fn resolve_temp<'a>(&self, s: &Select<'a>) -> Option<Select<'a>> {
+    let is_temp = |t: &TableRef| t.db.is_none() && self.is_temp(t.table);
+    if !s.tables.iter().any(is_temp) { return None; }
+    let mut s = s.clone();
+    for t in &mut s.tables {
+      if is_temp(t) { t.db = Some(TEMP); }
+    }
+    Some(s)
+  }
+
+  pub(crate) fn remove_temp(&mut self) {
+    if let Some((temp, path)) = self.temp.take() {
+      drop(temp);
+      let _ = (fs::remove_file(&path), fs::remove_file(path.with_extension(LOB_SUFFIX)));
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,109 @@
+use std::{fmt, str};
+use serde::ser::{self, Serialize, Serializer, SerializeStruct, Impossible};
+use typed_arena::Arena;
+
+use common::{*, Error::*};
+use syntax::ast::Insert;
+use crate::Eval;
+
+impl Eval {
+  // insert `rows` (usually structs deriving `Serialize`) into `table`, the fields of a struct are matched with cols by name
+  // `None` is null, and a string can be the value of a date col; the strings are kept in `alloc`, like the sql text in `exec_all`
+  pub fn insert_rows<'a, T: Serialize>(&mut self, table: &'a str, rows: &[T], alloc: &'a Arena<u8>) -> ModifyResult<'a, u32> {
+    let rows = rows.iter().map(|r| match r.serialize(Ser) {
+      Ok(Out::Row(r)) => Ok(r),
+      Ok(Out::Val(_)) => Err(InvalidRow("a row must be a struct".to_owned())),
+      Err(e) => Err(InvalidRow(e.0)),
+    }).collect::<Result<Vec<_>>>()?;
+    if rows.is_empty() { return Ok(0); }
+    let cols = rows[0].iter().map(|&(col, _)| col).collect();
+    let vals = rows.into_iter().map(|r| r.into_iter().map(|(_, v)| CLit::new(match v {
+      Value::Lit(x) => x,
+      Value::Str(s) => Lit::Str(unsafe { str::from_utf8_unchecked(alloc.alloc_extend(s.bytes())) }),
+    })).collect()).collect();
+    query::insert(&Insert { table, vals, cols: Some(cols) }, self.db()?)
+  }
+}
+
+#[derive(Debug)]
+struct SerError(String);
+
+impl fmt::Display for SerError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(&self.0) }
+}
+
+impl std::error::Error for SerError {}
+
+impl ser::Error for SerError {
+  fn custom<T: fmt::Display>(msg: T) -> Self { SerError(msg.to_string()) }
+}
+
+// a value of a col, a string is owned until it is copied to the arena
+enum Value {
+  Lit(Lit<'static>),
+  Str(String),
+}
+
+// a struct is a row of (field name, value), and other supported types are values
+enum Out {
+  Val(Value),
+  Row(Vec<(&'static str, Value)>),
+}
+
+struct Ser;
+
+struct RowSer(Vec<(&'static str, Value)>);
+
+fn unsupported<T>(what: &str) -> std::result::Result<T, SerError> { Err(SerError(format!("{} is not supported as a value", what))) }
+
+macro_rules! number {
+  ($($f: ident: $ty: ty),*) => { $(fn $f(self, v: $ty) -> std::result::Result<Out, SerError> { Ok(Out::Val(Value::Lit(Lit::Number(v as f64)))) })* };
+}
+
+impl Serializer for Ser {
+  type Ok = Out;
+  type Error = SerError;
+  type SerializeSeq = Impossible<Out, SerError>;
+  type SerializeTuple = Impossible<Out, SerError>;
+  type SerializeTupleStruct = Impossible<Out, SerError>;
+  type SerializeTupleVariant = Impossible<Out, SerError>;
+  type SerializeMap = Impossible<Out, SerError>;
+  type SerializeStruct = RowSer;
+  type SerializeStructVariant = Impossible<Out, SerError>;
+
+  fn serialize_bool(self, v: bool) -> std::result::Result<Out, SerError> { Ok(Out::Val(Value::Lit(Lit::Bool(v)))) }
+  number!(serialize_i8: i8, serialize_i16: i16, serialize_i32: i32, serialize_i64: i64, serialize_u8: u8, serialize_u16: u16,
+    serialize_u32: u32, serialize_u64: u64, serialize_f32: f32, serialize_f64: f64);
+  fn serialize_char(self, v: char) -> std::result::Result<Out, SerError> { Ok(Out::Val(Value::Str(v.to_string()))) }
+  fn serialize_str(self, v: &str) -> std::result::Result<Out, SerError> { Ok(Out::Val(Value::Str(v.to_owned()))) }
+  fn serialize_bytes(self, _: &[u8]) -> std::result::Result<Out, SerError> { unsupported("bytes") }
+  fn serialize_none(self) -> std::result::Result<Out, SerError> { Ok(Out::Val(Value::Lit(Lit::Null))) }
+  fn serialize_some<T: ?Sized + Serialize>(self, v: &T) -> std::result::Result<Out, SerError> { v.serialize(self) }
+  fn serialize_unit(self) -> std::result::Result<Out, SerError> { Ok(Out::Val(Value::Lit(Lit::Null))) }
+  fn serialize_unit_struct(self, _: &'static str) -> std::result::Result<Out, SerError> { Ok(Out::Val(Value::Lit(Lit::Null))) }
+  // the name of a variant without fields, like a string
+  fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> std::result::Result<Out, SerError> { self.serialize_str(variant) }
+  fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _: &'static str, v: &T) -> std::result::Result<Out, SerError> { v.serialize(self) }
+  fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _: &'static str, _: u32, _: &'static str, _: &T) -> std::result::Result<Out, SerError> { unsupported("enum") }
+  fn serialize_seq(self, _: Option<usize>) -> std::result::Result<Self::SerializeSeq, SerError> { unsupported("sequence") }
+  fn serialize_tuple(self, _: usize) -> std::result::Result<Self::SerializeTuple, SerError> { unsupported("tuple") }
+  fn serialize_tuple_struct(self, _: &'static str, _: usize) -> std::result::Result<Self::SerializeTupleStruct, SerError> { unsupported("tuple struct") }
+  fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> std::result::Result<Self::SerializeTupleVariant, SerError> { unsupported("enum") }
+  fn serialize_map(self, _: Option<usize>) -> std::result::Result<Self::SerializeMap, SerError> { unsupported("map") }
+  fn serialize_struct(self, _: &'static str, len: usize) -> std::result::Result<RowSer, SerError> { Ok(RowSer(Vec::with_capacity(len))) }
+  fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> std::result::Result<Self::SerializeStructVariant, SerError> { unsupported("enum") }
+}
+
+impl SerializeStruct for RowSer {
+  type Ok = Out;
+  type Error = SerError;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, v: &T) -> std::result::Result<(), SerError> {
+    match v.serialize(Ser)? {
+      Out::Val(v) => (self.0.push((key, v)), Ok(())).1,
+      Out::Row(_) => unsupported(&format!("struct (in field `{}`)", key)),
+    }
+  }
+
+  fn end(self) -> std::result::Result<Out, SerError> { Ok(Out::Row(self.0)) }
+}
@@ -1,10 +1,11 @@
 use unchecked_unwrap::UncheckedUnwrap;
+use std::mem::size_of;
 
 use common::{*, Error::*, BareTy::*};
-use db::{Db, is_null, hash_pks};
+use db::{Db, is_null, hash_pks, coerce, eval_dft_fn};
 use syntax::ast::*;
 use physics::*;
-use crate::{Index, handle_all};
+use crate::{Index, RTree, handle_all, in_index, fulltext_build, spatial_point};
 
 // some alter operation cannot be put in `db` crate, because the need some index operation, and `index` crate depends on `db` crate
 
@@ -13,52 +14,191 @@ use crate::{Index, handle_all};
 // mainly because even if you did that, there is no serious consequence
 pub fn create_index<'a>(db: &mut Db, c: &CreateIndex<'a>) -> Result<'a, ()> {
   unsafe {
-    for &tp_id in db.dp().tables() {
+    for tp_id in db.tables() {
       for ci in db.get_page::<TablePage>(tp_id).cols() {
         if ci.idx_name().filter(|&x| x == c.index).is_some() { return Err(DupIndex(c.index)); }
       }
     }
     let (tp_id, tp) = db.get_tp(c.table)?;
-    let ci = tp.get_ci(c.col)?;
-    if ci.ty.is_varchar() { return Err(UnsupportedVarcharOp(c.col)); }
+    let ci = tp.get_ci(c.col, db.ignore_case)?;
+    if c.fulltext {
+      match ci.ty { char!() | varchar!() => {}, ty => return Err(InvalidFullTextTy(ty)) }
+    } else if ci.ty.is_varchar() { return Err(UnsupportedVarcharOp(c.col)); }
+    let spatial = match c.spatial {
+      Some(y) => {
+        let y_ci = tp.get_ci(y, db.ignore_case)?;
+        if y_ci.p() == ci.p() { return Err(DupCol(y)); }
+        for ci in &[&*ci, &*y_ci] {
+          match ci.ty { float!() => {}, ty => return Err(InvalidSpatialTy(ty)) }
+        }
+        Some(y_ci.idx(&tp.cols))
+      }
+      None => None,
+    };
+    if let Some(prefix) = c.prefix {
+      match ci.ty { char!(size) if prefix != 0 && prefix < size => {}, _ => return Err(InvalidIndexPrefix { col: c.col, prefix }) }
+    }
+    if let Some(func) = c.func {
+      match ci.ty { char!() => {}, ty => return Err(InvalidStrFnTy { func, ty }) }
+    }
+    let partial = match &c.where_ { Some(cond) => Some(check_partial(db, tp, ci, cond)?), None => None };
     if ci.index == !0 {
-      db.alloc_index(ci, c.index)?;
-      insert_all(db, tp_id, tp, ci);
+      if let Some(y_id) = spatial {
+        db.alloc_rtree(ci, y_id, c.index)?;
+      } else {
+        ci.flags.set(ColFlags::FULLTEXT, c.fulltext); // `alloc_index` uses it to decide the key size
+        db.alloc_index(ci, c.index).map_err(|e| (ci.flags.remove(ColFlags::FULLTEXT), e).1)?;
+        (ci.flags.set(ColFlags::DESC, c.desc), ci.set_idx_fn(c.func));
+        if let Some(prefix) = c.prefix {
+          ci.idx_prefix = prefix;
+          db.get_page::<IndexPage>(ci.index).init(true, ci.idx_key_size());
+        }
+      }
+      if let Some(partial) = partial { write_partial(db, ci, partial); }
+      build_all(db, tp_id, tp, ci);
+    }
+    Ok(())
+  }
+}
+
+// check the predicate of the partial index on `ci`, return (col id, op, val) to be stored in `PartialPred`
+unsafe fn check_partial<'a>(db: &mut Db, tp: &mut TablePage, ci: &ColInfo, cond: &Cond<'a>) -> Result<'a, (u8, u8, Option<Align4U8>)> {
+  let (col, op, val) = match *cond {
+    Cond::Cmp(op, col, Atom::Lit(val)) if !val.is_null() => (col, op as u8, Some(val)),
+    Cond::Null(col, null) => (col, if null { PARTIAL_NULL } else { PARTIAL_NOTNULL }, None),
+    _ => return Err(InvalidPartialCond(cond.lhs_col().col)),
+  };
+  if let Some(t) = col.table { if col.db.is_some() || t != tp.name() { return Err(NoSuchTable(t)); } }
+  let p_ci = tp.get_ci(col.col, db.ignore_case)?;
+  let val = match val {
+    Some(_) if p_ci.ty.is_varchar() => return Err(InvalidPartialCond(col.col)),
+    Some(val) => {
+      let buf = Align4U8::new(p_ci.ty.size() as usize);
+      Some((db.lit2ptr(buf.ptr, p_ci.ty.fix_ty(), val)?, buf).1)
+    }
+    None => None,
+  };
+  let size = val.as_ref().map(|v| v.size).unwrap_or(0);
+  if ci.check != !0 && ci.partial_off(db.get_page::<CheckPage>(ci.check >> 1)) + size_of::<PartialPred>() + size > MAX_CHECK_BYTES {
+    return Err(CheckTooLong(ci.name()));
+  }
+  Ok((p_ci.idx(&tp.cols) as u8, op, val))
+}
+
+// store the predicate in the check page of `ci` (allocate one if there isn't)
+unsafe fn write_partial(db: &mut Db, ci: &mut ColInfo, (ci_id, op, val): (u8, u8, Option<Align4U8>)) {
+  let cp = if ci.check == !0 {
+    let (id, cp) = db.alloc_page::<CheckPage>();
+    ci.check = id << 1;
+    (cp.count = 0, cp).1
+  } else { db.get_page::<CheckPage>(ci.check >> 1) };
+  let p = cp.data.as_mut_ptr().add(ci.partial_off(cp));
+  *(p as *mut PartialPred) = PartialPred { ci_id, op, _rsv: [0; 2] };
+  if let Some(val) = val { p.add(size_of::<PartialPred>()).copy_from_nonoverlapping(val.ptr, val.size); }
+  ci.flags.insert(ColFlags::PARTIAL);
+}
+
+// create `table` with the same cols, constraints, defaults, comments, indexes and partitions as `like`, but no record
+// a foreign key of `like` referring to itself refers to `table` in the copy, and each named index gets a new name (see `fresh_index_name`)
+pub fn create_table_like<'a>(db: &mut Db, table: &'a str, like: &'a str) -> Result<'a, ()> {
+  unsafe {
+    let (tp_id, tp) = db.get_tp(like)?;
+    let (mut cols, mut cons, mut indexes) = (vec![], vec![], vec![]);
+    let pks = tp.primary_cols().map(|ci| ci.name()).collect::<Vec<_>>();
+    if !pks.is_empty() { cons.push(ColCons::Primary(pks)); }
+    for (ci_id, ci) in tp.cols().iter().enumerate() {
+      let col = ci.name();
+      let mut dft = ci.dft_fn().map(Dft::Fn);
+      if ci.check != !0 {
+        let cp = db.get_page::<CheckPage>(ci.check >> 1);
+        let sz = ci.ty.size() as usize;
+        let check = (0..cp.count as usize).map(|i| db.ptr2lit(cp.data.as_ptr().add(i * sz), ci.ty)).collect::<Vec<_>>();
+        if !check.is_empty() { cons.push(ColCons::Check(col, check)); }
+        if (ci.check & 1) == 1 { dft = Some(Dft::Lit(db.ptr2lit(cp.data.as_ptr().add(cp.count as usize * sz), ci.ty))); }
+      }
+      cols.push(ColDecl { col, ty: ci.ty, notnull: ci.flags.contains(ColFlags::NOTNULL), dft });
+      if ci.flags.contains(ColFlags::UNIQUE) { cons.push(ColCons::Unique(col)); }
+      if ci.f_table != !0 {
+        let f_tp = db.get_page::<TablePage>(ci.f_table);
+        let f_table = if ci.f_table == tp_id { table } else { f_tp.name() };
+        cons.push(ColCons::Foreign { col, f_table, f_col: f_tp.cols.get_unchecked(ci.f_col as usize).name(), on_delete: ci.on_delete(), on_update: ci.on_update() });
+      }
+      if let Some(c) = db.comment(tp, Some(ci_id as u32)) { cons.push(ColCons::Comment(col, c)); }
+      match ci.idx_name() {
+        Some(index) if !index.is_empty() => {
+          let where_ = db.partial_pred(ci).map(|(&mut PartialPred { ci_id, op, .. }, val)| {
+            let p_ci = tp.cols.get_unchecked(ci_id as usize);
+            let p_col = ColRef { db: None, table: None, col: p_ci.name() };
+            match op {
+              PARTIAL_NULL | PARTIAL_NOTNULL => Cond::Null(p_col, op == PARTIAL_NULL),
+              _ => Cond::Cmp(CmpOp::ALL[op as usize], p_col, Atom::Lit(db.ptr2lit(val, p_ci.ty))),
+            }
+          });
+          let spatial = if ci.idx_spatial != 0 { Some(tp.cols.get_unchecked(ci.idx_spatial as usize - 1).name()) } else { None };
+          indexes.push((index, CreateIndex {
+            index: "", table, col, desc: ci.flags.contains(ColFlags::DESC), prefix: if ci.idx_prefix != 0 { Some(ci.idx_prefix) } else { None },
+            func: ci.idx_fn(), where_, fulltext: ci.flags.contains(ColFlags::FULLTEXT), spatial,
+          }));
+        }
+        _ => {}
+      }
+    }
+    db.create_table(&CreateTable { table, cols, cons })?;
+    let new_tp = db.get_tp(table).unchecked_unwrap().1;
+    if let Some(c) = db.comment(tp, None) { db.write_comment(new_tp, None, c); }
+    db.copy_partitions(tp, new_tp);
+    for (index, c) in indexes {
+      let name = fresh_index_name(db, index);
+      // it is valid on `like`, and the new table has no record, so it never fails
+      create_index(db, &CreateIndex { index: &name, ..c }).unwrap();
     }
     Ok(())
   }
 }
 
+// a name for a copy of the index `index`, which is not used by any index in `db`, like "idx" -> "idx1"
+unsafe fn fresh_index_name(db: &mut Db, index: &str) -> String {
+  let used = db.tables().flat_map(|tp_id| db.pr().get_page::<TablePage>(tp_id).cols().iter().filter_map(|ci| ci.idx_name()))
+    .collect::<HashSet<_>>();
+  (1..).map(|n: u32| {
+    let n = n.to_string();
+    let mut end = index.len().min(MAX_IDX_NAME - n.len());
+    while !index.is_char_boundary(end) { end -= 1; }
+    index[..end].to_owned() + &n
+  }).find(|name| !used.contains(name.as_str())).unchecked_unwrap()
+}
+
 pub fn add_foreign<'a>(db: &mut Db, a: &AddForeign<'a>) -> Result<'a, ()> {
   unsafe {
-    let (tp_id, tp) = db.get_tp(a.table)?;
-    let ci = tp.get_ci(a.col)?;
-    let ci_id = ci.idx(&tp.cols);
-    if ci.f_table != !0 { return Err(DupConstraint(a.col)); }
-    let (f_tp_id, f_tp) = db.get_tp(a.f_table)?;
-    let f_ci = f_tp.get_ci(a.f_col)?;
-    let f_ci_id = f_ci.idx(&f_tp.cols);
-    if !f_ci.unique(f_tp.primary_cols().count()) { return Err(ForeignOnNotUnique(a.f_col)); }
+    // the referenced col may be in the same table (or even be the col itself), so handles are used instead of holding 2 `&mut ColInfo` of them
+    let t = db.table_handle(a.table)?;
+    let c = t.col(db, a.col)?;
+    if c.info(db)?.f_table != !0 { return Err(DupConstraint(a.col)); }
+    if db.part_col(&t.page(db)?) == Some(c.id()) { return Err(ModifyPartitioned(a.col)); }
+    let f_c = db.table_handle(a.f_table)?.col(db, a.f_col)?;
+    let (ci, f_ci) = (c.info(db)?, f_c.info(db)?);
+    if !f_ci.unique(f_c.table().page(db)?.primary_cols().count()) { return Err(ForeignOnNotUnique(a.f_col)); }
     debug_assert!(!f_ci.ty.is_varchar());
-    if f_ci.ty != ci.ty { return Err(IncompatibleForeignTy { foreign: f_ci.ty, own: ci.ty }); }
+    if !ci.ty.foreign_compatible(f_ci.ty) { return Err(IncompatibleForeignTy { foreign: f_ci.ty, own: ci.ty }); }
+    let (off, ty, f_ty) = (ci.off as usize, ci.ty, f_ci.ty);
+    let records = t.records(db)?.collect::<Vec<_>>();
     macro_rules! handle {
       ($ty: ident) => {{
-        let index = Index::<{ $ty }>::new(db, f_tp_id, f_ci_id);
-        for (data, _) in db.record_iter(tp) {
-          let ptr = data.add(ci.off as usize);
-          if !is_null(data, ci_id) && !index.contains(ptr) {
-            return Err(PutNonexistentForeign { col: a.col, val: db.ptr2lit(ptr, ci.ty) });
+        let index = Index::<{ $ty }>::new(db, f_c.table().id(), f_c.id());
+        for &(data, _) in &records {
+          let (ptr, mut buf) = (data.add(off), None);
+          if !is_null(data, c.id()) && !coerce(ptr, ty, f_ty, &mut buf).map(|p| index.contains(p)).unwrap_or(false) {
+            return Err(PutNonexistentForeign { col: a.col, val: db.ptr2lit(ptr, ty) });
           }
         }
       }};
     }
-    handle_all!(ci.ty.fix_ty().ty, handle);
+    handle_all!(ty.fix_ty().ty, handle);
     // now no error can occur
-    (ci.f_table = f_tp_id, ci.f_col = f_ci_id as u8);
-    if ci.index == !0 {
-      db.alloc_index(ci, "").unchecked_unwrap();
-      insert_all(db, tp_id, tp, ci);
-    }
+    let tp = db.get_page::<TablePage>(t.id());
+    let ci = tp.cols.get_unchecked_mut(c.id() as usize);
+    (ci.f_table = f_c.table().id(), ci.f_col = f_c.id() as u8, ci.f_action = a.on_delete as u8 | (a.on_update as u8) << 2);
+    full_index(db, t.id(), tp, ci.pr());
     Ok(())
   }
 }
@@ -70,8 +210,8 @@ pub fn add_primary<'a>(db: &mut Db, table: &'a str, cols: &[&'a str]) -> Result<
     let old_len = pks.len();
     pks.reserve(cols.len());
     for (idx, &col) in cols.iter().enumerate() {
-      if cols.iter().take(idx).any(|&x| x == col) { return Err(DupCol(col)); }
-      let ci = tp.get_ci(col)?;
+      if cols.iter().take(idx).any(|&x| db.name_eq(x, col)) { return Err(DupCol(col)); }
+      let ci = tp.get_ci(col, db.ignore_case)?;
       if ci.flags.contains(ColFlags::PRIMARY) { return Err(DupConstraint(col)); }
       if ci.ty.is_varchar() { return Err(UnsupportedVarcharOp(col)); }
       pks.push(ci);
@@ -99,8 +239,8 @@ pub fn drop_primary<'a>(db: &mut Db, table: &'a str, cols: &[&'a str]) -> Result
     let mut pks = tp.primary_cols().collect::<Vec<_>>();
     let mut new_len = pks.len();
     for (idx, &col) in cols.iter().enumerate() {
-      if cols.iter().take(idx).any(|&x| x == col) { return Err(DupCol(col)); }
-      let ci = &*tp.get_ci(col)?;
+      if cols.iter().take(idx).any(|&x| db.name_eq(x, col)) { return Err(DupCol(col)); }
+      let ci = &*tp.get_ci(col, db.ignore_case)?;
       if let Some(idx) = pks.iter().position(|&x| x.p() == ci.p()) {
         let p = pks.as_mut_ptr();
         p.add(idx).swap(p.add((new_len -= 1, new_len).1));
@@ -119,51 +259,69 @@ pub fn drop_primary<'a>(db: &mut Db, table: &'a str, cols: &[&'a str]) -> Result
   }
 }
 
+// a table has at most MAX_COL cols; if the new col doesn't fit in the pages after the table page, the table page is moved (see `Db::move_tp`),
+// so the table gets a new id; the handles of it are invalid anyway, because the table gets a new version
 pub fn add_col<'a>(db: &mut Db, table: &'a str, col: &ColDecl<'a>) -> Result<'a, ()> {
   unsafe {
-    let (tp_id, tp) = db.get_tp(table)?;
+    let (mut tp_id, mut tp) = db.get_tp(table)?;
+    if tp.part != !0 { return Err(ModifyPartitioned(table)); }
     if tp.col_num == MAX_COL as u8 { return Err(ColTooMany(tp.col_num as usize + 1)); }
-    if col.col.len() > MAX_COL_NAME { return Err(ColNameTooLong(col.col)); }
-    if tp.get_ci(col.col).is_ok() { return Err(DupCol(col.col)); }
-    let dft = col.dft.unwrap_or(CLit::new(Lit::Null));
+    if col.col.len() > MAX_NAME { return Err(ColNameTooLong(col.col)); }
+    if tp.get_ci(col.col, db.ignore_case).is_ok() { return Err(DupCol(col.col)); }
+    // existing records get the value of `dft_fn` (evaluated for each of them) or `dft`
+    let (dft, dft_fn) = match col.dft {
+      Some(Dft::Fn(f)) => (CLit::new(Lit::Null), Some(f)),
+      Some(Dft::Lit(dft)) => (dft, None),
+      None => (CLit::new(Lit::Null), None),
+    };
+    if let Some(f) = dft_fn {
+      if col.ty.is_varchar() { return Err(UnsupportedVarcharOp(col.col)); }
+      if !f.valid_for(col.ty) { return Err(InvalidDftFnTy { func: f, ty: col.ty }); }
+    }
     let dft = if !dft.is_null() {
       if col.ty.is_varchar() { return Err(UnsupportedVarcharOp(col.col)); }
       let buf = Align4U8::new(col.ty.size() as usize);
       Some((db.lit2ptr(buf.ptr, col.ty.fix_ty(), dft)?, buf).1)
-    } else if col.notnull && tp.count != 0 { return Err(PutNullOnNotNull); } else { None };
-    // basically copied from Db::create_table...
-    let mut size = ((tp.col_num + 1) as usize + 31) / 32 * 4;
-    for ci in tp.cols() { size += ci.ty.size() as usize; }
-    size = (size + 3) & !3;
-    if size > MAX_DATA_BYTE { return Err(ColSizeTooBig(size)); }
+    } else if col.notnull && tp.count != 0 && dft_fn.is_none() { return Err(PutNullOnNotNull); } else { None };
     // now no error can occur
     let bs_size = ((tp.col_num as usize + 31) / 32 * 4, ((tp.col_num + 1) as usize + 31) / 32 * 4);
+    let extra = table_pages(tp.col_num as usize + 1, tp.names + long_name_bytes(col.col, MAX_COL_NAME));
+    if extra != tp.extra_pages() { // the new col doesn't fit in the current col pages or name pages
+      let (id, tp1) = db.move_tp(tp_id, extra);
+      (tp_id = id, tp = tp1);
+    }
 
-    let iter = db.record_iter(tp);
-    tp.cols.get_unchecked_mut(tp.col_num as usize).init(col.ty, 0, col.col, col.notnull); // `off` will be overwritten in `calc_size`
+    let (iter, old_overflow) = (db.record_iter(tp), tp.overflow());
+    tp.cols.get_unchecked_mut(tp.col_num as usize).init(col.ty, 0, col.notnull); // `off` will be overwritten in `calc_size`
+    tp.set_col_name(tp.col_num as usize, col.col);
     tp.col_num += 1;
     calc_size(tp);
+    tp.version = db.new_version();
 
-    let (size, cap, col_num) = (tp.size as usize, tp.cap, tp.col_num as usize);
+    let (size, cap, overflow, col_num) = (tp.size as usize, tp.cap, tp.overflow(), tp.col_num as usize);
     if let Some(dft) = dft.as_ref() {
       let (cp_id, cp) = db.alloc_page::<CheckPage>();
       tp.cols.get_unchecked_mut(col_num - 1).check = (cp_id << 1) | 1;
       cp.count = 0;
       cp.data.as_mut_ptr().copy_from_nonoverlapping(dft.ptr, dft.size);
     }
+    if let Some(f) = dft_fn { tp.cols.get_unchecked_mut(col_num - 1).dft_fn = f as u8 + 1; }
     let last_off = tp.cols.get_unchecked_mut(col_num - 1).off as usize;
-    let (mut dp_id, mut dp) = db.alloc_page::<DataPage>();
-    dp.init(!0);
+    let (mut dp_id, mut dp) = db.alloc_pages::<DataPage>(overflow);
+    dp.init(!0, 0, tp.bloom_bits());
     for (old, _) in iter {
-      let new = alloc_slot(db, &mut dp_id, &mut dp, cap, size);
+      let new = alloc_slot(db, &mut dp_id, &mut dp, cap, size, overflow);
       new.copy_from_nonoverlapping(old, bs_size.0);
       new.add(bs_size.1).copy_from_nonoverlapping(old.add(bs_size.0), last_off - bs_size.1);
       if let Some(dft) = dft.as_ref() {
         bsdel(new as *mut u32, col_num - 1);
         new.add(last_off).copy_from_nonoverlapping(dft.ptr, dft.size);
+      } else if let Some(f) = dft_fn {
+        bsdel(new as *mut u32, col_num - 1);
+        eval_dft_fn(new.add(last_off), col.ty.fix_ty(), f);
       } else { bsset(new as *mut u32, col_num - 1); }
     }
-    reset_data(db, tp_id, tp, dp_id, dp);
+    reset_data(db, tp_id, tp, old_overflow, dp_id, dp);
     index_unique_primary(db, tp_id, tp); // it is currently useless, because `add_col` won't affect primary keys
     Ok(())
   }
@@ -172,8 +330,9 @@ pub fn add_col<'a>(db: &mut Db, table: &'a str, col: &ColDecl<'a>) -> Result<'a,
 pub fn drop_col<'a>(db: &mut Db, table: &'a str, col: &'a str) -> Result<'a, ()> {
   unsafe {
     let (tp_id, tp) = db.get_tp(table)?;
+    if tp.part != !0 { return Err(ModifyPartitioned(table)); }
     let col_num = tp.col_num as usize;
-    let ci = tp.get_ci(col)?;
+    let ci = tp.get_ci(col, db.ignore_case)?;
     let ci_id = ci.idx(&tp.cols) as usize;
     if col_num == 1 { return Err(ColTooFew); }
     if db.foreign_links_to(tp_id).any(|x| x.2 == ci_id as u8) { return Err(ModifyTableWithForeignLink(table)); }
@@ -187,7 +346,23 @@ pub fn drop_col<'a>(db: &mut Db, table: &'a str, col: &'a str) -> Result<'a, ()>
     // the padding in right side may change, so need to copy data one by one; r_size_off is Vec<(size, old off, new off)>
     let mut r_size_off = tp.cols.get_unchecked(ci_id + 1..col_num).iter().map(|ci| (ci.ty.size(), ci.off, 0u16)).collect::<Vec<_>>();
 
-    if ci.index != !0 { db.dealloc_index(ci.index); }
+    // a partial index whose predicate is on the dropped col is also dropped, col ids in other predicates are adjusted
+    // so is a spatial index whose y col is the dropped col
+    for (idx, ci1) in tp.cols().iter().enumerate() {
+      if idx == ci_id { continue; }
+      match db.partial_pred(ci1) {
+        Some((pred, _)) if pred.ci_id as usize == ci_id => db.clear_index(ci1.pr()),
+        Some((pred, _)) if pred.ci_id as usize > ci_id => pred.ci_id -= 1,
+        _ => {}
+      }
+      match ci1.idx_spatial as usize {
+        0 => {}
+        y if y - 1 == ci_id => db.clear_index(ci1.pr()),
+        y if y - 1 > ci_id => ci1.pr().idx_spatial -= 1,
+        _ => {}
+      }
+    }
+    if ci.index != !0 { db.dealloc_index(ci); }
     if ci.check != !0 { db.dealloc_page(ci.check >> 1); }
     if ci.ty.is_varchar() {
       for (data, _) in db.record_iter(tp) {
@@ -195,19 +370,24 @@ pub fn drop_col<'a>(db: &mut Db, table: &'a str, col: &'a str) -> Result<'a, ()>
       }
     }
 
-    let iter = db.record_iter(tp); // it will iterate over old data because necessary information is copied into iter
+    db.drop_col_comment(tp, ci_id as u32);
+
+    let (iter, old_overflow) = (db.record_iter(tp), tp.overflow()); // it will iterate over old data because necessary information is copied into iter
     tp.cols.as_mut_ptr().add(ci_id).copy_from(tp.cols.as_mut_ptr().add(ci_id + 1), col_num - ci_id - 1);
+    for ci in tp.cols.get_unchecked_mut(ci_id..col_num - 1) { ci.name_moved(size_of::<ColInfo>() as u32); }
     tp.col_num -= 1;
     calc_size(tp);
+    tp.version = db.new_version();
+    for page in tp.extra_pages() + 1..=table_pages(col_num, tp.names) { db.dealloc_page(tp_id + page); } // the col page no longer used
 
-    let (size, cap, col_num) = (tp.size as usize, tp.cap, tp.col_num as usize);
+    let (size, cap, overflow, col_num) = (tp.size as usize, tp.cap, tp.overflow(), tp.col_num as usize);
     for idx in ci_id..col_num {
       r_size_off.get_unchecked_mut(idx - ci_id).2 = tp.cols.get_unchecked(idx).off;
     }
-    let (mut dp_id, mut dp) = db.alloc_page::<DataPage>();
-    dp.init(!0);
+    let (mut dp_id, mut dp) = db.alloc_pages::<DataPage>(overflow);
+    dp.init(!0, 0, tp.bloom_bits());
     for (old, _) in iter {
-      let new = alloc_slot(db, &mut dp_id, &mut dp, cap, size);
+      let new = alloc_slot(db, &mut dp_id, &mut dp, cap, size, overflow);
       (new as *mut u32).write_bytes(0, bs_size.1);
       for i in 0..ci_id {
         if is_null(old, i as u32) { bsset(new as *mut u32, i); }
@@ -220,12 +400,73 @@ pub fn drop_col<'a>(db: &mut Db, table: &'a str, col: &'a str) -> Result<'a, ()>
         new.add(new_off as usize).copy_from_nonoverlapping(old.add(old_off as usize), size as usize);
       }
     }
-    reset_data(db, tp_id, tp, dp_id, dp);
+    reset_data(db, tp_id, tp, old_overflow, dp_id, dp);
     index_unique_primary(db, tp_id, tp);
     Ok(())
   }
 }
 
+// rebuild the index on `col` from the records in table, or all indexes (together with Bloom filters in data pages) if `col` is None
+// the name, kind and predicate of an index are kept, only its pages are replaced
+pub fn reindex<'a>(db: &mut Db, table: &'a str, col: Option<&'a str>) -> Result<'a, ()> {
+  unsafe {
+    let (tp_id, tp) = db.get_tp(table)?;
+    match col {
+      Some(col) => {
+        let ci = tp.get_ci(col, db.ignore_case)?;
+        if ci.index == !0 { return Err(NoColIndex(col)); }
+        rebuild_index(db, tp_id, tp, ci);
+      }
+      None => {
+        db.bloom_rebuild(tp);
+        for ci in tp.cols() {
+          if ci.index != !0 { rebuild_index(db, tp_id, tp, ci); }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+// move the records of `table` into as few data pages as possible (each partition has at most one page not full), return the number of released pages
+// records get new rids, so the Bloom filters and all indexes are rebuilt; the records themselves (including the varchar refs in them) are unchanged, so are the statistics
+pub fn optimize_table<'a>(db: &mut Db, table: &'a str) -> Result<'a, u32> {
+  unsafe {
+    let (tp_id, tp) = db.get_tp(table)?;
+    let (size, cap, overflow) = (tp.size as usize, tp.cap, tp.overflow());
+    let iter = db.record_iter(tp); // it will iterate over old pages, new pages are not linked to `tp` until all records are moved
+    // the page being filled of each partition, !0 for none
+    let mut cur = vec![!0; db.part_count(tp) as usize];
+    let (mut first, mut new_num) = (!0, 0);
+    for (old, rid) in iter {
+      let old_dp = db.get_page::<DataPage>(rid.page());
+      let fill = cur.get_unchecked_mut(old_dp.part as usize);
+      if *fill == !0 || db.get_page::<DataPage>(*fill).count == cap {
+        let (id, dp) = db.alloc_pages::<DataPage>(overflow);
+        dp.init(first, old_dp.part, tp.bloom_bits());
+        (first = id, *fill = id, new_num += overflow + 1);
+      }
+      let dp = db.get_page::<DataPage>(*fill);
+      let slot = (dp.count as usize, dp.count += 1).0;
+      bsset(dp.used.as_mut_ptr(), slot);
+      dp.data.as_mut_ptr().add(slot * size).copy_from_nonoverlapping(old, size);
+      if db.last_insert == Some(rid) { db.last_insert = Some(Rid::new(*fill, slot as u32)); }
+    }
+    let (mut page, mut old_num) = (tp.first, 0);
+    while page != !0 { (page = db.get_page::<DataPage>(page).next, old_num += overflow + 1); }
+    db.drop_list(tp.first, overflow);
+    tp.first = first;
+    for (part, &id) in cur.iter().enumerate() {
+      *db.free_list(tp, part as u16) = if id == !0 || db.get_page::<DataPage>(id).count == cap { !0 } else { id };
+    }
+    db.bloom_rebuild(tp);
+    for ci in tp.cols() {
+      if ci.index != !0 { rebuild_index(db, tp_id, tp, ci); }
+    }
+    Ok(old_num - new_num)
+  }
+}
+
 unsafe fn calc_size(tp: &mut TablePage) {
   let mut size = (tp.col_num as u16 + 31) / 32 * 4;
   for ci in tp.cols() {
@@ -234,13 +475,13 @@ unsafe fn calc_size(tp: &mut TablePage) {
     size += ci.ty.size();
   }
   size = (size + 3) & !3;
-  (tp.size = size, tp.cap = MAX_DATA_BYTE as u16 / size);
+  (tp.size = size, tp.cap = slot_cap(size, tp.col_num));
 }
 
-unsafe fn alloc_slot(db: &mut Db, dp_id: &mut u32, dp: &mut &mut DataPage, cap: u16, size: usize) -> *mut u8 {
+unsafe fn alloc_slot(db: &mut Db, dp_id: &mut u32, dp: &mut &mut DataPage, cap: u16, size: usize, overflow: u32) -> *mut u8 {
   if dp.count == cap {
-    let (new_dp_id, new_dp) = db.alloc_page::<DataPage>();
-    new_dp.init(*dp_id);
+    let (new_dp_id, new_dp) = db.alloc_pages::<DataPage>(overflow);
+    new_dp.init(*dp_id, 0, bloom_bits(size as u16, cap));
     (*dp_id = new_dp_id, *dp = new_dp);
   }
   let cur = (dp.count as usize, dp.count += 1).0;
@@ -248,44 +489,75 @@ unsafe fn alloc_slot(db: &mut Db, dp_id: &mut u32, dp: &mut &mut DataPage, cap:
   dp.data.as_mut_ptr().add(cur * size)
 }
 
-unsafe fn reset_data(db: &mut Db, tp_id: u32, tp: &mut TablePage, dp_id: u32, dp: &DataPage) {
-  db.drop_list(tp.first);
+// `old_overflow` is the number of overflow pages after each old data page, `tp.overflow()` may have changed
+unsafe fn reset_data(db: &mut Db, tp_id: u32, tp: &mut TablePage, old_overflow: u32, dp_id: u32, dp: &DataPage) {
+  db.drop_list(tp.first, old_overflow);
   tp.first = dp_id;
   tp.first_free = if dp.count == tp.cap { !0 } else { dp_id };
+  (db.bloom_rebuild(tp), db.clear_stats(tp));
   for ci in tp.cols() {
-    if ci.index != !0 {
-      db.dealloc_index(ci.index);
-      let (id, ip) = db.alloc_page::<IndexPage>();
-      ci.pr().index = id;
-      ip.init(true, ci.ty.size());
-      insert_all(db, tp_id, tp, ci);
-    }
+    if ci.index != !0 { rebuild_index(db, tp_id, tp, ci); }
   }
 }
 
+// replace the index of `ci` with a new one of the same kind, built from the records in table
+unsafe fn rebuild_index(db: &mut Db, tp_id: u32, tp: &TablePage, ci: &ColInfo) {
+  db.dealloc_index(ci);
+  new_index(db, tp_id, tp, ci);
+}
+
+// like `rebuild_index`, but the pages of the old index are not deallocated, for an index that can't be walked, see `repair_db`
+pub(crate) unsafe fn new_index(db: &mut Db, tp_id: u32, tp: &TablePage, ci: &ColInfo) {
+  ci.pr().index = if ci.idx_spatial != 0 {
+    let (id, rp) = db.alloc_page::<RTreePage>();
+    (rp.init(true), id).1
+  } else {
+    let (id, ip) = db.alloc_page::<IndexPage>();
+    (ip.init(true, ci.idx_key_size()), id).1
+  };
+  build_all(db, tp_id, tp, ci);
+}
+
 
 unsafe fn index_unique_primary(db: &mut Db, tp_id: u32, tp: &TablePage) {
   for (idx, ci) in tp.cols().iter().enumerate() {
     if ci.flags.contains(ColFlags::PRIMARY) {
-      if ci.index == !0 && !tp.cols().get_unchecked(idx + 1..).iter().any(|ci| ci.flags.contains(ColFlags::PRIMARY)) {
-        db.alloc_index(ci.pr(), "").unchecked_unwrap();
-        insert_all(db, tp_id, tp, ci);
+      if !tp.cols().get_unchecked(idx + 1..).iter().any(|ci| ci.flags.contains(ColFlags::PRIMARY)) {
+        full_index(db, tp_id, tp, ci.pr());
       }
       break;
     }
   }
 }
 
-unsafe fn insert_all(db: &mut Db, tp_id: u32, tp: &TablePage, ci: &ColInfo) {
+// constraints are checked by searching the index, so a prefix / expression / partial / full-text / spatial index is not enough, replace it with a full one
+unsafe fn full_index(db: &mut Db, tp_id: u32, tp: &TablePage, ci: &mut ColInfo) {
+  if ci.index == !0 {
+    db.alloc_index(ci, "").unchecked_unwrap();
+  } else if !ci.idx_exact() {
+    db.dealloc_index(ci);
+    let (id, ip) = db.alloc_page::<IndexPage>();
+    (ci.index = id, ci.idx_prefix = 0, ci.idx_spatial = 0, ci.set_idx_fn(None), ci.flags.remove(ColFlags::FULLTEXT), db.clear_partial(ci));
+    ip.init(true, ci.idx_key_size());
+  } else { return; }
+  build_all(db, tp_id, tp, ci);
+}
+
+// fill the (empty) index of `ci` with all records in `tp` that should be in it (see `in_index`)
+unsafe fn build_all(db: &mut Db, tp_id: u32, tp: &TablePage, ci: &ColInfo) {
+  if ci.flags.contains(ColFlags::FULLTEXT) { return fulltext_build(db, tp_id, tp, ci); }
   let ci_id = ci.idx(&tp.cols);
-  macro_rules! handle {
-    ($ty: ident) => {{
-      let mut index = Index::<{ $ty }>::new(db, tp_id, ci_id);
-      for (data, rid) in db.record_iter(tp) {
-        if !is_null(data, ci_id) { index.insert(data.add(ci.off as usize), rid); }
-      }
-    }};
+  let db1 = (db as *mut Db).r();
+  if ci.idx_spatial != 0 {
+    let mut rtree = RTree::new(db1, tp_id, ci_id);
+    for (data, rid) in db.record_iter(tp) {
+      if in_index(db1, tp, ci, data) { rtree.insert(spatial_point(tp, ci, data), rid); }
+    }
+    return;
   }
+  let keys = db.record_iter(tp).filter(|&(data, _)| in_index(db1, tp, ci, data))
+    .map(|(data, rid)| (data.add(ci.off as usize) as *const u8, rid)).collect::<Vec<_>>();
+  macro_rules! handle { ($ty: ident) => {{ Index::<{ $ty }>::new(db, tp_id, ci_id).bulk_build(keys); }}; }
   handle_all!(ci.ty.fix_ty().ty, handle);
 }
 
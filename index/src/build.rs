@@ -0,0 +1,80 @@
+use common::*;
+use physics::*;
+use crate::{Index, cmp::Cmp};
+
+impl<const T: BareTy> Index<{ T }> {
+  // insert many keys at once, like `bulk_build` if the index is empty, otherwise they are inserted one by one in sorted order,
+  // so neighbouring keys go to the same leaf, and pages are visited in order
+  pub unsafe fn bulk_insert(&mut self, mut keys: Vec<(*const u8, Rid)>) {
+    let root = self.root();
+    let ip = self.db().get_page::<IndexPage>(root);
+    if ip.leaf && ip.count == 0 { return self.bulk_build(keys); }
+    keys.sort_unstable_by(|l, r| Cmp::<{ T }>::cmp(l.0, r.0).then(l.1.cmp(&r.1)));
+    for (data, rid) in keys { self.insert(data, rid); }
+  }
+
+  // build the whole tree from `keys`, caller guarantee the index is empty (e.g., just allocated by `Db::alloc_index`)
+  // `keys` are (data ptr, rid) of all not-null records, they are sorted here and then filled into pages bottom-up,
+  // which is much faster than inserting them one by one
+  pub unsafe fn bulk_build(&mut self, mut keys: Vec<(*const u8, Rid)>) {
+    // for prefix index or expression index, keys are different from col data, compute them first (see `fix_key`)
+    let rid_off = self.rid_off();
+    let mut fixed = vec![];
+    if !self.ci().idx_exact() {
+      fixed.resize(keys.len() * rid_off, 0u8);
+      for (idx, (data, _)) in keys.iter_mut().enumerate() {
+        let p = fixed.as_mut_ptr().add(idx * rid_off);
+        (p.copy_from_nonoverlapping(*data, rid_off), self.fix_key(p));
+        *data = p;
+      }
+    }
+    keys.sort_unstable_by(|l, r| Cmp::<{ T }>::cmp(l.0, r.0).then(l.1.cmp(&r.1)));
+    let root = self.root();
+    let ip = self.db().get_page::<IndexPage>(root);
+    debug_assert!(ip.leaf && ip.count == 0);
+    let (rid_off, leaf_cap) = (ip.rid_off, ip.cap);
+    let write_key = |p: *mut u8, (data, rid): (*const u8, Rid)| {
+      p.copy_from_nonoverlapping(data, rid_off as usize);
+      *(p.add(rid_off as usize) as *mut Rid) = rid;
+    };
+    if keys.len() < leaf_cap as usize { // the root leaf can hold them all
+      let slot_size = ip.slot_size() as usize;
+      for (i, &key) in keys.iter().enumerate() { write_key(ip.data.as_mut_ptr().add(i * slot_size), key); }
+      ip.count = keys.len() as u16;
+      return;
+    }
+    self.db().dealloc_page(root);
+    let mut level = self.build_level(true, rid_off, leaf_cap, keys.len(), |p, i| write_key(p, *keys.get_unchecked(i)));
+    let key_size = rid_off as usize + 4;
+    let inner_cap = MAX_INDEX_BYTES as u16 / (key_size as u16 + 4);
+    while level.len() > 1 {
+      let db = self.db();
+      level = self.build_level(false, rid_off, inner_cap, level.len(), |p, i| {
+        let ch = *level.get_unchecked(i);
+        p.copy_from_nonoverlapping(db.get_page::<IndexPage>(ch).data.as_ptr(), key_size); // the min key of child
+        *(p.add(key_size) as *mut u32) = ch;
+      });
+    }
+    self.make_root(*level.get_unchecked(0));
+  }
+
+  // distribute `n` slots evenly to as few pages as possible, every page has count < cap, and count >= cap / 2 if there are more than 1 pages
+  // `write(p, i)` should write the `i`th slot to `p`; return the page ids of this level in order (they are linked by `next`)
+  unsafe fn build_level(&mut self, leaf: bool, rid_off: u16, cap: u16, n: usize, mut write: impl FnMut(*mut u8, usize)) -> Vec<u32> {
+    let max = cap as usize - 1;
+    let page_num = (n + max - 1) / max;
+    let mut pages = Vec::with_capacity(page_num);
+    let mut i = 0;
+    for page_idx in 0..page_num {
+      let count = n / page_num + (page_idx < n % page_num) as usize;
+      let (id, ip) = self.db().alloc_page::<IndexPage>();
+      (ip.next = !0, ip.count = count as u16, ip.leaf = leaf, ip.rid_off = rid_off, ip.cap = cap);
+      let slot_size = ip.slot_size() as usize;
+      for j in 0..count { write(ip.data.as_mut_ptr().add(j * slot_size), i + j); }
+      i += count;
+      if let Some(&last) = pages.last() { self.db().get_page::<IndexPage>(last).next = id; }
+      pages.push(id);
+    }
+    pages
+  }
+}
\ No newline at end of file
@@ -0,0 +1,399 @@
+use std::{fmt::Write, cmp::Ordering, ptr};
+use unchecked_unwrap::UncheckedUnwrap;
+
+use common::{*, Error::*, BareTy::*};
+use db::{Db, is_null, coerce};
+use physics::*;
+use crate::{Index, handle_all, in_index, cmp::Cmp, alter::new_index};
+
+// scan all foreign key cols, and report every record whose value doesn't exist in the referenced col, one per line
+// the last line is the number of such records, the database is not modified
+pub fn check_foreign_keys(db: &mut Db) -> String {
+  unsafe {
+    let (mut s, mut cnt) = (String::new(), 0);
+    // a col may reference a col of its own table, so the pages are resolved by handles instead of holding 2 `&mut TablePage`
+    // the handles are got here and nothing is altered while checking, so they are always valid
+    for t in db.table_handles().collect::<Vec<_>>() {
+      for (c, f_c) in t.cols(db).unchecked_unwrap().filter_map(|c| Some((c, c.foreign(db).unchecked_unwrap()?))).collect::<Vec<_>>() {
+        let (ci, f_ci) = (c.info(db).unchecked_unwrap(), f_c.info(db).unchecked_unwrap());
+        let (off, ty, f_ty) = (ci.off as usize, ci.ty, f_ci.ty);
+        // collected first, because the iterator borrows `db`, which is used to report the records
+        let records = t.records(db).unchecked_unwrap().collect::<Vec<_>>();
+        macro_rules! handle {
+          ($ty: ident) => {{
+            let index = Index::<{ $ty }>::new(db, f_c.table().id(), f_c.id());
+            for &(data, rid) in &records {
+              let (ptr, mut buf) = (data.add(off), None);
+              if !is_null(data, c.id()) && !coerce(ptr, ty, f_ty, &mut buf).map(|p| index.contains(p)).unwrap_or(false) {
+                writeln!(s, "`{}.{}` = {:?} at {:?}: not found in `{}.{}`", t.name(db).unchecked_unwrap(), c.name(db).unchecked_unwrap(), db.ptr2lit(ptr, ty), rid,
+                  f_c.table().name(db).unchecked_unwrap(), f_c.name(db).unchecked_unwrap()).unchecked_unwrap();
+                cnt += 1;
+              }
+            }
+          }};
+        }
+        handle_all!(ty.fix_ty().ty, handle);
+      }
+    }
+    write!(s, "{} orphaned record(s)", cnt).unchecked_unwrap();
+    s
+  }
+}
+
+// the pages of a db and what each of them is used by, for `check_db` and `repair_db`
+struct Checker {
+  pages: u32,
+  // page id -> 1 + its user's index in `users`, 0 for not used
+  owner: Vec<u32>,
+  users: Vec<String>,
+  // the problems found and their number
+  s: String,
+  cnt: u32,
+  // Some(the fixes made) for `repair_db`, then a structure is fixed right after its problems are found
+  fixes: Option<String>,
+  fixed: u32,
+}
+
+// compare the data part of 2 keys of an index, see `Cmp::cmp`
+type KeyCmp = unsafe fn(*const u8, *const u8) -> Ordering;
+
+impl Checker {
+  fn new(db: &Db, repair: bool) -> Checker {
+    let users = vec!["the db page".to_owned(), "the page tags".to_owned()];
+    let mut c = Checker { pages: 0, owner: vec![], users, s: String::new(), cnt: 0, fixes: if repair { Some(String::new()) } else { None }, fixed: 0 };
+    (c.grow(db), c.owner[0] = 1, c).2
+  }
+
+  fn report(&mut self, msg: String) {
+    (self.s.push_str(&msg), self.s.push('\n'), self.cnt += 1);
+  }
+
+  fn repair(&self) -> bool { self.fixes.is_some() }
+
+  fn fix(&mut self, msg: String) {
+    if let Some(f) = &mut self.fixes { (f.push_str(&msg), f.push('\n'), self.fixed += 1); }
+  }
+
+  // the pages appended to the file by the fixes are not used yet, except the `KindPage`s among them
+  fn grow(&mut self, db: &Db) {
+    let old = self.pages;
+    self.pages = (db.file_sizes().0 / PAGE_SIZE as u64) as u32;
+    self.owner.resize(self.pages as usize, 0);
+    for p in (old..self.pages).filter(|&p| KindPage::is_kind_page(p)) { self.owner[p as usize] = 2; }
+  }
+
+  // mark `page` and the `extra` pages after it as used by `who`
+  // false if any of them is out of range or is already used (it is reported), then the structure there should not be followed
+  fn claim(&mut self, page: u32, extra: u32, who: &str) -> bool {
+    if page == 0 || page >= self.pages || extra >= self.pages - page {
+      return (self.report(format!("{}: page {} is out of range", who, page)), false).1;
+    }
+    for p in page..=page + extra {
+      let o = self.owner[p as usize];
+      if o != 0 { return (self.report(format!("{}: page {} is also used by {}", who, p, self.users[o as usize - 1])), false).1; }
+    }
+    if self.users.last().map(|u| u != who).unwrap_or(true) { self.users.push(who.to_owned()); }
+    for p in page..=page + extra { self.owner[p as usize] = self.users.len() as u32; }
+    true
+  }
+
+  // like `claim`, but the number of extra pages is stored in the first page, so it is read by `extra` after the first page is claimed
+  fn claim_with(&mut self, page: u32, extra: impl FnOnce() -> u32, who: &str) -> bool {
+    self.claim(page, 0, who) && {
+      let extra = extra();
+      extra == 0 || self.claim(page + 1, extra - 1, who)
+    }
+  }
+
+  // forget the pages claimed by `who` (the last one of that name), e.g., the old pages of an index that is built again
+  fn release(&mut self, who: &str) {
+    if let Some(id) = self.users.iter().rposition(|u| u == who) {
+      for o in &mut self.owner { if *o == id as u32 + 1 { *o = 0; } }
+    }
+  }
+}
+
+// walk all structures in the db: the table directory, the table pages, data pages, statistics, partitions and indexes of every table,
+// and the free list, and report every inconsistency found between them, one per line; the last line is the number of problems
+// a page used by 2 structures or out of the file is reported instead of followed, so a corrupt db doesn't crash it; the db is not modified
+pub fn check_db(db: &mut Db) -> String {
+  unsafe {
+    let mut c = Checker::new(db, false);
+    walk(db, &mut c);
+    write!(c.s, "{} problem(s)", c.cnt).unchecked_unwrap();
+    c.s
+  }
+}
+
+// fix the problems found by `check_db` as far as possible, and report the fixes made, one per line; the last line is the number of them
+// a broken link of the table directory or a data chain is cut (the tables / records after it are lost), a table whose page is broken is removed,
+// the counts and free lists of a table are computed again from the used slots, an index with any problem is built again from the records,
+// broken statistics, comments and checks are dropped, and the free list is rebuilt from the pages not used by anything
+// problems of partitions are not fixed; `check_db` after this reports the problems left
+pub fn repair_db(db: &mut Db) -> String {
+  unsafe {
+    let mut c = Checker::new(db, true);
+    walk(db, &mut c);
+    let mut s = c.fixes.unchecked_unwrap();
+    write!(s, "{} fix(es)", c.fixed).unchecked_unwrap();
+    s
+  }
+}
+
+// the shape of the B+tree index on `table.col` (see `Index::dump_tree`), then the violated invariants of it (see `Index::check_tree`), one per line;
+// the last line is the number of them, the database is not modified
+pub fn check_index_tree<'a>(db: &mut Db, table: &'a str, col: &'a str) -> Result<'a, String> {
+  unsafe {
+    let (tp_id, tp) = db.get_tp(table)?;
+    let ci = tp.get_ci(col, db.ignore_case)?;
+    if ci.index == !0 || ci.idx_spatial != 0 { return Err(NoColIndex(col)); }
+    let ci_id = ci.idx(&tp.cols);
+    macro_rules! handle {
+      ($ty: ident) => {{
+        let index = Index::<{ $ty }>::new(db, tp_id, ci_id);
+        (index.dump_tree(), index.check_tree())
+      }};
+    }
+    let ty = if ci.flags.contains(ColFlags::FULLTEXT) { Char } else { ci.ty.fix_ty().ty };
+    let (mut s, problems) = handle_all!(ty, handle);
+    s.push('\n');
+    for p in &problems { writeln!(s, "{}", p).unchecked_unwrap(); }
+    write!(s, "{} problem(s)", problems.len()).unchecked_unwrap();
+    Ok(s)
+  }
+}
+
+unsafe fn walk(db: &mut Db, c: &mut Checker) {
+  // the fixes only allocate pages at the end of the file, instead of taking them from the (maybe broken) free list
+  let first_free = db.dp().first_free;
+  if c.repair() { db.dp().first_free = !0; }
+  let (mut dirs, mut link, mut page) = (vec![], &mut db.dp().dir as *mut u32, db.dp().dir);
+  while page != !0 {
+    if !c.claim(page, 0, "the table directory") {
+      if c.repair() { (*link = !0, c.fix(format!("the table directory is cut before page {}", page))); }
+      break;
+    }
+    let dir = db.get_page::<DirPage>(page);
+    (dirs.push(page), link = &mut dir.next as *mut u32, page = dir.next);
+  }
+  let dp = db.dp();
+  let num = dp.table_num as usize;
+  let mut tables = dp.tables.iter().take(num.min(MAX_TABLE)).copied().collect::<Vec<_>>();
+  for (k, &dir) in dirs.iter().enumerate() {
+    tables.extend(db.get_page::<DirPage>(dir).tables.iter().take(num.saturating_sub(MAX_TABLE + k * DIR_TABLES).min(DIR_TABLES)));
+  }
+  if tables.len() < num {
+    c.report(format!("the table directory: {} of {} table(s) are missing", num - tables.len(), num));
+    if c.repair() { (dp.table_num = tables.len() as u32, c.fix(format!("{} table(s) missing in the table directory are lost", num - tables.len()))); }
+  }
+  let bad = tables.iter().copied().filter(|&tp_id| !check_table(db, c, tp_id)).collect::<Vec<_>>();
+  for &tp_id in tables.iter().filter(|tp_id| !bad.contains(tp_id)) {
+    let tp = db.get_page::<TablePage>(tp_id);
+    for ci in tp.cols().iter().filter(|ci| ci.f_table != !0 && (bad.contains(&ci.f_table) || !tables.contains(&ci.f_table))) {
+      c.report(format!("`{}.{}`: the referenced page {} is not a table", tp.name(), ci.name(), ci.f_table));
+      if c.repair() { (ci.pr().f_table = !0, c.fix(format!("the foreign key on `{}.{}` is dropped", tp.name(), ci.name()))); }
+    }
+  }
+  if c.repair() {
+    for &tp_id in &bad { (db.remove_table(tp_id), c.fix(format!("the table of page {} is removed", tp_id))); }
+    c.grow(db);
+  }
+  let before = c.cnt;
+  let mut page = first_free;
+  while page != !0 && c.claim(page, 0, "the free list") { page = *db.get_page::<u32>(page); }
+  for p in 1..c.pages {
+    if c.owner[p as usize] == 0 { c.report(format!("page {} is neither used nor in the free list", p)); }
+  }
+  if c.repair() {
+    if c.cnt == before {
+      db.dp().first_free = first_free;
+    } else {
+      c.release("the free list");
+      let free = (1..c.pages).filter(|&p| c.owner[p as usize] == 0).collect::<Vec<_>>();
+      db.dp().first_free = !0;
+      for &p in free.iter().rev() { db.dealloc_page(p); }
+      c.fix(format!("the free list is rebuilt with {} page(s)", free.len()));
+    }
+  }
+}
+
+// false if the table page itself is broken, then nothing in the table is checked
+unsafe fn check_table(db: &mut Db, c: &mut Checker, tp_id: u32) -> bool {
+  if !c.claim(tp_id, 0, "a table") { return false; }
+  let tp = db.get_page::<TablePage>(tp_id);
+  if tp.col_num == 0 || tp.col_num as usize > MAX_COL || tp.cap == 0 || tp.cap as usize > MAX_SLOT {
+    return (c.report(format!("table page {}: invalid layout", tp_id)), false).1;
+  }
+  if tp.extra_pages() != 0 && !c.claim(tp_id + 1, tp.extra_pages() - 1, "a table") { return false; }
+  let name = tp.name();
+  if tp.stats != !0 {
+    let who = format!("the statistics of `{}`", name);
+    if c.claim_with(tp.stats, || db.get_page::<StatsPage>(tp.stats).col_pages(), &who) {
+      for cs in db.get_page::<StatsPage>(tp.stats).cols_mut().iter_mut().filter(|cs| cs.hist != !0) {
+        if !c.claim(cs.hist, 0, &who) && c.repair() { (cs.hist = !0, c.fix(format!("a histogram of `{}` is dropped", name))); }
+      }
+    } else if c.repair() {
+      (tp.stats = !0, c.fix(format!("the statistics of `{}` are dropped", name)));
+    }
+  }
+  if tp.comment != !0 && !c.claim(tp.comment, 0, &format!("the comments of `{}`", name)) && c.repair() {
+    (tp.comment = !0, c.fix(format!("the comments of `{}` are dropped", name)));
+  }
+  let pp = if tp.part != !0 && c.claim(tp.part, 0, &format!("the partitions of `{}`", name)) { db.part_page(tp) } else { None };
+  for ci in tp.cols().iter().filter(|ci| ci.check != !0) {
+    if !c.claim(ci.check >> 1, 0, &format!("the checks of `{}.{}`", name, ci.name())) && c.repair() {
+      (ci.pr().check = !0, c.fix(format!("the checks and default value of `{}.{}` are dropped", name, ci.name())));
+    }
+  }
+
+  // data pages: the used slots should agree with the counts, and the pages with free slots should be exactly the ones in the free lists
+  let (who, overflow) = (format!("the data of `{}`", name), tp.overflow());
+  let (mut data, mut records, mut count, mut link, mut page) = (vec![], vec![], 0, &mut tp.first as *mut u32, tp.first);
+  while page != !0 {
+    if !c.claim(page, overflow, &who) {
+      if c.repair() { (*link = !0, c.fix(format!("the data of `{}` is cut before page {}", name, page))); }
+      break;
+    }
+    let dp = db.get_page::<DataPage>(page);
+    let used = (0..tp.cap as usize).filter(|&i| bsget(dp.used.as_ptr(), i)).collect::<Vec<_>>();
+    if used.len() != dp.count as usize {
+      c.report(format!("data page {} of `{}`: count = {}, but {} slot(s) are used", page, name, dp.count, used.len()));
+      if c.repair() { (dp.count = used.len() as u16, c.fix(format!("the count of data page {} of `{}` is set to {}", page, name, used.len()))); }
+    }
+    if (tp.cap as usize..MAX_SLOT).any(|i| bsget(dp.used.as_ptr(), i)) {
+      c.report(format!("data page {} of `{}`: slots beyond the capacity are used", page, name));
+      if c.repair() {
+        for i in tp.cap as usize..MAX_SLOT { bsdel(dp.used.as_mut_ptr(), i); }
+        c.fix(format!("the slots beyond the capacity of data page {} of `{}` are freed", page, name));
+      }
+    }
+    if let Some(pp) = &pp {
+      if dp.part >= pp.count { c.report(format!("data page {} of `{}`: partition {} doesn't exist", page, name, dp.part)); }
+    }
+    count += used.len();
+    records.extend(used.iter().map(|&i| ((page << LOG_MAX_SLOT) | i as u32, dp.data.as_ptr().add(i * tp.size as usize))));
+    (data.push(page), link = &mut dp.next as *mut u32, page = dp.next);
+  }
+  if count != tp.count as usize {
+    c.report(format!("`{}`: count = {}, but {} record(s) are in the data pages", name, tp.count, count));
+    if c.repair() { (tp.count = count as u32, c.fix(format!("the count of `{}` is set to {}", name, count))); }
+  }
+  let (before, parts) = (c.cnt, pp.as_ref().map(|pp| pp.count).unwrap_or(1));
+  let (data_set, mut free) = (data.iter().copied().collect::<HashSet<_>>(), HashSet::new());
+  for part in 0..parts {
+    let mut page = *db.free_list(tp, part);
+    while page != !0 {
+      if !data_set.contains(&page) { c.report(format!("the free list of `{}`: page {} is not a data page of it", name, page)); break; }
+      if !free.insert(page) { c.report(format!("the free list of `{}`: page {} appears more than once", name, page)); break; }
+      let dp = db.get_page::<DataPage>(page);
+      if dp.count >= tp.cap { c.report(format!("the free list of `{}`: page {} is full", name, page)); }
+      if dp.part != part { c.report(format!("the free list of `{}`: page {} is not in partition {}", name, page, part)); }
+      page = dp.next_free;
+    }
+  }
+  for &page in &data {
+    if db.get_page::<DataPage>(page).count < tp.cap && !free.contains(&page) {
+      c.report(format!("data page {} of `{}`: it has free slots, but is not in the free list", page, name));
+    }
+  }
+  if c.repair() && c.cnt != before {
+    for part in 0..parts { *db.free_list(tp, part) = !0; }
+    for &page in data.iter().rev() {
+      let dp = db.get_page::<DataPage>(page);
+      if dp.count < tp.cap && dp.part < parts {
+        let head = db.free_list(tp, dp.part);
+        (dp.next_free = *head, *head = page);
+      }
+    }
+    c.fix(format!("the free lists of `{}` are rebuilt", name));
+  }
+
+  // indexes: every entry should point to a used slot, and every record that should be in an index (see `in_index`) should be in it
+  let records = records.into_iter().collect::<HashMap<_, _>>();
+  for ci in tp.cols().iter().filter(|ci| ci.index != !0) {
+    let (who, before) = (format!("the index on `{}.{}`", name, ci.name()), c.cnt);
+    if c.claim(ci.index, 0, &who) { check_index(db, c, tp, ci, &records, &who); }
+    if c.repair() && c.cnt != before {
+      (c.release(&who), new_index(db, tp_id, tp, ci), c.grow(db));
+      if c.claim(ci.index, 0, &who) { check_index(db, c, tp, ci, &records, &who); } // claim the pages of the new one
+      c.fix(format!("{} is built again", who));
+    }
+  }
+  true
+}
+
+// check the index on `ci`, whose root is claimed; `records` is rid (as u32) -> record of all records in the table
+unsafe fn check_index(db: &mut Db, c: &mut Checker, tp: &TablePage, ci: &ColInfo, records: &HashMap<u32, *const u8>, who: &str) {
+  let mut keys = vec![];
+  if ci.idx_spatial != 0 {
+    check_rtree(db, c, ci.index, &mut keys, who);
+  } else {
+    let ty = if ci.flags.contains(ColFlags::FULLTEXT) { Char } else { ci.ty.fix_ty().ty };
+    macro_rules! handle { ($ty: ident) => { Cmp::<{ $ty }>::cmp as KeyCmp }; }
+    let cmp = handle_all!(ty, handle);
+    let rid_off = db.get_page::<IndexPage>(ci.index).rid_off as usize;
+    check_btree(db, c, (cmp, rid_off), ci.index, ptr::null(), ptr::null(), &mut keys, who);
+    if ci.idx_exact() {
+      for &(key, rid) in &keys {
+        if records.get(&rid).map(|&data| cmp(key, data.add(ci.off as usize)) != Ordering::Equal).unwrap_or(false) {
+          c.report(format!("{}: the key of {:?} differs from the record", who, Rid::new(rid >> LOG_MAX_SLOT, rid & (MAX_SLOT as u32 - 1))));
+        }
+      }
+    }
+  }
+  let rids = keys.into_iter().map(|(_, rid)| rid).collect::<HashSet<_>>();
+  for &rid in &rids {
+    if !records.contains_key(&rid) { c.report(format!("{}: it has an entry of slot {} of page {}, which is not a record", who, rid & (MAX_SLOT as u32 - 1), rid >> LOG_MAX_SLOT)); }
+  }
+  if !ci.flags.contains(ColFlags::FULLTEXT) { // a record may have no word, then it has no entry
+    for (&rid, &data) in records {
+      if !rids.contains(&rid) && in_index(db, tp, ci, data) {
+        c.report(format!("{}: {:?} is missing", who, Rid::new(rid >> LOG_MAX_SLOT, rid & (MAX_SLOT as u32 - 1))));
+      }
+    }
+  }
+}
+// walk the (claimed) B+tree node `page`, whose keys should be in [`lb`, `ub`) (null for unbounded), and collect (key, rid) of its leaves
+// `cmp` and `rid_off` are the ones of the root, which all nodes should agree with
+unsafe fn check_btree(db: &mut Db, c: &mut Checker, (cmp, rid_off): (KeyCmp, usize), page: u32, lb: *const u8, ub: *const u8, keys: &mut Vec<(*const u8, u32)>, who: &str) {
+  let ip = db.get_page::<IndexPage>(page);
+  if ip.rid_off as usize != rid_off || ip.cap as u32 * ip.slot_size() as u32 > MAX_INDEX_BYTES || ip.count >= ip.cap {
+    return c.report(format!("{}: index page {} has an invalid layout", who, page));
+  }
+  let (slot_size, key_size) = (ip.slot_size() as usize, ip.key_size() as usize);
+  let at = |pos: usize| ip.data.as_ptr().add(pos * slot_size);
+  let rid = |key: *const u8| *(key.add(rid_off) as *const u32);
+  let full = |l: *const u8, r: *const u8| cmp(l, r).then(rid(l).cmp(&rid(r)));
+  if ip.count == 0 {
+    if !lb.is_null() { c.report(format!("{}: index page {} is empty", who, page)); }
+    return;
+  }
+  for i in 1..ip.count as usize {
+    if full(at(i - 1), at(i)) != Ordering::Less { c.report(format!("{}: the keys of index page {} are not in order at slot {}", who, page, i)); }
+  }
+  if (!lb.is_null() && full(lb, at(0)) != Ordering::Equal) || (!ub.is_null() && full(at(ip.count as usize - 1), ub) != Ordering::Less) {
+    c.report(format!("{}: the keys of index page {} are out of the range of its parent", who, page));
+  }
+  if ip.leaf {
+    keys.extend((0..ip.count as usize).map(|i| (at(i), rid(at(i)))));
+  } else {
+    for i in 0..ip.count as usize {
+      let child = *(at(i).add(key_size) as *const u32);
+      let ub = if i + 1 == ip.count as usize { ub } else { at(i + 1) };
+      if c.claim(child, 0, who) { check_btree(db, c, (cmp, rid_off), child, at(i), ub, keys, who); }
+    }
+  }
+}
+
+// walk the (claimed) R-tree node `page`, and collect the rids of its leaves (the points are not checked, so the keys are null)
+unsafe fn check_rtree(db: &mut Db, c: &mut Checker, page: u32, keys: &mut Vec<(*const u8, u32)>, who: &str) {
+  let rp = db.get_page::<RTreePage>(page);
+  if rp.count as usize > MAX_RTREE_ENTRY { return c.report(format!("{}: R-tree page {} has an invalid layout", who, page)); }
+  for e in rp.entries() {
+    if rp.leaf {
+      keys.push((ptr::null(), e.ptr));
+    } else if c.claim(e.ptr, 0, who) {
+      check_rtree(db, c, e.ptr, keys, who);
+    }
+  }
+}
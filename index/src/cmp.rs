@@ -38,4 +38,19 @@ pub unsafe fn upper_bound<const T: BareTy>(ip: &IndexPage, x: *const u8) -> usiz
     }
   }
   i as usize
+}
+
+// return the first index `i` that the `i`th element is >= `x`, i.e., the number of elements < `x`
+pub unsafe fn lower_bound<const T: BareTy>(ip: &IndexPage, x: *const u8) -> usize {
+  let (slot_size, rid_off) = (ip.slot_size() as usize, ip.rid_off as usize);
+  let (mut i, mut last) = (0, ip.count as isize - 1);
+  while i <= last {
+    let mid = (i + last) >> 1;
+    if Cmp::<{ T }>::cmp_full(ip.data.as_ptr().add(mid as usize * slot_size), x, rid_off) == Ordering::Less {
+      i = mid + 1;
+    } else {
+      last = mid - 1;
+    }
+  }
+  i as usize
 }
\ No newline at end of file
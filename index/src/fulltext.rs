@@ -0,0 +1,104 @@
+use unchecked_unwrap::UncheckedUnwrap;
+
+use common::{*, BareTy::*};
+use physics::*;
+use db::Db;
+use crate::{Index, in_index};
+
+// a full-text index is an `Index<{ Char }>` on (word, rid), a record has one key for every distinct word in its text
+// words are stored in the format of char col, so the key size is always `KEY_SIZE` (see `ColInfo::idx_key_size`)
+const KEY_SIZE: usize = MAX_TOKEN + 1;
+
+// split `s` into words (maximal runs of alphanumeric chars) in lower case, a word longer than MAX_TOKEN bytes is truncated
+pub fn tokenize(s: &str) -> impl Iterator<Item = String> + '_ {
+  s.split(|ch: char| !ch.is_alphanumeric()).filter(|w| !w.is_empty()).map(|w| {
+    let mut w = w.to_lowercase();
+    if w.len() > MAX_TOKEN {
+      let mut end = MAX_TOKEN;
+      while !w.is_char_boundary(end) { end -= 1; }
+      w.truncate(end);
+    }
+    w
+  })
+}
+
+// the text of a not-null char / varchar col, `ptr` points to the col in record
+pub unsafe fn text<'a>(db: &Db, ci: &ColInfo, ptr: *const u8) -> &'a str {
+  if ci.ty.is_varchar() { db.varchar(ptr) } else { str_from_db(ptr) }
+}
+
+// write `w` as a key into `p`, which has KEY_SIZE bytes
+unsafe fn write_key(p: *mut u8, w: &str) {
+  *p = w.len() as u8;
+  p.add(1).copy_from_nonoverlapping(w.as_ptr(), w.len());
+}
+
+// keys of all distinct words in `s`, one key per KEY_SIZE bytes
+unsafe fn keys(s: &str) -> Vec<u8> {
+  let mut words = tokenize(s).collect::<Vec<_>>();
+  (words.sort_unstable(), words.dedup());
+  let mut keys = vec![0; words.len() * KEY_SIZE];
+  for (idx, w) in words.iter().enumerate() { write_key(keys.as_mut_ptr().add(idx * KEY_SIZE), w); }
+  keys
+}
+
+// insert all words of the (not-null) text at `ptr` of record `rid` into the full-text index on col `ci_id`
+pub unsafe fn fulltext_insert(db: &mut Db, tp_id: u32, ci_id: u32, ptr: *const u8, rid: Rid) {
+  let ci = db.get_page::<TablePage>(tp_id).cols.get_unchecked(ci_id as usize);
+  let keys = keys(text(db, ci, ptr));
+  let mut index = Index::<{ Char }>::new(db, tp_id, ci_id);
+  for k in keys.chunks_exact(KEY_SIZE) { index.insert(k.as_ptr(), rid); }
+}
+
+// the reverse of `fulltext_insert`, the text should be the same as when it was inserted
+pub unsafe fn fulltext_delete(db: &mut Db, tp_id: u32, ci_id: u32, ptr: *const u8, rid: Rid) {
+  let ci = db.get_page::<TablePage>(tp_id).cols.get_unchecked(ci_id as usize);
+  let keys = keys(text(db, ci, ptr));
+  let mut index = Index::<{ Char }>::new(db, tp_id, ci_id);
+  for k in keys.chunks_exact(KEY_SIZE) { index.delete(k.as_ptr(), rid); }
+}
+
+// fill the (empty) full-text index on `ci` with the words of all records that should be in it (see `in_index`)
+pub(crate) unsafe fn fulltext_build(db: &mut Db, tp_id: u32, tp: &TablePage, ci: &ColInfo) {
+  let (mut all, mut rids) = (vec![], vec![]);
+  let db1 = (db as *mut Db).r();
+  for (data, rid) in db.record_iter(tp) {
+    if in_index(db1, tp, ci, data) {
+      let keys = keys(text(db1, ci, data.add(ci.off as usize)));
+      rids.extend(std::iter::repeat(rid).take(keys.len() / KEY_SIZE));
+      all.extend_from_slice(&keys);
+    }
+  }
+  let keys = rids.into_iter().enumerate().map(|(idx, rid)| (all.as_ptr().add(idx * KEY_SIZE), rid)).collect();
+  Index::<{ Char }>::new(db, tp_id, ci.idx(&tp.cols)).bulk_build(keys);
+}
+
+// rids of all records containing `word` in ascending order, `word` should come from `tokenize`
+pub unsafe fn fulltext_search(db: &mut Db, tp_id: u32, ci_id: u32, word: &str) -> Vec<Rid> {
+  let mut key = [0u8; KEY_SIZE];
+  write_key(key.as_mut_ptr(), word);
+  let mut index = Index::<{ Char }>::new(db, tp_id, ci_id);
+  let (mut it, end) = (index.lower_bound(key.as_ptr()), index.upper_bound(key.as_ptr()));
+  let mut ret = vec![];
+  while it != end { ret.push(it.next().unchecked_unwrap()); }
+  ret
+}
+
+// the weight of every word in `query` for ranking, a rarer word has a higher weight: ln(1 + n / df),
+// where n is the number of records in table, and df is the number of records containing the word
+pub unsafe fn fulltext_weights(db: &mut Db, tp_id: u32, ci_id: u32, query: &str) -> HashMap<String, f32> {
+  let n = db.get_page::<TablePage>(tp_id).count as f32;
+  let mut ret = HashMap::new();
+  for w in tokenize(query) {
+    if !ret.contains_key(&w) {
+      let df = fulltext_search(db, tp_id, ci_id, &w).len() as f32;
+      ret.insert(w, if df == 0.0 { 0.0 } else { (1.0 + n / df).ln() });
+    }
+  }
+  ret
+}
+
+// the relevance of `text` to the query, every occurrence of a query word in `text` adds its weight
+pub fn fulltext_score(text: &str, weights: &HashMap<String, f32>) -> f32 {
+  tokenize(text).map(|w| weights.get(&w).copied().unwrap_or(0.0)).sum()
+}
\ No newline at end of file
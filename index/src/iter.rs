@@ -1,4 +1,5 @@
 use std::mem;
+use unchecked_unwrap::UncheckedUnwrap;
 
 use common::*;
 use db::Db;
@@ -9,21 +10,25 @@ pub struct IndexIter<'a> {
   db: &'a mut Db,
   page: u32,
   slot: u16,
+  // (internal page, child pos) from root to `page`, only maintained by `prev_key`, because leaves have no link to the previous one
+  path: Vec<(u32, u16)>,
 }
 
 impl IndexIter<'_> {
-  pub unsafe fn next(&mut self) -> Option<Rid> {
+  pub unsafe fn next(&mut self) -> Option<Rid> { self.next_key().map(|(_, rid)| rid) }
+
+  // also return the pointer to the key, it points into IndexPage, so it can be used as data of the indexed col
+  pub unsafe fn next_key(&mut self) -> Option<(*const u8, Rid)> {
     let mut ip = self.db.get_page::<IndexPage>(self.page);
     if self.slot == ip.count {
       if ip.next == !0 { return None; }
-      self.page = ip.next;
-      self.slot = 0;
+      (self.page = ip.next, self.slot = 0, self.path.clear());
       ip = self.db.get_page::<IndexPage>(self.page);
     }
     let slot = (self.slot, self.slot += 1).0;
     let data_rid = ip.data.as_mut_ptr().add((slot * ip.slot_size()) as usize);
     let rid = *(data_rid.add(ip.rid_off as usize) as *const Rid);
-    Some(rid)
+    Some((data_rid as *const u8, rid))
   }
 }
 
@@ -36,30 +41,89 @@ impl<const T: BareTy> Index<{ T }> {
     let mut page = self.root();
     loop {
       let ip = self.db().get_page::<IndexPage>(page);
-      if ip.leaf { break IndexIter { db: self.db(), page, slot: 0 }; }
+      if ip.leaf { break IndexIter { db: self.db(), page, slot: 0, path: vec![] }; }
       page = *(ip.data.as_mut_ptr().add(ip.key_size() as usize) as *mut u32);
     }
   }
 
+  // the position after the last key, it is only useful for `prev_key`
+  pub unsafe fn end<'a>(&mut self) -> IndexIter<'a> {
+    let mut page = self.root();
+    loop {
+      let ip = self.db().get_page::<IndexPage>(page);
+      if ip.leaf { break IndexIter { db: self.db(), page, slot: ip.count, path: vec![] }; }
+      page = *(ip.data.as_mut_ptr().add((ip.count - 1) as usize * ip.slot_size() as usize + ip.key_size() as usize) as *mut u32);
+    }
+  }
+
+  // move `it` backward by one slot and return the key there, the reverse of `IndexIter::next_key`
+  // leaves don't have a link to the previous leaf, so the first time it crosses a leaf, it searches from root for the last key < the first key
+  // of the current leaf and records the path in `it`; after that, the previous leaf is found by walking up and down the path
+  pub unsafe fn prev_key(&mut self, it: &mut IndexIter) -> Option<(*const u8, Rid)> {
+    if it.slot == 0 {
+      if it.path.is_empty() {
+        let ip = self.db().get_page::<IndexPage>(it.page);
+        if ip.count == 0 { return None; } // only an empty root can have no key
+        let (page, slot) = self.do_lower_bound(ip.data.as_ptr(), &mut it.path);
+        (it.page = page, it.slot = slot);
+      } else {
+        // go up to the nearest internal page that has a child before the path, then go down along its last children
+        loop {
+          let (_, pos) = it.path.last_mut()?;
+          if *pos != 0 { break *pos -= 1; }
+          it.path.pop();
+        }
+        let (mut page, mut pos) = *it.path.last().unchecked_unwrap();
+        loop {
+          let ip = self.db().get_page::<IndexPage>(page);
+          page = *(ip.data.as_mut_ptr().add(pos as usize * ip.slot_size() as usize + ip.key_size() as usize) as *mut u32);
+          let ip = self.db().get_page::<IndexPage>(page);
+          if ip.leaf { break (it.page = page, it.slot = ip.count); }
+          pos = ip.count - 1;
+          it.path.push((page, pos));
+        };
+      }
+      if it.slot == 0 { return None; }
+    }
+    it.slot -= 1;
+    let ip = self.db().get_page::<IndexPage>(it.page);
+    let data_rid = ip.data.as_mut_ptr().add((it.slot * ip.slot_size()) as usize);
+    Some((data_rid as *const u8, *(data_rid.add(ip.rid_off as usize) as *const Rid)))
+  }
+
   pub unsafe fn lower_bound<'a>(&mut self, data: *const u8) -> IndexIter<'a> {
     // 00..00 is the smallest, but this will trigger a warning (because Rid is marked as non-zero)
     // so use 00..01, it is also small enough
     let data_rid = self.make_data_rid(data, mem::transmute(1));
     let (page, slot) = self.do_upper_bound(data_rid.ptr);
-    IndexIter { db: self.db(), page, slot }
+    IndexIter { db: self.db(), page, slot, path: vec![] }
   }
 
   pub unsafe fn upper_bound<'a>(&mut self, data: *const u8) -> IndexIter<'a> {
     // rid = 11..11, which is the biggest
     let data_rid = self.make_data_rid(data, mem::transmute(!0));
     let (page, slot) = self.do_upper_bound(data_rid.ptr);
-    IndexIter { db: self.db(), page, slot }
+    IndexIter { db: self.db(), page, slot, path: vec![] }
   }
 
   pub unsafe fn contains(&self, data: *const u8) -> bool {
     self.pr().lower_bound(data) != self.pr().upper_bound(data)
   }
 
+  // return the leaf and the slot of the first key >= `data_rid`, the leaf contains the last key < `data_rid` (if any)
+  // the internal pages on the way and the child pos taken in them are pushed to `path`
+  unsafe fn do_lower_bound(&mut self, data_rid: *const u8, path: &mut Vec<(u32, u16)>) -> (u32, u16) {
+    let mut page = self.root();
+    loop {
+      let ip = self.db().get_page::<IndexPage>(page);
+      let (slot_size, key_size) = (ip.slot_size() as usize, ip.key_size() as usize);
+      macro_rules! at_ch { ($pos: expr) => { *(ip.data.as_mut_ptr().add($pos * slot_size + key_size) as *mut u32) }; }
+      if ip.leaf { break (page, cmp::lower_bound::<{ T }>(ip, data_rid) as u16); }
+      let pos = cmp::lower_bound::<{ T }>(ip, data_rid).max(1) - 1;
+      (path.push((page, pos as u16)), page = at_ch!(pos));
+    }
+  }
+
   unsafe fn do_upper_bound(&mut self, data_rid: *const u8) -> (u32, u16) {
     let mut page = self.root();
     loop {
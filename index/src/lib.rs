@@ -3,7 +3,7 @@
 #![feature(ptr_offset_from)]
 #![feature(box_syntax)]
 
-use std::{ptr::{self, NonNull}, marker::PhantomData, cmp::Ordering};
+use std::{ptr::{self, NonNull}, marker::PhantomData, cmp::Ordering, slice};
 
 use common::*;
 use db::Db;
@@ -13,8 +13,13 @@ use crate::cmp::*;
 pub mod cmp;
 pub mod iter;
 pub mod alter;
+pub mod build;
+pub mod partial;
+pub mod fulltext;
+pub mod rtree;
+pub mod check;
 
-pub use alter::*;
+pub use crate::{alter::*, partial::*, fulltext::*, rtree::*, check::*};
 
 // using both lifetime parameter and const parameter will cause my rustc (1.40.0-nightly) to ICE, so just use pointer here
 pub struct Index<const T: BareTy> {
@@ -31,6 +36,15 @@ impl<const T: BareTy> Index<{ T }> {
   // these 2 functions are not frequently called, so not save these 2 values in `Index` struct
   unsafe fn root(&self) -> u32 { self.db.r().get_page::<TablePage>(self.tp_id).cols.get_unchecked_mut(self.ci_id as usize).index }
   unsafe fn rid_off(&self) -> usize { self.db.r().get_page::<IndexPage>(self.root()).rid_off as usize }
+  unsafe fn ci(&self) -> &ColInfo { self.db.r().get_page::<TablePage>(self.tp_id).cols.get_unchecked(self.ci_id as usize) }
+
+  // for prefix index, the length of the string in key should be truncated; for expression index, the function is applied on it
+  unsafe fn fix_key(&self, key: *mut u8) {
+    let ci = self.ci();
+    if T != BareTy::Char || ci.idx_exact() { return; }
+    if ci.idx_prefix != 0 { *key = (*key).min(ci.idx_prefix); }
+    if let Some(f) = ci.idx_fn() { f.apply_in_place(slice::from_raw_parts_mut(key.add(1), *key as usize)); }
+  }
 
   // caller guarantee data_rid doesn't exist in tree
   pub unsafe fn insert(&mut self, data: *const u8, rid: Rid) {
@@ -164,6 +178,7 @@ impl<const T: BareTy> Index<{ T }> {
     let rid_off = self.rid_off();
     let data_rid = Align4U8::new(rid_off + 4);
     data_rid.ptr.copy_from_nonoverlapping(data, rid_off);
+    self.fix_key(data_rid.ptr);
     *(data_rid.ptr.add(rid_off) as *mut Rid) = rid;
     data_rid
   }
@@ -187,27 +202,79 @@ impl<const T: BareTy> Index<{ T }> {
 
   // it is only called explicitly, so there is no `if cfg!(debug_assertions)`
   pub unsafe fn debug_check_all(&self) {
-    unsafe fn dfs<const T: BareTy>(s: &Index<{ T }>, page: u32, lb: *const u8, ub: *const u8) {
-      s.debug_check(page);
+    let problems = self.check_tree();
+    assert!(problems.is_empty(), "{:?}", problems);
+  }
+
+  // the invariants of the whole tree that are violated, one message for each, empty if it is valid:
+  // in every node keys are strictly ascending and `count < cap`, every node except root is at least half full, the first key of a child
+  // is the key pointing to it in the parent, and all its keys are before the next key in the parent; all leaves are at the same depth
+  pub unsafe fn check_tree(&self) -> Vec<String> {
+    unsafe fn dfs<const T: BareTy>(s: &Index<{ T }>, page: u32, depth: u32, lb: *const u8, ub: *const u8, leaf_depth: &mut Option<u32>, problems: &mut Vec<String>) {
       let ip = s.pr().db().get_page::<IndexPage>(page);
-      let (slot_size, key_size) = (ip.slot_size() as usize, ip.key_size() as usize);
+      let (slot_size, key_size, rid_off) = (ip.slot_size() as usize, ip.key_size() as usize, s.rid_off());
       macro_rules! at { ($pos: expr) => { ip.data.as_mut_ptr().add($pos * slot_size) }; }
       macro_rules! at_ch { ($pos: expr) => { *(ip.data.as_mut_ptr().add($pos * slot_size + key_size) as *mut u32) }; }
-      if !lb.is_null() {
-        // the min key must be the dup key
-        assert_eq!(Cmp::<{ T }>::cmp_full(lb, at!(0), s.rid_off()), Ordering::Equal);
+      if ip.rid_off as usize != rid_off { return problems.push(format!("page {}: rid offset = {}, but it is {} in root", page, ip.rid_off, rid_off)); }
+      if ip.count >= ip.cap { problems.push(format!("page {}: count = {} >= cap = {}", page, ip.count, ip.cap)); }
+      if page != s.root() && ip.count < ip.cap / 2 { problems.push(format!("page {}: count = {} < cap / 2 = {}", page, ip.count, ip.cap / 2)); }
+      for i in 1..ip.count as usize {
+        if Cmp::<{ T }>::cmp_full(at!(i - 1), at!(i), rid_off) != Ordering::Less { problems.push(format!("page {}: key {} is not before key {}", page, i - 1, i)); }
       }
-      if !ub.is_null() {
-        assert_eq!(Cmp::<{ T }>::cmp_full(at!(ip.count as usize - 1), ub, s.rid_off()), Ordering::Less);
+      if ip.count == 0 { return; } // only an empty root
+      if !lb.is_null() && Cmp::<{ T }>::cmp_full(lb, at!(0), rid_off) != Ordering::Equal {
+        problems.push(format!("page {}: the first key is not the key pointing to it", page));
       }
-      if !ip.leaf {
+      if !ub.is_null() && Cmp::<{ T }>::cmp_full(at!(ip.count as usize - 1), ub, rid_off) != Ordering::Less {
+        problems.push(format!("page {}: the last key is not before the next key in parent", page));
+      }
+      if ip.leaf {
+        match *leaf_depth {
+          Some(d) if d != depth => problems.push(format!("page {}: leaf at depth {}, but another is at depth {}", page, depth, d)),
+          _ => *leaf_depth = Some(depth),
+        }
+      } else {
         for i in 0..ip.count as usize {
           let ub = if i + 1 == ip.count as usize { ub } else { at!(i + 1) };
-          dfs(s, at_ch!(i), at!(i), ub);
+          dfs(s, at_ch!(i), depth + 1, at!(i), ub, leaf_depth, problems);
         }
       }
     }
-    dfs(self, self.root(), ptr::null(), ptr::null());
+    let mut problems = vec![];
+    dfs(self, self.root(), 0, ptr::null(), ptr::null(), &mut None, &mut problems);
+    problems
+  }
+
+  // the shape of the tree: one line per node in pre-order, indented by its depth, with its page id, fill factor and the range of its keys,
+  // then a line of the depth, the numbers of nodes / leaves / keys (keys of inner nodes are copies of keys in leaves, so they are not counted)
+  // and the fill factor of all nodes
+  pub unsafe fn dump_tree(&self) -> String {
+    use std::fmt::Write;
+    // (depth, nodes, leaves, keys, sum of count, sum of cap)
+    type Sum = (u32, u32, u32, u64, u64, u64);
+    unsafe fn dfs<const T: BareTy>(s: &Index<{ T }>, page: u32, depth: u32, out: &mut String, sum: &mut Sum) {
+      let ip = s.pr().db().get_page::<IndexPage>(page);
+      let db = s.pr().db();
+      let (slot_size, key_size) = (ip.slot_size() as usize, ip.key_size() as usize);
+      macro_rules! at { ($pos: expr) => { ip.data.as_mut_ptr().add($pos * slot_size) }; }
+      macro_rules! at_ch { ($pos: expr) => { *(ip.data.as_mut_ptr().add($pos * slot_size + key_size) as *mut u32) }; }
+      let ty = ColTy::FixTy(FixTy { ty: T, size: 0 });
+      let _ = write!(out, "{:1$}page {2}: {3}, {4} / {5} ({6}%)", "", depth as usize * 2, page, if ip.leaf { "leaf" } else { "inner" },
+        ip.count, ip.cap, ip.count as u32 * 100 / ip.cap as u32);
+      if ip.count != 0 { let _ = write!(out, ", keys = [{:?}, {:?}]", db.ptr2lit(at!(0), ty), db.ptr2lit(at!(ip.count as usize - 1), ty)); }
+      out.push('\n');
+      sum.0 = sum.0.max(depth + 1);
+      (sum.1 += 1, sum.4 += ip.count as u64, sum.5 += ip.cap as u64);
+      if ip.leaf {
+        (sum.2 += 1, sum.3 += ip.count as u64);
+      } else {
+        for i in 0..ip.count as usize { dfs(s, at_ch!(i), depth + 1, out, sum); }
+      }
+    }
+    let (mut out, mut sum) = (String::new(), (0, 0, 0, 0, 0, 0));
+    dfs(self, self.root(), 0, &mut out, &mut sum);
+    let _ = write!(out, "depth = {}, nodes = {}, leaves = {}, keys = {}, fill = {}%", sum.0, sum.1, sum.2, sum.3, sum.4 * 100 / sum.5);
+    out
   }
 
   #[cfg(feature = "print-dot")]
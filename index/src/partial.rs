@@ -0,0 +1,22 @@
+use common::{*, BareTy::*};
+use physics::*;
+use db::{Db, is_null};
+use crate::{handle_all, cmp::Cmp};
+
+// whether record `data` should have a key in the index on `ci`: not null (both cols for a spatial index),
+// and satisfies the predicate if it is a partial index
+pub unsafe fn in_index(db: &Db, tp: &TablePage, ci: &ColInfo, data: *const u8) -> bool {
+  if is_null(data, ci.idx(&tp.cols)) || (ci.idx_spatial != 0 && is_null(data, ci.idx_spatial as u32 - 1)) { return false; }
+  let (&mut PartialPred { ci_id, op, .. }, val) = match db.partial_pred(ci) { Some(p) => p, None => return true };
+  let null = is_null(data, ci_id as u32);
+  match op {
+    PARTIAL_NULL => null,
+    PARTIAL_NOTNULL => !null,
+    _ => {
+      let p_ci = tp.cols.get_unchecked(ci_id as usize);
+      let ptr = data.add(p_ci.off as usize);
+      macro_rules! handle { ($ty: ident) => { Cmp::<{ $ty }>::cmp(ptr, val) }; }
+      !null && CmpOp::ALL[op as usize].test(handle_all!(p_ci.ty.fix_ty().ty, handle))
+    }
+  }
+}
\ No newline at end of file
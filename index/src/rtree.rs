@@ -0,0 +1,171 @@
+use std::mem;
+
+use common::*;
+use physics::*;
+use db::Db;
+
+// the R-tree of a spatial index on (x, y), where x is the `ci_id`th col and y is stored in its `idx_spatial`
+// a leaf entry is the point of a record, an inner entry is the bounding box of a child
+// insertion chooses the child that needs the least enlargement, and a full node is split into halves by the center of entries
+// deletion doesn't merge underfull nodes, it only removes empty ones, so the tree may be sparse after many deletions
+// like `Index`, it uses pointer to avoid lifetime parameter
+pub struct RTree {
+  db: *mut Db,
+  tp_id: u32,
+  ci_id: u32,
+}
+
+// the point of record `data` in the spatial index on `ci`, both cols should be not null
+pub unsafe fn spatial_point(tp: &TablePage, ci: &ColInfo, data: *const u8) -> Rect {
+  let y = tp.cols.get_unchecked(ci.idx_spatial as usize - 1);
+  Rect::point(*(data.add(ci.off as usize) as *const f32), *(data.add(y.off as usize) as *const f32))
+}
+
+impl RTree {
+  pub unsafe fn new(db: &mut Db, tp_id: u32, ci_id: u32) -> RTree { RTree { db, tp_id, ci_id } }
+
+  unsafe fn db<'a>(&mut self) -> &'a mut Db { self.db.r() }
+  unsafe fn root(&self) -> u32 { self.db.r().get_page::<TablePage>(self.tp_id).cols.get_unchecked(self.ci_id as usize).index }
+  unsafe fn set_root(&mut self, root: u32) { self.db().get_page::<TablePage>(self.tp_id).cols.get_unchecked_mut(self.ci_id as usize).index = root; }
+
+  // caller guarantee (point, rid) doesn't exist in tree
+  pub unsafe fn insert(&mut self, point: Rect, rid: Rid) {
+    let root = self.root();
+    if let Some(split_page) = self.do_insert(root, RTreeEntry { rect: point, ptr: mem::transmute(rid) }) {
+      let (new_id, new) = self.db().alloc_page::<RTreePage>();
+      new.init(false);
+      for &page in &[root, split_page] {
+        let rect = self.db().get_page::<RTreePage>(page).mbr();
+        push(new, RTreeEntry { rect, ptr: page });
+      }
+      self.set_root(new_id);
+    }
+  }
+
+  // return Some(new page id) if `page` is split
+  unsafe fn do_insert(&mut self, page: u32, e: RTreeEntry) -> Option<u32> {
+    let rp = self.db().get_page::<RTreePage>(page);
+    if !rp.leaf {
+      let pos = choose(rp, &e.rect);
+      let ch = rp.entries.get_unchecked(pos).ptr;
+      if let Some(split_page) = self.do_insert(ch, e) {
+        rp.entries.get_unchecked_mut(pos).rect = self.db().get_page::<RTreePage>(ch).mbr();
+        let rect = self.db().get_page::<RTreePage>(split_page).mbr();
+        push(rp, RTreeEntry { rect, ptr: split_page });
+      } else {
+        let old = &mut rp.entries.get_unchecked_mut(pos).rect;
+        *old = old.union(&e.rect);
+      }
+    } else { push(rp, e); }
+    if rp.count as usize == MAX_RTREE_ENTRY { Some(self.split(rp)) } else { None }
+  }
+
+  // move half of the entries in `rp` into a new page, return its id
+  // entries are sorted by the center on the axis where centers spread wider, so the two halves overlap less
+  unsafe fn split(&mut self, rp: &mut RTreePage) -> u32 {
+    let es = rp.entries.get_unchecked_mut(..rp.count as usize);
+    let (mut lo, mut hi) = ((std::f32::MAX, std::f32::MAX), (std::f32::MIN, std::f32::MIN));
+    for e in es.iter() {
+      let (x, y) = e.rect.center();
+      (lo.0 = lo.0.min(x), lo.1 = lo.1.min(y), hi.0 = hi.0.max(x), hi.1 = hi.1.max(y));
+    }
+    if hi.0 - lo.0 >= hi.1 - lo.1 {
+      es.sort_unstable_by(|l, r| fcmp(l.rect.center().0, r.rect.center().0));
+    } else {
+      es.sort_unstable_by(|l, r| fcmp(l.rect.center().1, r.rect.center().1));
+    }
+    let (new_id, new) = self.db().alloc_page::<RTreePage>();
+    new.init(rp.leaf);
+    let half = rp.count / 2;
+    new.count = rp.count - half;
+    new.entries.as_mut_ptr().copy_from_nonoverlapping(rp.entries.as_ptr().add(half as usize), new.count as usize);
+    rp.count = half;
+    new_id
+  }
+
+  // caller guarantee (point, rid) exists in tree
+  pub unsafe fn delete(&mut self, point: Rect, rid: Rid) {
+    let mut root = self.root();
+    let found = self.do_delete(root, &point, mem::transmute(rid));
+    debug_assert!(found);
+    // shrink the height while the root has only one child
+    loop {
+      let rp = self.db().get_page::<RTreePage>(root);
+      if rp.leaf || rp.count != 1 { break; }
+      let old = (root, root = rp.entries.get_unchecked(0).ptr).0;
+      (self.db().dealloc_page(old), self.set_root(root));
+    }
+  }
+
+  // return true if found and deleted
+  unsafe fn do_delete(&mut self, page: u32, point: &Rect, rid: u32) -> bool {
+    let rp = self.db().get_page::<RTreePage>(page);
+    for (pos, e) in rp.entries().iter().enumerate() {
+      if !e.rect.contains(point) { continue; }
+      if rp.leaf {
+        if e.ptr != rid { continue; }
+      } else {
+        if !self.do_delete(e.ptr, point, rid) { continue; }
+        let ch = self.db().get_page::<RTreePage>(e.ptr);
+        if ch.count != 0 {
+          rp.entries.get_unchecked_mut(pos).rect = ch.mbr();
+          return true;
+        }
+        self.db().dealloc_page(e.ptr);
+      }
+      rp.count -= 1;
+      *rp.entries.get_unchecked_mut(pos) = *rp.entries.get_unchecked(rp.count as usize);
+      return true;
+    }
+    false
+  }
+
+  // call `f` with the rid of every point in `rect`, in no particular order
+  pub unsafe fn search(&mut self, rect: &Rect, f: &mut impl FnMut(Rid)) {
+    let root = self.root();
+    self.do_search(root, rect, f);
+  }
+
+  unsafe fn do_search(&mut self, page: u32, rect: &Rect, f: &mut impl FnMut(Rid)) {
+    let rp = self.db().get_page::<RTreePage>(page);
+    for e in rp.entries() {
+      if rp.leaf {
+        if rect.contains(&e.rect) { f(mem::transmute(e.ptr)); }
+      } else if rect.intersects(&e.rect) { self.do_search(e.ptr, rect, f); }
+    }
+  }
+
+  // check that every bounding box is the tightest one covering its child, and all leaves are at the same depth
+  // return the number of points
+  pub unsafe fn debug_check_all(&self) -> usize {
+    unsafe fn dfs(db: &mut Db, page: u32, depth: u32, leaf_depth: &mut Option<u32>) -> usize {
+      let rp = db.get_page::<RTreePage>(page);
+      assert!((rp.count as usize) < MAX_RTREE_ENTRY);
+      if rp.leaf {
+        assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+        return rp.count as usize;
+      }
+      assert_ne!(rp.count, 0);
+      rp.entries().iter().map(|e| {
+        assert_eq!(e.rect, db.get_page::<RTreePage>(e.ptr).mbr());
+        dfs(db, e.ptr, depth + 1, leaf_depth)
+      }).sum()
+    }
+    dfs(self.db.r(), self.root(), 0, &mut None)
+  }
+}
+
+unsafe fn push(rp: &mut RTreePage, e: RTreeEntry) {
+  *rp.entries.get_unchecked_mut(rp.count as usize) = e;
+  rp.count += 1;
+}
+
+// the child that needs the least enlargement to include `rect`, choose the smaller one on tie
+unsafe fn choose(rp: &RTreePage, rect: &Rect) -> usize {
+  let cost = |e: &RTreeEntry| { let area = e.rect.area(); (e.rect.union(rect).area() - area, area) };
+  let es = rp.entries();
+  (1..es.len()).fold(0, |best, i| {
+    let (c, c1) = (cost(es.get_unchecked(i)), cost(es.get_unchecked(best)));
+    if c.0 < c1.0 || (c.0 == c1.0 && c.1 < c1.1) { i } else { best }
+  })
+}
\ No newline at end of file
@@ -0,0 +1,25 @@
+use std::mem::size_of;
+
+use common::*;
+use crate::{MAX_COL, VarcharSlot};
+
+// comments of a table and its cols, set by `comment on`, the texts are stored in lob like varchar
+// `len == 0` for no comment, then `lob_id` and `cap` are meaningless
+#[repr(C)]
+pub struct CommentPage {
+  pub table: VarcharSlot,
+  pub cols: [VarcharSlot; MAX_COL],
+}
+
+// so that the length and the allocated bytes of a comment fit in `VarcharSlot`
+pub const MAX_COMMENT: usize = 8192;
+
+impl CommentPage {
+  pub fn init(&mut self) {
+    self.table.len = 0;
+    for c in self.cols.iter_mut() { c.len = 0; }
+  }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+fn _ck() { const_assert!(size_of::<CommentPage>() <= PAGE_SIZE); }
\ No newline at end of file
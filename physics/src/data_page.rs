@@ -7,20 +7,53 @@ pub struct DataPage {
   // !0 for none
   pub next_free: u32,
   pub count: u16,
-  pub _rsv: [u8; 2],
+  // the partition that all records in this page belong to, 0 if the table is not partitioned (see `PartPage`)
+  pub part: u16,
   pub used: [u32; common::MAX_SLOT_BS],
+  // the slots, followed by the Bloom filter of values in this page (see `bloom_bits` and `Db::bloom_add`)
   pub data: [u8; common::MAX_DATA_BYTE],
 }
 
+// the number of bits set for one value
+pub const BLOOM_K: u32 = 3;
+// the bytes of Bloom filter reserved for each value in a page (see `slot_cap`), 8 bits per value with BLOOM_K = 3 give about 3% false positives
+pub const BLOOM_BYTES_PER_VAL: usize = 1;
+
+// the bits of the Bloom filter in a data page of a table with slot `size` and `cap` (see `TablePage`)
+// the filter takes the words after the slots, so it is at least BLOOM_BYTES_PER_VAL bytes for each col of each slot,
+// unless a record takes (almost) a whole data page, then the filter is small or absent, which is fine for so few records
+pub fn bloom_bits(size: u16, cap: u16) -> usize { common::MAX_DATA_BYTE.saturating_sub((size as usize * cap as usize + 3) & !3) / 4 * 32 }
+
 impl DataPage {
-  pub unsafe fn init(&mut self, next: u32) {
+  // `bits` is the size of Bloom filter of the table (see `bloom_bits`), the same for all methods below
+  pub unsafe fn init(&mut self, next: u32, part: u16, bits: usize) {
     self.next = next;
     self.next_free = !0;
-    self.count = 0;
+    (self.count = 0, self.part = part);
     self.used.as_mut_ptr().write_bytes(0, common::MAX_SLOT_BS);
+    self.bloom_clear(bits);
+  }
+
+  unsafe fn bloom(&self, bits: usize) -> *mut u32 { self.data.as_ptr().add(common::MAX_DATA_BYTE - bits / 8) as *mut u32 }
+
+  pub unsafe fn bloom_clear(&mut self, bits: usize) { self.bloom(bits).write_bytes(0, bits / 32); }
+
+  pub unsafe fn bloom_set(&mut self, bits: usize, hash: u64) {
+    if bits == 0 { return; }
+    for i in 0..BLOOM_K { common::bsset(self.bloom(bits), bloom_bit(hash, i, bits)); }
+  }
+
+  // false means the value of `hash` is definitely not in this page, always true if there is no filter
+  pub unsafe fn bloom_test(&self, bits: usize, hash: u64) -> bool {
+    bits == 0 || (0..BLOOM_K).all(|i| common::bsget(self.bloom(bits), bloom_bit(hash, i, bits)))
   }
 }
 
+// the `i`th bit of `hash` in a filter of `bits`, derived from its two halves by double hashing
+fn bloom_bit(hash: u64, i: u32, bits: usize) -> usize {
+  ((hash as u32).wrapping_add(i.wrapping_mul((hash >> 32) as u32 | 1)) as usize) % bits
+}
+
 // for simplicity, one check list always use one page, if size exceeds the limit, just reject it
 #[repr(C)]
 pub struct CheckPage {
@@ -31,6 +64,18 @@ pub struct CheckPage {
 
 pub const MAX_CHECK_BYTES: usize = 8188;
 
+// the predicate of a partial index, `col <op> val`, or `col is null` / `col is not null` (`op` is PARTIAL_NULL / PARTIAL_NOTNULL)
+// `col` is the `ci_id`-th col in table, `op` is `CmpOp as u8`, `val` follows this struct, in the format of `col`
+#[repr(C)]
+pub struct PartialPred {
+  pub ci_id: u8,
+  pub op: u8,
+  pub _rsv: [u8; 2],
+}
+
+pub const PARTIAL_NULL: u8 = 6;
+pub const PARTIAL_NOTNULL: u8 = 7;
+
 // a blob slot can either be a FreeBlobSlot, or a [u8; 32]
 #[repr(C)]
 pub struct FreeLobSlot {
@@ -1,33 +1,91 @@
-use std::{mem::size_of, slice};
+use std::mem::size_of;
 
 use common::*;
 
 #[repr(C)]
 pub struct DbPage {
   pub magic: [u8; MAGIC_LEN],
-  pub _rsv1: [u8; 2],
+  // `FORMAT_VERSION` when it is created
+  pub format: u16,
   // !0 for none
   pub first_free: u32,
-  // using u16 here is not to save space (since there is still enough space in _rsv)
-  // but to explicitly show that u16 is enough
-  pub table_num: u16,
-  pub _rsv2: [u8; 2],
+  // the first MAX_TABLE table ids are in `tables`, the others are in the chain of `DirPage` from `dir`
+  pub table_num: u32,
+  // !0 for none
+  pub dir: u32,
+  // the number of migrations applied to this db, see `Eval::migrate` of the driver
+  pub schema_version: u32,
+  // the last version given to a table, see `TablePage::version`
+  pub last_version: u32,
+  // the values stored by `set persist`, indexed by `Setting`, !0 for not set
+  // there is room for MAX_SETTING of them, so adding a setting doesn't move `tables`
+  pub settings: [u32; MAX_SETTING],
   pub tables: [u32; MAX_TABLE],
 }
 
-pub const MAX_TABLE: usize = 2041;
+pub const MAX_SETTING: usize = 16;
+pub const MAX_TABLE: usize = 2022;
 
 impl DbPage {
   pub fn init(&mut self) {
-    self.magic = *MAGIC;
+    (self.magic = *MAGIC, self.format = FORMAT_VERSION);
     self.first_free = !0;
-    self.table_num = 0;
+    (self.table_num = 0, self.dir = !0, self.schema_version = 0, self.last_version = 0);
+    self.settings = [!0; MAX_SETTING];
   }
+}
+
+// the table ids that don't fit in `DbPage::tables`, the k-th page in the chain holds the ids from MAX_TABLE + k * DIR_TABLES
+// a page is only allocated when it is needed, and deallocated once it is empty, see `Db::add_table`
+#[repr(C)]
+pub struct DirPage {
+  // !0 for none
+  pub next: u32,
+  pub tables: [u32; DIR_TABLES],
+}
+
+pub const DIR_TABLES: usize = 2047;
 
-  pub unsafe fn tables<'a>(&self) -> &'a [u32] {
-    slice::from_raw_parts(self.tables.as_ptr(), self.table_num as usize)
+// the tag of what each page is used for, so the use of a page can be checked without walking the db (see `Db::page`)
+// the pages from 1 are divided into groups of KIND_SPAN pages, the first page of a group is a `KindPage` holding the tags of the group
+// the db page (page 0) is in no group, its tag is always `TAG_HEADER`
+#[repr(C)]
+pub struct KindPage {
+  pub tags: [u8; KIND_SPAN],
+}
+
+pub const KIND_SPAN: usize = common::PAGE_SIZE;
+
+// a newly appended page is zeroed, so it is free; the pages after the first one of a structure taking several pages are `TAG_EXTRA`
+pub const TAG_FREE: u8 = 0;
+pub const TAG_HEADER: u8 = 1;
+pub const TAG_KIND: u8 = 2;
+pub const TAG_DIR: u8 = 3;
+pub const TAG_TABLE: u8 = 4;
+pub const TAG_DATA: u8 = 5;
+pub const TAG_STATS: u8 = 6;
+pub const TAG_HIST: u8 = 7;
+pub const TAG_COMMENT: u8 = 8;
+pub const TAG_PART: u8 = 9;
+pub const TAG_CHECK: u8 = 10;
+pub const TAG_INDEX: u8 = 11;
+pub const TAG_RTREE: u8 = 12;
+pub const TAG_EXTRA: u8 = 13;
+
+impl KindPage {
+  // the `KindPage` holding the tag of `page` (which is not 0), and the index of the tag in it
+  pub fn of(page: u32) -> (u32, usize) {
+    let (group, idx) = ((page - 1) / KIND_SPAN as u32, (page - 1) as usize % KIND_SPAN);
+    (group * KIND_SPAN as u32 + 1, idx)
   }
+
+  pub fn is_kind_page(page: u32) -> bool { page != 0 && (page - 1) as usize % KIND_SPAN == 0 }
 }
 
 #[cfg_attr(tarpaulin, skip)]
-fn _ck() { const_assert_eq!(size_of::<DbPage>(), common::PAGE_SIZE); }
\ No newline at end of file
+fn _ck() {
+  const_assert_eq!(size_of::<DbPage>(), common::PAGE_SIZE);
+  const_assert!(SETTING_NUM <= MAX_SETTING);
+  const_assert_eq!(size_of::<DirPage>(), common::PAGE_SIZE);
+  const_assert_eq!(size_of::<KindPage>(), common::PAGE_SIZE);
+}
@@ -7,5 +7,9 @@ pub mod db_page;
 pub mod index_page;
 pub mod table_page;
 pub mod rid;
+pub mod rtree_page;
+pub mod stats_page;
+pub mod comment_page;
+pub mod part_page;
 
-pub use crate::{data_page::*, db_page::*, index_page::*, table_page::*, rid::*};
\ No newline at end of file
+pub use crate::{data_page::*, db_page::*, index_page::*, table_page::*, rid::*, rtree_page::*, stats_page::*, comment_page::*, part_page::*};
\ No newline at end of file
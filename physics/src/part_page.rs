@@ -0,0 +1,36 @@
+use std::mem::size_of;
+
+use common::*;
+
+// how the records of a partitioned table are assigned to partitions, each data page only holds records of one partition
+// (see `DataPage::part`), so a scan can skip the pages of partitions that can't satisfy its where list,
+// and all records of a partition can be dropped together with its pages
+#[repr(C)]
+pub struct PartPage {
+  // the partition col, a null value is always in partition 0
+  pub ci_id: u8,
+  // by hash: a value is in partition `bloom_hash % count` (see `Db::bloom_hash`)
+  // by range: a value is in partition i if it is in [bounds[i - 1], bounds[i]), bounds[-1] = -inf, bounds[count - 1] = +inf
+  pub hash: bool,
+  pub count: u16,
+  // the head of the list of pages with free slots of each partition, !0 for none; `TablePage::first_free` is not used
+  pub free: [u32; MAX_PART],
+  // the `count - 1` ascending bounds of a range partitioning, in the format of the col
+  pub bounds: [u8; MAX_PART_BYTES],
+}
+
+pub const MAX_PART: usize = 64;
+pub const MAX_PART_BYTES: usize = PAGE_SIZE - 4 - MAX_PART * 4;
+
+impl PartPage {
+  pub unsafe fn init(&mut self, ci_id: u8, hash: bool, count: u16) {
+    (self.ci_id = ci_id, self.hash = hash, self.count = count);
+    for f in self.free.iter_mut() { *f = !0; }
+  }
+
+  // the `i`th bound, `size` is the size of the col
+  pub unsafe fn bound(&self, i: usize, size: usize) -> *const u8 { self.bounds.as_ptr().add(i * size) }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+fn _ck() { const_assert_eq!(size_of::<PartPage>(), PAGE_SIZE); }
\ No newline at end of file
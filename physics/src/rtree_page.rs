@@ -0,0 +1,67 @@
+use std::{mem::size_of, slice};
+
+// a node of the R-tree of a spatial index, the tree is balanced, all leaves are at the same depth
+#[repr(C)]
+pub struct RTreePage {
+  pub count: u16,
+  pub leaf: bool,
+  pub _rsv: u8,
+  pub entries: [RTreeEntry; MAX_RTREE_ENTRY],
+}
+
+// `rect` is the bounding box of the child for inner node, and is the point (min == max) of the record for leaf
+// `ptr` is the child page id for inner node, and is the rid of the record for leaf
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct RTreeEntry {
+  pub rect: Rect,
+  pub ptr: u32,
+}
+
+// [min_x, max_x] * [min_y, max_y], both ends are inclusive
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+  pub min_x: f32,
+  pub min_y: f32,
+  pub max_x: f32,
+  pub max_y: f32,
+}
+
+pub const MAX_RTREE_ENTRY: usize = 409;
+
+impl RTreePage {
+  pub fn init(&mut self, leaf: bool) { (self.count = 0, self.leaf = leaf); }
+
+  pub unsafe fn entries<'a>(&self) -> &'a [RTreeEntry] {
+    slice::from_raw_parts(self.entries.as_ptr(), self.count as usize)
+  }
+
+  // the bounding box of all entries, the page should not be empty
+  pub unsafe fn mbr(&self) -> Rect {
+    let es = self.entries();
+    es.iter().skip(1).fold(es.get_unchecked(0).rect, |r, e| r.union(&e.rect))
+  }
+}
+
+impl Rect {
+  pub fn point(x: f32, y: f32) -> Rect { Rect { min_x: x, min_y: y, max_x: x, max_y: y } }
+
+  pub fn area(&self) -> f32 { (self.max_x - self.min_x) * (self.max_y - self.min_y) }
+
+  pub fn union(&self, r: &Rect) -> Rect {
+    Rect { min_x: self.min_x.min(r.min_x), min_y: self.min_y.min(r.min_y), max_x: self.max_x.max(r.max_x), max_y: self.max_y.max(r.max_y) }
+  }
+
+  pub fn intersects(&self, r: &Rect) -> bool {
+    self.min_x <= r.max_x && r.min_x <= self.max_x && self.min_y <= r.max_y && r.min_y <= self.max_y
+  }
+
+  pub fn contains(&self, r: &Rect) -> bool {
+    self.min_x <= r.min_x && r.max_x <= self.max_x && self.min_y <= r.min_y && r.max_y <= self.max_y
+  }
+
+  pub fn center(&self) -> (f32, f32) { ((self.min_x + self.max_x) / 2.0, (self.min_y + self.max_y) / 2.0) }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+fn _ck() { const_assert!(size_of::<RTreePage>() <= common::PAGE_SIZE); }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::{mem::size_of, slice};
+
+use common::*;
+use crate::{MAX_INLINE_COL, COLS_PER_PAGE, col_pages};
+
+// statistics of a table collected by `analyze`, they are not updated by insert / delete / update, so they may be stale
+// they are collected again once there are too many changes since then, see `Db::refresh_stats`
+#[repr(C)]
+pub struct StatsPage {
+  // the number of records when it is collected
+  pub count: u32,
+  // the number of records inserted / deleted / updated since it is collected
+  pub changes: u32,
+  // the number of cols when it is collected, `cols` continues in the pages after this one like `TablePage::cols`
+  pub col_num: u32,
+  pub cols: [ColStats; MAX_INLINE_COL],
+}
+
+#[repr(C)]
+pub struct ColStats {
+  pub nulls: u32,
+  // the number of distinct not-null values, counted by their hashes (see `Db::bloom_hash`), so it is an estimate
+  pub distinct: u32,
+  // min / max of not-null values in the format of col, meaningless if `distinct == 0`
+  // a char / varchar value is stored in the format of char, and truncated to MAX_STATS_STR bytes
+  pub min: [u8; STATS_VAL_SIZE],
+  pub max: [u8; STATS_VAL_SIZE],
+  // histogram page id (see `HistPage`), !0 for none, i.e., it is not a number / date col, or it has no not-null value
+  pub hist: u32,
+}
+
+pub const STATS_VAL_SIZE: usize = 26;
+pub const MAX_STATS_STR: usize = STATS_VAL_SIZE - 1;
+
+impl StatsPage {
+  pub unsafe fn init(&mut self, col_num: u32) {
+    self.col_num = col_num;
+    for cs in self.cols_mut() { cs.hist = !0; }
+  }
+
+  pub unsafe fn cols_mut<'a>(&mut self) -> &'a mut [ColStats] { slice::from_raw_parts_mut(self.cols.as_mut_ptr(), self.col_num as usize) }
+
+  // the number of pages after this page, see `col_pages`
+  pub fn col_pages(&self) -> u32 { col_pages(self.col_num as usize) }
+}
+
+// an equi-depth histogram of the not-null values of a number / date col, collected with its `ColStats`
+// values are placed on the number axis (a date is the number of days from CE), and each bucket [bounds[i], bounds[i + 1]]
+// holds about 1 / `buckets` of the values, so a frequent value may take up several buckets with bounds[i] == bounds[i + 1]
+#[repr(C)]
+pub struct HistPage {
+  pub buckets: u32,
+  pub _rsv: u32,
+  pub bounds: [f64; MAX_HIST_BOUNDS],
+}
+
+pub const MAX_HIST_BOUNDS: usize = 1023;
+// the number of buckets of a histogram, fewer if the col has fewer not-null values
+pub const HIST_BUCKETS: usize = 100;
+
+impl HistPage {
+  pub unsafe fn bounds(&self) -> &[f64] { self.bounds.get_unchecked(..=self.buckets as usize) }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+fn _ck() {
+  const_assert_eq!(size_of::<ColStats>(), PAGE_SIZE / COLS_PER_PAGE);
+  const_assert!(size_of::<StatsPage>() <= PAGE_SIZE);
+  const_assert_eq!(size_of::<HistPage>(), PAGE_SIZE);
+  const_assert!(HIST_BUCKETS < MAX_HIST_BOUNDS);
+}
\ No newline at end of file
@@ -1,12 +1,22 @@
 use std::{mem::size_of, slice};
 
 use common::{*, Error::*};
+use crate::{bloom_bits, BLOOM_BYTES_PER_VAL};
 
 bitflags::bitflags! {
   pub struct ColFlags: u8 {
     const PRIMARY = 0b1;
     const NOTNULL = 0b10;
     const UNIQUE = 0b100;
+    // declared by `create index ... (col desc)`; it doesn't change the order in index, because index can be scanned in both directions
+    const DESC = 0b1000;
+    // the index is built on `lower(col)` / `upper(col)`, instead of col itself
+    const LOWER = 0b10000;
+    const UPPER = 0b100000;
+    // the index only contains records satisfying a predicate, which is stored in the check page (see `PartialPred`)
+    const PARTIAL = 0b1000000;
+    // a full-text index, keys are (word, rid) pairs of all words in the text (see `index::fulltext`)
+    const FULLTEXT = 0b10000000;
     const NOTNULL1 = Self::PRIMARY.bits | Self::NOTNULL.bits; // if any bits in NOTNULL1 exists, this slot can't be null
   }
 }
@@ -32,29 +42,72 @@ pub struct ColInfo {
   pub idx_name: [u8; MAX_IDX_NAME],
   pub name_len: u8,
   pub name: [u8; MAX_COL_NAME],
+  // if it is not 0, only the first `idx_prefix` bytes of a char col are put into index; it is always 0 if `index == !0`
+  pub idx_prefix: u8,
+  // if it is not 0, `index` is the root of a spatial index (see `RTreePage`) on the point (this col, `idx_spatial - 1`th col)
+  pub idx_spatial: u8,
+  // the low 2 bits are the `FkAction` on delete, the next 2 bits are the one on update, meaningless if `f_table == !0`
+  pub f_action: u8,
+  // 0 for none, otherwise the default value is `DftFn::ALL[dft_fn - 1]` instead of the one in check page
+  pub dft_fn: u8,
 }
 
 impl ColInfo {
-  // `idx_name_len` and `idx_name` is not initialized here
-  pub unsafe fn init(&mut self, ty: ColTy, off: u16, name: &str, notnull: bool) {
+  // `idx_name_len` and `idx_name` is not initialized here, neither is the name (see `TablePage::set_col_name`)
+  pub unsafe fn init(&mut self, ty: ColTy, off: u16, notnull: bool) {
     self.ty = ty;
     self.off = off;
-    self.index = !0;
+    (self.index = !0, self.idx_prefix = 0, self.idx_spatial = 0);
     self.check = !0;
-    self.name_len = name.len() as u8;
-    self.name.as_mut_ptr().copy_from_nonoverlapping(name.as_ptr(), name.len());
     self.flags = if notnull { ColFlags::NOTNULL } else { ColFlags::empty() };
-    self.f_table = !0;
+    (self.f_table = !0, self.f_action = 0, self.dft_fn = 0);
   }
 
-  pub unsafe fn name<'a>(&self) -> &'a str {
-    str_from_parts(self.name.as_ptr(), self.name_len as usize)
+  pub unsafe fn name<'a>(&self) -> &'a str { get_name(self.name.as_ptr(), MAX_COL_NAME, self.name_len) }
+
+  // `self` is moved `by` bytes towards the table page (i.e., a col before it is dropped), so the offset to its long name grows as well
+  pub unsafe fn name_moved(&mut self, by: u32) {
+    if self.name_len as usize > MAX_COL_NAME {
+      let p = self.name.as_mut_ptr() as *mut u32;
+      p.write_unaligned(p.read_unaligned() + by);
+    }
   }
 
   pub unsafe fn idx_name<'a>(&self) -> Option<&'a str> {
     if self.index != !0 { Some(str_from_parts(self.idx_name.as_ptr(), self.idx_name_len as usize)) } else { None }
   }
 
+  // the size of data part in the key of index
+  pub fn idx_key_size(&self) -> u16 {
+    if self.flags.contains(ColFlags::FULLTEXT) { MAX_TOKEN as u16 + 1 } else if self.idx_prefix == 0 { self.ty.size() } else { self.idx_prefix as u16 + 1 }
+  }
+
+  pub fn idx_fn(&self) -> Option<StrFn> {
+    if self.flags.contains(ColFlags::LOWER) { Some(StrFn::Lower) } else if self.flags.contains(ColFlags::UPPER) { Some(StrFn::Upper) } else { None }
+  }
+
+  pub fn set_idx_fn(&mut self, func: Option<StrFn>) {
+    self.flags.remove(ColFlags::LOWER | ColFlags::UPPER);
+    match func { Some(StrFn::Lower) => self.flags.insert(ColFlags::LOWER), Some(StrFn::Upper) => self.flags.insert(ColFlags::UPPER), None => {} }
+  }
+
+  // keys in index are exactly the col values of all not-null records, i.e., it is not a prefix / expression / partial / full-text / spatial index
+  // only such an index can be used to check constraints, or to provide col values / order
+  pub fn idx_exact(&self) -> bool {
+    self.idx_prefix == 0 && self.idx_spatial == 0 && !self.flags.intersects(ColFlags::LOWER | ColFlags::UPPER | ColFlags::PARTIAL | ColFlags::FULLTEXT)
+  }
+
+  // the offset of `PartialPred` in the check page, after the check list and the default value, aligned to 4
+  pub fn partial_off(&self, cp: &CheckPage) -> usize {
+    ((cp.count as usize + (self.check & 1) as usize) * self.ty.size() as usize + 3) & !3
+  }
+
+  pub fn on_delete(&self) -> FkAction { FkAction::ALL[(self.f_action & 3) as usize] }
+
+  pub fn on_update(&self) -> FkAction { FkAction::ALL[(self.f_action >> 2 & 3) as usize] }
+
+  pub fn dft_fn(&self) -> Option<DftFn> { if self.dft_fn == 0 { None } else { Some(DftFn::ALL[self.dft_fn as usize - 1]) } }
+
   pub fn unique(&self, primary_cnt: usize) -> bool {
     self.flags.contains(ColFlags::UNIQUE) || (self.flags.contains(ColFlags::PRIMARY) && primary_cnt == 1)
   }
@@ -68,35 +121,109 @@ pub struct TablePage {
   pub first_free: u32,
   // there are at most (64G / 16) = 4G records, so u32 is enough
   pub count: u32,
+  // statistics page id (see `StatsPage`), !0 for not analyzed
+  pub stats: u32,
+  // comment page id (see `CommentPage`), !0 for none
+  pub comment: u32,
+  // partition page id (see `PartPage`), !0 for not partitioned
+  pub part: u32,
   // the size of a single slot, including null-bitset and data
   pub size: u16,
-  // always equal to MAX_DATA_BYTE / size, store it just to avoid division
+  // always equal to `slot_cap(size, col_num)`, store it just to avoid division
   pub cap: u16,
+  // the number of bytes used in the name pages, which hold the names longer than the inline ones (see `put_name`)
+  // the bytes of a renamed table / dropped col are not reused
+  pub names: u32,
+  // given by `Db::new_version` when the table is created, and again whenever its cols are added or dropped
+  // a version is never given twice in a db, so a `TableHandle` can tell whether the table is still the one it was got from
+  pub version: u32,
   pub name_len: u8,
   pub name: [u8; MAX_TABLE_NAME],
   pub col_num: u8,
-  pub cols: [ColInfo; MAX_COL],
+  // only the first MAX_INLINE_COL cols are in this page, the others continue in the pages right after it (see `col_pages`)
+  // so they are still contiguous in memory, and can be indexed as usual
+  pub cols: [ColInfo; MAX_INLINE_COL],
 }
 
-pub const MAX_TABLE_NAME: usize = 46;
+// a table / col name longer than them is stored in the name pages, and the inline bytes hold where it is, see `TablePage::put_name`
+pub const MAX_TABLE_NAME: usize = 26;
 pub const MAX_COL_NAME: usize = 25;
-pub const MAX_IDX_NAME: usize = 15;
-pub const MAX_COL: usize = 127;
+// the length of a name is stored in u8
+pub const MAX_NAME: usize = 255;
+pub const MAX_IDX_NAME: usize = 13;
+// a word longer than it is truncated in full-text index
+pub const MAX_TOKEN: usize = 31;
+pub const MAX_INLINE_COL: usize = 127;
+// the limit of cols in a table, the cols after the first MAX_INLINE_COL ones are in the col pages (see `col_pages`)
+// col ids are stored in u8 (e.g., `col_num`, `f_col`, `PartialPred::ci_id`), so it can't be larger without changing the layout of these pages
+pub const MAX_COL: usize = 255;
+// the number of `ColInfo` (or `ColStats`) in a whole page
+pub const COLS_PER_PAGE: usize = PAGE_SIZE / size_of::<ColInfo>();
+
+// the number of pages after a table page (or a stats page) that hold the cols after the first MAX_INLINE_COL ones
+pub fn col_pages(col_num: usize) -> u32 { ((col_num.saturating_sub(MAX_INLINE_COL) + COLS_PER_PAGE - 1) / COLS_PER_PAGE) as u32 }
+
+// the number of pages after a table page with `col_num` cols and `names` bytes used in name pages
+// the name pages are after the col pages for MAX_COL cols (even if there are fewer cols), so that adding cols doesn't move them
+pub fn table_pages(col_num: usize, names: u32) -> u32 {
+  if names == 0 { col_pages(col_num) } else { col_pages(MAX_COL) + ((names as usize + PAGE_SIZE - 1) / PAGE_SIZE) as u32 }
+}
+
+// the number of bytes `name` takes in the name pages, 0 if it fits in `inline` bytes
+pub fn long_name_bytes(name: &str, inline: usize) -> u32 { if name.len() > inline { name.len() as u32 } else { 0 } }
+
+// the name written by `TablePage::put_name` into `buf`
+unsafe fn get_name<'a>(buf: *const u8, inline: usize, len: u8) -> &'a str {
+  let len = len as usize;
+  str_from_parts(if len <= inline { buf } else { buf.add((buf as *const u32).read_unaligned() as usize) }, len)
+}
 
 impl TablePage {
-  pub unsafe fn init(&mut self, size: u16, col_num: u8, name: &str) {
+  // the names of the table and its cols are not initialized here, see `set_name` and `set_col_name`
+  pub unsafe fn init(&mut self, size: u16, col_num: u8) {
     (self.first = !0, self.first_free = !0);
-    self.count = 0;
-    (self.size = size, self.cap = MAX_DATA_BYTE as u16 / size);
-    self.name_len = name.len() as u8;
-    self.name.as_mut_ptr().copy_from_nonoverlapping(name.as_ptr(), name.len());
+    (self.count = 0, self.stats = !0, self.comment = !0, self.part = !0);
+    (self.size = size, self.cap = slot_cap(size, col_num), self.names = 0);
     self.col_num = col_num;
   }
 
-  pub unsafe fn name<'a>(&self) -> &'a str {
-    str_from_parts(self.name.as_ptr(), self.name_len as usize)
+  pub unsafe fn name<'a>(&self) -> &'a str { get_name(self.name.as_ptr(), MAX_TABLE_NAME, self.name_len) }
+
+  // the pages after this page should be enough for a long `name`, see `table_pages`
+  pub unsafe fn set_name(&mut self, name: &str) {
+    self.name_len = name.len() as u8;
+    self.put_name(self.name.as_mut_ptr(), MAX_TABLE_NAME, name);
+  }
+
+  // the pages after this page should be enough for a long `name`, see `table_pages`
+  pub unsafe fn set_col_name(&mut self, ci_id: usize, name: &str) {
+    let ci = self.cols.as_mut_ptr().add(ci_id);
+    (*ci).name_len = name.len() as u8;
+    self.put_name((*ci).name.as_mut_ptr(), MAX_COL_NAME, name);
   }
 
+  // write `name` into the inline bytes `buf` if it fits in `inline` bytes, otherwise append it to the name pages and write its offset from `buf` into `buf`
+  // the name pages are allocated together with this page, so the offset is still valid after `Db::move_tp`
+  unsafe fn put_name(&mut self, buf: *mut u8, inline: usize, name: &str) {
+    if name.len() <= inline { return buf.copy_from_nonoverlapping(name.as_ptr(), name.len()); }
+    let at = (self as *mut TablePage as *mut u8).add((col_pages(MAX_COL) as usize + 1) * PAGE_SIZE + self.names as usize);
+    at.copy_from_nonoverlapping(name.as_ptr(), name.len());
+    (buf as *mut u32).write_unaligned(at.offset_from(buf) as u32);
+    self.names += name.len() as u32;
+  }
+
+  // the number of pages after this page, see `table_pages`
+  pub fn extra_pages(&self) -> u32 { table_pages(self.col_num as usize, self.names) }
+
+  // the number of col pages after this page, see `col_pages`
+  pub fn col_pages(&self) -> u32 { col_pages(self.col_num as usize) }
+
+  // the number of overflow pages after each data page of this table, see `overflow_pages`
+  pub fn overflow(&self) -> u32 { overflow_pages(self.size) }
+
+  // the bits of Bloom filter in each data page of this table, see `bloom_bits`
+  pub fn bloom_bits(&self) -> usize { bloom_bits(self.size, self.cap) }
+
   pub unsafe fn cols<'a>(&self) -> &'a [ColInfo] {
     slice::from_raw_parts(self.cols.as_ptr(), self.col_num as usize)
   }
@@ -105,14 +232,23 @@ impl TablePage {
     self.cols().iter().filter(|ci| ci.flags.contains(ColFlags::PRIMARY))
   }
 
-  pub unsafe fn get_ci<'a, 'b>(&mut self, col: &'b str) -> Result<'b, &'a mut ColInfo> {
-    match self.pr().cols().iter().map(|c| c.name()).enumerate().find(|n| n.1 == col) {
+  // `ignore_case` is `Db::ignore_case` of the database it is in, see `name_eq`
+  pub unsafe fn get_ci<'a, 'b>(&mut self, col: &'b str, ignore_case: bool) -> Result<'b, &'a mut ColInfo> {
+    match self.pr().cols().iter().map(|c| c.name()).enumerate().find(|n| name_eq(n.1, col, ignore_case)) {
       Some((idx, _)) => Ok(self.pr().cols.get_unchecked_mut(idx)),
       None => Err(NoSuchCol(col)),
     }
   }
 }
 
+// the number of records in a data page, each record also reserves BLOOM_BYTES_PER_VAL bytes of Bloom filter for each col (see `bloom_bits`)
+// a record wider than MAX_DATA_BYTE takes a whole data page
+pub fn slot_cap(size: u16, col_num: u8) -> u16 { (MAX_DATA_BYTE / (size as usize + col_num as usize * BLOOM_BYTES_PER_VAL)).max(1).min(MAX_SLOT) as u16 }
+
+// a record wider than MAX_DATA_BYTE spills into the overflow pages right after its data page (see `Db::alloc_pages`)
+// so it is still contiguous in memory, and all cols in it can be accessed by `off` as usual
+pub fn overflow_pages(size: u16) -> u32 { ((size as usize).saturating_sub(MAX_DATA_BYTE) + PAGE_SIZE - 1) as u32 / PAGE_SIZE as u32 }
+
 #[cfg_attr(tarpaulin, skip)]
 fn _ck() {
   const_assert_eq!(size_of::<ColInfo>(), 64);
@@ -0,0 +1,95 @@
+use unchecked_unwrap::UncheckedUnwrap;
+
+use common::*;
+use syntax::ast::*;
+use db::{Db, iter::RecordIter};
+use crate::{select::{Col, SelectCtx, SelectResult, select_attached}, row::Row, predicate::{and, one_predicate}, subquery::SubqueryErr, filter::{fulltext_cond, bloom_conds, part_mask}};
+
+// the rows of a select, produced one by one
+// a single table select without `order by`, aggregation, `tablesample`, offset or full-text ranking is produced lazily as the scan advances, so it takes constant memory
+// other selects need all rows before producing the first one (to join, sort or aggregate them), they are computed by `select_attached` at first
+pub struct Cursor<'b> {
+  pub cols: Vec<Col<'b>>,
+  inner: Inner<'b>,
+  // the last row returned by `next_row`
+  cur: Vec<CLit<'b>>,
+}
+
+enum Inner<'b> {
+  Scan { db: &'b Db, tp_id: u32, it: RecordIter<'b>, pred: Box<dyn Fn(*const u8) -> bool + 'b>, limit: usize },
+  Done { data: std::vec::IntoIter<CLit<'b>> },
+}
+
+impl<'b> Cursor<'b> {
+  // like `next`, but returns a `Row` with typed accessors, which is valid until the next call
+  pub fn next_row(&mut self) -> Option<Row<'_, 'b>> {
+    self.cur = self.next()?;
+    Some(Row { cols: &self.cols, data: &self.cur })
+  }
+
+  // the next (at most) `n` rows as a page of result, the scan continues from where the last page stopped, so paging never rescans
+  // an empty page means the cursor is exhausted
+  pub fn fetch(&mut self, n: usize) -> SelectResult<'b> {
+    let data = self.by_ref().take(n).flatten().collect();
+    SelectResult { cols: self.cols.clone(), data }
+  }
+}
+
+impl<'b> Iterator for Cursor<'b> {
+  type Item = Vec<CLit<'b>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    unsafe {
+      match &mut self.inner {
+        Inner::Scan { db, tp_id, it, pred, limit } => {
+          if *limit == 0 { return None; }
+          let read = &mut (*db).pr().table_counters(*tp_id).read;
+          let data = it.find(|(data, _)| (*read += 1, pred(*data)).1)?.0;
+          *limit -= 1;
+          Some(self.cols.iter().map(|col| {
+            let (ci_id, ci) = col.ci.unchecked_unwrap();
+            db.data2lit(data, ci_id, ci)
+          }).collect())
+        }
+        Inner::Done { data } => {
+          let row = data.take(self.cols.len()).collect::<Vec<_>>();
+          if row.is_empty() { None } else { Some(row) }
+        }
+      }
+    }
+  }
+}
+
+// the cursor may keep a predicate made from `s` (see `one_predicate`), so the statement must outlive it
+pub fn cursor<'a: 'b, 'b>(s: &Select<'a>, db: &'b Db) -> Result<'a, Cursor<'b>> {
+  cursor_attached(s, db, |_| None)
+}
+
+// `attached` is the same as in `select_attached`
+pub fn cursor_attached<'a: 'b, 'b>(s: &Select<'a>, db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db>) -> Result<'a, Cursor<'b>> {
+  unsafe {
+    let has_agg = s.ops.iter().flatten().any(|agg| agg.op.is_some());
+    // a subquery may fail while the rows are produced, where the error can't be returned, so it is left to `select_attached`
+    let has_sub = s.where_.iter().any(|c| match c { Cond::Sub(..) | Cond::Quant(..) => true, _ => false });
+    if s.tables.len() == 1 && s.tables[0].sample.is_none() && s.order.is_none() && !has_agg && s.offset == 0 && !has_sub {
+      let ctx = SelectCtx::new(&s.tables, db, &attached)?;
+      let (_, &(db, tp_id, tp)) = ctx.tbls.get_index(0).unchecked_unwrap();
+      let mut preds = Vec::with_capacity(s.where_.len());
+      for cond in &s.where_ {
+        ctx.one_where(cond.lhs_col())?;
+        if let Some((r, _)) = cond.rhs_col_op() { ctx.one_where(r)?; }
+        preds.push(one_predicate(db, cond, tp, &SubqueryErr::default())?.local());
+      }
+      if fulltext_cond(db, tp, &s.where_).is_none() {
+        let cols = ctx.mk_tbls(&s.ops)?.pop().unchecked_unwrap();
+        let hashes = bloom_conds(db.pr(), tp, &s.where_).into_iter().map(|(_, h)| h).collect();
+        let it = db.pr().record_iter_bloom(tp, hashes, part_mask(db.pr(), tp, &s.where_));
+        let limit = s.limit.map(|l| l as usize).unwrap_or(!0);
+        db.pr().counters.full_scans += 1;
+        return Ok(Cursor { cols, inner: Inner::Scan { db, tp_id, it, pred: box and(preds), limit }, cur: vec![] });
+      }
+    }
+    let SelectResult { cols, data } = select_attached(s, db, attached)?;
+    Ok(Cursor { cols, inner: Inner::Done { data: data.into_iter() }, cur: vec![] })
+  }
+}
\ No newline at end of file
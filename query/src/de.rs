@@ -0,0 +1,93 @@
+use std::fmt;
+use serde::{de::{self, Deserialize, Deserializer, Visitor, MapAccess, DeserializeSeed, IntoDeserializer, value::StringDeserializer}, forward_to_deserialize_any};
+
+use common::*;
+use crate::{row::Row, select::SelectResult};
+
+// why a row can't be deserialized, e.g., a field of the struct is not a col of the result, or a value is not of the type of its field
+#[derive(Debug)]
+pub struct DeError(pub String);
+
+impl fmt::Display for DeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(&self.0) }
+}
+
+impl std::error::Error for DeError {}
+
+impl de::Error for DeError {
+  fn custom<T: fmt::Display>(msg: T) -> Self { DeError(msg.to_string()) }
+}
+
+impl<'b> Row<'_, 'b> {
+  // the row as a `T` (usually a struct deriving `Deserialize`), its fields are matched with cols by name (see `Col::name`), and other cols are ignored
+  // null is `None` of an `Option` field, an integral number can be read as an int, a date is read as a string like "2020-01-02"
+  pub fn deserialize<T: Deserialize<'b>>(&self) -> Result<T, DeError> { T::deserialize(RowDe { row: *self, idx: 0 }) }
+}
+
+impl<'b> SelectResult<'b> {
+  pub fn deserialize_rows<T: Deserialize<'b>>(&self) -> Result<Vec<T>, DeError> { self.rows().map(|r| r.deserialize()).collect() }
+}
+
+// a row is a map from col names to values
+struct RowDe<'r, 'b> {
+  row: Row<'r, 'b>,
+  idx: usize,
+}
+
+impl<'de> Deserializer<'de> for RowDe<'_, 'de> {
+  type Error = DeError;
+
+  fn deserialize_any<V: Visitor<'de>>(self, v: V) -> Result<V::Value, DeError> { v.visit_map(self) }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit unit_struct newtype_struct seq tuple
+    tuple_struct map struct enum identifier ignored_any
+  }
+}
+
+impl<'de> MapAccess<'de> for RowDe<'_, 'de> {
+  type Error = DeError;
+
+  fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, DeError> {
+    match self.row.cols.get(self.idx) {
+      Some(col) => {
+        let name: StringDeserializer<DeError> = col.name().into_deserializer();
+        seed.deserialize(name).map(Some)
+      }
+      None => Ok(None),
+    }
+  }
+
+  fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+    let lit = self.row.data[self.idx].lit();
+    self.idx += 1;
+    seed.deserialize(LitDe(lit))
+  }
+}
+
+struct LitDe<'b>(Lit<'b>);
+
+impl<'de> Deserializer<'de> for LitDe<'de> {
+  type Error = DeError;
+
+  fn deserialize_any<V: Visitor<'de>>(self, v: V) -> Result<V::Value, DeError> {
+    match self.0 {
+      Lit::Null => v.visit_unit(),
+      Lit::Bool(x) => v.visit_bool(x),
+      // all numbers are f64 in `Lit`, so an int col can only be told by its value
+      Lit::Number(x) if x as i64 as f64 == x => v.visit_i64(x as i64),
+      Lit::Number(x) => v.visit_f64(x),
+      Lit::Date(x) => v.visit_string(x.to_string()),
+      Lit::Str(x) => v.visit_borrowed_str(x),
+    }
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, v: V) -> Result<V::Value, DeError> {
+    if let Lit::Null = self.0 { v.visit_none() } else { v.visit_some(self) }
+  }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit unit_struct newtype_struct seq tuple
+    tuple_struct map struct enum identifier ignored_any
+  }
+}
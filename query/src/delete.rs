@@ -1,32 +1,59 @@
-use common::{*, BareTy::*};
+use common::{*, Error::*};
 use syntax::ast::*;
-use db::{Db, is_null};
-use index::{Index, handle_all};
-use crate::{predicate::one_where, filter::filter, check_foreign_link};
+use physics::*;
+use db::Db;
+use crate::{predicate::one_where, subquery::SubqueryErr, filter::filter, foreign::Cascade, SelectResult};
 
-pub fn delete<'a>(d: &Delete<'a>, db: &mut Db) -> ModifyResult<'a, u32> {
+// the returned count doesn't include the records deleted by `on delete cascade`
+pub fn delete<'a>(d: &Delete<'a>, db: &mut Db) -> ModifyResult<'a, u32> { delete_rows(d, db, |_| {}) }
+
+// like `delete`, but the deleted rows are output like a select of `cols`, all cols if None
+pub fn delete_returning<'a, 'b>(d: &Delete<'a>, cols: Option<&[&'a str]>, db: &'b mut Db) -> ModifyResult<'a, SelectResult<'b>> {
+  unsafe {
+    let tp = db.get_tp(d.table)?.1;
+    let cols = SelectResult::returning_cols(db, tp, cols)?;
+    let (p, mut data) = (db as *mut Db, vec![]);
+    // the values are read before the record is deleted, and strings are copied because their space may be freed or reused
+    delete_rows(d, db, |rec| data.extend(SelectResult::record(p.r(), &cols, rec, true)))?;
+    Ok(SelectResult { cols, data })
+  }
+}
+
+// `f` is called with each record to delete, before it is deleted
+fn delete_rows<'a>(d: &Delete<'a>, db: &mut Db, mut f: impl FnMut(*const u8)) -> ModifyResult<'a, u32> {
   unsafe {
     let (tp_id, tp) = db.get_tp(d.table)?;
-    let f_links = db.foreign_links_to(tp_id).collect::<Vec<_>>();
-    let pred = one_where(db.pr(), &d.where_, tp)?;
-    let mut cnt = 0;
+    let err = SubqueryErr::default();
+    let pred = one_where(db.pr(), &d.where_, tp, &err)?;
+    let (mut cnt, mut c) = (0, Cascade::default());
     if let Err(e) = filter(db.pr(), &d.where_, tp_id, pred, |data, rid| {
-      check_foreign_link(db, tp, data, &f_links)?;
+      // a record of a self-referencing table may have been deleted by `on delete cascade` of a previous one
+      if !bsget(db.get_page::<DataPage>(rid.page()).used.as_ptr(), rid.slot() as usize) { return Ok(()); }
+      f(data);
+      c.delete(db, tp_id, rid)?;
       // now no error can occur
-      for (ci_id, ci) in tp.cols().iter().enumerate() {
-        let (ci_id, ptr) = (ci_id as u32, data.add(ci.off as usize));
-        if !is_null(data, ci_id) {
-          if ci.index != !0 {
-            macro_rules! handle { ($ty: ident) => {{ Index::<{ $ty }>::new(db, tp_id, ci_id).delete(ptr, rid); }}; }
-            handle_all!(ci.ty.fix_ty().ty, handle);
-          }
-          if ci.ty.is_varchar() { db.free_varchar(ptr); }
-        }
-      }
-      db.dealloc_data_slot(tp, rid);
+      c.apply(db);
       cnt += 1;
-      tp.count -= 1;
       Ok(())
-    }, false) { Err(ModifyError(cnt, e)) } else { Ok(cnt) }
+    }, false).and_then(|_| err.check()) { Err(ModifyError(cnt, e)) } else { Ok(cnt) }
+  }
+}
+
+// delete all records in partition `part` of a partitioned table (foreign keys are checked, and their actions are taken, like `delete`),
+// then free the data pages of the partition; the partition is still there, and later records of it are put into new pages
+pub fn drop_partition<'a>(table: &'a str, part: u32, db: &mut Db) -> ModifyResult<'a, u32> {
+  unsafe {
+    let (tp_id, tp) = db.get_tp(table)?;
+    if db.part_col(tp).is_none() || part >= db.part_count(tp) as u32 { return Err(NoSuchPartition { table, part }.into()); }
+    let rids = db.record_iter_bloom(tp, vec![], 1 << part).map(|(_, rid)| rid).collect::<Vec<_>>();
+    let (mut cnt, mut c) = (0, Cascade::default());
+    for rid in rids {
+      if !bsget(db.get_page::<DataPage>(rid.page()).used.as_ptr(), rid.slot() as usize) { continue; }
+      if let Err(e) = c.delete(db, tp_id, rid) { return Err(ModifyError(cnt, e)); }
+      c.apply(db);
+      cnt += 1;
+    }
+    db.drop_partition_pages(tp, part as u16);
+    Ok(cnt)
   }
 }
\ No newline at end of file
@@ -0,0 +1,111 @@
+use unchecked_unwrap::UncheckedUnwrap;
+use std::{borrow::Borrow, cmp::Ordering};
+use chrono::Datelike;
+
+use common::{*, BareTy::*, CmpOp::*};
+use syntax::ast::*;
+use physics::*;
+use db::Db;
+use crate::filter::IndexRange;
+
+// the selectivity of a condition that can't be estimated from statistics, e.g., `like`
+const DEFAULT_SEL: f64 = 1.0 / 3.0;
+
+// the estimated number of records in `tp` satisfying all of `where_`, None if `tp` is not analyzed
+// conditions are assumed to be independent, and values of a col are assumed to be uniformly distributed in each bucket of its histogram,
+// or in [min, max] if it has no histogram
+pub(crate) unsafe fn estimate_rows<'a>(db: &Db, tp: &TablePage, where_: &[impl Borrow<Cond<'a>>]) -> Option<f64> {
+  let sp = db.stats(tp)?;
+  Some(where_.iter().fold(sp.count as f64, |rows, cond| rows * selectivity(db, tp, sp, cond.borrow())))
+}
+
+// like `estimate_rows`, but if `tp` is not analyzed, every condition is assumed to have DEFAULT_SEL
+pub(crate) unsafe fn filtered_rows<'a>(db: &Db, tp: &TablePage, where_: &[impl Borrow<Cond<'a>>]) -> f64 {
+  estimate_rows(db, tp, where_).unwrap_or_else(|| tp.count as f64 * DEFAULT_SEL.powi(where_.len() as i32))
+}
+
+// the estimated fraction of records in `tp` satisfying `cond`, all cols in `cond` belong to `tp`
+pub(crate) unsafe fn selectivity(db: &Db, tp: &TablePage, sp: &StatsPage, cond: &Cond) -> f64 {
+  if sp.count == 0 { return 0.0; }
+  // safe because `one_predicate` have verified the name
+  let ci = tp.pr().get_ci(cond.lhs_col().col, db.ignore_case).unchecked_unwrap();
+  let cs = sp.cols.get_unchecked(ci.idx(&tp.cols) as usize);
+  let not_null = 1.0 - cs.nulls as f64 / sp.count as f64;
+  let eq = if cs.distinct == 0 { 0.0 } else { not_null / cs.distinct as f64 };
+  match *cond {
+    Cond::Null(_, null) => if null { 1.0 - not_null } else { not_null },
+    Cond::Cmp(_, _, Atom::Lit(r)) if r.is_null() => 0.0,
+    Cond::Cmp(Eq, _, Atom::Lit(_)) => eq,
+    Cond::Cmp(Ne, _, Atom::Lit(_)) => not_null - eq,
+    Cond::Cmp(op, _, Atom::Lit(r)) => match lt_fraction(db, ci, cs, r) {
+      Some(lt) => {
+        let sel = match op { Lt => not_null * lt, Le => not_null * lt + eq, Gt => not_null * (1.0 - lt) - eq, _ => not_null * (1.0 - lt) };
+        sel.max(0.0).min(not_null)
+      }
+      None => not_null * DEFAULT_SEL,
+    }
+    _ => DEFAULT_SEL,
+  }
+}
+
+// the fraction of not-null values of `ci` that are < `val`, None if it can't be estimated (e.g., `ci` is not a number / date col)
+unsafe fn lt_fraction(db: &Db, ci: &ColInfo, cs: &ColStats, val: CLit) -> Option<f64> {
+  let v = num(ci.ty, val.lit())?;
+  if let Some(hp) = db.hist(cs) { return Some(hist_lt_fraction(hp.bounds(), v)); }
+  let (min, max) = match db.stats_min_max(ci, cs) { Some((min, max)) => (num(ci.ty, min.lit())?, num(ci.ty, max.lit())?), None => return Some(0.0) };
+  Some(if max > min { ((v - min) / (max - min)).max(0.0).min(1.0) } else if v > min { 1.0 } else { 0.0 })
+}
+
+// every bucket holds the same number of values, so the fraction is the number of buckets < `v`, counting a partial bucket by linear interpolation
+fn hist_lt_fraction(bounds: &[f64], v: f64) -> f64 {
+  let buckets = bounds.len() - 1;
+  let lt = bounds.windows(2).map(|b| if b[1] < v { 1.0 } else if b[0] < v { (v - b[0]) / (b[1] - b[0]) } else { 0.0 }).sum::<f64>();
+  lt / buckets as f64
+}
+
+// the estimated number of records in `range`, None if `tp` is not analyzed
+pub(crate) unsafe fn range_rows(db: &Db, tp: &TablePage, range: &IndexRange) -> Option<f64> {
+  let sp = db.stats(tp)?;
+  if sp.count == 0 { return Some(0.0); }
+  let ci = tp.cols.get_unchecked(range.ci_id as usize);
+  let cs = sp.cols.get_unchecked(range.ci_id as usize);
+  let not_null = 1.0 - cs.nulls as f64 / sp.count as f64;
+  let eq = if cs.distinct == 0 { 0.0 } else { not_null / cs.distinct as f64 };
+  let sel = match (&range.lo, &range.hi) {
+    (Some(lo), Some(hi)) if lo.inclusive && hi.inclusive && lo.val.cmp(hi.val) == Ordering::Equal => eq,
+    (lo, hi) => {
+      let lo = match lo { Some(lo) => lt_fraction(db, ci, cs, lo.val), None => Some(0.0) };
+      let hi = match hi { Some(hi) => lt_fraction(db, ci, cs, hi.val), None => Some(1.0) };
+      match (lo, hi) {
+        (Some(lo), Some(hi)) => (not_null * (hi - lo) + eq).max(0.0).min(not_null),
+        _ => not_null * DEFAULT_SEL,
+      }
+    }
+  };
+  Some(sel * tp.count as f64)
+}
+
+// costs are measured in pages: a full scan reads all data pages sequentially, and checks all records
+// an index scan reads a few index pages, and then reads one data page for each record in range, which is a random read
+const RANDOM_COST: f64 = 4.0;
+const DESCENT_COST: f64 = 2.0;
+const RECORD_COST: f64 = 0.01;
+
+pub(crate) fn full_scan_cost(tp: &TablePage) -> f64 {
+  (tp.count as f64 / tp.cap as f64).ceil() + tp.count as f64 * RECORD_COST
+}
+
+// None if `tp` is not analyzed
+pub(crate) unsafe fn index_scan_cost(db: &Db, tp: &TablePage, range: &IndexRange) -> Option<f64> {
+  Some(DESCENT_COST + range_rows(db, tp, range)? * (RANDOM_COST + RECORD_COST))
+}
+
+// the position of a not-null value of a col of type `ty` on the number axis, None if it is not a number / date
+fn num(ty: ColTy, lit: Lit) -> Option<f64> {
+  match (ty, lit) {
+    (_, Lit::Number(x)) => Some(x),
+    (_, Lit::Date(d)) => Some(d.num_days_from_ce() as f64),
+    (date!(), Lit::Str(s)) => db::date(s).ok().map(|d| d.num_days_from_ce() as f64),
+    _ => None,
+  }
+}
\ No newline at end of file
@@ -0,0 +1,313 @@
+use unchecked_unwrap::UncheckedUnwrap;
+use std::fmt::Write;
+
+use common::*;
+use syntax::ast::*;
+use db::Db;
+use crate::{select::{SelectCtx, sortable, select_attached}, predicate::{and, one_predicate, cross_predicate, Pred}, subquery::SubqueryErr, filter::{choose_range, fulltext_cond, spatial_cond, bloom_conds, part_mask}, estimate::estimate_rows};
+
+// how a table is accessed by a select, see `explain_plan`
+#[derive(Debug, Default)]
+pub struct Access {
+  // the name in the select (an alias of attached database is included)
+  pub table: String,
+  // "full scan", "row count" (count(*) answered by the record count of the table), "index only scan", "index scan", "full-text index scan" or "spatial index scan"
+  pub method: &'static str,
+  // the indexed col (or `func(col)`, or `(x, y)` for a spatial index) of an index scan
+  pub index: Option<String>,
+  // the range of an index scan, like "[1, +inf)", or "[x0, x1] * [y0, y1]" for a spatial index
+  pub range: Option<String>,
+  pub backward: bool,
+  // the index is scanned as a whole for merge join
+  pub merge_join: bool,
+  // only this percentage of data pages are scanned, by `tablesample`
+  pub sample: Option<f32>,
+  // the cols whose Bloom filters are tested on each data page of a full scan
+  pub bloom: Vec<String>,
+  // (the partitions scanned, the number of partitions) of a full scan of a partitioned table, see `part_mask`
+  pub partitions: Option<(Vec<u16>, u16)>,
+  // the conditions on this table (excluding the ones comparing with other tables)
+  pub conds: Vec<String>,
+  // whether the scanned records still need to be filtered by `conds`
+  pub filter: bool,
+  pub estimated_rows: Option<f64>,
+  // the number of records satisfying `conds`, only counted by `explain_analyze`
+  pub actual_rows: Option<u64>,
+  // all access paths considered by cost, (indexed col, cost), None for full scan; the chosen one is the cheapest
+  pub costs: Vec<(Option<String>, f64)>,
+}
+
+// the plan of a select, see `explain_plan`
+#[derive(Debug, Default)]
+pub struct Plan {
+  // in the order they are joined (see `join_order`)
+  pub tables: Vec<Access>,
+  // the cols of the two tables, like ("t.a", "u.b")
+  pub merge_join: Option<(String, String)>,
+  // the full-text indexed col by which rows are ranked
+  pub rank: Option<String>,
+  // (col, desc, limit), if rows are sorted after they are fetched; the limit includes the offset, as these rows are all needed
+  pub sort: Option<(String, bool, Option<u32>)>,
+  // the number of rows in the result, only counted by `explain_analyze`
+  pub actual_rows: Option<usize>,
+}
+
+pub fn explain<'a>(s: &Select<'a>, db: &Db) -> Result<'a, String> {
+  explain_attached(s, db, |_| None)
+}
+
+// the text form of `explain_plan`, one line for a table, in the order they are joined
+pub fn explain_attached<'a, 'b>(s: &Select<'a>, db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db>) -> Result<'a, String> {
+  explain_plan(s, db, attached).map(|p| p.text())
+}
+
+// describe how `select_attached` will access each table
+// the select is checked in the same way as `select_attached`, so an invalid select is also an error here
+pub fn explain_plan<'a, 'b>(s: &Select<'a>, db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db>) -> Result<'a, Plan> {
+  plan(s, db, attached, false)
+}
+
+// like `explain_plan`, but the select is also run, and the actual row counts are filled
+// the actual rows of a table are counted by scanning the whole table, so a sampled table has all its records counted
+pub fn explain_analyze<'a, 'b>(s: &Select<'a>, db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db> + Copy) -> Result<'a, Plan> {
+  let mut plan = plan(s, db, attached, true)?;
+  plan.actual_rows = Some(select_attached(s, db, attached)?.row_count());
+  Ok(plan)
+}
+
+fn plan<'a, 'b>(s: &Select<'a>, db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db>, actual: bool) -> Result<'a, Plan> {
+  unsafe {
+    let tbl_num = s.tables.len();
+    if tbl_num == 0 { return Ok(Plan::default()); }
+    let ctx = SelectCtx::new(&s.tables, db, attached)?;
+    let mut one_wheres = vec![vec![]; tbl_num];
+    // the first `sortable` predicate on (x, y) with x > y, like `cross_cols` in `select_attached`
+    let mut cross_cols = vec![None; tbl_num * tbl_num];
+    let implied = ctx.implied(&s.where_)?;
+    for cond in s.where_.iter().chain(&implied) {
+      let (tp_l, ci_l, idx_l) = ctx.one_where(cond.lhs_col())?;
+      let r = match cond.rhs_col_op() { Some((r, op)) => Some((ctx.one_where(r)?, op)), None => None };
+      match r {
+        Some(((tp_r, ci_r, idx_r), op)) if idx_r != idx_l => {
+          cross_predicate((ctx.db(idx_l), ctx.db(idx_r)), op, (ci_l, ci_r), (tp_l, tp_r))?;
+          if sortable(op, ci_l, ci_r) {
+            let cross = if idx_l > idx_r { (op, ci_l, ci_r) } else { (op.rev(), ci_r, ci_l) };
+            cross_cols.get_unchecked_mut(idx_l.max(idx_r) * tbl_num + idx_l.min(idx_r)).get_or_insert(cross);
+          }
+        }
+        _ => {
+          one_predicate(ctx.db(idx_l), cond, tp_l, &SubqueryErr::default())?;
+          one_wheres.get_unchecked_mut(idx_l).push(cond);
+        }
+      }
+    }
+    let cols = ctx.mk_tbls(&s.ops)?;
+    let has_agg = s.ops.iter().flatten().any(|agg| agg.op.is_some());
+    let order = ctx.order(&s.order)?.filter(|_| !has_agg);
+    let (order_ci, desc) = (order.map(|(_, ci_id, _, _)| ci_id), order.map(|(.., desc)| desc).unwrap_or(false));
+    let mut sorted = order.is_none();
+    let merge = ctx.merge_join(if tbl_num == 2 { *cross_cols.get_unchecked(tbl_num) } else { None });
+    let mut plan = Plan::default();
+    for idx in ctx.join_order(&one_wheres, |x, y| cross_cols.get_unchecked(x.max(y) * tbl_num + x.min(y)).is_some()) {
+      let &(db, _, tp) = ctx.tbls.get_index(idx).unchecked_unwrap().1;
+      let where_ = one_wheres.get_unchecked(idx);
+      let col_name = |ci_id: u32| {
+        let ci = tp.cols.get_unchecked(ci_id as usize);
+        match ci.idx_fn() { Some(f) => format!("{}({})", f.name(), ci.name()), None => ci.name().to_owned() }
+      };
+      let mut a = Access { table: ctx.name(idx), conds: where_.iter().map(|c| format!("{:?}", c)).collect(), filter: !where_.is_empty(), ..Access::default() };
+      if let Some(p) = ctx.sample(idx) {
+        (a.method = "full scan", a.sample = Some(p));
+      } else if tbl_num == 1 && ctx.count_only(cols.get_unchecked(0), where_).is_some() {
+        (a.method = "row count", sorted = true);
+      } else if let Some(range) = if tbl_num == 1 { ctx.index_only(cols.get_unchecked(0), where_, order_ci) } else { None } {
+        (a.method = "index only scan", a.backward = desc && !has_agg, a.index = Some(col_name(range.ci_id)), a.range = Some(format!("{:?}", range)));
+        // the keys outside the range are never visited
+        a.filter = false;
+        sorted = true;
+      } else if let Some(range) = order_ci.and_then(|ci_id| ctx.index_order(ci_id, where_)) {
+        (a.method = "index scan", a.backward = desc, a.index = Some(col_name(range.ci_id)), a.range = Some(format!("{:?}", range)), a.filter = !range.exact);
+        sorted = true;
+      } else if let Some(merge) = merge {
+        let ci_id = if idx == 0 { merge.0 } else { merge.1 };
+        (a.method = "index scan", a.index = Some(col_name(ci_id)), a.merge_join = true);
+        if order.map(|(idx1, ci_id1, ..)| (idx1, ci_id1)) == Some((idx, ci_id)) { sorted = true; }
+      } else if let Some((ci_id, _)) = fulltext_cond(db, tp, where_) {
+        (a.method = "full-text index scan", a.index = Some(col_name(ci_id)), a.filter = true);
+      } else if let Some((ci_id, rect)) = spatial_cond(tp, where_) {
+        let ci = tp.cols.get_unchecked(ci_id as usize);
+        (a.method = "spatial index scan", a.index = Some(format!("({}, {})", ci.name(), tp.cols.get_unchecked(ci.idx_spatial as usize - 1).name())));
+        (a.range = Some(format!("[{}, {}] * [{}, {}]", rect.min_x, rect.max_x, rect.min_y, rect.max_y)), a.filter = true);
+      } else {
+        let (range, costs) = choose_range(db.pr(), where_, tp);
+        if let Some(range) = range {
+          (a.method = "index scan", a.index = Some(col_name(range.ci_id)), a.range = Some(format!("{:?}", range)), a.filter = !range.exact);
+        } else {
+          a.method = "full scan";
+          a.bloom = bloom_conds(db.pr(), tp, where_).iter().map(|&(ci_id, _)| tp.cols.get_unchecked(ci_id as usize).name().to_owned()).collect();
+          if db.part_col(tp).is_some() {
+            let (parts, count) = (part_mask(db.pr(), tp, where_), db.part_count(tp));
+            a.partitions = Some(((0..count).filter(|&i| parts >> i & 1 == 1).collect(), count));
+          }
+        }
+        a.costs = costs.into_iter().map(|(ci_id, cost)| (ci_id.map(|ci_id| col_name(ci_id)), cost)).collect();
+      }
+      a.estimated_rows = estimate_rows(db, tp, where_).map(|rows| rows * ctx.sample(idx).map(|p| p as f64 / 100.0).unwrap_or(1.0));
+      if actual { a.actual_rows = Some(count_rows(db, tp, where_)?); }
+      plan.tables.push(a);
+    }
+    if let Some((ci_id0, ci_id1)) = merge {
+      let (tp0, tp1) = (ctx.tbls.get_index(0).unchecked_unwrap().1 .2, ctx.tbls.get_index(1).unchecked_unwrap().1 .2);
+      plan.merge_join = Some((format!("{}.{}", ctx.name(0), tp0.cols.get_unchecked(ci_id0 as usize).name()), format!("{}.{}", ctx.name(1), tp1.cols.get_unchecked(ci_id1 as usize).name())));
+    }
+    let rank = ctx.tbls.values().enumerate().find_map(|(idx, &(_, _, tp))| Some((idx, fulltext_cond(db, tp, one_wheres.get_unchecked(idx))?.0, tp)));
+    if let (Some((idx, ci_id, tp)), None, false) = (rank, order, has_agg) {
+      plan.rank = Some(format!("{}.{}", ctx.name(idx), tp.cols.get_unchecked(ci_id as usize).name()));
+    }
+    if let (Some((idx, _, ci, _)), false) = (order, sorted) {
+      plan.sort = Some((format!("{}.{}", ctx.name(idx), ci.name()), desc, s.limit.map(|l| l.saturating_add(s.offset))));
+    }
+    Ok(plan)
+  }
+}
+
+// the number of records of `tp` satisfying all of `where_`
+unsafe fn count_rows<'a>(db: &Db, tp: &TablePage, where_: &[&Cond<'a>]) -> Result<'a, u64> {
+  let err = SubqueryErr::default();
+  let pred = and(where_.iter().map(|c| one_predicate(db, c, tp, &err).map(Pred::local)).collect::<Result<Vec<_>>>()?);
+  let count = db.pr().record_iter(tp).filter(|&(data, _)| pred(data)).count() as u64;
+  (err.check()?, Ok(count)).1
+}
+
+// an operator in the pipeline drawn by `Plan::tree` and `Plan::dot`, its children feed rows into it
+struct Node {
+  label: String,
+  children: Vec<Node>,
+}
+
+impl Access {
+  // like "t: index scan on a [1, +inf), then filter (estimated rows = 10)"
+  fn describe(&self) -> String {
+    let (a, mut ret) = (self, String::new());
+    write!(ret, "{}: {}", a.table, a.method).unchecked_unwrap();
+    if a.backward { ret += " backward"; }
+    if let Some(index) = &a.index { write!(ret, " on {}", index).unchecked_unwrap(); }
+    if let Some(p) = a.sample { write!(ret, " on {}% of data pages", p).unchecked_unwrap(); }
+    if let Some(range) = &a.range { write!(ret, " {}{}", if a.method == "spatial index scan" { "in " } else { "" }, range).unchecked_unwrap(); }
+    if a.merge_join { ret += " for merge join"; }
+    if let Some((parts, count)) = &a.partitions {
+      let parts = parts.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+      write!(ret, " on {} of {} partitions ({})", parts.len(), count, parts.join(", ")).unchecked_unwrap();
+    }
+    if !a.bloom.is_empty() { write!(ret, " with bloom filter on {}", a.bloom.join(", ")).unchecked_unwrap(); }
+    if a.filter { ret += ", then filter"; }
+    if let Some(rows) = a.estimated_rows { write!(ret, " (estimated rows = {:.0})", rows).unchecked_unwrap(); }
+    if let Some(rows) = a.actual_rows { write!(ret, " (actual rows = {})", rows).unchecked_unwrap(); }
+    if !a.costs.is_empty() {
+      let costs = a.costs.iter().map(|(index, cost)| match index {
+        Some(index) => format!("index scan on {} = {:.2}", index, cost),
+        None => format!("full scan = {:.2}", cost),
+      }).collect::<Vec<_>>();
+      write!(ret, " (cost: {})", costs.join(", ")).unchecked_unwrap();
+    }
+    ret
+  }
+}
+
+impl Plan {
+  pub fn text(&self) -> String {
+    let mut ret = String::new();
+    for a in &self.tables { writeln!(ret, "{}", a.describe()).unchecked_unwrap(); }
+    if let Some((l, r)) = &self.merge_join { writeln!(ret, "merge join on {} = {}", l, r).unchecked_unwrap(); }
+    if let Some(rank) = &self.rank { writeln!(ret, "rank by relevance on {}", rank).unchecked_unwrap(); }
+    if let Some((col, desc, limit)) = &self.sort {
+      let top = limit.map(|l| format!(", only keep the first {}", l)).unwrap_or_default();
+      writeln!(ret, "sort by {}{}{}", col, if *desc { " desc" } else { "" }, top).unchecked_unwrap();
+    }
+    (ret.pop(), ret).1
+  }
+
+  // like {"tables": [{"table": "t", "method": "index scan", "index": "a", "range": "[1, 1]", ...}], "merge_join": null, "rank": null, "sort": null}
+  // the fields of a table are the same as `Access`, absent ones are null
+  pub fn json(&self) -> String {
+    fn opt<T>(x: &Option<T>, f: impl Fn(&T) -> String) -> String { x.as_ref().map(f).unwrap_or_else(|| "null".to_owned()) }
+    fn arr<T>(xs: &[T], f: impl Fn(&T) -> String) -> String { format!("[{}]", xs.iter().map(f).collect::<Vec<_>>().join(", ")) }
+    let tables = arr(&self.tables, |a| {
+      let costs = arr(&a.costs, |(index, cost)| format!("{{\"index\": {}, \"cost\": {}}}", opt(index, |x| quote(x)), cost));
+      format!("{{\"table\": {}, \"method\": {}, \"index\": {}, \"range\": {}, \"backward\": {}, \"merge_join\": {}, \"sample\": {}, \"bloom\": {}, \"partitions\": {}, \"conds\": {}, \"filter\": {}, \"estimated_rows\": {}, \"actual_rows\": {}, \"costs\": {}}}",
+              quote(&a.table), quote(a.method), opt(&a.index, |x| quote(x)), opt(&a.range, |x| quote(x)), a.backward, a.merge_join, opt(&a.sample, |x| x.to_string()),
+              arr(&a.bloom, |x| quote(x)), opt(&a.partitions, |(parts, count)| format!("{{\"scanned\": {}, \"count\": {}}}", arr(parts, |x| x.to_string()), count)),
+              arr(&a.conds, |x| quote(x)), a.filter, opt(&a.estimated_rows, |x| x.to_string()), opt(&a.actual_rows, |x| x.to_string()), costs)
+    });
+    let merge_join = opt(&self.merge_join, |(l, r)| format!("{{\"left\": {}, \"right\": {}}}", quote(l), quote(r)));
+    let sort = opt(&self.sort, |(col, desc, limit)| format!("{{\"col\": {}, \"desc\": {}, \"limit\": {}}}", quote(col), desc, opt(limit, |x| x.to_string())));
+    format!("{{\"tables\": {}, \"merge_join\": {}, \"rank\": {}, \"sort\": {}}}", tables, merge_join, opt(&self.rank, |x| quote(x)), sort)
+  }
+
+  // tables are joined from left to right, each join takes the rows joined so far and the rows of the next table, see `Node`
+  fn pipeline(&self) -> Node {
+    let leaf = |a: &Access| Node { label: a.describe(), children: vec![] };
+    let mut node = self.tables.first().map(leaf);
+    for a in self.tables.iter().skip(1) {
+      let label = match &self.merge_join { Some((l, r)) => format!("merge join on {} = {}", l, r), None => "nested loop join".to_owned() };
+      node = Some(Node { label, children: node.into_iter().chain(Some(leaf(a))).collect() });
+    }
+    if let Some(rank) = &self.rank { node = Some(Node { label: format!("rank by relevance on {}", rank), children: node.into_iter().collect() }); }
+    if let Some((col, desc, limit)) = &self.sort {
+      let top = limit.map(|l| format!(", only keep the first {}", l)).unwrap_or_default();
+      node = Some(Node { label: format!("sort by {}{}{}", col, if *desc { " desc" } else { "" }, top), children: node.into_iter().collect() });
+    }
+    let label = match self.actual_rows { Some(rows) => format!("result (actual rows = {})", rows), None => "result".to_owned() };
+    Node { label, children: node.into_iter().collect() }
+  }
+
+  // like
+  // result (actual rows = 1)
+  // └── nested loop join
+  //     ├── u: full scan (estimated rows = 1) (actual rows = 1)
+  //     └── t: index scan on a (estimated rows = 2) (actual rows = 2)
+  pub fn tree(&self) -> String {
+    fn draw(node: &Node, prefix: &str, ret: &mut String) {
+      for (idx, c) in node.children.iter().enumerate() {
+        let last = idx + 1 == node.children.len();
+        writeln!(ret, "{}{}{}", prefix, if last { "└── " } else { "├── " }, c.label).unchecked_unwrap();
+        draw(c, &format!("{}{}", prefix, if last { "    " } else { "│   " }), ret);
+      }
+    }
+    let root = self.pipeline();
+    let mut ret = root.label.clone();
+    ret.push('\n');
+    draw(&root, "", &mut ret);
+    (ret.pop(), ret).1
+  }
+
+  // the pipeline in Graphviz DOT, an edge goes from the operator producing rows to the one consuming them
+  pub fn dot(&self) -> String {
+    fn draw(node: &Node, next: &mut usize, ret: &mut String) -> usize {
+      let id = (*next, *next += 1).0;
+      writeln!(ret, "  n{} [label={}];", id, quote(&node.label)).unchecked_unwrap();
+      for c in &node.children {
+        let c_id = draw(c, next, ret);
+        writeln!(ret, "  n{} -> n{};", c_id, id).unchecked_unwrap();
+      }
+      id
+    }
+    let mut ret = String::from("digraph plan {\n  node [shape=box];\n");
+    draw(&self.pipeline(), &mut 0, &mut ret);
+    (ret.push('}'), ret).1
+  }
+}
+
+// `s` as a double-quoted string of json, it is also used as a DOT string
+fn quote(s: &str) -> String {
+  let mut ret = String::from("\"");
+  for c in s.chars() {
+    match c {
+      '"' => ret += "\\\"",
+      '\\' => ret += "\\\\",
+      c if (c as u32) < 0x20 => write!(ret, "\\u{:04x}", c as u32).unchecked_unwrap(),
+      c => ret.push(c),
+    }
+  }
+  (ret.push('"'), ret).1
+}
\ No newline at end of file
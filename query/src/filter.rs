@@ -1,65 +1,335 @@
 use unchecked_unwrap::UncheckedUnwrap;
-use std::borrow::Borrow;
+use std::{borrow::Borrow, cmp::Ordering, fmt};
 
 use common::{*, BareTy::*, CmpOp::*};
 use syntax::ast::*;
 use physics::*;
 use db::Db;
-use index::{Index, handle_all};
+use index::{Index, RTree, handle_all, cmp::Cmp, tokenize, fulltext_search};
+use crate::{predicate::{within_rect, BatchPred, batch_predicates}, estimate::{full_scan_cost, index_scan_cost}};
 
-// return true for successfully filtered with index
-unsafe fn try_filter_with_index<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>], tp_id: u32,
-                                    pred: &impl Fn(*const u8) -> bool, f: &mut impl FnMut(*mut u8, Rid) -> Result<'a, ()>) -> Result<'a, bool> {
-  let tp = db.get_page::<TablePage>(tp_id);
+// one end of an index range, `val` is kept for display, `buf` holds it in the column's format
+pub(crate) struct Bound<'a> {
+  pub inclusive: bool,
+  pub val: CLit<'a>,
+  pub buf: Align4U8,
+}
+
+// the key range [lo, hi] on an indexed column, None means unbounded on that side
+pub(crate) struct IndexRange<'a> {
+  pub ci_id: u32,
+  pub lo: Option<Bound<'a>>,
+  pub hi: Option<Bound<'a>>,
+  // all conditions in `where_` are expressed by this range, so records in it don't need to be checked by `pred` again
+  pub exact: bool,
+}
+
+impl IndexRange<'_> {
+  // lo > hi, or lo == hi but not both inclusive
+  pub unsafe fn is_empty(&self, ty: BareTy) -> bool {
+    match (&self.lo, &self.hi) {
+      (Some(lo), Some(hi)) => match cmp(ty, lo.buf.ptr, hi.buf.ptr) {
+        Ordering::Less => false,
+        Ordering::Equal => !(lo.inclusive && hi.inclusive),
+        Ordering::Greater => true,
+      }
+      _ => false,
+    }
+  }
+}
+
+// like [1, 5), (-inf, 'abc']
+impl fmt::Debug for IndexRange<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.lo { Some(lo) => write!(f, "{}{:?}", if lo.inclusive { '[' } else { '(' }, lo.val)?, None => write!(f, "(-inf")? }
+    match &self.hi { Some(hi) => write!(f, ", {:?}{}", hi.val, if hi.inclusive { ']' } else { ')' }), None => write!(f, ", +inf)") }
+  }
+}
+
+pub(crate) unsafe fn cmp(ty: BareTy, l: *const u8, r: *const u8) -> Ordering {
+  macro_rules! handle {
+    ($ty: ident) => { Cmp::<{ $ty }>::cmp(l, r) };
+  }
+  handle_all!(ty, handle)
+}
+
+// the indexed column of `cond` if it is a comparison with a not-null literal (except by `<>`) that can be answered by the index,
+// for an expression index on `func(col)`, only comparisons on `func(col)` are used; a full-text / spatial index is never used here
+unsafe fn range_col<'a>(db: &mut Db, cond: &Cond<'a>, where_: &[impl Borrow<Cond<'a>>], tp: &TablePage) -> Option<u32> {
+  let (l, func) = match *cond {
+    Cond::Cmp(op, l, Atom::Lit(r)) if op != Ne && !r.is_null() => (l, None),
+    Cond::Fn(func, op, l, r) if op != Ne && !r.is_null() => (l, Some(func)),
+    // only the first item is used, see `col_range`
+    Cond::Row(op, ref cols, ref lits) if op != Ne && cols.len() == lits.len() && !lits[0].is_null() => (cols[0], None),
+    _ => return None,
+  };
+  // safe because `one_predicate` have verified the name
+  let ci = tp.pr().get_ci(l.col, db.ignore_case).unchecked_unwrap();
+  if ci.index == !0 || ci.flags.contains(ColFlags::FULLTEXT) || ci.idx_spatial != 0 || ci.idx_fn() != func || !implied(db, tp, ci, where_) { return None; }
+  Some(ci.idx(&tp.cols))
+}
+
+// choose the first indexed column that is compared with a not-null literal (except by `<>`), see `col_range`
+pub(crate) unsafe fn index_range<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>], tp: &TablePage) -> Option<IndexRange<'a>> {
+  index_ranges(db, where_, tp).into_iter().next()
+}
+
+// the index range to scan for `where_`, None for full scan
+// if `tp` is analyzed, the cheapest one among all index ranges and full scan is chosen by estimated cost, otherwise it is `index_range`
+// the estimated costs of all candidates are also returned (the col id is None for full scan), which is empty if `tp` is not analyzed
+pub(crate) unsafe fn choose_range<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>], tp: &TablePage) -> (Option<IndexRange<'a>>, Vec<(Option<u32>, f64)>) {
+  let ranges = index_ranges(db, where_, tp);
+  if db.stats(tp).is_none() { return (ranges.into_iter().next(), vec![]); }
+  let mut costs = vec![(None, full_scan_cost(tp))];
+  for r in &ranges { costs.push((Some(r.ci_id), index_scan_cost(db, tp, r).unchecked_unwrap())); }
+  let best = (0..costs.len()).min_by(|&l, &r| fcmp(costs.get_unchecked(l).1, costs.get_unchecked(r).1)).unchecked_unwrap();
+  (if best == 0 { None } else { ranges.into_iter().nth(best - 1) }, costs)
+}
+
+// the ranges of all indexed columns that are compared with a not-null literal (except by `<>`), in the order of their first comparison
+unsafe fn index_ranges<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>], tp: &TablePage) -> Vec<IndexRange<'a>> {
+  let mut cols = vec![];
+  for cond in where_ {
+    if let Some(ci_id) = range_col(db, cond.borrow(), where_, tp) {
+      if !cols.contains(&ci_id) { cols.push(ci_id); }
+    }
+  }
+  cols.into_iter().filter_map(|ci_id| col_range(db, where_, tp, ci_id)).collect()
+}
+
+// merge all comparisons on the `ci_id`th column (which can be answered by its index) into the tightest range
+unsafe fn col_range<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>], tp: &TablePage, ci_id: u32) -> Option<IndexRange<'a>> {
+  let mut range: Option<IndexRange> = None;
+  let mut exact = true;
   for cond in where_ {
-    if let &Cond::Cmp(op, l, Atom::Lit(r)) = cond.borrow() {
-      match r.lit() {
-        Lit::Null => {}
-        _ => {
-          // safe because `one_predicate` have verified the name
-          let ci = tp.pr().get_ci(l.col).unchecked_unwrap();
-          let ci_id = ci.idx(&tp.cols);
-          if ci.index != !0 {
-            let buf = Align4U8::new(ci.ty.size() as usize);
-            let is_only_pred = where_.len() == 1;
-            // safe because `one_predicate` have done type check
-            db.lit2ptr(buf.ptr, ci.ty.fix_ty(), r).unchecked_unwrap();
-            macro_rules! handle {
-              ($ty: ident) => {{
-                let mut index = Index::<{ $ty }>::new(db, tp_id, ci_id);
-                match op {
-                  Lt | Le | Eq => {
-                    let (mut it, end) = match op {
-                      Lt => (index.iter(), index.lower_bound(buf.ptr)),
-                      Le => (index.iter(), index.upper_bound(buf.ptr)),
-                      Eq => (index.lower_bound(buf.ptr), index.upper_bound(buf.ptr)),
-                      _ => impossible!(),
-                    };
-                    while it != end {
-                      let rid = it.next().unchecked_unwrap();
-                      let ptr = db.get_data_slot(tp, rid);
-                      if is_only_pred || pred(ptr) { f(ptr, rid)?; }
-                    }
-                  },
-                  Ge | Gt => {
-                    let mut it = if op == Ge { index.lower_bound(buf.ptr) } else { index.upper_bound(buf.ptr) };
-                    while let Some(rid) = it.next() {
-                      let ptr = db.get_data_slot(tp, rid);
-                      if is_only_pred || pred(ptr) { f(ptr, rid)?; }
-                    }
-                  },
-                  Ne => continue, // can't optimize with index
-                }
-              }};
-            }
-            handle_all!(ci.ty.fix_ty().ty, handle);
-            return Ok(true);
+    let (op, r) = match *cond.borrow() {
+      Cond::Cmp(op, _, Atom::Lit(r)) | Cond::Fn(_, op, _, r) if range_col(db, cond.borrow(), where_, tp) == Some(ci_id) => (op, r),
+      // `(a, b) > (1, 2)` implies `a >= 1`, so the range is not exact if there are more items
+      Cond::Row(op, _, ref lits) if range_col(db, cond.borrow(), where_, tp) == Some(ci_id) => {
+        if lits.len() == 1 { (op, lits[0]) } else {
+          exact = false;
+          (match op { Lt => Le, Gt => Ge, op => op }, lits[0])
+        }
+      }
+      _ => {
+        exact = false;
+        continue;
+      }
+    };
+    let ci = tp.cols.get_unchecked(ci_id as usize);
+    let buf = Align4U8::new(ci.ty.size() as usize);
+    // `one_predicate` have done type check, but it may still fail (e.g., a too long string for char), then just don't use it
+    if db.lit2ptr(buf.ptr, ci.ty.fix_ty(), r).is_err() {
+      exact = false;
+      continue;
+    }
+    let range = range.get_or_insert_with(|| IndexRange { ci_id, lo: None, hi: None, exact: true });
+    let ty = ci.ty.fix_ty().ty;
+    // replace `old` if `new` is tighter, `ord` is the ordering of a tighter bound's value compared with the old one
+    let tighten = |old: &mut Option<Bound<'a>>, new: Bound<'a>, ord: Ordering| {
+      let replace = match old {
+        Some(old) => match cmp(ty, new.buf.ptr, old.buf.ptr) {
+          Ordering::Equal => !new.inclusive,
+          o => o == ord,
+        }
+        None => true,
+      };
+      if replace { *old = Some(new); }
+    };
+    let bound = |inclusive, buf| Bound { inclusive, val: r, buf };
+    match op {
+      Lt | Le => tighten(&mut range.hi, bound(op == Le, buf), Ordering::Less),
+      Gt | Ge => tighten(&mut range.lo, bound(op == Ge, buf), Ordering::Greater),
+      Eq => {
+        let buf1 = Align4U8::new(buf.size);
+        buf1.ptr.copy_from_nonoverlapping(buf.ptr, buf.size);
+        tighten(&mut range.lo, bound(true, buf), Ordering::Greater);
+        tighten(&mut range.hi, bound(true, buf1), Ordering::Less);
+      }
+      Ne => impossible!(),
+    }
+  }
+  range.map(|mut r| {
+    let prefix = tp.cols.get_unchecked(r.ci_id as usize).idx_prefix;
+    if prefix != 0 {
+      // keys in a prefix index are truncated, so bounds are truncated in the same way and become inclusive,
+      // and records in the range still need to be checked by `pred`
+      for b in r.lo.iter_mut().chain(r.hi.iter_mut()) {
+        (*b.buf.ptr = (*b.buf.ptr).min(prefix), b.inclusive = true);
+      }
+      exact = false;
+    }
+    IndexRange { exact, ..r }
+  })
+}
+
+// whether `where_` implies the predicate of the partial index on `ci` (always true if it is not a partial index)
+// only simple cases are recognized: the predicate itself is in `where_`, or the predicate is `col is not null`,
+// and `col` is compared with a not-null literal in `where_`
+unsafe fn implied<'a>(db: &mut Db, tp: &TablePage, ci: &ColInfo, where_: &[impl Borrow<Cond<'a>>]) -> bool {
+  let (&mut PartialPred { ci_id, op, .. }, val) = match db.partial_pred(ci) { Some(p) => p, None => return true };
+  let (p_ci, ignore_case) = (tp.cols.get_unchecked(ci_id as usize), db.ignore_case);
+  where_.iter().any(|cond| match *cond.borrow() {
+    Cond::Null(l, null) if name_eq(l.col, p_ci.name(), ignore_case) => op == if null { PARTIAL_NULL } else { PARTIAL_NOTNULL },
+    Cond::Cmp(op1, l, Atom::Lit(r)) if name_eq(l.col, p_ci.name(), ignore_case) && !r.is_null() => match op {
+      PARTIAL_NULL => false,
+      PARTIAL_NOTNULL => true,
+      _ => op1 as u8 == op && {
+        let buf = Align4U8::new(p_ci.ty.size() as usize);
+        db.lit2ptr(buf.ptr, p_ci.ty.fix_ty(), r).is_ok() && cmp(p_ci.ty.fix_ty().ty, buf.ptr, val) == Ordering::Equal
+      }
+    }
+    Cond::Like(l, _) | Cond::Fn(_, _, l, _) | Cond::Match(l, _) | Cond::Within(l, _, _) | Cond::Bit(_, l, ..) if name_eq(l.col, p_ci.name(), ignore_case) => op == PARTIAL_NOTNULL,
+    // `(a, b) <> (1, 2)` holds for `a = null, b = 3`
+    Cond::Row(op1, ref cols, _) if op1 != Ne && name_eq(cols[0].col, p_ci.name(), ignore_case) => op == PARTIAL_NOTNULL,
+    Cond::RowIn(ref cols, _) if name_eq(cols[0].col, p_ci.name(), ignore_case) => op == PARTIAL_NOTNULL,
+    _ => false,
+  })
+}
+
+// visit all keys in `range` in ascending order (or descending if `rev`), until `f` returns false
+// `f` receives the pointer to the key in IndexPage and the rid of the record
+// the empty range should be checked by caller (`IndexRange::is_empty`)
+pub(crate) unsafe fn index_scan<'a>(db: &mut Db, tp_id: u32, range: &IndexRange, rev: bool, mut f: impl FnMut(*const u8, Rid) -> Result<'a, bool>) -> Result<'a, ()> {
+  let tp = db.get_page::<TablePage>(tp_id);
+  db.counters.index_scans += 1;
+  let mut read = 0;
+  let mut f = |key: *const u8, rid: Rid| (read += 1, f(key, rid)).1;
+  let ty = tp.cols.get_unchecked(range.ci_id as usize).ty.fix_ty().ty;
+  macro_rules! handle {
+    ($ty: ident) => {{
+      let mut index = Index::<{ $ty }>::new(db, tp_id, range.ci_id);
+      if rev {
+        let mut it = match &range.hi {
+          Some(hi) if hi.inclusive => index.upper_bound(hi.buf.ptr),
+          Some(hi) => index.lower_bound(hi.buf.ptr),
+          None => index.end(),
+        };
+        // positions can't be compared like below, because the same position may be represented by (page, count) and (next page, 0)
+        while let Some((key, rid)) = index.prev_key(&mut it) {
+          if let Some(lo) = &range.lo {
+            match cmp(ty, key, lo.buf.ptr) { Ordering::Less => break, Ordering::Equal if !lo.inclusive => break, _ => {} }
           }
+          if !f(key, rid)? { break; }
+        }
+      } else {
+        let mut it = match &range.lo {
+          Some(lo) if lo.inclusive => index.lower_bound(lo.buf.ptr),
+          Some(lo) => index.upper_bound(lo.buf.ptr),
+          None => index.iter(),
+        };
+        // the range is not empty, so `end` is not before `it`
+        let end = match &range.hi {
+          Some(hi) if hi.inclusive => Some(index.upper_bound(hi.buf.ptr)),
+          Some(hi) => Some(index.lower_bound(hi.buf.ptr)),
+          None => None,
+        };
+        loop {
+          if end.as_ref().map(|end| &it == end).unwrap_or(false) { break; }
+          let (key, rid) = match it.next_key() { Some(x) => x, None => break };
+          if !f(key, rid)? { break; }
         }
       }
+    }};
+  }
+  handle_all!(ty, handle);
+  db.table_counters(tp_id).read += read;
+  Ok(())
+}
+
+// the first `match(col) against (query)` in `where_`, return (col id, query)
+pub(crate) unsafe fn fulltext_cond<'a>(db: &Db, tp: &TablePage, where_: &[impl Borrow<Cond<'a>>]) -> Option<(u32, &'a str)> {
+  where_.iter().find_map(|cond| match *cond.borrow() {
+    // safe because `one_predicate` have verified the name
+    Cond::Match(l, query) => Some((tp.pr().get_ci(l.col, db.ignore_case).unchecked_unwrap().idx(&tp.cols), query)),
+    _ => None,
+  })
+}
+
+// rids of records containing any word in `query`, in ascending order without duplication
+unsafe fn fulltext_rids(db: &mut Db, tp_id: u32, ci_id: u32, query: &str) -> Vec<Rid> {
+  let mut rids = vec![];
+  for w in tokenize(query) { rids.extend(fulltext_search(db, tp_id, ci_id, &w)); }
+  (rids.sort_unstable(), rids.dedup());
+  rids
+}
+
+// the first `within(x, y, ...)` in `where_` that has a spatial index on (x, y), return (x's col id, the box)
+pub(crate) unsafe fn spatial_cond<'a>(db: &Db, tp: &TablePage, where_: &[impl Borrow<Cond<'a>>]) -> Option<(u32, Rect)> {
+  where_.iter().find_map(|cond| match *cond.borrow() {
+    Cond::Within(x, y, lits) => {
+      // safe because `one_predicate` have verified the names and the box
+      let (x, y) = (tp.pr().get_ci(x.col, db.ignore_case).unchecked_unwrap(), tp.pr().get_ci(y.col, db.ignore_case).unchecked_unwrap());
+      if x.idx_spatial as u32 != y.idx(&tp.cols) + 1 { return None; }
+      Some((x.idx(&tp.cols), within_rect(x.ty, &lits).unchecked_unwrap()))
+    }
+    _ => None,
+  })
+}
+
+// (col id, hash) of every `col = lit` in `where_`, a page whose Bloom filter lacks any of the hashes can be skipped in full scan
+// empty if the data pages of `tp` have no filter (see `bloom_bits`)
+pub(crate) unsafe fn bloom_conds<'a>(db: &mut Db, tp: &TablePage, where_: &[impl Borrow<Cond<'a>>]) -> Vec<(u32, u64)> {
+  if tp.bloom_bits() == 0 { return vec![]; }
+  where_.iter().filter_map(|cond| match *cond.borrow() {
+    Cond::Cmp(Eq, l, Atom::Lit(r)) if !r.is_null() => {
+      // safe because `one_predicate` have verified the name
+      let ci = tp.pr().get_ci(l.col, db.ignore_case).unchecked_unwrap();
+      let ci_id = ci.idx(&tp.cols);
+      Some((ci_id, db.bloom_hash_lit(ci_id, ci.ty, r)?))
     }
+    _ => None,
+  }).collect()
+}
+
+// the bitset of partitions that may contain records satisfying `where_` (see `Db::part_mask`), all ones if `tp` is not partitioned
+pub(crate) unsafe fn part_mask<'a>(db: &mut Db, tp: &TablePage, where_: &[impl Borrow<Cond<'a>>]) -> u64 {
+  let ci_id = match db.part_col(tp) { Some(ci_id) => ci_id, None => return !0 };
+  // safe because `one_predicate` have verified the name
+  let ignore_case = db.ignore_case;
+  let is_part = |col: ColRef| tp.pr().get_ci(col.col, ignore_case).unchecked_unwrap().idx(&tp.cols) == ci_id;
+  where_.iter().fold(!0, |mask, cond| mask & match *cond.borrow() {
+    Cond::Cmp(op, l, Atom::Lit(r)) if is_part(l) => db.part_mask(tp, op, r),
+    // null values are in partition 0
+    Cond::Null(l, true) if is_part(l) => 1,
+    _ => !0,
+  })
+}
+
+// return true for successfully filtered with index
+// a `match` / `within` is answered by the full-text / spatial index first, because it is usually more selective than a range
+pub(crate) unsafe fn try_filter_with_index<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>], tp_id: u32,
+                                               pred: &impl Fn(*const u8) -> bool, f: &mut impl FnMut(*mut u8, Rid) -> Result<'a, ()>) -> Result<'a, bool> {
+  let tp = db.read_page::<TablePage>(tp_id);
+  if let Some((ci_id, query)) = fulltext_cond(db, tp, where_) {
+    let rids = fulltext_rids(db, tp_id, ci_id, query);
+    (db.counters.index_scans += 1, db.table_counters(tp_id).read += rids.len() as u64);
+    for rid in rids {
+      let ptr = db.read_data_slot(tp, rid) as *mut u8;
+      if pred(ptr) { f(ptr, rid)?; }
+    }
+    return Ok(true);
+  }
+  if let Some((ci_id, rect)) = spatial_cond(db, tp, where_) {
+    let mut rids = vec![];
+    RTree::new(db, tp_id, ci_id).search(&rect, &mut |rid| rids.push(rid));
+    rids.sort_unstable(); // visit data pages in order
+    (db.counters.index_scans += 1, db.table_counters(tp_id).read += rids.len() as u64);
+    for rid in rids {
+      let ptr = db.read_data_slot(tp, rid) as *mut u8;
+      if pred(ptr) { f(ptr, rid)?; }
+    }
+    return Ok(true);
   }
-  Ok(false)
+  let range = match choose_range(db, where_, tp).0 { Some(range) => range, None => return Ok(false) };
+  if range.is_empty(tp.cols.get_unchecked(range.ci_id as usize).ty.fix_ty().ty) { return Ok(true); }
+  let db1 = (db as *mut Db).r();
+  index_scan(db, tp_id, &range, false, |_, rid| {
+    let ptr = db1.read_data_slot(tp, rid) as *mut u8;
+    if range.exact || pred(ptr) { f(ptr, rid)?; }
+    Ok(true)
+  })?;
+  Ok(true)
 }
 
 // guarantee the `*mut u8` passed to f only comes from DataPage, not from IndexPage
@@ -68,9 +338,56 @@ unsafe fn try_filter_with_index<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>
 pub(crate) unsafe fn filter<'a>(db: &mut Db, where_: &[impl Borrow<Cond<'a>>], tp_id: u32,
                                 pred: impl Fn(*const u8) -> bool, mut f: impl FnMut(*mut u8, Rid) -> Result<'a, ()>,
                                 use_index: bool) -> Result<'a, ()> {
+  // the pages are only read by the scans, but `f` may modify the record, so its page is accessed for writing first (see `Db::read_page`)
+  let db1 = db.pr();
+  let mut f = |data: *mut u8, rid: Rid| (db1.get_page::<DataPage>(rid.page()), f(data, rid)).1;
   if !use_index || !try_filter_with_index(db, where_, tp_id, &pred, &mut f)? {
-    let tp = db.get_page::<TablePage>(tp_id);
-    for (data, rid) in db.record_iter(tp) { if pred(data) { f(data, rid)?; } }
+    full_scan(db, tp_id, where_, None, pred, f)?;
+  }
+  Ok(())
+}
+
+// the used slots of `dp` that may satisfy `where_`: none if the page fails the Bloom filter test, otherwise the ones satisfying `batch`
+pub(crate) unsafe fn page_slots(dp: &DataPage, tp: &TablePage, hashes: &[u64], batch: &[BatchPred]) -> [u32; MAX_SLOT_BS] {
+  let mut slots = [0; MAX_SLOT_BS];
+  if hashes.iter().all(|&h| dp.bloom_test(tp.bloom_bits(), h)) {
+    slots = dp.used;
+    for b in batch { b.eval(dp.data.as_ptr(), tp.size as usize, tp.cap as usize, &mut slots); }
+  }
+  slots
+}
+
+// whether data page `page` is in a `tablesample` of `percent`%, pages are chosen by a hash of their id,
+// so the same pages are chosen every time, unless the table is modified
+pub(crate) fn sampled(page: u32, percent: f32) -> bool {
+  let mut h = (page as u64).wrapping_mul(0x9E3779B97F4A7C15);
+  h = (h ^ (h >> 32)).wrapping_mul(0xD6E8FEB86659FD93);
+  (((h ^ (h >> 32)) % 10000) as f32) < percent * 100.0
+}
+
+// visit all data pages of table `tp_id`, evaluating simple conditions a page at a time (see `page_slots`), `pred` is only called on the remaining slots
+// if `sample` is Some, only the `sampled` pages are visited
+// all rows in a page are reported to progress (and counted as read) at once, including the pages skipped by the Bloom filter
+pub(crate) unsafe fn full_scan<'a>(db: &mut Db, tp_id: u32, where_: &[impl Borrow<Cond<'a>>], sample: Option<f32>,
+                                   pred: impl Fn(*const u8) -> bool, mut f: impl FnMut(*mut u8, Rid) -> Result<'a, ()>) -> Result<'a, ()> {
+  let tp = db.read_page::<TablePage>(tp_id);
+  db.counters.full_scans += 1;
+  let (hashes, parts) = (bloom_conds(db, tp, where_).into_iter().map(|(_, h)| h).collect::<Vec<_>>(), part_mask(db, tp, where_));
+  let (batch, mut page) = (batch_predicates(db, tp, where_), tp.first);
+  while page != !0 {
+    let dp = db.read_page::<DataPage>(page);
+    if parts >> dp.part & 1 == 0 || !sample.map(|p| sampled(page, p)).unwrap_or(true) {
+      page = dp.next;
+      continue;
+    }
+    db.progress_add(dp.count as u64);
+    db.table_counters(tp_id).read += dp.count as u64;
+    let slots = page_slots(dp, tp, &hashes, &batch);
+    for i in 0..tp.cap as usize {
+      let data = dp.data.as_ptr().add(i * tp.size as usize) as *mut u8;
+      if bsget(slots.as_ptr(), i) && pred(data) { f(data, Rid::new(page, i as u32))?; }
+    }
+    page = dp.next;
   }
   Ok(())
 }
\ No newline at end of file
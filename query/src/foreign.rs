@@ -0,0 +1,180 @@
+use std::{ptr::null, collections::{BTreeSet, BTreeMap, HashMap}};
+use unchecked_unwrap::UncheckedUnwrap;
+
+use common::{*, Error::*, BareTy::*};
+use physics::*;
+use db::{Db, ChangeOp, is_null, coerce};
+use index::{Index, RTree, handle_all, in_index, fulltext_insert, fulltext_delete, spatial_point};
+
+// what deleting / updating a record leads to, by following the foreign links to it with their actions (see `FkAction`)
+// if `Db::foreign_keys` is false, only the record itself is deleted
+// all changes are collected before any of them is made, so a `restrict` foreign link anywhere in the chain rejects the whole change
+#[derive(Default)]
+pub(crate) struct Cascade {
+  // (tp_id, rid) of the records to delete
+  del: BTreeSet<(u32, Rid)>,
+  // (tp_id, ci_id, rid) => the new value of the col, null pointer for null
+  // a new value points into the record (or the update buffer) that causes the change, it is valid until `apply`
+  set: BTreeMap<(u32, u32, Rid), *const u8>,
+  // `Db::foreign_links_to` of each visited table, they are kept between records
+  links: HashMap<u32, Vec<(u32, u8, u8)>>,
+}
+
+impl Cascade {
+  unsafe fn links(&mut self, db: &mut Db, tp_id: u32) -> Vec<(u32, u8, u8)> {
+    self.links.entry(tp_id).or_insert_with(|| db.foreign_links_to(tp_id).collect()).clone()
+  }
+
+  // record `rid` of table `tp_id` is to be deleted
+  pub(crate) unsafe fn delete<'a>(&mut self, db: &mut Db, tp_id: u32, rid: Rid) -> Result<'a, ()> {
+    if !self.del.insert((tp_id, rid)) { return Ok(()); } // it is on a cycle of `cascade` links
+    if !db.foreign_keys { return Ok(()); }
+    let tp = db.get_page::<TablePage>(tp_id);
+    let data = db.get_data_slot(tp, rid);
+    for (tp_id1, ci_id1, ci_id) in self.links(db, tp_id) {
+      if is_null(data, ci_id as u32) { continue; }
+      let ci = tp.cols.get_unchecked(ci_id as usize);
+      let ptr = data.add(ci.off as usize);
+      let rids = referencing(db, tp_id1, ci_id1 as u32, ci, ptr);
+      if rids.is_empty() { continue; }
+      match db.get_page::<TablePage>(tp_id1).cols.get_unchecked(ci_id1 as usize).on_delete() {
+        FkAction::Restrict => return Err(ModifyColWithForeignLink { col: ci.name(), val: db.ptr2lit(ptr, ci.ty) }),
+        FkAction::Cascade => for rid1 in rids { self.delete(db, tp_id1, rid1)?; }
+        FkAction::SetNull => self.set(db, tp_id1, ci_id1 as u32, &rids, ptr, null())?,
+      }
+    }
+    Ok(())
+  }
+
+  // `old` value of col `ci_id` of table `tp_id` is to be changed to `new` (null pointer for null) in a record, `old` is not null
+  pub(crate) unsafe fn update<'a>(&mut self, db: &mut Db, tp_id: u32, ci_id: u32, old: *const u8, new: *const u8) -> Result<'a, ()> {
+    if !db.foreign_keys { return Ok(()); }
+    let ci = db.get_page::<TablePage>(tp_id).cols.get_unchecked(ci_id as usize);
+    for (tp_id1, ci_id1, _) in self.links(db, tp_id).into_iter().filter(|l| l.2 as u32 == ci_id) {
+      let rids = referencing(db, tp_id1, ci_id1 as u32, ci, old);
+      if rids.is_empty() { continue; }
+      match db.get_page::<TablePage>(tp_id1).cols.get_unchecked(ci_id1 as usize).on_update() {
+        FkAction::Restrict => return Err(ModifyColWithForeignLink { col: ci.name(), val: db.ptr2lit(old, ci.ty) }),
+        FkAction::Cascade => self.set(db, tp_id1, ci_id1 as u32, &rids, old, new)?,
+        FkAction::SetNull => self.set(db, tp_id1, ci_id1 as u32, &rids, old, null())?,
+      }
+    }
+    Ok(())
+  }
+
+  // col `ci_id` of records `rids` of table `tp_id` is to be changed from `old` to `new`, this col may also be referenced
+  // `new` is the value of the referenced col, so it may be a Char longer than this col
+  unsafe fn set<'a>(&mut self, db: &mut Db, tp_id: u32, ci_id: u32, rids: &[Rid], old: *const u8, new: *const u8) -> Result<'a, ()> {
+    let ci = db.get_page::<TablePage>(tp_id).cols.get_unchecked(ci_id as usize);
+    if new.is_null() && ci.flags.intersects(ColFlags::NOTNULL1) { return Err(PutNullOnNotNull); }
+    if !new.is_null() && ci.ty.fix_ty().ty == Char && *new as u16 + 1 > ci.ty.size() {
+      return Err(ColLitMismatch { ty: ci.ty, val: db.ptr2lit(new, ci.ty) });
+    }
+    let mut changed = false;
+    for &rid in rids { changed |= self.set.insert((tp_id, ci_id, rid), new).is_none(); }
+    // if no record is newly changed, it is on a cycle of foreign links
+    if changed { self.update(db, tp_id, ci_id, old, new) } else { Ok(()) }
+  }
+
+  // make all the collected changes, and clear them for the next record
+  pub(crate) unsafe fn apply(&mut self, db: &mut Db) {
+    for (&(tp_id, ci_id, rid), &new) in &self.set {
+      if !self.del.contains(&(tp_id, rid)) { set_col(db, tp_id, ci_id, rid, new); }
+    }
+    for &(tp_id, rid) in &self.del {
+      let tp = db.get_page::<TablePage>(tp_id);
+      let data = db.get_data_slot(tp, rid);
+      let old = db.cdc_record(tp, data);
+      for (ci_id, ci) in tp.cols().iter().enumerate() {
+        if !is_null(data, ci_id as u32) {
+          index_delete(db, tp_id, tp, ci_id as u32, data, rid);
+          if ci.ty.is_varchar() { db.free_varchar(data.add(ci.off as usize)); }
+        }
+      }
+      db.dealloc_data_slot(tp, rid);
+      tp.count -= 1;
+      db.table_counters(tp_id).deleted += 1;
+      db.stats_changed(tp, 1);
+      db.cdc_push(tp, ChangeOp::Delete, old, None);
+    }
+    (self.del.clear(), self.set.clear());
+  }
+}
+
+// the records of table `tp_id1` whose col `ci_id1` (which has a foreign link to `ci`) is `ptr`
+unsafe fn referencing(db: &mut Db, tp_id1: u32, ci_id1: u32, ci: &ColInfo, ptr: *const u8) -> Vec<Rid> {
+  let (mut rids, mut buf) = (vec![], None);
+  let ptr = match coerce(ptr, ci.ty, db.get_page::<TablePage>(tp_id1).cols.get_unchecked(ci_id1 as usize).ty, &mut buf) {
+    Some(ptr) => ptr, None => return rids, // too long for col `ci_id1`
+  };
+  macro_rules! handle {
+    ($ty: ident) => {{
+      let mut index = Index::<{ $ty }>::new(db, tp_id1, ci_id1);
+      let (mut it, end) = (index.lower_bound(ptr), index.upper_bound(ptr));
+      while it != end { rids.push(it.next().unchecked_unwrap()); }
+    }};
+  }
+  handle_all!(ci.ty.fix_ty().ty, handle);
+  rids
+}
+
+// set col `ci_id` of record `rid` to `new` (null pointer for null), the indexes affected by this col are maintained
+unsafe fn set_col(db: &mut Db, tp_id: u32, ci_id: u32, rid: Rid, new: *const u8) {
+  let tp = db.get_page::<TablePage>(tp_id);
+  let data = db.get_data_slot(tp, rid);
+  let ci = tp.cols.get_unchecked(ci_id as usize);
+  let old = db.cdc_record(tp, data);
+  // the same as in `update`, an index may be affected by the predicate of a partial index, or the y col of a spatial index
+  let affected = |ci_id1: usize, ci1: &ColInfo| ci1.index != !0 && !is_null(data, ci_id1 as u32) &&
+    (ci_id1 as u32 == ci_id || ci1.flags.contains(ColFlags::PARTIAL) || ci1.idx_spatial as u32 == ci_id + 1);
+  for (ci_id1, ci1) in tp.cols().iter().enumerate() {
+    if affected(ci_id1, ci1) { index_delete(db, tp_id, tp, ci_id1 as u32, data, rid); }
+  }
+  if new.is_null() {
+    bsset(data as *mut u32, ci_id as usize);
+  } else {
+    bsdel(data as *mut u32, ci_id as usize);
+    // a Char may come from a col of different size, only its length and content are copied
+    let size = if ci.ty.fix_ty().ty == Char { *new as usize + 1 } else { ci.ty.size() as usize };
+    data.add(ci.off as usize).copy_from_nonoverlapping(new, size);
+    db.bloom_add(tp, rid.page(), data);
+  }
+  for (ci_id1, ci1) in tp.cols().iter().enumerate() {
+    if affected(ci_id1, ci1) { index_insert(db, tp_id, tp, ci_id1 as u32, data, rid); }
+  }
+  db.stats_changed(tp, 1);
+  let new = db.cdc_record(tp, data);
+  db.cdc_push(tp, ChangeOp::Update, old, new);
+}
+
+// remove the not-null col `ci_id` of record `data` from the index on it (if any)
+unsafe fn index_delete(db: &mut Db, tp_id: u32, tp: &TablePage, ci_id: u32, data: *const u8, rid: Rid) {
+  let ci = tp.cols.get_unchecked(ci_id as usize);
+  let ptr = data.add(ci.off as usize);
+  if ci.index != !0 && in_index(db, tp, ci, data) {
+    if ci.idx_spatial != 0 {
+      RTree::new(db, tp_id, ci_id).delete(spatial_point(tp, ci, data), rid);
+    } else if ci.flags.contains(ColFlags::FULLTEXT) {
+      fulltext_delete(db, tp_id, ci_id, ptr, rid);
+    } else {
+      macro_rules! handle { ($ty: ident) => {{ Index::<{ $ty }>::new(db, tp_id, ci_id).delete(ptr, rid); }}; }
+      handle_all!(ci.ty.fix_ty().ty, handle);
+    }
+  }
+}
+
+// the reverse of `index_delete`
+unsafe fn index_insert(db: &mut Db, tp_id: u32, tp: &TablePage, ci_id: u32, data: *const u8, rid: Rid) {
+  let ci = tp.cols.get_unchecked(ci_id as usize);
+  let ptr = data.add(ci.off as usize);
+  if ci.index != !0 && in_index(db, tp, ci, data) {
+    if ci.idx_spatial != 0 {
+      RTree::new(db, tp_id, ci_id).insert(spatial_point(tp, ci, data), rid);
+    } else if ci.flags.contains(ColFlags::FULLTEXT) {
+      fulltext_insert(db, tp_id, ci_id, ptr, rid);
+    } else {
+      macro_rules! handle { ($ty: ident) => {{ Index::<{ $ty }>::new(db, tp_id, ci_id).insert(ptr, rid); }}; }
+      handle_all!(ci.ty.fix_ty().ty, handle);
+    }
+  }
+}
\ No newline at end of file
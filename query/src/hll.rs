@@ -0,0 +1,28 @@
+// HyperLogLog, estimating the number of distinct values from their hashes with constant memory (2 ^ HLL_P bytes)
+// the standard error is about 1.04 / sqrt(2 ^ HLL_P), so 0.8% here
+const HLL_P: u32 = 14;
+const HLL_M: usize = 1 << HLL_P;
+
+pub(crate) struct Hll { regs: Box<[u8]> }
+
+impl Hll {
+  pub(crate) fn new() -> Hll { Hll { regs: vec![0; HLL_M].into_boxed_slice() } }
+
+  // `hash` should be well mixed in all bits, like the one from `Db::bloom_hash`
+  pub(crate) fn add(&mut self, hash: u64) {
+    // the low HLL_P bits choose the register, the rank is the position of the first 1 bit in the rest (starting from 1)
+    let idx = hash as usize & (HLL_M - 1);
+    let rank = ((hash >> HLL_P) | (1 << (64 - HLL_P))).trailing_zeros() as u8 + 1;
+    let reg = unsafe { self.regs.get_unchecked_mut(idx) };
+    *reg = (*reg).max(rank);
+  }
+
+  pub(crate) fn count(&self) -> u64 {
+    let m = HLL_M as f64;
+    let sum = self.regs.iter().map(|&r| 1.0 / (1u64 << r) as f64).sum::<f64>();
+    let est = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+    let zeros = self.regs.iter().filter(|&&r| r == 0).count();
+    // linear counting is more accurate for small cardinality
+    (if est <= 2.5 * m && zeros != 0 { m * (m / zeros as f64).ln() } else { est }).round() as u64
+  }
+}
\ No newline at end of file
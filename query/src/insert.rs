@@ -4,8 +4,9 @@ use std::{borrow::Cow::{self, *}, cmp::Ordering::*};
 use common::{*, BareTy::*, Error::*};
 use syntax::ast::*;
 use physics::*;
-use index::{Index, cmp::Cmp, handle_all};
-use db::{Db, is_null, hash_pks};
+use index::{Index, RTree, cmp::Cmp, handle_all, in_index, fulltext_insert, spatial_point};
+use db::{Db, ChangeOp, is_null, hash_pks, coerce, eval_dft_fn};
+use crate::SelectResult;
 
 // update can also use this
 pub(crate) struct InsertCtx<'a> {
@@ -16,6 +17,7 @@ pub(crate) struct InsertCtx<'a> {
   pub(crate) pk_set: HashSet<u128>,
   // these 2 not used in update (it may be a little waste, but is acceptable)
   cols: Option<Box<[u32]>>,
+  // for a col with `DftFn`, it is the name of the function, the value is generated by `fill`
   dfts: Box<[CLit<'a>]>,
 }
 
@@ -29,7 +31,7 @@ impl<'a> InsertCtx<'a> {
     let cols = if let Some(cols1) = cols {
       let mut cols = vec![0; cols1.len()].into_boxed_slice();
       for (idx, c) in cols1.iter().enumerate() {
-        *cols.get_unchecked_mut(idx) = tp.get_ci(c)?.idx(&tp.cols);
+        *cols.get_unchecked_mut(idx) = tp.get_ci(c, db.ignore_case)?.idx(&tp.cols);
       }
       Some(cols)
     } else { None };
@@ -40,6 +42,7 @@ impl<'a> InsertCtx<'a> {
         let ptr = cp.data.as_ptr().add(cp.count as usize * ci.ty.size() as usize); // the one-past-last slot
         *dfts.get_unchecked_mut(idx) = db.ptr2lit(ptr, ci.ty);
       }
+      if let Some(f) = ci.dft_fn() { *dfts.get_unchecked_mut(idx) = CLit::new(Lit::Str(f.name())); }
     }
     Ok(InsertCtx { db: db.pr(), tp, tp_id, pks, pk_set, cols, dfts })
   }
@@ -66,7 +69,14 @@ impl<'a> InsertCtx<'a> {
     }
   }
 
-  unsafe fn insert(&mut self, buf: *mut u8, vals: &[CLit<'a>]) -> Result<'a, ()> {
+  // whether the `ci_id`th col takes its default value, if `n` values are given
+  fn defaulted(&self, ci_id: u32, n: usize) -> bool {
+    match &self.cols { Some(cols) => !cols.contains(&ci_id), None => ci_id as usize >= n }
+  }
+
+  // fill `buf` with `vals` and check all constraints, but the record is not written to the table yet
+  unsafe fn fill(&mut self, buf: *mut u8, vals: &[CLit<'a>]) -> Result<'a, ()> {
+    let n = vals.len();
     let vals = self.get_insert_val(vals)?;
     (buf as *mut u32).write_bytes(0, (vals.len() + 31) / 32); // clear null-bitset
     for (ci_id, &val) in vals.iter().enumerate() {
@@ -74,6 +84,8 @@ impl<'a> InsertCtx<'a> {
       if val.is_null() {
         if ci.flags.intersects(ColFlags::NOTNULL1) { return Err(PutNullOnNotNull); }
         bsset(buf as *mut u32, ci_id);
+      } else if let Some(f) = ci.dft_fn().filter(|_| self.defaulted(ci_id as u32, n)) {
+        eval_dft_fn(buf.add(ci.off as usize), ci.ty.fix_ty(), f);
       } else if !ci.ty.is_varchar() {
         self.db.lit2ptr(buf.add(ci.off as usize), ci.ty.fix_ty(), val)?;
       }
@@ -90,29 +102,59 @@ impl<'a> InsertCtx<'a> {
     for (ci_id, &val) in vals.iter().enumerate() {
       if !val.is_null() { Db::varchar_ck(self.tp.cols.get_unchecked(ci_id).ty, val)?; }
     }
-    // now no error can occur
+    Ok(())
+  }
+
+  // `vals` must have passed `fill`, so no error can occur
+  unsafe fn write_varchar(&mut self, buf: *mut u8, vals: &[CLit<'a>]) {
+    let vals = self.get_insert_val(vals).unchecked_unwrap();
     for (ci_id, &val) in vals.iter().enumerate() {
       let ci = self.tp.cols.get_unchecked(ci_id);
       if !val.is_null() && ci.ty.is_varchar() {
         match val.lit() { Lit::Str(s) => self.db.lit2varchar(buf.add(ci.off as usize), s, false), _ => impossible!() }
       }
     }
-    self.tp.count += 1;
-    let rid = self.db.alloc_data_slot(self.tp_id); // the `used` bit is set here, and `count` grows here
-    let (page, slot) = (rid.page(), rid.slot());
-    let dp = self.db.get_page::<DataPage>(page);
+  }
+
+  // copy the filled `buf` to the data slot `rid`
+  unsafe fn write(&mut self, buf: *const u8, rid: Rid) {
+    let dp = self.db.get_page::<DataPage>(rid.page());
     let size = self.tp.size as usize;
-    dp.data.as_mut_ptr().add(slot as usize * size).copy_from_nonoverlapping(buf, size);
+    dp.data.as_mut_ptr().add(rid.slot() as usize * size).copy_from_nonoverlapping(buf, size);
+    self.db.bloom_add(self.tp, rid.page(), buf);
+    self.db.progress_tick();
+    self.db.table_counters(self.tp_id).inserted += 1;
+    self.db.stats_changed(self.tp, 1);
+    let new = self.db.cdc_record(self.tp, buf);
+    self.db.cdc_push(self.tp, ChangeOp::Insert, None, new);
+    self.db.last_insert = Some(rid);
+  }
+
+  // the rid of the new record is returned
+  unsafe fn insert(&mut self, buf: *mut u8, vals: &[CLit<'a>]) -> Result<'a, Rid> {
+    self.fill(buf, vals)?;
+    // now no error can occur
+    self.write_varchar(buf, vals);
+    self.tp.count += 1;
+    let part = self.db.partition_of(self.tp, buf);
+    let rid = self.db.alloc_data_slot(self.tp_id, part); // the `used` bit is set here, and `count` grows here
+    self.write(buf, rid);
     // update index
     for (ci_id, ci) in self.tp.cols().iter().enumerate() {
       let ci_id = ci_id as u32;
-      if ci.index != !0 && !is_null(buf, ci_id) {  // null item doesn't get inserted to index
+      if ci.index != !0 && in_index(self.db, self.tp, ci, buf) {  // null item (or not satisfying partial index) doesn't get inserted to index
         let ptr = buf.add(ci.off as usize);
-        macro_rules! handle { ($ty: ident) => {{ Index::<{ $ty }>::new(self.db, self.tp_id, ci_id).insert(ptr, rid); }}; }
-        handle_all!(ci.ty.fix_ty().ty, handle);
+        if ci.idx_spatial != 0 {
+          RTree::new(self.db, self.tp_id, ci_id).insert(spatial_point(self.tp, ci, buf), rid);
+        } else if ci.flags.contains(ColFlags::FULLTEXT) {
+          fulltext_insert(self.db, self.tp_id, ci_id, ptr, rid);
+        } else {
+          macro_rules! handle { ($ty: ident) => {{ Index::<{ $ty }>::new(self.db, self.tp_id, ci_id).insert(ptr, rid); }}; }
+          handle_all!(ci.ty.fix_ty().ty, handle);
+        }
       }
     }
-    Ok(())
+    Ok(rid)
   }
 
   // `rid` is used for unique check, if rid is Some && a rid `rid1` is found in Index && `rid1` is equal to `rid`, it is not regarded as a duplicate
@@ -135,13 +177,17 @@ impl<'a> InsertCtx<'a> {
         }
         handle_all!(ci.ty.fix_ty().ty, handle);
       }
-      if ci.f_table != !0 {
+      if ci.f_table != !0 && self.db.foreign_keys {
+        let (f_ty, mut buf) = (self.db.get_page::<TablePage>(ci.f_table).cols.get_unchecked(ci.f_col as usize).ty, None);
         macro_rules! handle {
           ($ty: ident) => {{
-            if !Index::<{ $ty }>::new(self.db, ci.f_table, ci.f_col as u32).contains(ptr) { return Err(PutNonexistentForeign { col: ci.name(), val }); }
+            match coerce(ptr, ci.ty, f_ty, &mut buf) {
+              Some(p) if Index::<{ $ty }>::new(self.db, ci.f_table, ci.f_col as u32).contains(p) => {}
+              _ => return Err(PutNonexistentForeign { col: ci.name(), val }),
+            }
           }};
         }
-        handle_all!(ci.ty.fix_ty().ty, handle); // their type are exactly the same, so can use `ptr` directly to search in index, `create_table` guarantee this
+        handle_all!(ci.ty.fix_ty().ty, handle); // their types are the same except the size of Char (see `coerce`), `create_table` guarantees this
       }
       if ci.check != !0 {
         let cp = self.db.get_page::<CheckPage>(ci.check >> 1);
@@ -168,4 +214,88 @@ pub fn insert<'a>(i: &Insert<'a>, db: &mut Db) -> ModifyResult<'a, u32> {
     }
     Ok(cnt)
   }
+}
+
+// like `insert`, but the inserted rows (with default values filled) are output like a select of `cols`, all cols if None
+pub fn insert_returning<'a, 'b>(i: &Insert<'a>, cols: Option<&[&'a str]>, db: &'b mut Db) -> ModifyResult<'a, SelectResult<'b>> {
+  unsafe {
+    let mut ctx = InsertCtx::new(db, i.table, i.cols.as_deref())?;
+    let tp = &*(ctx.tp as *const TablePage);
+    let cols = SelectResult::returning_cols(db, tp, cols)?;
+    let buf = Align4U8::new(ctx.tp.size as usize);
+    let mut rids = Vec::with_capacity(i.vals.len());
+    for vals in &i.vals {
+      match ctx.insert(buf.ptr, vals) {
+        Ok(rid) => rids.push(rid),
+        Err(e) => return Err(ModifyError(rids.len() as u32, e)),
+      }
+    }
+    // the records are located after all rows are inserted, since inserting may move pages
+    let data = rids.iter().map(|&rid| db.get_data_slot(tp, rid) as *const u8).collect::<Vec<_>>();
+    Ok(SelectResult::records(db, cols, &data))
+  }
+}
+
+// like `insert`, but all rows are inserted at once, which is much faster for many rows:
+// 1. all rows are checked before any of them is written, so either all rows are inserted, or none of them is (the count in error is always 0)
+// 2. data slots are allocated a page at a time (see `Db::alloc_data_slots`)
+// 3. keys are inserted to each index together in sorted order, or built bottom-up if the index is empty (see `Index::bulk_insert`)
+// unlike `insert`, a foreign key can't refer to another row in the same batch, because they are checked against the table before the batch
+pub fn insert_batch<'a>(i: &Insert<'a>, db: &mut Db) -> ModifyResult<'a, u32> {
+  unsafe {
+    let mut ctx = InsertCtx::new(db, i.table, i.cols.as_deref())?;
+    let (size, n) = (ctx.tp.size as usize, i.vals.len());
+    let buf = Align4U8::new(size * n.max(1));
+    for (idx, vals) in i.vals.iter().enumerate() {
+      ctx.fill(buf.ptr.add(idx * size), vals).map_err(|e| ModifyError(0, e))?;
+    }
+    ctx.check_batch_unique(buf.ptr, &i.vals).map_err(|e| ModifyError(0, e))?;
+    // now no error can occur
+    ctx.tp.count += n as u32;
+    let parts = (0..n).map(|idx| ctx.db.partition_of(ctx.tp, buf.ptr.add(idx * size))).collect::<Vec<_>>();
+    let rids = ctx.db.alloc_data_slots(ctx.tp_id, &parts);
+    for (idx, (vals, &rid)) in i.vals.iter().zip(&rids).enumerate() {
+      ctx.write_varchar(buf.ptr.add(idx * size), vals);
+      ctx.write(buf.ptr.add(idx * size), rid);
+    }
+    let (db, tp, tp_id) = (ctx.db as *mut Db, &*ctx.tp, ctx.tp_id);
+    for (ci_id, ci) in tp.cols().iter().enumerate() {
+      if ci.index == !0 { continue; }
+      let ci_id = ci_id as u32;
+      let rows = rids.iter().map(|&rid| (db.r().get_data_slot(tp, rid), rid)).filter(|&(data, _)| in_index(db.r(), tp, ci, data));
+      if ci.idx_spatial != 0 {
+        for (data, rid) in rows { RTree::new(db.r(), tp_id, ci_id).insert(spatial_point(tp, ci, data), rid); }
+      } else if ci.flags.contains(ColFlags::FULLTEXT) {
+        for (data, rid) in rows { fulltext_insert(db.r(), tp_id, ci_id, data.add(ci.off as usize), rid); }
+      } else {
+        let keys = rows.map(|(data, rid)| (data.add(ci.off as usize) as *const u8, rid)).collect::<Vec<_>>();
+        macro_rules! handle { ($ty: ident) => {{ Index::<{ $ty }>::new(db.r(), tp_id, ci_id).bulk_insert(keys); }}; }
+        handle_all!(ci.ty.fix_ty().ty, handle);
+      }
+    }
+    Ok(n as u32)
+  }
+}
+
+impl<'a> InsertCtx<'a> {
+  // rows in a batch are not in the indexes when they are checked by `check_col`, so duplicates among them are found here
+  // `buf` holds the filled rows, the values of each unique col are sorted to find equal neighbours
+  unsafe fn check_batch_unique(&self, buf: *const u8, rows: &[Vec<CLit<'a>>]) -> Result<'a, ()> {
+    let size = self.tp.size as usize;
+    for (ci_id, ci) in self.tp.cols().iter().enumerate() {
+      if !ci.unique(self.pks.len()) { continue; }
+      let mut vals = (0..rows.len()).map(|idx| (buf.add(idx * size), idx)).filter(|&(data, _)| !is_null(data, ci_id as u32))
+        .map(|(data, idx)| (data.add(ci.off as usize), idx)).collect::<Vec<_>>();
+      macro_rules! handle { ($ty: ident) => {{ vals.sort_unstable_by(|l, r| Cmp::<{ $ty }>::cmp(l.0, r.0)); }}; }
+      handle_all!(ci.ty.fix_ty().ty, handle);
+      for w in vals.windows(2) {
+        macro_rules! handle { ($ty: ident) => { Cmp::<{ $ty }>::cmp(w[0].0, w[1].0) }; }
+        if handle_all!(ci.ty.fix_ty().ty, handle) == Equal {
+          let val = *self.get_insert_val(rows.get_unchecked(w[1].1)).unchecked_unwrap().get_unchecked(ci_id);
+          return Err(PutDupOnUnique { col: ci.name(), val });
+        }
+      }
+    }
+    Ok(())
+  }
 }
\ No newline at end of file
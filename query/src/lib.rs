@@ -7,30 +7,20 @@ pub mod insert;
 pub mod delete;
 pub mod select;
 pub mod update;
+pub mod explain;
+pub mod sort;
+pub mod parallel;
+pub mod cursor;
+pub mod row;
+#[cfg(feature = "with-serde")]
+pub mod de;
 mod predicate;
+mod subquery;
 mod filter;
+mod estimate;
+mod hll;
+mod foreign;
 
-pub use crate::{insert::*, delete::*, select::*, update::*};
-
-use db::{Db, is_null};
-use physics::*;
-use index::{Index, handle_all};
-use common::{*, Error::*, BareTy::*};
-
-// return Err if there is a foreign link to `data`
-unsafe fn check_foreign_link<'a>(db: &Db, tp: &TablePage, data: *const u8, f_links: &[(u32, u8, u8)]) -> Result<'a, ()> {
-  let db = db.pr();
-  for &(tp_id1, ci_id1, ci_id) in f_links {
-    let ci = tp.cols.get_unchecked(ci_id as usize);
-    let ptr = data.add(ci.off as usize);
-    macro_rules! handle {
-      ($ty: ident) => {{
-        if !is_null(data, ci_id as u32) && Index::<{ $ty }>::new(db, tp_id1, ci_id1 as u32).contains(ptr) {
-          return Err(ModifyColWithForeignLink { col: ci.name(), val: db.ptr2lit(ptr, ci.ty) });
-        }
-      }};
-    }
-    handle_all!(ci.ty.fix_ty().ty, handle);
-  }
-  Ok(())
-}
+pub use crate::{insert::*, delete::*, select::*, update::*, explain::*, cursor::*, row::*};
+#[cfg(feature = "with-serde")]
+pub use crate::de::*;
\ No newline at end of file
@@ -0,0 +1,146 @@
+use unchecked_unwrap::UncheckedUnwrap;
+use std::{borrow::Borrow, cmp::Ordering, panic};
+use crossbeam_utils::thread;
+
+use common::{*, AggOp::*};
+use syntax::ast::*;
+use physics::*;
+use db::{Db, is_null};
+use crate::{filter::{try_filter_with_index, choose_range, fulltext_cond, spatial_cond, bloom_conds, part_mask, page_slots, full_scan, sampled, cmp},
+            predicate::{Pred, batch_predicates}, select::Col};
+
+// a table with fewer data pages is scanned in the current thread, because starting threads costs more than scanning it
+pub const PAR_MIN_PAGES: usize = 64;
+
+// split the data pages of table `tp_id` passing `where_` into `threads` ranges, and scan each range in a thread, which folds the records passing `preds` into a `T`
+// return the `T`s in the order of ranges, or None if the table should be scanned serially: `threads` is 1, the table is small,
+// or some predicate is `Local`; all pages are resolved in the current thread, the threads only read the records in them
+// progress (see `Db::progress_tick`) is not reported in a parallel scan
+unsafe fn par_scan<'a, 'p, T: Send>(db: &mut Db, threads: usize, where_: &[impl Borrow<Cond<'a>>], tp_id: u32, sample: Option<f32>, preds: &[Pred],
+                                    init: impl Fn() -> T + Sync, fold: impl Fn(&mut T, &'p [u8], Rid) + Sync) -> Option<Vec<T>> {
+  if threads <= 1 { return None; }
+  let preds = preds.iter().map(|p| match p { Pred::Sync(p) => Some(&**p), Pred::Local(_) => None }).collect::<Option<Vec<_>>>()?;
+  let tp = &*db.read_page::<TablePage>(tp_id);
+  let (parts, mut pages, mut page) = (part_mask(db, tp, where_), vec![], tp.first);
+  while page != !0 {
+    let dp: &'p DataPage = db.read_page::<DataPage>(page);
+    if parts >> dp.part & 1 == 1 && sample.map(|p| sampled(page, p)).unwrap_or(true) { pages.push((page, dp)); }
+    page = dp.next;
+  }
+  if pages.len() < PAR_MIN_PAGES { return None; }
+  db.counters.full_scans += 1;
+  db.table_counters(tp_id).read += pages.iter().map(|(_, dp)| dp.count as u64).sum::<u64>();
+  let hashes = bloom_conds(db, tp, where_).into_iter().map(|(_, h)| h).collect::<Vec<_>>();
+  let batch = batch_predicates(db, tp, where_);
+  let (init, fold, preds, hashes, batch, size) = (&init, &fold, &preds, &hashes, &batch, tp.size as usize);
+  let ret = thread::scope(|s| {
+    let handles = pages.chunks((pages.len() + threads - 1) / threads).map(|chunk| s.spawn(move |_| {
+      let mut acc = init();
+      for &(page, dp) in chunk {
+        let slots = page_slots(dp, tp, hashes, batch);
+        for i in 0..tp.cap as usize {
+          let rec = dp.data.get_unchecked(i * size..(i + 1) * size);
+          if bsget(slots.as_ptr(), i) && preds.iter().all(|p| p(rec.as_ptr())) { fold(&mut acc, rec, Rid::new(page, i as u32)); }
+        }
+      }
+      acc
+    })).collect::<Vec<_>>();
+    handles.into_iter().map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e))).collect()
+  });
+  Some(ret.unwrap_or_else(|e| panic::resume_unwind(e)))
+}
+
+// like `filter` with `use_index` = true, but the full scan of a large table may be split into ranges of data pages scanned by `threads` (see `par_scan`)
+// `f` is still called in the current thread, in the same order as a serial scan, but only after the whole scan, so `f` can't modify the table
+// if `sample` is Some, indexes are not used, and only the `sampled` pages are scanned
+pub(crate) unsafe fn par_filter<'a>(db: &mut Db, threads: usize, where_: &[impl Borrow<Cond<'a>>], tp_id: u32, sample: Option<f32>,
+                                    preds: &[Pred], mut f: impl FnMut(*mut u8, Rid) -> Result<'a, ()>) -> Result<'a, ()> {
+  let pred = |p: *const u8| preds.iter().all(|x| x.test(p));
+  if sample.is_none() && try_filter_with_index(db, where_, tp_id, &pred, &mut f)? { return Ok(()); }
+  match par_scan(db, threads, where_, tp_id, sample, preds, Vec::new, |rids, _, rid| rids.push(rid)) {
+    Some(rids) => {
+      let tp = db.read_page::<TablePage>(tp_id);
+      for rid in rids.into_iter().flatten() { f(db.read_data_slot(tp, rid) as *mut u8, rid)?; }
+      Ok(())
+    }
+    None => full_scan(db, tp_id, where_, sample, pred, f),
+  }
+}
+
+// the partial result of an aggregate over the records of some pages
+#[derive(Copy, Clone, Default)]
+struct Partial<'p> {
+  // the number of not null values (all records for `CountAll`)
+  n: u64,
+  // for `Sum` and `Avg`
+  sum: f64,
+  // the record with the smallest (`Min`) or largest (`Max`) not null value
+  best: Option<&'p [u8]>,
+}
+
+impl<'p> Partial<'p> {
+  unsafe fn better(col: &Col, x: &[u8], y: Option<&[u8]>) -> bool {
+    let (_, ci) = col.ci.unchecked_unwrap();
+    let off = ci.off as usize;
+    y.map_or(true, |y| {
+      let ord = cmp(ci.ty.fix_ty().ty, x.as_ptr().add(off), y.as_ptr().add(off));
+      ord == if col.op == Some(Min) { Ordering::Less } else { Ordering::Greater }
+    })
+  }
+
+  unsafe fn add(&mut self, col: &Col, rec: &'p [u8]) {
+    let (ci_id, ci) = match col.ci { Some(ci) => ci, None => { self.n += 1; return; } }; // `CountAll`
+    if is_null(rec.as_ptr(), ci_id) { return; }
+    self.n += 1;
+    let ptr = rec.as_ptr().add(ci.off as usize);
+    match col.op.unchecked_unwrap() {
+      Sum | Avg => self.sum += match ci.ty { int!() => *(ptr as *const i32) as f64, float!() => *(ptr as *const f32) as f64, _ => impossible!() },
+      Min | Max => if Partial::better(col, rec, self.best) { self.best = Some(rec); }
+      _ => {}
+    }
+  }
+
+  // `other` is the partial result of later pages
+  unsafe fn merge(&mut self, col: &Col, other: Partial<'p>) {
+    (self.n += other.n, self.sum += other.sum);
+    if let Some(rec) = other.best { if Partial::better(col, rec, self.best) { self.best = Some(rec); } }
+  }
+
+  unsafe fn lit<'b>(&self, db: &Db, col: &Col) -> CLit<'b> {
+    CLit::new(match col.op.unchecked_unwrap() {
+      Count | CountAll => Lit::Number(self.n as f64),
+      _ if self.n == 0 => Lit::Null,
+      Sum => Lit::Number(self.sum),
+      Avg => Lit::Number(self.sum / self.n as f64),
+      _ => { // Min | Max
+        let (ci_id, ci) = col.ci.unchecked_unwrap();
+        return db.data2lit(self.best.unchecked_unwrap().as_ptr(), ci_id, ci);
+      }
+    })
+  }
+}
+
+// the aggregates `cols` of the records of table `tp_id` passing `where_` and `preds`, each thread aggregates its pages (see `par_scan`),
+// and the partial results are merged, so records are not collected; the sum of floats may differ from a serial scan in the last bits
+// None if the table is scanned serially, or it is answered by an index, or an aggregate can't be merged from partial results
+pub(crate) unsafe fn par_aggregate<'a, 'b>(db: &'b Db, threads: usize, where_: &[impl Borrow<Cond<'a>>], tp_id: u32, sample: Option<f32>,
+                                           preds: &[Pred], cols: &[Col<'b>]) -> Option<Vec<CLit<'b>>> {
+  let mergeable = |col: &Col| match col.op {
+    Some(Count) | Some(CountAll) | Some(Sum) | Some(Avg) => true,
+    Some(Min) | Some(Max) => !col.ci.unchecked_unwrap().1.ty.is_varchar(),
+    _ => false,
+  };
+  if !cols.iter().all(mergeable) { return None; }
+  let tp = db.pr().get_page::<TablePage>(tp_id);
+  let indexed = fulltext_cond(db, tp, where_).is_some() || spatial_cond(db, tp, where_).is_some() || choose_range(db.pr(), where_, tp).0.is_some();
+  if sample.is_none() && indexed { return None; }
+  let init = || vec![Partial::default(); cols.len()];
+  let partials = par_scan(db.pr(), threads, where_, tp_id, sample, preds, &init, |acc, rec, _| {
+    for (p, col) in acc.iter_mut().zip(cols) { Partial::add(p, col, rec); }
+  })?;
+  let mut ret = init();
+  for partial in partials {
+    for ((p, col), other) in ret.iter_mut().zip(cols).zip(partial) { p.merge(col, other); }
+  }
+  Some(ret.iter().zip(cols).map(|(p, col)| p.lit(db, col)).collect())
+}
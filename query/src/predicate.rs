@@ -1,9 +1,12 @@
 use chrono::NaiveDate;
+use std::{borrow::Borrow, cmp::Ordering};
 
 use common::{*, Error::*, BareTy::*, CmpOp::*};
 use syntax::ast::*;
 use physics::*;
 use db::{is_null, Db};
+use index::{tokenize, text};
+use crate::subquery::{Subquery, SubqueryErr, Values, first, lit_ty};
 
 macro_rules! handle_op {
   ($cmp: ident, $op:expr, $p: ident, $l: expr, $r: expr) => {
@@ -17,21 +20,42 @@ macro_rules! handle_op {
 // the pointer from IndexPage cannot be passed to predicate!
 // It is just the data ptr, but all these predicate accept the pointer to the beginning of the whole data slot
 
+// a predicate on the records of one table, it is `Sync` if it only reads the record, so scanning threads can share it (see `par_scan`)
+// reading a varchar or text (from other pages of the db) or evaluating a subquery (which has a cache) is `Local`
+pub enum Pred<'a> {
+  Sync(Box<dyn Fn(*const u8) -> bool + Sync + 'a>),
+  Local(Box<dyn Fn(*const u8) -> bool + 'a>),
+}
+
+impl<'a> Pred<'a> {
+  pub fn test(&self, p: *const u8) -> bool {
+    match self { Pred::Sync(f) => f(p), Pred::Local(f) => f(p) }
+  }
+
+  pub fn local(self) -> Box<dyn Fn(*const u8) -> bool + 'a> {
+    match self { Pred::Sync(f) => f, Pred::Local(f) => f }
+  }
+}
+
 // assume both lhs and rhs belongs to tp's table, so ColRef::table is not checked
-pub unsafe fn one_predicate<'a, 'b>(db: &'a Db, e: &Cond<'b>, tp: &TablePage) -> Result<'b, Box<dyn Fn(*const u8) -> bool + 'a>> {
+// a subquery in `e` is kept in the predicate, so the statement must outlive it, and its errors are kept in `err`
+pub(crate) unsafe fn one_predicate<'a, 'b: 'a>(db: &'a Db, e: &Cond<'b>, tp: &TablePage, err: &SubqueryErr<'b>) -> Result<'b, Pred<'a>> {
   let tp = tp.pr();
-  let l = tp.get_ci(e.lhs_col().col)?;
+  let l = tp.get_ci(e.lhs_col().col, db.ignore_case)?;
   let l_id = l.idx(&tp.cols) as u8; // reduce the size of lambda closure, do conversion inside lambda
   let l_off = l.off;
   match *e {
     Cond::Cmp(op, _, r) => match r {
       Atom::Lit(r) => {
         macro_rules! cmp {
-          ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(box move |$p| !is_null($p, l_id as u32) && $l $op $r) };
+          ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(Pred::Sync(box move |$p| !is_null($p, l_id as u32) && $l $op $r)) };
+        }
+        macro_rules! db_cmp {
+          ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(Pred::Local(box move |$p| !is_null($p, l_id as u32) && $l $op $r)) };
         }
         // the match logic is basically the same as the logic in `lit2ptr`, though the content is different
         match (l.ty, r.lit()) {
-          (_, Lit::Null) => Ok(box |_| false), // comparing with null always returns false
+          (_, Lit::Null) => Ok(Pred::Sync(box |_| false)), // comparing with null always returns false
           (bool!(), Lit::Bool(v)) => handle_op!(cmp, op, p, *(p.add(l_off as _) as *const bool), v),
           (int!(), Lit::Number(v)) => handle_op!(cmp, op, p, *(p.add(l_off as _) as *const i32), v as i32),
           (float!(), Lit::Number(v)) => handle_op!(cmp, op, p, *(p.add(l_off as _) as *const f32), v as f32),
@@ -39,23 +63,28 @@ pub unsafe fn one_predicate<'a, 'b>(db: &'a Db, e: &Cond<'b>, tp: &TablePage) ->
             let date = db::date(v)?;
             handle_op!(cmp, op, p, *(p.add(l_off as _) as *const NaiveDate), date)
           }
+          // from the value of a date col, e.g. in a correlated subquery
+          (date!(), Lit::Date(date)) => handle_op!(cmp, op, p, *(p.add(l_off as _) as *const NaiveDate), date),
           (char!(), Lit::Str(v)) => {
             let v = Box::<str>::from(v);
             handle_op!(cmp, op, p, str_from_db(p.add(l_off as _)), v.as_ref())
           }
           (varchar!(), Lit::Str(v)) => {
             let v = Box::<str>::from(v);
-            handle_op!(cmp, op, p, db.varchar(p.add(l_off as _)), v.as_ref())
+            handle_op!(db_cmp, op, p, db.varchar(p.add(l_off as _)), v.as_ref())
           }
           _ => return Err(ColLitMismatch { ty: l.ty, val: r })
         }
       }
       Atom::ColRef(r) => {
-        let r = tp.get_ci(r.col)?;
+        let r = tp.get_ci(r.col, db.ignore_case)?;
         let r_id = r.idx(&tp.cols) as u16;
         let r_off = r.off;
         macro_rules! cmp {
-          ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(box move |$p| !is_null($p, l_id as u32) && !is_null($p, r_id as u32) && $l $op $r) };
+          ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(Pred::Sync(box move |$p| !is_null($p, l_id as u32) && !is_null($p, r_id as u32) && $l $op $r)) };
+        }
+        macro_rules! db_cmp {
+          ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(Pred::Local(box move |$p| !is_null($p, l_id as u32) && !is_null($p, r_id as u32) && $l $op $r)) };
         }
         match (l.ty, r.ty) {
           (bool!(), bool!()) => handle_op!(cmp, op, p, *(p.add(l_off as _) as *const bool), *(p.add(r_off as _) as *const bool)),
@@ -65,28 +94,159 @@ pub unsafe fn one_predicate<'a, 'b>(db: &'a Db, e: &Cond<'b>, tp: &TablePage) ->
           (float!(), int!()) => handle_op!(cmp, op, p, *(p.add(l_off as _) as *const f32), *(p.add(r_off as _) as *const i32) as f32),
           (date!(), date!()) => handle_op!(cmp, op, p, *(p.add(l_off as _) as *const NaiveDate), *(p.add(r_off as _) as *const NaiveDate)),
           (char!(), char!()) => handle_op!(cmp, op, p, str_from_db(p.add(l_off as _)), str_from_db(p.add(r_off as _))),
-          (char!(), varchar!()) => handle_op!(cmp, op, p, str_from_db(p.add(l_off as _)), db.varchar(p.add(r_off as _))),
-          (varchar!(), char!()) => handle_op!(cmp, op, p, db.varchar(p.add(l_off as _)), str_from_db(p.add(r_off as _))),
-          (varchar!(), varchar!()) => handle_op!(cmp, op, p, db.varchar(p.add(l_off as _)), db.varchar(p.add(r_off as _))),
+          (char!(), varchar!()) => handle_op!(db_cmp, op, p, str_from_db(p.add(l_off as _)), db.varchar(p.add(r_off as _))),
+          (varchar!(), char!()) => handle_op!(db_cmp, op, p, db.varchar(p.add(l_off as _)), str_from_db(p.add(r_off as _))),
+          (varchar!(), varchar!()) => handle_op!(db_cmp, op, p, db.varchar(p.add(l_off as _)), db.varchar(p.add(r_off as _))),
           (l, r) => return Err(ColMismatch { l, r })
         }
       }
     },
-    Cond::Null(_, null) => Ok(if null { box move |p| is_null(p, l_id as u32) } else { box move |p| !is_null(p, l_id as u32) }),
+    Cond::Null(_, null) => Ok(Pred::Sync(if null { box move |p| is_null(p, l_id as u32) } else { box move |p| !is_null(p, l_id as u32) })),
     Cond::Like(_, like) => {
       let re = db::like2re(like)?;
       match l.ty {
-        char!() => Ok(box move |p| !is_null(p, l_id as u32) && re.is_match(str_from_db(p.add(l_off as _)))),
-        varchar!() => Ok(box move |p| !is_null(p, l_id as u32) && re.is_match(db.varchar(p.add(l_off as _)))),
+        char!() => Ok(Pred::Sync(box move |p| !is_null(p, l_id as u32) && re.is_match(str_from_db(p.add(l_off as _))))),
+        varchar!() => Ok(Pred::Local(box move |p| !is_null(p, l_id as u32) && re.is_match(db.varchar(p.add(l_off as _))))),
         _ => Err(InvalidLikeTy(l.ty))
       }
     }
+    Cond::Fn(func, op, _, r) => {
+      macro_rules! cmp {
+        ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(Pred::Sync(box move |$p| !is_null($p, l_id as u32) && $l.as_str() $op $r)) };
+      }
+      macro_rules! db_cmp {
+        ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(Pred::Local(box move |$p| !is_null($p, l_id as u32) && $l.as_str() $op $r)) };
+      }
+      match (l.ty, r.lit()) {
+        (char!(), Lit::Null) | (varchar!(), Lit::Null) => Ok(Pred::Sync(box |_| false)),
+        (char!(), Lit::Str(v)) => {
+          let v = Box::<str>::from(v);
+          handle_op!(cmp, op, p, func.apply(str_from_db(p.add(l_off as _))), v.as_ref())
+        }
+        (varchar!(), Lit::Str(v)) => {
+          let v = Box::<str>::from(v);
+          handle_op!(db_cmp, op, p, func.apply(db.varchar(p.add(l_off as _))), v.as_ref())
+        }
+        (char!(), _) | (varchar!(), _) => Err(ColLitMismatch { ty: l.ty, val: r }),
+        _ => Err(InvalidStrFnTy { func, ty: l.ty }),
+      }
+    }
+    Cond::Bit(bit, _, mask, op, r) => {
+      match l.ty { int!() => {} ty => return Err(InvalidBitTy { op: bit, ty }) }
+      let mask = match mask.lit() { Lit::Number(x) if x as i32 as f64 == x => x as i32, _ => return Err(ColLitMismatch { ty: l.ty, val: mask }) };
+      macro_rules! cmp {
+        ($op: tt, $p: ident, $l: expr, $r: expr) => { Ok(Pred::Sync(box move |$p| !is_null($p, l_id as u32) && $l.map_or(false, |x| (x as f64) $op $r))) };
+      }
+      match r.lit() {
+        Lit::Null => Ok(Pred::Sync(box |_| false)),
+        Lit::Number(v) => handle_op!(cmp, op, p, bit.bit(*(p.add(l_off as _) as *const i32), mask), v),
+        _ => Err(ColLitMismatch { ty: l.ty, val: r }),
+      }
+    }
+    Cond::Row(op, ref cols, ref lits) => {
+      if cols.len() != lits.len() { return Err(RowArityMismatch { cols: cols.len(), vals: lits.len() }); }
+      let cmps = cols.iter().zip(lits).map(|(c, &r)| lit_cmp(db, tp, c, r)).collect::<Result<'b, Vec<_>>>()?;
+      Ok(Pred::Local(match op {
+        // `=` and `<>` are decided by all items, e.g., `(null, 1) <> (2, 1)` is true
+        Eq => box move |p| cmps.iter().all(|c| c(p) == Some(Ordering::Equal)),
+        Ne => box move |p| cmps.iter().any(|c| c(p).map_or(false, |o| o != Ordering::Equal)),
+        // the first unequal item decides, a null before it makes the result unknown (false)
+        _ => box move |p| {
+          for c in &cmps {
+            match c(p) { Some(Ordering::Equal) => {}, Some(o) => return op.test(o), None => return false }
+          }
+          op.test(Ordering::Equal)
+        }
+      }))
+    }
+    Cond::RowIn(ref cols, ref rows) => {
+      let mut cmps = Vec::with_capacity(rows.len());
+      for row in rows {
+        if cols.len() != row.len() { return Err(RowArityMismatch { cols: cols.len(), vals: row.len() }); }
+        cmps.push(cols.iter().zip(row).map(|(c, &r)| lit_cmp(db, tp, c, r)).collect::<Result<'b, Vec<_>>>()?);
+      }
+      Ok(Pred::Local(box move |p| cmps.iter().any(|row| row.iter().all(|c| c(p) == Some(Ordering::Equal)))))
+    }
+    Cond::Quant(op, _, all, ref s) => {
+      let sub = Subquery::new(db, s, tp, Values::new, err)?;
+      if lit_ty(l.ty) != lit_ty(sub.ty) { return Err(ColMismatch { l: l.ty, r: sub.ty }); }
+      let l = &*l;
+      Ok(Pred::Local(box move |p| sub.eval(p).map_or(false, |v| v.test(op, all, db.data2lit(p, l_id as u32, l).lit()))))
+    }
+    Cond::Sub(op, _, ref s) => {
+      let sub = Subquery::new(db, s, tp, first, err)?;
+      if lit_ty(l.ty) != lit_ty(sub.ty) { return Err(ColMismatch { l: l.ty, r: sub.ty }); }
+      let l = &*l;
+      Ok(Pred::Local(box move |p| {
+        let (x, v) = (db.data2lit(p, l_id as u32, l).lit(), sub.eval(p));
+        v.map_or(false, |v| !x.is_null() && !v.is_null() && op.test(x.cmp(&v)))
+      }))
+    }
+    Cond::Within(_, y, lits) => {
+      let y = tp.get_ci(y.col, db.ignore_case)?;
+      let (y_id, y_off) = (y.idx(&tp.cols) as u8, y.off);
+      for &ty in &[l.ty, y.ty] {
+        match ty { float!() => {}, ty => return Err(InvalidSpatialTy(ty)) }
+      }
+      let rect = within_rect(l.ty, &lits)?;
+      Ok(Pred::Sync(box move |p| !is_null(p, l_id as u32) && !is_null(p, y_id as u32) &&
+        rect.contains(&Rect::point(*(p.add(l_off as _) as *const f32), *(p.add(y_off as _) as *const f32)))))
+    }
+    Cond::Match(col, query) => {
+      if !l.flags.contains(ColFlags::FULLTEXT) { return Err(NoFullTextIndex(col.col)); }
+      let words = tokenize(query).collect::<HashSet<_>>();
+      let l = &*l;
+      Ok(Pred::Local(box move |p| !is_null(p, l_id as u32) && tokenize(text(db, l, p.add(l_off as _))).any(|w| words.contains(&w))))
+    }
+  }
+}
+
+// compare `col` in a record with `r`, None if either of them is null, used by `Cond::Row` and `Cond::RowIn`
+// an int col is compared with the literal as a float, so `(a) < (1.5)` holds for `a = 1`
+unsafe fn lit_cmp<'a, 'b>(db: &'a Db, tp: &TablePage, col: &ColRef<'b>, r: CLit<'b>) -> Result<'b, Box<dyn Fn(*const u8) -> Option<Ordering> + 'a>> {
+  let tp = tp.pr();
+  if let Some(t) = col.table { if col.db.is_some() || !db.name_eq(t, tp.name()) { return Err(NoSuchTable(t)); } }
+  let l = tp.get_ci(col.col, db.ignore_case)?;
+  let (l_id, l_off) = (l.idx(&tp.cols), l.off as usize);
+  macro_rules! cmp {
+    ($p: ident, $l: expr, $r: expr) => { Ok(box move |$p| if is_null($p, l_id) { None } else { $l.partial_cmp(&$r) }) };
+  }
+  match (l.ty, r.lit()) {
+    (_, Lit::Null) => Ok(box |_| None),
+    (bool!(), Lit::Bool(v)) => cmp!(p, *(p.add(l_off) as *const bool), v),
+    (int!(), Lit::Number(v)) => cmp!(p, *(p.add(l_off) as *const i32) as f64, v),
+    (float!(), Lit::Number(v)) => cmp!(p, *(p.add(l_off) as *const f32), v as f32),
+    (date!(), Lit::Str(v)) => {
+      let date = db::date(v)?;
+      cmp!(p, *(p.add(l_off) as *const NaiveDate), date)
+    }
+    (date!(), Lit::Date(date)) => cmp!(p, *(p.add(l_off) as *const NaiveDate), date),
+    (char!(), Lit::Str(v)) => {
+      let v = Box::<str>::from(v);
+      cmp!(p, str_from_db(p.add(l_off)), v.as_ref())
+    }
+    (varchar!(), Lit::Str(v)) => {
+      let v = Box::<str>::from(v);
+      cmp!(p, db.varchar(p.add(l_off)), v.as_ref())
+    }
+    _ => Err(ColLitMismatch { ty: l.ty, val: r })
+  }
+}
+
+// the box in `within(x, y, x1, y1, x2, y2)`, the corners can be given in any order, `ty` is the type of x
+pub fn within_rect<'a>(ty: ColTy, lits: &[CLit<'a>; 4]) -> Result<'a, Rect> {
+  let mut v = [0.0; 4];
+  for (v, &lit) in v.iter_mut().zip(lits) {
+    *v = match lit.lit() { Lit::Number(x) => x as f32, _ => return Err(ColLitMismatch { ty, val: lit }) };
   }
+  Ok(Rect { min_x: v[0].min(v[2]), min_y: v[1].min(v[3]), max_x: v[0].max(v[2]), max_y: v[1].max(v[3]) })
 }
 
-pub unsafe fn cross_predicate<'a, 'b>(db: &'a Db, op: CmpOp, col: (&ColInfo, &ColInfo), tp: (&TablePage, &TablePage))
+// `db` is a pair because the 2 tables may come from different databases (one of them is attached)
+pub unsafe fn cross_predicate<'a, 'b>(db: (&'a Db, &'a Db), op: CmpOp, col: (&ColInfo, &ColInfo), tp: (&TablePage, &TablePage))
                                       -> Result<'b, Box<dyn Fn((*const u8, *const u8)) -> bool + 'a>> {
   let (l, r) = col;
+  let (db_l, db_r) = db;
   let (l_id, r_id) = (l.idx(&tp.0.cols) as u16, r.idx(&tp.1.cols) as u16);
   let (l_off, r_off) = (l.off, r.off);
   macro_rules! cmp {
@@ -100,25 +260,78 @@ pub unsafe fn cross_predicate<'a, 'b>(db: &'a Db, op: CmpOp, col: (&ColInfo, &Co
     (float!(), int!()) => handle_op!(cmp, op, p, *(p.0.add(l_off as _) as *const f32), *(p.1.add(r_off as _) as *const i32) as f32),
     (date!(), date!()) => handle_op!(cmp, op, p, *(p.0.add(l_off as _) as *const NaiveDate), *(p.1.add(r_off as _) as *const NaiveDate)),
     (char!(), char!()) => handle_op!(cmp, op, p, str_from_db(p.0.add(l_off as _)), str_from_db(p.1.add(r_off as _))),
-    (char!(), varchar!()) => handle_op!(cmp, op, p, str_from_db(p.0.add(l_off as _)), db.varchar(p.1.add(r_off as _))),
-    (varchar!(), char!()) => handle_op!(cmp, op, p, db.varchar(p.0.add(l_off as _)), str_from_db(p.1.add(r_off as _))),
-    (varchar!(), varchar!()) => handle_op!(cmp, op, p, db.varchar(p.0.add(l_off as _)), db.varchar(p.1.add(r_off as _))),
+    (char!(), varchar!()) => handle_op!(cmp, op, p, str_from_db(p.0.add(l_off as _)), db_r.varchar(p.1.add(r_off as _))),
+    (varchar!(), char!()) => handle_op!(cmp, op, p, db_l.varchar(p.0.add(l_off as _)), str_from_db(p.1.add(r_off as _))),
+    (varchar!(), varchar!()) => handle_op!(cmp, op, p, db_l.varchar(p.0.add(l_off as _)), db_r.varchar(p.1.add(r_off as _))),
     (l, r) => return Err(ColMismatch { l, r })
   }
 }
 
-pub unsafe fn one_where<'a, 'b>(db: &'a Db, where_: &[Cond<'b>], tp: &TablePage) -> Result<'b, impl Fn(*const u8) -> bool + 'a> {
+pub(crate) unsafe fn one_where<'a, 'b: 'a>(db: &'a Db, where_: &[Cond<'b>], tp: &TablePage, err: &SubqueryErr<'b>) -> Result<'b, impl Fn(*const u8) -> bool + 'a> {
   let mut preds = Vec::with_capacity(where_.len());
   for cond in where_ {
     let (l, r) = (cond.lhs_col(), cond.rhs_col_op().map(|x| x.0));
-    if let Some(t) = l.table { if t != tp.name() { return Err(NoSuchTable(t)); } }
-    if let Some(&ColRef { table: Some(t), .. }) = r { if t != tp.name() { return Err(NoSuchTable(t)); } }
+    // the table is in the database in use, so a col qualified by an attached database is not in it
+    if let Some(t) = l.table { if l.db.is_some() || !db.name_eq(t, tp.name()) { return Err(NoSuchTable(t)); } }
+    if let Some(&ColRef { db, table: Some(t), .. }) = r { if db.is_some() || t != tp.name() { return Err(NoSuchTable(t)); } }
     // table name is checked before, col name & type & value format/size all checked in one_predicate
-    preds.push(one_predicate(db, cond, tp)?);
+    preds.push(one_predicate(db, cond, tp, err)?.local());
   }
   Ok(and(preds))
 }
 
 pub fn and<'a, T: Copy + 'a>(ps: Vec<Box<dyn Fn(T) -> bool + 'a>>) -> impl Fn(T) -> bool + 'a {
   move |t| ps.iter().all(|p| p(t))
+}
+
+#[derive(Copy, Clone)]
+enum BatchLit { Bool(bool), Int(i32), Float(f32) }
+
+// a comparison between a bool / int / float col and a non-null literal, evaluated column-at-a-time over all slots of a data page
+// the loop is branch-free and writes a bitset like `DataPage::used`, so the compiler can vectorize it with SIMD where available
+#[derive(Copy, Clone)]
+pub(crate) struct BatchPred { id: u32, off: usize, op: CmpOp, lit: BatchLit }
+
+// the conditions in `where_` that can be evaluated as `BatchPred`, others (and mismatched types, which `one_predicate` reports) are skipped
+// they are only a prefilter, the slots passing them are still checked by the full predicate
+pub(crate) unsafe fn batch_predicates<'a>(db: &Db, tp: &TablePage, where_: &[impl Borrow<Cond<'a>>]) -> Vec<BatchPred> {
+  where_.iter().filter_map(|cond| match *cond.borrow() {
+    Cond::Cmp(op, l, Atom::Lit(r)) => {
+      let ci = tp.pr().get_ci(l.col, db.ignore_case).ok()?;
+      let lit = match (ci.ty, r.lit()) {
+        (bool!(), Lit::Bool(v)) => BatchLit::Bool(v),
+        (int!(), Lit::Number(v)) => BatchLit::Int(v as i32),
+        (float!(), Lit::Number(v)) => BatchLit::Float(v as f32),
+        _ => return None,
+      };
+      Some(BatchPred { id: ci.idx(&tp.cols), off: ci.off as usize, op, lit })
+    }
+    _ => None,
+  }).collect()
+}
+
+impl BatchPred {
+  // clear the bits in `mask` of the slots not satisfying it, `data` points to slot 0, and there are `cap` slots of `size` bytes
+  pub(crate) unsafe fn eval(&self, data: *const u8, size: usize, cap: usize, mask: &mut [u32]) {
+    let (id, off) = (self.id, self.off);
+    macro_rules! batch {
+      ($op: tt, $p: ident, $l: expr, $r: expr) => {
+        for (w, mask) in mask.iter_mut().enumerate().take((cap + 31) / 32) {
+          if *mask == 0 { continue; }
+          let mut bits = 0;
+          for j in 0..(cap - w * 32).min(32) {
+            let $p = data.add((w * 32 + j) * size);
+            // a null slot still has (meaningless) bytes for the value, reading them avoids a branch
+            bits |= ((!is_null($p, id) & ($l $op $r)) as u32) << j;
+          }
+          *mask &= bits;
+        }
+      };
+    }
+    match self.lit {
+      BatchLit::Bool(v) => handle_op!(batch, self.op, p, *(p.add(off) as *const bool), v),
+      BatchLit::Int(v) => handle_op!(batch, self.op, p, *(p.add(off) as *const i32), v),
+      BatchLit::Float(v) => handle_op!(batch, self.op, p, *(p.add(off) as *const f32), v),
+    }
+  }
 }
\ No newline at end of file
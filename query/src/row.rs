@@ -0,0 +1,96 @@
+use chrono::NaiveDate;
+
+use common::{*, BareTy::*, AggOp::*};
+use crate::select::{Col, SelectResult};
+
+impl Col<'_> {
+  // the name in the header of result, like "a", "max(a)" or "count(*)"
+  pub fn name(&self) -> String {
+    match (self.op, self.ci) {
+      (Some(op), Some((_, ci))) => format!("{}({})", op.name(), ci.name()),
+      (None, Some((_, ci))) => ci.name().to_owned(),
+      _ => "count(*)".to_owned(),
+    }
+  }
+
+  // the type of values in this col, avg, sum and the statistical ones are always float, count and approx_count_distinct are always int,
+  // group_concat is always varchar
+  pub fn ty(&self) -> ColTy {
+    match (self.op, self.ci) {
+      (Some(Avg), _) | (Some(Sum), _) | (Some(Stddev), _) | (Some(Variance), _) | (Some(Median), _) | (Some(PercentileCont), _) => ColTy::FixTy(FixTy { ty: Float, size: 0 }),
+      (Some(GroupConcat), _) => ColTy::Varchar(!0),
+      (Some(Count), _) | (Some(CountAll), _) | (Some(ApproxCountDistinct), _) | (_, None) => ColTy::FixTy(FixTy { ty: Int, size: 0 }),
+      (_, Some((_, ci))) => ci.ty,
+    }
+  }
+}
+
+// a value that can be read from a `Lit`, None if the `Lit` is not of this type
+// null is not any type except `Option<T>`, which reads null as `Some(None)`
+pub trait FromLit<'b>: Sized {
+  fn from_lit(lit: Lit<'b>) -> Option<Self>;
+}
+
+impl<'b> FromLit<'b> for bool {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { if let Lit::Bool(x) = lit { Some(x) } else { None } }
+}
+
+impl<'b> FromLit<'b> for f64 {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { if let Lit::Number(x) = lit { Some(x) } else { None } }
+}
+
+impl<'b> FromLit<'b> for f32 {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { f64::from_lit(lit).map(|x| x as f32) }
+}
+
+// only integral numbers are int, so a float col or avg can't be read as int unless its value is integral
+impl<'b> FromLit<'b> for i32 {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { f64::from_lit(lit).filter(|x| x.fract() == 0.0).map(|x| x as i32) }
+}
+
+impl<'b> FromLit<'b> for NaiveDate {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { if let Lit::Date(x) = lit { Some(x) } else { None } }
+}
+
+impl<'b> FromLit<'b> for &'b str {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { if let Lit::Str(x) = lit { Some(x) } else { None } }
+}
+
+impl<'b> FromLit<'b> for String {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { <&'b str>::from_lit(lit).map(|x| x.to_owned()) }
+}
+
+impl<'b, T: FromLit<'b>> FromLit<'b> for Option<T> {
+  fn from_lit(lit: Lit<'b>) -> Option<Self> { if let Lit::Null = lit { Some(None) } else { T::from_lit(lit).map(Some) } }
+}
+
+// a row in the result of select, `data[i]` is the value of `cols[i]`
+#[derive(Copy, Clone)]
+pub struct Row<'r, 'b> {
+  pub cols: &'r [Col<'b>],
+  pub data: &'r [CLit<'b>],
+}
+
+impl<'b> Row<'_, 'b> {
+  // the value of the `idx`th col, None if `idx` is out of range, or the value is not of type T (see `FromLit`)
+  pub fn get<T: FromLit<'b>>(&self, idx: usize) -> Option<T> { T::from_lit(self.data.get(idx)?.lit()) }
+
+  // like `get`, but the col is located by its name (see `Col::name`), the first one is used if there are many
+  pub fn get_by_name<T: FromLit<'b>>(&self, name: &str) -> Option<T> { self.get(self.col_idx(name)?) }
+
+  // false if `idx` is out of range
+  pub fn is_null(&self, idx: usize) -> bool { self.data.get(idx).map(|x| x.is_null()).unwrap_or(false) }
+
+  pub fn col_idx(&self, name: &str) -> Option<usize> { self.cols.iter().position(|col| col.name() == name) }
+}
+
+impl<'b> SelectResult<'b> {
+  pub fn row(&self, idx: usize) -> Option<Row<'_, 'b>> {
+    let n = self.cols.len();
+    Some(Row { cols: &self.cols, data: self.data.get(idx * n..(idx + 1) * n).filter(|_| n != 0)? })
+  }
+
+  pub fn rows(&self) -> impl Iterator<Item = Row<'_, 'b>> {
+    (0..self.row_count()).map(move |idx| Row { cols: &self.cols, data: &self.data[idx * self.cols.len()..(idx + 1) * self.cols.len()] })
+  }
+}
\ No newline at end of file
@@ -1,11 +1,12 @@
 use unchecked_unwrap::UncheckedUnwrap;
-use std::{fmt::Write, mem};
+use std::{fmt::Write, mem, cmp::Ordering};
 
 use common::{*, BareTy::*, Error::*, AggOp::*, CmpOp::*};
 use syntax::ast::*;
 use physics::*;
 use db::{Db, is_null};
-use crate::{predicate::{and, one_predicate, cross_predicate}, filter::filter};
+use index::{fulltext_weights, fulltext_score, text};
+use crate::{predicate::{and, one_predicate, cross_predicate, Pred}, subquery::SubqueryErr, filter::{index_range, index_scan, fulltext_cond, cmp, IndexRange}, estimate::filtered_rows, sort::{sort_rows, top_rows}, parallel::{par_filter, par_aggregate}, hll::Hll};
 use chrono::NaiveDate;
 use ordslice::Ext;
 
@@ -14,6 +15,37 @@ pub struct Col<'a> {
   // if op == Some(CountAll), `ci` is None, otherwise `ci` will always be Some
   pub op: Option<AggOp>,
   pub ci: Option<(u32, &'a ColInfo)>,
+  // the checked `ast::AggArg` of `op`
+  pub arg: Option<ColArg<'a>>,
+}
+
+#[derive(Copy, Clone)]
+pub enum ColArg<'a> {
+  // `order` is (table idx, col id, col, desc), like the result of `SelectCtx::order`
+  Concat { sep: &'a str, order: Option<(usize, u32, &'a ColInfo, bool)> },
+  // the fraction of `PercentileCont` (or `Median`) in asc order
+  Percentile(f64),
+}
+
+// `vals` are the not null values of a Int or Float col, for `Stddev`, `Variance`, `Median` and `PercentileCont`
+fn stat<'a>(op: AggOp, arg: Option<ColArg>, mut vals: Vec<f64>) -> CLit<'a> {
+  let n = vals.len() as f64;
+  CLit::new(match op {
+    Stddev | Variance if vals.len() < 2 => Lit::Null,
+    Stddev | Variance => {
+      let mean = vals.iter().sum::<f64>() / n;
+      let var = vals.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+      Lit::Number(if op == Stddev { var.sqrt() } else { var })
+    }
+    _ if vals.is_empty() => Lit::Null,
+    _ => {
+      let p = match arg { Some(ColArg::Percentile(p)) => p, _ => 0.5 };
+      vals.sort_unstable_by(|&l, &r| fcmp(l, r));
+      let pos = p * (n - 1.0);
+      let (lo, hi) = (vals[pos.floor() as usize], vals[pos.ceil() as usize]);
+      Lit::Number(lo + (hi - lo) * (pos - pos.floor()))
+    }
+  })
 }
 
 pub struct SelectResult<'a> {
@@ -23,9 +55,35 @@ pub struct SelectResult<'a> {
 }
 
 impl SelectResult<'_> {
+  // the cols of `tp` in `returning col, ...` of insert / update / delete, all cols if `cols` is None
+  pub(crate) unsafe fn returning_cols<'a, 'b>(db: &Db, tp: &'b TablePage, cols: Option<&[&'a str]>) -> Result<'a, Vec<Col<'b>>> {
+    let col = |ci_id, ci| Col { op: None, ci: Some((ci_id, ci)), arg: None };
+    match cols {
+      Some(cols) => cols.iter().map(|c| tp.pr().get_ci(c, db.ignore_case).map(|ci| col(ci.idx(&tp.cols), &*ci))).collect(),
+      None => Ok(tp.cols().iter().enumerate().map(|(ci_id, ci)| col(ci_id as u32, ci)).collect()),
+    }
+  }
+
+  // the values of `cols` in the records `data`, which are affected by insert / update
+  pub(crate) unsafe fn records<'a>(db: &'a Db, cols: Vec<Col<'a>>, data: &[*const u8]) -> SelectResult<'a> {
+    let data = data.iter().flat_map(|&d| SelectResult::record(db, &cols, d, false)).collect();
+    SelectResult { cols, data }
+  }
+
+  // the values of `cols` in the record `data`, strings are copied to `db` (see `Db::temp_str`) if `copy`, e.g. the record is going to be deleted
+  pub(crate) unsafe fn record<'a, 'c>(db: &'a Db, cols: &'c [Col<'a>], data: *const u8, copy: bool) -> impl Iterator<Item = CLit<'a>> + 'c where 'a: 'c {
+    cols.iter().map(move |col| {
+      let (ci_id, ci) = col.ci.unchecked_unwrap();
+      match db.data2lit(data, ci_id, ci) {
+        x if copy => match x.lit() { Lit::Str(s) => CLit::new(Lit::Str(db.temp_str(s.to_owned()))), _ => x },
+        x => x,
+      }
+    })
+  }
+
   // `data` is 2-d array of dimension = tbls.len() * (data.len() / tbls.len())
-  // tbls[i] <-> data[i], both belongs to a table
-  unsafe fn new<'a>(db: &Db, tbls: &[Vec<Col<'a>>], data: &[*const u8]) -> SelectResult<'a> {
+  // tbls[i] <-> data[i] <-> dbs[i], all belongs to a table
+  unsafe fn new<'a>(dbs: &[&'a Db], tbls: &[Vec<Col<'a>>], data: &[*const u8]) -> SelectResult<'a> {
     let result_num = data.len() / tbls.len();
     // if has agg, all col should have agg (checked in mk_tbls)
     let has_agg = tbls.iter().flatten().any(|col| col.op.is_some());
@@ -54,7 +112,7 @@ impl SelectResult<'_> {
             Min | Max => {
               let (ci_id, ci) = col.ci.unchecked_unwrap();
               let it = (0..result_num).filter_map(|i| {
-                let lit = db.data2lit(*data.get_unchecked(i * tbls.len() + idx), ci_id, ci);
+                let lit = dbs.get_unchecked(idx).data2lit(*data.get_unchecked(i * tbls.len() + idx), ci_id, ci);
                 if lit.is_null() { None } else { Some(lit) }
               });
               // can't use function reference directly because `cmp` is unsafe
@@ -65,6 +123,52 @@ impl SelectResult<'_> {
               !is_null(*data.get_unchecked(i * tbls.len() + idx), col.ci.unchecked_unwrap().0)
             }).count() as f64)),
             CountAll => CLit::new(Lit::Number(result_num as f64)),
+            ApproxCountDistinct => {
+              let (ci_id, ci) = col.ci.unchecked_unwrap();
+              let (db, mut hll) = (dbs.get_unchecked(idx), Hll::new());
+              for i in 0..result_num {
+                let data = *data.get_unchecked(i * tbls.len() + idx);
+                if !is_null(data, ci_id) { hll.add(db.bloom_hash(ci_id, ci.ty, data.add(ci.off as usize))); }
+              }
+              CLit::new(Lit::Number(hll.count() as f64))
+            }
+            Stddev | Variance | Median | PercentileCont => { // only accept Int, Float, checked in mk_tbls
+              let (ci_id, ci) = col.ci.unchecked_unwrap();
+              let vals = (0..result_num).filter_map(|i| {
+                let data = *data.get_unchecked(i * tbls.len() + idx);
+                if is_null(data, ci_id) { return None; }
+                let ptr = data.add(ci.off as usize);
+                Some(match ci.ty { int!() => *(ptr as *const i32) as f64, float!() => *(ptr as *const f32) as f64, _ => impossible!() })
+              }).collect();
+              stat(op, col.arg, vals)
+            }
+            GroupConcat => {
+              let (ci_id, ci) = col.ci.unchecked_unwrap();
+              let (sep, order) = match col.arg { Some(ColArg::Concat { sep, order }) => (sep, order), _ => impossible!() };
+              let db = *dbs.get_unchecked(idx);
+              let mut vals = (0..result_num).filter_map(|i| {
+                let lit = db.data2lit(*data.get_unchecked(i * tbls.len() + idx), ci_id, ci);
+                if lit.is_null() { None } else { Some((i, lit)) }
+              }).collect::<Vec<_>>();
+              if let Some((idx1, ci_id1, ci1, desc)) = order {
+                let db1 = dbs.get_unchecked(idx1);
+                let key = |i: usize| db1.data2lit(*data.get_unchecked(i * tbls.len() + idx1), ci_id1, ci1);
+                // null keys are the smallest, like in `order by`; the sort is stable, so equal keys keep the order of the result
+                vals.sort_by(|&(l, _), &(r, _)| {
+                  let (l, r) = (key(l), key(r));
+                  let ord = match (l.is_null(), r.is_null()) { (false, false) => l.cmp(r), (l, r) => r.cmp(&l) };
+                  if desc { ord.reverse() } else { ord }
+                });
+              }
+              if vals.is_empty() { CLit::new(Lit::Null) } else {
+                let mut s = String::new();
+                for (i, (_, lit)) in vals.iter().enumerate() {
+                  if i != 0 { s += sep; }
+                  match lit.lit() { Lit::Str(x) => s += x, lit => write!(s, "{:?}", lit).unchecked_unwrap() }
+                }
+                CLit::new(Lit::Str(db.temp_str(s)))
+              }
+            }
           }
         })
       }).collect()
@@ -75,7 +179,7 @@ impl SelectResult<'_> {
       for i in 0..result_num {
         let mut j = 0;
         for (idx, tbl) in tbls.iter().enumerate() {
-          let data = *data.get_unchecked(i * tbls.len() + idx);
+          let (db, data) = (dbs.get_unchecked(idx), *data.get_unchecked(i * tbls.len() + idx));
           for col in tbl {
             let (ci_id, ci) = col.ci.unchecked_unwrap();
             ret.as_mut_ptr().add(i * row + j).write(db.data2lit(data, ci_id, ci));
@@ -88,6 +192,14 @@ impl SelectResult<'_> {
     SelectResult { cols: tbls.iter().flatten().copied().collect(), data }
   }
 
+  // skip `offset` rows, then keep at most `limit` rows
+  fn limit(mut self, offset: u32, limit: Option<u32>) -> Self {
+    let row = self.cols.len();
+    self.data.drain(..(offset as usize * row).min(self.data.len()));
+    if let Some(limit) = limit { self.data.truncate(limit as usize * row); }
+    self
+  }
+
   pub fn row_count(&self) -> usize {
     self.data.len().checked_div(self.cols.len()).unwrap_or(0)
   }
@@ -95,10 +207,8 @@ impl SelectResult<'_> {
   pub fn csv(&self) -> String {
     unsafe {
       let mut csv = String::new();
-      for &Col { op, ci, .. } in &self.cols {
-        if let Some((_, ci)) = ci {
-          if let Some(op) = op { write!(csv, "{}({})", op.name(), ci.name()).unchecked_unwrap(); } else { csv += ci.name(); }
-        } else { csv += "count(*)"; }
+      for col in &self.cols {
+        csv += &col.name();
         csv.push(',');
       }
       (csv.pop(), csv.push('\n'));
@@ -127,17 +237,86 @@ impl SelectResult<'_> {
   }
 }
 
-struct SelectCtx<'a, 'b> {
-  tbls: IndexMap<&'a str, (u32, &'b TablePage)>,
+// a cross predicate `l op r` can be evaluated by sorting the records of one table and binary searching the col of the other table in them,
+// if `op` is not Ne and the cols are both fixed and have the same type (ignore size)
+pub(crate) fn sortable(op: CmpOp, ci_l: &ColInfo, ci_r: &ColInfo) -> bool {
+  op != Ne && !ci_l.ty.is_varchar() && !ci_r.ty.is_varchar() && ci_l.ty.fix_ty().ty == ci_r.ty.fix_ty().ty
+}
+
+// `rs.0` and `rs.1` are records of table 0 and table 1, sorted by the not-null cols of type `ty` at `off.0` and `off.1`,
+// return all pairs of records with equal cols that satisfy `pred`, in the order of the cols
+unsafe fn merge_rows(rs: (&[*const u8], &[*const u8]), ty: BareTy, off: (usize, usize), pred: impl Fn((*const u8, *const u8)) -> bool) -> Vec<*const u8> {
+  let (ls, rs) = rs;
+  let (mut ret, mut i, mut j) = (vec![], 0, 0);
+  while i < ls.len() && j < rs.len() {
+    let (l, r) = (ls.get_unchecked(i).add(off.0), rs.get_unchecked(j).add(off.1));
+    match cmp(ty, l, r) {
+      Ordering::Less => i += 1,
+      Ordering::Greater => j += 1,
+      Ordering::Equal => { // combine the groups with this key
+        let i1 = i + ls.get_unchecked(i..).iter().take_while(|&&x| cmp(ty, x.add(off.0), l) == Ordering::Equal).count();
+        let j1 = j + rs.get_unchecked(j..).iter().take_while(|&&x| cmp(ty, x.add(off.1), r) == Ordering::Equal).count();
+        for &x in ls.get_unchecked(i..i1) {
+          for &y in rs.get_unchecked(j..j1) {
+            if pred((y, x)) { ret.extend_from_slice(&[x, y]); }
+          }
+        }
+        (i = i1, j = j1);
+      }
+    }
+  }
+  ret
+}
+
+pub(crate) struct SelectCtx<'a, 'b> {
+  // keyed by the alias of the attached database (None for the database in use) and the table name, so tables with the same name
+  // in different databases can be selected together; the Db is where the table locates
+  pub tbls: IndexMap<(Option<&'a str>, &'a str), (&'b Db, u32, &'b TablePage)>,
   cols: HashMap<&'a str, Option<(&'b TablePage, &'b ColInfo, usize)>>,
+  // the percent in `tablesample` of each table
+  samples: Vec<Option<f32>>,
+  // `Db::ignore_case` of the database in use
+  ignore_case: bool,
 }
 
 impl<'a, 'b> SelectCtx<'a, 'b> {
-  unsafe fn one_where(&self, cr: &ColRef<'a>) -> Result<'a, (&'b TablePage, &'b ColInfo, usize)> {
+  pub(crate) unsafe fn new(tables: &[TableRef<'a>], db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db>) -> Result<'a, Self> {
+    let mut tbls = IndexMap::default();
+    let mut cols = HashMap::new();
+    for (idx, &TableRef { db: alias, table: t, sample }) in tables.iter().enumerate() {
+      if let Some(p) = sample { if !(0.0..=100.0).contains(&p) { return Err(InvalidSample(p)); } }
+      let db = if let Some(alias) = alias { attached(alias).ok_or(NoSuchAttach(alias))? } else { db };
+      let (tp_id, tp) = db.pr().get_tp(t)?;
+      if tbls.keys().any(|&(alias1, t1)| alias1 == alias && db.name_eq(t1, t)) { return Err(DupTable(t)); }
+      tbls.insert((alias, t), (db, tp_id, &*tp.p()));
+      for ci in tp.cols() {
+        // if it exist, make it None; if it doesn't exist, insert it
+        cols.entry(ci.name()).and_modify(|x| *x = None).or_insert(Some((&*tp.p(), ci, idx)));
+      }
+    }
+    Ok(SelectCtx { tbls, cols, samples: tables.iter().map(|t| t.sample).collect(), ignore_case: db.ignore_case })
+  }
+
+  // a sampled table is always scanned by `par_filter` on the sampled pages, no index is used
+  pub(crate) fn sample(&self, idx: usize) -> Option<f32> { unsafe { *self.samples.get_unchecked(idx) } }
+
+  pub(crate) unsafe fn one_where(&self, cr: &ColRef<'a>) -> Result<'a, (&'b TablePage, &'b ColInfo, usize)> {
     if let Some(t) = cr.table {
-      if let Some((tbl_idx_l, _, &tp)) = self.tbls.get_full(t) {
-        Ok((tp.1.pr(), tp.1.pr().get_ci(cr.col)?, tbl_idx_l))
-      } else { Err(NoSuchTable(t)) }
+      let found = self.tbls.iter().enumerate().filter(|&(_, (&(alias1, t1), _))| cr.db.map_or(true, |a| alias1 == Some(a)) && name_eq(t1, t, self.ignore_case)).collect::<Vec<_>>();
+      // `t.col` without the database prefers `t` in the database in use, otherwise `t` must be in only one database
+      let one = if found.len() == 1 { found.first() } else { found.iter().find(|&&(_, (&(alias1, _), _))| alias1.is_none()) };
+      match one {
+        Some(&(tbl_idx_l, (_, &(_, _, tp)))) => Ok((tp.pr(), tp.pr().get_ci(cr.col, self.ignore_case)?, tbl_idx_l)),
+        None if found.is_empty() => Err(NoSuchTable(t)),
+        None => Err(AmbiguousTable(t)),
+      }
+    } else if self.ignore_case { // `cols` is keyed by the exact names
+      let mut found = self.tbls.values().enumerate().filter_map(|(idx, &(_, _, tp))| Some((tp, &*tp.pr().get_ci(cr.col, self.ignore_case).ok()?, idx)));
+      match (found.next(), found.next()) {
+        (Some(x), None) => Ok(x),
+        (Some(_), Some(_)) => Err(AmbiguousCol(cr.col)),
+        _ => Err(NoSuchCol(cr.col)),
+      }
     } else {
       match self.cols.get(cr.col) {
         Some(&Some((tp, ci, tbl_idx_l))) => Ok((tp.pr(), ci.pr(), tbl_idx_l)),
@@ -147,65 +326,265 @@ impl<'a, 'b> SelectCtx<'a, 'b> {
     }
   }
 
+  pub(crate) unsafe fn db(&self, idx: usize) -> &'b Db { self.tbls.get_index(idx).unchecked_unwrap().1 .0 }
+
+  // the name of the table `idx`, qualified by its database only if another selected table has the same name
+  pub(crate) unsafe fn name(&self, idx: usize) -> String {
+    let &(alias, t) = self.tbls.get_index(idx).unchecked_unwrap().0;
+    match alias {
+      Some(alias) if self.tbls.keys().filter(|&&(_, t1)| name_eq(t1, t, self.ignore_case)).count() > 1 => format!("{}.{}", alias, t),
+      _ => t.to_owned(),
+    }
+  }
+
+  // if a single table select only needs one indexed col (in result and in `where_`), and all conditions can be expressed by a range on it,
+  // it can be answered only with the index keys, without visiting the records; return the range to scan in this case
+  // `order` is the col id in `order`, the result is in its order only if it is the same col
+  pub(crate) unsafe fn index_only(&self, cols: &[Col], where_: &[&Cond<'a>], order: Option<u32>) -> Option<IndexRange<'a>> {
+    if self.tbls.len() != 1 || self.sample(0).is_some() { return None; }
+    let (_, &(db, _, tp)) = self.tbls.get_index(0).unchecked_unwrap();
+    let range = if where_.is_empty() { None } else { Some(index_range(db.pr(), where_, tp).filter(|r| r.exact)?) };
+    let ci_id = match range.as_ref().map(|r| r.ci_id).or_else(|| cols.iter().find_map(|c| c.ci.map(|(ci_id, _)| ci_id))) {
+      Some(ci_id) => ci_id,
+      // only count(*) without where, any indexed not null col is ok
+      None => tp.cols().iter().position(|ci| ci.index != !0 && ci.flags.intersects(ColFlags::NOTNULL1))? as u32,
+    };
+    let ci = tp.cols.get_unchecked(ci_id as usize);
+    // keys in a prefix index or an expression index are not col values
+    if ci.index == !0 || !ci.idx_exact() || order.map(|o| o != ci_id).unwrap_or(false) || cols.iter().any(|c| c.ci.map(|(ci_id1, _)| ci_id1 != ci_id).unwrap_or(false)) {
+      return None;
+    }
+    // null is not put into index, if null records are needed in result (by projection or count(*)), they must be excluded by the col or by `where_`
+    if range.is_none() && !ci.flags.intersects(ColFlags::NOTNULL1) && cols.iter().any(|c| c.op.is_none() || c.op == Some(CountAll)) {
+      return None;
+    }
+    // `group_concat` is only computed in `SelectResult::new`
+    if cols.iter().any(|c| c.op == Some(GroupConcat)) { return None; }
+    Some(range.unwrap_or(IndexRange { ci_id, lo: None, hi: None, exact: true }))
+  }
+
+  // if a single table select only has count(*) and no `where_`, it is the record count maintained in the table page by insert / delete
+  // return it in this case, so that no record or index key is visited
+  pub(crate) unsafe fn count_only(&self, cols: &[Col], where_: &[&Cond<'a>]) -> Option<u32> {
+    if self.tbls.len() != 1 || self.sample(0).is_some() || !where_.is_empty() || cols.iter().any(|c| c.op != Some(CountAll)) { return None; }
+    Some(self.tbls.get_index(0).unchecked_unwrap().1 .2.count)
+  }
+
+  // `range` is returned by `index_only`, `limit` is not applied to the result, it is only for stopping scanning early
+  unsafe fn select_index_only(&self, cols: Vec<Col<'b>>, range: IndexRange, desc: bool, limit: Option<u32>) -> SelectResult<'b> {
+    let (_, &(db, tp_id, tp)) = self.tbls.get_index(0).unchecked_unwrap();
+    let ci = tp.cols.get_unchecked(range.ci_id as usize);
+    let has_agg = cols.iter().any(|col| col.op.is_some());
+    let limit = if has_agg { !0 } else { limit.map(|l| l as usize).unwrap_or(!0) };
+    let mut keys = vec![];
+    if !range.is_empty(ci.ty.fix_ty().ty) {
+      if has_agg && cols.iter().all(|col| col.op == Some(Min) || col.op == Some(Max)) {
+        // only the first key (for min) and the last key (for max) in the range are needed, each is the first leaf entry visited from its end
+        // so `keys.first()` and `keys.last()` below are still the min and the max
+        for &(op, rev) in &[(Min, false), (Max, true)] {
+          if cols.iter().any(|col| col.op == Some(op)) {
+            index_scan(db.pr(), tp_id, &range, rev, |key, _| Ok((keys.push(key), false).1)).unchecked_unwrap();
+          }
+        }
+      } else {
+        index_scan(db.pr(), tp_id, &range, desc && !has_agg, |key, _| Ok((keys.push(key), keys.len() < limit).1)).unchecked_unwrap();
+      }
+    }
+    // keys are sorted and not null, so aggregation is simpler than `SelectResult::new`
+    let data = if has_agg {
+      cols.iter().map(|col| {
+        let op = col.op.unchecked_unwrap();
+        match op {
+          Avg | Sum => {
+            let sum = keys.iter().map(|&k| match ci.ty { int!() => *(k as *const i32) as f64, float!() => *(k as *const f32) as f64, _ => impossible!() }).sum::<f64>();
+            CLit::new(if keys.is_empty() { Lit::Null } else { Lit::Number(if op == Avg { sum / keys.len() as f64 } else { sum }) })
+          }
+          Min | Max => if op == Min { keys.first() } else { keys.last() }.map(|&k| db.ptr2lit(k, ci.ty)).unwrap_or(CLit::new(Lit::Null)),
+          Count | CountAll => CLit::new(Lit::Number(keys.len() as f64)),
+          ApproxCountDistinct => {
+            let mut hll = Hll::new();
+            for &k in &keys { hll.add(db.bloom_hash(range.ci_id, ci.ty, k)); }
+            CLit::new(Lit::Number(hll.count() as f64))
+          }
+          Stddev | Variance | Median | PercentileCont => {
+            stat(op, col.arg, keys.iter().map(|&k| match ci.ty { int!() => *(k as *const i32) as f64, float!() => *(k as *const f32) as f64, _ => impossible!() }).collect())
+          }
+          GroupConcat => impossible!(), // rejected by `index_only`
+        }
+      }).collect()
+    } else {
+      keys.iter().flat_map(|&k| cols.iter().map(move |_| db.ptr2lit(k, ci.ty))).collect()
+    };
+    SelectResult { cols, data }
+  }
+
+  // a single table select ordered by an indexed col can scan the index in order instead of sorting all results
+  // null is not put into index, so null records must be excluded by the col or by `where_`
+  pub(crate) unsafe fn index_order(&self, ci_id: u32, where_: &[&Cond<'a>]) -> Option<IndexRange<'a>> {
+    if self.tbls.len() != 1 || self.sample(0).is_some() { return None; }
+    let (_, &(db, _, tp)) = self.tbls.get_index(0).unchecked_unwrap();
+    let ci = tp.cols.get_unchecked(ci_id as usize);
+    // a prefix index or an expression index doesn't give the order of col values
+    if ci.index == !0 || !ci.idx_exact() { return None; }
+    match index_range(db.pr(), where_, tp) {
+      Some(range) if range.ci_id == ci_id => Some(range),
+      _ if ci.flags.intersects(ColFlags::NOTNULL1) => Some(IndexRange { ci_id, lo: None, hi: None, exact: where_.is_empty() }),
+      _ => None,
+    }
+  }
+
+  // return (table idx, col id, col info, desc)
+  pub(crate) unsafe fn order(&self, order: &Option<(ColRef<'a>, bool)>) -> Result<'a, Option<(usize, u32, &'b ColInfo, bool)>> {
+    Ok(match order {
+      Some((col, desc)) => {
+        let (tp, ci, idx) = self.one_where(col)?;
+        Some((idx, ci.idx(&tp.cols), ci, *desc))
+      }
+      None => None,
+    })
+  }
+
+  // the order to join tables in, a permutation of table idx; `linked(x, y)` is true if there is a `sortable` predicate on tables x and y
+  // with more than two tables, start from the table with the fewest estimated rows (after its own conditions), then each time join
+  // the smallest table linked to a joined one, so it can be joined by binary search on its sorted rows, and only join an unlinked table
+  // (a cartesian product) if there is no linked one; with two or fewer tables, the order of the from list is kept
+  pub(crate) unsafe fn join_order(&self, one_wheres: &[Vec<&Cond<'a>>], linked: impl Fn(usize, usize) -> bool) -> Vec<usize> {
+    let tbl_num = self.tbls.len();
+    if tbl_num <= 2 { return (0..tbl_num).collect(); }
+    let rows = self.tbls.values().zip(one_wheres).enumerate()
+      .map(|(idx, (&(db, _, tp), where_))| filtered_rows(db, tp, where_) * self.sample(idx).map(|p| p as f64 / 100.0).unwrap_or(1.0)).collect::<Vec<_>>();
+    let (mut ret, mut rest) = (Vec::with_capacity(tbl_num), (0..tbl_num).collect::<Vec<_>>());
+    while !rest.is_empty() {
+      let link = |x: usize| ret.iter().any(|&y| linked(x, y));
+      // `min_by` returns the first one on tie, so the order of the from list is kept among equal tables
+      let pos = (0..rest.len()).min_by(|&x, &y| {
+        let (x, y) = (*rest.get_unchecked(x), *rest.get_unchecked(y));
+        link(y).cmp(&link(x)).then(fcmp(*rows.get_unchecked(x), *rows.get_unchecked(y)))
+      }).unchecked_unwrap();
+      ret.push(rest.remove(pos));
+    }
+    ret
+  }
+
+  // conditions implied by `where_`: if `l = r` joins two tables, a comparison of `l` with a literal also applies to `r`, and vice versa
+  // they are pushed into the scan of the other table, so it is also filtered (maybe by an index) before joining
+  // only conditions in `where_` are used, so a chain like `a = b and b = c and a = 1` only implies `b = 1`
+  pub(crate) unsafe fn implied(&self, where_: &[Cond<'a>]) -> Result<'a, Vec<Cond<'a>>> {
+    let mut ret = vec![];
+    for cond in where_ {
+      if let Cond::Cmp(Eq, l, Atom::ColRef(r)) = *cond {
+        let ((_, ci_l, idx_l), (_, ci_r, idx_r)) = (self.one_where(&l)?, self.one_where(&r)?);
+        if idx_l == idx_r || !sortable(Eq, ci_l, ci_r) { continue; }
+        for cond1 in where_ {
+          if let Cond::Cmp(op, col, Atom::Lit(x)) = *cond1 {
+            let (_, ci, idx) = self.one_where(&col)?;
+            if (idx, ci as *const ColInfo) == (idx_l, ci_l as *const ColInfo) { ret.push(Cond::Cmp(op, r, Atom::Lit(x))); }
+            if (idx, ci as *const ColInfo) == (idx_r, ci_r as *const ColInfo) { ret.push(Cond::Cmp(op, l, Atom::Lit(x))); }
+          }
+        }
+      }
+    }
+    Ok(ret)
+  }
+
+  // two tables joined by `=` on cols that both have an exact index can be joined by merging (see `merge_rows`): scan both indexes in order,
+  // and combine records with equal keys; it needs neither sorting nor binary searching, and the result is ordered by the join key
+  // `cross_col` is the `sortable` predicate on (table 1, table 0), return the col id of the join col in table 0 and table 1
+  pub(crate) unsafe fn merge_join(&self, cross_col: Option<(CmpOp, &ColInfo, &ColInfo)>) -> Option<(u32, u32)> {
+    if self.tbls.len() != 2 || self.samples.iter().any(|s| s.is_some()) { return None; }
+    let (op, ci1, ci0) = cross_col?;
+    if op != Eq || [ci0, ci1].iter().any(|ci| ci.index == !0 || !ci.idx_exact()) { return None; }
+    let (tp0, tp1) = (self.tbls.get_index(0).unchecked_unwrap().1 .2, self.tbls.get_index(1).unchecked_unwrap().1 .2);
+    Some((ci0.idx(&tp0.cols), ci1.idx(&tp1.cols)))
+  }
+
   // the validity of AggOp is checked here
-  unsafe fn mk_tbls(&self, ops: &Option<Vec<Agg<'a>>>) -> Result<'a, Vec<Vec<Col<'b>>>> {
+  pub(crate) unsafe fn mk_tbls(&self, ops: &Option<Vec<Agg<'a>>>) -> Result<'a, Vec<Vec<Col<'b>>>> {
     if let Some(ops) = ops {
       if ops.iter().any(|agg| agg.op.is_some()) != ops.iter().all(|agg| agg.op.is_some()) {
         return Err(MixedSelect);
       }
       let mut ret = vec![vec![]; self.tbls.len()];
-      for &Agg { op, col } in ops {
+      for &Agg { op, col, ref arg } in ops {
         if op == Some(CountAll) {
           // I admit it is quite ugly...
-          ret.get_unchecked_mut(0).push(Col { op, ci: None });
+          ret.get_unchecked_mut(0).push(Col { op, ci: None, arg: None });
         } else {
           let (tp, ci, idx) = self.one_where(&col)?;
           if let Some(op) = op {
-            if op == Avg || op == Sum {
+            if op != Min && op != Max && op != Count && op != ApproxCountDistinct && op != GroupConcat {
               match ci.ty { int!() | float!() => {} col => return Err(InvalidAgg { col, op }), }
             }
           }
-          ret.get_unchecked_mut(idx).push(Col { op, ci: Some((ci.idx(&tp.cols), ci)) });
+          let arg = match arg {
+            // the separator is copied into the db, because the result doesn't borrow the sql text
+            Some(AggArg::Concat { sep, order }) => Some(ColArg::Concat { sep: self.db(idx).temp_str((*sep).to_owned()), order: self.order(order)? }),
+            &Some(AggArg::Percentile { p, desc }) => {
+              if !(0.0..=1.0).contains(&p) { return Err(InvalidPercentile(p)); }
+              Some(ColArg::Percentile(if desc { 1.0 - p as f64 } else { p as f64 }))
+            }
+            None => None,
+          };
+          ret.get_unchecked_mut(idx).push(Col { op, ci: Some((ci.idx(&tp.cols), ci)), arg });
         }
       }
       Ok(ret)
     } else { // select *
-      Ok(self.tbls.iter().map(|(_, &(_, tp))| {
-        tp.cols().iter().enumerate().map(|(ci_id, ci)| Col { op: None, ci: Some((ci_id as u32, ci)) }).collect()
+      Ok(self.tbls.iter().map(|(_, &(_, _, tp))| {
+        tp.cols().iter().enumerate().map(|(ci_id, ci)| Col { op: None, ci: Some((ci_id as u32, ci)), arg: None }).collect()
       }).collect())
     }
   }
 }
 
 pub fn select<'a, 'b>(s: &Select<'a>, db: &'b Db) -> Result<'a, SelectResult<'b>> {
+  select_attached(s, db, |_| None)
+}
+
+// all cols of the record `rid` of `table`, e.g. the one just inserted (see `Db::last_insert`)
+pub fn select_rid<'a, 'b>(table: &'a str, rid: Rid, db: &'b Db) -> Result<'a, SelectResult<'b>> {
+  unsafe {
+    let tp = db.pr().get_tp(table)?.1;
+    // `rid` comes from the user, a page that is not a data page is rejected at once, otherwise it must be in the data pages of `table`
+    let found = db.page::<DataPage>(rid.page()).is_ok() && rid.slot() < tp.cap as u32 && {
+      let mut page = tp.first;
+      while page != !0 && page != rid.page() { page = db.page::<DataPage>(page)?.next; }
+      page != !0 && bsget(db.page::<DataPage>(page)?.used.as_ptr(), rid.slot() as usize)
+    };
+    if !found { return Err(NoSuchRecord { page: rid.page(), slot: rid.slot() }); }
+    let cols = SelectResult::returning_cols(db, tp, None)?;
+    Ok(SelectResult::records(db, cols, &[db.read_data_slot(tp, rid)]))
+  }
+}
+
+// `attached` maps the alias of an attached database to the database, table like `alias.table` is searched in it
+pub fn select_attached<'a, 'b>(s: &Select<'a>, db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db>) -> Result<'a, SelectResult<'b>> {
+  let err = SubqueryErr::default();
+  let res = select_with(s, db, attached, &err)?;
+  (err.check()?, Ok(res)).1
+}
+
+fn select_with<'a, 'b>(s: &Select<'a>, db: &'b Db, attached: impl Fn(&str) -> Option<&'b Db>, err: &SubqueryErr<'a>) -> Result<'a, SelectResult<'b>> {
   unsafe {
-    let db = db.pr();
     let tbl_num = s.tables.len();
     if tbl_num == 0 { return Ok(SelectResult { cols: vec![], data: vec![] }); }
     macro_rules! at { ($arr: expr, $x: expr, $y: expr) => { $arr.get_unchecked_mut($x * tbl_num + $y) }; }
-    let mut tbls = IndexMap::default();
-    let mut cols = HashMap::new();
-    for (idx, &t) in s.tables.iter().enumerate() {
-      let (tp_id, tp) = db.get_tp(t)?;
-      if tbls.insert(t, (tp_id, &*tp.p())).is_some() { return Err(DupTable(t)); }
-      for ci in tp.cols() {
-        // if it exist, make it None; if it doesn't exist, insert it
-        cols.entry(ci.name()).and_modify(|x| *x = None).or_insert(Some((&*tp.p(), ci, idx)));
-      }
-    }
-    let ctx = SelectCtx { tbls, cols };
+    let ctx = SelectCtx::new(&s.tables, db, attached)?;
+    // the settings of the session are on the main database, they also apply to the tables of attached ones
+    let (threads, budget) = (db.scan_threads, db.sort_budget);
 
     let mut one_preds = Vec::with_capacity(tbl_num);
     // `cross_preds` is 2-d array, dim = tbl_num * tbl_num
     // cross_preds[x][y] means a predicate that accept (x, y), only use lower parts (x > y)
     let mut cross_preds = Vec::with_capacity(tbl_num * tbl_num);
     // `cross_cols` store the col info of `cross_preds`, for optimization use
-    // if one of the CmpOp is not Ne and their types are the same (ignore size) and are both fixed, it will be put into `cross_cols`, and we can use binary search to locate RHS
+    // if one of the predicates is `sortable`, it will be put into `cross_cols`, and we can use binary search to locate RHS
     let mut cross_cols = vec![None; tbl_num * tbl_num];
     for _ in 0..tbl_num { one_preds.push(vec![]); } // Box<Fn> is not Clone, so must use loop to push
     for _ in 0..tbl_num * tbl_num { cross_preds.push(vec![]); }
     let mut one_wheres = vec![vec![]; tbl_num];
-    for cond in &s.where_ {
+    let implied = ctx.implied(&s.where_)?;
+    for cond in s.where_.iter().chain(&implied) {
       let (l, r) = (cond.lhs_col(), cond.rhs_col_op());
       let (mut tp_l, mut ci_l, mut idx_l) = ctx.one_where(l)?;
       if let Some(((mut tp_r, mut ci_r, mut idx_r), mut op)) = {
@@ -219,23 +598,71 @@ pub fn select<'a, 'b>(s: &Select<'a>, db: &'b Db) -> Result<'a, SelectResult<'b>
           mem::swap(&mut ci_l, &mut ci_r);
           mem::swap(&mut idx_l, &mut idx_r);
         }
-        at!(cross_preds, idx_l, idx_r).push(cross_predicate(db.pr(), op, (ci_l, ci_r), (tp_l, tp_r))?);
-        if op != Ne && !ci_l.ty.is_varchar() && !ci_r.ty.is_varchar() && ci_l.ty.fix_ty().ty == ci_r.ty.fix_ty().ty {
+        at!(cross_preds, idx_l, idx_r).push(cross_predicate((ctx.db(idx_l), ctx.db(idx_r)), op, (ci_l, ci_r), (tp_l, tp_r))?);
+        if sortable(op, ci_l, ci_r) {
           at!(cross_cols, idx_l, idx_r).get_or_insert((op, ci_l, ci_r)); // store the first expr
         }
       } else { // in one table
-        one_preds.get_unchecked_mut(idx_l).push(one_predicate(db.pr(), cond, tp_l)?);
+        one_preds.get_unchecked_mut(idx_l).push(one_predicate(ctx.db(idx_l), cond, tp_l, err)?);
         one_wheres.get_unchecked_mut(idx_l).push(cond);
       }
     }
 
+    let order = ctx.order(&s.order)?;
+    // if there is aggregation, there is only one result row, so `order` is useless
+    let has_agg = s.ops.iter().flatten().any(|agg| agg.op.is_some());
+    let order = order.filter(|_| !has_agg);
+    // the rows needed before skipping `offset`
+    let rows = s.limit.map(|l| l.saturating_add(s.offset));
+    if tbl_num == 1 {
+      let cols = ctx.mk_tbls(&s.ops)?.pop().unchecked_unwrap();
+      if let Some(count) = ctx.count_only(&cols, one_wheres.get_unchecked(0)) {
+        let data = cols.iter().map(|_| CLit::new(Lit::Number(count as f64))).collect();
+        return Ok(SelectResult { cols, data }.limit(s.offset, s.limit));
+      }
+      if let Some(range) = ctx.index_only(&cols, one_wheres.get_unchecked(0), order.map(|(_, ci_id, _, _)| ci_id)) {
+        let desc = order.map(|(.., desc)| desc).unwrap_or(false);
+        return Ok(ctx.select_index_only(cols, range, desc, rows).limit(s.offset, s.limit));
+      }
+    }
+    let index_order = order.and_then(|(_, ci_id, _, desc)| Some((ctx.index_order(ci_id, one_wheres.get_unchecked(0))?, desc)));
+    let merge = ctx.merge_join(if tbl_num == 2 { *at!(cross_cols, 1, 0) } else { None });
+
+    if let (1, true) = (tbl_num, has_agg) { // aggregated by scanning threads if possible, without collecting the records
+      let (_, &(db, tp_id, _)) = ctx.tbls.get_index(0).unchecked_unwrap();
+      let cols = ctx.mk_tbls(&s.ops)?.pop().unchecked_unwrap();
+      if let Some(data) = par_aggregate(db, threads, one_wheres.get_unchecked(0), tp_id, ctx.sample(0), one_preds.get_unchecked(0), &cols) {
+        return Ok(SelectResult { cols, data }.limit(s.offset, s.limit));
+      }
+    }
     let mut cross_preds = cross_preds.into_iter().map(|p| and(p)).collect::<Vec<_>>();
     let mut one_results = vec![vec![]; tbl_num];
     for (idx, pred) in one_preds.into_iter().enumerate() { // idx in 0..tbl_num
-      let (_, &(tp_id, tp)) = ctx.tbls.get_index(idx).unchecked_unwrap();
+      let (_, &(db, tp_id, tp)) = ctx.tbls.get_index(idx).unchecked_unwrap();
       let where_ = one_wheres.get_unchecked(idx);
       let one_result = one_results.get_unchecked_mut(idx);
-      filter(db, where_, tp_id, and(pred), |x, _| {
+      if let Some((range, desc)) = &index_order { // `index_order` is Some only if tbl_num == 1, so no need to handle `cross_cols`
+        let (pred, limit) = (and(pred.into_iter().map(Pred::local).collect()), rows.map(|l| l as usize).unwrap_or(!0));
+        if !range.is_empty(tp.cols.get_unchecked(range.ci_id as usize).ty.fix_ty().ty) {
+          index_scan(db.pr(), tp_id, range, *desc, |_, rid| {
+            let x = db.read_data_slot(tp, rid);
+            if range.exact || pred(x) { one_result.push(x as *const u8); }
+            Ok(one_result.len() < limit)
+          }).unchecked_unwrap();
+        }
+        continue;
+      }
+      if let Some(merge) = merge { // scan the whole index in order, null is not in index, and is rejected by `=` anyway
+        let (range, pred) = (IndexRange { ci_id: if idx == 0 { merge.0 } else { merge.1 }, lo: None, hi: None, exact: false },
+                             and(pred.into_iter().map(Pred::local).collect()));
+        index_scan(db.pr(), tp_id, &range, false, |_, rid| {
+          let x = db.read_data_slot(tp, rid);
+          if pred(x) { one_result.push(x as *const u8); }
+          Ok(true)
+        }).unchecked_unwrap();
+        continue;
+      }
+      par_filter(db.pr(), threads, where_, tp_id, ctx.sample(idx), &pred, |x, _| {
         // remove some null data, it can optimize a little, but mainly for making later handling easier
         // if it participate in any comparison, then reject null results, so later the sort + binary search can avoid handling null
         if (0..idx).all(|idx1| at!(cross_cols, idx, idx1).map(|(_, ci, _)| !is_null(x, ci.idx(&tp.cols))).unwrap_or(true)) &&
@@ -243,65 +670,124 @@ pub fn select<'a, 'b>(s: &Select<'a>, db: &'b Db) -> Result<'a, SelectResult<'b>
           one_result.push(x as *const u8);
         }
         Ok(())
-      }, true).unchecked_unwrap();
+      }).unchecked_unwrap();
     }
 
-    let res0 = one_results.get_unchecked(0);
-    let mut final_ = Vec::<*const u8>::with_capacity(res0.len() * tbl_num);
-    final_.set_len(res0.len() * tbl_num);
-    for (i, &x) in res0.iter().enumerate() {
-      final_.as_mut_ptr().add(i * tbl_num).write(x);
-    }
+    let mut final_;
+    if let Some((ci_id0, ci_id1)) = merge {
+      let (tp0, tp1) = (ctx.tbls.get_index(0).unchecked_unwrap().1 .2, ctx.tbls.get_index(1).unchecked_unwrap().1 .2);
+      let (ci0, ci1) = (tp0.cols.get_unchecked(ci_id0 as usize), tp1.cols.get_unchecked(ci_id1 as usize));
+      final_ = merge_rows((one_results.get_unchecked(0), one_results.get_unchecked(1)), ci0.ty.fix_ty().ty,
+                          (ci0.off as usize, ci1.off as usize), &*at!(cross_preds, 1, 0));
+    } else {
+      // rows in `final_` are always indexed by table idx, only the order to fill them is changed
+      let join = ctx.join_order(&one_wheres, |x, y| cross_cols.get_unchecked(x.max(y) * tbl_num + x.min(y)).is_some());
+      let first = *join.get_unchecked(0);
+      let res0 = one_results.get_unchecked(first);
+      final_ = Vec::<*const u8>::with_capacity(res0.len() * tbl_num);
+      final_.set_len(res0.len() * tbl_num);
+      for (i, &x) in res0.iter().enumerate() {
+        final_.as_mut_ptr().add(i * tbl_num + first).write(x);
+      }
 
-    for idx_r in 1..one_results.len() {
-      let rs = one_results.get_unchecked_mut(idx_r);
-      let mut new_final_ = Vec::<*const u8>::new();
-      macro_rules! join {
-        ($old_row: expr, $range: expr) => {
-          for &r in rs.get_unchecked($range) {
-            if (0..idx_r).all(|idx_l| at!(cross_preds, idx_r, idx_l)((r, *$old_row.add(idx_l)))) {
-              let old_len = new_final_.len();
-              new_final_.reserve(tbl_num);
-              new_final_.set_len(old_len + tbl_num);
-              new_final_.as_mut_ptr().add(old_len).copy_from_nonoverlapping($old_row, tbl_num);
-              *new_final_.get_unchecked_mut(old_len + idx_r) = r;
+      for (k, &idx_r) in join.iter().enumerate().skip(1) {
+        let joined = join.get_unchecked(..k);
+        let rs = one_results.get_unchecked_mut(idx_r);
+        let mut new_final_ = Vec::<*const u8>::new();
+        macro_rules! join {
+          ($old_row: expr, $range: expr) => {
+            for &r in rs.get_unchecked($range) {
+              // `cross_preds` only use lower parts, so the larger idx goes first
+              if joined.iter().all(|&idx_l| if idx_r > idx_l {
+                at!(cross_preds, idx_r, idx_l)((r, *$old_row.add(idx_l)))
+              } else {
+                at!(cross_preds, idx_l, idx_r)((*$old_row.add(idx_l), r))
+              }) {
+                let old_len = new_final_.len();
+                new_final_.reserve(tbl_num);
+                new_final_.set_len(old_len + tbl_num);
+                new_final_.as_mut_ptr().add(old_len).copy_from_nonoverlapping($old_row, tbl_num);
+                *new_final_.get_unchecked_mut(old_len + idx_r) = r;
+              }
             }
-          }
-        };
-      }
-      if let Some((idx_l, (op, ci_r, ci_l))) = (0..idx_r).filter_map(|idx_l| at!(cross_cols,idx_r, idx_l).map(|x| (idx_l, x))).next() {
-        let (off_l, off_r) = (ci_l.off as usize, ci_r.off as usize);
-        match ci_r.ty.fix_ty().ty {
-          Bool => rs.sort_unstable_by_key(|&x| *(x.add(off_r) as *const bool)),
-          Int => rs.sort_unstable_by_key(|&x| *(x.add(off_r) as *const i32)),
-          // note that both `l` and `r` use `off_r` here, because they are both from the `rs`
-          Float => rs.sort_unstable_by(|&l, &r| fcmp(*(l.add(off_r) as *const f32), *(r.add(off_r) as *const f32))),
-          Date => rs.sort_unstable_by_key(|&x| *(x.add(off_r) as *const NaiveDate)),
-          Char => rs.sort_unstable_by_key(|&x| str_from_db(x.add(off_r))),
-        }
-        for old_idx in 0..(final_.len() / tbl_num) {
-          let old_row = final_.as_ptr().add(old_idx * tbl_num);
-          let l = (*old_row.add(idx_l)).add(off_l);
-          let rg = match ci_r.ty.fix_ty().ty {
-            Bool => rs.equal_range_by(|&r| (*(r.add(off_r) as *const bool)).cmp(&*(l as *const bool))),
-            Int => rs.equal_range_by(|&r| (*(r.add(off_r) as *const i32)).cmp(&*(l as *const i32))),
-            Float => rs.equal_range_by(|&r| fcmp(*(r.add(off_r) as *const f32), *(l as *const f32))),
-            Date => rs.equal_range_by(|&r| (*(r.add(off_r) as *const NaiveDate)).cmp(&*(l as *const NaiveDate))),
-            Char => rs.equal_range_by(|&r| str_from_db(r.add(off_r)).cmp(str_from_db(l))),
           };
-          let rg = match op {
-            Lt => 0..rg.start, Le => 0..rg.end, Ge => rg.start..rs.len(), Gt => rg.end..rs.len(), Eq => rg, Ne => impossible!(),
-          };
-          join!(old_row, rg);
         }
-      } else {
-        for old_idx in 0..(final_.len() / tbl_num) {
-          let old_row = final_.as_ptr().add(old_idx * tbl_num);
-          join!(old_row, ..);
+        // (idx_l, (op, ci_r, ci_l)) means `r op l`
+        let cross_col = joined.iter().find_map(|&idx_l| if idx_r > idx_l {
+          at!(cross_cols, idx_r, idx_l).map(|x| (idx_l, x))
+        } else {
+          at!(cross_cols, idx_l, idx_r).map(|(op, ci_l, ci_r)| (idx_l, (op.rev(), ci_r, ci_l)))
+        });
+        if let Some((idx_l, (op, ci_r, ci_l))) = cross_col {
+          let (off_l, off_r) = (ci_l.off as usize, ci_r.off as usize);
+          match ci_r.ty.fix_ty().ty {
+            Bool => rs.sort_unstable_by_key(|&x| *(x.add(off_r) as *const bool)),
+            Int => rs.sort_unstable_by_key(|&x| *(x.add(off_r) as *const i32)),
+            // note that both `l` and `r` use `off_r` here, because they are both from the `rs`
+            Float => rs.sort_unstable_by(|&l, &r| fcmp(*(l.add(off_r) as *const f32), *(r.add(off_r) as *const f32))),
+            Date => rs.sort_unstable_by_key(|&x| *(x.add(off_r) as *const NaiveDate)),
+            Char => rs.sort_unstable_by_key(|&x| str_from_db(x.add(off_r))),
+          }
+          for old_idx in 0..(final_.len() / tbl_num) {
+            let old_row = final_.as_ptr().add(old_idx * tbl_num);
+            let l = (*old_row.add(idx_l)).add(off_l);
+            let rg = match ci_r.ty.fix_ty().ty {
+              Bool => rs.equal_range_by(|&r| (*(r.add(off_r) as *const bool)).cmp(&*(l as *const bool))),
+              Int => rs.equal_range_by(|&r| (*(r.add(off_r) as *const i32)).cmp(&*(l as *const i32))),
+              Float => rs.equal_range_by(|&r| fcmp(*(r.add(off_r) as *const f32), *(l as *const f32))),
+              Date => rs.equal_range_by(|&r| (*(r.add(off_r) as *const NaiveDate)).cmp(&*(l as *const NaiveDate))),
+              Char => rs.equal_range_by(|&r| str_from_db(r.add(off_r)).cmp(str_from_db(l))),
+            };
+            let rg = match op {
+              Lt => 0..rg.start, Le => 0..rg.end, Ge => rg.start..rs.len(), Gt => rg.end..rs.len(), Eq => rg, Ne => impossible!(),
+            };
+            join!(old_row, rg);
+          }
+        } else {
+          for old_idx in 0..(final_.len() / tbl_num) {
+            let old_row = final_.as_ptr().add(old_idx * tbl_num);
+            join!(old_row, ..);
+          }
         }
+        final_ = new_final_;
+      }
+    }
+    // without `order by`, results are ranked by relevance to the first `match` (see `fulltext_score`), the most relevant first
+    if let (None, false) = (order, has_agg) {
+      let cond = ctx.tbls.values().enumerate().find_map(|(idx, &(_, _, tp))| Some((idx, fulltext_cond(db, tp, one_wheres.get_unchecked(idx))?)));
+      if let Some((idx, (ci_id, query))) = cond {
+        let (_, &(db, tp_id, tp)) = ctx.tbls.get_index(idx).unchecked_unwrap();
+        let ci = tp.cols.get_unchecked(ci_id as usize);
+        let weights = fulltext_weights(db.pr(), tp_id, ci_id, query);
+        let score = |data: *const u8| fulltext_score(text(db, ci, data.add(ci.off as usize)), &weights);
+        let mut rows = final_.chunks_exact(tbl_num).map(|r| (score(*r.get_unchecked(idx)), r)).collect::<Vec<_>>();
+        rows.sort_by(|l, r| fcmp(r.0, l.0)); // stable, so records with the same score are in the order of scanning
+        final_ = rows.into_iter().flat_map(|(_, r)| r.iter().copied()).collect();
       }
-      final_ = new_final_;
     }
-    Ok(SelectResult::new(db, &ctx.mk_tbls(&s.ops)?, &final_))
+    // the result of merge join is ordered by the join key, it can be reused if the order is on either of the join cols
+    let merge_order = match (order, merge) {
+      (Some((idx, ci_id, _, desc)), Some(merge)) if ci_id == if idx == 0 { merge.0 } else { merge.1 } => Some(desc),
+      _ => None,
+    };
+    if let Some(true) = merge_order {
+      final_ = final_.chunks_exact(tbl_num).rev().flatten().copied().collect();
+    }
+    if let (Some((idx, ci_id, ci, desc)), None, None) = (order, &index_order, merge_order) {
+      let db = ctx.db(idx);
+      let cmp = |l: &[*const u8], r: &[*const u8]| {
+        let (l, r) = (db.data2lit(*l.get_unchecked(idx), ci_id, ci), db.data2lit(*r.get_unchecked(idx), ci_id, ci));
+        let ord = match (l.is_null(), r.is_null()) { (false, false) => l.cmp(r), (l, r) => r.cmp(&l) }; // null is the smallest
+        if desc { ord.reverse() } else { ord }
+      };
+      // with a limit smaller than the number of rows, only the first rows are needed (`order` is None if there is aggregation)
+      final_ = match rows {
+        Some(limit) if (limit as usize) < final_.len() / tbl_num => top_rows(&final_, tbl_num, limit as usize, cmp),
+        _ => sort_rows(&final_, tbl_num, budget, cmp)?,
+      };
+    }
+    if let (Some(limit), false) = (rows, has_agg) { final_.truncate(limit as usize * tbl_num); }
+    let dbs = ctx.tbls.values().map(|&(db, _, _)| db).collect::<Vec<_>>();
+    Ok(SelectResult::new(&dbs, &ctx.mk_tbls(&s.ops)?, &final_).limit(s.offset, s.limit))
   }
 }
\ No newline at end of file
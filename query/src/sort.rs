@@ -0,0 +1,135 @@
+use std::{cmp::Ordering, collections::BinaryHeap, fs::{self, File}, io::{BufReader, BufWriter, Read, Write}, mem, path::PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+use common::*;
+
+// for naming temporary files, so concurrent sorts in one process don't conflict
+static RUN_ID: AtomicUsize = AtomicUsize::new(0);
+
+const PTR: usize = mem::size_of::<usize>();
+
+// a run spilled to a temporary file, rows are stored as record pointers (they stay valid because db files are memory mapped)
+struct Run {
+  path: PathBuf,
+  reader: BufReader<File>,
+}
+
+impl Run {
+  fn write<'a>(rows: &[&[*const u8]]) -> Result<'a, Run> {
+    let path = std::env::temp_dir().join(format!("db-sort-{}-{}.run", std::process::id(), RUN_ID.fetch_add(1, Relaxed)));
+    let mut w = BufWriter::new(File::create(&path)?);
+    for &row in rows {
+      for &p in row { w.write_all(&(p as usize).to_ne_bytes())?; }
+    }
+    w.flush()?;
+    let reader = BufReader::new(File::open(&path)?);
+    Ok(Run { path, reader })
+  }
+
+  // the next row of `tbl_num` record pointers, None if the run is exhausted
+  fn next<'a>(&mut self, tbl_num: usize) -> Result<'a, Option<Vec<*const u8>>> {
+    let mut buf = vec![0; tbl_num * PTR];
+    match self.reader.read_exact(&mut buf) {
+      Ok(()) => Ok(Some(buf.chunks_exact(PTR).map(|p| {
+        let mut x = [0; PTR];
+        x.copy_from_slice(p);
+        usize::from_ne_bytes(x) as *const u8
+      }).collect())),
+      Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+      Err(e) => Err(e.into()),
+    }
+  }
+}
+
+impl Drop for Run {
+  fn drop(&mut self) { let _ = fs::remove_file(&self.path); }
+}
+
+// the head of a run in merging, `BinaryHeap` is a max-heap, so the order is reversed
+// on tie, the row from the earlier run goes first, so merging is stable
+struct Head<'c> {
+  row: Vec<*const u8>,
+  run: usize,
+  cmp: &'c dyn Fn(&[*const u8], &[*const u8]) -> Ordering,
+}
+
+impl Ord for Head<'_> {
+  fn cmp(&self, other: &Self) -> Ordering { (self.cmp)(&other.row, &self.row).then(other.run.cmp(&self.run)) }
+}
+
+impl PartialOrd for Head<'_> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(Ord::cmp(self, other)) }
+}
+
+impl PartialEq for Head<'_> {
+  fn eq(&self, other: &Self) -> bool { Ord::cmp(self, other) == Ordering::Equal }
+}
+
+impl Eq for Head<'_> {}
+
+// stably sort the rows of `tbl_num` record pointers in `data` by `cmp`
+// if the rows to sort take more memory than `budget` bytes (see `Db::sort_budget`), they are sorted in runs that fit in it,
+// which are spilled to temporary files, and merged by reading them back
+pub(crate) fn sort_rows<'a>(data: &[*const u8], tbl_num: usize, budget: usize, cmp: impl Fn(&[*const u8], &[*const u8]) -> Ordering) -> Result<'a, Vec<*const u8>> {
+  // a row to sort takes a slice (2 pointers), and stable sort needs a buffer of the same size
+  let run_len = (budget / (4 * PTR)).max(1);
+  let rows = data.len() / tbl_num;
+  if rows <= run_len {
+    let mut rows = data.chunks_exact(tbl_num).collect::<Vec<_>>();
+    rows.sort_by(|l, r| cmp(l, r));
+    return Ok(rows.concat());
+  }
+  let mut runs = vec![];
+  for chunk in data.chunks(run_len * tbl_num) {
+    let mut rows = chunk.chunks_exact(tbl_num).collect::<Vec<_>>();
+    rows.sort_by(|l, r| cmp(l, r));
+    runs.push(Run::write(&rows)?);
+  }
+  let mut heap = BinaryHeap::with_capacity(runs.len());
+  for (run, r) in runs.iter_mut().enumerate() {
+    if let Some(row) = r.next(tbl_num)? { heap.push(Head { row, run, cmp: &cmp }); }
+  }
+  let mut ret = Vec::with_capacity(data.len());
+  while let Some(Head { row, run, .. }) = heap.pop() {
+    ret.extend_from_slice(&row);
+    if let Some(row) = runs[run].next(tbl_num)? { heap.push(Head { row, run, cmp: &cmp }); }
+  }
+  Ok(ret)
+}
+
+// a candidate in `top_rows`, ordered by `cmp`, and then by the position in input, so the result is the same as a stable sort
+struct Cand<'r, 'c> {
+  row: &'r [*const u8],
+  idx: usize,
+  cmp: &'c dyn Fn(&[*const u8], &[*const u8]) -> Ordering,
+}
+
+impl Ord for Cand<'_, '_> {
+  fn cmp(&self, other: &Self) -> Ordering { (self.cmp)(self.row, other.row).then(self.idx.cmp(&other.idx)) }
+}
+
+impl PartialOrd for Cand<'_, '_> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(Ord::cmp(self, other)) }
+}
+
+impl PartialEq for Cand<'_, '_> {
+  fn eq(&self, other: &Self) -> bool { Ord::cmp(self, other) == Ordering::Equal }
+}
+
+impl Eq for Cand<'_, '_> {}
+
+// the first `k` rows of `sort_rows(data, tbl_num, cmp)`, without sorting all rows
+// a max-heap keeps the best `k` rows so far, a row is pushed only if it is better than the worst one, which is popped then
+pub(crate) fn top_rows(data: &[*const u8], tbl_num: usize, k: usize, cmp: impl Fn(&[*const u8], &[*const u8]) -> Ordering) -> Vec<*const u8> {
+  if k == 0 { return vec![]; }
+  let mut heap = BinaryHeap::with_capacity(k + 1);
+  for (idx, row) in data.chunks_exact(tbl_num).enumerate() {
+    let cand = Cand { row, idx, cmp: &cmp };
+    if heap.len() < k {
+      heap.push(cand);
+    } else if heap.peek().map(|worst| cand < *worst).unwrap_or(false) {
+      (heap.pop(), heap.push(cand));
+    }
+  }
+  heap.into_sorted_vec().iter().flat_map(|c| c.row.iter().copied()).collect()
+}
\ No newline at end of file
@@ -0,0 +1,129 @@
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+
+use common::{*, Error::*, CmpOp::*};
+use syntax::ast::*;
+use physics::*;
+use db::Db;
+use crate::{select::{select, SelectCtx}, SelectResult};
+
+// a subquery in a condition on the records of `tp`, like `col > (select avg(x) from t2 where t2.k = t1.k)`
+// a condition in its where list like `inner op outer` (or `outer op inner`) is correlated, `outer` is a col of `tp` qualified by its table name
+// (and the subquery doesn't select from a table of the same name), it is replaced by the value in the record being checked
+// the result is made into `T` by `prep`, and kept for each distinct tuple of the outer values, so the subquery is executed once for each of them
+// (and never if no record is checked)
+// it keeps a copy of the subquery, so the predicate made from it can't outlive the statement (`'b: 'a` in `one_predicate`)
+pub(crate) struct Subquery<'a, 'b, T> {
+  db: &'a Db,
+  s: Select<'b>,
+  // (index in `s.where_`, outer col id, outer col) of each correlated condition
+  outer: Vec<(usize, u32, &'a ColInfo)>,
+  prep: fn(SelectResult<'a>) -> T,
+  cache: RefCell<HashMap<String, Rc<T>>>,
+  err: SubqueryErr<'b>,
+  // the type of the only col selected by the subquery
+  pub ty: ColTy,
+}
+
+impl<'a, 'b, T> Subquery<'a, 'b, T> {
+  // the selected col and the types of the correlated conditions are checked here, other errors are reported by an execution (see `SubqueryErr`)
+  pub(crate) unsafe fn new(db: &'a Db, s: &Select<'b>, tp: &TablePage, prep: fn(SelectResult<'a>) -> T, err: &SubqueryErr<'b>) -> Result<'b, Self> {
+    let mut s = s.clone();
+    let inner = s.tables.iter().map(|t| t.table).collect::<Vec<_>>();
+    let is_outer = |c: &ColRef| c.db.is_none() && c.table.map_or(false, |t| db.name_eq(t, tp.name()) && !inner.iter().any(|&t1| db.name_eq(t1, t)));
+    let mut outer = vec![];
+    for (i, cond) in s.where_.iter_mut().enumerate() {
+      let (op, l, r) = match *cond {
+        Cond::Cmp(op, l, Atom::ColRef(r)) if is_outer(&l) && !is_outer(&r) => (op.rev(), r, l),
+        Cond::Cmp(op, l, Atom::ColRef(r)) if !is_outer(&l) && is_outer(&r) => (op, l, r),
+        _ => continue,
+      };
+      let ci = tp.pr().get_ci(r.col, db.ignore_case)?;
+      // the type of the inner col is checked here if its table is known, otherwise by the first execution
+      if let Some(t) = l.table.or_else(|| if inner.len() == 1 { Some(inner[0]) } else { None }) {
+        let ty = db.pr().get_tp(t)?.1.get_ci(l.col, db.ignore_case)?.ty;
+        if lit_ty(ty) != lit_ty(ci.ty) { return Err(ColMismatch { l: ty, r: ci.ty }); }
+      }
+      // the value is replaced by the one in the record in `eval`
+      *cond = Cond::Cmp(op, l, Atom::Lit(CLit::new(Lit::Null)));
+      outer.push((i, ci.idx(&tp.cols), &*ci));
+    }
+    if s.tables.is_empty() { return Err(SubqueryColNum(0)); }
+    let cols = SelectCtx::new(&s.tables, db, |_| None)?.mk_tbls(&s.ops)?.concat();
+    if cols.len() != 1 { return Err(SubqueryColNum(cols.len())); }
+    let ty = cols.get_unchecked(0).ty();
+    Ok(Subquery { db, s, outer, prep, cache: RefCell::new(HashMap::new()), err: err.clone(), ty })
+  }
+
+  // the prepared result for the record `data`, None if the subquery fails, then the error is kept in `err`
+  pub(crate) unsafe fn eval(&self, data: *const u8) -> Option<Rc<T>> {
+    let vals = self.outer.iter().map(|&(_, ci_id, ci)| self.db.data2lit(data, ci_id, ci)).collect::<Vec<_>>();
+    let key = key(&vals);
+    if let Some(res) = self.cache.borrow().get(&key) { return Some(res.clone()); }
+    let mut s = self.s.clone();
+    for (&(i, ..), v) in self.outer.iter().zip(vals) {
+      if let Cond::Cmp(_, _, r) = s.where_.get_unchecked_mut(i) { *r = Atom::Lit(v); }
+    }
+    let res = match select(&s, self.db) { Ok(res) => Rc::new((self.prep)(res)), Err(e) => return (self.err.set(e), None).1 };
+    self.cache.borrow_mut().insert(key, res.clone());
+    Some(res)
+  }
+}
+
+// the first error of the subqueries in the conditions of a statement; a subquery is executed while the records are checked, where an error
+// can't be returned, so the condition is false for the record, and the statement returns the error after the scan instead of its result
+#[derive(Default, Clone)]
+pub(crate) struct SubqueryErr<'b>(Rc<RefCell<Option<Error<'b>>>>);
+
+impl<'b> SubqueryErr<'b> {
+  fn set(&self, e: Error<'b>) { self.0.borrow_mut().get_or_insert(e); }
+
+  pub(crate) fn check(&self) -> Result<'b, ()> { self.0.borrow_mut().take().map_or(Ok(()), Err) }
+}
+
+fn key(vals: &[CLit]) -> String { format!("{:?}", vals) }
+
+// the variant of `Lit` that values of `ty` are read as
+pub(crate) fn lit_ty(ty: ColTy) -> LitTy {
+  match ty { bool!() => LitTy::Bool, int!() | float!() => LitTy::Number, date!() => LitTy::Date, _ => LitTy::Str }
+}
+
+// the value of a scalar subquery: the first value of the result, null if it is empty
+pub(crate) fn first<'a>(res: SelectResult<'a>) -> Lit<'a> {
+  res.data.first().map_or(Lit::Null, |x| x.lit())
+}
+
+// the values of a subquery in `col op all / any (select ...)`
+pub(crate) struct Values<'a> {
+  // sorted and deduplicated, so a comparison with all values reduces to one with the min / max, or a binary search
+  vals: Vec<Lit<'a>>,
+  has_null: bool,
+}
+
+impl<'a> Values<'a> {
+  pub(crate) fn new(res: SelectResult<'a>) -> Values<'a> {
+    let has_null = res.data.iter().any(|x| x.is_null());
+    let mut vals = res.data.iter().map(|x| x.lit()).filter(|x| !x.is_null()).collect::<Vec<_>>();
+    unsafe {
+      vals.sort_unstable_by(|l, r| l.cmp(r));
+      vals.dedup_by(|l, r| l.cmp(r) == Ordering::Equal);
+    }
+    Values { vals, has_null }
+  }
+
+  // `x op all (vals)` if `all`, otherwise `x op any (vals)`
+  // `all` of no value is true even if `x` is null, a null value makes `all` unknown (false) or false
+  pub(crate) unsafe fn test(&self, op: CmpOp, all: bool, x: Lit) -> bool {
+    let vals = &self.vals;
+    if vals.is_empty() { return all && !self.has_null; }
+    if x.is_null() || (all && self.has_null) { return false; }
+    let (min, max) = (vals.get_unchecked(0), vals.get_unchecked(vals.len() - 1));
+    match (op, all) {
+      (Eq, false) => vals.binary_search_by(|v| v.cmp(&x)).is_ok(),
+      (Ne, true) => vals.binary_search_by(|v| v.cmp(&x)).is_err(),
+      (Eq, true) => vals.len() == 1 && x.cmp(min) == Ordering::Equal,
+      (Ne, false) => vals.len() > 1 || x.cmp(min) != Ordering::Equal,
+      // `x < any` is `x < max`, `x < all` is `x < min`, and the opposite for `>`
+      _ => op.test(x.cmp(if (op == Lt || op == Le) != all { max } else { min })),
+    }
+  }
+}
\ No newline at end of file
@@ -1,59 +1,90 @@
 use unchecked_unwrap::UncheckedUnwrap;
 use regex::Regex;
-use std::cmp::Ordering::*;
+use std::{cmp::Ordering::*, ptr::null, slice};
 
 use common::{*, Error::*, BinOp::*, CmpOp::*, BareTy::*};
 use syntax::ast::*;
 use physics::*;
-use db::{Db, is_null, hash_pks};
-use index::{Index, handle_all};
-use crate::{predicate::one_where, filter::filter, check_foreign_link, InsertCtx};
+use db::{Db, ChangeOp, is_null, hash_pks};
+use index::{Index, RTree, handle_all, in_index, fulltext_insert, fulltext_delete, spatial_point};
+use crate::{predicate::one_where, subquery::SubqueryErr, filter::filter, foreign::Cascade, InsertCtx, SelectResult};
 
-unsafe fn check<'a>(e: &Expr<'a>, tp: &mut TablePage, re_cache: &mut HashMap<&'a str, Regex>) -> Result<'a, LitTy> {
+unsafe fn check<'a>(db: &Db, e: &Expr<'a>, tp: &mut TablePage, re_cache: &mut HashMap<&'a str, Regex>) -> Result<'a, LitTy> {
   match e {
     Expr::Atom(x) => Ok(match x {
       Atom::Lit(x) => x.lit().ty(),
       Atom::ColRef(col) => {
-        if let Some(t) = col.table { if t != tp.name() { return Err(NoSuchTable(t)); } }
-        let ci = tp.get_ci(col.col)?;
+        if let Some(t) = col.table { if col.db.is_some() || !db.name_eq(t, tp.name()) { return Err(NoSuchTable(t)); } }
+        let ci = tp.get_ci(col.col, db.ignore_case)?;
         match ci.ty {
           ColTy::FixTy(ty) => match ty.ty { Bool => LitTy::Bool, Int | Float => LitTy::Number, Date => LitTy::Date, Char => LitTy::Str },
           varchar!() => LitTy::Str,
         }
       }
     }),
-    Expr::Null(x, _) => (check(x, tp, re_cache)?, Ok(LitTy::Bool)).1,
+    Expr::Null(x, _) => (check(db, x, tp, re_cache)?, Ok(LitTy::Bool)).1,
     Expr::Like(x, like) => {
-      match check(x, tp, re_cache)? { LitTy::Str => {} ty => return Err(InvalidLikeTy1(ty)) };
+      match check(db, x, tp, re_cache)? { LitTy::Str => {} ty => return Err(InvalidLikeTy1(ty)) };
       re_cache.insert(like, db::like2re(like)?);
       Ok(LitTy::Bool)
     }
     Expr::And(box (l, r)) | Expr::Or(box (l, r)) => {
-      match check(l, tp, re_cache)? { LitTy::Bool => {} ty => return Err(IncompatibleLogic(ty)) };
-      match check(r, tp, re_cache)? { LitTy::Bool => {} ty => return Err(IncompatibleLogic(ty)) };
+      match check(db, l, tp, re_cache)? { LitTy::Bool => {} ty => return Err(IncompatibleLogic(ty)) };
+      match check(db, r, tp, re_cache)? { LitTy::Bool => {} ty => return Err(IncompatibleLogic(ty)) };
       Ok(LitTy::Bool)
     }
     Expr::Cmp(op, box (l, r)) => {
-      let (l, r) = (check(l, tp, re_cache)?, check(r, tp, re_cache)?);
+      let (l, r) = (check(db, l, tp, re_cache)?, check(db, r, tp, re_cache)?);
       if l == r { Ok(LitTy::Bool) } else { Err(IncompatibleCmp { op: *op, l, r }) }
     }
     Expr::Bin(op, box (l, r)) => {
-      match check(l, tp, re_cache)? { LitTy::Number => {} ty => return Err(IncompatibleBin { op: *op, ty }) };
-      match check(r, tp, re_cache)? { LitTy::Number => {} ty => return Err(IncompatibleBin { op: *op, ty }) };
+      match check(db, l, tp, re_cache)? { LitTy::Number => {} ty => return Err(IncompatibleBin { op: *op, ty }) };
+      match check(db, r, tp, re_cache)? { LitTy::Number => {} ty => return Err(IncompatibleBin { op: *op, ty }) };
+      Ok(LitTy::Number)
+    }
+    Expr::Interval(box x, _) => match check(db, x, tp, re_cache)? { LitTy::Date => Ok(LitTy::Date), ty => Err(IncompatibleBin { op: Add, ty }) },
+    Expr::Coalesce(args) | Expr::Greatest(args) | Expr::Least(args) => {
+      let func = match e { Expr::Coalesce(_) => "coalesce", Expr::Greatest(_) => "greatest", _ => "least" };
+      // null literals are compatible with any type
+      let mut ret = LitTy::Null;
+      for x in args {
+        match (ret, check(db, x, tp, re_cache)?) {
+          (_, LitTy::Null) => {}
+          (LitTy::Null, ty) => ret = ty,
+          (l, r) => if l != r { return Err(IncompatibleArgs { func, l, r }); }
+        }
+      }
+      Ok(ret)
+    }
+    Expr::NullIf(box (l, r)) => {
+      let (l, r) = (check(db, l, tp, re_cache)?, check(db, r, tp, re_cache)?);
+      if l == r || l == LitTy::Null || r == LitTy::Null { Ok(l) } else { Err(IncompatibleArgs { func: "nullif", l, r }) }
+    }
+    Expr::Math(func, box (x, y)) => {
+      for x in Some(x).into_iter().chain(y) {
+        match check(db, x, tp, re_cache)? { LitTy::Number => {} ty => return Err(InvalidMathArg { func: *func, ty }) };
+      }
+      Ok(LitTy::Number)
+    }
+    Expr::Random(seed) => {
+      if let Some(x) = seed { match check(db, x, tp, re_cache)? { LitTy::Number | LitTy::Null => {} ty => return Err(InvalidRandSeed(ty)) } }
       Ok(LitTy::Number)
     }
   }
 }
 
+// `x` as an int, None if it is not an integer in the range of i32
+fn int(x: f64) -> Option<i32> { if x as i32 as f64 == x { Some(x as i32) } else { None } }
+
 // if one of the operand is null, the result is null (including comparison, e.g., (null = null) evaluates to null, instead of false in select)
 // the only exception is "is (not) null" check, it always return bool
-// if arithmetic result is NaN, the result is null
+// if arithmetic result is NaN (or a bitwise op is given a non-integer operand), the result is null
 unsafe fn eval<'a>(db: &Db, e: &Expr<'a>, tp: &mut TablePage, data: *const u8, re_cache: &HashMap<&'a str, Regex>) -> Lit<'a> {
   match e {
     Expr::Atom(x) => match x {
       Atom::Lit(x) => *x,
       Atom::ColRef(col) => {
-        let ci = tp.get_ci(col.col).unchecked_unwrap();
+        let ci = tp.get_ci(col.col, db.ignore_case).unchecked_unwrap();
         let ci_id = ci.idx(&tp.cols);
         db.data2lit(data, ci_id, ci)
       }
@@ -83,29 +114,75 @@ unsafe fn eval<'a>(db: &Db, e: &Expr<'a>, tp: &mut TablePage, data: *const u8, r
       // since we cannot have type mismatch here, if it is not Number, it can only be Null
       let l = match eval(db, l, tp, data, re_cache) { Lit::Number(x) => x, _ => return Lit::Null };
       let r = match eval(db, r, tp, data, re_cache) { Lit::Number(x) => x, _ => return Lit::Null };
-      let res = match op { Add => l + r, Sub => l - r, Mul => l * r, Div => l / r, Mod => l % r, };
+      let res = match op {
+        Add => l + r, Sub => l - r, Mul => l * r, Div => l / r, Mod => l % r,
+        // operands of a bitwise op should be integers in the range of int
+        _ => match (int(l), int(r)) { (Some(l), Some(r)) => op.bit(l, r).map_or(std::f64::NAN, |x| x as f64), _ => std::f64::NAN },
+      };
+      if res.is_nan() { Lit::Null } else { Lit::Number(res) }
+    }
+    // null if the result is out of the range of Date
+    Expr::Interval(box x, i) => match eval(db, x, tp, data, re_cache) { Lit::Date(d) => i.add(d).map_or(Lit::Null, Lit::Date), _ => Lit::Null },
+    Expr::Coalesce(args) => args.iter().map(|x| eval(db, x, tp, data, re_cache)).find(|x| !x.is_null()).unwrap_or(Lit::Null),
+    Expr::Greatest(args) | Expr::Least(args) => {
+      let greatest = if let Expr::Greatest(_) = e { true } else { false };
+      let it = args.iter().map(|x| eval(db, x, tp, data, re_cache)).filter(|x| !x.is_null());
+      // `check` guarantees they have the same type
+      if greatest { it.max_by(|l, r| l.cmp(r)) } else { it.min_by(|l, r| l.cmp(r)) }.unwrap_or(Lit::Null)
+    }
+    Expr::NullIf(box (l, r)) => {
+      let (l, r) = (eval(db, l, tp, data, re_cache), eval(db, r, tp, data, re_cache));
+      if !l.is_null() && !r.is_null() && l.cmp(&r) == Equal { Lit::Null } else { l }
+    }
+    Expr::Math(func, box (x, y)) => {
+      let x = match eval(db, x, tp, data, re_cache) { Lit::Number(x) => x, _ => return Lit::Null };
+      let y = match y.as_ref().map(|y| eval(db, y, tp, data, re_cache)) { Some(Lit::Number(y)) => y, None => 0.0, _ => return Lit::Null };
+      let res = func.apply(x, y);
       if res.is_nan() { Lit::Null } else { Lit::Number(res) }
     }
+    Expr::Random(None) => Lit::Number(random()),
+    Expr::Random(Some(x)) => match eval(db, x, tp, data, re_cache) { Lit::Number(x) => Lit::Number(seeded_random(x as i64 as u64)), _ => Lit::Null },
   }
 }
 
-pub fn update<'a>(u: &Update<'a>, db: &mut Db) -> ModifyResult<'a, u32> {
+pub fn update<'a>(u: &Update<'a>, db: &mut Db) -> ModifyResult<'a, u32> { update_rows(u, db, |_| {}) }
+
+// like `update`, but the updated rows (with new values) are output like a select of `cols`, all cols if None
+pub fn update_returning<'a, 'b>(u: &Update<'a>, cols: Option<&[&'a str]>, db: &'b mut Db) -> ModifyResult<'a, SelectResult<'b>> {
+  unsafe {
+    let tp = db.get_tp(u.table)?.1;
+    let cols = SelectResult::returning_cols(db, tp, cols)?;
+    let mut rids = vec![];
+    update_rows(u, db, |rid| rids.push(rid))?;
+    // records are updated in place, so their rids don't change
+    let data = rids.iter().map(|&rid| db.get_data_slot(tp, rid) as *const u8).collect::<Vec<_>>();
+    Ok(SelectResult::records(db, cols, &data))
+  }
+}
+
+// `f` is called with the rid of each updated record
+fn update_rows<'a>(u: &Update<'a>, db: &mut Db, mut f: impl FnMut(Rid)) -> ModifyResult<'a, u32> {
   unsafe {
     let mut ctx = InsertCtx::new(db, u.table, None)?;
-    let f_links = db.foreign_links_to(ctx.tp_id).collect::<Vec<_>>();
-    let pred = one_where(db.pr(), &u.where_, ctx.tp)?;
+    // the cols referenced by foreign keys
+    let mut referenced = db.foreign_links_to(ctx.tp_id).map(|(_, _, ci_id)| ci_id as u32).collect::<Vec<_>>();
+    (referenced.sort_unstable(), referenced.dedup());
+    let err = SubqueryErr::default();
+    let pred = one_where(db.pr(), &u.where_, ctx.tp, &err)?;
     let mut re_cache = HashMap::new();
     let mut cols = Vec::with_capacity(u.sets.len());
     let mut vals = vec![CLit::new(Lit::Null); u.sets.len()]; // the initial value is useless (and not really necessary...)
     for (col, e) in &u.sets {
-      cols.push(&*ctx.tp.get_ci(col)?);
-      check(e, ctx.tp, &mut re_cache)?;
+      let ci = ctx.tp.get_ci(col, db.ignore_case)?;
+      // records are updated in place, so they can't move to another partition
+      if db.part_col(ctx.tp) == Some(ci.idx(&ctx.tp.cols)) { return Err(ModifyPartitioned(col).into()); }
+      cols.push(&*ci);
+      check(db, e, ctx.tp, &mut re_cache)?;
     }
     let slot_size = ctx.tp.size as usize;
     let buf = Align4U8::new(slot_size); // update to buf, then copy to db
-    let mut cnt = 0;
+    let (mut cnt, mut c) = (0, Cascade::default());
     if let Err(e) = filter(db.pr(), &u.where_, ctx.tp_id, pred, |data, rid| {
-      check_foreign_link(db, ctx.tp, data, &f_links)?;
       buf.ptr.copy_from_nonoverlapping(data, slot_size);
       for (idx, (_, e)) in u.sets.iter().enumerate() {
         let ci = *cols.get_unchecked(idx);
@@ -128,7 +205,31 @@ pub fn update<'a>(u: &Update<'a>, db: &mut Db) -> ModifyResult<'a, u32> {
       for (idx, &val) in vals.iter().enumerate() {
         if !val.is_null() { Db::varchar_ck(cols.get_unchecked(idx).ty, val)?; }
       }
+      // the foreign links to a referenced col only matter if its value is really changed
+      for &ci_id in &referenced {
+        let ci = ctx.tp.cols.get_unchecked(ci_id as usize);
+        let (old, new) = (data.add(ci.off as usize), buf.ptr.add(ci.off as usize));
+        if is_null(data, ci_id) { continue; }
+        if is_null(buf.ptr, ci_id) {
+          c.update(db, ctx.tp_id, ci_id, old, null())?;
+        } else if slice::from_raw_parts(old, ci.ty.size() as usize) != slice::from_raw_parts(new, ci.ty.size() as usize) {
+          c.update(db, ctx.tp_id, ci_id, old, new)?;
+        }
+      }
       // now no error can occur
+      // the old values are captured before any of them is overwritten (a varchar may be overwritten in place)
+      let old = db.cdc_record(ctx.tp, data);
+      // an index may be affected by cols other than the indexed one: any col in the predicate of a partial index (so always check it),
+      // and the y col of a spatial index
+      let set = |ci: &ColInfo| cols.iter().any(|&c| c.p() == ci.p());
+      let affected = |ci: &ColInfo| ci.index != !0 && (ci.flags.contains(ColFlags::PARTIAL) || set(ci) ||
+        (ci.idx_spatial != 0 && set(ctx.tp.cols.get_unchecked(ci.idx_spatial as usize - 1))));
+      // a varchar may be overwritten in place below, so words of the old text must be removed from full-text index before that
+      for (ci_id, ci) in ctx.tp.cols().iter().enumerate() {
+        if affected(ci) && ci.flags.contains(ColFlags::FULLTEXT) && in_index(db, ctx.tp, ci, data) {
+          fulltext_delete(db, ctx.tp_id, ci_id as u32, data.add(ci.off as usize), rid);
+        }
+      }
       for (idx, &val) in vals.iter().enumerate() {
         let ci = *cols.get_unchecked(idx);
         let ci_id = ci.idx(&ctx.tp.cols);
@@ -142,25 +243,40 @@ pub fn update<'a>(u: &Update<'a>, db: &mut Db) -> ModifyResult<'a, u32> {
           }
         }
       }
-      for (col, _) in &u.sets {
-        let ci = ctx.tp.get_ci(col).unchecked_unwrap();
-        let ci_id = ci.idx(&ctx.tp.cols);
-        if ci.index != !0 && !is_null(buf.ptr, ci_id) {
+      for (ci_id, ci) in ctx.tp.cols().iter().enumerate() {
+        if affected(ci) {
+          let (old_in, new_in) = (in_index(db, ctx.tp, ci, data), in_index(db, ctx.tp, ci, buf.ptr));
           let old = data.add(ci.off as usize);
           let new = buf.ptr.add(ci.off as usize);
+          if ci.flags.contains(ColFlags::FULLTEXT) {
+            if new_in { fulltext_insert(db, ctx.tp_id, ci_id as u32, new, rid); }
+            continue;
+          }
+          if ci.idx_spatial != 0 {
+            let mut rtree = RTree::new(db, ctx.tp_id, ci_id as u32);
+            if old_in { rtree.delete(spatial_point(ctx.tp, ci, data), rid); }
+            if new_in { rtree.insert(spatial_point(ctx.tp, ci, buf.ptr), rid); }
+            continue;
+          }
           macro_rules! handle {
             ($ty: ident) => {{
-              let mut index = Index::<{ $ty }>::new(db, ctx.tp_id, ci_id);
-              index.delete(old, rid);
-              index.insert(new, rid);
+              let mut index = Index::<{ $ty }>::new(db, ctx.tp_id, ci_id as u32);
+              if old_in { index.delete(old, rid); }
+              if new_in { index.insert(new, rid); }
             }};
           }
           handle_all!(ci.ty.fix_ty().ty, handle);
         }
       }
+      db.bloom_add(ctx.tp, rid.page(), buf.ptr); // old values are left in the filter, which is harmless
       data.copy_from_nonoverlapping(buf.ptr, slot_size);
+      db.stats_changed(ctx.tp, 1);
+      let new = db.cdc_record(ctx.tp, data);
+      db.cdc_push(ctx.tp, ChangeOp::Update, old, new);
+      c.apply(db); // after the record itself, because it may reference itself
       cnt += 1;
+      f(rid);
       Ok(())
-    }, false) { Err(ModifyError(cnt, e)) } else { Ok(cnt) }
+    }, false).and_then(|_| err.check()) { Err(ModifyError(cnt, e)) } else { Ok(cnt) }
   }
 }
\ No newline at end of file
@@ -13,7 +13,17 @@ pub enum Stmt<'a> {
   ShowDbs,
   UseDb(&'a str),
   CreateTable(CreateTable<'a>),
-  DropTable(&'a str),
+  // "create temporary table", see `Eval::create_temp_table`; it is boxed so that its type differs from `CreateTable`'s, and both can be `into` a `Stmt`
+  CreateTempTable(Box<CreateTable<'a>>),
+  CreateTableAs(CreateTableAs<'a>),
+  // "create table table like like", see `index::create_table_like`
+  CreateTableLike { table: &'a str, like: &'a str },
+  // "create table t (...) partition by range / hash (col) (...)", see `Db::create_partitioned_table`
+  CreatePartitioned { create: CreateTable<'a>, part: Partition<'a> },
+  // "alter table t drop partition n", see `query::drop_partition`
+  DropPartition { table: &'a str, part: u32 },
+  // "drop table t cascade" => cascade is true, foreign keys referencing t in other tables are dropped instead of rejecting it
+  DropTable { table: &'a str, cascade: bool },
   ShowTable(&'a str),
   ShowTables,
   CreateIndex(CreateIndex<'a>),
@@ -26,10 +36,35 @@ pub enum Stmt<'a> {
   Rename { old: &'a str, new: &'a str },
   AddForeign(AddForeign<'a>),
   DropForeign { table: &'a str, col: &'a str },
+  // report the records whose foreign key doesn't exist in the referenced col
+  CheckForeignKeys,
+  // report the inconsistencies between the pages of the current db, see `index::check_db`
+  CheckDb,
+  // "repair database" => None, fix the problems of the current db found by "check database" in place, see `index::repair_db`
+  // "repair database src into dst" => Some((src, dst)), copy db src to a fresh db dst and repair it there, leaving src unchanged
+  RepairDb(Option<(&'a str, &'a str)>),
+  // "comment on table t" => col is None; "comment on column t.col" => col is Some; an empty comment removes the old one
+  Comment { table: &'a str, col: Option<&'a str>, comment: &'a str },
   AddPrimary { table: &'a str, cols: Vec<&'a str> },
   DropPrimary { table: &'a str, cols: Vec<&'a str> },
   AddCol { table: &'a str, col: ColDecl<'a> },
   DropCol { table: &'a str, col: &'a str },
+  // "reindex table" => col is None, rebuild all indexes in table; "reindex table(col)" => col is Some
+  Reindex { table: &'a str, col: Option<&'a str> },
+  // "analyze" => None, collect statistics of all tables
+  Analyze(Option<&'a str>),
+  // "optimize table t", move the records of t into as few pages as possible, and release the emptied pages
+  Optimize(&'a str),
+  // `path` is the database file, tables in it are referred to as `alias.table` in the from list of select
+  Attach { path: &'a str, alias: &'a str },
+  Detach(&'a str),
+  Explain(Explain<'a>),
+  // "set name = val" / "pragma name = val" => persist is false, the setting only lasts in this session
+  // "set persist name = val" => persist is true, the setting is also stored in the current db, and applied again when it is used
+  Set { name: &'a str, val: CLit<'a>, persist: bool },
+  // "pragma name", show the current value of the setting
+  ShowSetting(&'a str),
+  Returning(Returning<'a>),
 }
 
 #[derive(Debug)]
@@ -46,12 +81,63 @@ pub struct Update<'a> {
   pub where_: Vec<Cond<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Select<'a> {
   // None for select *
   pub ops: Option<Vec<Agg<'a>>>,
-  pub tables: Vec<&'a str>,
+  pub tables: Vec<TableRef<'a>>,
   pub where_: Vec<Cond<'a>>,
+  // the bool is true for desc; null is smaller than any other value
+  pub order: Option<(ColRef<'a>, bool)>,
+  pub limit: Option<u32>,
+  // the number of rows skipped before `limit` is applied, 0 for none
+  pub offset: u32,
+  // the subqueries in from list, each is also in `tables` as `DERIVED.alias`
+  pub derived: Vec<Derived<'a>>,
+}
+
+// the alias of the database that derived tables are materialized in, it can't be used by `attach`
+pub const DERIVED: &str = "derived";
+
+// `(select ...) [as] alias [(col, ...)]` or `(values (...), ...) [as] alias [(col, ...)]` in from list, the rows are treated as a table named `alias`
+// its cols are named by the list, or by the result cols if the list is omitted (like `id` or `count(*)`, `column1` and so on for values)
+#[derive(Debug, Clone)]
+pub struct Derived<'a> {
+  pub alias: &'a str,
+  pub cols: Option<Vec<&'a str>>,
+  pub src: DerivedSrc<'a>,
+}
+
+#[derive(Debug, Clone)]
+pub enum DerivedSrc<'a> {
+  Select(Select<'a>),
+  // the rows should have the same number of values, and the values in one col should have the same type (or be null)
+  Values(Vec<Vec<CLit<'a>>>),
+}
+
+impl<'a> Derived<'a> {
+  pub fn table_ref(&self) -> TableRef<'a> { TableRef { db: Some(DERIVED), table: self.alias, sample: None } }
+}
+
+// only select can be explained
+#[derive(Debug)]
+pub struct Explain<'a> {
+  pub select: Select<'a>,
+  pub format: ExplainFormat,
+}
+
+// "explain select ..." => Text; "explain (format json / dot / tree) select ..." => Json / Dot / Tree (see `Plan::json`, `Plan::dot`, `Plan::tree`)
+// Dot and Tree also run the select, to show the actual row counts along with the estimated ones (see `query::explain_analyze`)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExplainFormat { Text, Json, Dot, Tree }
+
+// "insert / update / delete ... returning *" => cols is None; "... returning col, ..." => cols is Some
+// the affected rows (new values for update, removed values for delete) are output like the result of a select, instead of their count
+#[derive(Debug)]
+pub struct Returning<'a> {
+  // only insert, update or delete
+  pub stmt: Box<Stmt<'a>>,
+  pub cols: Option<Vec<&'a str>>,
 }
 
 #[derive(Debug)]
@@ -60,16 +146,38 @@ pub struct Delete<'a> {
   pub where_: Vec<Cond<'a>>,
 }
 
+// `db` is the alias of an attached database, None for the database in use
+#[derive(Copy, Clone)]
+pub struct TableRef<'a> {
+  pub db: Option<&'a str>,
+  pub table: &'a str,
+  // `tablesample (p percent)`, only about p% of data pages are visited, aggregations are computed on them without scaling
+  pub sample: Option<f32>,
+}
+
+// `db` is the alias of an attached database (see `TableRef`), it is only given together with `table`
 #[derive(Copy, Clone)]
 pub struct ColRef<'a> {
+  pub db: Option<&'a str>,
   pub table: Option<&'a str>,
   pub col: &'a str,
 }
 
 // Agg is short for Aggregation
+#[derive(Clone)]
 pub struct Agg<'a> {
   pub col: ColRef<'a>,
   pub op: Option<AggOp>,
+  // extra arguments of some `op`, None for the others
+  pub arg: Option<AggArg<'a>>,
+}
+
+#[derive(Copy, Clone)]
+pub enum AggArg<'a> {
+  // for `GroupConcat`, values are joined in the order of `order` (ColRef, desc), or in the order of the result if None
+  Concat { sep: &'a str, order: Option<(ColRef<'a>, bool)> },
+  // for `PercentileCont`, the fraction, and whether the values are ordered in desc
+  Percentile { p: f32, desc: bool },
 }
 
 #[derive(Debug)]
@@ -79,11 +187,41 @@ pub struct CreateTable<'a> {
   pub cons: Vec<ColCons<'a>>,
 }
 
+// "partition by range (col) (b1, b2, ...)" => hash is false, records with col in [b(i), b(i + 1)) are in partition i (b0 = -inf, and the last is +inf)
+// "partition by hash (col) (n)" => hash is true, vals is [n], the number of partitions
+// a record with null col is always in partition 0
+#[derive(Debug)]
+pub struct Partition<'a> {
+  pub col: &'a str,
+  pub hash: bool,
+  pub vals: Vec<CLit<'a>>,
+}
+
+// "create table t as select ..." => cols is None, the cols have the names of the result cols
+// "create table t (col, ...) as select ..." => cols is Some, they rename the result cols
+#[derive(Debug)]
+pub struct CreateTableAs<'a> {
+  pub table: &'a str,
+  pub cols: Option<Vec<&'a str>>,
+  pub select: Select<'a>,
+}
+
 #[derive(Debug)]
 pub struct CreateIndex<'a> {
   pub index: &'a str,
   pub table: &'a str,
   pub col: &'a str,
+  pub desc: bool,
+  // only index the first `prefix` bytes of a char col
+  pub prefix: Option<u8>,
+  // index `func(col)` instead of col
+  pub func: Option<StrFn>,
+  // only records satisfying it are put into index, it can only be `col op lit` or `col is [not] null`
+  pub where_: Option<Cond<'a>>,
+  // a full-text index on the words in a char / varchar col, for `match(col) against (query)`, it can't have any above option
+  pub fulltext: bool,
+  // a spatial index on the point (col, spatial), for `within(col, spatial, ...)`, it can't have any above option
+  pub spatial: Option<&'a str>,
 }
 
 #[derive(Debug)]
@@ -92,6 +230,8 @@ pub struct AddForeign<'a> {
   pub col: &'a str,
   pub f_table: &'a str,
   pub f_col: &'a str,
+  pub on_delete: FkAction,
+  pub on_update: FkAction,
 }
 
 #[derive(Debug)]
@@ -99,24 +239,50 @@ pub struct ColDecl<'a> {
   pub col: &'a str,
   pub ty: ColTy,
   pub notnull: bool,
-  pub dft: Option<CLit<'a>>,
+  pub dft: Option<Dft<'a>>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Dft<'a> {
+  Lit(CLit<'a>),
+  // evaluated for each inserted record
+  Fn(DftFn),
 }
 
 // Cons for Constraint
 #[derive(Debug)]
 pub enum ColCons<'a> {
   Primary(Vec<&'a str>),
-  Foreign { col: &'a str, f_table: &'a str, f_col: &'a str },
+  Foreign { col: &'a str, f_table: &'a str, f_col: &'a str, on_delete: FkAction, on_update: FkAction },
   Unique(&'a str),
   Check(&'a str, Vec<CLit<'a>>),
+  // (col, comment), from `col ty comment '...'`
+  Comment(&'a str, &'a str),
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum Cond<'a> {
   Cmp(CmpOp, ColRef<'a>, Atom<'a>),
   // true for `is null`, false for `is not null`
   Null(ColRef<'a>, bool),
   Like(ColRef<'a>, &'a str),
+  // `func(col) op lit`, it can be served by an index on `func(col)`
+  Fn(StrFn, CmpOp, ColRef<'a>, CLit<'a>),
+  // `match(col) against (query)`, true if the text in col contains any word in query, it requires a full-text index on col
+  Match(ColRef<'a>, &'a str),
+  // `within(x, y, x1, y1, x2, y2)`, true if the point (x, y) is in the box with corners (x1, y1) and (x2, y2), it can be served by a spatial index
+  Within(ColRef<'a>, ColRef<'a>, [CLit<'a>; 4]),
+  // `col op mask cmp lit`, `op` is a bitwise op (`BinOp::bit`), like `flags & 4 <> 0`; `~col cmp lit` is `col ^ -1 cmp lit`
+  Bit(BinOp, ColRef<'a>, CLit<'a>, CmpOp, CLit<'a>),
+  // `(col1, col2, ...) op (lit1, lit2, ...)`, compared lexicographically, like `(a, b) > (1, 'x')` is `a > 1 or (a = 1 and b > 'x')`
+  Row(CmpOp, Vec<ColRef<'a>>, Vec<CLit<'a>>),
+  // `(col1, col2, ...) in ((lit1, lit2, ...), ...)`, true if the row is equal to any of the rows
+  RowIn(Vec<ColRef<'a>>, Vec<Vec<CLit<'a>>>),
+  // `col op all (select ...)` if the bool is true, otherwise `col op any (select ...)` (or `some`), the subquery should select 1 col
+  Quant(CmpOp, ColRef<'a>, bool, Box<Select<'a>>),
+  // `col op (select ...)`, the subquery should select 1 col, its first value is used (null if there is none)
+  // it can refer to the table of `col` like `t1.k`, see `query::subquery::Subquery`
+  Sub(CmpOp, ColRef<'a>, Box<Select<'a>>),
 }
 
 // this is arithmetic expr, only appears in the set list of update, not in where list of select and delete
@@ -129,11 +295,27 @@ pub enum Expr<'a> {
   Or(Box<(Expr<'a>, Expr<'a>)>),
   Cmp(CmpOp, Box<(Expr<'a>, Expr<'a>)>),
   Bin(BinOp, Box<(Expr<'a>, Expr<'a>)>),
+  // `date + interval n unit`, `date - interval` is stored with the negated interval
+  Interval(Box<Expr<'a>>, Interval),
+  // `func(x)` or `func(x, y)`, the second argument is given for and only for `Round` (optional), `Mod` and `Power`
+  Math(MathFn, Box<(Expr<'a>, Option<Expr<'a>>)>),
+  // the first not null argument, `ifnull(x, y)` is `coalesce(x, y)`
+  Coalesce(Vec<Expr<'a>>),
+  // null if the 2 arguments are equal, otherwise the first one
+  NullIf(Box<(Expr<'a>, Expr<'a>)>),
+  // the largest / smallest not null argument
+  Greatest(Vec<Expr<'a>>),
+  Least(Vec<Expr<'a>>),
+  // `random()` is evaluated again for every row, `rand(seed)` only depends on `seed` (truncated to an integer), see `common::random`
+  Random(Option<Box<Expr<'a>>>),
 }
 
 impl<'a> Cond<'a> {
   pub fn lhs_col(&self) -> &ColRef<'a> {
-    match self { Cond::Cmp(_, l, _) | Cond::Null(l, _) | Cond::Like(l, _) => l }
+    match self { Cond::Cmp(_, l, _) | Cond::Null(l, _) | Cond::Like(l, _) | Cond::Fn(_, _, l, _) | Cond::Match(l, _) | Cond::Within(l, _, _) | Cond::Bit(_, l, ..) | Cond::Quant(_, l, ..) | Cond::Sub(_, l, _) => l,
+      // the parser never makes an empty row
+      Cond::Row(_, cols, _) | Cond::RowIn(cols, _) => &cols[0],
+    }
   }
 
   pub fn rhs_col_op(&self) -> Option<(&ColRef<'a>, CmpOp)> {
@@ -144,15 +326,33 @@ impl<'a> Cond<'a> {
 #[derive(Copy, Clone)]
 pub enum Atom<'a> { ColRef(ColRef<'a>), Lit(CLit<'a>) }
 
+impl fmt::Debug for TableRef<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(db) = self.db { write!(f, "{}.{}", db, self.table) } else { write!(f, "{}", self.table) }
+  }
+}
+
 impl fmt::Debug for ColRef<'_> {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(db) = self.db { write!(f, "{}.", db)?; }
     if let Some(table) = self.table { write!(f, "{}.{}", table, self.col) } else { write!(f, "{}", self.col) }
   }
 }
 
 impl fmt::Debug for Agg<'_> {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    if let Some(op) = self.op { write!(f, "{}({:?})", op.name(), self.col) } else { write!(f, "{:?}", self.col) }
+    match (self.op, &self.arg) {
+      (Some(op), Some(AggArg::Concat { sep, order })) => {
+        write!(f, "{}({:?}", op.name(), self.col)?;
+        if let Some((col, desc)) = order { write!(f, " order by {:?}{}", col, if *desc { " desc" } else { "" })?; }
+        write!(f, " separator {:?})", Lit::Str(*sep))
+      }
+      (Some(op), Some(AggArg::Percentile { p, desc })) => {
+        write!(f, "{}({}) within group (order by {:?}{})", op.name(), p, self.col, if *desc { " desc" } else { "" })
+      }
+      (Some(op), _) => write!(f, "{}({:?})", op.name(), self.col),
+      _ => write!(f, "{:?}", self.col),
+    }
   }
 }
 
@@ -168,7 +368,65 @@ impl fmt::Debug for Cond<'_> {
       Cond::Cmp(op, l, r) => write!(f, "{:?} {} {:?}", l, op.name(), r),
       Cond::Null(x, null) => write!(f, "{:?} is {}null", x, if *null { "" } else { "not " }),
       Cond::Like(x, like) => write!(f, "{:?} like '{}'", x, like),
+      Cond::Fn(func, op, l, r) => write!(f, "{}({:?}) {} {:?}", func.name(), l, op.name(), r),
+      Cond::Match(x, query) => write!(f, "match({:?}) against ('{}')", x, query),
+      Cond::Within(x, y, [x1, y1, x2, y2]) => write!(f, "within({:?}, {:?}, {:?}, {:?}, {:?}, {:?})", x, y, x1, y1, x2, y2),
+      Cond::Bit(op, l, mask, cmp, r) => write!(f, "{:?} {} {:?} {} {:?}", l, op.name(), mask, cmp.name(), r),
+      Cond::Row(op, cols, lits) => write!(f, "{} {} {}", Row(cols), op.name(), Row(lits)),
+      Cond::RowIn(cols, rows) => {
+        write!(f, "{} in (", Row(cols))?;
+        for (i, row) in rows.iter().enumerate() { write!(f, "{}{}", if i == 0 { "" } else { ", " }, Row(row))?; }
+        f.write_str(")")
+      }
+      Cond::Quant(op, l, all, s) => write!(f, "{:?} {} {} ({})", l, op.name(), if *all { "all" } else { "any" }, s),
+      Cond::Sub(op, l, s) => write!(f, "{:?} {} ({})", l, op.name(), s),
+    }
+  }
+}
+
+// like "select a, max(b) from t where a > 1 order by a desc limit 1 offset 1"
+impl fmt::Display for Select<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("select ")?;
+    match &self.ops {
+      Some(ops) => for (i, op) in ops.iter().enumerate() { write!(f, "{}{:?}", if i == 0 { "" } else { ", " }, op)?; },
+      None => f.write_str("*")?,
     }
+    f.write_str(" from ")?;
+    for (i, t) in self.tables.iter().enumerate() {
+      f.write_str(if i == 0 { "" } else { ", " })?;
+      match self.derived.iter().find(|d| t.db == Some(DERIVED) && d.alias == t.table) {
+        Some(d) => {
+          match &d.src {
+            DerivedSrc::Select(s) => write!(f, "({})", s)?,
+            DerivedSrc::Values(rows) => {
+              f.write_str("(values ")?;
+              for (i, row) in rows.iter().enumerate() { write!(f, "{}{}", if i == 0 { "" } else { ", " }, Row(row))?; }
+              f.write_str(")")?;
+            }
+          }
+          write!(f, " as {}", d.alias)?;
+          if let Some(cols) = &d.cols { write!(f, "({})", cols.join(", "))?; }
+        }
+        None => write!(f, "{:?}", t)?,
+      }
+    }
+    for (i, c) in self.where_.iter().enumerate() { write!(f, " {} {:?}", if i == 0 { "where" } else { "and" }, c)?; }
+    if let Some((col, desc)) = self.order { write!(f, " order by {:?}{}", col, if desc { " desc" } else { "" })?; }
+    if let Some(limit) = self.limit { write!(f, " limit {}", limit)?; }
+    if self.offset != 0 { write!(f, " offset {}", self.offset)?; }
+    Ok(())
+  }
+}
+
+// `(x1, x2, ...)`, for printing `Cond::Row`, `Cond::RowIn` and `DerivedSrc::Values`
+struct Row<'a, T>(&'a [T]);
+
+impl<T: fmt::Debug> fmt::Display for Row<'_, T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("(")?;
+    for (i, x) in self.0.iter().enumerate() { write!(f, "{}{:?}", if i == 0 { "" } else { ", " }, x)?; }
+    f.write_str(")")
   }
 }
 
@@ -180,6 +438,16 @@ impl fmt::Debug for Expr<'_> {
       Expr::Like(x, like) => write!(f, "({:?}) like '{}'", x, like),
       Expr::And(box (l, r)) => write!(f, "({:?}) and ({:?})", l, r), Expr::Or(box (l, r)) => write!(f, "({:?}) or ({:?})", l, r),
       Expr::Cmp(op, box (l, r)) => write!(f, "({:?}) {} ({:?})", l, op.name(), r), Expr::Bin(op, box (l, r)) => write!(f, "({:?}) {} ({:?})", l, op.name(), r),
+      Expr::Interval(box x, i) => write!(f, "({:?}) + {:?}", x, i),
+      Expr::Math(func, box (x, None)) => write!(f, "{}({:?})", func.name(), x),
+      Expr::Math(func, box (x, Some(y))) => write!(f, "{}({:?}, {:?})", func.name(), x, y),
+      Expr::Coalesce(args) | Expr::Greatest(args) | Expr::Least(args) => {
+        f.write_str(match self { Expr::Coalesce(_) => "coalesce(", Expr::Greatest(_) => "greatest(", _ => "least(" })?;
+        for (i, x) in args.iter().enumerate() { write!(f, "{}{:?}", if i == 0 { "" } else { ", " }, x)?; }
+        f.write_str(")")
+      }
+      Expr::NullIf(box (x, y)) => write!(f, "nullif({:?}, {:?})", x, y),
+      Expr::Random(None) => f.write_str("random()"), Expr::Random(Some(x)) => write!(f, "rand({:?})", x),
     }
   }
 }
\ No newline at end of file
@@ -9,19 +9,41 @@ pub use crate::{ast::*, parser::*};
 
 use typed_arena::Arena;
 
-use common::{ParserError as PE, ParserErrorKind::*, Error};
+use common::{ParserErrorKind::*, Error, CLit, Dialect};
 
 pub fn work<'a>(code: &'a str, alloc: &'a Arena<u8>) -> Result<Vec<Stmt<'a>>, Error<'a>> {
-  let mut p = Parser { pe: vec![], alloc };
-  match p.parse(&mut Lexer::new(code.as_bytes())) {
-    Ok(ss) if p.pe.is_empty() => Ok(ss),
-    Err(t) => {
-      match t.ty {
-        TokenKind::_Err => p.pe.push(PE { line: t.line, col: t.col, kind: UnexpectedChar(t.piece[0] as char) }),
-        _ => p.pe.push(PE { line: t.line, col: t.col, kind: SyntaxError }),
+  work_with_params(code, alloc, &[])
+}
+
+// placeholders in `code` are replaced by `params` (see `Parser::params`), it is an error if any of them is not bound
+pub fn work_with_params<'a>(code: &'a str, alloc: &'a Arena<u8>, params: &'a [CLit<'a>]) -> Result<Vec<Stmt<'a>>, Error<'a>> {
+  work_in_dialect(code, alloc, params, Dialect::Default)
+}
+
+// `dialect` is the dialect at the start of `code`, a `set dialect = ...` in it changes the dialect for the statements after it
+// after a syntax error, parsing restarts after the next semicolon, so the errors of all statements are reported at once
+pub fn work_in_dialect<'a>(code: &'a str, alloc: &'a Arena<u8>, params: &'a [CLit<'a>], dialect: Dialect) -> Result<Vec<Stmt<'a>>, Error<'a>> {
+  let mut p = Parser { pe: vec![], code, alloc, params, next_param: 0, dialect };
+  let (mut lexer, mut ret) = (Lexer::new(code.as_bytes()), vec![]);
+  loop {
+    match p.parse(&mut lexer) {
+      Ok(ss) => break ret.extend(ss),
+      Err(t) => {
+        match t.ty {
+          TokenKind::_Err => p.err(&t, UnexpectedChar(t.piece[0] as char)),
+          _ => p.err(&t, SyntaxError),
+        }
+        // the statement ends at the error token or the next semicolon, tokens in between (including unexpected chars) are skipped
+        let mut t = t;
+        loop {
+          match t.ty {
+            TokenKind::Semicolon => break,
+            TokenKind::_Eof => return Err(Error::ParserErrors(p.pe.into())),
+            _ => t = lexer.next(),
+          }
+        }
       }
-      Err(Error::ParserErrors(p.pe.into()))
     }
-    _ => Err(Error::ParserErrors(p.pe.into())),
   }
+  if p.pe.is_empty() { Ok(ret) } else { Err(Error::ParserErrors(p.pe.into())) }
 }
\ No newline at end of file
@@ -1,37 +1,120 @@
 use std::str::{self, FromStr};
 use typed_arena::Arena;
 
-use common::{BareTy::{*, self}, FixTy, ColTy, ParserError as PE, ParserErrorKind::*, Lit, CLit, AggOp::*, BinOp::*, CmpOp::*};
+use common::{BareTy::{*, self}, FixTy, ColTy, ParserError as PE, ParserErrorKind::{*, self}, Lit, CLit, AggOp::*, BinOp::{*, self}, CmpOp::{*, self}, StrFn, FkAction, DftFn, Setting, Dialect, Interval, IntervalUnit, MathFn};
 use crate::ast::*;
 use crate::Stmt::AddPrimary;
 
 pub struct Parser<'a> {
   pub pe: Vec<PE<'a>>,
+  // the sql text being parsed, tokens are slices of it
+  pub code: &'a str,
   // allocator for string
   pub alloc: &'a Arena<u8>,
+  // values bound to placeholders, `?` takes the next one, `$n` takes the nth one (starting from 1)
+  pub params: &'a [CLit<'a>],
+  // the number of `?` met
+  pub next_param: usize,
+  // it is changed by a `set dialect = ...` in the sql text, so the statements after it are parsed in the new dialect
+  pub dialect: Dialect,
 }
 
 impl<'p> Parser<'p> {
-  // it seems that sql doesn't support any escape characters (like \n, \t), in order to represent ', it uses ''
-  fn escape(&self, s: &'p str) -> &'p str {
-    if s.contains("''") {
-      let s = s.replace("''", "'");
+  // ' is represented by '' or \', and \\, \n, \r, \t, \uXXXX (4 hex digits) are escapes of \, newline, carriage return, tab, a unicode char
+  // other backslashes (including an invalid \u) are kept as they are
+  // if `like`, \\ is kept, since the pattern has its own backslash escapes (see `db::escape_re`)
+  fn escape_str(&self, s: &'p str, like: bool) -> &'p str {
+    if !s.contains('\\') { return self.unquote(s, "''", "'"); }
+    let (mut ret, mut chs) = (String::with_capacity(s.len()), s.chars());
+    while let Some(ch) = chs.next() {
+      match ch {
+        '\'' => (chs.next(), ret.push('\'')).1, // the lexer guarantees it is ''
+        '\\' => {
+          let rest = chs.as_str();
+          match chs.next() {
+            Some('\'') => ret.push('\''),
+            Some('\\') if !like => ret.push('\\'),
+            Some('n') => ret.push('\n'),
+            Some('r') => ret.push('\r'),
+            Some('t') => ret.push('\t'),
+            Some('u') => match rest.get(1..5).filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()))
+              .and_then(|h| std::char::from_u32(u32::from_str_radix(h, 16).unwrap())) {
+              Some(u) => (ret.push(u), chs = rest[5..].chars()).0,
+              None => ret += "\\u",
+            },
+            Some(ch) => (ret.push('\\'), ret.push(ch)).0,
+            None => ret.push('\\'),
+          }
+        }
+        ch => ret.push(ch),
+      }
+    }
+    unsafe { str::from_utf8_unchecked(self.alloc.alloc_extend(ret.bytes())) }
+  }
+
+  fn escape(&self, s: &'p str) -> &'p str { self.escape_str(s, false) }
+
+  // `l + i`, `l` is a date literal (or null), `op` is the `+` / `-` for the error position
+  fn add_interval(&mut self, l: CLit<'p>, op: &Token<'p>, i: Interval) -> CLit<'p> {
+    let s = match l.lit() { Lit::Null => return l, Lit::Str(s) => s, _ => op.str() };
+    match common::date(s).ok().and_then(|d| i.add(d)) {
+      Some(d) => CLit::new(Lit::Str(unsafe { str::from_utf8_unchecked(self.alloc.alloc_extend(d.format("%Y-%m-%d").to_string().bytes())) })),
+      None => (self.err(op, InvalidIntervalDate(s)), l).1,
+    }
+  }
+
+  // an int literal like 0xFF or 0b101 (`radix` is 16 or 2), it must be in the range of i32 like a decimal one
+  fn radix(&mut self, t: &Token<'p>, radix: u32) -> CLit<'p> {
+    let s = t.str();
+    let (neg, digits) = if s.starts_with('-') { (true, &s[3..]) } else { (false, &s[2..]) };
+    match i64::from_str_radix(digits, radix).ok().map(|x| if neg { -x } else { x }).filter(|&x| x as i32 as i64 == x) {
+      Some(x) => CLit::new(Lit::Number(x as f64)),
+      None => (self.err(t, InvalidInt(s)), CLit::new(Lit::Null)).1,
+    }
+  }
+
+  // the content of a quoted identifier, `"` in it is represented by `""`
+  fn escape_id(&self, s: &'p str) -> &'p str { self.unquote(s, "\"\"", "\"") }
+
+  fn unquote(&self, s: &'p str, doubled: &str, quote: &str) -> &'p str {
+    if s.contains(doubled) {
+      let s = s.replace(doubled, quote);
       let s = self.alloc.alloc_extend(s.bytes());
       unsafe { str::from_utf8_unchecked(s) }
     } else { s }
   }
+
+  // an error at token `t`, its offset is where `t` is in `code`, or the end of `code` if `t` is not in it (e.g. eof)
+  pub(crate) fn err(&mut self, t: &Token<'p>, kind: ParserErrorKind<'p>) {
+    let (start, p) = (self.code.as_ptr() as usize, t.piece.as_ptr() as usize);
+    let offset = if start <= p && p + t.piece.len() <= start + self.code.len() { p - start } else { self.code.len() };
+    self.pe.push(PE { line: t.line, col: t.col, offset: offset as u32, kind });
+  }
+
+  // token `t` is only accepted in `Dialect::MySql`
+  fn mysql(&mut self, t: &Token<'p>) {
+    if self.dialect != Dialect::MySql { self.err(t, MySqlOnly(t.str().trim_end_matches(|c: char| c == '(' || c.is_whitespace()))); }
+  }
+
+  fn set_dialect<'a>(&mut self, name: &'a str, val: CLit<'a>) {
+    if let (Some(Setting::Dialect), Some(d)) = (Setting::from_name(name), Setting::Dialect.parse(val.lit())) {
+      self.dialect = if d == Dialect::MySql as u32 { Dialect::MySql } else { Dialect::Default };
+    }
+  }
 }
 
 impl<'p> Token<'p> {
   fn str_trim(&self) -> &'p str { unsafe { str::from_utf8_unchecked(self.piece.get_unchecked(1..self.piece.len() - 1)) } }
   fn str(&self) -> &'p str { unsafe { str::from_utf8_unchecked(self.piece) } }
-  fn parse<T: FromStr + Default, U>(&self, ok: impl Fn(T) -> U, mut err: impl FnMut(u32, u32, &'p str)) -> U {
+  fn parse<T: FromStr + Default, U>(&self, ok: impl Fn(T) -> U, mut err: impl FnMut(&Token<'p>, &'p str)) -> U {
     let s = self.str();
-    ok(s.parse().unwrap_or_else(|_| (err(self.line, self.col, s), T::default()).1))
+    ok(s.parse().unwrap_or_else(|_| (err(self, s), T::default()).1))
   }
 }
 
 type FieldList<'p> = (Vec<ColDecl<'p>>, Vec<ColCons<'p>>);
+// (col, prefix, func) in create index
+type IndexCol<'p> = (&'p str, Option<u8>, Option<StrFn>);
 
 #[parser_macros::lalr1(Program)]
 #[use_unsafe]
@@ -41,8 +124,12 @@ priority = [
   { assoc = 'left', terms = ['And'] },
   { assoc = 'no_assoc', terms = ['Eq', 'Ne'] },
   { assoc = 'no_assoc', terms = ['Le', 'Ge', 'Lt', 'Gt'] },
+  { assoc = 'left', terms = ['BitOr'] },
+  { assoc = 'left', terms = ['BitAnd'] },
+  { assoc = 'left', terms = ['Shl', 'Shr'] },
   { assoc = 'left', terms = ['Add', 'Sub'] },
   { assoc = 'left', terms = ['Mul', 'Div', 'Mod'] },
+  { assoc = 'left', terms = ['BitXor'] },
   { assoc = 'no_assoc', terms = ['Is', 'Like'] },
   { assoc = 'no_assoc', terms = ['UMinus'] },
   { assoc = 'no_assoc', terms = ['RPar'] },
@@ -54,13 +141,25 @@ priority = [
 '(u|U)(s|S)(e|E)' = 'Use'
 '(s|S)(h|H)(o|O)(w|W)' = 'Show'
 '(d|D)(e|E)(s|S)(c|C)' = 'Desc'
+'(a|A)(s|S)(c|C)' = 'Asc'
+'(o|O)(r|R)(d|D)(e|E)(r|R)\s+(b|B)(y|Y)' = 'OrderBy'
+'(p|P)(a|A)(r|R)(t|T)(i|I)(t|T)(i|I)(o|O)(n|N)\s+(b|B)(y|Y)' = 'PartitionBy'
+'(l|L)(i|I)(m|M)(i|I)(t|T)' = 'Limit'
+'(r|R)(e|E)(t|T)(u|U)(r|R)(n|N)(i|I)(n|N)(g|G)' = 'Returning'
 '(a|A)(l|L)(t|T)(e|E)(r|R)\s+(t|T)(a|A)(b|B)(l|L)(e|E)' = 'AlterTable'
 '(a|A)(d|D)(d|D)' = 'Add1'
 '(r|R)(e|E)(n|N)(a|A)(m|M)(e|E)\s+(t|T)(o|O)' = 'RenameTo'
 '(d|D)(a|A)(t|T)(a|A)(b|B)(a|A)(s|S)(e|E)(s|S)' = 'DataBases'
 '(d|D)(a|A)(t|T)(a|A)(b|B)(a|A)(s|S)(e|E)' = 'DataBase'
+'(a|A)(t|T)(t|T)(a|A)(c|C)(h|H)' = 'Attach'
+'(d|D)(e|E)(t|T)(a|A)(c|C)(h|H)' = 'Detach'
+'(e|E)(x|X)(p|P)(l|L)(a|A)(i|I)(n|N)' = 'Explain'
+'(i|I)(n|N)(t|T)(e|E)(r|R)(v|V)(a|A)(l|L)' = 'Interval'
+'(t|T)(a|A)(b|B)(l|L)(e|E)(s|S)(a|A)(m|M)(p|P)(l|L)(e|E)' = 'TableSample'
+'(p|P)(e|E)(r|R)(c|C)(e|E)(n|N)(t|T)' = 'Percent'
 '(t|T)(a|A)(b|B)(l|L)(e|E)(s|S)' = 'Tables'
 '(t|T)(a|A)(b|B)(l|L)(e|E)' = 'Table'
+'(t|T)(e|E)(m|M)(p|P)(o|O)(r|R)(a|A)(r|R)(y|Y)' = 'Temporary'
 '(s|S)(e|E)(l|L)(e|E)(c|C)(t|T)' = 'Select'
 '(d|D)(e|E)(l|L)(e|E)(t|T)(e|E)' = 'Delete'
 '(i|I)(n|N)(s|S)(e|E)(r|R)(t|T)\s+(i|I)(n|N)(t|T)(o|O)' = 'InsertInto'
@@ -68,6 +167,8 @@ priority = [
 '(v|V)(a|A)(l|L)(u|U)(e|E)(s|S)' = 'Values'
 '(r|R)(e|E)(f|F)(e|E)(r|R)(e|E)(n|N)(c|C)(e|E)(s|S)' = 'References'
 '(s|S)(e|E)(t|T)' = 'Set'
+'(c|C)(a|A)(s|S)(c|C)(a|A)(d|D)(e|E)' = 'Cascade'
+'(r|R)(e|E)(s|S)(t|T)(r|R)(i|I)(c|C)(t|T)' = 'Restrict'
 '(f|F)(r|R)(o|O)(m|M)' = 'From'
 '(w|W)(h|H)(e|E)(r|R)(e|E)' = 'Where'
 '(s|S)(u|U)(m|M)' = 'Sum'
@@ -75,16 +176,68 @@ priority = [
 '(m|M)(i|I)(n|N)' = 'Min'
 '(m|M)(a|A)(x|X)' = 'Max'
 '(c|C)(o|O)(u|U)(n|N)(t|T)' = 'Count'
+'(a|A)(p|P)(p|P)(r|R)(o|O)(x|X)_(c|C)(o|O)(u|U)(n|N)(t|T)_(d|D)(i|I)(s|S)(t|T)(i|I)(n|N)(c|C)(t|T)' = 'ApproxCountDistinct'
+'(g|G)(r|R)(o|O)(u|U)(p|P)_(c|C)(o|O)(n|N)(c|C)(a|A)(t|T)' = 'GroupConcat'
+'(s|S)(t|T)(r|R)(i|I)(n|N)(g|G)_(a|A)(g|G)(g|G)' = 'StringAgg'
+'(s|S)(e|E)(p|P)(a|A)(r|R)(a|A)(t|T)(o|O)(r|R)' = 'Separator'
+'(g|G)(r|R)(o|O)(u|U)(p|P)' = 'Group'
+# a function name is only a keyword when followed by `(`, so they can still be col names
+'(l|L)(o|O)(w|W)(e|E)(r|R)\s*\(' = 'Lower'
+'(u|U)(p|P)(p|P)(e|E)(r|R)\s*\(' = 'Upper'
+'(l|L)(c|C)(a|A)(s|S)(e|E)\s*\(' = 'LCase'
+'(u|U)(c|C)(a|A)(s|S)(e|E)\s*\(' = 'UCase'
+'(s|S)(t|T)(d|D)(d|D)(e|E)(v|V)\s*\(' = 'Stddev'
+'(v|V)(a|A)(r|R)(i|I)(a|A)(n|N)(c|C)(e|E)\s*\(' = 'Variance'
+'(m|M)(e|E)(d|D)(i|I)(a|A)(n|N)\s*\(' = 'Median'
+'(p|P)(e|E)(r|R)(c|C)(e|E)(n|N)(t|T)(i|I)(l|L)(e|E)_(c|C)(o|O)(n|N)(t|T)\s*\(' = 'PercentileCont'
+'(a|A)(b|B)(s|S)\s*\(' = 'Abs'
+'(r|R)(o|O)(u|U)(n|N)(d|D)\s*\(' = 'Round'
+'(c|C)(e|E)(i|I)(l|L)((i|I)(n|N)(g|G))?\s*\(' = 'Ceil'
+'(f|F)(l|L)(o|O)(o|O)(r|R)\s*\(' = 'Floor'
+'(m|M)(o|O)(d|D)\s*\(' = 'ModFn'
+'(p|P)(o|O)(w|W)((e|E)(r|R))?\s*\(' = 'Power'
+'(s|S)(q|Q)(r|R)(t|T)\s*\(' = 'Sqrt'
+'(c|C)(o|O)(a|A)(l|L)(e|E)(s|S)(c|C)(e|E)\s*\(' = 'Coalesce'
+'(n|N)(u|U)(l|L)(l|L)(i|I)(f|F)\s*\(' = 'NullIf'
+'(i|I)(f|F)(n|N)(u|U)(l|L)(l|L)\s*\(' = 'IfNull'
+'(g|G)(r|R)(e|E)(a|A)(t|T)(e|E)(s|S)(t|T)\s*\(' = 'Greatest'
+'(l|L)(e|E)(a|A)(s|S)(t|T)\s*\(' = 'Least'
+'(r|R)(a|A)(n|N)(d|D)(o|O)(m|M)\s*\(' = 'Random'
+'(r|R)(a|A)(n|N)(d|D)\s*\(' = 'Rand'
+'(m|M)(a|A)(t|T)(c|C)(h|H)\s*\(' = 'Match'
+'(a|A)(g|G)(a|A)(i|I)(n|N)(s|S)(t|T)\s*\(' = 'Against'
+'(f|F)(u|U)(l|L)(l|L)(t|T)(e|E)(x|X)(t|T)\s+(i|I)(n|N)(d|D)(e|E)(x|X)' = 'FulltextIndex'
+'(s|S)(p|P)(a|A)(t|T)(i|I)(a|A)(l|L)' = 'Spatial'
+'(w|W)(i|I)(t|T)(h|H)(i|I)(n|N)' = 'Within'
+'(a|A)(l|L)(l|L)' = 'All'
+'(a|A)(n|N)(y|Y)' = 'Any'
+'(s|S)(o|O)(m|M)(e|E)' = 'Some1'
+'(r|R)(e|E)(i|I)(n|N)(d|D)(e|E)(x|X)' = 'Reindex'
+'(a|A)(n|N)(a|A)(l|L)(y|Y)(z|Z)(e|E)' = 'Analyze'
+'(o|O)(p|P)(t|T)(i|I)(m|M)(i|I)(z|Z)(e|E)' = 'Optimize'
+'(r|R)(e|E)(p|P)(a|A)(i|I)(r|R)' = 'Repair'
 '(n|N)(o|O)(t|T)\s+(n|N)(u|U)(l|L)(l|L)' = 'NotNull'
 '(p|P)(r|R)(i|I)(m|M)(a|A)(r|R)(y|Y)\s+(k|K)(e|E)(y|Y)' = 'PrimaryKey'
+'(f|F)(o|O)(r|R)(e|E)(i|I)(g|G)(n|N)\s+(k|K)(e|E)(y|Y)(s|S)' = 'ForeignKeys'
 '(f|F)(o|O)(r|R)(e|E)(i|I)(g|G)(n|N)\s+(k|K)(e|E)(y|Y)' = 'ForeignKey'
 '(u|U)(n|N)(i|I)(q|Q)(u|U)(e|E)' = 'Unique'
 '(l|L)(i|I)(k|K)(e|E)' = 'Like'
 '(i|I)(n|N)(d|D)(e|E)(x|X)' = 'Index'
 '(c|C)(h|H)(e|E)(c|C)(k|K)' = 'Check'
+'(c|C)(o|O)(m|M)(m|M)(e|E)(n|N)(t|T)' = 'Comment'
+'(c|C)(o|O)(l|L)(u|U)(m|M)(n|N)' = 'Column'
+'(p|P)(r|R)(a|A)(g|G)(m|M)(a|A)' = 'Pragma'
+'(p|P)(e|E)(r|R)(s|S)(i|I)(s|S)(t|T)' = 'Persist'
 '(d|D)(e|E)(f|F)(a|A)(u|U)(l|L)(t|T)' = 'Default'
+'(c|C)(u|U)(r|R)(r|R)(e|E)(n|N)(t|T)_(d|D)(a|A)(t|T)(e|E)' = 'CurrentDate'
+'((c|C)(u|U)(r|R)(r|R)(e|E)(n|N)(t|T)_(t|T)(i|I)(m|M)(e|E)(s|S)(t|T)(a|A)(m|M)(p|P))|((n|N)(o|O)(w|W)\s*\(\s*\))' = 'Now'
+'(u|U)(u|U)(i|I)(d|D)4\s*\(\s*\)' = 'Uuid4'
+'(c|C)(u|U)(r|R)(d|D)(a|A)(t|T)(e|E)\s*\(\s*\)' = 'CurDate'
+'(u|U)(u|U)(i|I)(d|D)\s*\(\s*\)' = 'Uuid'
+'(i|I)(n|N)(t|T)(o|O)' = 'Into'
 '(i|I)(n|N)' = 'In'
 '(o|O)(n|N)' = 'On'
+'(a|A)(s|S)' = 'As'
 '(i|I)(s|S)' = 'Is'
 '(b|B)(i|I)(g|G)(i|I)(n|N)(t|T)' = 'Int' # handle bigint as int, decimal as float
 '(i|I)(n|N)(t|T)(e|E)(g|G)(e|E)(r|R)' = 'Int'
@@ -96,6 +249,7 @@ priority = [
 '(f|F)(l|L)(o|O)(a|A)(t|T)' = 'Float'
 '(d|D)(a|A)(t|T)(e|E)' = 'Date'
 '(a|A)(n|N)(d|D)' = 'And'
+'(b|B)(e|E)(t|T)(w|W)(e|E)(e|E)(n|N)' = 'Between'
 '(o|O)(r|R)' = 'Or'
 '(n|N)(u|U)(l|L)(l|L)' = 'Null'
 '(t|T)(r|R)(u|U)(e|E)' = 'True'
@@ -113,20 +267,39 @@ priority = [
 '\*' = 'Mul'
 '/' = 'Div'
 '%' = 'Mod'
+'&' = 'BitAnd'
+'\|' = 'BitOr'
+'\^' = 'BitXor'
+'<<' = 'Shl'
+'>>' = 'Shr'
+'~' = 'BitNot'
 '\.' = 'Dot'
 ',' = 'Comma'
 ';' = 'Semicolon'
+'\?' = 'Param'
+'\$\d+' = 'Param'
 '--[^\n]*' = '_Eps'
+'/\*([^\*]|\*+[^\*/])*\*+/' = '_Eps'
 '\s+' = '_Eps'
+'-?0(x|X)[0-9A-Fa-f]+' = 'HexLit'
+'-?0(b|B)[01]+' = 'BinLit'
+"(x|X)'[0-9A-Fa-f]*'" = 'BytesLit'
 '-?\d+\.\d*' = 'FloatLit'
 '-?\d+' = 'IntLit'
-"'(('')|[^'])*'" = 'StrLit'
+"'(('')|(\\\\.)|[^'\\\\])*'" = 'StrLit'
 '[A-Za-z]\w*' = 'Id1'
+'`[^`]+`' = 'BackQuoted'
+'"(("")|[^"])+"' = 'Quoted'
 '.' = '_Err'
 "##)]
 impl<'p> Parser<'p> {
   #[rule(Id -> Id1)]
   fn id(t: Token) -> &'p str { t.str() }
+  #[rule(Id -> BackQuoted)]
+  fn id_back_quoted(&mut self, t: Token) -> &'p str { (self.mysql(&t), t.str_trim()).1 }
+  // it is never a keyword, and may contain spaces or upper case letters, which are kept as they are
+  #[rule(Id -> Quoted)]
+  fn id_quoted(&self, t: Token) -> &'p str { self.escape_id(t.str_trim()) }
 
   #[rule(Program ->)]
   fn stmt_list0() -> Vec<Stmt<'p>> { vec![] }
@@ -146,42 +319,146 @@ impl<'p> Parser<'p> {
   #[rule(Stmt -> Use DataBase Id)]
   fn stmt_use_db1(_: Token, _: Token, db: &'p str) -> Stmt<'p> { Stmt::UseDb(db) }
   #[rule(Stmt -> Drop Table Id)]
-  fn stmt_drop_table(_: Token, _: Token, table: &'p str) -> Stmt<'p> { Stmt::DropTable(table) }
-  #[rule(Stmt -> Create Index Id On Id LPar Id RPar)]
-  fn stmt_create_index(_: Token, _: Token, index: &'p str, _: Token, table: &'p str, _: Token, col: &'p str, _: Token) -> Stmt<'p> { CreateIndex { index, table, col }.into() }
+  fn stmt_drop_table0(_: Token, _: Token, table: &'p str) -> Stmt<'p> { Stmt::DropTable { table, cascade: false } }
+  #[rule(Stmt -> Drop Table Id Cascade)]
+  fn stmt_drop_table1(_: Token, _: Token, table: &'p str, _: Token) -> Stmt<'p> { Stmt::DropTable { table, cascade: true } }
+  #[rule(Stmt -> Create Index Id On Id LPar IndexCol AscDesc RPar PartialM)]
+  fn stmt_create_index(_: Token, _: Token, index: &'p str, _: Token, table: &'p str, _: Token, c: IndexCol<'p>, desc: bool, _: Token, where_: Option<Cond<'p>>) -> Stmt<'p> {
+    CreateIndex { index, table, col: c.0, desc, prefix: c.1, func: c.2, where_, fulltext: false, spatial: None }.into()
+  }
+  #[rule(Stmt -> Create FulltextIndex Id On Id LPar Id RPar)]
+  fn stmt_create_fulltext(_: Token, _: Token, index: &'p str, _: Token, table: &'p str, _: Token, col: &'p str, _: Token) -> Stmt<'p> {
+    CreateIndex { index, table, col, desc: false, prefix: None, func: None, where_: None, fulltext: true, spatial: None }.into()
+  }
+  #[rule(Stmt -> Create Spatial Index Id On Id LPar Id Comma Id RPar)]
+  fn stmt_create_spatial(_: Token, _: Token, _: Token, index: &'p str, _: Token, table: &'p str, _: Token, col: &'p str, _: Token, y: &'p str, _: Token) -> Stmt<'p> {
+    CreateIndex { index, table, col, desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: Some(y) }.into()
+  }
+  #[rule(Stmt -> Reindex Id)]
+  fn stmt_reindex0(_: Token, table: &'p str) -> Stmt<'p> { Stmt::Reindex { table, col: None } }
+  #[rule(Stmt -> Reindex Id LPar Id RPar)]
+  fn stmt_reindex1(_: Token, table: &'p str, _: Token, col: &'p str, _: Token) -> Stmt<'p> { Stmt::Reindex { table, col: Some(col) } }
+  #[rule(Stmt -> Analyze)]
+  fn stmt_analyze0(_: Token) -> Stmt<'p> { Stmt::Analyze(None) }
+  #[rule(Stmt -> Analyze Id)]
+  fn stmt_analyze1(_: Token, table: &'p str) -> Stmt<'p> { Stmt::Analyze(Some(table)) }
+  #[rule(Stmt -> Optimize Table Id)]
+  fn stmt_optimize(_: Token, _: Token, table: &'p str) -> Stmt<'p> { Stmt::Optimize(table) }
   #[rule(Stmt -> Drop Index Id)]
   fn stmt_drop_index(_: Token, _: Token, index: &'p str) -> Stmt<'p> { Stmt::DropIndex { index, table: None } }
   #[rule(Stmt -> Create Table Id LPar FieldList RPar)]
   fn stmt_create_table(_: Token, _: Token, table: &'p str, _: Token, (cols, cons): FieldList<'p>, _: Token) -> Stmt<'p> { CreateTable { table, cols, cons }.into() }
+  #[rule(Stmt -> Create Table Id LPar FieldList RPar Partition)]
+  fn stmt_create_partitioned(_: Token, _: Token, table: &'p str, _: Token, (cols, cons): FieldList<'p>, _: Token, part: Partition<'p>) -> Stmt<'p> {
+    Stmt::CreatePartitioned { create: CreateTable { table, cols, cons }, part }
+  }
+  #[rule(Stmt -> Create Table Id As Query)]
+  fn stmt_create_table_as0(_: Token, _: Token, table: &'p str, _: Token, select: Select<'p>) -> Stmt<'p> { CreateTableAs { table, cols: None, select }.into() }
+  #[rule(Stmt -> Create Table Id LPar IdList RPar As Query)]
+  fn stmt_create_table_as1(_: Token, _: Token, table: &'p str, _: Token, cols: Vec<&'p str>, _: Token, _: Token, select: Select<'p>) -> Stmt<'p> {
+    CreateTableAs { table, cols: Some(cols), select }.into()
+  }
+  #[rule(Stmt -> Create Table Id Like Id)]
+  fn stmt_create_table_like(_: Token, _: Token, table: &'p str, _: Token, like: &'p str) -> Stmt<'p> { Stmt::CreateTableLike { table, like } }
+  #[rule(Stmt -> Create Temporary Table Id LPar FieldList RPar)]
+  fn stmt_create_temp_table(_: Token, _: Token, _: Token, table: &'p str, _: Token, (cols, cons): FieldList<'p>, _: Token) -> Stmt<'p> {
+    Stmt::CreateTempTable(box CreateTable { table, cols, cons })
+  }
   #[rule(Stmt -> Show Tables)]
   fn stmt_show_tables(_: Token, _: Token) -> Stmt<'p> { Stmt::ShowTables }
   #[rule(Stmt -> Desc Id)]
   fn stmt_show_table0(_: Token, table: &'p str) -> Stmt<'p> { Stmt::ShowTable(table) }
   #[rule(Stmt -> Show Table Id)]
   fn stmt_show_table1(_: Token, _: Token, table: &'p str) -> Stmt<'p> { Stmt::ShowTable(table) }
-  #[rule(Stmt -> Select Mul From IdList WhereM)]
-  fn stmt_select0(_: Token, _: Token, _: Token, tables: Vec<&'p str>, where_: Vec<Cond<'p>>) -> Stmt<'p> { Select { ops: None, tables, where_ }.into() }
-  #[rule(Stmt -> Select AggList From IdList WhereM)]
-  fn stmt_select1(_: Token, ops: Vec<Agg<'p>>, _: Token, tables: Vec<&'p str>, where_: Vec<Cond<'p>>) -> Stmt<'p> { Select { ops: Some(ops), tables, where_ }.into() }
+  #[rule(Stmt -> Query)]
+  fn stmt_select(s: Select<'p>) -> Stmt<'p> { s.into() }
+  #[rule(Stmt -> Explain Query)]
+  fn stmt_explain0(_: Token, select: Select<'p>) -> Stmt<'p> { Explain { select, format: ExplainFormat::Text }.into() }
+  #[rule(Stmt -> Explain LPar Id1 Id1 RPar Query)]
+  fn stmt_explain1(&mut self, _: Token, _: Token, format: Token, name: Token, _: Token, select: Select<'p>) -> Stmt<'p> {
+    if !format.str().eq_ignore_ascii_case("format") { self.err(&format, SyntaxError); }
+    let format = match name.str().to_ascii_lowercase().as_str() {
+      "json" => ExplainFormat::Json, "dot" => ExplainFormat::Dot, "tree" => ExplainFormat::Tree,
+      _ => (self.err(&name, SyntaxError), ExplainFormat::Text).1,
+    };
+    Explain { select, format }.into()
+  }
   #[rule(Stmt -> InsertInto Id Values LitListList)]
   fn stmt_insert0(_: Token, table: &'p str, _: Token, vals: Vec<Vec<CLit<'p>>>) -> Stmt<'p> { Insert { table, cols: None, vals }.into() }
   #[rule(Stmt -> InsertInto Id LPar IdList RPar Values LitListList)]
   fn stmt_insert1(_: Token, table: &'p str, _: Token, cols: Vec<&'p str>, _: Token, _: Token, vals: Vec<Vec<CLit<'p>>>) -> Stmt<'p> { Insert { table, cols: Some(cols), vals }.into() }
+  #[rule(Stmt -> InsertInto Id Values LitListList Returning RetCols)]
+  fn stmt_insert2(_: Token, table: &'p str, _: Token, vals: Vec<Vec<CLit<'p>>>, _: Token, ret: Option<Vec<&'p str>>) -> Stmt<'p> {
+    Returning { stmt: box Stmt::Insert(Insert { table, cols: None, vals }), cols: ret }.into()
+  }
+  #[rule(Stmt -> InsertInto Id LPar IdList RPar Values LitListList Returning RetCols)]
+  fn stmt_insert3(_: Token, table: &'p str, _: Token, cols: Vec<&'p str>, _: Token, _: Token, vals: Vec<Vec<CLit<'p>>>, _: Token, ret: Option<Vec<&'p str>>) -> Stmt<'p> {
+    Returning { stmt: box Stmt::Insert(Insert { table, cols: Some(cols), vals }), cols: ret }.into()
+  }
   #[rule(Stmt -> Update Id Set SetList WhereM)]
   fn stmt_update(_: Token, table: &'p str, _: Token, sets: Vec<(&'p str, Expr<'p>)>, where_: Vec<Cond<'p>>) -> Stmt<'p> { Update { table, sets, where_ }.into() }
   #[rule(Stmt -> Delete From Id WhereM)]
   fn stmt_delete(_: Token, _: Token, table: &'p str, where_: Vec<Cond<'p>>) -> Stmt<'p> { Delete { table, where_ }.into() }
+  #[rule(Stmt -> Update Id Set SetList WhereM Returning RetCols)]
+  fn stmt_update1(_: Token, table: &'p str, _: Token, sets: Vec<(&'p str, Expr<'p>)>, where_: Vec<Cond<'p>>, _: Token, ret: Option<Vec<&'p str>>) -> Stmt<'p> {
+    Returning { stmt: box Stmt::Update(Update { table, sets, where_ }), cols: ret }.into()
+  }
+  #[rule(Stmt -> Delete From Id WhereM Returning RetCols)]
+  fn stmt_delete1(_: Token, _: Token, table: &'p str, where_: Vec<Cond<'p>>, _: Token, ret: Option<Vec<&'p str>>) -> Stmt<'p> {
+    Returning { stmt: box Stmt::Delete(Delete { table, where_ }), cols: ret }.into()
+  }
+
+  #[rule(Stmt -> Attach StrLit As Id)]
+  fn stmt_attach0(_: Token, path: Token, _: Token, alias: &'p str) -> Stmt<'p> { Stmt::Attach { path: path.str_trim(), alias } }
+  #[rule(Stmt -> Attach DataBase StrLit As Id)]
+  fn stmt_attach1(_: Token, _: Token, path: Token, _: Token, alias: &'p str) -> Stmt<'p> { Stmt::Attach { path: path.str_trim(), alias } }
+  #[rule(Stmt -> Detach Id)]
+  fn stmt_detach0(_: Token, alias: &'p str) -> Stmt<'p> { Stmt::Detach(alias) }
+  #[rule(Stmt -> Detach DataBase Id)]
+  fn stmt_detach1(_: Token, _: Token, alias: &'p str) -> Stmt<'p> { Stmt::Detach(alias) }
 
-  #[rule(Stmt -> AlterTable Id Add1 Index Id On LPar Id RPar)]
-  fn alter_create_index1(_: Token, table: &'p str, _: Token, _: Token, index: &'p str, _: Token, _: Token, col: &'p str, _: Token) -> Stmt<'p> { CreateIndex { index, table, col }.into() }
+  #[rule(Stmt -> AlterTable Id Add1 Index Id On LPar IndexCol AscDesc RPar PartialM)]
+  fn alter_create_index1(_: Token, table: &'p str, _: Token, _: Token, index: &'p str, _: Token, _: Token, c: IndexCol<'p>, desc: bool, _: Token, where_: Option<Cond<'p>>) -> Stmt<'p> {
+    CreateIndex { index, table, col: c.0, desc, prefix: c.1, func: c.2, where_, fulltext: false, spatial: None }.into()
+  }
+  #[rule(Stmt -> AlterTable Id Add1 FulltextIndex Id On LPar Id RPar)]
+  fn alter_create_fulltext(_: Token, table: &'p str, _: Token, _: Token, index: &'p str, _: Token, _: Token, col: &'p str, _: Token) -> Stmt<'p> {
+    CreateIndex { index, table, col, desc: false, prefix: None, func: None, where_: None, fulltext: true, spatial: None }.into()
+  }
+  #[rule(Stmt -> AlterTable Id Add1 Spatial Index Id On LPar Id Comma Id RPar)]
+  fn alter_create_spatial(_: Token, table: &'p str, _: Token, _: Token, _: Token, index: &'p str, _: Token, _: Token, col: &'p str, _: Token, y: &'p str, _: Token) -> Stmt<'p> {
+    CreateIndex { index, table, col, desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: Some(y) }.into()
+  }
   #[rule(Stmt -> AlterTable Id Drop Index Id)]
   fn alter_drop_index1(_: Token, table: &'p str, _: Token, _: Token, index: &'p str) -> Stmt<'p> { Stmt::DropIndex { index, table: Some(table) } }
   #[rule(Stmt -> AlterTable Id RenameTo Id)]
   fn alter_rename(_: Token, old: &'p str, _: Token, new: &'p str) -> Stmt<'p> { Stmt::Rename { old, new } }
-  #[rule(Stmt -> AlterTable Id Add1 ForeignKey LPar Id RPar References Id LPar Id RPar)]
-  fn alter_add_foreign(_: Token, table: &'p str, _: Token, _: Token, _: Token, col: &'p str, _: Token, _: Token, f_table: &'p str, _: Token, f_col: &'p str, _: Token) -> Stmt<'p> { AddForeign { table, col, f_table, f_col }.into() }
+  #[rule(Stmt -> AlterTable Id Add1 ForeignKey LPar Id RPar References Id LPar Id RPar FkActions)]
+  fn alter_add_foreign(_: Token, table: &'p str, _: Token, _: Token, _: Token, col: &'p str, _: Token, _: Token, f_table: &'p str, _: Token, f_col: &'p str, _: Token, (on_delete, on_update): (FkAction, FkAction)) -> Stmt<'p> { AddForeign { table, col, f_table, f_col, on_delete, on_update }.into() }
   #[rule(Stmt -> AlterTable Id Drop ForeignKey Id)]
   fn alter_drop_foreign(_: Token, table: &'p str, _: Token, _: Token, col: &'p str) -> Stmt<'p> { Stmt::DropForeign { table, col } }
+  #[rule(Stmt -> Check ForeignKeys)]
+  fn stmt_check_foreign_keys(_: Token, _: Token) -> Stmt<'p> { Stmt::CheckForeignKeys }
+  #[rule(Stmt -> Check DataBase)]
+  fn stmt_check_db(_: Token, _: Token) -> Stmt<'p> { Stmt::CheckDb }
+  #[rule(Stmt -> Repair DataBase)]
+  fn stmt_repair_db0(_: Token, _: Token) -> Stmt<'p> { Stmt::RepairDb(None) }
+  #[rule(Stmt -> Repair DataBase Id Into Id)]
+  fn stmt_repair_db1(_: Token, _: Token, src: &'p str, _: Token, dst: &'p str) -> Stmt<'p> { Stmt::RepairDb(Some((src, dst))) }
+  #[rule(Stmt -> Comment On Table Id Is StrLit)]
+  fn stmt_comment0(&self, _: Token, _: Token, _: Token, table: &'p str, _: Token, s: Token) -> Stmt<'p> { Stmt::Comment { table, col: None, comment: self.escape(s.str_trim()) } }
+  #[rule(Stmt -> Comment On Column Id Dot Id Is StrLit)]
+  fn stmt_comment1(&self, _: Token, _: Token, _: Token, table: &'p str, _: Token, col: &'p str, _: Token, s: Token) -> Stmt<'p> {
+    Stmt::Comment { table, col: Some(col), comment: self.escape(s.str_trim()) }
+  }
+  #[rule(Stmt -> Set Id Eq SetVal)]
+  fn stmt_set0(&mut self, _: Token, name: &'p str, _: Token, val: CLit<'p>) -> Stmt<'p> { (self.set_dialect(name, val), Stmt::Set { name, val, persist: false }).1 }
+  #[rule(Stmt -> Set Persist Id Eq SetVal)]
+  fn stmt_set1(&mut self, _: Token, _: Token, name: &'p str, _: Token, val: CLit<'p>) -> Stmt<'p> { (self.set_dialect(name, val), Stmt::Set { name, val, persist: true }).1 }
+  #[rule(Stmt -> Pragma Id Eq SetVal)]
+  fn stmt_pragma0(&mut self, _: Token, name: &'p str, _: Token, val: CLit<'p>) -> Stmt<'p> { (self.set_dialect(name, val), Stmt::Set { name, val, persist: false }).1 }
+  #[rule(Stmt -> Pragma Id)]
+  fn stmt_pragma1(_: Token, name: &'p str) -> Stmt<'p> { Stmt::ShowSetting(name) }
   #[rule(Stmt -> AlterTable Id Add1 PrimaryKey LPar IdList RPar)]
   fn alter_add_primary(_: Token, table: &'p str, _: Token, _: Token, _: Token, cols: Vec<&'p str>, _: Token) -> Stmt<'p> { AddPrimary { table, cols }.into() }
   #[rule(Stmt -> AlterTable Id Drop PrimaryKey LPar IdList RPar)]
@@ -190,22 +467,131 @@ impl<'p> Parser<'p> {
   fn alter_add_col(_: Token, table: &'p str, _: Token, col: ColDecl<'p>) -> Stmt<'p> { Stmt::AddCol { table, col } }
   #[rule(Stmt -> AlterTable Id Drop Id)]
   fn alter_drop_col(_: Token, table: &'p str, _: Token, col: &'p str) -> Stmt<'p> { Stmt::DropCol { table, col } }
+  // `partition` is not a keyword, so it can still be the name of a col
+  #[rule(Stmt -> AlterTable Id Drop Id1 IntLit)]
+  fn alter_drop_partition(&mut self, _: Token, table: &'p str, _: Token, partition: Token, t: Token) -> Stmt<'p> {
+    if !partition.str().eq_ignore_ascii_case("partition") { self.err(&partition, SyntaxError); }
+    Stmt::DropPartition { table, part: t.parse(|x| x, |t, s| self.err(t, InvalidInt(s))) }
+  }
+
+  #[rule(Partition -> PartitionBy Id1 LPar Id RPar LPar LitList RPar)]
+  fn partition(&mut self, _: Token, kind: Token, _: Token, col: &'p str, _: Token, _: Token, vals: Vec<CLit<'p>>, _: Token) -> Partition<'p> {
+    let hash = kind.str().eq_ignore_ascii_case("hash");
+    if !hash && !kind.str().eq_ignore_ascii_case("range") { self.err(&kind, SyntaxError); }
+    Partition { col, hash, vals }
+  }
+
+  #[rule(Query -> Select Mul From TableList WhereM OrderM LimitM)]
+  fn query0(_: Token, _: Token, _: Token, (tables, derived): (Vec<TableRef<'p>>, Vec<Derived<'p>>), where_: Vec<Cond<'p>>, order: Option<(ColRef<'p>, bool)>, (limit, offset): (Option<u32>, u32)) -> Select<'p> {
+    Select { ops: None, tables, where_, order, limit, offset, derived }
+  }
+  #[rule(Query -> Select AggList From TableList WhereM OrderM LimitM)]
+  fn query1(_: Token, ops: Vec<Agg<'p>>, _: Token, (tables, derived): (Vec<TableRef<'p>>, Vec<Derived<'p>>), where_: Vec<Cond<'p>>, order: Option<(ColRef<'p>, bool)>, (limit, offset): (Option<u32>, u32)) -> Select<'p> {
+    Select { ops: Some(ops), tables, where_, order, limit, offset, derived }
+  }
+
+  #[rule(OrderM -> OrderBy ColRef AscDesc)]
+  fn order_m1(_: Token, col: ColRef<'p>, desc: bool) -> Option<(ColRef<'p>, bool)> { Some((col, desc)) }
+  #[rule(OrderM ->)]
+  fn order_m0() -> Option<(ColRef<'p>, bool)> { None }
+
+  // (limit, offset)
+  #[rule(LimitM -> Limit IntLit)]
+  fn limit_m1(&mut self, _: Token, t: Token) -> (Option<u32>, u32) { (t.parse(Some, |t, s| self.err(t, InvalidInt(s))), 0) }
+  #[rule(LimitM -> Limit IntLit Comma IntLit)]
+  fn limit_m2(&mut self, _: Token, offset: Token, comma: Token, t: Token) -> (Option<u32>, u32) {
+    self.mysql(&comma);
+    (t.parse(Some, |t, s| self.err(t, InvalidInt(s))), offset.parse(|x| x, |t, s| self.err(t, InvalidInt(s))))
+  }
+  #[rule(LimitM ->)]
+  fn limit_m0() -> (Option<u32>, u32) { (None, 0) }
+
+  #[rule(IndexCol -> Id PrefixM)]
+  fn index_col0(col: &'p str, prefix: Option<u8>) -> IndexCol<'p> { (col, prefix, None) }
+  #[rule(IndexCol -> StrFn Id RPar)]
+  fn index_col1(func: StrFn, col: &'p str, _: Token) -> IndexCol<'p> { (col, None, Some(func)) }
+
+  #[rule(PartialM -> Where Cond)]
+  fn partial_m1(_: Token, c: Cond<'p>) -> Option<Cond<'p>> { Some(c) }
+  #[rule(PartialM ->)]
+  fn partial_m0() -> Option<Cond<'p>> { None }
+
+  #[rule(PrefixM -> LPar IntLit RPar)]
+  fn prefix_m1(&mut self, _: Token, t: Token, _: Token) -> Option<u8> { t.parse(Some, |t, s| self.err(t, InvalidInt(s))) }
+  #[rule(PrefixM ->)]
+  fn prefix_m0() -> Option<u8> { None }
+
+  #[rule(AscDesc -> Asc)]
+  fn asc_desc_asc(_: Token) -> bool { false }
+  #[rule(AscDesc -> Desc)]
+  fn asc_desc_desc(_: Token) -> bool { true }
+  #[rule(AscDesc ->)]
+  fn asc_desc0() -> bool { false }
 
   #[rule(WhereM -> Where CondList)]
   fn where_m1(_: Token, where_: Vec<Cond<'p>>) -> Vec<Cond<'p>> { where_ }
   #[rule(WhereM ->)]
   fn where_m0() -> Vec<Cond<'p>> { vec![] }
 
+  // the cols after `returning`, None for *
+  #[rule(RetCols -> Mul)]
+  fn ret_cols0(_: Token) -> Option<Vec<&'p str>> { None }
+  #[rule(RetCols -> IdList)]
+  fn ret_cols1(cols: Vec<&'p str>) -> Option<Vec<&'p str>> { Some(cols) }
+
   #[rule(IdList -> Id)]
   fn id_list0(i: &'p str) -> Vec<&'p str> { vec![i] }
   #[rule(IdList -> IdList Comma Id)]
   fn id_list1(mut il: Vec<&'p str>, _: Token, i: &'p str) -> Vec<&'p str> { (il.push(i), il).1 }
 
+  // (tables, derived tables among them)
+  #[rule(TableList -> TableRef)]
+  fn table_list0(t: TableRef<'p>) -> (Vec<TableRef<'p>>, Vec<Derived<'p>>) { (vec![t], vec![]) }
+  #[rule(TableList -> Derived)]
+  fn table_list1(d: Derived<'p>) -> (Vec<TableRef<'p>>, Vec<Derived<'p>>) { (vec![d.table_ref()], vec![d]) }
+  #[rule(TableList -> TableList Comma TableRef)]
+  fn table_list2((mut tl, dl): (Vec<TableRef<'p>>, Vec<Derived<'p>>), _: Token, t: TableRef<'p>) -> (Vec<TableRef<'p>>, Vec<Derived<'p>>) { (tl.push(t), (tl, dl)).1 }
+  #[rule(TableList -> TableList Comma Derived)]
+  fn table_list3((mut tl, mut dl): (Vec<TableRef<'p>>, Vec<Derived<'p>>), _: Token, d: Derived<'p>) -> (Vec<TableRef<'p>>, Vec<Derived<'p>>) {
+    (tl.push(d.table_ref()), dl.push(d), (tl, dl)).2
+  }
+
+  #[rule(Derived -> DerivedSrc Id)]
+  fn derived0(src: DerivedSrc<'p>, alias: &'p str) -> Derived<'p> { Derived { alias, cols: None, src } }
+  #[rule(Derived -> DerivedSrc As Id)]
+  fn derived1(src: DerivedSrc<'p>, _: Token, alias: &'p str) -> Derived<'p> { Derived { alias, cols: None, src } }
+  #[rule(Derived -> DerivedSrc Id LPar IdList RPar)]
+  fn derived2(src: DerivedSrc<'p>, alias: &'p str, _: Token, cols: Vec<&'p str>, _: Token) -> Derived<'p> { Derived { alias, cols: Some(cols), src } }
+  #[rule(Derived -> DerivedSrc As Id LPar IdList RPar)]
+  fn derived3(src: DerivedSrc<'p>, _: Token, alias: &'p str, _: Token, cols: Vec<&'p str>, _: Token) -> Derived<'p> { Derived { alias, cols: Some(cols), src } }
+
+  #[rule(DerivedSrc -> LPar Query RPar)]
+  fn derived_src0(_: Token, s: Select<'p>, _: Token) -> DerivedSrc<'p> { DerivedSrc::Select(s) }
+  #[rule(DerivedSrc -> LPar Values LitListList RPar)]
+  fn derived_src1(_: Token, _: Token, rows: Vec<Vec<CLit<'p>>>, _: Token) -> DerivedSrc<'p> { DerivedSrc::Values(rows) }
+
+  #[rule(TableRef -> Id SampleM)]
+  fn table_ref0(table: &'p str, sample: Option<f32>) -> TableRef<'p> { TableRef { db: None, table, sample } }
+  #[rule(TableRef -> Id Dot Id SampleM)]
+  fn table_ref1(db: &'p str, _: Token, table: &'p str, sample: Option<f32>) -> TableRef<'p> { TableRef { db: Some(db), table, sample } }
+
+  #[rule(SampleM -> TableSample LPar IntLit Percent RPar)]
+  fn sample_m1(&mut self, _: Token, _: Token, t: Token, _: Token, _: Token) -> Option<f32> { t.parse(Some, |t, s| self.err(t, InvalidInt(s))) }
+  #[rule(SampleM -> TableSample LPar FloatLit Percent RPar)]
+  fn sample_m2(&mut self, _: Token, _: Token, t: Token, _: Token, _: Token) -> Option<f32> { t.parse(Some, |t, s| self.err(t, InvalidFloat(s))) }
+  #[rule(SampleM ->)]
+  fn sample_m0() -> Option<f32> { None }
+
   #[rule(AggList -> Agg)]
   fn agg_list0(a: Agg<'p>) -> Vec<Agg<'p>> { vec![a] }
   #[rule(AggList -> AggList Comma Agg)]
   fn agg_list1(mut al: Vec<Agg<'p>>, _: Token, a: Agg<'p>) -> Vec<Agg<'p>> { (al.push(a), al).1 }
 
+  #[rule(ExprList -> Expr)]
+  fn expr_list0(e: Expr<'p>) -> Vec<Expr<'p>> { vec![e] }
+  #[rule(ExprList -> ExprList Comma Expr)]
+  fn expr_list1(mut el: Vec<Expr<'p>>, _: Token, e: Expr<'p>) -> Vec<Expr<'p>> { (el.push(e), el).1 }
+
   #[rule(LitList -> Lit)]
   fn lit_list0(l: CLit<'p>) -> Vec<CLit<'p>> { vec![l] }
   #[rule(LitList -> LitList Comma Lit)]
@@ -231,6 +617,47 @@ impl<'p> Parser<'p> {
   fn expr_div(l: Expr<'p>, _: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(Div, box (l, r)) }
   #[rule(Expr -> Expr Mod Expr)]
   fn expr_mod(l: Expr<'p>, _: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(Mod, box (l, r)) }
+  #[rule(Expr -> BitNot Expr)]
+  #[prec(UMinus)]
+  fn expr_bit_not(_: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(BitXor, box (r, Expr::Atom(Atom::Lit(CLit::new(Lit::Number(-1.0)))))) }
+  #[rule(Expr -> Expr BitAnd Expr)]
+  fn expr_bit_and(l: Expr<'p>, _: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(BitAnd, box (l, r)) }
+  #[rule(Expr -> Expr BitOr Expr)]
+  fn expr_bit_or(l: Expr<'p>, _: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(BitOr, box (l, r)) }
+  #[rule(Expr -> Expr BitXor Expr)]
+  fn expr_bit_xor(l: Expr<'p>, _: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(BitXor, box (l, r)) }
+  #[rule(Expr -> Expr Shl Expr)]
+  fn expr_bit_shl(l: Expr<'p>, _: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(Shl, box (l, r)) }
+  #[rule(Expr -> Expr Shr Expr)]
+  fn expr_bit_shr(l: Expr<'p>, _: Token, r: Expr<'p>) -> Expr<'p> { Expr::Bin(Shr, box (l, r)) }
+  #[rule(Expr -> MathFn1 Expr RPar)]
+  fn expr_math1(func: MathFn, x: Expr<'p>, _: Token) -> Expr<'p> { Expr::Math(func, box (x, None)) }
+  #[rule(Expr -> MathFn2 Expr Comma Expr RPar)]
+  fn expr_math2(func: MathFn, x: Expr<'p>, _: Token, y: Expr<'p>, _: Token) -> Expr<'p> { Expr::Math(func, box (x, Some(y))) }
+  #[rule(Expr -> Round Expr RPar)]
+  fn expr_round1(_: Token, x: Expr<'p>, _: Token) -> Expr<'p> { Expr::Math(MathFn::Round, box (x, None)) }
+  #[rule(Expr -> Round Expr Comma Expr RPar)]
+  fn expr_round2(_: Token, x: Expr<'p>, _: Token, y: Expr<'p>, _: Token) -> Expr<'p> { Expr::Math(MathFn::Round, box (x, Some(y))) }
+  #[rule(Expr -> Coalesce ExprList RPar)]
+  fn expr_coalesce(_: Token, args: Vec<Expr<'p>>, _: Token) -> Expr<'p> { Expr::Coalesce(args) }
+  #[rule(Expr -> Greatest ExprList RPar)]
+  fn expr_greatest(_: Token, args: Vec<Expr<'p>>, _: Token) -> Expr<'p> { Expr::Greatest(args) }
+  #[rule(Expr -> Least ExprList RPar)]
+  fn expr_least(_: Token, args: Vec<Expr<'p>>, _: Token) -> Expr<'p> { Expr::Least(args) }
+  #[rule(Expr -> Random RPar)]
+  fn expr_random(_: Token, _: Token) -> Expr<'p> { Expr::Random(None) }
+  #[rule(Expr -> Rand RPar)]
+  fn expr_rand(_: Token, _: Token) -> Expr<'p> { Expr::Random(None) }
+  #[rule(Expr -> Rand Expr RPar)]
+  fn expr_rand_seed(_: Token, seed: Expr<'p>, _: Token) -> Expr<'p> { Expr::Random(Some(box seed)) }
+  #[rule(Expr -> IfNull Expr Comma Expr RPar)]
+  fn expr_if_null(_: Token, x: Expr<'p>, _: Token, y: Expr<'p>, _: Token) -> Expr<'p> { Expr::Coalesce(vec![x, y]) }
+  #[rule(Expr -> NullIf Expr Comma Expr RPar)]
+  fn expr_null_if(_: Token, x: Expr<'p>, _: Token, y: Expr<'p>, _: Token) -> Expr<'p> { Expr::NullIf(box (x, y)) }
+  #[rule(Expr -> Expr Add IntervalVal)]
+  fn expr_add_interval(l: Expr<'p>, _: Token, i: Interval) -> Expr<'p> { Expr::Interval(box l, i) }
+  #[rule(Expr -> Expr Sub IntervalVal)]
+  fn expr_sub_interval(l: Expr<'p>, _: Token, i: Interval) -> Expr<'p> { Expr::Interval(box l, i.neg()) }
   #[rule(Expr -> LPar Expr RPar)]
   fn expr_par(_: Token, e: Expr<'p>, _: Token) -> Expr<'p> { e }
   #[rule(Expr -> Expr Lt Expr)]
@@ -254,7 +681,7 @@ impl<'p> Parser<'p> {
   #[rule(Expr -> Expr Is NotNull)]
   fn expr_is_not_null(e: Expr<'p>, _: Token, _: Token) -> Expr<'p> { Expr::Null(box e, false) }
   #[rule(Expr -> Expr Like StrLit)]
-  fn expr_like(&self, e: Expr<'p>, _: Token, s: Token) -> Expr<'p> { Expr::Like(box e, self.escape(s.str_trim())) }
+  fn expr_like(&self, e: Expr<'p>, _: Token, s: Token) -> Expr<'p> { Expr::Like(box e, self.escape_str(s.str_trim(), true)) }
 
   #[rule(SetList -> Id Eq Expr)]
   fn set_list0(col: &'p str, _: Token, l: Expr<'p>) -> Vec<(&'p str, Expr<'p>)> { vec![(col, l)] }
@@ -269,17 +696,23 @@ impl<'p> Parser<'p> {
   fn field_list2(mut fl: FieldList<'p>, _: Token, c: ColDecl<'p>) -> FieldList<'p> { (fl.0.push(c), fl).1 }
   #[rule(FieldList -> FieldList Comma ColCons)]
   fn field_list3(mut fl: FieldList<'p>, _: Token, c: ColCons<'p>) -> FieldList<'p> { (fl.1.push(c), fl).1 }
+  #[rule(FieldList -> ColDecl Comment StrLit)]
+  fn field_list4(&self, c: ColDecl<'p>, _: Token, s: Token) -> FieldList<'p> { let cons = ColCons::Comment(c.col, self.escape(s.str_trim())); (vec![c], vec![cons]) }
+  #[rule(FieldList -> FieldList Comma ColDecl Comment StrLit)]
+  fn field_list5(&self, mut fl: FieldList<'p>, _: Token, c: ColDecl<'p>, _: Token, s: Token) -> FieldList<'p> {
+    (fl.1.push(ColCons::Comment(c.col, self.escape(s.str_trim()))), fl.0.push(c), fl).2
+  }
 
   #[rule(ColDecl -> Id ColTy)]
   fn field0(col: &'p str, ty: ColTy) -> ColDecl<'p> { ColDecl { col, ty, notnull: false, dft: None } }
   #[rule(ColDecl -> Id ColTy NotNull)]
   fn field1(col: &'p str, ty: ColTy, _: Token) -> ColDecl<'p> { ColDecl { col, ty, notnull: true, dft: None } }
-  #[rule(ColDecl -> Id ColTy Default Lit)]
-  fn field2(col: &'p str, ty: ColTy, _: Token, dft: CLit<'p>) -> ColDecl<'p> { ColDecl { col, ty, notnull: false, dft: Some(dft) } }
-  #[rule(ColDecl -> Id ColTy NotNull Default Lit)]
-  fn field3(col: &'p str, ty: ColTy, _: Token, _: Token, dft: CLit<'p>) -> ColDecl<'p> { ColDecl { col, ty, notnull: true, dft: Some(dft) } }
-  #[rule(ColCons -> ForeignKey LPar Id RPar References Id LPar Id RPar)]
-  fn field5(_: Token, _: Token, col: &'p str, _: Token, _: Token, f_table: &'p str, _: Token, f_col: &'p str, _: Token) -> ColCons<'p> { ColCons::Foreign { col, f_table, f_col } }
+  #[rule(ColDecl -> Id ColTy Default Dft)]
+  fn field2(col: &'p str, ty: ColTy, _: Token, dft: Dft<'p>) -> ColDecl<'p> { ColDecl { col, ty, notnull: false, dft: Some(dft) } }
+  #[rule(ColDecl -> Id ColTy NotNull Default Dft)]
+  fn field3(col: &'p str, ty: ColTy, _: Token, _: Token, dft: Dft<'p>) -> ColDecl<'p> { ColDecl { col, ty, notnull: true, dft: Some(dft) } }
+  #[rule(ColCons -> ForeignKey LPar Id RPar References Id LPar Id RPar FkActions)]
+  fn field5(_: Token, _: Token, col: &'p str, _: Token, _: Token, f_table: &'p str, _: Token, f_col: &'p str, _: Token, (on_delete, on_update): (FkAction, FkAction)) -> ColCons<'p> { ColCons::Foreign { col, f_table, f_col, on_delete, on_update } }
   #[rule(ColCons -> PrimaryKey LPar IdList RPar)]
   fn field6(_: Token, _: Token, il: Vec<&'p str>, _: Token) -> ColCons<'p> { ColCons::Primary(il) }
   #[rule(ColCons -> Unique LPar Id RPar)]
@@ -287,57 +720,225 @@ impl<'p> Parser<'p> {
   #[rule(ColCons -> Check LPar Id In LPar LitList RPar RPar)]
   fn field8(_: Token, _: Token, col: &'p str, _: Token, _: Token, ll: Vec<CLit<'p>>, _: Token, _: Token) -> ColCons<'p> { ColCons::Check(col, ll) }
 
+  #[rule(Dft -> Lit)]
+  fn dft_lit(l: CLit<'p>) -> Dft<'p> { Dft::Lit(l) }
+  #[rule(Dft -> CurrentDate)]
+  fn dft_current_date(_: Token) -> Dft<'p> { Dft::Fn(DftFn::CurrentDate) }
+  #[rule(Dft -> Now)]
+  fn dft_now(_: Token) -> Dft<'p> { Dft::Fn(DftFn::Now) }
+  #[rule(Dft -> Uuid4)]
+  fn dft_uuid4(_: Token) -> Dft<'p> { Dft::Fn(DftFn::Uuid4) }
+  #[rule(Dft -> CurDate)]
+  fn dft_cur_date(&mut self, t: Token) -> Dft<'p> { (self.mysql(&t), Dft::Fn(DftFn::CurrentDate)).1 }
+  #[rule(Dft -> Uuid)]
+  fn dft_uuid(&mut self, t: Token) -> Dft<'p> { (self.mysql(&t), Dft::Fn(DftFn::Uuid4)).1 }
+
+  // (on delete, on update), the later one wins if one is specified more than once
+  #[rule(FkActions ->)]
+  fn fk_actions0() -> (FkAction, FkAction) { (FkAction::Restrict, FkAction::Restrict) }
+  #[rule(FkActions -> FkActions On Delete FkAct)]
+  fn fk_actions1(a: (FkAction, FkAction), _: Token, _: Token, d: FkAction) -> (FkAction, FkAction) { (d, a.1) }
+  #[rule(FkActions -> FkActions On Update FkAct)]
+  fn fk_actions2(a: (FkAction, FkAction), _: Token, _: Token, u: FkAction) -> (FkAction, FkAction) { (a.0, u) }
+  #[rule(FkAct -> Restrict)]
+  fn fk_act0(_: Token) -> FkAction { FkAction::Restrict }
+  #[rule(FkAct -> Cascade)]
+  fn fk_act1(_: Token) -> FkAction { FkAction::Cascade }
+  #[rule(FkAct -> Set Null)]
+  fn fk_act2(_: Token, _: Token) -> FkAction { FkAction::SetNull }
+
   #[rule(Agg -> ColRef)]
-  fn agg0(col: ColRef<'p>) -> Agg<'p> { Agg { col, op: None } }
+  fn agg0(col: ColRef<'p>) -> Agg<'p> { Agg { col, op: None, arg: None } }
   #[rule(Agg -> Avg LPar ColRef RPar)]
-  fn agg_avg(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Avg) } }
+  fn agg_avg(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Avg), arg: None } }
   #[rule(Agg -> Sum LPar ColRef RPar)]
-  fn agg_sum(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Sum) } }
+  fn agg_sum(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Sum), arg: None } }
   #[rule(Agg -> Min LPar ColRef RPar)]
-  fn agg_min(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Min) } }
+  fn agg_min(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Min), arg: None } }
   #[rule(Agg -> Max LPar ColRef RPar)]
-  fn agg_max(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Max) } }
+  fn agg_max(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Max), arg: None } }
   #[rule(Agg -> Count LPar ColRef RPar)]
-  fn agg_count(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Count) } }
+  fn agg_count(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Count), arg: None } }
+  #[rule(Agg -> ApproxCountDistinct LPar ColRef RPar)]
+  fn agg_approx_count_distinct(_: Token, _: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(ApproxCountDistinct), arg: None } }
   // for CountAll, `col` is not accessible (for compatibility, `col` is not defined as Option<ColRef>)
   // "*" is just for the convenience of printing
   #[rule(Agg -> Count LPar Mul RPar)]
-  fn agg_count_all(_: Token, _: Token, _: Token, _: Token) -> Agg<'p> { Agg { col: ColRef { table: None, col: "*" }, op: Some(CountAll) } }
+  fn agg_count_all(_: Token, _: Token, _: Token, _: Token) -> Agg<'p> { Agg { col: ColRef { db: None, table: None, col: "*" }, op: Some(CountAll), arg: None } }
+
+  #[rule(Agg -> Stddev ColRef RPar)]
+  fn agg_stddev(_: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Stddev), arg: None } }
+  #[rule(Agg -> Variance ColRef RPar)]
+  fn agg_variance(_: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Variance), arg: None } }
+  #[rule(Agg -> Median ColRef RPar)]
+  fn agg_median(_: Token, col: ColRef<'p>, _: Token) -> Agg<'p> { Agg { col, op: Some(Median), arg: None } }
+  // `percentile_cont(p) within group (order by col [asc|desc])`, the range of `p` is checked in query
+  #[rule(Agg -> PercentileCont FloatLit RPar Within Group LPar OrderBy ColRef AscDesc RPar)]
+  fn agg_percentile_cont0(&mut self, _: Token, t: Token, _: Token, _: Token, _: Token, _: Token, _: Token, col: ColRef<'p>, desc: bool, _: Token) -> Agg<'p> {
+    let p = t.parse(|x: f32| x, |t, s| self.err(t, InvalidFloat(s)));
+    Agg { col, op: Some(PercentileCont), arg: Some(AggArg::Percentile { p, desc }) }
+  }
+  #[rule(Agg -> PercentileCont IntLit RPar Within Group LPar OrderBy ColRef AscDesc RPar)]
+  fn agg_percentile_cont1(&mut self, _: Token, t: Token, _: Token, _: Token, _: Token, _: Token, _: Token, col: ColRef<'p>, desc: bool, _: Token) -> Agg<'p> {
+    let p = t.parse(|x: i32| x as f32, |t, s| self.err(t, InvalidInt(s)));
+    Agg { col, op: Some(PercentileCont), arg: Some(AggArg::Percentile { p, desc }) }
+  }
+  // mysql style `group_concat(col [order by col [asc|desc]] [separator 'sep'])`, `, 'sep'` can replace `separator 'sep'`
+  #[rule(Agg -> GroupConcat LPar ColRef OrderM RPar)]
+  fn agg_group_concat0(_: Token, _: Token, col: ColRef<'p>, order: Option<(ColRef<'p>, bool)>, _: Token) -> Agg<'p> {
+    Agg { col, op: Some(GroupConcat), arg: Some(AggArg::Concat { sep: ",", order }) }
+  }
+  #[rule(Agg -> GroupConcat LPar ColRef OrderM Separator StrLit RPar)]
+  fn agg_group_concat1(&self, _: Token, _: Token, col: ColRef<'p>, order: Option<(ColRef<'p>, bool)>, _: Token, sep: Token, _: Token) -> Agg<'p> {
+    Agg { col, op: Some(GroupConcat), arg: Some(AggArg::Concat { sep: self.escape(sep.str_trim()), order }) }
+  }
+  #[rule(Agg -> GroupConcat LPar ColRef OrderM Comma StrLit RPar)]
+  fn agg_group_concat2(&self, _: Token, _: Token, col: ColRef<'p>, order: Option<(ColRef<'p>, bool)>, _: Token, sep: Token, _: Token) -> Agg<'p> {
+    Agg { col, op: Some(GroupConcat), arg: Some(AggArg::Concat { sep: self.escape(sep.str_trim()), order }) }
+  }
+  // postgres style `string_agg(col, 'sep' [order by col [asc|desc]])`
+  #[rule(Agg -> StringAgg LPar ColRef Comma StrLit OrderM RPar)]
+  fn agg_string_agg(&self, _: Token, _: Token, col: ColRef<'p>, _: Token, sep: Token, order: Option<(ColRef<'p>, bool)>, _: Token) -> Agg<'p> {
+    Agg { col, op: Some(GroupConcat), arg: Some(AggArg::Concat { sep: self.escape(sep.str_trim()), order }) }
+  }
 
   #[rule(ColRef -> Id)]
-  fn col_ref0(col: &'p str) -> ColRef<'p> { ColRef { table: None, col } }
+  fn col_ref0(col: &'p str) -> ColRef<'p> { ColRef { db: None, table: None, col } }
   #[rule(ColRef -> Id Dot Id)]
-  fn col_ref1(table: &'p str, _: Token, col: &'p str) -> ColRef<'p> { ColRef { table: Some(table), col } }
+  fn col_ref1(table: &'p str, _: Token, col: &'p str) -> ColRef<'p> { ColRef { db: None, table: Some(table), col } }
+  #[rule(ColRef -> Id Dot Id Dot Id)]
+  fn col_ref2(db: &'p str, _: Token, table: &'p str, _: Token, col: &'p str) -> ColRef<'p> { ColRef { db: Some(db), table: Some(table), col } }
+
+  #[rule(ColRefList -> ColRef)]
+  fn col_ref_list0(c: ColRef<'p>) -> Vec<ColRef<'p>> { vec![c] }
+  #[rule(ColRefList -> ColRefList Comma ColRef)]
+  fn col_ref_list1(mut cl: Vec<ColRef<'p>>, _: Token, c: ColRef<'p>) -> Vec<ColRef<'p>> { (cl.push(c), cl).1 }
 
   #[rule(CondList -> CondList And Cond)]
   fn where1(mut cl: Vec<Cond<'p>>, _: Token, c: Cond<'p>) -> Vec<Cond<'p>> { (cl.push(c), cl).1 }
   #[rule(CondList -> Cond)]
   fn where0(c: Cond<'p>) -> Vec<Cond<'p>> { vec![c] }
+  // `x between l and r` is just `x >= l and x <= r`
+  #[rule(CondList -> CondList And ColRef Between CondAtom And CondAtom)]
+  fn where_between1(mut cl: Vec<Cond<'p>>, _: Token, c: ColRef<'p>, _: Token, l: Atom<'p>, _: Token, r: Atom<'p>) -> Vec<Cond<'p>> {
+    cl.extend_from_slice(&[Cond::Cmp(Ge, c, l), Cond::Cmp(Le, c, r)]);
+    cl
+  }
+  #[rule(CondList -> ColRef Between CondAtom And CondAtom)]
+  fn where_between0(c: ColRef<'p>, _: Token, l: Atom<'p>, _: Token, r: Atom<'p>) -> Vec<Cond<'p>> { vec![Cond::Cmp(Ge, c, l), Cond::Cmp(Le, c, r)] }
 
-  #[rule(Cond -> ColRef Lt Atom)]
+  #[rule(Cond -> ColRef Lt CondAtom)]
   fn cond_lt(l: ColRef<'p>, _: Token, r: Atom<'p>) -> Cond<'p> { Cond::Cmp(Lt, l, r) }
-  #[rule(Cond -> ColRef Le Atom)]
+  #[rule(Cond -> ColRef Le CondAtom)]
   fn cond_le(l: ColRef<'p>, _: Token, r: Atom<'p>) -> Cond<'p> { Cond::Cmp(Le, l, r) }
-  #[rule(Cond -> ColRef Ge Atom)]
+  #[rule(Cond -> ColRef Ge CondAtom)]
   fn cond_ge(l: ColRef<'p>, _: Token, r: Atom<'p>) -> Cond<'p> { Cond::Cmp(Ge, l, r) }
-  #[rule(Cond -> ColRef Gt Atom)]
+  #[rule(Cond -> ColRef Gt CondAtom)]
   fn cond_gt(l: ColRef<'p>, _: Token, r: Atom<'p>) -> Cond<'p> { Cond::Cmp(Gt, l, r) }
-  #[rule(Cond -> ColRef Eq Atom)]
+  #[rule(Cond -> ColRef Eq CondAtom)]
   fn cond_eq(l: ColRef<'p>, _: Token, r: Atom<'p>) -> Cond<'p> { Cond::Cmp(Eq, l, r) }
-  #[rule(Cond -> ColRef Ne Atom)]
+  #[rule(Cond -> ColRef Ne CondAtom)]
   fn cond_ne(l: ColRef<'p>, _: Token, r: Atom<'p>) -> Cond<'p> { Cond::Cmp(Ne, l, r) }
   #[rule(Cond -> ColRef Is Null)]
   fn cond_is_null(c: ColRef<'p>, _: Token, _: Token) -> Cond<'p> { Cond::Null(c, true) }
   #[rule(Cond -> ColRef Is NotNull)]
   fn cond_is_not_null(c: ColRef<'p>, _: Token, _: Token) -> Cond<'p> { Cond::Null(c, false) }
   #[rule(Cond -> ColRef Like StrLit)]
-  fn cond_like(c: ColRef<'p>, _: Token, s: Token) -> Cond<'p> { Cond::Like(c, s.str_trim()) }
+  fn cond_like(&self, c: ColRef<'p>, _: Token, s: Token) -> Cond<'p> { Cond::Like(c, self.escape_str(s.str_trim(), true)) }
+  #[rule(Cond -> StrFn ColRef RPar Op Lit)]
+  fn cond_fn(func: StrFn, c: ColRef<'p>, _: Token, op: CmpOp, r: CLit<'p>) -> Cond<'p> { Cond::Fn(func, op, c, r) }
+  #[rule(Cond -> ColRef BitOp Lit Op Lit)]
+  fn cond_bit(c: ColRef<'p>, op: BinOp, mask: CLit<'p>, cmp: CmpOp, r: CLit<'p>) -> Cond<'p> { Cond::Bit(op, c, mask, cmp, r) }
+  #[rule(Cond -> BitNot ColRef Op Lit)]
+  fn cond_bit_not(_: Token, c: ColRef<'p>, cmp: CmpOp, r: CLit<'p>) -> Cond<'p> { Cond::Bit(BitXor, c, CLit::new(Lit::Number(-1.0)), cmp, r) }
+  #[rule(Cond -> LPar ColRefList RPar Op LPar LitList RPar)]
+  fn cond_row(_: Token, cols: Vec<ColRef<'p>>, _: Token, op: CmpOp, _: Token, lits: Vec<CLit<'p>>, _: Token) -> Cond<'p> { Cond::Row(op, cols, lits) }
+  #[rule(Cond -> LPar ColRefList RPar In LPar LitListList RPar)]
+  fn cond_row_in(_: Token, cols: Vec<ColRef<'p>>, _: Token, _: Token, _: Token, rows: Vec<Vec<CLit<'p>>>, _: Token) -> Cond<'p> { Cond::RowIn(cols, rows) }
+  #[rule(Cond -> ColRef Op LPar Query RPar)]
+  fn cond_sub(l: ColRef<'p>, op: CmpOp, _: Token, s: Select<'p>, _: Token) -> Cond<'p> { Cond::Sub(op, l, box s) }
+  #[rule(Cond -> ColRef Op All LPar Query RPar)]
+  fn cond_all(l: ColRef<'p>, op: CmpOp, _: Token, _: Token, s: Select<'p>, _: Token) -> Cond<'p> { Cond::Quant(op, l, true, box s) }
+  #[rule(Cond -> ColRef Op Any LPar Query RPar)]
+  fn cond_any(l: ColRef<'p>, op: CmpOp, _: Token, _: Token, s: Select<'p>, _: Token) -> Cond<'p> { Cond::Quant(op, l, false, box s) }
+  #[rule(Cond -> ColRef Op Some1 LPar Query RPar)]
+  fn cond_some(l: ColRef<'p>, op: CmpOp, _: Token, _: Token, s: Select<'p>, _: Token) -> Cond<'p> { Cond::Quant(op, l, false, box s) }
+  #[rule(Cond -> Match ColRef RPar Against StrLit RPar)]
+  fn cond_match(&self, _: Token, c: ColRef<'p>, _: Token, _: Token, s: Token, _: Token) -> Cond<'p> { Cond::Match(c, self.escape(s.str_trim())) }
+  #[rule(Cond -> Within LPar ColRef Comma ColRef Comma Lit Comma Lit Comma Lit Comma Lit RPar)]
+  fn cond_within(_: Token, _: Token, x: ColRef<'p>, _: Token, y: ColRef<'p>, _: Token, x1: CLit<'p>, _: Token, y1: CLit<'p>, _: Token, x2: CLit<'p>, _: Token, y2: CLit<'p>, _: Token) -> Cond<'p> {
+    Cond::Within(x, y, [x1, y1, x2, y2])
+  }
+
+  #[rule(StrFn -> Lower)]
+  fn str_fn_lower(_: Token) -> StrFn { StrFn::Lower }
+  #[rule(StrFn -> Upper)]
+  fn str_fn_upper(_: Token) -> StrFn { StrFn::Upper }
+  #[rule(StrFn -> LCase)]
+  fn str_fn_lcase(&mut self, t: Token) -> StrFn { (self.mysql(&t), StrFn::Lower).1 }
+  #[rule(StrFn -> UCase)]
+  fn str_fn_ucase(&mut self, t: Token) -> StrFn { (self.mysql(&t), StrFn::Upper).1 }
+
+  // functions taking one / two arguments, `round` can take both, so it has its own rules
+  #[rule(MathFn1 -> Abs)]
+  fn math_fn_abs(_: Token) -> MathFn { MathFn::Abs }
+  #[rule(MathFn1 -> Ceil)]
+  fn math_fn_ceil(_: Token) -> MathFn { MathFn::Ceil }
+  #[rule(MathFn1 -> Floor)]
+  fn math_fn_floor(_: Token) -> MathFn { MathFn::Floor }
+  #[rule(MathFn1 -> Sqrt)]
+  fn math_fn_sqrt(_: Token) -> MathFn { MathFn::Sqrt }
+  #[rule(MathFn2 -> ModFn)]
+  fn math_fn_mod(_: Token) -> MathFn { MathFn::Mod }
+  #[rule(MathFn2 -> Power)]
+  fn math_fn_power(_: Token) -> MathFn { MathFn::Power }
+
+  #[rule(BitOp -> BitAnd)]
+  fn bit_op_and(_: Token) -> BinOp { BitAnd }
+  #[rule(BitOp -> BitOr)]
+  fn bit_op_or(_: Token) -> BinOp { BitOr }
+  #[rule(BitOp -> BitXor)]
+  fn bit_op_xor(_: Token) -> BinOp { BitXor }
+  #[rule(BitOp -> Shl)]
+  fn bit_op_shl(_: Token) -> BinOp { Shl }
+  #[rule(BitOp -> Shr)]
+  fn bit_op_shr(_: Token) -> BinOp { Shr }
+
+  #[rule(Op -> Lt)]
+  fn op_lt(_: Token) -> CmpOp { Lt }
+  #[rule(Op -> Le)]
+  fn op_le(_: Token) -> CmpOp { Le }
+  #[rule(Op -> Ge)]
+  fn op_ge(_: Token) -> CmpOp { Ge }
+  #[rule(Op -> Gt)]
+  fn op_gt(_: Token) -> CmpOp { Gt }
+  #[rule(Op -> Eq)]
+  fn op_eq(_: Token) -> CmpOp { Eq }
+  #[rule(Op -> Ne)]
+  fn op_ne(_: Token) -> CmpOp { Ne }
 
   #[rule(Atom -> ColRef)]
   fn atom_col_ref(c: ColRef<'p>) -> Atom<'p> { Atom::ColRef(c) }
   #[rule(Atom -> Lit)]
   fn atom_lit(l: CLit<'p>) -> Atom<'p> { Atom::Lit(l) }
 
+  // the rhs of a `Cond`, it is an `Atom`, or a date literal plus / minus an interval, which is folded to a date here
+  #[rule(CondAtom -> ColRef)]
+  fn cond_atom_col_ref(c: ColRef<'p>) -> Atom<'p> { Atom::ColRef(c) }
+  #[rule(CondAtom -> Lit)]
+  fn cond_atom_lit(l: CLit<'p>) -> Atom<'p> { Atom::Lit(l) }
+  #[rule(CondAtom -> Lit Add IntervalVal)]
+  fn cond_atom_add(&mut self, l: CLit<'p>, op: Token, i: Interval) -> Atom<'p> { Atom::Lit(self.add_interval(l, &op, i)) }
+  #[rule(CondAtom -> Lit Sub IntervalVal)]
+  fn cond_atom_sub(&mut self, l: CLit<'p>, op: Token, i: Interval) -> Atom<'p> { Atom::Lit(self.add_interval(l, &op, i.neg())) }
+
+  #[rule(IntervalVal -> Interval IntLit Id1)]
+  fn interval(&mut self, _: Token, n: Token, unit: Token) -> Interval {
+    let n = n.parse(|x: i32| x, |t, s| self.err(t, InvalidInt(s)));
+    let unit = IntervalUnit::from_name(unit.str()).unwrap_or_else(|| (self.err(&unit, InvalidIntervalUnit(unit.str())), IntervalUnit::Day).1);
+    Interval { n, unit }
+  }
+
   #[rule(Lit -> Null)]
   fn lit_null(_: Token) -> CLit<'p> { CLit::new(Lit::Null) }
   #[rule(Lit -> True)]
@@ -345,11 +946,43 @@ impl<'p> Parser<'p> {
   #[rule(Lit -> False)]
   fn lit_false(_: Token) -> CLit<'p> { CLit::new(Lit::Bool(false)) }
   #[rule(Lit -> IntLit)]
-  fn lit_int(&mut self, t: Token) -> CLit<'p> { t.parse(|x: i32| CLit::new(Lit::Number(x as f64)), |line, col, s| self.pe.push(PE { line, col, kind: InvalidInt(s) })) }
+  fn lit_int(&mut self, t: Token) -> CLit<'p> { t.parse(|x: i32| CLit::new(Lit::Number(x as f64)), |t, s| self.err(t, InvalidInt(s))) }
+  #[rule(Lit -> HexLit)]
+  fn lit_hex(&mut self, t: Token) -> CLit<'p> { self.radix(&t, 16) }
+  #[rule(Lit -> BinLit)]
+  fn lit_bin(&mut self, t: Token) -> CLit<'p> { self.radix(&t, 2) }
+  // the bytes must be a utf-8 string, since there is no binary type yet
+  #[rule(Lit -> BytesLit)]
+  fn lit_bytes(&mut self, t: Token) -> CLit<'p> {
+    let hex = &t.str()[2..t.piece.len() - 1];
+    let bytes = (0..hex.len() / 2).map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap()).collect::<Vec<_>>();
+    match str::from_utf8(&bytes) {
+      Ok(s) if hex.len() % 2 == 0 => CLit::new(Lit::Str(unsafe { str::from_utf8_unchecked(self.alloc.alloc_extend(s.bytes())) })),
+      _ => (self.err(&t, InvalidBytes(t.str())), CLit::new(Lit::Null)).1,
+    }
+  }
   #[rule(Lit -> FloatLit)]
-  fn lit_float(&mut self, t: Token) -> CLit<'p> { t.parse(|x: f32| CLit::new(Lit::Number(x as f64)), |line, col, s| self.pe.push(PE { line, col, kind: InvalidFloat(s) })) }
+  fn lit_float(&mut self, t: Token) -> CLit<'p> { t.parse(|x: f32| CLit::new(Lit::Number(x as f64)), |t, s| self.err(t, InvalidFloat(s))) }
   #[rule(Lit -> StrLit)]
-  fn lit_str(t: Token) -> CLit<'p> { CLit::new(Lit::Str(t.str_trim())) }
+  fn lit_str(&self, t: Token) -> CLit<'p> { CLit::new(Lit::Str(self.escape(t.str_trim()))) }
+  #[rule(Lit -> Param)]
+  fn lit_param(&mut self, t: Token) -> CLit<'p> {
+    let idx = if t.piece[0] == b'?' {
+      (Some(self.next_param), self.next_param += 1).0
+    } else { t.str()[1..].parse::<usize>().ok().and_then(|n| n.checked_sub(1)) };
+    match idx.and_then(|idx| self.params.get(idx)) {
+      Some(&lit) => lit,
+      None => (self.err(&t, InvalidParam(t.str())), CLit::new(Lit::Null)).1,
+    }
+  }
+
+  // a bare word like `on` / `off` is the same as a string
+  #[rule(SetVal -> Lit)]
+  fn set_val_lit(l: CLit<'p>) -> CLit<'p> { l }
+  #[rule(SetVal -> Id)]
+  fn set_val_id(s: &'p str) -> CLit<'p> { CLit::new(Lit::Str(s)) }
+  #[rule(SetVal -> On)]
+  fn set_val_on(t: Token) -> CLit<'p> { CLit::new(Lit::Str(t.str())) }
 
   #[rule(BareTy -> Bool)]
   fn bare_ty_bool(_: Token) -> BareTy { Bool }
@@ -363,9 +996,9 @@ impl<'p> Parser<'p> {
   fn bare_ty_var_char(_: Token) -> BareTy { Char }
 
   #[rule(ColTy -> BareTy LPar IntLit RPar)]
-  fn col_ty(&mut self, ty: BareTy, _: Token, t: Token, _: Token) -> ColTy { t.parse(|size| ColTy::FixTy(FixTy { size, ty }), |line, col, s| self.pe.push(PE { line, col, kind: InvalidTypeSize(s) })) }
+  fn col_ty(&mut self, ty: BareTy, _: Token, t: Token, _: Token) -> ColTy { t.parse(|size| ColTy::FixTy(FixTy { size, ty }), |t, s| self.err(t, InvalidTypeSize(s))) }
   #[rule(ColTy -> Varchar LPar IntLit RPar)]
-  fn col_ty_varchar(&mut self, _: Token, _: Token, t: Token, _: Token) -> ColTy { t.parse(|size| ColTy::Varchar(size), |line, col, s| self.pe.push(PE { line, col, kind: InvalidTypeSize(s) })) }
+  fn col_ty_varchar(&mut self, _: Token, _: Token, t: Token, _: Token) -> ColTy { t.parse(|size| ColTy::Varchar(size), |t, s| self.err(t, InvalidTypeSize(s))) }
   #[rule(ColTy -> Bool)]
   fn col_ty_bool(_: Token) -> ColTy { ColTy::FixTy(FixTy { size: 0, ty: Bool }) }
   #[rule(ColTy -> Int)]
@@ -16,6 +16,19 @@ select * from ORDERS where O_CUSTKEY >= 745;
 select * from ORDERS where O_CUSTKEY = 567;
 select * from ORDERS where O_CUSTKEY = 0;
 select * from ORDERS where O_CUSTKEY = 751;
+select * from ORDERS where O_CUSTKEY > 5 and O_CUSTKEY <= 10 and O_ORDERSTATUS = 'F';
+select * from ORDERS where O_CUSTKEY between 100 and 110;
+select * from ORDERS where O_CUSTKEY > 10 and O_CUSTKEY < 5; -- empty range
+select count(*), min(O_CUSTKEY), max(O_CUSTKEY) from ORDERS where O_CUSTKEY >= 100; -- these select only uses index
+select O_CUSTKEY from ORDERS where O_CUSTKEY between 100 and 110;
+explain select count(*) from ORDERS where O_CUSTKEY >= 100;
+explain select * from ORDERS where O_CUSTKEY between 100 and 110 and O_ORDERSTATUS = 'F';
+explain select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY and C_NAME = 'Customer#000000001';
+
+select * from ORDERS order by O_TOTALPRICE desc limit 10;
+select * from ORDERS where O_CUSTKEY > 100 order by O_CUSTKEY desc limit 10; -- scan index backward
+select O_CUSTKEY from ORDERS order by O_CUSTKEY limit 5;
+select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY order by C_NAME limit 3;
 
 select O_ORDERKEY, avg(O_TOTALPRICE) from ORDERS; -- error, mixed select
 select avg(O_TOTALPRICE), min(O_TOTALPRICE), max(O_TOTALPRICE) from ORDERS where O_TOTALPRICE >= 100000;
@@ -54,4 +67,13 @@ insert into test values ('hello', 'hello', 'world');
 insert into test values ('world', 'hello', 'hello');
 select * from test where c = v1 and v1 = c;
 select * from test where v1 = v2;
-drop table test;
\ No newline at end of file
+drop table test;
+
+create database attachDB; use attachDB; create table t (k int, name char(10)); insert into t values (1, 'one'), (2, 'two');
+use orderDB; attach 'attachDB' as a;
+attach database 'attachDB' as a; -- error, dup alias
+select * from b.t; -- error, no such attached database
+select N_NAME, t.name from NATION, a.t where N_NATIONKEY = t.k;
+detach a;
+select * from a.t; -- error, already detached
+drop database attachDB;
\ No newline at end of file
@@ -0,0 +1,526 @@
+use driver::Eval;
+use std::collections::BTreeMap;
+use rand::prelude::*;
+use syntax::ast::*;
+use common::{*, BareTy::*};
+use index::Index;
+use crate::util::{lit, out};
+
+#[test]
+fn cursor() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let vals = (0..3000).map(|i| (rng.gen_range(0, 500), i)).collect::<Vec<(i32, i32)>>();
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  let select = |x, order: bool, limit| Select {
+    ops: None, tables: vec![TableRef { db: None, table: "c", sample: None }], order: if order { Some((ColRef { db: None, table: None, col: "a" }, false)) } else { None }, limit,
+    where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "a" }, Atom::Lit(lit(x)))],
+    offset: 0,
+    derived: vec![],
+  };
+  e.exec(&Stmt::CreateDb("cursor")).unwrap();
+  e.exec(&Stmt::UseDb("cursor")).unwrap();
+  e.exec(&CreateTable { table: "c", cols: vec![int("a"), int("i")], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "c", vals: vals.iter().map(|&(a, i)| vec![lit(a), lit(i)]).collect(), cols: None })).unwrap();
+  let fmt = |row: &[CLit]| format!("{:?}", row);
+  for &(x, order, limit) in &[(100, false, None), (100, true, None), (500, false, Some(10)), (0, false, None), (300, true, Some(50))] {
+    // the lazy scan (without order) and the computed result (with order) are both the same as `select`
+    let expect = e.select(&select(x, order, limit)).unwrap();
+    let cursor = e.cursor(&select(x, order, limit)).unwrap();
+    assert_eq!(cursor.cols.len(), 2);
+    let rows = cursor.map(|row| fmt(&row)).collect::<Vec<_>>();
+    assert_eq!(rows, expect.data.chunks_exact(2).map(fmt).collect::<Vec<_>>());
+  }
+  // only the consumed rows are produced
+  assert_eq!(e.cursor(&select(500, false, None)).unwrap().take(3).count(), 3);
+  // fetching pages continues the scan, all pages together are the whole result
+  for &order in &[false, true] {
+    let expect = e.select(&select(300, order, None)).unwrap();
+    let (mut cursor, mut pages) = (e.cursor(&select(300, order, None)).unwrap(), vec![]);
+    loop {
+      let page = cursor.fetch(7);
+      if page.row_count() == 0 { break; }
+      assert!(page.row_count() <= 7);
+      pages.extend(page.data.chunks_exact(2).map(fmt));
+    }
+    assert_eq!(pages, expect.data.chunks_exact(2).map(fmt).collect::<Vec<_>>());
+    assert_eq!(cursor.fetch(7).row_count(), 0);
+  }
+  assert!(e.cursor(&Select { ops: None, tables: vec![TableRef { db: None, table: "c1", sample: None }], where_: vec![], order: None, limit: None, offset: 0, derived: vec![] }).is_err());
+  e.exec(&Stmt::DropDb("cursor")).unwrap();
+}
+
+#[test]
+fn typed_row() {
+  let mut e = Eval::default();
+  let ty = |ty, size| ColTy::FixTy(FixTy { size, ty });
+  let decl = |col, ty| ColDecl { col, ty, notnull: false, dft: None };
+  let (string, num, null) = (|s| CLit::new(Lit::Str(s)), |x| CLit::new(Lit::Number(x)), CLit::new(Lit::Null));
+  let all = || Select { ops: None, tables: vec![TableRef { db: None, table: "r", sample: None }], where_: vec![], order: Some((ColRef { db: None, table: None, col: "i" }, false)), limit: None, offset: 0, derived: vec![] };
+  e.exec(&Stmt::CreateDb("typed_row")).unwrap();
+  e.exec(&Stmt::UseDb("typed_row")).unwrap();
+  e.exec(&CreateTable { table: "r", cols: vec![decl("i", ty(Int, 0)), decl("f", ty(Float, 0)), decl("s", ty(Char, 10)), decl("v", ColTy::Varchar(20)),
+                                               decl("d", ty(Date, 0)), decl("b", ty(Bool, 0))], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "r", cols: None, vals: vec![
+    vec![num(1.0), num(1.5), string("abc"), string("a varchar"), string("2020-01-02"), CLit::new(Lit::Bool(true))],
+    vec![num(2.0), null, null, null, null, null],
+  ] })).unwrap();
+  let res = e.select(&all()).unwrap();
+  assert_eq!(res.cols.iter().map(|c| c.name()).collect::<Vec<_>>(), ["i", "f", "s", "v", "d", "b"]);
+  assert_eq!((res.cols[0].ty().fix_ty().ty, res.cols[4].ty().fix_ty().ty, res.cols[3].ty()), (Int, Date, ColTy::Varchar(20)));
+  let r0 = res.row(0).unwrap();
+  assert_eq!((r0.get::<i32>(0), r0.get::<f32>(1), r0.get::<&str>(2), r0.get_by_name::<String>("v")), (Some(1), Some(1.5), Some("abc"), Some("a varchar".to_owned())));
+  assert_eq!((r0.get::<&str>(4), format!("{:?}", r0.data[4]), r0.get_by_name::<bool>("b")), (None, "2020-01-02".to_owned(), Some(true)));
+  // type mismatch, out of range, and no such col
+  assert_eq!((r0.get::<i32>(1), r0.get::<bool>(0), r0.get::<i32>(6), r0.get_by_name::<i32>("x")), (None, None, None, None));
+  let r1 = res.row(1).unwrap();
+  assert!((1..6).all(|idx| r1.is_null(idx)) && !r1.is_null(0) && !r1.is_null(6));
+  assert_eq!((r1.get::<f32>(1), r1.get::<Option<f32>>(1), r1.get::<Option<i32>>(0)), (None, Some(None), Some(Some(2))));
+  assert!(res.row(2).is_none());
+  assert_eq!(res.rows().map(|r| r.get::<i32>(0).unwrap()).collect::<Vec<_>>(), [1, 2]);
+  // aggregations have their own names and types
+  let agg = |op, col| Agg { op: Some(op), col: ColRef { db: None, table: None, col }, arg: None };
+  let res = e.select(&Select { ops: Some(vec![agg(AggOp::Avg, "i"), agg(AggOp::Max, "s"), agg(AggOp::CountAll, "i")]), ..all() }).unwrap();
+  assert_eq!(res.cols.iter().map(|c| c.name()).collect::<Vec<_>>(), ["avg(i)", "max(s)", "count(*)"]);
+  let row = res.row(0).unwrap();
+  assert_eq!((row.get::<f64>(0), row.get::<i32>(0), row.get_by_name::<&str>("max(s)"), row.get_by_name::<i32>("count(*)")), (Some(1.5), None, Some("abc"), Some(2)));
+  // a cursor produces the same rows
+  let mut cursor = e.cursor(&Select { order: None, ..all() }).unwrap();
+  let mut ids = vec![];
+  while let Some(row) = cursor.next_row() { ids.push(row.get_by_name::<i32>("i").unwrap()); }
+  (ids.sort(), assert_eq!(ids, [1, 2]));
+  e.exec(&Stmt::DropDb("typed_row")).unwrap();
+}
+
+#[test]
+fn serde_row() {
+  use serde::{Serialize, Deserialize};
+  use typed_arena::Arena;
+  #[derive(Serialize)]
+  struct In<'a> { i: i32, f: Option<f64>, s: &'a str, d: &'a str }
+  #[derive(Deserialize, Debug, PartialEq)]
+  struct Out<'a> { s: &'a str, i: i32, f: Option<f64>, d: String }
+  #[derive(Serialize)]
+  struct Bad { i: i32, v: Vec<i32> }
+  let mut e = Eval::default();
+  let alloc = Arena::new();
+  e.exec_all("create database serde_row; use serde_row; create table r (i int, f float, s char(10), d date);", &alloc, |_| {}, |_| {}).unwrap();
+  // fields in another order than the cols, and a date from a string
+  let rows = [In { d: "2020-01-02", s: "abc", f: Some(1.5), i: 1 }, In { d: "2021-12-31", s: "x", f: None, i: 2 }];
+  assert_eq!(e.insert_rows("r", &rows, &alloc).unwrap(), 2);
+  assert_eq!(e.insert_rows::<In>("r", &[], &alloc).unwrap(), 0);
+  match e.insert_rows("r", &[Bad { i: 3, v: vec![] }], &alloc) {
+    Err(ModifyError(_, Error::InvalidRow(_))) => {}
+    r => panic!("{:?}", r),
+  }
+  let res = match &syntax::work("select * from r order by i;", &alloc).unwrap()[0] { Stmt::Select(s) => e.select(s).unwrap(), _ => unreachable!() };
+  assert_eq!(res.deserialize_rows::<Out>().unwrap(), [Out { s: "abc", i: 1, f: Some(1.5), d: "2020-01-02".to_owned() }, Out { s: "x", i: 2, f: None, d: "2021-12-31".to_owned() }]);
+  // a field that is not a col, and a value not of the type of its field
+  #[derive(Deserialize, Debug)]
+  struct NoCol { x: i32 }
+  #[derive(Deserialize, Debug)]
+  struct WrongTy { s: i32 }
+  assert!(res.row(0).unwrap().deserialize::<NoCol>().is_err() && res.row(0).unwrap().deserialize::<WrongTy>().is_err());
+  e.exec(&Stmt::DropDb("serde_row")).unwrap();
+}
+
+#[test]
+fn params() {
+  use typed_arena::Arena;
+  use std::cell::RefCell;
+  let mut e = Eval::default();
+  let (alloc, out) = (Arena::default(), RefCell::new(vec![]));
+  let string = |s| CLit::new(Lit::Str(s));
+  e.exec(&Stmt::CreateDb("params")).unwrap();
+  e.exec(&Stmt::UseDb("params")).unwrap();
+  e.exec_all("create table p (id int, name char(20), d date);", &alloc, |_| {}, |_| {}).unwrap();
+  // a value with quotes is never parsed as sql
+  let vals = [lit(1), string("it's'); drop p;--"), string("2020-01-01"), lit(2), CLit::new(Lit::Null)];
+  e.exec_with_params("insert into p values (?, ?, ?), ($4, $2, $5);", &alloc, &vals, |_| {}).unwrap();
+  e.exec_with_params("select id from p where name = $1 and d is null;", &alloc, &[string("it's'); drop p;--")], |x| out.borrow_mut().push(x.to_owned())).unwrap();
+  e.exec_with_params("select count(*) from p where id >= ? and id <= ?;", &alloc, &[lit(1), lit(2)], |x| out.borrow_mut().push(x.to_owned())).unwrap();
+  assert_eq!(out.into_inner(), ["id\n2", "count(*)\n2"]);
+  // missing values, and values of wrong type
+  assert!(e.exec_with_params("select * from p where id = ? and name = ?;", &alloc, &[lit(1)], |_| {}).is_err());
+  assert!(e.exec_with_params("select * from p where id = $0;", &alloc, &[lit(1)], |_| {}).is_err());
+  assert!(e.exec_with_params("insert into p values (?, 'a', null);", &alloc, &[string("a")], |_| {}).is_err());
+  assert!(e.exec_with_params("insert into p values (1, 'a', ?);", &alloc, &[string("not a date")], |_| {}).is_err());
+  e.exec(&Stmt::DropDb("params")).unwrap();
+}
+
+#[test]
+fn cached() {
+  use std::cell::RefCell;
+  let mut e = Eval::default();
+  let out = RefCell::new(vec![]);
+  e.exec(&Stmt::CreateDb("cached")).unwrap();
+  e.exec(&Stmt::UseDb("cached")).unwrap();
+  e.exec_cached("create table c (id int);", |_| {}).unwrap();
+  for i in 0..3 {
+    e.exec_cached("insert into c (id) values (1), (2);", |_| {}).unwrap();
+    e.exec_cached("select count(*) from c;", |x| out.borrow_mut().push(x.to_owned())).unwrap();
+    // names are resolved in every execution, so the cached statements see the change of schema
+    if i == 0 { e.exec_cached("alter table c add x int default 5;", |_| {}).unwrap(); }
+    if i == 1 { e.exec_cached("alter table c drop x;", |_| {}).unwrap(); }
+  }
+  assert_eq!(out.into_inner(), ["count(*)\n2", "count(*)\n4", "count(*)\n6"]);
+  assert_eq!(e.cached(), 5);
+  // syntax errors are reported every time
+  assert!(e.exec_cached("select * from;", |_| {}).is_err());
+  assert!(e.exec_cached("select * from;", |_| {}).is_err());
+  assert!(e.exec_cached("select x from c;", |_| {}).is_err());
+  assert_eq!(e.cached(), 7);
+  e.exec(&Stmt::DropDb("cached")).unwrap();
+}
+
+#[test]
+fn progress() {
+  use std::{rc::Rc, cell::RefCell};
+  use db::ProgressOp::{self, *};
+  let mut e = Eval::default();
+  let log = Rc::new(RefCell::new(Vec::<(ProgressOp, u64)>::new()));
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  e.exec(&Stmt::CreateDb("progress")).unwrap();
+  e.exec(&Stmt::UseDb("progress")).unwrap();
+  let log1 = log.clone();
+  e.db().unwrap().set_progress(300, move |op, n| log1.borrow_mut().push((op, n)));
+  e.exec(&CreateTable { table: "p", cols: vec![int("a")], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "p", vals: (0..1000).map(|i| vec![lit(i)]).collect(), cols: None })).unwrap();
+  assert_eq!(log.replace(vec![]), [(Insert, 300), (Insert, 600), (Insert, 900)]);
+  // the count restarts in every statement
+  let select = Select { ops: None, tables: vec![TableRef { db: None, table: "p", sample: None }], where_: vec![], order: None, limit: None, offset: 0, derived: vec![] };
+  e.exec(&Stmt::Select(select)).unwrap();
+  assert_eq!(log.replace(vec![]), [(Scan, 300), (Scan, 600), (Scan, 900)]);
+  e.exec(&CreateIndex { index: "p_a", table: "p", col: "a", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  let build = log.replace(vec![]);
+  assert!(!build.is_empty() && build.iter().all(|&(op, n)| op == IndexBuild && n % 300 == 0));
+  e.db().unwrap().clear_progress();
+  e.exec(&Stmt::Insert(Insert { table: "p", vals: (0..1000).map(|i| vec![lit(i)]).collect(), cols: None })).unwrap();
+  assert!(log.borrow().is_empty());
+  e.exec(&Stmt::DropDb("progress")).unwrap();
+}
+
+#[test]
+fn insert_batch() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  e.exec(&Stmt::CreateDb("insert_batch")).unwrap();
+  e.exec(&Stmt::UseDb("insert_batch")).unwrap();
+  e.exec(&CreateTable { table: "q", cols: vec![int("id"), int("u"), int("a")], cons: vec![ColCons::Primary(vec!["id"]), ColCons::Unique("u")] }.into()).unwrap();
+  e.exec(&CreateIndex { index: "q_a", table: "q", col: "a", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  let mut rows = BTreeMap::new();
+  let mut batch = |e: &mut Eval, ids: std::ops::Range<i32>, rows: &mut BTreeMap<i32, i32>| {
+    let vals = ids.map(|id| (id, rng.gen_range(0, 100))).collect::<Vec<_>>();
+    let n = e.insert_batch(&Insert { table: "q", vals: vals.iter().map(|&(id, a)| vec![lit(id), lit(id * 2), lit(a)]).collect(), cols: None }).unwrap();
+    assert_eq!(n as usize, vals.len());
+    rows.extend(vals);
+  };
+  // the first batch builds the empty indexes bottom-up, the second one inserts to them
+  batch(&mut e, 0..3000, &mut rows);
+  batch(&mut e, 3000..5000, &mut rows);
+  unsafe {
+    let db = e.db().unwrap();
+    let (tp_id, tp) = db.get_tp("q").unwrap();
+    for col in &["id", "u", "a"] { Index::<{Int}>::new(db, tp_id, tp.get_ci(col, false).unwrap().idx(&tp.cols)).debug_check_all(); }
+  }
+  let select = |where_| Select { ops: Some(vec![Agg { op: None, col: ColRef { db: None, table: None, col: "id" }, arg: None }]), tables: vec![TableRef { db: None, table: "q", sample: None }], where_, order: None, limit: None, offset: 0, derived: vec![] };
+  for a in 0..100 {
+    let res = e.select(&select(vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "a" }, Atom::Lit(lit(a)))])).unwrap();
+    let mut ids = res.rows().map(|r| r.get::<i32>(0).unwrap()).collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, rows.iter().filter(|&(_, &a1)| a1 == a).map(|(&id, _)| id).collect::<Vec<_>>());
+  }
+  // duplicates with the table or inside the batch, nothing is inserted
+  let dup_table = vec![vec![lit(5000), lit(10000), lit(0)], vec![lit(5001), lit(2), lit(0)]];
+  let dup_batch = vec![vec![lit(5000), lit(10000), lit(0)], vec![lit(5001), lit(10000), lit(0)]];
+  for vals in vec![dup_table, dup_batch] {
+    match e.insert_batch(&Insert { table: "q", vals, cols: None }) {
+      Err(ModifyError(0, Error::PutDupOnUnique { col: "u", .. })) => {}
+      r => panic!("{:?}", r),
+    }
+  }
+  assert_eq!(e.select(&select(vec![])).unwrap().row_count(), 5000);
+  e.exec(&Stmt::DropDb("insert_batch")).unwrap();
+}
+
+#[test]
+fn table_handle() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("table_handle")).unwrap();
+  e.exec(&Stmt::UseDb("table_handle")).unwrap();
+  e.exec_cached("create table t (id int, parent int, name char(8), primary key(id), foreign key(parent) references t(id));", |_| {}).unwrap();
+  e.exec_cached("create table u (id int, t_id int, foreign key(t_id) references t(id)); insert into t values (1, null, 'a'), (2, 1, null);", |_| {}).unwrap();
+  let db = e.db().unwrap();
+  let (t, u) = (db.table_handle("t").unwrap(), db.table_handle("u").unwrap());
+  assert!(db.table_handle("no").is_err() && t.col(db, "no").is_err());
+  assert_eq!(db.table_handles().collect::<Vec<_>>(), vec![t, u]);
+  assert_eq!((t.name(db).unwrap(), t.cols(db).unwrap().count()), ("t", 3));
+  let (id, parent, name) = (t.col(db, "id").unwrap(), t.col(db, "parent").unwrap(), t.col(db, "name").unwrap());
+  // handles of the same table are held together, and resolved only on access
+  assert_eq!((parent.foreign(db).unwrap(), u.col(db, "t_id").unwrap().foreign(db).unwrap(), id.foreign(db).unwrap()), (Some(id), Some(id), None));
+  assert_eq!((name.name(db).unwrap(), name.table(), id.info(db).unwrap().index != !0), ("name", t, true));
+  let vals = t.records(db).unwrap().collect::<Vec<_>>().into_iter()
+    .map(|(data, _)| unsafe { (id.value(db, data).unwrap().unwrap(), parent.value(db, data).unwrap(), name.value(db, data).unwrap()) }).collect::<Vec<_>>();
+  assert_eq!(format!("{:?}", vals), "[(1, None, Some('a')), (2, Some(1), None)]");
+  e.exec_cached("insert into u values (1, null), (2, 1); alter table u drop foreign key t_id; alter table u add primary key (id);", |_| {}).unwrap();
+  assert!(e.exec_cached("insert into u values (3, 4); alter table u add foreign key (t_id) references u(id);", |_| {}).is_err());
+  e.exec_cached("delete from u where id = 3; alter table u add foreign key (t_id) references u(id);", |_| {}).unwrap();
+  e.exec_cached("set foreign_keys = off; insert into u values (3, 4); set foreign_keys = on;", |_| {}).unwrap();
+  let orphans = out(&mut e, "check foreign keys;");
+  assert!(orphans.starts_with("`u.t_id` = 4 at ") && orphans.ends_with(": not found in `u.id`\n1 orphaned record(s)"), "{}", orphans);
+  // a handle of a dropped table is rejected on access, instead of reading whatever its page holds now, so is one of a table whose cols are changed
+  e.exec_cached("drop table u; create table v (a int); alter table t add x int;", |_| {}).unwrap();
+  let db = e.db().unwrap();
+  assert!(match t.records(db) { Err(Error::StaleHandle(_)) => true, _ => false });
+  match (u.name(db), name.info(db)) { (Err(Error::StaleHandle(_)), Err(Error::StaleHandle(_))) => {}, _ => panic!() }
+  let t = db.table_handle("t").unwrap();
+  assert_eq!((t.cols(db).unwrap().count(), t.col(db, "name").unwrap().name(db).unwrap()), (4, "name"));
+  e.exec(&Stmt::DropDb("table_handle")).unwrap();
+}
+
+#[test]
+fn statement_log() {
+  use std::{rc::Rc, cell::RefCell};
+  let log = Rc::new(RefCell::new(vec![]));
+  let mut e = Eval::default();
+  let log1 = log.clone();
+  e.set_log(move |entry| log1.borrow_mut().push((entry.rows, entry.error.is_some(), entry.line())));
+  e.exec(&Stmt::CreateDb("statement_log")).unwrap();
+  e.exec(&Stmt::UseDb("statement_log")).unwrap();
+  e.exec_cached("create table t (a int, primary key(a)); insert into t values (1), (2); select * from t;", |_| {}).unwrap();
+  assert!(e.exec_cached("insert into t values (3), (1), (4);", |_| {}).is_err());
+  assert!(e.exec_cached("delete from no_such;", |_| {}).is_err());
+  e.exec_cached("update t set a = 5 where a = 3;", |_| {}).unwrap();
+  let log = log.replace(vec![]);
+  assert_eq!(log.iter().map(|&(rows, err, _)| (rows, err)).collect::<Vec<_>>(),
+    [(None, false), (None, false), (None, false), (Some(2), false), (None, false), (Some(1), true), (Some(0), true), (Some(1), false)]);
+  let fields = log[3].2.split('\t').collect::<Vec<_>>();
+  assert!(fields.len() == 5 && fields[0].contains('.') && fields[1].parse::<u64>().is_ok() && fields[2] == "2" && fields[3] == "ok");
+  assert!(fields[4].starts_with("Insert") && !log[3].2.contains('\n'));
+  assert!(log[6].2.split('\t').nth(3).unwrap().starts_with("error: NoSuchTable"));
+  // a log file is appended to
+  e.set_log_file("statement_log.log").unwrap();
+  e.exec_cached("select * from t; select * from t;", |_| {}).unwrap();
+  e.clear_log();
+  e.exec_cached("select * from t;", |_| {}).unwrap();
+  assert_eq!(std::fs::read_to_string("statement_log.log").unwrap().lines().count(), 2);
+  std::fs::remove_file("statement_log.log").unwrap();
+  e.exec(&Stmt::DropDb("statement_log")).unwrap();
+}
+
+#[test]
+fn slow_log() {
+  use std::{rc::Rc, cell::RefCell, time::Duration};
+  let log = Rc::new(RefCell::new(vec![]));
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("slow_log")).unwrap();
+  e.exec(&Stmt::UseDb("slow_log")).unwrap();
+  let log1 = log.clone();
+  e.set_slow_log(Duration::from_secs(3600), move |entry| log1.borrow_mut().push(entry.plan.clone()));
+  e.exec_cached("create table t (a int, b int, primary key(a)); insert into t values (1, 1), (2, 2); select * from t;", |_| {}).unwrap();
+  assert!(log.borrow().is_empty());
+  let log1 = log.clone();
+  // every statement takes at least 0
+  e.set_slow_log(Duration::from_secs(0), move |entry| log1.borrow_mut().push(entry.plan.clone()));
+  e.exec_cached("select * from t where a = 1; delete from t where b = 2; insert into t values (3, 3);", |_| {}).unwrap();
+  let log = log.replace(vec![]);
+  assert_eq!(log.len(), 3);
+  assert!(log[0].as_ref().unwrap().starts_with("t: index scan on a") && log[1].as_ref().unwrap().starts_with("t: full scan") && log[2].is_none());
+  e.set_slow_log_file(Duration::from_secs(0), "slow_log.log").unwrap();
+  e.exec_cached("select * from t;", |_| {}).unwrap();
+  e.clear_slow_log();
+  e.exec_cached("select * from t;", |_| {}).unwrap();
+  let file = std::fs::read_to_string("slow_log.log").unwrap();
+  let lines = file.lines().collect::<Vec<_>>();
+  assert!(lines.len() == 2 && lines[0].contains("\tSelect(") && lines[1].starts_with("\tt: full scan"));
+  std::fs::remove_file("slow_log.log").unwrap();
+  e.exec(&Stmt::DropDb("slow_log")).unwrap();
+}
+
+#[test]
+fn sys_counters() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("sys_counters")).unwrap();
+  e.exec(&Stmt::UseDb("sys_counters")).unwrap();
+  let select = |e: &mut Eval, sql| out(e, sql).lines().skip(1).map(|l| l.split(',').map(|x| x.parse::<i32>().unwrap()).collect()).collect::<Vec<Vec<_>>>();
+  e.exec_cached("create table t (a int, b int, primary key(a)); create table u (a int);", |_| {}).unwrap();
+  e.exec_cached("insert into t values (1, 1), (2, 2), (3, 3); insert into t values (4, 4);", |_| {}).unwrap();
+  e.exec_cached("select * from t where b = 1; select * from t where a = 2; delete from t where a = 3;", |_| {}).unwrap();
+  let counter = |e: &mut Eval, name: &str| select(e, &format!("select value from sys.counters where name = '{}';", name))[0][0];
+  // delete never scans by index
+  assert_eq!((counter(&mut e, "full_scans"), counter(&mut e, "index_scans")), (2, 1));
+  assert!(counter(&mut e, "pages_allocated") > 0);
+  // every sql text above is different, the second one for cache_misses is the first hit
+  assert_eq!((counter(&mut e, "cache_misses"), counter(&mut e, "cache_misses"), counter(&mut e, "cache_hits")), (7, 7, 1));
+  // 4 records by each full scan, 1 record by the index scan
+  assert_eq!(select(&mut e, "select rows_read, rows_inserted, rows_deleted from sys.table_counters where table_name = 't';"), [[9, 4, 1]]);
+  assert_eq!(select(&mut e, "select rows_read, rows_inserted, rows_deleted from sys.table_counters where table_name = 'u';"), [[0, 0, 0]]);
+  assert!(e.exec(&Stmt::Attach { path: "sys_counters", alias: "sys" }).is_err());
+  // they are not stored in the db
+  e.exec(&Stmt::UseDb("sys_counters")).unwrap();
+  assert_eq!(counter(&mut e, "full_scans"), 0);
+  e.exec(&Stmt::DropDb("sys_counters")).unwrap();
+}
+
+#[test]
+fn metrics() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("metrics")).unwrap();
+  e.exec(&Stmt::UseDb("metrics")).unwrap();
+  e.exec_cached("create table t (a int); insert into t values (1); select * from t;", |_| {}).unwrap();
+  assert!(e.exec_cached("select * from no_such;", |_| {}).is_err());
+  let m = e.metrics();
+  assert_eq!((m.ok, m.error, m.buckets.iter().sum::<u64>()), (5, 1, 6));
+  let text = e.metrics_text();
+  for l in &["# TYPE db_statements_total counter", "db_statements_total{status=\"ok\"} 5", "db_statements_total{status=\"error\"} 1",
+    "# TYPE db_statement_duration_seconds histogram", "db_statement_duration_seconds_bucket{le=\"+Inf\"} 6", "db_statement_duration_seconds_count 6",
+    "db_scans_total{method=\"full\"} 1", "db_file_size_bytes{file=\"lob\"} "] {
+    assert!(text.lines().any(|x| x.starts_with(l)), "{}", l);
+  }
+  // the buckets are cumulative
+  let buckets = text.lines().filter(|l| l.starts_with("db_statement_duration_seconds_bucket")).map(|l| l.rsplit(' ').next().unwrap().parse::<u64>().unwrap()).collect::<Vec<_>>();
+  assert!(buckets.len() == 9 && buckets.windows(2).all(|w| w[0] <= w[1]));
+  e.exec(&Stmt::DropDb("metrics")).unwrap();
+}
+
+#[test]
+fn exec_batch() {
+  use typed_arena::Arena;
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("exec_batch")).unwrap();
+  e.exec(&Stmt::UseDb("exec_batch")).unwrap();
+  let alloc = Arena::default();
+  let code = "create table t (a int, b char(10)); insert into t values (1, 'x;y'); insert into t values ('z', 1); insert into t values (2, 'w'); select b from t;";
+  let res = e.exec_batch(code, &alloc).unwrap();
+  assert_eq!(res.len(), 5);
+  assert!(res[0].is_ok() && res[1].is_ok() && res[3].is_ok());
+  assert!(res[2].is_err());
+  // the semicolon in the string doesn't split the statement
+  assert_eq!(res[4].as_ref().unwrap(), "b\n\"x;y\"\n\"w\"");
+  // nothing is executed if any statement can't be parsed
+  let alloc = Arena::default();
+  assert!(e.exec_batch("insert into t values (3, 'v'); select from t;", &alloc).is_err());
+  let alloc = Arena::default();
+  assert_eq!(e.exec_batch("select a from t where b = 'v';", &alloc).unwrap()[0].as_ref().unwrap(), "a");
+  e.exec(&Stmt::DropDb("exec_batch")).unwrap();
+}
+
+#[test]
+fn last_insert_rid() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("last_insert_rid")).unwrap();
+  e.exec(&Stmt::UseDb("last_insert_rid")).unwrap();
+  assert_eq!(e.last_insert_rid(), None);
+  e.exec_cached("create table item (name char(10), price float default 1.5);
+    insert into item (name) values ('pen'), ('pen');", |_| {}).unwrap();
+  // the rows have no unique key, but the last one can still be found
+  let rid = e.last_insert_rid().unwrap();
+  assert_eq!(e.select_rid("item", rid).unwrap().csv(), "name,price\n\"pen\",1.5");
+  e.exec_cached("insert into item values ('ink', 2);", |_| {}).unwrap();
+  let rid1 = e.last_insert_rid().unwrap();
+  assert_ne!(rid, rid1);
+  assert_eq!(e.select_rid("item", rid1).unwrap().csv(), "name,price\n\"ink\",2");
+  // a failed insert doesn't change it
+  assert!(e.exec_cached("insert into item values ('a', 1, 2);", |_| {}).is_err());
+  assert_eq!(e.last_insert_rid(), Some(rid1));
+  e.exec_cached("delete from item where name = 'ink';", |_| {}).unwrap();
+  assert_eq!(format!("{:?}", e.select_rid("item", rid1).err().unwrap()), format!("NoSuchRecord {{ page: {}, slot: {} }}", rid1.page(), rid1.slot()));
+  e.exec_cached("create table t (id int);", |_| {}).unwrap();
+  assert!(e.select_rid("t", rid).is_err());
+  e.exec(&Stmt::DropDb("last_insert_rid")).unwrap();
+}
+
+#[test]
+fn cdc() {
+  use db::{Value::*, ChangeOp};
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("cdc")).unwrap();
+  e.exec(&Stmt::UseDb("cdc")).unwrap();
+  e.exec_cached("create table p (id int, name varchar(10), primary key(id)); create table c (pid int, foreign key(pid) references p(id) on delete cascade);", |_| {}).unwrap();
+  // changes before cdc is enabled are never captured
+  e.exec_cached("insert into p values (0, 'zero');", |_| {}).unwrap();
+  assert!(e.db().unwrap().changes_since(0).is_none());
+  e.db().unwrap().enable_cdc(4);
+  let rx = e.db().unwrap().subscribe_changes().unwrap();
+  e.exec_cached("insert into p values (1, 'one'); insert into c values (1); update p set name = 'uno' where id = 1; delete from p where id = 1;", |_| {}).unwrap();
+  let changes = rx.try_iter().collect::<Vec<_>>();
+  let brief = changes.iter().map(|c| (c.pos, c.table.as_str(), c.op)).collect::<Vec<_>>();
+  assert_eq!(brief, [(1, "p", ChangeOp::Insert), (2, "c", ChangeOp::Insert), (3, "p", ChangeOp::Update), (4, "p", ChangeOp::Delete), (5, "c", ChangeOp::Delete)]);
+  assert_eq!((&changes[2].old, &changes[2].new), (&Some(vec![Number(1.0), Str("one".into())]), &Some(vec![Number(1.0), Str("uno".into())])));
+  assert_eq!((&changes[3].old, &changes[3].new), (&Some(vec![Number(1.0), Str("uno".into())]), &None));
+  // only the last 4 changes are kept, so a consumer at position 0 has to resync from position 2
+  let db = e.db().unwrap();
+  assert_eq!(db.changes_since(0).unwrap().err(), Some(2));
+  assert_eq!(db.changes_since(3).unwrap().ok().unwrap().map(|c| c.pos).collect::<Vec<_>>(), [4, 5]);
+  assert_eq!(db.changes_since(5).unwrap().ok().unwrap().count(), 0);
+  db.disable_cdc();
+  assert!(rx.recv().is_err());
+  e.exec(&Stmt::DropDb("cdc")).unwrap();
+}
+
+#[test]
+fn change_hooks() {
+  use std::{rc::Rc, cell::RefCell};
+  use db::ChangeOp;
+  let mut e = Eval::default();
+  let seen = Rc::new(RefCell::new(vec![]));
+  let seen1 = seen.clone();
+  // it is kept when another db is used
+  e.on_change(move |c| seen1.borrow_mut().push((c.table.clone(), c.op, c.pos, c.new.as_ref().map(|v| v.len()))));
+  e.exec(&Stmt::CreateDb("change_hooks")).unwrap();
+  e.exec(&Stmt::UseDb("change_hooks")).unwrap();
+  e.exec_cached("create table t (a int, b int, primary key(a)); create temporary table tmp (a int);", |_| {}).unwrap();
+  e.exec_cached("insert into t values (1, 1), (2, 2); update t set b = 0 where a = 2; insert into tmp values (1);", |_| {}).unwrap();
+  // the first row is inserted before the error
+  assert!(e.exec_cached("insert into t values (3, 3), (1, 1);", |_| {}).is_err());
+  e.insert_batch(&Insert { table: "t", vals: vec![vec![lit(4), lit(4)]], cols: None }).unwrap();
+  e.exec_cached("delete from t where a >= 3;", |_| {}).unwrap();
+  let t = |op, new| ("t".to_owned(), op, 0, new);
+  assert_eq!(*seen.borrow(), [t(ChangeOp::Insert, Some(2)), t(ChangeOp::Insert, Some(2)), t(ChangeOp::Update, Some(2)), ("tmp".to_owned(), ChangeOp::Insert, 0, Some(1)),
+    t(ChangeOp::Insert, Some(2)), t(ChangeOp::Insert, Some(2)), t(ChangeOp::Delete, None), t(ChangeOp::Delete, None)]);
+  e.clear_change_hooks();
+  e.exec_cached("delete from t;", |_| {}).unwrap();
+  assert_eq!(seen.borrow().len(), 8);
+  e.exec(&Stmt::DropDb("change_hooks")).unwrap();
+}
+
+#[test]
+fn result_cache() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("result_cache")).unwrap();
+  e.exec(&Stmt::UseDb("result_cache")).unwrap();
+  let hits = |e: &Eval| { let r = e.result_cache().unwrap(); (r.hits, r.misses, r.len()) };
+  e.exec_cached("create table p (id int, primary key(id)); create table c (pid int, foreign key(pid) references p(id) on delete cascade); create table u (a int);", |_| {}).unwrap();
+  e.exec_cached("insert into p values (1), (2); insert into c values (1), (2), (2);", |_| {}).unwrap();
+  e.enable_result_cache(2);
+  assert_eq!(out(&mut e, "select count(*) from c where pid = 2;"), "count(*)\n2");
+  // only the parsed statement matters
+  assert_eq!(out(&mut e, "SELECT count(*)  FROM c WHERE pid = 2;"), "count(*)\n2");
+  assert_eq!(hits(&e), (1, 1, 1));
+  let arena = typed_arena::Arena::default();
+  e.exec_with_params("select count(*) from c where pid = ?;", &arena, &[CLit::new(Lit::Number(2.0))], |x| assert_eq!(x, "count(*)\n2")).unwrap();
+  assert_eq!(hits(&e), (2, 1, 1));
+  // a change of another table keeps the output
+  e.exec_cached("insert into u values (1);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select count(*) from c where pid = 2;"), "count(*)\n2");
+  assert_eq!(hits(&e), (3, 1, 1));
+  // the records of c are deleted by `on delete cascade`
+  e.exec_cached("delete from p where id = 2;", |_| {}).unwrap();
+  assert_eq!(hits(&e).2, 0);
+  assert_eq!(out(&mut e, "select count(*) from c where pid = 2;"), "count(*)\n0");
+  // a subquery reads p
+  assert_eq!(out(&mut e, "select pid from c where pid = (select id from p);"), "pid\n1");
+  assert_eq!(hits(&e), (3, 3, 2));
+  e.exec_cached("insert into p values (5);", |_| {}).unwrap();
+  assert_eq!(hits(&e).2, 1);
+  // a ddl drops all of them
+  e.exec_cached("alter table u add b int;", |_| {}).unwrap();
+  assert_eq!(hits(&e).2, 0);
+  // the tables of sys change in every statement, so such a select is never cached
+  out(&mut e, "select value from sys.counters where name = 'full_scans';");
+  assert_eq!(hits(&e), (3, 3, 0));
+  e.disable_result_cache();
+  assert!(e.result_cache().is_none());
+  e.exec(&Stmt::DropDb("result_cache")).unwrap();
+}
@@ -0,0 +1,186 @@
+use driver::Eval;
+use syntax::ast::*;
+use common::*;
+use physics::*;
+use db::PageKind;
+use crate::util::out;
+
+#[test]
+fn check_db() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("check_db")).unwrap();
+  e.exec(&Stmt::UseDb("check_db")).unwrap();
+  e.exec_cached("create table t (id int, v char(20), primary key(id)); create index t_v on t(v);", |_| {}).unwrap();
+  for i in 0..300 { e.exec_cached(&format!("insert into t values ({}, 'v{}');", i, i), |_| {}).unwrap(); }
+  e.exec_cached("delete from t where id < 100; update t set v = 'w' where id > 250; analyze t;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "check database;"), "0 problem(s)");
+  unsafe { // free a used slot without removing its index entries, and break the free list into a cycle
+    let db = e.db().unwrap();
+    let tp = db.get_tp("t").unwrap().1;
+    let rid = db.record_iter(tp).next().unwrap().1;
+    let dp = db.get_page::<DataPage>(rid.page());
+    bsdel(dp.used.as_mut_ptr(), rid.slot() as usize);
+    dp.next_free = rid.page();
+    tp.first_free = rid.page();
+  }
+  let report = e.exec(&Stmt::CheckDb).unwrap().into_owned();
+  for line in &["slot(s) are used", "`t`: count = 200, but 199 record(s) are in the data pages", "appears more than once", "the index on `t.id`: it has an entry of slot",
+    "the index on `t.v`: it has an entry of slot"] {
+    assert!(report.contains(line), "{}", report);
+  }
+  assert!(!report.ends_with("\n0 problem(s)"), "{}", report);
+  e.exec(&Stmt::DropDb("check_db")).unwrap();
+}
+
+#[test]
+fn repair_db() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("repair_db")).unwrap();
+  e.exec(&Stmt::UseDb("repair_db")).unwrap();
+  e.exec_cached("create table t (id int, v char(20), primary key(id)); create index t_v on t(v);", |_| {}).unwrap();
+  for i in 0..300 { e.exec_cached(&format!("insert into t values ({}, 'v{}');", i, i), |_| {}).unwrap(); }
+  e.exec_cached("delete from t where id < 100;", |_| {}).unwrap();
+  unsafe { // lose a record, empty the root of an index, and put a data page into the free list
+    let db = e.db().unwrap();
+    let tp = db.get_tp("t").unwrap().1;
+    let rid = db.record_iter(tp).next().unwrap().1;
+    bsdel(db.get_page::<DataPage>(rid.page()).used.as_mut_ptr(), rid.slot() as usize);
+    db.get_page::<IndexPage>(tp.get_ci("v", false).unwrap().index).count = 0;
+    db.dp().first_free = tp.first;
+  }
+  // the copy is repaired, the original is not
+  let report = e.exec(&Stmt::RepairDb(Some(("repair_db", "repair_db_copy")))).unwrap().into_owned();
+  assert!(report.contains("the index on `t.v` is built again") && report.contains("the free list is rebuilt"), "{}", report);
+  assert_ne!(e.exec(&Stmt::CheckDb).unwrap(), "0 problem(s)");
+  e.exec_cached("repair database;", |_| {}).unwrap();
+  assert_eq!(e.exec(&Stmt::CheckDb).unwrap(), "0 problem(s)");
+  assert_eq!(e.exec(&Stmt::RepairDb(None)).unwrap(), "0 fix(es)");
+  let out = std::cell::RefCell::new(vec![]);
+  for db in &["repair_db", "repair_db_copy"] {
+    e.exec(&Stmt::UseDb(db)).unwrap();
+    assert_eq!(e.exec(&Stmt::CheckDb).unwrap(), "0 problem(s)");
+    e.exec_cached("select count(*) from t; select id from t where v = 'v150';", |x| out.borrow_mut().push(x.to_owned())).unwrap();
+  }
+  assert_eq!(out.into_inner(), ["count(*)\n199", "id\n150", "count(*)\n199", "id\n150"]);
+  e.exec(&Stmt::DropDb("repair_db")).unwrap();
+  e.exec(&Stmt::DropDb("repair_db_copy")).unwrap();
+}
+
+#[test]
+fn dump_page() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("dump_page")).unwrap();
+  e.exec(&Stmt::UseDb("dump_page")).unwrap();
+  e.exec_cached("create table t (id int, v char(4), primary key(id)); insert into t values (1, 'a'), (2, null); delete from t where id = 1;", |_| {}).unwrap();
+  unsafe {
+    let db = e.db().unwrap();
+    let (tp_id, tp) = db.get_tp("t").unwrap();
+    assert_eq!(db.page_kind(tp.first), PageKind::Data { tp_id, start: tp.first });
+    assert_eq!(db.page_kind(1), PageKind::Kind);
+    assert!(db.dump_page(0).unwrap().starts_with("page 0: db page, table count = 1"));
+    let dump = db.dump_page(tp_id).unwrap();
+    assert!(dump.contains("table `t`, record size = ") && dump.contains("- col 1: `v`"), "{}", dump);
+    let dump = db.dump_page(tp.first).unwrap();
+    assert!(dump.contains("- used: 01") && dump.ends_with("- slot 1: (2, null)"), "{}", dump);
+    let root = tp.get_ci("id", false).unwrap().index;
+    assert_eq!(db.dump_page(root).unwrap(), format!("page {}: leaf of the index on `t.id`, count = 1, cap = {}, key size = 8, next = none\n  - slot 0: key = 2, rid = Rid {{ page: {}, slot: 1 }}",
+      root, db.get_page::<IndexPage>(root).cap, tp.first));
+    let pages = (db.file_sizes().0 / PAGE_SIZE as u64) as u32;
+    assert!(db.dump_page(pages).is_err());
+  }
+  e.exec(&Stmt::DropDb("dump_page")).unwrap();
+}
+
+#[test]
+fn page_guard() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("page_guard")).unwrap();
+  e.exec(&Stmt::UseDb("page_guard")).unwrap();
+  e.exec_cached("create table t (id int, v char(4), primary key(id)); insert into t values (1, 'a'), (2, null);", |_| {}).unwrap();
+  let db = e.db().unwrap();
+  let tp = db.table("t").unwrap();
+  assert_eq!((tp.name(), tp.cols().len(), tp.col_name(1), tp.col_name(2)), ("t", 2, Some("v"), None));
+  let (tp_id, first, root) = (tp.id(), tp.first, tp.cols()[0].index);
+  assert_eq!(db.page::<DataPage>(first).unwrap().count, 2);
+  assert_eq!(db.page::<IndexPage>(root).unwrap().count, 2);
+  assert_eq!(db.page::<TablePage>(tp_id).unwrap().name(), "t");
+  match db.page::<IndexPage>(first) { Err(Error::WrongPageKind { page, expect: "index page" }) => assert_eq!(page, first), _ => panic!() }
+  match db.page::<DbPage>(!0 - 1) { Err(Error::NoSuchPage(_)) => {}, _ => panic!() }
+  // page 1 holds the tags of the pages, it is used by nothing else
+  match db.page::<TablePage>(1) { Err(Error::WrongPageKind { page: 1, expect: "table page" }) => {}, _ => panic!() }
+  assert!(db.table("no").is_err());
+  db.table_mut("t").unwrap().count -= 1;
+  assert_eq!(db.page_mut::<TablePage>(tp_id).unwrap().count, 1);
+  db.table_mut("t").unwrap().count += 1;
+  e.exec(&Stmt::DropDb("page_guard")).unwrap();
+}
+
+#[test]
+fn optimize_table() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("optimize_table")).unwrap();
+  e.exec(&Stmt::UseDb("optimize_table")).unwrap();
+  let pages = |e: &mut Eval| unsafe {
+    let db = e.db().unwrap();
+    let (mut page, mut n) = (db.get_tp("t").unwrap().1.first, 0);
+    while page != !0 { (page = db.get_page::<DataPage>(page).next, n += 1); }
+    n
+  };
+  let vals = (0..2000).map(|i| format!("({}, 's{}')", i, i)).collect::<Vec<_>>().join(", ");
+  e.exec_cached(&format!("create table t (id int, s varchar(20), primary key(id)); insert into t values {};", vals), |_| {}).unwrap();
+  e.exec_cached("delete from t where id >= 100;", |_| {}).unwrap();
+  let before = pages(&mut e);
+  assert!(before > 1);
+  assert_eq!(out(&mut e, "optimize table t;"), format!("{} page(s) released", before - 1));
+  assert_eq!(pages(&mut e), 1);
+  // the index refers to the moved records, and the varchars are kept
+  assert_eq!(out(&mut e, "select s from t where id = 42;"), "s\n\"s42\"");
+  assert_eq!(out(&mut e, "select count(*) from t where id < 50;"), "count(*)\n50");
+  e.exec_cached("insert into t values (2000, 'x'); delete from t where id = 0;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select count(*) from t;"), "count(*)\n100");
+  assert_eq!(out(&mut e, "optimize table t;"), "0 page(s) released");
+  assert!(e.exec_cached("optimize table t1;", |_| {}).is_err());
+  e.exec(&Stmt::DropDb("optimize_table")).unwrap();
+}
+
+#[test]
+fn migrate() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("migrate")).unwrap();
+  e.exec(&Stmt::UseDb("migrate")).unwrap();
+  let alloc = typed_arena::Arena::default();
+  let mut scripts = vec!["create table t (a int);", "alter table t add b int default 0; insert into t values (1, 2);"];
+  assert_eq!(e.migrate(&scripts, &alloc).unwrap(), 2);
+  assert_eq!(e.migrate(&scripts, &alloc).unwrap(), 0);
+  // the version is stored in the db
+  e.exec(&Stmt::UseDb("migrate")).unwrap();
+  assert_eq!(e.db().unwrap().schema_version(), 2);
+  scripts.push("create index t_a on t(a); insert into t values (2, 3);");
+  scripts.push("alter table t drop c;");
+  scripts.push("insert into t values (3, 4);");
+  // the third one is applied, the fourth one fails, and the fifth one is not applied
+  assert_eq!(format!("{:?}", e.migrate(&scripts, &alloc).unwrap_err()), "NoSuchCol(\"c\")");
+  assert_eq!(e.db().unwrap().schema_version(), 3);
+  assert_eq!(out(&mut e, "select a, b from t;"), "a,b\n1,2\n2,3");
+  scripts[3] = "alter table t drop b;";
+  assert_eq!(e.migrate(&scripts, &alloc).unwrap(), 2);
+  assert_eq!(out(&mut e, "select * from t;"), "a\n1\n2\n3");
+  assert_eq!(format!("{:?}", e.migrate(&scripts[..2], &alloc).unwrap_err()), "SchemaVersionTooNew { version: 5, known: 2 }");
+  e.exec(&Stmt::DropDb("migrate")).unwrap();
+}
+
+#[test]
+fn format_version() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("format_version")).unwrap();
+  let mut bytes = std::fs::read("format_version").unwrap();
+  assert_eq!(bytes[MAGIC_LEN..MAGIC_LEN + 2], FORMAT_VERSION.to_ne_bytes());
+  // a file made before the format version existed has 0 there, its pages have other layouts
+  bytes[MAGIC_LEN..MAGIC_LEN + 2].copy_from_slice(&0u16.to_ne_bytes());
+  std::fs::write("format_version", &bytes).unwrap();
+  match e.exec(&Stmt::UseDb("format_version")) {
+    Err(ModifyError(_, Error::InvalidFormat { found: 0, expect: FORMAT_VERSION })) => {}
+    _ => panic!(),
+  }
+  e.exec(&Stmt::DropDb("format_version")).unwrap();
+}
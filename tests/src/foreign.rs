@@ -0,0 +1,193 @@
+use driver::Eval;
+use syntax::ast::*;
+use crate::util::out;
+
+#[test]
+fn foreign_on_delete() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("foreign_on_delete")).unwrap();
+  e.exec(&Stmt::UseDb("foreign_on_delete")).unwrap();
+  let count = |e: &mut Eval, sql| out(e, sql).split('\n').nth(1).unwrap().parse::<i32>().unwrap();
+  for sql in &["create table p (id int, primary key(id));",
+    "create table c (id int, pid int, primary key(id), foreign key(pid) references p(id) on delete cascade);",
+    "create table n (id int, pid int, foreign key(pid) references p(id) on delete set null);",
+    "create table g (cid int, foreign key(cid) references c(id));",
+    "insert into p values (1), (2), (3);",
+    "insert into c values (10, 1), (11, 1), (20, 2);",
+    "insert into n values (1, 1), (2, 2), (3, 3);",
+    "insert into g values (20);",
+    "delete from p where id = 1;"] {
+    e.exec_cached(sql, |_| {}).unwrap();
+  }
+  assert_eq!(count(&mut e, "select count(*) from c;"), 1);
+  assert_eq!(count(&mut e, "select count(*) from n where pid is null;"), 1);
+  assert_eq!(count(&mut e, "select count(*) from n where pid = 1;"), 0); // removed from the index on pid
+  // 2 is referenced by c.20, which is referenced by g without an action, so nothing is modified
+  assert!(e.exec_cached("delete from p where id = 2;", |_| {}).is_err());
+  assert_eq!((count(&mut e, "select count(*) from p;"), count(&mut e, "select count(*) from c;")), (2, 1));
+  e.exec_cached("delete from g;", |_| {}).unwrap();
+  e.exec_cached("delete from p where id = 2;", |_| {}).unwrap();
+  assert_eq!((count(&mut e, "select count(*) from c;"), count(&mut e, "select count(*) from n where pid is null;")), (0, 2));
+  // set null on a notnull col is rejected
+  e.exec_cached("create table m (pid int not null, foreign key(pid) references p(id) on delete set null);", |_| {}).unwrap();
+  e.exec_cached("insert into m values (3);", |_| {}).unwrap();
+  assert!(e.exec_cached("delete from p;", |_| {}).is_err());
+  // cascade in a self-referencing table, a deleted record may be visited later by the scan
+  e.exec_cached("create table t (id int, parent int, primary key(id));", |_| {}).unwrap();
+  e.exec_cached("alter table t add foreign key (parent) references t(id) on delete cascade;", |_| {}).unwrap();
+  e.exec_cached("insert into t values (1, null), (2, 1), (3, 2), (4, null), (5, null);", |_| {}).unwrap();
+  e.exec_cached("update t set parent = 4 where id = 4;", |_| {}).unwrap();
+  e.exec_cached("delete from t where parent is null and id < 5;", |_| {}).unwrap();
+  assert_eq!(count(&mut e, "select count(*) from t;"), 2);
+  e.exec_cached("delete from t where id = 4;", |_| {}).unwrap(); // it references itself
+  assert_eq!(count(&mut e, "select count(*) from t;"), 1);
+  e.exec(&Stmt::DropDb("foreign_on_delete")).unwrap();
+}
+
+#[test]
+fn foreign_on_update() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("foreign_on_update")).unwrap();
+  e.exec(&Stmt::UseDb("foreign_on_update")).unwrap();
+  let count = |e: &mut Eval, sql| out(e, sql).split('\n').nth(1).unwrap().parse::<i32>().unwrap();
+  for sql in &["create table p (id int, x int, primary key(id));",
+    "create table c (id int, pid int, unique(pid), foreign key(pid) references p(id) on update cascade on delete cascade);",
+    "create table g (cid int, foreign key(cid) references c(pid) on update cascade on delete set null);",
+    "create table n (pid int, foreign key(pid) references p(id) on update set null);",
+    "create table r (pid int, foreign key(pid) references p(id));",
+    "insert into p values (1, 0), (2, 0), (3, 0);",
+    "insert into c values (10, 1), (20, 2);",
+    "insert into g values (1), (1), (2);",
+    "insert into n values (1), (2);",
+    "insert into r values (3);",
+    // a referenced col that is not changed doesn't matter, even if it is referenced without an action
+    "update p set x = 1;",
+    "update p set id = 3 where id = 3;",
+    "update p set id = 11 where id = 1;"] {
+    e.exec_cached(sql, |_| {}).unwrap();
+  }
+  // the change is cascaded through c.pid to g.cid, and removed from the index on n.pid
+  assert_eq!(count(&mut e, "select count(*) from c where pid = 11;"), 1);
+  assert_eq!((count(&mut e, "select count(*) from g where cid = 11;"), count(&mut e, "select count(*) from g where cid = 1;")), (2, 0));
+  assert_eq!((count(&mut e, "select count(*) from n where pid is null;"), count(&mut e, "select count(*) from n where pid = 1;")), (1, 0));
+  assert!(e.exec_cached("update p set id = 33 where id = 3;", |_| {}).is_err());
+  assert_eq!(count(&mut e, "select count(*) from p where id = 3;"), 1);
+  // the new value is checked by the cascade too, so the chain can continue from the updated record
+  e.exec_cached("update p set id = id + 100 where id <> 3;", |_| {}).unwrap();
+  assert_eq!((count(&mut e, "select count(*) from c where pid > 100;"), count(&mut e, "select count(*) from g where cid > 100;")), (2, 3));
+  e.exec_cached("delete from p where id = 111;", |_| {}).unwrap();
+  assert_eq!((count(&mut e, "select count(*) from c;"), count(&mut e, "select count(*) from g where cid is null;")), (1, 2));
+  // a record referencing itself
+  e.exec_cached("create table t (id int, parent int, primary key(id));", |_| {}).unwrap();
+  e.exec_cached("alter table t add foreign key (parent) references t(id) on update cascade;", |_| {}).unwrap();
+  e.exec_cached("insert into t values (1, null), (2, 1);", |_| {}).unwrap();
+  e.exec_cached("update t set parent = 1 where id = 1;", |_| {}).unwrap();
+  e.exec_cached("update t set id = 5 where id = 1;", |_| {}).unwrap();
+  assert_eq!(count(&mut e, "select count(*) from t where parent = 5;"), 2);
+  e.exec(&Stmt::DropDb("foreign_on_update")).unwrap();
+}
+
+#[test]
+fn drop_table_cascade() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("drop_table_cascade")).unwrap();
+  e.exec(&Stmt::UseDb("drop_table_cascade")).unwrap();
+  for sql in &["create table p (id int, primary key(id));",
+    "create table c (id int, pid int, primary key(id), foreign key(pid) references p(id));",
+    "create table g (cid int, foreign key(cid) references c(id));",
+    "insert into p values (1);",
+    "insert into c values (1, 1);"] {
+    e.exec_cached(sql, |_| {}).unwrap();
+  }
+  assert!(e.exec(&Stmt::DropTable { table: "p", cascade: false }).is_err());
+  // the foreign key of g is dropped with c, then nothing references p
+  e.exec(&Stmt::DropTable { table: "c", cascade: true }).unwrap();
+  e.exec(&Stmt::DropTable { table: "p", cascade: false }).unwrap();
+  e.exec_cached("insert into g values (2);", |_| {}).unwrap();
+  assert!(!e.exec(&Stmt::ShowTable("g")).unwrap().contains("foreign"));
+  // a foreign key in the table itself doesn't prevent dropping it
+  e.exec_cached("create table t (id int, parent int, primary key(id));", |_| {}).unwrap();
+  e.exec_cached("alter table t add foreign key (parent) references t(id);", |_| {}).unwrap();
+  e.exec(&Stmt::DropTable { table: "t", cascade: false }).unwrap();
+  e.exec(&Stmt::DropDb("drop_table_cascade")).unwrap();
+}
+
+#[test]
+fn check_foreign_keys() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("check_foreign_keys")).unwrap();
+  e.exec(&Stmt::UseDb("check_foreign_keys")).unwrap();
+  for sql in &["create table p (id int, primary key(id));",
+    "create table c (id int, pid int, foreign key(pid) references p(id));",
+    "insert into p values (1), (2);",
+    "insert into c values (1, 1), (2, null), (3, 2);"] {
+    e.exec_cached(sql, |_| {}).unwrap();
+  }
+  assert_eq!(e.exec(&Stmt::CheckForeignKeys).unwrap(), "0 orphaned record(s)");
+  unsafe { // write a value that doesn't exist in p to the last record, bypassing the check
+    let db = e.db().unwrap();
+    let tp = db.get_tp("c").unwrap().1;
+    let (id_off, pid_off) = (tp.get_ci("id", false).unwrap().off as usize, tp.get_ci("pid", false).unwrap().off as usize);
+    let data = db.record_iter(tp).map(|(data, _)| data).find(|&data| *(data.add(id_off) as *const i32) == 3).unwrap();
+    *(data.add(pid_off) as *mut i32) = 5;
+  }
+  let report = e.exec(&Stmt::CheckForeignKeys).unwrap().into_owned();
+  assert!(report.starts_with("`c.pid` = 5 at") && report.ends_with("not found in `p.id`\n1 orphaned record(s)"), "{}", report);
+  e.exec(&Stmt::DropDb("check_foreign_keys")).unwrap();
+}
+
+#[test]
+fn self_referencing_foreign() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("self_referencing_foreign")).unwrap();
+  e.exec(&Stmt::UseDb("self_referencing_foreign")).unwrap();
+  assert!(e.exec_cached("create table t (id int, parent int, foreign key(parent) references t(id));", |_| {}).is_err()); // id is not unique
+  assert!(e.exec_cached("create table t (id int, parent float, unique(id), foreign key(parent) references t(id));", |_| {}).is_err());
+  assert!(e.exec_cached("create table t (id int, parent int, unique(id), foreign key(parent) references t(no));", |_| {}).is_err());
+  e.exec_cached("create table t (id int, parent int, primary key(id), foreign key(parent) references t(id) on delete cascade);", |_| {}).unwrap();
+  e.exec_cached("insert into t values (1, null), (2, 1), (3, 2), (4, 1), (5, null);", |_| {}).unwrap();
+  assert!(e.exec_cached("insert into t values (6, 7);", |_| {}).is_err());
+  assert!(e.exec_cached("update t set parent = 7 where id = 5;", |_| {}).is_err());
+  assert!(e.exec(&Stmt::ShowTable("t")).unwrap().contains("foreign: `t.id` on delete cascade"));
+  e.exec_cached("delete from t where id = 1;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id from t;"), "id\n5");
+  e.exec(&Stmt::DropDb("self_referencing_foreign")).unwrap();
+}
+
+#[test]
+fn foreign_char_size() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("foreign_char_size")).unwrap();
+  e.exec(&Stmt::UseDb("foreign_char_size")).unwrap();
+  let count = |e: &mut Eval, sql| out(e, sql).split('\n').nth(1).unwrap().parse::<i32>().unwrap();
+  e.exec_cached("create table p (id char(10), x int, unique(id), unique(x));", |_| {}).unwrap();
+  assert!(e.exec_cached("create table bad (x float, foreign key(x) references p(x));", |_| {}).is_err());
+  for sql in &["create table s (pid char(4), foreign key(pid) references p(id) on update cascade);",
+    "create table l (pid char(20), foreign key(pid) references p(id) on update cascade on delete cascade);",
+    "insert into p values ('ab', 1), ('abcdefgh', 2);",
+    "insert into s values ('ab'), ('ab');",
+    "insert into l values ('ab'), ('abcdefgh');"] {
+    e.exec_cached(sql, |_| {}).unwrap();
+  }
+  // the values are compared, not the sizes of cols
+  assert!(e.exec_cached("insert into s values ('abc');", |_| {}).is_err());
+  assert!(e.exec_cached("insert into l values ('abcdefghij');", |_| {}).is_err());
+  assert!(e.exec_cached("insert into l values ('abcdefghijk');", |_| {}).is_err());
+  assert!(e.exec_cached("update l set pid = 'x';", |_| {}).is_err());
+  // the new value is too long for s.pid
+  assert!(e.exec_cached("update p set id = 'abcde' where id = 'ab';", |_| {}).is_err());
+  e.exec_cached("update p set id = 'xy' where id = 'ab';", |_| {}).unwrap();
+  assert_eq!((count(&mut e, "select count(*) from s where pid = 'xy';"), count(&mut e, "select count(*) from l where pid = 'xy';")), (2, 1));
+  // a value longer than s.pid can't be referenced by s
+  e.exec_cached("update p set id = 'abcdefghij' where id = 'abcdefgh';", |_| {}).unwrap();
+  assert_eq!(count(&mut e, "select count(*) from l where pid = 'abcdefghij';"), 1);
+  e.exec_cached("delete from p where x = 2;", |_| {}).unwrap();
+  assert_eq!(count(&mut e, "select count(*) from l;"), 1);
+  e.exec_cached("alter table s drop foreign key pid;", |_| {}).unwrap();
+  e.exec_cached("insert into s values ('zz');", |_| {}).unwrap();
+  assert!(e.exec_cached("alter table s add foreign key (pid) references p(id);", |_| {}).is_err());
+  e.exec_cached("delete from s where pid = 'zz';", |_| {}).unwrap();
+  e.exec_cached("alter table s add foreign key (pid) references p(id);", |_| {}).unwrap();
+  assert_eq!(e.exec(&Stmt::CheckForeignKeys).unwrap(), "0 orphaned record(s)");
+  e.exec(&Stmt::DropDb("foreign_char_size")).unwrap();
+}
@@ -0,0 +1,243 @@
+use driver::Eval;
+use syntax::ast::*;
+use common::*;
+use crate::util::{out, err};
+
+#[test]
+fn default_fn() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("default_fn")).unwrap();
+  e.exec(&Stmt::UseDb("default_fn")).unwrap();
+  let select = |e: &mut Eval, sql| out(e, sql).lines().skip(1).map(|l| l.split(',').map(|x| x.trim_matches('"').to_owned()).collect()).collect::<Vec<Vec<_>>>();
+  for sql in &["create table bad (u date default uuid4());", "create table bad (d char(9) default current_date);",
+    "create table bad (t char(18) default current_timestamp);", "create table bad (i int default now());", "create table bad (u varchar(40) default uuid4());"] {
+    assert!(e.exec_cached(sql, |_| {}).is_err());
+  }
+  e.exec_cached("create table t (id int, d date default current_date, s char(10) default current_date, ts char(19) default now(), u char(36) default uuid4(), unique(u));", |_| {}).unwrap();
+  e.exec_cached("insert into t (id) values (1), (2); insert into t values (3); insert into t values (4, null, null, null, null);", |_| {}).unwrap();
+  let rows = select(&mut e, "select d, s, ts, u from t where id < 4;");
+  assert_eq!(rows.len(), 3);
+  for r in &rows {
+    assert!(r[0] == r[1] && r[2].starts_with(&r[1]) && r[2].len() == 19);
+    assert!(r[3].len() == 36 && &r[3][14..15] == "4" && r[3].matches('-').count() == 4);
+  }
+  // each record gets a new uuid
+  assert!(rows[0][3] != rows[1][3] && rows[1][3] != rows[2][3] && rows[0][3] != rows[2][3]);
+  // a value given explicitly (even null) is not replaced
+  assert_eq!(select(&mut e, "select d, s, ts, u from t where id = 4;"), vec![vec!["", "", "", ""]]);
+  e.exec_cached("alter table t add v char(36) not null default uuid4();", |_| {}).unwrap();
+  assert_eq!(select(&mut e, "select count(*) from t where v is null;"), vec![vec!["0"]]);
+  assert!(e.exec(&Stmt::ShowTable("t")).unwrap().contains("default: uuid4()"));
+  e.exec(&Stmt::DropDb("default_fn")).unwrap();
+}
+
+#[test]
+fn date_format() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("date_format")).unwrap();
+  e.exec(&Stmt::UseDb("date_format")).unwrap();
+  e.exec_cached("create table t (a int, d date); insert into t values (1, '2020-01-05'), (2, '2020/01/06'), (3, '2020-01-07 12:30:00'), \
+                 (4, '2020/01/08T01:02:03'), (5, '2020-01-09 23:59');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select d from t where a = 4;"), "d\n2020-01-08");
+  // the time is ignored
+  assert_eq!(out(&mut e, "select a from t where d >= '2020/01/07 23:00' and d < '2020-01-09';"), "a\n3\n4");
+  for d in &["05/01/2020", "2020-13-01", "2020-01-05 25:00", "2020.01.05"] {
+    match e.exec_cached(&format!("insert into t values (6, '{}');", d), |_| {}) {
+      Err(ModifyError(_, Error::InvalidDate { date, expect, .. })) => assert_eq!((date, expect), (*d, db::DATE_EXPECT)),
+      _ => panic!(),
+    }
+  }
+  e.exec(&Stmt::DropDb("date_format")).unwrap();
+}
+
+#[test]
+fn interval() {
+  let d = |s| date(s).unwrap();
+  let i = |n, unit| Interval { n, unit };
+  assert_eq!(i(1, IntervalUnit::Month).add(d("2020-01-31")), Some(d("2020-02-29")));
+  assert_eq!(i(-13, IntervalUnit::Month).add(d("2020-03-31")), Some(d("2019-02-28")));
+  assert_eq!(i(1, IntervalUnit::Year).add(d("2020-02-29")), Some(d("2021-02-28")));
+  assert_eq!(i(-2, IntervalUnit::Week).add(d("2020-01-01")), Some(d("2019-12-18")));
+  assert_eq!(i(std::i32::MAX, IntervalUnit::Year).add(d("2020-01-01")), None);
+  assert_eq!(IntervalUnit::from_name("Days"), Some(IntervalUnit::Day));
+
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("interval")).unwrap();
+  e.exec(&Stmt::UseDb("interval")).unwrap();
+  e.exec_cached("create table t (a int, d date); insert into t values (1, '2020-01-31'), (2, '2020-02-29'), (3, '2020-03-01');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select a from t where d >= '2020-01-31' + interval 1 month;"), "a\n2\n3");
+  assert_eq!(out(&mut e, "select a from t where d between '2020-03-01' - interval 1 day and '2020/02/01' + INTERVAL 4 weeks;"), "a\n2");
+  assert_eq!(out(&mut e, "update t set d = d + interval 1 year where a = 2; select d from t where a = 2;"), "d\n2021-02-28");
+  assert_eq!(out(&mut e, "update t set d = (d - interval 1 month) + interval 1 day where a = 3; select d from t where a = 3;"), "d\n2020-02-02");
+  // params are bound when parsed, so they can be folded too
+  let alloc = typed_arena::Arena::default();
+  let params = [CLit::new(Lit::Str("2020-03-01"))];
+  match &syntax::work_with_params("select a from t where d < ? - interval 30 day;", &alloc, &params).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{:?}", s.where_), "[d < '2020-01-31']"),
+    _ => panic!(),
+  }
+  assert!(err(&mut e, "select a from t where d > '2020-01-01' + interval 1 fortnight;").contains("InvalidIntervalUnit(\"fortnight\")"));
+  assert!(err(&mut e, "select a from t where d > 1 + interval 1 day;").contains("InvalidIntervalDate"));
+  assert_eq!(err(&mut e, "update t set a = a + interval 1 day;"), "IncompatibleBin { op: Add, ty: Number }");
+  e.exec(&Stmt::DropDb("interval")).unwrap();
+}
+
+#[test]
+fn group_concat() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("group_concat")).unwrap();
+  e.exec(&Stmt::UseDb("group_concat")).unwrap();
+  e.exec_cached("create table t (id int, tag varchar(10), d date); insert into t values (3, 'c', '2020-01-03'), (1, 'a', null), (2, null, '2020-01-02'), (4, 'b', '2020-01-01');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select group_concat(tag) from t;"), "group_concat(tag)\n\"c,a,b\"");
+  assert_eq!(out(&mut e, "select group_concat(tag order by id separator '; ') from t;"), "group_concat(tag)\n\"a; c; b\"");
+  assert_eq!(out(&mut e, "select group_concat(tag order by tag desc, '') from t;"), "group_concat(tag)\n\"cba\"");
+  // null keys are the smallest, and values of other types are joined as text
+  assert_eq!(out(&mut e, "select string_agg(id, '-' order by d), count(*) from t;"), "group_concat(id),count(*)\n\"1-4-2-3\",4");
+  assert_eq!(out(&mut e, "select group_concat(d order by id desc) from t where id > 1;"), "group_concat(d)\n\"2020-01-01,2020-01-03,2020-01-02\"");
+  // no not null value
+  assert_eq!(out(&mut e, "select group_concat(tag) from t where id = 2;"), "group_concat(tag)\n");
+  match &syntax::work("select string_agg(tag, '''' order by id desc) from t;", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{:?}", s.ops), "Some([group_concat(tag order by id desc separator '''')])"),
+    _ => panic!(),
+  }
+  e.exec(&Stmt::DropDb("group_concat")).unwrap();
+}
+
+#[test]
+fn stat_agg() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("stat_agg")).unwrap();
+  e.exec(&Stmt::UseDb("stat_agg")).unwrap();
+  e.exec_cached("create table t (a int, b float, s char(1)); insert into t values (1, 1.5, 'x'), (2, null, 'y'), (3, 0.5, 'z'), (null, 2.5, 'z'), (4, 3.5, 'z'), (5, -1.5, 'z'), (6, 2, 'z');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select variance(a), median(a), median(b) from t;"), "variance(a),median(a),median(b)\n3.5,3.5,1.75");
+  assert_eq!(out(&mut e, "select stddev(a) from t where a < 5;"), format!("stddev(a)\n{}", (5.0f64 / 3.0).sqrt()));
+  assert_eq!(out(&mut e, "select percentile_cont(0.25) within group (order by a), percentile_cont(0.25) within group (order by a desc) from t;"),
+             "percentile_cont(a),percentile_cont(a)\n2.25,4.75");
+  assert_eq!(out(&mut e, "select percentile_cont(1) within group (order by b), percentile_cont(0) within group (order by b) from t;"),
+             "percentile_cont(b),percentile_cont(b)\n3.5,-1.5");
+  // a sample of one value has no variance
+  assert_eq!(out(&mut e, "select stddev(a), variance(a), median(a) from t where s = 'x';"), "stddev(a),variance(a),median(a)\n,,1");
+  assert_eq!(out(&mut e, "select median(a) from t where s = 'w';"), "median(a)\n");
+  // the same with an index on `a`, which may answer the query only with the index keys
+  e.exec_cached("create index i on t (a);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select variance(a), median(a) from t where a > 0;"), "variance(a),median(a)\n3.5,3.5");
+  assert!(err(&mut e, "select median(s) from t;").contains("op: Median"));
+  assert_eq!(err(&mut e, "select percentile_cont(1.5) within group (order by a) from t;"), "InvalidPercentile(1.5)");
+  e.exec(&Stmt::DropDb("stat_agg")).unwrap();
+}
+
+#[test]
+fn bitwise() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("bitwise")).unwrap();
+  e.exec(&Stmt::UseDb("bitwise")).unwrap();
+  e.exec_cached("create table t (id int, flags int, f float); insert into t values (1, 5, 1), (2, 6, 2), (3, null, 3), (4, 12, 4);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id from t where flags & 2 = 2;"), "id\n2");
+  assert_eq!(out(&mut e, "select id from t where flags | 1 = 13;"), "id\n4");
+  assert_eq!(out(&mut e, "select id from t where flags ^ 5 = 0;"), "id\n1");
+  assert_eq!(out(&mut e, "select id from t where flags << 1 > 10;"), "id\n2\n4");
+  assert_eq!(out(&mut e, "select id from t where flags >> 2 = 3;"), "id\n4");
+  assert_eq!(out(&mut e, "select id from t where ~flags = -6;"), "id\n1");
+  // hash bucketing, and a shift amount out of [0, 32) never matches
+  assert_eq!(out(&mut e, "select id from t where id & 1 = 1;"), "id\n1\n3");
+  assert_eq!(out(&mut e, "select id from t where flags << 32 = 0;"), "id");
+  assert_eq!(out(&mut e, "update t set flags = flags | 16 where id = 1; update t set flags = ~flags & 255 where id = 2; select flags from t where id < 3;"), "flags\n21\n249");
+  // `|` < `&` < shifts < `+` < `^` in precedence
+  assert_eq!(out(&mut e, "update t set flags = 1 | 6 & 3, id = 1 + 2 << 1 where id = 4; select id, flags from t where flags = 3;"), "id,flags\n6,3");
+  assert_eq!(out(&mut e, "update t set flags = 2 + 3 ^ 1 where id = 6; select flags from t where id = 6;"), "flags\n4");
+  // null for a non-integer operand or a shift amount out of range
+  assert_eq!(out(&mut e, "update t set flags = 1.5 & 1 where id = 1; update t set flags = 1 << 40 where id = 2; select id from t where flags is null;"), "id\n1\n2\n3");
+  match &syntax::work("select * from t where flags & 4 = 4;", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{:?}", s.where_), "[flags & 4 == 4]"),
+    _ => panic!(),
+  }
+  assert!(err(&mut e, "select id from t where f & 1 = 1;").starts_with("InvalidBitTy { op: BitAnd"));
+  assert!(err(&mut e, "select id from t where flags & 1.5 = 1;").starts_with("ColLitMismatch"));
+  e.exec(&Stmt::DropDb("bitwise")).unwrap();
+}
+
+#[test]
+fn math_fn() {
+  use MathFn::*;
+  assert_eq!((Round.apply(2.5, 0.0), Round.apply(-2.5, 0.0), Round.apply(1.255, 1.0), Round.apply(1234.0, -2.0)), (3.0, -3.0, 1.3, 1200.0));
+  assert_eq!((Round.apply(1.5, 400.0), Round.apply(1.5, -400.0)), (1.5, 0.0));
+  assert!(Round.apply(1.5, 0.5).is_nan() && Sqrt.apply(-1.0, 0.0).is_nan() && Mod.apply(1.0, 0.0).is_nan());
+  assert_eq!((Ceil.apply(-1.5, 0.0), Floor.apply(-1.5, 0.0), Abs.apply(-3.0, 0.0), Mod.apply(-7.0, 3.0), Power.apply(2.0, -1.0)), (-1.0, -2.0, 3.0, -1.0, 0.5));
+
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("math_fn")).unwrap();
+  e.exec(&Stmt::UseDb("math_fn")).unwrap();
+  e.exec_cached("create table t (id int, i int, f float, s char(5)); insert into t values (1, -7, 2.25, 'a'), (2, 9, -0.5, 'b'), (3, null, 16, 'c');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "update t set i = abs(i) + mod(i, 4), f = sqrt(f); select i, f from t;"), "i,f\n4,1.5\n10,\n,4");
+  assert_eq!(out(&mut e, "update t set f = round(f * 3, 0) where id = 1; update t set f = power(f, 0.5) + ceil(-0.5) where id = 3; select f from t;"),
+             "f\n5\n\n2");
+  assert_eq!(out(&mut e, "update t set i = floor(ROUND(f) / 2), f = pow(2, CEILING(1.2)) where id = 1; select i, f from t where id = 1;"), "i,f\n2,4");
+  match &syntax::work("update t set i = round(i, -1) + abs(i);", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Update(u) => assert_eq!(format!("{:?}", u.sets), "[(\"i\", (round(i, -1)) + (abs(i)))]"),
+    _ => panic!(),
+  }
+  assert_eq!(err(&mut e, "update t set i = sqrt(s);"), "InvalidMathArg { func: Sqrt, ty: Str }");
+  e.exec(&Stmt::DropDb("math_fn")).unwrap();
+}
+
+#[test]
+fn null_fn() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("null_fn")).unwrap();
+  e.exec(&Stmt::UseDb("null_fn")).unwrap();
+  e.exec_cached("create table t (id int, a int, b int, s varchar(10)); insert into t values (1, null, 2, null), (2, 3, null, 'x'), (3, null, null, '');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "update t set a = coalesce(a, b, -1), s = ifnull(s, 'none'); select a, s from t;"), "a,s\n2,\"none\"\n3,\"x\"\n-1,\"\"");
+  // nullif turns a sentinel value back into null
+  assert_eq!(out(&mut e, "update t set a = nullif(a, -1), s = nullif(s, ''); select id from t where a is null and s is null;"), "id\n3");
+  assert_eq!(out(&mut e, "update t set b = nullif(b, null) + coalesce(null, null, id * 10); select b from t;"), "b\n12\n\n");
+  match &syntax::work("update t set a = coalesce(a, ifnull(b, 0));", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Update(u) => assert_eq!(format!("{:?}", u.sets), "[(\"a\", coalesce(a, coalesce(b, 0)))]"),
+    _ => panic!(),
+  }
+  assert_eq!(err(&mut e, "update t set a = coalesce(null, a, s);"), "IncompatibleArgs { func: \"coalesce\", l: Number, r: Str }");
+  assert_eq!(err(&mut e, "update t set a = nullif(a, 'x');"), "IncompatibleArgs { func: \"nullif\", l: Number, r: Str }");
+  e.exec(&Stmt::DropDb("null_fn")).unwrap();
+}
+
+#[test]
+fn greatest_least() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("greatest_least")).unwrap();
+  e.exec(&Stmt::UseDb("greatest_least")).unwrap();
+  e.exec_cached("create table t (id int, a int, b float, d date, s char(5)); insert into t values (1, 3, 2.5, '2020-01-02', 'b'), (2, null, 1, null, 'a'), (3, null, null, null, null);", |_| {}).unwrap();
+  // null arguments are skipped, the result is null only if all of them are null
+  assert_eq!(out(&mut e, "update t set a = greatest(a, b, id), b = least(a, b, id); select a, b from t;"), "a,b\n3,1\n2,1\n3,3");
+  assert_eq!(out(&mut e, "update t set s = least(s, 'ab', null) where id < 3; select s from t;"), "s\n\"ab\"\n\"a\"\n");
+  assert_eq!(out(&mut e, "update t set s = greatest(s, null) where id = 3; select id from t where s is null;"), "id\n3");
+  assert_eq!(err(&mut e, "update t set a = least(a, d);"), "IncompatibleArgs { func: \"least\", l: Number, r: Date }");
+  e.exec(&Stmt::DropDb("greatest_least")).unwrap();
+}
+
+#[test]
+fn random_fn() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("random")).unwrap();
+  e.exec(&Stmt::UseDb("random")).unwrap();
+  e.exec_cached("create table t (id int, x float, y float); insert into t values (1, null, null), (2, null, null), (3, null, null);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "pragma random_seed;"), "random_seed = off");
+  assert_eq!(out(&mut e, "update t set x = random(); select id from t where x >= 0 and x < 1;"), "id\n1\n2\n3");
+  // the same seed gives the same sequence
+  e.exec_cached("set random_seed = 42; update t set x = random();", |_| {}).unwrap();
+  let first = out(&mut e, "select x from t;");
+  e.exec_cached("set random_seed = 42; update t set x = rand();", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select x from t;"), first);
+  assert_eq!(out(&mut e, "pragma random_seed;"), "random_seed = 42");
+  // rand(seed) only depends on the seed, and the first value after seeding is the same as rand(seed)
+  e.exec_cached("update t set y = rand(42);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id from t where x = y;"), "id\n1");
+  assert_eq!(out(&mut e, "update t set y = rand(id) - rand(id + 0.5); select id from t where y = 0;"), "id\n1\n2\n3");
+  assert_eq!(out(&mut e, "update t set y = rand(null); select id from t where y is null;"), "id\n1\n2\n3");
+  e.exec_cached("set random_seed = off;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "pragma random_seed;"), "random_seed = off");
+  match &syntax::work("update t set x = rand(id) + random();", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Update(u) => assert_eq!(format!("{:?}", u.sets), "[(\"x\", (rand(id)) + (random()))]"),
+    _ => panic!(),
+  }
+  assert_eq!(err(&mut e, "update t set x = rand('a');"), "InvalidRandSeed(Str)");
+  e.exec(&Stmt::DropDb("random")).unwrap();
+}
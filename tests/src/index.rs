@@ -1,12 +1,11 @@
 use driver::Eval;
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, BTreeMap};
 use rand::prelude::*;
 use syntax::ast::*;
 use common::{*, BareTy::*};
 use physics::*;
-use index::Index;
-
-fn lit<'a>(x: i32) -> CLit<'a> { CLit::new(Lit::Number(x as f64)) }
+use index::{Index, RTree};
+use crate::util::{lit, out};
 
 #[test]
 fn index() {
@@ -28,7 +27,7 @@ fn index() {
     macro_rules! del {
       ($range: expr) => {
         for &d in &del[$range] {
-          e.exec(&Stmt::Delete(Delete { table: "index", where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { table: None, col: "id" }, Atom::Lit(lit(d)))] })).unwrap();
+          e.exec(&Stmt::Delete(Delete { table: "index", where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(d)))] })).unwrap();
           let rm = map.range((&(d, 0))..(&(d, N as i32))).cloned().collect::<Vec<_>>();
           for x in rm { map.remove(&x); }
         }
@@ -40,8 +39,12 @@ fn index() {
         for &t in &test {
           let index_count = e.select(&Select {
             ops: None,
-            tables: vec!["index"],
-            where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { table: None, col: "id" }, Atom::Lit(lit(t)))],
+            tables: vec![TableRef { db: None, table: "index", sample: None }],
+            where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(t)))],
+            order: None,
+            limit: None,
+            offset: 0,
+            derived: vec![],
           }).unwrap().row_count();
           let map_count = map.range((&(t, 0))..(&(t, N as i32))).count();
           assert_eq!(index_count, map_count);
@@ -54,11 +57,11 @@ fn index() {
     e.exec(&Stmt::CreateDb("index")).unwrap();
     e.exec(&Stmt::UseDb("index")).unwrap();
     e.exec(&CreateTable { table: "index", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: true, dft: None }], cons: vec![] }.into()).unwrap();
-    e.exec(&CreateIndex { index: "id_index", table: "index", col: "id" }.into()).unwrap();
+    e.exec(&CreateIndex { index: "id_index", table: "index", col: "id", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
     unsafe { // modify IndexPage's cap to generate more splits
       let db = e.db().unwrap();
       let (tp_id, tp) = db.get_tp("index").unwrap();
-      let ci = tp.get_ci("id").unwrap();
+      let ci = tp.get_ci("id", false).unwrap();
       table = tp_id;
       col = ci.idx(&tp.cols);
       db.get_page::<IndexPage>(ci.index).cap = 8;
@@ -73,4 +76,535 @@ fn index() {
     test!();
     e.exec(&Stmt::DropDb("index")).unwrap();
   }
-}
\ No newline at end of file
+}
+
+#[test]
+fn bulk() {
+  const N: usize = 10000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  for &max in &[N / 100, N, N * 100] {
+    let mut e = Eval::default();
+    let mut map = BTreeMap::new();
+    let vals = (0..N).map(|_| rng.gen_range(0, max as i32)).collect::<Vec<_>>();
+    for &x in &vals { *map.entry(x).or_insert(0) += 1; }
+    e.exec(&Stmt::CreateDb("bulk")).unwrap();
+    e.exec(&Stmt::UseDb("bulk")).unwrap();
+    e.exec(&CreateTable { table: "bulk", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+    let mut ins = vals.iter().map(|x| vec![lit(*x)]).collect::<Vec<_>>();
+    ins.push(vec![CLit::new(Lit::Null)]); // null is not put into index
+    e.exec(&Stmt::Insert(Insert { table: "bulk", vals: ins, cols: None })).unwrap();
+    // the index is built from existing records here
+    e.exec(&CreateIndex { index: "id_index", table: "bulk", col: "id", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+    unsafe {
+      let db = e.db().unwrap();
+      let (tp_id, tp) = db.get_tp("bulk").unwrap();
+      let ci_id = tp.get_ci("id", false).unwrap().idx(&tp.cols);
+      Index::<{Int}>::new(db, tp_id, ci_id).debug_check_all();
+    }
+    for &t in vals.iter().take(N / 10) {
+      let index_count = e.select(&Select {
+        ops: None,
+        tables: vec![TableRef { db: None, table: "bulk", sample: None }],
+        where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(t)))],
+        order: None,
+        limit: None,
+        offset: 0,
+        derived: vec![],
+      }).unwrap().row_count();
+      assert_eq!(index_count, map[&t]);
+    }
+    // the bulk built tree should still be valid for later modification
+    e.exec(&Stmt::Delete(Delete { table: "bulk", where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(max as i32 / 2)))] })).unwrap();
+    e.exec(&Stmt::Insert(Insert { table: "bulk", vals: vals.iter().map(|x| vec![lit(*x)]).collect(), cols: None })).unwrap();
+    unsafe {
+      let db = e.db().unwrap();
+      let (tp_id, tp) = db.get_tp("bulk").unwrap();
+      let ci_id = tp.get_ci("id", false).unwrap().idx(&tp.cols);
+      Index::<{Int}>::new(db, tp_id, ci_id).debug_check_all();
+    }
+    e.exec(&Stmt::DropDb("bulk")).unwrap();
+  }
+}
+
+#[test]
+fn range() {
+  const N: usize = 10000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let vals = (0..N).map(|_| rng.gen_range(0, N as i32)).collect::<Vec<_>>();
+  e.exec(&Stmt::CreateDb("range")).unwrap();
+  e.exec(&Stmt::UseDb("range")).unwrap();
+  e.exec(&CreateTable { table: "range", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "range", vals: vals.iter().map(|x| vec![lit(*x)]).collect(), cols: None })).unwrap();
+  e.exec(&CreateIndex { index: "id_index", table: "range", col: "id", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  let ops = [CmpOp::Lt, CmpOp::Le, CmpOp::Gt, CmpOp::Ge, CmpOp::Eq];
+  for _ in 0..1000 {
+    // 1 ~ 3 bounds on the indexed col, maybe empty range, maybe with a not indexed condition (`<>`)
+    let mut where_ = (0..rng.gen_range(1, 4)).map(|_| (*ops.choose(&mut rng).unwrap(), rng.gen_range(-10, N as i32 + 10))).collect::<Vec<_>>();
+    if rng.gen() { where_.push((CmpOp::Ne, rng.gen_range(0, N as i32))); }
+    let matched = vals.iter().copied().filter(|&x| where_.iter().all(|&(op, y)| match op {
+      CmpOp::Lt => x < y, CmpOp::Le => x <= y, CmpOp::Gt => x > y, CmpOp::Ge => x >= y, CmpOp::Eq => x == y, CmpOp::Ne => x != y,
+    })).collect::<Vec<_>>();
+    let expect = matched.len();
+    let index_count = e.select(&Select {
+      ops: None,
+      tables: vec![TableRef { db: None, table: "range", sample: None }],
+      where_: where_.iter().map(|&(op, y)| Cond::Cmp(op, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(y)))).collect(),
+      order: None,
+      limit: None,
+      offset: 0,
+      derived: vec![],
+    }).unwrap().row_count();
+    assert_eq!(index_count, expect);
+    // these may be answered only with the index
+    let aggs = [AggOp::CountAll, AggOp::Count, AggOp::Min, AggOp::Max, AggOp::Sum];
+    let res = e.select(&Select {
+      ops: Some(aggs.iter().map(|&op| Agg { col: ColRef { db: None, table: None, col: if op == AggOp::CountAll { "*" } else { "id" } }, op: Some(op), arg: None }).collect()),
+      tables: vec![TableRef { db: None, table: "range", sample: None }],
+      where_: where_.iter().map(|&(op, y)| Cond::Cmp(op, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(y)))).collect(),
+      order: None,
+      limit: None,
+      offset: 0,
+      derived: vec![],
+    }).unwrap();
+    let num = |x: Option<i32>| format!("{:?}", x.map(|x| Lit::Number(x as f64)).unwrap_or(Lit::Null));
+    assert_eq!(format!("{:?}", res.data), format!("[{}, {}, {}, {}, {}]", expect, expect, num(matched.iter().copied().min()),
+                                                  num(matched.iter().copied().max()), num(if expect == 0 { None } else { Some(matched.iter().sum()) })));
+    // ordered by the indexed col, the index is scanned forward or backward
+    let (desc, limit) = (rng.gen(), rng.gen_range(0, 20));
+    let res = e.select(&Select {
+      ops: None,
+      tables: vec![TableRef { db: None, table: "range", sample: None }],
+      where_: where_.iter().map(|&(op, y)| Cond::Cmp(op, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(y)))).collect(),
+      order: Some((ColRef { db: None, table: None, col: "id" }, desc)),
+      limit: Some(limit),
+      offset: 0,
+      derived: vec![],
+    }).unwrap();
+    let mut sorted = matched.clone();
+    (sorted.sort(), if desc { sorted.reverse(); }, sorted.truncate(limit as usize));
+    assert_eq!(format!("{:?}", res.data), format!("{:?}", sorted.iter().map(|&x| Lit::Number(x as f64)).collect::<Vec<_>>()));
+  }
+  e.exec(&Stmt::DropDb("range")).unwrap();
+}
+
+#[test]
+fn prefix() {
+  const N: usize = 3000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  // a small alphabet, so that many strings share the same prefix
+  let mut gen = || (0..rng.gen_range(1, 9)).map(|_| *b"abc".choose(&mut rng).unwrap() as char).collect::<String>();
+  let vals = (0..N).map(|_| gen()).collect::<Vec<_>>();
+  let tests = (0..300).map(|_| gen()).collect::<Vec<_>>();
+  let s = |x: &str| CLit::new(Lit::Str(x));
+  e.exec(&Stmt::CreateDb("prefix")).unwrap();
+  e.exec(&Stmt::UseDb("prefix")).unwrap();
+  e.exec(&CreateTable { table: "prefix", cols: vec![ColDecl { col: "name", ty: ColTy::FixTy(FixTy { size: 8, ty: Char }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "prefix", vals: vals[..N / 2].iter().map(|x| vec![s(x)]).collect(), cols: None })).unwrap();
+  // the first half is put into index by bulk build, the second half by insertion
+  e.exec(&CreateIndex { index: "name_index", table: "prefix", col: "name", desc: false, prefix: Some(2), func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "prefix", vals: vals[N / 2..].iter().map(|x| vec![s(x)]).collect(), cols: None })).unwrap();
+  unsafe {
+    let db = e.db().unwrap();
+    let (tp_id, tp) = db.get_tp("prefix").unwrap();
+    let ci_id = tp.get_ci("name", false).unwrap().idx(&tp.cols);
+    Index::<{Char}>::new(db, tp_id, ci_id).debug_check_all();
+  }
+  let ops = [CmpOp::Lt, CmpOp::Le, CmpOp::Gt, CmpOp::Ge, CmpOp::Eq];
+  for t in &tests {
+    let op = *ops.choose(&mut rng).unwrap();
+    let expect = vals.iter().filter(|x| match op {
+      CmpOp::Lt => x < &t, CmpOp::Le => x <= &t, CmpOp::Gt => x > &t, CmpOp::Ge => x >= &t, CmpOp::Eq => x == &t, CmpOp::Ne => x != &t,
+    }).count();
+    for &desc in &[false, true] {
+      let res = e.select(&Select {
+        ops: None,
+        tables: vec![TableRef { db: None, table: "prefix", sample: None }],
+        where_: vec![Cond::Cmp(op, ColRef { db: None, table: None, col: "name" }, Atom::Lit(s(t)))],
+        order: Some((ColRef { db: None, table: None, col: "name" }, desc)),
+        limit: None,
+        offset: 0,
+        derived: vec![],
+      }).unwrap();
+      assert_eq!(res.row_count(), expect);
+    }
+  }
+  e.exec(&Stmt::DropDb("prefix")).unwrap();
+}
+
+#[test]
+fn expr() {
+  const N: usize = 3000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let mut gen = || (0..rng.gen_range(1, 5)).map(|_| *b"aAbB".choose(&mut rng).unwrap() as char).collect::<String>();
+  let vals = (0..N).map(|_| gen()).collect::<Vec<_>>();
+  let tests = (0..300).map(|_| gen()).collect::<Vec<_>>();
+  let s = |x: &str| CLit::new(Lit::Str(x));
+  e.exec(&Stmt::CreateDb("expr")).unwrap();
+  e.exec(&Stmt::UseDb("expr")).unwrap();
+  e.exec(&CreateTable { table: "expr", cols: vec![ColDecl { col: "name", ty: ColTy::FixTy(FixTy { size: 4, ty: Char }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "expr", vals: vals[..N / 2].iter().map(|x| vec![s(x)]).collect(), cols: None })).unwrap();
+  e.exec(&CreateIndex { index: "name_index", table: "expr", col: "name", desc: false, prefix: None, func: Some(StrFn::Lower), where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "expr", vals: vals[N / 2..].iter().map(|x| vec![s(x)]).collect(), cols: None })).unwrap();
+  unsafe {
+    let db = e.db().unwrap();
+    let (tp_id, tp) = db.get_tp("expr").unwrap();
+    let ci_id = tp.get_ci("name", false).unwrap().idx(&tp.cols);
+    Index::<{Char}>::new(db, tp_id, ci_id).debug_check_all();
+  }
+  let ops = [CmpOp::Lt, CmpOp::Le, CmpOp::Gt, CmpOp::Ge, CmpOp::Eq];
+  for t in &tests {
+    let op = *ops.choose(&mut rng).unwrap();
+    let count = |func: StrFn| vals.iter().filter(|x| {
+      let x = func.apply(x);
+      match op { CmpOp::Lt => &x < t, CmpOp::Le => &x <= t, CmpOp::Gt => &x > t, CmpOp::Ge => &x >= t, CmpOp::Eq => &x == t, CmpOp::Ne => &x != t }
+    }).count();
+    for &func in &[StrFn::Lower, StrFn::Upper] { // only the former is served by index
+      let res = e.select(&Select {
+        ops: None,
+        tables: vec![TableRef { db: None, table: "expr", sample: None }],
+        where_: vec![Cond::Fn(func, op, ColRef { db: None, table: None, col: "name" }, s(t))],
+        order: None,
+        limit: None,
+        offset: 0,
+        derived: vec![],
+      }).unwrap();
+      assert_eq!(res.row_count(), count(func));
+    }
+  }
+  e.exec(&Stmt::DropDb("expr")).unwrap();
+}
+
+#[test]
+fn partial() {
+  const N: usize = 3000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  // (id, status), only records with status = 1 are in index
+  let mut vals = (0..N).map(|_| (rng.gen_range(0, 100), rng.gen_range(0, 3))).collect::<Vec<(i32, i32)>>();
+  let eq = |col, x| Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col }, Atom::Lit(lit(x)));
+  e.exec(&Stmt::CreateDb("partial")).unwrap();
+  e.exec(&Stmt::UseDb("partial")).unwrap();
+  e.exec(&CreateTable { table: "partial", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                      ColDecl { col: "status", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "partial", vals: vals[..N / 2].iter().map(|&(id, s)| vec![lit(id), lit(s)]).collect(), cols: None })).unwrap();
+  e.exec(&CreateIndex { index: "id_index", table: "partial", col: "id", desc: false, prefix: None, func: None, where_: Some(eq("status", 1)), fulltext: false, spatial: None }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "partial", vals: vals[N / 2..].iter().map(|&(id, s)| vec![lit(id), lit(s)]).collect(), cols: None })).unwrap();
+  for round in 0..3 {
+    if round != 0 { // move some records into / out of index, and delete some
+      let (id, s) = (rng.gen_range(0, 100), rng.gen_range(0, 3));
+      e.exec(&Stmt::Update(Update { table: "partial", sets: vec![("status", Expr::Atom(Atom::Lit(lit(s))))], where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(id)))] })).unwrap();
+      for x in &mut vals { if x.0 < id { x.1 = s; } }
+      let id = rng.gen_range(0, 100);
+      e.exec(&Stmt::Delete(Delete { table: "partial", where_: vec![eq("id", id)] })).unwrap();
+      vals.retain(|x| x.0 != id);
+    }
+    unsafe {
+      let db = e.db().unwrap();
+      let (tp_id, tp) = db.get_tp("partial").unwrap();
+      let ci_id = tp.get_ci("id", false).unwrap().idx(&tp.cols);
+      let mut index = Index::<{Int}>::new(db, tp_id, ci_id);
+      index.debug_check_all();
+      let mut it = index.iter();
+      let mut count = 0;
+      while it.next().is_some() { count += 1; }
+      assert_eq!(count, vals.iter().filter(|x| x.1 == 1).count());
+    }
+    for id in 0..100 {
+      for &s in &[0, 1] { // status = 1 can use the index, status = 0 can't
+        let res = e.select(&Select {
+          ops: None,
+          tables: vec![TableRef { db: None, table: "partial", sample: None }],
+          where_: vec![eq("id", id), eq("status", s)],
+          order: None,
+          limit: None,
+          offset: 0,
+          derived: vec![],
+        }).unwrap();
+        assert_eq!(res.row_count(), vals.iter().filter(|&&x| x == (id, s)).count());
+      }
+    }
+  }
+  e.exec(&Stmt::DropDb("partial")).unwrap();
+}
+
+#[test]
+fn fulltext() {
+  const N: usize = 2000;
+  const WORDS: [&str; 8] = ["apple", "Banana", "cherry", "DATE", "elder", "fig", "grape", "a-very-long-word-that-is-longer-than-max-token"];
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let gen = |rng: &mut rand_chacha::ChaCha8Rng| (0..rng.gen_range(1, 6)).map(|_| *WORDS.choose(rng).unwrap()).collect::<Vec<_>>().join(if rng.gen() { " " } else { ", " });
+  // (id, body)
+  let mut vals = (0..N).map(|_| (rng.gen_range(0, 100), gen(&mut rng))).collect::<Vec<(i32, String)>>();
+  let s = |x: &str| CLit::new(Lit::Str(x));
+  let words = |x: &str| index::tokenize(x).collect::<BTreeSet<_>>();
+  e.exec(&Stmt::CreateDb("fulltext")).unwrap();
+  e.exec(&Stmt::UseDb("fulltext")).unwrap();
+  e.exec(&CreateTable { table: "fulltext", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                       ColDecl { col: "body", ty: ColTy::Varchar(255), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "fulltext", vals: vals[..N / 2].iter().map(|(id, b)| vec![lit(*id), s(b)]).collect(), cols: None })).unwrap();
+  e.exec(&CreateIndex { index: "body_index", table: "fulltext", col: "body", desc: false, prefix: None, func: None, where_: None, fulltext: true, spatial: None }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "fulltext", vals: vals[N / 2..].iter().map(|(id, b)| vec![lit(*id), s(b)]).collect(), cols: None })).unwrap();
+  let queries = ["apple", "banana date", "FIG", "cherry, grape", "a-very-long-word-that-is-longer-than", "nothing"];
+  for round in 0..3 {
+    if round != 0 { // rewrite the text of some records (which may reuse the space of old text), and delete some
+      let (id, b) = (rng.gen_range(0, 100), gen(&mut rng));
+      e.exec(&Stmt::Update(Update { table: "fulltext", sets: vec![("body", Expr::Atom(Atom::Lit(s(&b))))], where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(id)))] })).unwrap();
+      for x in &mut vals { if x.0 < id { x.1 = b.clone(); } }
+      let id = rng.gen_range(0, 100);
+      e.exec(&Stmt::Delete(Delete { table: "fulltext", where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(id)))] })).unwrap();
+      vals.retain(|x| x.0 != id);
+    }
+    unsafe {
+      let db = e.db().unwrap();
+      let (tp_id, tp) = db.get_tp("fulltext").unwrap();
+      let ci_id = tp.get_ci("body", false).unwrap().idx(&tp.cols);
+      let mut index = Index::<{Char}>::new(db, tp_id, ci_id);
+      index.debug_check_all();
+      let mut it = index.iter();
+      let mut count = 0;
+      while it.next().is_some() { count += 1; }
+      assert_eq!(count, vals.iter().map(|x| words(&x.1).len()).sum::<usize>());
+    }
+    for q in &queries {
+      let q_words = words(q);
+      let res = e.select(&Select {
+        ops: None,
+        tables: vec![TableRef { db: None, table: "fulltext", sample: None }],
+        where_: vec![Cond::Match(ColRef { db: None, table: None, col: "body" }, q)],
+        order: None,
+        limit: None,
+        offset: 0,
+        derived: vec![],
+      }).unwrap();
+      assert_eq!(res.row_count(), vals.iter().filter(|x| !words(&x.1).is_disjoint(&q_words)).count());
+      // ranked by relevance, a rarer word has a higher weight
+      let weights = q_words.iter().map(|w| {
+        let df = vals.iter().filter(|x| words(&x.1).contains(w)).count() as f32;
+        (w.clone(), if df == 0.0 { 0.0 } else { (1.0 + vals.len() as f32 / df).ln() })
+      }).collect::<HashMap<_, _>>();
+      let scores = (0..res.row_count()).map(|i| match res.data[i * 2 + 1].lit() {
+        Lit::Str(b) => index::fulltext_score(b, &weights),
+        _ => unreachable!(),
+      }).collect::<Vec<_>>();
+      assert!(scores.windows(2).all(|w| w[0] >= w[1] - 1e-4));
+    }
+  }
+  e.exec(&Stmt::DropDb("fulltext")).unwrap();
+}
+
+#[test]
+fn spatial() {
+  const N: usize = 3000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let f = |x: f32| CLit::new(Lit::Number(x as f64));
+  let opt = |x: Option<f32>| x.map(f).unwrap_or(CLit::new(Lit::Null));
+  // (id, x, y), coordinates are multiples of 0.5 in [0, 100), so they are exact in f32 and many points share the same coordinate
+  let gen = |rng: &mut rand_chacha::ChaCha8Rng| if rng.gen_range(0, 20) == 0 { None } else { Some(rng.gen_range(0, 200) as f32 / 2.0) };
+  let mut vals = (0..N).map(|_| (rng.gen_range(0, 100), gen(&mut rng), gen(&mut rng))).collect::<Vec<(i32, Option<f32>, Option<f32>)>>();
+  let within = |lits: [CLit<'static>; 4]| Cond::Within(ColRef { db: None, table: None, col: "x" }, ColRef { db: None, table: None, col: "y" }, lits);
+  e.exec(&Stmt::CreateDb("spatial")).unwrap();
+  e.exec(&Stmt::UseDb("spatial")).unwrap();
+  e.exec(&CreateTable { table: "spatial", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                      ColDecl { col: "x", ty: ColTy::FixTy(FixTy { size: 0, ty: Float }), notnull: false, dft: None },
+                                                      ColDecl { col: "y", ty: ColTy::FixTy(FixTy { size: 0, ty: Float }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "spatial", vals: vals[..N / 2].iter().map(|&(id, x, y)| vec![lit(id), opt(x), opt(y)]).collect(), cols: None })).unwrap();
+  e.exec(&CreateIndex { index: "xy_index", table: "spatial", col: "x", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: Some("y") }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "spatial", vals: vals[N / 2..].iter().map(|&(id, x, y)| vec![lit(id), opt(x), opt(y)]).collect(), cols: None })).unwrap();
+  for round in 0..3 {
+    if round != 0 { // move some points (or make them null), and delete some
+      let (id, y) = (rng.gen_range(0, 100), gen(&mut rng));
+      e.exec(&Stmt::Update(Update { table: "spatial", sets: vec![("y", Expr::Atom(Atom::Lit(opt(y))))], where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(id)))] })).unwrap();
+      for v in &mut vals { if v.0 < id { v.2 = y; } }
+      let id = rng.gen_range(0, 100);
+      e.exec(&Stmt::Delete(Delete { table: "spatial", where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(id)))] })).unwrap();
+      vals.retain(|v| v.0 != id);
+    }
+    unsafe {
+      let db = e.db().unwrap();
+      let (tp_id, tp) = db.get_tp("spatial").unwrap();
+      let ci_id = tp.get_ci("x", false).unwrap().idx(&tp.cols);
+      let count = RTree::new(db, tp_id, ci_id).debug_check_all();
+      assert_eq!(count, vals.iter().filter(|v| v.1.is_some() && v.2.is_some()).count());
+    }
+    for _ in 0..100 {
+      let mut c = [0.0; 4];
+      for c in &mut c { *c = rng.gen_range(-10, 210) as f32 / 2.0; }
+      let (min_x, max_x, min_y, max_y) = (c[0].min(c[2]), c[0].max(c[2]), c[1].min(c[3]), c[1].max(c[3]));
+      let res = e.select(&Select {
+        ops: None,
+        tables: vec![TableRef { db: None, table: "spatial", sample: None }],
+        where_: vec![within([f(c[0]), f(c[1]), f(c[2]), f(c[3])])],
+        order: None,
+        limit: None,
+        offset: 0,
+        derived: vec![],
+      }).unwrap();
+      assert_eq!(res.row_count(), vals.iter().filter(|v| match (v.1, v.2) {
+        (Some(x), Some(y)) => min_x <= x && x <= max_x && min_y <= y && y <= max_y,
+        _ => false,
+      }).count());
+    }
+  }
+  e.exec(&Stmt::DropDb("spatial")).unwrap();
+}
+
+#[test]
+fn bloom() {
+  const N: usize = 5000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let s = |x: &str| CLit::new(Lit::Str(x));
+  let f = |x: f32| CLit::new(Lit::Number(x as f64));
+  // (id, name, score), `score` has both 0.0 and -0.0, which are equal
+  let mut vals = (0..N).map(|_| (rng.gen_range(0, 1000), format!("n{}", rng.gen_range(0, 1000)), [0.0, -0.0, 1.5, -2.5][rng.gen_range(0, 4)]))
+    .collect::<Vec<(i32, String, f32)>>();
+  let eq = |col, val| Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col }, Atom::Lit(val));
+  e.exec(&Stmt::CreateDb("bloom")).unwrap();
+  e.exec(&Stmt::UseDb("bloom")).unwrap();
+  e.exec(&CreateTable { table: "bloom", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                    ColDecl { col: "name", ty: ColTy::Varchar(20), notnull: false, dft: None },
+                                                    ColDecl { col: "score", ty: ColTy::FixTy(FixTy { size: 0, ty: Float }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "bloom", vals: vals.iter().map(|(id, n, sc)| vec![lit(*id), s(n), f(*sc)]).collect(), cols: None })).unwrap();
+  for round in 0..3 {
+    if round != 0 { // change some values, the old ones are left in filters, and delete some
+      let (id, n) = (rng.gen_range(0, 1000), format!("n{}", rng.gen_range(0, 1000)));
+      e.exec(&Stmt::Update(Update { table: "bloom", sets: vec![("name", Expr::Atom(Atom::Lit(s(&n))))], where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(id)))] })).unwrap();
+      for x in &mut vals { if x.0 < id { x.1 = n.clone(); } }
+      let id = rng.gen_range(0, 1000);
+      e.exec(&Stmt::Delete(Delete { table: "bloom", where_: vec![eq("id", lit(id))] })).unwrap();
+      vals.retain(|x| x.0 != id);
+    }
+    for _ in 0..50 {
+      let (id, n) = (rng.gen_range(0, 1000), format!("n{}", rng.gen_range(0, 1000)));
+      let sc = [0.0, -0.0, 1.5, 3.0][rng.gen_range(0, 4)];
+      for (where_, expect) in vec![
+        (vec![eq("id", lit(id))], vals.iter().filter(|x| x.0 == id).count()),
+        (vec![eq("name", s(&n))], vals.iter().filter(|x| x.1 == n).count()),
+        (vec![eq("id", lit(id)), eq("score", f(sc))], vals.iter().filter(|x| x.0 == id && x.2 == sc).count()),
+      ] {
+        let res = e.select(&Select { ops: None, tables: vec![TableRef { db: None, table: "bloom", sample: None }], where_, order: None, limit: None, offset: 0, derived: vec![] }).unwrap();
+        assert_eq!(res.row_count(), expect);
+      }
+    }
+  }
+  unsafe { // the filters do skip some pages for a value that doesn't exist
+    let db = e.db().unwrap();
+    let (_, tp) = db.get_tp("bloom").unwrap();
+    let h = db.bloom_hash_lit(0, tp.cols[0].ty, lit(1000)).unwrap();
+    let (mut page, mut pages, mut skipped) = (tp.first, 0, 0);
+    while page != !0 {
+      let dp = db.get_page::<DataPage>(page);
+      if !dp.bloom_test(tp.bloom_bits(), h) { skipped += 1; }
+      (page = dp.next, pages += 1);
+    }
+    assert_ne!(skipped, 0);
+    // the filter grows with the values in a page, so it still skips most pages of a narrow table (see `bloom_bits`)
+    assert!(skipped * 2 > pages);
+  }
+  e.exec(&Stmt::DropDb("bloom")).unwrap();
+}
+
+#[test]
+fn reindex() {
+  const N: usize = 3000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let f = |x: f32| CLit::new(Lit::Number(x as f64));
+  // (id, x, y)
+  let vals = (0..N).map(|_| (rng.gen_range(0, 1000), rng.gen_range(0, 100) as f32, rng.gen_range(0, 100) as f32)).collect::<Vec<(i32, f32, f32)>>();
+  let id = |op, x| Cond::Cmp(op, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(x)));
+  let within = Cond::Within(ColRef { db: None, table: None, col: "x" }, ColRef { db: None, table: None, col: "y" }, [f(10.0), f(20.0), f(50.0), f(60.0)]);
+  e.exec(&Stmt::CreateDb("reindex")).unwrap();
+  e.exec(&Stmt::UseDb("reindex")).unwrap();
+  e.exec(&CreateTable { table: "reindex", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                      ColDecl { col: "x", ty: ColTy::FixTy(FixTy { size: 0, ty: Float }), notnull: false, dft: None },
+                                                      ColDecl { col: "y", ty: ColTy::FixTy(FixTy { size: 0, ty: Float }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "reindex", vals: vals.iter().map(|&(id, x, y)| vec![lit(id), f(x), f(y)]).collect(), cols: None })).unwrap();
+  e.exec(&CreateIndex { index: "id_index", table: "reindex", col: "id", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  e.exec(&CreateIndex { index: "xy_index", table: "reindex", col: "x", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: Some("y") }.into()).unwrap();
+  let check = |e: &mut Eval| {
+    for (where_, expect) in vec![
+      (vec![id(CmpOp::Lt, 500)], vals.iter().filter(|v| v.0 < 500).count()),
+      (vec![within], vals.iter().filter(|v| 10.0 <= v.1 && v.1 <= 50.0 && 20.0 <= v.2 && v.2 <= 60.0).count()),
+    ] {
+      let res = e.select(&Select { ops: None, tables: vec![TableRef { db: None, table: "reindex", sample: None }], where_, order: None, limit: None, offset: 0, derived: vec![] }).unwrap();
+      assert_eq!(res.row_count(), expect);
+    }
+  };
+  // make both indexes lose all their entries, as if they were corrupted
+  let corrupt = |e: &mut Eval| unsafe {
+    let db = e.db().unwrap();
+    let (_, tp) = db.get_tp("reindex").unwrap();
+    db.get_page::<IndexPage>(tp.get_ci("id", false).unwrap().index).count = 0;
+    db.get_page::<RTreePage>(tp.get_ci("x", false).unwrap().index).count = 0;
+  };
+  check(&mut e);
+  corrupt(&mut e);
+  e.exec(&Stmt::Reindex { table: "reindex", col: Some("id") }).unwrap();
+  e.exec(&Stmt::Reindex { table: "reindex", col: Some("x") }).unwrap();
+  check(&mut e);
+  corrupt(&mut e);
+  e.exec(&Stmt::Reindex { table: "reindex", col: None }).unwrap();
+  check(&mut e);
+  unsafe {
+    let db = e.db().unwrap();
+    let tp_id = db.get_tp("reindex").unwrap().0;
+    Index::<{ Int }>::new(db, tp_id, 0).debug_check_all();
+    assert_eq!(RTree::new(db, tp_id, 1).debug_check_all(), N);
+  }
+  assert!(e.exec(&Stmt::Reindex { table: "reindex", col: Some("y") }).is_err());
+  e.exec(&Stmt::DropDb("reindex")).unwrap();
+}
+
+#[test]
+fn check_index_tree() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("check_index_tree")).unwrap();
+  e.exec(&Stmt::UseDb("check_index_tree")).unwrap();
+  let vals = (0..3000).map(|i| format!("({}, {})", i, i % 7)).collect::<Vec<_>>().join(", ");
+  e.exec_cached(&format!("create table t (id int, v int, primary key(id)); insert into t values {};", vals), |_| {}).unwrap();
+  unsafe {
+    let db = e.db().unwrap();
+    assert!(index::check_index_tree(db, "t", "v").is_err());
+    let out = index::check_index_tree(db, "t", "id").unwrap();
+    let root = db.get_tp("t").unwrap().1.get_ci("id", false).unwrap().index;
+    assert!(out.starts_with(&format!("page {}: inner, ", root)) && out.contains("\n  page ") && out.contains(", 2999]\n"), "{}", out);
+    assert!(out.contains("keys = 3000, fill = ") && out.ends_with("\n0 problem(s)"), "{}", out);
+    // the first key of the first leaf is now after the second one, and different from the key pointing to it
+    let ip = db.get_page::<IndexPage>(root);
+    let leaf = db.get_page::<IndexPage>(*(ip.data.as_ptr().add(ip.key_size() as usize) as *const u32));
+    *(leaf.data.as_mut_ptr() as *mut i32) = 5000;
+    let out = index::check_index_tree(db, "t", "id").unwrap();
+    assert!(out.contains(": key 0 is not before key 1\n") && out.contains(": the first key is not the key pointing to it\n"), "{}", out);
+    assert!(out.ends_with("\n2 problem(s)"), "{}", out);
+  }
+  e.exec(&Stmt::DropDb("check_index_tree")).unwrap();
+}
+
+#[test]
+fn min_max_index() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("min_max_index")).unwrap();
+  e.exec(&Stmt::UseDb("min_max_index")).unwrap();
+  e.exec_cached(&format!("create table t (a int, b float); create index t_a on t(a); insert into t values (null, 0), {};",
+    (0..1000).map(|i| format!("({}, {})", (i * 7) % 1000, i)).collect::<Vec<_>>().join(", ")), |_| {}).unwrap();
+  let read = |e: &mut Eval| out(e, "select rows_read from sys.table_counters where table_name = 't';").lines().nth(1).unwrap().parse::<u64>().unwrap();
+  let read0 = read(&mut e);
+  // null is not in the index, and is ignored by min / max anyway
+  assert_eq!(out(&mut e, "select min(a), max(a), min(a) from t;"), "min(a),max(a),min(a)\n0,999,0");
+  assert_eq!(read(&mut e) - read0, 2);
+  assert_eq!(out(&mut e, "select max(a) from t where a < 500 and a >= 10;"), "max(a)\n499");
+  assert_eq!(read(&mut e) - read0, 3);
+  assert_eq!(out(&mut e, "select min(a) from t where a > 2000;"), "min(a)\n");
+  // another op needs all keys in the range
+  assert_eq!(out(&mut e, "select min(a), count(a) from t where a < 10;"), "min(a),count(a)\n0,10");
+  assert_eq!(read(&mut e) - read0, 13);
+  // the col isn't indexed, so it is still a full scan
+  assert_eq!(out(&mut e, "select max(b) from t;"), "max(b)\n999");
+  e.exec(&Stmt::DropDb("min_max_index")).unwrap();
+}
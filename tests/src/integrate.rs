@@ -30,19 +30,48 @@ fn select() {
   ok!(e, "select * from ORDERS where O_CUSTKEY = 567;");
   ok!(e, "select * from ORDERS where O_CUSTKEY = 0;");
   ok!(e, "select * from ORDERS where O_CUSTKEY = 751;");
+  ok!(e, "select * from ORDERS where O_CUSTKEY > 5 and O_CUSTKEY <= 10 and O_ORDERSTATUS = 'F';");
+  ok!(e, "select * from ORDERS where O_CUSTKEY between 100 and 110;");
+  ok!(e, "select * from ORDERS where O_CUSTKEY > 10 and O_CUSTKEY < 5; -- empty range");
+  ok!(e, "select count(*), min(O_CUSTKEY), max(O_CUSTKEY) from ORDERS where O_CUSTKEY >= 100; -- these select only uses index");
+  ok!(e, "select O_CUSTKEY from ORDERS where O_CUSTKEY between 100 and 110;");
+  ok!(e, "explain select count(*) from ORDERS where O_CUSTKEY >= 100;");
+  ok!(e, "select approx_count_distinct(O_CUSTKEY), count(O_CUSTKEY) from ORDERS;");
+  ok!(e, "explain select * from ORDERS where O_CUSTKEY between 100 and 110 and O_ORDERSTATUS = 'F';");
+  ok!(e, "explain select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY and C_NAME = 'Customer#000000001';");
+  err!(e, "explain select * from ORDERS where NO_SUCH_COL = 1;");
+  ok!(e, "explain (format json) select * from ORDERS where O_CUSTKEY between 100 and 110 and O_ORDERSTATUS = 'F';");
+  err!(e, "explain (format xml) select * from ORDERS;");
+  ok!(e, "explain (format tree) select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY and O_ORDERSTATUS = 'F';");
+  ok!(e, "explain (format dot) select * from ORDERS where O_CUSTKEY between 100 and 110;");
+
+  ok!(e, "select * from ORDERS order by O_TOTALPRICE desc limit 10;");
+  ok!(e, "select * from ORDERS where O_CUSTKEY > 100 order by O_CUSTKEY desc limit 10; -- scan index backward");
+  ok!(e, "select O_CUSTKEY from ORDERS order by O_CUSTKEY limit 5;");
+  ok!(e, "select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY order by C_NAME limit 3;");
+  ok!(e, "explain select * from ORDERS where O_CUSTKEY > 100 order by O_CUSTKEY desc limit 10;");
+  ok!(e, "explain select * from ORDERS order by O_TOTALPRICE;");
+  err!(e, "select * from ORDERS order by NO_SUCH_COL;");
 
   err!(e, "select O_ORDERKEY, avg(O_TOTALPRICE) from ORDERS; -- error, mixed select");
   ok!(e, "select avg(O_TOTALPRICE), min(O_TOTALPRICE), max(O_TOTALPRICE) from ORDERS where O_TOTALPRICE >= 100000;");
 
   ok!(e, "select * from ORDERS, CUSTOMER, NATION where O_CUSTKEY = C_CUSTKEY and C_NATIONKEY = N_NATIONKEY and N_NAME <> 'INDIA';");
+  ok!(e, "explain select * from ORDERS, CUSTOMER, NATION where O_CUSTKEY = C_CUSTKEY and C_NATIONKEY = N_NATIONKEY and N_NAME <> 'INDIA'; -- joined from NATION");
+  ok!(e, "explain select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY order by C_CUSTKEY;");
+  ok!(e, "select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY and O_TOTALPRICE > 1000 order by O_CUSTKEY desc;");
+  ok!(e, "explain select * from ORDERS, CUSTOMER where O_CUSTKEY = C_CUSTKEY and C_CUSTKEY < 10; -- also O_CUSTKEY < 10");
+  ok!(e, "select count(*), avg(O_TOTALPRICE) from ORDERS tablesample (10 percent);");
+  ok!(e, "explain select * from ORDERS tablesample (2.5 percent) where O_CUSTKEY = 100; -- no index on sampled table");
+  err!(e, "select * from ORDERS tablesample (200 percent);");
 
   ok!(e, "create table test (name varchar(10));");
-  ok!(e, r#"insert into test values ('''\n\r\t\');"#);
-  err!(e, r#"insert into test values ('\n\n\n\n\n\n'); -- error, too long (\n is interpreted literally)"#);
-  ok!(e, r#"select * from test where name like '%\';"#);
-  ok!(e, r#"select * from test where name like '%\\'; -- the same as above"#);
+  ok!(e, r#"insert into test values ('''\n\r\t\\');"#);
+  err!(e, r#"insert into test values ('\n\n\n\n\n\n\n\n\n\n\n'); -- error, too long (each \n is one char)"#);
+  ok!(e, r#"select * from test where name like '%\\';"#);
+  ok!(e, r#"select * from test where name like '%\n\r\t%';"#);
   ok!(e, r#"insert into test values ('%%__\\''');"#);
-  ok!(e, r#"select * from test where name like '\%\%\_\_\\\\''';"#);
+  ok!(e, r#"select * from test where name like '\%\%\_\_\\''';"#);
   ok!(e, "insert into test values (null);");
   ok!(e, "select count(name) from test; -- 2");
   ok!(e, "drop table test;");
@@ -69,6 +98,19 @@ fn select() {
   ok!(e, "select * from test where c = v1 and v1 = c;");
   ok!(e, "select * from test where v1 = v2;");
   ok!(e, "drop table test;");
+
+  ok!(e, "create database attachDB; use attachDB; create table t (k int, name char(10)); insert into t values (1, 'one'), (2, 'two');");
+  ok!(e, "use orderDB; attach 'attachDB' as a;");
+  err!(e, "attach database 'attachDB' as a; -- error, dup alias");
+  err!(e, "select * from b.t; -- error, no such attached database");
+  ok!(e, "select N_NAME, t.name from NATION, a.t where N_NATIONKEY = t.k;");
+  ok!(e, "create table t (k int); insert into t values (1), (3);");
+  ok!(e, "select t.k, a.t.name from t, a.t where t.k = a.t.k;");
+  err!(e, "select * from t, a.t, a.t; -- error, dup table");
+  ok!(e, "drop table t;");
+  ok!(e, "detach a;");
+  err!(e, "select * from a.t; -- error, already detached");
+  ok!(e, "drop database attachDB;");
 }
 
 fn insert() {
@@ -153,6 +195,10 @@ fn alter() {
   ok!(e, "select sum(C_CUSTKEY), sum(C_ACCTBAL) from CUSTOMER;");
   ok!(e, "alter table CUSTOMER drop C_ADDRESS;");
   ok!(e, "alter table CUSTOMER add foo char(10) not null default 'foo';");
+  ok!(e, "comment on column CUSTOMER.foo is 'added by test';");
+  ok!(e, "comment on table CUSTOMER is 'customers';");
+  err!(e, "comment on column CUSTOMER.bar is 'bar'; -- error, no such col");
+  ok!(e, "desc CUSTOMER;");
   ok!(e, "select sum(C_CUSTKEY), sum(C_ACCTBAL) from CUSTOMER;");
 
   ok!(e, "create table test (i int, v varchar(10));");
@@ -163,9 +209,13 @@ fn alter() {
   err!(e, "alter table test add f bool not null; -- error, f will be null");
   ok!(e, "alter table test add f float default 233;");
   ok!(e, "insert into test values (0, 'world', false);");
+  ok!(e, "alter table test add d date default current_date;");
+  err!(e, "alter table test add u char(10) default uuid4(); -- error, too short for a uuid");
+  ok!(e, "alter table test add u char(36) default uuid4();");
+  ok!(e, "insert into test (i, v) values (2, 'default');");
   ok!(e, "select * from test;");
 
-  ok!(e, "alter table test drop b; alter table test drop v; alter table test drop f;");
+  ok!(e, "alter table test drop b; alter table test drop v; alter table test drop f; alter table test drop d; alter table test drop u;");
   err!(e, "alter table test drop i; -- error, col num will be 0");
 
   ok!(e, "drop table test;");
@@ -175,10 +225,75 @@ fn alter() {
   err!(e, "alter table test1 add primary key (a, a); -- error, dup col");
   err!(e, "alter table test1 add primary key (a); -- error, dup constraint");
   err!(e, "alter table test1 drop a; -- error, there is foreign link to a");
+  ok!(e, "alter table test2 drop foreign key f_a;");
+  ok!(e, "alter table test2 add foreign key (f_a) references test1(a) on delete set null;");
+  ok!(e, "insert into test1 values (1, 1); insert into test2 values ('a', 'b', 1, 1);");
+  ok!(e, "desc test2;");
+  ok!(e, "delete from test1 where a = 1; -- sets test2.f_a to null");
+  ok!(e, "alter table test2 drop foreign key f_a;");
+  ok!(e, "alter table test2 add foreign key (f_a) references test1(a) on update cascade on delete restrict;");
+  ok!(e, "insert into test1 values (2, 2); update test2 set f_a = 2;");
+  ok!(e, "update test1 set a = 3 where a = 2; -- sets test2.f_a to 3");
+  ok!(e, "desc test2;");
+  err!(e, "create table test3 (a int, foreign key(a) references test1(a) on delete); -- error");
   err!(e, "alter table test1 add primary key (b); -- error, a will not be unique");
   err!(e, "alter table test1 drop primary key (a); -- error, a will not be unique");
+  err!(e, "drop table test1; -- error, there is foreign link to test1");
+  ok!(e, "check foreign keys;");
+  ok!(e, "pragma foreign_keys;");
+  err!(e, "set foreign_keys = maybe; -- error, invalid value");
+  err!(e, "pragma no_such_setting; -- error");
+  ok!(e, "set foreign_keys = off; insert into test2 values ('a', 'b', 100, 100);");
+  ok!(e, "check foreign keys;");
+  ok!(e, "delete from test2 where f_a = 100; set foreign_keys = on;");
+  err!(e, "select * from test1 limit 1, 1; -- error, only in the mysql dialect");
+  ok!(e, "set dialect = mysql; select `a` from `test1` limit 1, 1; set dialect = default;");
+  err!(e, "select * from TEST1; -- error, names are case sensitive by default");
+  ok!(e, "set ignore_case = on; select A from TEST1 where test1.a = 1; set ignore_case = off;");
+  ok!(e, r#"create table "quoted table" ("a col" int, "order" int); insert into "quoted table" values (1, 2); select "order" from "quoted table";"#);
+  err!(e, r#"select order from "quoted table"; -- error, a keyword needs quoting"#);
+  ok!(e, r#"drop table "quoted table";"#);
+  ok!(e, "/* a block comment;\n over lines */ select a /* inline */ from test1; -- a line comment");
+  ok!(e, r#"create table quotes (s char(20)); insert into quotes values ('it''s'), ('it\'s'), ('a\nb\u00e9'); select * from quotes where s like 'it\'%'; drop table quotes;"#);
+  ok!(e, "insert into test1 values (0xff, 0b11); select * from test1 where a = 0xFF;");
+  err!(e, "insert into test1 values (0x100000000, 1); -- error, out of the range of int");
+  ok!(e, "create table dates (d date); insert into dates values ('2020-01-05'), ('2020/01/06'), ('2020-01-07 12:30:00'); select * from dates where d > '2020/01/05';");
+  err!(e, "insert into dates values ('01/05/2020'); -- error, the year comes first");
+  ok!(e, "select * from dates where d >= '2020-01-31' - interval 1 month; update dates set d = d + interval 2 weeks where d < '2020-01-06';");
+  err!(e, "select * from dates where d >= '2020-01-31' - interval 1 hour; -- error, the unit is at least a day");
+  ok!(e, "drop table dates;");
+  ok!(e, "select group_concat(a order by b desc separator '; '), string_agg(b, '|' order by a) from test1;");
+  err!(e, "select group_concat(a), b from test1; -- error, b is not aggregated");
+  ok!(e, "select stddev(a), variance(a), median(b), percentile_cont(0.9) within group (order by b desc) from test1;");
+  err!(e, "select percentile_cont(1.5) within group (order by a) from test1; -- error, the fraction is in [0, 1]");
+  ok!(e, "create table stats (median int, stddev float); insert into stats values (1, 0.5); select median, stddev, median(median) from stats; drop table stats;");
+  ok!(e, "select * from test1 where a & 0xf0 = 0xf0 and ~b <> 0; update test1 set b = b << 2 | 1 where a >> 4 = 15;");
+  err!(e, "select * from test1 where a & 1.5 = 1; -- error, the mask is not an int");
+  ok!(e, "update test1 set b = round(sqrt(abs(b - 100)), 1) + mod(a, 7) * power(2, 3) - ceil(0.5) + floor(-0.5);");
+  err!(e, "update test1 set b = abs('1'); -- error, the argument is not a number");
+  ok!(e, "create table prices (round int, floor float); insert into prices values (1, 2.5); update prices set floor = floor(floor) + round(round); select round, floor from prices; drop table prices;");
+  ok!(e, "update test1 set b = coalesce(nullif(b, 13), ifnull(null, a), 0);");
+  err!(e, "update test1 set b = coalesce(b, 'none'); -- error, the arguments have different types");
+  ok!(e, "update test1 set b = greatest(a, b, null) - least(a, b, 10);");
+  ok!(e, "create table bounds (least int, greatest int); insert into bounds values (1, 2); update bounds set least = least(least, greatest), greatest = coalesce(greatest, 0); select least, greatest from bounds; drop table bounds;");
+  ok!(e, "set random_seed = 7; update test1 set b = floor(rand() * 100) + rand(a);");
+  ok!(e, "create table draws (rand float, random float); insert into draws values (0.5, 0.25); update draws set rand = random(), random = rand; select rand, random from draws; drop table draws;");
+  ok!(e, "select * from test1 where (a, b) > (1, 2) and (a, b) in ((2, 3), (4, 5));");
+  ok!(e, "select * from test1 where a > all (select b from test1 where b < 10) and b = any (select a from test1);");
+  ok!(e, "select * from test1 where b > (select avg(b) from test1) and a <= (select max(f_b) from test2 where test2.f_a = test1.a);");
+  ok!(e, "select count(*) from (select a, b from test1 where b > 0) as t (x, y), test2 where t.x = test2.f_a;");
+  ok!(e, "select a, name from test1, (values (1, 'one'), (2, 'two')) as v (n, name) where test1.a = v.n;");
+  ok!(e, "insert into test1 values (100, 200), (101, 201) returning a; update test1 set b = 0 where a >= 100 returning *; delete from test1 where a >= 100 returning b;");
+  ok!(e, "create temporary table stage (a int, b int); insert into stage values (1, 2); select * from stage, test1 where stage.a = test1.a; drop table stage;");
+  ok!(e, "create table test1_copy (x, y) as select a, b from test1 where b > 0; select count(*) from test1_copy; drop table test1_copy;");
+  ok!(e, "create table test2_like like test2; desc test2_like; drop table test2_like;");
+  ok!(e, "create table test2_part (a int, b char(4)) partition by range (a) (10, 20); insert into test2_part values (1, 'x'), (15, 'y'), (30, null); select * from test2_part where a > 12; alter table test2_part drop partition 1; drop table test2_part;");
+  ok!(e, "select * from sys.counters;");
+  ok!(e, "select * from sys.table_counters where rows_inserted > 0;");
+  err!(e, "attach 'orderDB' as sys; -- error, sys is reserved");
+  ok!(e, "drop table test1 cascade; -- drops the foreign key in test2");
+  ok!(e, "insert into test2 values ('a', 'b', 100, 100);");
   ok!(e, "drop table test2;");
-  ok!(e, "drop table test1;");
 
   ok!(e, "create table test (a int, b int);");
   ok!(e, "insert into test values (1, 1), (1, 2);");
@@ -223,8 +338,175 @@ fn errors() {
   err!(e, "create table t1 (v varchar(10), foreign key (v) references t(v)); -- error");
   ok!(e, "create table t1 (v varchar(10));");
   err!(e, "alter table t1 add foreign key (v) references t(v); -- error");
+  err!(e, "create table t2 (v varchar(10), w varchar(10), foreign key (w) references t2(v)); -- error");
+  ok!(e, "create table t2 (id int, parent int, unique(id), foreign key (parent) references t2(id)); -- self-referencing");
+  ok!(e, "drop table t2;");
+  ok!(e, "create table t2 (id char(10), unique(id));");
+  ok!(e, "create table t3 (id char(4), foreign key (id) references t2(id) on update cascade); -- Char of different sizes");
+  ok!(e, "insert into t2 values ('abc'), ('abcdefgh'); insert into t3 values ('abc');");
+  err!(e, "insert into t3 values ('abcd'); -- error, not found in t2");
+  err!(e, "update t2 set id = 'abcdef' where id = 'abc'; -- error, too long for t3");
+  ok!(e, "drop table t3;");
+  ok!(e, "drop table t2;");
+  ok!(e, "drop table t;");
+  ok!(e, "drop table t1;");
+
+  ok!(e, "create table t (id int not null, name char(10));");
+  ok!(e, "insert into t values (1, 'a'), (3, 'c'), (2, 'b');");
+  ok!(e, "create index t_id_idx on t(id desc);");
+  ok!(e, "desc t;");
+  ok!(e, "select * from t order by id desc limit 2;");
+  ok!(e, "alter table t add index t_name_idx on(name asc);");
+  ok!(e, "drop index t_id_idx;");
+  err!(e, "create index t_id_idx on t(id(2)); -- error, prefix on int");
+  err!(e, "create index t_name_idx1 on t(name(10)); -- error, prefix not shorter than char");
+  err!(e, "create index t_name_idx1 on t(name(0)); -- error");
+  ok!(e, "drop index t_name_idx;");
+  ok!(e, "create index t_name_idx on t(name(1));");
+  ok!(e, "desc t;");
+  ok!(e, "select * from t where name = 'b';");
+  ok!(e, "select * from t where name > 'a' order by name;");
+  ok!(e, "explain select name from t where name >= 'b';");
+  ok!(e, "alter table t add primary key (name);");
+  ok!(e, "drop table t;");
+
+  ok!(e, "create table t (id int, name char(10));");
+  ok!(e, "insert into t values (1, 'Alice'), (2, 'BOB'), (3, 'bob');");
+  err!(e, "create index t_id_idx on t(lower(id)); -- error, lower on int");
+  ok!(e, "create index t_name_idx on t(lower(name));");
+  ok!(e, "desc t;");
+  ok!(e, "select * from t where lower(name) = 'bob';");
+  ok!(e, "select * from t where upper(name) >= 'B' and lower(name) < 'c';");
+  ok!(e, "explain select * from t where lower(name) = 'bob';");
+  ok!(e, "update t set id = 4 where lower(name) = 'alice';");
+  ok!(e, "delete from t where lower(name) = 'bob';");
+  err!(e, "select * from t where lower(id) = 'a'; -- error, lower on int");
+  err!(e, "select * from t where lower(name) = 1; -- error");
+  ok!(e, "alter table t add primary key (name);");
+  ok!(e, "drop table t;");
+
+  // function names are not reserved
+  ok!(e, "create table t (lower char(10), upper char(10), lcase int);");
+  ok!(e, "insert into t (lower, upper, lcase) values ('a', 'B', 1);");
+  ok!(e, "create index t_lower_idx on t(upper (lower));");
+  ok!(e, "select lower, upper from t where lower(upper) = 'b' and lower = 'a';");
+  ok!(e, "drop table t;");
+
+  ok!(e, "create table t (id int, status char(10) default 'active', note varchar(10));");
+  ok!(e, "insert into t values (1, 'active', 'a'), (2, 'closed', 'b'), (3, null, null);");
+  err!(e, "create index t_id_idx on t(id) where status = null; -- error, always false");
+  err!(e, "create index t_id_idx on t(id) where status = id; -- error");
+  err!(e, "create index t_id_idx on t(id) where note = 'a'; -- error, varchar");
+  err!(e, "create index t_id_idx on t(id) where status = 1; -- error, type mismatch");
+  ok!(e, "create index t_id_idx on t(id) where status = 'active';");
+  ok!(e, "alter table t add index t_status_idx on(status) where note is not null;");
+  ok!(e, "desc t;");
+  ok!(e, "select * from t where id = 1 and status = 'active';");
+  ok!(e, "explain select * from t where id = 1 and status = 'active';");
+  ok!(e, "explain select * from t where id = 1;");
+  ok!(e, "select * from t where status = 'closed' and note = 'b';");
+  ok!(e, "update t set status = 'active' where id = 2;");
+  ok!(e, "delete from t where id = 1 and status = 'active';");
+  ok!(e, "alter table t drop note;");
+  ok!(e, "desc t;");
+  ok!(e, "drop index t_id_idx;");
+  ok!(e, "drop table t;");
+
+  ok!(e, "create table t (id int, title char(20), body varchar(100));");
+  ok!(e, "insert into t values (1, 'Rust', 'Rust is fast; rust is safe.'), (2, 'C', 'C is fast'), (3, null, null);");
+  err!(e, "create fulltext index t_id_idx on t(id); -- error, not text");
+  err!(e, "select * from t where match(body) against ('rust'); -- error, no full-text index");
+  ok!(e, "create fulltext index t_body_idx on t(body);");
+  ok!(e, "alter table t add fulltext index t_title_idx on(title);");
+  ok!(e, "desc t;");
+  ok!(e, "select * from t where match(body) against ('RUST fast');");
+  ok!(e, "select * from t where match(title) against ('c') and id > 1;");
+  ok!(e, "explain select * from t where match(body) against ('safe');");
+  ok!(e, "update t set body = 'nothing here' where id = 1;");
+  ok!(e, "delete from t where match(body) against ('fast');");
+  ok!(e, "delete from t where title is null;");
+  ok!(e, "alter table t add match int; alter table t add against int; alter table t add fulltext int; -- only keywords before `(` or `index`");
+  ok!(e, "select match, against, fulltext from t where match(body) against ('safe');");
+  ok!(e, "alter table t drop match; alter table t drop against; alter table t drop fulltext;");
+  ok!(e, "alter table t add primary key (title);");
+  ok!(e, "desc t;");
+  ok!(e, "drop index t_body_idx;");
   ok!(e, "drop table t;");
+
+  ok!(e, "create table t (id int, x float, y float, z float);");
+  ok!(e, "insert into t values (1, 0, 0, 1), (2, 1.5, 2.5, 2), (3, -3, 4, 3), (4, null, 1, 4), (5, 10, 10, null);");
+  err!(e, "create spatial index t_id_idx on t(id, x); -- error, not float");
+  err!(e, "create spatial index t_xx_idx on t(x, x); -- error, same col");
+  err!(e, "select * from t where within(id, x, 0, 0, 1, 1); -- error, not float");
+  err!(e, "select * from t where within(x, y, 0, 0, 'a', 1); -- error, not number");
+  ok!(e, "create spatial index t_xy_idx on t(x, y);");
+  ok!(e, "alter table t add spatial index t_yz_idx on(y, z);");
+  ok!(e, "desc t;");
+  ok!(e, "select * from t where within(x, y, 2, 3, -5, -1);");
+  ok!(e, "select * from t where within(y, z, 0, 0, 100, 100) and id > 2;");
+  ok!(e, "explain select * from t where within(x, y, 2, 3, -5, -1);");
+  ok!(e, "update t set y = 100 where id = 2;");
+  ok!(e, "update t set x = 5, z = 5 where id = 4;");
+  ok!(e, "select * from t where within(x, y, 0, 0, 5, 5);");
+  ok!(e, "delete from t where within(x, y, 0, 0, 1, 1);");
+  ok!(e, "alter table t drop y; -- drops both spatial indexes");
+  ok!(e, "desc t;");
+  ok!(e, "drop table t;");
+
+  ok!(e, "create table t (id int, s varchar(10), f float);");
+  ok!(e, "insert into t values (1, 'a', 0), (2, 'b', -0.0), (3, null, 1.5);");
+  ok!(e, "explain select * from t where id = 1 and s = 'a';");
+  ok!(e, "explain select * from t where id > 1;");
+  ok!(e, "select * from t where f = 0;");
+  ok!(e, "update t set s = 'c' where id = 1;");
+  ok!(e, "select * from t where s = 'c';");
+  ok!(e, "select * from t where s = 'a';");
+  ok!(e, "alter table t add g int default 5; -- rebuilds filters");
+  ok!(e, "select * from t where g = 5 and f = 1.5;");
+  ok!(e, "drop table t;");
+
+  ok!(e, "create table t (id int, x float, y float, body varchar(50), z int, primary key (id));");
+  ok!(e, "insert into t values (1, 0, 0, 'hello world', 1), (2, 1, 1, 'hello', 2), (3, null, 2, null, 3);");
+  ok!(e, "create spatial index t_xy_idx on t(x, y);");
+  ok!(e, "create fulltext index t_body_idx on t(body);");
+  ok!(e, "create index t_y_idx on t(y) where x >= 0;");
+  err!(e, "reindex t(body1); -- error, no such col");
+  err!(e, "reindex t(z); -- error, no index");
+  err!(e, "reindex t1; -- error, no such table");
+  ok!(e, "reindex t(id);");
+  ok!(e, "reindex t(body);");
+  ok!(e, "delete from t where id = 2;");
+  ok!(e, "reindex t;");
+  ok!(e, "optimize table t;");
+  err!(e, "optimize table t1; -- error, no such table");
+  ok!(e, "desc t;");
+  ok!(e, "select * from t where match(body) against ('hello');");
+  ok!(e, "select * from t where within(x, y, 0, 0, 1, 1);");
+  ok!(e, "select * from t where y > 0 and x >= 0;");
+  ok!(e, "drop table t;");
+
+  ok!(e, "create table t (id int, name char(40), d date, f float, b bool);");
+  ok!(e, "create table t1 (id int);");
+  ok!(e, "explain select * from t where id = 1; -- no estimate before analyze");
+  ok!(e, "analyze t;");
+  ok!(e, "insert into t values (1, 'a very long name that is longer than 27', '2020-01-01', 1.5, true), (2, 'b', '2020-03-01', null, false), (3, null, null, -1, null);");
+  err!(e, "analyze t2; -- error, no such table");
+  ok!(e, "analyze;");
+  ok!(e, "desc t;");
+  ok!(e, "explain select * from t where id = 1;");
+  ok!(e, "explain select * from t where id > 1 and name is not null;");
+  ok!(e, "explain select * from t where d < '2020-02-01' and f >= 0 and b = true;");
+  ok!(e, "explain select * from t, t1 where t.id = t1.id and t.name like 'a%';");
+  ok!(e, "create index t_id_idx on t(id);");
+  ok!(e, "create index t_f_idx on t(f);");
+  ok!(e, "explain select * from t where id = 1 and f > 0; -- chosen by cost");
+  ok!(e, "select * from t where id = 1 and f > 0;");
+  ok!(e, "explain select * from t where name = 'b' and f < 100;");
+  ok!(e, "set auto_analyze = off; pragma auto_analyze; set auto_analyze = on;");
+  ok!(e, "alter table t add g int; -- discards stats");
+  ok!(e, "desc t;");
   ok!(e, "drop table t1;");
+  ok!(e, "drop table t;");
 }
 
 #[test]
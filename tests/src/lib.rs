@@ -3,4 +3,41 @@ mod integrate;
 #[cfg(test)]
 mod index;
 #[cfg(test)]
-mod lob;
\ No newline at end of file
+mod lob;
+#[cfg(test)]
+mod plan;
+#[cfg(test)]
+mod api;
+#[cfg(test)]
+mod foreign;
+#[cfg(test)]
+mod check;
+#[cfg(test)]
+mod parse;
+#[cfg(test)]
+mod func;
+#[cfg(test)]
+mod subquery;
+#[cfg(test)]
+mod table;
+
+// helpers shared by the tests above
+#[cfg(test)]
+mod util {
+  use driver::Eval;
+  use common::*;
+
+  pub fn lit<'a>(x: i32) -> CLit<'a> { CLit::new(Lit::Number(x as f64)) }
+
+  // the output of the last statement in `sql`, all statements must succeed
+  pub fn out(e: &mut Eval, sql: &str) -> String {
+    let out = std::cell::RefCell::new(String::new());
+    e.exec_cached(sql, |x| *out.borrow_mut() = x.to_owned()).unwrap();
+    out.into_inner()
+  }
+
+  // the error of the statement in `sql` that fails, in debug format
+  pub fn err(e: &mut Eval, sql: &str) -> String {
+    match e.exec_cached(sql, |_| {}) { Err(ModifyError(_, e)) => format!("{:?}", e), Ok(_) => panic!() }
+  }
+}
@@ -3,6 +3,7 @@ use rand::prelude::*;
 use syntax::ast::*;
 use common::{*, BareTy::*};
 use physics::*;
+use db::{Db, IoMode};
 
 fn lit<'a>(x: usize) -> CLit<'a> { CLit::new(Lit::Number(x as f64)) }
 
@@ -23,7 +24,7 @@ fn lob() {
     ],
     cons: vec![],
   }.into()).unwrap();
-  e.exec(&CreateIndex { index: "id_index", table: "lob", col: "id" }.into()).unwrap();
+  e.exec(&CreateIndex { index: "id_index", table: "lob", col: "id", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
   let mut result = Vec::new();
   for i in 0..N {
     if rng.gen_bool(ALLOC_RATE) {
@@ -38,7 +39,7 @@ fn lob() {
     } else {
       if !result.is_empty() {
         let idx = rng.gen_range(0, result.len());
-        e.exec(&Stmt::Delete(Delete { table: "lob", where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { table: None, col: "id" }, Atom::Lit(lit(idx)))] })).unwrap();
+        e.exec(&Stmt::Delete(Delete { table: "lob", where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(idx)))] })).unwrap();
         result[idx] = None;
       }
       result.push(None);
@@ -46,9 +47,13 @@ fn lob() {
   }
   for i in 0..N {
     let sel = e.select(&Select {
-      ops: Some(vec![Agg { col: ColRef { table: None, col: "v" }, op: None }]),
-      tables: vec!["lob"],
-      where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { table: None, col: "id" }, Atom::Lit(lit(i)))],
+      ops: Some(vec![Agg { col: ColRef { db: None, table: None, col: "v" }, op: None, arg: None }]),
+      tables: vec![TableRef { db: None, table: "lob", sample: None }],
+      where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(i)))],
+      order: None,
+      limit: None,
+      offset: 0,
+      derived: vec![],
     }).unwrap();
     if let Some(str) = result[i].as_ref() {
       assert_eq!(sel.row_count(), 1);
@@ -58,4 +63,51 @@ fn lob() {
     }
   }
   e.exec(&Stmt::DropDb("lob")).unwrap();
-}
\ No newline at end of file
+}
+
+#[test]
+fn vfs_fallback() {
+  const N: usize = 2000;
+  const LEN: usize = 10000; // N * LEN bytes of lob take about 20 blocks (see `BLOCK`)
+  let path = "vfs_fallback";
+  let strs = (0..N).map(|i| format!("{:01$}", i, LEN)).collect::<Vec<_>>();
+  // only a few blocks are kept in memory, the others are written back and read again when accessed
+  let insert = |db: &mut Db, i: usize| {
+    query::insert(&Insert { table: "t", vals: vec![vec![lit(i), CLit::new(Lit::Str(&strs[i]))]], cols: None }, db).unwrap();
+    db.trim_cache().unwrap();
+  };
+  {
+    let mut db = Db::create_with(path, IoMode::File).unwrap();
+    assert_eq!(db.io_mode(), IoMode::File);
+    db.cache_blocks = 2;
+    db.create_table(&CreateTable {
+      table: "t",
+      cols: vec![
+        ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: true, dft: None },
+        ColDecl { col: "v", ty: ColTy::Varchar(LEN as u16), notnull: true, dft: None }
+      ],
+      cons: vec![],
+    }).unwrap();
+    for i in 0..N { insert(&mut db, i); }
+    // the freed lob slots are reused, but not merged across blocks
+    query::delete(&Delete { table: "t", where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(N / 2)))] }, &mut db).unwrap();
+    db.trim_cache().unwrap();
+    for i in 0..N / 2 { insert(&mut db, i); }
+    assert_eq!(index::check_db(&mut db), "0 problem(s)");
+    db.close().unwrap(); // the changes still in memory are written back
+  }
+  let mut db = Db::open(path).unwrap();
+  assert_eq!(db.io_mode(), IoMode::Mmap);
+  let t = db.table_handle("t").unwrap();
+  let (id, v) = (t.col(&db, "id").unwrap(), t.col(&db, "v").unwrap());
+  let mut vals = t.records(&mut db).unwrap().collect::<Vec<_>>().into_iter().map(|(data, _)| unsafe {
+    match (id.value(&db, data).unwrap().unwrap().lit(), v.value(&db, data).unwrap().unwrap().lit()) {
+      (Lit::Number(id), Lit::Str(v)) => (id as usize, v.to_owned()),
+      _ => panic!(),
+    }
+  }).collect::<Vec<_>>();
+  vals.sort();
+  assert_eq!(vals, strs.into_iter().enumerate().collect::<Vec<_>>());
+  drop(db);
+  let _ = (std::fs::remove_file(path), std::fs::remove_file(std::path::Path::new(path).with_extension(LOB_SUFFIX)));
+}
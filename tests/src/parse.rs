@@ -0,0 +1,247 @@
+use driver::Eval;
+use syntax::ast::*;
+use common::*;
+use physics::*;
+use crate::util::{out, err};
+
+#[test]
+fn comment() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("comment")).unwrap();
+  e.exec(&Stmt::UseDb("comment")).unwrap();
+  e.exec_cached("create table t (a int comment 'the ''first'' col', b int, c int comment 'the third col');", |_| {}).unwrap();
+  e.exec_cached("comment on table t is 'a table';", |_| {}).unwrap();
+  e.exec_cached("comment on column t.b is 'the second col';", |_| {}).unwrap();
+  assert!(e.exec_cached("comment on column t.d is 'no such col';", |_| {}).is_err());
+  let long = format!("comment on table t is '{}';", "x".repeat(MAX_COMMENT + 1));
+  assert!(e.exec_cached(&long, |_| {}).is_err());
+  let desc = e.exec(&Stmt::ShowTable("t")).unwrap().into_owned();
+  for c in &["  - comment: \"a table\"", "    - comment: \"the 'first' col\"", "    - comment: \"the second col\"", "    - comment: \"the third col\""] {
+    assert!(desc.contains(c));
+  }
+  // the comments of later cols are moved with them, a new col has no comment
+  e.exec(&Stmt::DropCol { table: "t", col: "a" }).unwrap();
+  e.exec_cached("alter table t add d int;", |_| {}).unwrap();
+  e.exec_cached("comment on column t.c is 'changed';", |_| {}).unwrap();
+  e.exec_cached("comment on table t is '';", |_| {}).unwrap();
+  e.exec(&Stmt::UseDb("comment")).unwrap(); // they are persisted
+  let desc = e.exec(&Stmt::ShowTable("t")).unwrap().into_owned();
+  assert_eq!(desc.lines().filter(|l| l.contains("comment")).collect::<Vec<_>>(), ["    - comment: \"the second col\"", "    - comment: \"changed\""]);
+  e.exec(&Stmt::DropTable { table: "t", cascade: false }).unwrap();
+  e.exec(&Stmt::DropDb("comment")).unwrap();
+}
+
+#[test]
+fn settings() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("settings")).unwrap();
+  e.exec(&Stmt::UseDb("settings")).unwrap();
+  assert_eq!(out(&mut e, "pragma sort_memory;"), "sort_memory = 64MB");
+  assert_eq!(out(&mut e, "pragma SCAN_THREADS;"), "scan_threads = 1");
+  // the settings belong to the session, another session still has the defaults
+  e.exec_cached("set sort_memory = '1MB'; pragma scan_threads = 4;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "pragma sort_memory;"), "sort_memory = 1MB");
+  assert_eq!(show(&mut Eval::default(), "pragma scan_threads;"), "scan_threads = 1");
+  for sql in &["set no_such = 1;", "pragma no_such;", "set sort_memory = '64XB';", "set sort_memory = 0;", "set scan_threads = 0;",
+    "set scan_threads = 'many';", "set foreign_keys = 2;"] {
+    assert!(e.exec_cached(sql, |_| {}).is_err());
+  }
+  for sql in &["create table p (id int, primary key(id));",
+    "create table c (pid int, foreign key(pid) references p(id) on delete cascade);",
+    "insert into p values (1);", "insert into c values (1);"] {
+    e.exec_cached(sql, |_| {}).unwrap();
+  }
+  assert!(e.exec_cached("insert into c values (2);", |_| {}).is_err());
+  e.exec_cached("set foreign_keys = off;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "pragma foreign_keys;"), "foreign_keys = off");
+  e.exec_cached("insert into c values (2); delete from p where id = 1;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select count(*) from c;"), "count(*)\n2");
+  assert!(e.exec(&Stmt::CheckForeignKeys).unwrap().ends_with("2 orphaned record(s)"));
+  // a setting of the db that is not persisted is lost when the db is used again, but the ones of the session are kept
+  e.exec(&Stmt::UseDb("settings")).unwrap();
+  assert_eq!(out(&mut e, "pragma foreign_keys;"), "foreign_keys = on");
+  assert_eq!(out(&mut e, "pragma scan_threads;"), "scan_threads = 4");
+  e.exec_cached("set persist foreign_keys = false;", |_| {}).unwrap();
+  e.exec(&Stmt::UseDb("settings")).unwrap();
+  assert_eq!(out(&mut e, "pragma foreign_keys;"), "foreign_keys = off");
+  e.exec_cached("set persist foreign_keys = on;", |_| {}).unwrap();
+  assert!(e.exec_cached("insert into c values (3);", |_| {}).is_err());
+  e.exec(&Stmt::DropDb("settings")).unwrap();
+}
+
+#[test]
+fn error_span() {
+  use typed_arena::Arena;
+  let mut e = Eval::default();
+  let alloc = Arena::default();
+  e.exec(&Stmt::CreateDb("error_span")).unwrap();
+  e.exec(&Stmt::UseDb("error_span")).unwrap();
+  e.exec_all("create table t (a int, b char(10));", &alloc, |_| {}, |_| {}).unwrap();
+  // a parser error has the offset of the token
+  let code = "select *\nfrom t where;";
+  let err = syntax::work(code, &alloc).unwrap_err();
+  assert_eq!(err.span(code), Some(Span { offset: 21, len: 1, line: 2, col: 13 }));
+  assert_eq!(err.render(code), "error at line 2, col 13: SyntaxError\n  from t where;\n              ^");
+  let code = "select a from t limit 99999999999;";
+  let err = syntax::work(code, &alloc).unwrap_err();
+  assert_eq!(err.span(code), Some(Span { offset: 22, len: 11, line: 1, col: 23 }));
+  // a binding error is located by the name in it
+  let code = "insert into t values (1, 'x');\nselect a from t\n\twhere c = 1;";
+  let err = e.exec_all(code, &alloc, |_| {}, |_| {}).unwrap_err().1;
+  assert_eq!(err.span(code), Some(Span { offset: 54, len: 1, line: 3, col: 8 }));
+  assert_eq!(err.render(code), "error at line 3, col 8: NoSuchCol(\"c\")\n  \twhere c = 1;\n  \t      ^");
+  // the name doesn't come from the sql text
+  assert_eq!(Error::NoSuchCol("c").span(code), None);
+  assert_eq!(Error::NoSuchCol("c").render(code), "error: NoSuchCol(\"c\")");
+  e.exec(&Stmt::DropDb("error_span")).unwrap();
+}
+
+#[test]
+fn parser_recovery() {
+  use typed_arena::Arena;
+  let alloc = Arena::default();
+  let code = "select * from t where;\ncreate table u (a int);\ninsert into u values (1,;\nselect a from u limit 99999999999;\n\
+              select # from u; select a from u where a = ;\nselect a from;";
+  let errs = match syntax::work(code, &alloc) { Err(Error::ParserErrors(errs)) => errs, _ => panic!() };
+  let errs = errs.iter().map(|e| (e.line, e.col, format!("{:?}", e.kind))).collect::<Vec<_>>();
+  assert_eq!(errs, vec![(1, 22, "SyntaxError".to_owned()), (3, 25, "SyntaxError".to_owned()), (4, 23, "InvalidInt(\"99999999999\")".to_owned()),
+                        (5, 8, "UnexpectedChar('#')".to_owned()), (5, 44, "SyntaxError".to_owned()), (6, 14, "SyntaxError".to_owned())]);
+  // the statements are only returned without any error
+  assert_eq!(syntax::work("select a from u;\nselect a from v;", &alloc).unwrap().len(), 2);
+}
+
+#[test]
+fn dialect() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("dialect")).unwrap();
+  e.exec(&Stmt::UseDb("dialect")).unwrap();
+  e.exec_cached("create table t (a int, b char(10)); insert into t values (1, 'x'), (2, 'y'), (3, 'z');", |_| {}).unwrap();
+  let mysql = ["select `a` from t;", "select * from t limit 1, 1;", "select a from t where ucase(b) = 'Z';"];
+  for sql in &mysql { assert!(e.exec_cached(sql, |_| {}).is_err()); }
+  match e.exec_cached(mysql[1], |_| {}) {
+    Err(ModifyError(_, Error::ParserErrors(es))) => assert_eq!(format!("{:?}", es[0].kind), "MySqlOnly(\",\")"),
+    _ => panic!(),
+  }
+  assert_eq!(out(&mut e, "pragma dialect;"), "dialect = default");
+  // it takes effect from the next statement in the same text
+  assert_eq!(out(&mut e, "set dialect = mysql; select `a` from `t` order by a limit 1, 1;"), "a\n2");
+  assert_eq!(out(&mut e, "pragma dialect;"), "dialect = mysql");
+  // the texts cached in the default dialect are parsed again
+  for sql in &mysql { e.exec_cached(sql, |_| {}).unwrap(); }
+  assert_eq!(out(&mut e, mysql[2]), "a\n3");
+  assert!(e.exec_cached("set dialect = oracle;", |_| {}).is_err());
+  e.exec_cached("set dialect = default;", |_| {}).unwrap();
+  assert!(e.exec_cached(mysql[0], |_| {}).is_err());
+  // an offset is skipped after sorting, the cursor computes all rows for it
+  let select = Select { ops: None, tables: vec![TableRef { db: None, table: "t", sample: None }], where_: vec![], order: None, limit: Some(5), offset: 2, derived: vec![] };
+  assert_eq!(e.select(&select).unwrap().row_count(), 1);
+  assert_eq!(e.cursor(&select).unwrap().count(), 1);
+  let select = Select { order: Some((ColRef { db: None, table: None, col: "a" }, true)), limit: Some(1), offset: 1, ..select };
+  assert_eq!(format!("{:?}", e.select(&select).unwrap().data), format!("{:?}", [Lit::Number(2.0), Lit::Str("y")]));
+  e.exec(&Stmt::DropDb("dialect")).unwrap();
+}
+
+#[test]
+fn ignore_case() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("ignore_case")).unwrap();
+  e.exec(&Stmt::UseDb("ignore_case")).unwrap();
+  e.exec_cached("create table T (Id int, Name char(10)); insert into T values (1, 'x'), (2, 'y');", |_| {}).unwrap();
+  assert_eq!(err(&mut e, "select id from t;"), "NoSuchTable(\"t\")");
+  assert_eq!(err(&mut e, "select id from T;"), "NoSuchCol(\"id\")");
+  assert_eq!(out(&mut e, "pragma ignore_case;"), "ignore_case = off");
+  e.exec_cached("set ignore_case = on;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "pragma ignore_case;"), "ignore_case = on");
+  // the names are shown as they are declared
+  assert_eq!(out(&mut e, "select ID, name from t where t.id = 1;"), "Id,Name\n1,\"x\"");
+  assert_eq!(out(&mut e, "update t set NAME = 'z' where ID = 2; select name from T where id = 2;"), "Name\n\"z\"");
+  assert_eq!(err(&mut e, "create table t (a int);"), "DupTable(\"t\")");
+  assert_eq!(err(&mut e, "create table u (a int, A int);"), "DupCol(\"A\")");
+  assert_eq!(err(&mut e, "select * from t, T;"), "DupTable(\"T\")");
+  // it is shared by the temporary database, which is created after it is set
+  assert_eq!(out(&mut e, "create temporary table Stage (A int); insert into stage values (3); select a from STAGE;"), "A\n3");
+  e.exec_cached("set ignore_case = off;", |_| {}).unwrap();
+  assert_eq!(err(&mut e, "select a from Stage;"), "NoSuchCol(\"a\")");
+  assert_eq!(err(&mut e, "select id from T;"), "NoSuchCol(\"id\")");
+  e.exec(&Stmt::DropDb("ignore_case")).unwrap();
+}
+
+#[test]
+fn quoted_id() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("quoted_id")).unwrap();
+  e.exec(&Stmt::UseDb("quoted_id")).unwrap();
+  e.exec_cached(r#"create table "my table" ("first name" char(10), "select" int, "Say ""hi""" int);"#, |_| {}).unwrap();
+  e.exec_cached(r#"insert into "my table" values ('x', 1, 2), ('y', 3, 4);"#, |_| {}).unwrap();
+  assert_eq!(out(&mut e, r#"select "first name", "select" from "my table" where "my table"."select" = 1;"#), "first name,select\n\"x\",1");
+  assert_eq!(out(&mut e, r#"select "Say ""hi""" from "my table" where "first name" = 'y';"#), "Say \"hi\"\n4");
+  // a quoted identifier is never a keyword, and an unquoted keyword is never an identifier
+  assert!(e.exec_cached(r#"select select from "my table";"#, |_| {}).is_err());
+  assert!(e.exec_cached(r#"select "first name" from my table;"#, |_| {}).is_err());
+  // the case is kept
+  assert!(e.exec_cached(r#"select "First name" from "my table";"#, |_| {}).is_err());
+  assert!(e.exec_cached(r#"select "" from "my table";"#, |_| {}).is_err());
+  e.exec(&Stmt::DropDb("quoted_id")).unwrap();
+}
+
+#[test]
+fn sql_comment() {
+  use typed_arena::Arena;
+  let alloc = Arena::default();
+  let code = "/* a dump;\n ** with a header */ create table t (a int, -- the key; not null\n b char(20) /***/);\n\
+              insert /**/ into t values (1, '/* not a comment */ -- nor this');";
+  let ss = syntax::work(code, &alloc).unwrap();
+  assert_eq!(ss.len(), 2);
+  match &ss[1] {
+    Stmt::Insert(i) => assert_eq!(format!("{:?}", i.vals[0][1]), format!("{:?}", CLit::new(Lit::Str("/* not a comment */ -- nor this")))),
+    _ => panic!(),
+  }
+  // the lines in comments are counted for error positions
+  match syntax::work("/*\n\n*/ select a from;", &alloc) {
+    Err(Error::ParserErrors(es)) => assert_eq!((es[0].line, es[0].col), (3, 17)),
+    _ => panic!(),
+  }
+  // an unclosed block comment is not a comment
+  assert!(syntax::work("select a from t; /* select b from t;", &alloc).is_err());
+}
+
+#[test]
+fn str_escape() {
+  use typed_arena::Arena;
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("str_escape")).unwrap();
+  e.exec(&Stmt::UseDb("str_escape")).unwrap();
+  e.exec_cached(r#"create table t (a int, b char(20)); insert into t values (1, 'it''s'), (2, 'it\'s'), (3, 'a\nb\tc'), (4, 'é\\\x\u00zz');"#, |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select b from t where a <= 2;"), "b\n\"it's\"\n\"it's\"");
+  // a newline is kept in the quoted csv field
+  assert_eq!(out(&mut e, r#"select b from t where a = 3;"#), "b\n\"a\nb\tc\"");
+  assert_eq!(out(&mut e, r#"select b from t where a = 4;"#), "b\n\"é\\\\x\\u00zz\"");
+  assert_eq!(out(&mut e, r#"select a from t where b like 'it\'%';"#), "a\n1\n2");
+  assert_eq!(out(&mut e, r#"select a from t where b like '%\_%';"#), "a");
+  // the debug form of a string is a literal that parses back to it
+  let alloc = Arena::default();
+  for s in &["it's", "a\nb\tc", "\\x\\u00e9"] {
+    let code = format!("select a from t where b = {:?};", Lit::Str(s));
+    match &syntax::work(&code, &alloc).unwrap()[0] {
+      Stmt::Select(sel) => assert_eq!(format!("{:?}", sel.where_), format!("{:?}", vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "b" }, Atom::Lit(CLit::new(Lit::Str(s))))])),
+      _ => panic!(),
+    }
+  }
+  e.exec(&Stmt::DropDb("str_escape")).unwrap();
+}
+
+#[test]
+fn radix_lit() {
+  use typed_arena::Arena;
+  let alloc = Arena::default();
+  let lits = |code| match &syntax::work(code, &alloc).unwrap()[0] {
+    Stmt::Insert(i) => format!("{:?}", i.vals[0]),
+    _ => panic!(),
+  };
+  assert_eq!(lits("insert into t values (0xFF, 0X7fffffff, -0x80000000, 0b101, x'616263', X'', x'C3A9');"),
+             format!("{:?}", [Lit::Number(255.0), Lit::Number(2147483647.0), Lit::Number(-2147483648.0), Lit::Number(5.0), Lit::Str("abc"), Lit::Str(""), Lit::Str("é")].iter().map(|&l| CLit::new(l)).collect::<Vec<_>>()));
+  let errs = match syntax::work("insert into t values (0x80000000, 0b2, x'abc', x'ff');", &alloc) { Err(Error::ParserErrors(errs)) => errs, _ => panic!() };
+  assert_eq!(errs.iter().map(|e| format!("{:?}", e.kind)).collect::<Vec<_>>(), ["InvalidInt(\"0x80000000\")", "SyntaxError"]);
+  let errs = match syntax::work("insert into t values (x'abc', x'ff');", &alloc) { Err(Error::ParserErrors(errs)) => errs, _ => panic!() };
+  assert_eq!(errs.iter().map(|e| format!("{:?}", e.kind)).collect::<Vec<_>>(), ["InvalidBytes(\"x'abc'\")", "InvalidBytes(\"x'ff'\")"]);
+}
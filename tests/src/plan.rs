@@ -0,0 +1,529 @@
+use driver::Eval;
+use std::collections::BTreeSet;
+use rand::prelude::*;
+use syntax::ast::*;
+use common::{*, BareTy::*};
+use physics::*;
+use crate::util::{lit, out};
+
+#[test]
+fn analyze() {
+  const N: usize = 2000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let s = |x: &str| CLit::new(Lit::Str(x));
+  // (id, name), id is null with 1/10 probability
+  let vals = (0..N).map(|_| (if rng.gen_range(0, 10) == 0 { None } else { Some(rng.gen_range(-500, 500)) }, format!("name{}", rng.gen_range(0, 100))))
+    .collect::<Vec<(Option<i32>, String)>>();
+  e.exec(&Stmt::CreateDb("analyze")).unwrap();
+  e.exec(&Stmt::UseDb("analyze")).unwrap();
+  e.exec(&CreateTable { table: "analyze", cols: vec![ColDecl { col: "id", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                      ColDecl { col: "name", ty: ColTy::Varchar(30), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "analyze", vals: vals.iter().map(|(id, n)| vec![id.map(lit).unwrap_or(CLit::new(Lit::Null)), s(n)]).collect(), cols: None })).unwrap();
+  unsafe {
+    let db = e.db().unwrap();
+    let tp = db.get_tp("analyze").unwrap().1;
+    assert!(db.stats(tp).is_none());
+    db.analyze(Some("analyze")).unwrap();
+    let sp = db.stats(tp).unwrap();
+    assert_eq!(sp.count as usize, N);
+    let ids = vals.iter().filter_map(|v| v.0).collect::<BTreeSet<_>>();
+    let names = vals.iter().map(|v| v.1.as_str()).collect::<BTreeSet<_>>();
+    for (cs, nulls, distinct) in vec![(&sp.cols[0], N - vals.iter().filter(|v| v.0.is_some()).count(), ids.len()), (&sp.cols[1], 0, names.len())] {
+      assert_eq!((cs.nulls as usize, cs.distinct as usize), (nulls, distinct));
+    }
+    let (min, max) = db.stats_min_max(&tp.cols[0], &sp.cols[0]).unwrap();
+    assert_eq!((format!("{:?}", min), format!("{:?}", max)), (ids.iter().next().unwrap().to_string(), ids.iter().last().unwrap().to_string()));
+    let (min, max) = db.stats_min_max(&tp.cols[1], &sp.cols[1]).unwrap();
+    assert_eq!((format!("{:?}", min), format!("{:?}", max)), (format!("'{}'", names.iter().next().unwrap()), format!("'{}'", names.iter().last().unwrap())));
+  }
+  // the estimate is reported by explain
+  let explain = |e: &mut Eval, where_| {
+    let select = Select { ops: None, tables: vec![TableRef { db: None, table: "analyze", sample: None }], where_, order: None, limit: None, offset: 0, derived: vec![] };
+    e.exec(&Explain { select, format: ExplainFormat::Text }.into()).unwrap().into_owned()
+  };
+  let rows = |s: String| s[s.find("estimated rows = ").unwrap() + 17..s.len() - 1].parse::<f64>().unwrap();
+  let not_null = vals.iter().filter(|v| v.0.is_some()).count() as f64;
+  assert_eq!(rows(explain(&mut e, vec![])), N as f64);
+  assert_eq!(rows(explain(&mut e, vec![Cond::Null(ColRef { db: None, table: None, col: "id" }, false)])), not_null);
+  let lt = rows(explain(&mut e, vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "id" }, Atom::Lit(lit(0)))]));
+  assert!((lt - not_null / 2.0).abs() < not_null / 10.0);
+  e.exec(&Stmt::DropTable { table: "analyze", cascade: false }).unwrap();
+  e.exec(&Stmt::DropDb("analyze")).unwrap();
+}
+
+#[test]
+fn histogram() {
+  const N: usize = 2000;
+  let mut e = Eval::default();
+  // 9/10 of values are in [0, 100), and the others are in [100, 100000), so [min, max] interpolation is far from the truth
+  let vals = (0..N as i32).map(|i| if i % 10 == 0 { 100 + i * 49 } else { i % 100 }).collect::<Vec<_>>();
+  e.exec(&Stmt::CreateDb("histogram")).unwrap();
+  e.exec(&Stmt::UseDb("histogram")).unwrap();
+  e.exec(&CreateTable { table: "histogram", cols: vec![ColDecl { col: "x", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                        ColDecl { col: "s", ty: ColTy::Varchar(10), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "histogram", vals: vals.iter().map(|&x| vec![lit(x), CLit::new(Lit::Str("s"))]).collect(), cols: None })).unwrap();
+  e.exec(&Stmt::Analyze(Some("histogram"))).unwrap();
+  unsafe {
+    let db = e.db().unwrap();
+    let tp = db.get_tp("histogram").unwrap().1;
+    let sp = db.stats(tp).unwrap();
+    let bounds = db.hist(&sp.cols[0]).unwrap().bounds();
+    assert_eq!(bounds.len(), HIST_BUCKETS + 1);
+    assert_eq!((bounds[0], bounds[HIST_BUCKETS]), (*vals.iter().min().unwrap() as f64, *vals.iter().max().unwrap() as f64));
+    assert!(bounds.windows(2).all(|b| b[0] <= b[1]));
+    assert!(db.hist(&sp.cols[1]).is_none());
+  }
+  let explain = |e: &mut Eval, x| {
+    let select = Select { ops: None, tables: vec![TableRef { db: None, table: "histogram", sample: None }],
+      where_: vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "x" }, Atom::Lit(lit(x)))], order: None, limit: None, offset: 0, derived: vec![] };
+    let s = e.exec(&Explain { select, format: ExplainFormat::Text }.into()).unwrap().into_owned();
+    s[s.find("estimated rows = ").unwrap() + 17..s.len() - 1].parse::<f64>().unwrap()
+  };
+  for &x in &[50, 100, 50000] {
+    let real = vals.iter().filter(|&&v| v < x).count() as f64;
+    assert!((explain(&mut e, x) - real).abs() < N as f64 / 20.0);
+  }
+  e.exec(&Stmt::DropDb("histogram")).unwrap();
+}
+
+#[test]
+fn auto_analyze() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("auto_analyze")).unwrap();
+  e.exec(&Stmt::UseDb("auto_analyze")).unwrap();
+  e.exec_cached("create table t (a int); create table u (a int);", |_| {}).unwrap();
+  let insert = |e: &mut Eval, table, n: i32| { e.exec(&Stmt::Insert(Insert { table, vals: (0..n).map(|x| vec![lit(x)]).collect(), cols: None })).unwrap(); };
+  let stats = |e: &mut Eval, table| unsafe {
+    let db = e.db().unwrap();
+    let tp = db.get_tp(table).unwrap().1;
+    db.stats(tp).map(|sp| (sp.count, sp.changes))
+  };
+  (insert(&mut e, "t", 1000), insert(&mut e, "u", 1000));
+  e.exec(&Stmt::Analyze(Some("t"))).unwrap();
+  assert_eq!(stats(&mut e, "t"), Some((1000, 0)));
+  // 50 + 1000 * 0.1 changes are allowed
+  insert(&mut e, "t", 140);
+  e.exec_cached("update t set a = -1 where a = 0; delete from t where a = 1;", |_| {}).unwrap();
+  assert_eq!(stats(&mut e, "t"), Some((1000, 140 + 2 + 2)));
+  insert(&mut e, "t", 10);
+  assert_eq!(stats(&mut e, "t"), Some((1000 + 140 + 10 - 2, 0)));
+  // a table that is never analyzed is not analyzed automatically
+  insert(&mut e, "u", 1000);
+  assert_eq!(stats(&mut e, "u"), None);
+  e.exec_cached("set auto_analyze = off;", |_| {}).unwrap();
+  insert(&mut e, "t", 1000);
+  assert_eq!(stats(&mut e, "t"), Some((1148, 1000)));
+  e.exec(&Stmt::DropDb("auto_analyze")).unwrap();
+}
+
+#[test]
+fn cost() {
+  const N: usize = 5000;
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  // (a, b), `a` is nearly unique, `b` has only 2 values
+  let vals = (0..N).map(|_| (rng.gen_range(0, N as i32), rng.gen_range(0, 2))).collect::<Vec<(i32, i32)>>();
+  let cmp = |op, col, x| Cond::Cmp(op, ColRef { db: None, table: None, col }, Atom::Lit(lit(x)));
+  let explain = |e: &mut Eval, where_: &[Cond<'static>]| {
+    let select = Select { ops: None, tables: vec![TableRef { db: None, table: "cost", sample: None }], where_: where_.to_vec(), order: None, limit: None, offset: 0, derived: vec![] };
+    e.exec(&Explain { select, format: ExplainFormat::Text }.into()).unwrap().into_owned()
+  };
+  let count = |e: &mut Eval, where_: &[Cond<'static>]| {
+    e.select(&Select { ops: None, tables: vec![TableRef { db: None, table: "cost", sample: None }], where_: where_.to_vec(), order: None, limit: None, offset: 0, derived: vec![] }).unwrap().row_count()
+  };
+  e.exec(&Stmt::CreateDb("cost")).unwrap();
+  e.exec(&Stmt::UseDb("cost")).unwrap();
+  e.exec(&CreateTable { table: "cost", cols: vec![ColDecl { col: "a", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None },
+                                                   ColDecl { col: "b", ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None }], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "cost", vals: vals.iter().map(|&(a, b)| vec![lit(a), lit(b)]).collect(), cols: None })).unwrap();
+  for &col in &["a", "b"] {
+    e.exec(&CreateIndex { index: col, table: "cost", col, desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  }
+  let queries = [
+    vec![cmp(CmpOp::Eq, "b", 1), cmp(CmpOp::Eq, "a", 10)],
+    vec![cmp(CmpOp::Eq, "b", 1)],
+    vec![cmp(CmpOp::Ge, "a", 0)],
+    vec![cmp(CmpOp::Ge, "a", 100), cmp(CmpOp::Lt, "a", 105)],
+  ];
+  let expect = queries.iter().map(|q| count(&mut e, q)).collect::<Vec<_>>();
+  // without statistics, the index of the first compared col is used
+  assert!(explain(&mut e, &queries[0]).starts_with("cost: index scan on b"));
+  e.exec(&Stmt::Analyze(None)).unwrap();
+  for (q, &plan) in queries.iter().zip(&["index scan on a", "full scan", "full scan", "index scan on a"]) {
+    let s = explain(&mut e, q);
+    assert!(s.starts_with(&format!("cost: {}", plan)), "{}", s);
+    assert!(s.contains("(cost: full scan = "));
+  }
+  assert_eq!(queries.iter().map(|q| count(&mut e, q)).collect::<Vec<_>>(), expect);
+  assert_eq!(expect[0], vals.iter().filter(|v| v.1 == 1 && v.0 == 10).count());
+  e.exec(&Stmt::DropDb("cost")).unwrap();
+}
+
+#[test]
+fn join_order() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  // big(a, b) - mid(b, c) - small(c), joined on b and c
+  let big = (0..2000).map(|i| (i, rng.gen_range(0, 100))).collect::<Vec<(i32, i32)>>();
+  let mid = (0..200).map(|_| (rng.gen_range(0, 100), rng.gen_range(0, 50))).collect::<Vec<(i32, i32)>>();
+  let small = (0..5).map(|_| rng.gen_range(0, 50)).collect::<Vec<i32>>();
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  let col = |table, col| ColRef { db: None, table: Some(table), col };
+  let where_ = vec![
+    Cond::Cmp(CmpOp::Eq, col("big", "b"), Atom::ColRef(col("mid", "b"))),
+    Cond::Cmp(CmpOp::Eq, col("small", "c"), Atom::ColRef(col("mid", "c"))),
+  ];
+  let select = || Select {
+    ops: None, tables: ["big", "mid", "small"].iter().map(|&table| TableRef { db: None, table, sample: None }).collect(), where_: where_.clone(), order: None, limit: None,
+    offset: 0,
+    derived: vec![],
+  };
+  e.exec(&Stmt::CreateDb("join_order")).unwrap();
+  e.exec(&Stmt::UseDb("join_order")).unwrap();
+  e.exec(&CreateTable { table: "big", cols: vec![int("a"), int("b")], cons: vec![] }.into()).unwrap();
+  e.exec(&CreateTable { table: "mid", cols: vec![int("b"), int("c")], cons: vec![] }.into()).unwrap();
+  e.exec(&CreateTable { table: "small", cols: vec![int("c")], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "big", vals: big.iter().map(|&(a, b)| vec![lit(a), lit(b)]).collect(), cols: None })).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "mid", vals: mid.iter().map(|&(b, c)| vec![lit(b), lit(c)]).collect(), cols: None })).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "small", vals: small.iter().map(|&c| vec![lit(c)]).collect(), cols: None })).unwrap();
+  let expect = big.iter().map(|&(_, b)| mid.iter().filter(|m| m.0 == b).map(|m| small.iter().filter(|&&c| c == m.1).count()).sum::<usize>()).sum::<usize>();
+  for &analyze in &[false, true] {
+    if analyze { e.exec(&Stmt::Analyze(None)).unwrap(); }
+    // joined from the smallest table, and the next table is always linked to a joined one
+    let s = e.exec(&Explain { select: select(), format: ExplainFormat::Text }.into()).unwrap().into_owned();
+    assert_eq!(s.lines().map(|l| l.split(':').next().unwrap()).collect::<Vec<_>>(), ["small", "mid", "big"], "{}", s);
+    // the result is still in the order of the from list
+    let res = e.select(&select()).unwrap();
+    assert_eq!(res.row_count(), expect);
+    assert!(res.csv().starts_with("a,b,b,c,c\n"));
+    for row in res.data.chunks_exact(5) {
+      assert_eq!(format!("{:?}", row[1]), format!("{:?}", row[2]));
+      assert_eq!(format!("{:?}", row[3]), format!("{:?}", row[4]));
+    }
+  }
+  e.exec(&Stmt::DropDb("join_order")).unwrap();
+}
+
+#[test]
+fn merge_join() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  // l(a, x) and r(b, y), joined on a = b, both indexed, and some of them are null
+  let mut gen = |n| (0..n).map(|_| (if rng.gen_range(0, 10) == 0 { None } else { Some(rng.gen_range(0, 200)) }, rng.gen_range(0, 100))).collect::<Vec<(Option<i32>, i32)>>();
+  let (l, r) = (gen(1000), gen(500));
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  let select = |desc| Select {
+    ops: Some(vec![Agg { op: None, col: ColRef { db: None, table: None, col: "a" }, arg: None }, Agg { op: None, col: ColRef { db: None, table: None, col: "y" }, arg: None }]),
+    tables: vec![TableRef { db: None, table: "l", sample: None }, TableRef { db: None, table: "r", sample: None }],
+    where_: vec![Cond::Cmp(CmpOp::Eq, ColRef { db: None, table: None, col: "a" }, Atom::ColRef(ColRef { db: None, table: None, col: "b" })),
+                 Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "x" }, Atom::Lit(lit(50))),
+                 Cond::Cmp(CmpOp::Gt, ColRef { db: None, table: None, col: "y" }, Atom::ColRef(ColRef { db: None, table: None, col: "x" }))],
+    order: Some((ColRef { db: None, table: None, col: "b" }, desc)), limit: None,
+    offset: 0,
+    derived: vec![],
+  };
+  let row = |x: Option<i32>, y: i32| vec![x.map(lit).unwrap_or(CLit::new(Lit::Null)), lit(y)];
+  e.exec(&Stmt::CreateDb("merge_join")).unwrap();
+  e.exec(&Stmt::UseDb("merge_join")).unwrap();
+  e.exec(&CreateTable { table: "l", cols: vec![int("a"), int("x")], cons: vec![] }.into()).unwrap();
+  e.exec(&CreateTable { table: "r", cols: vec![int("b"), int("y")], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "l", vals: l.iter().map(|&(a, x)| row(a, x)).collect(), cols: None })).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "r", vals: r.iter().map(|&(b, y)| row(b, y)).collect(), cols: None })).unwrap();
+  let mut expect = l.iter().filter(|v| v.1 < 50).flat_map(|&(a, x)| r.iter().filter(move |&&(b, y)| a.is_some() && a == b && y > x).map(move |&(_, y)| (a.unwrap(), y))).collect::<Vec<_>>();
+  expect.sort_unstable();
+  for &desc in &[false, true] {
+    let s = e.exec(&Explain { select: select(desc), format: ExplainFormat::Text }.into()).unwrap().into_owned();
+    // without indexes, it is joined by sorting and binary searching, and the result needs sorting
+    assert!(!s.contains("merge join") && s.contains("sort by"), "{}", s);
+    let res = e.select(&select(desc)).unwrap().csv();
+    for &(index, table, col) in &[("l_a", "l", "a"), ("r_b", "r", "b")] {
+      e.exec(&CreateIndex { index, table, col, desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+    }
+    let s = e.exec(&Explain { select: select(desc), format: ExplainFormat::Text }.into()).unwrap().into_owned();
+    assert!(s.contains("merge join on l.a = r.b") && !s.contains("sort by"), "{}", s);
+    let res1 = e.select(&select(desc)).unwrap();
+    let num = |x: &CLit| match x.lit() { Lit::Number(x) => x as i32, _ => panic!() };
+    let mut got = res1.data.chunks_exact(2).map(|r| (num(&r[0]), num(&r[1]))).collect::<Vec<_>>();
+    // ordered by the join key, rows with the same key may be in any order
+    let keys = got.iter().map(|r| r.0).collect::<Vec<_>>();
+    assert!(keys.windows(2).all(|w| if desc { w[0] >= w[1] } else { w[0] <= w[1] }));
+    assert_eq!(res1.row_count(), res.lines().count() - 1);
+    got.sort_unstable();
+    assert_eq!(got, expect);
+    for &index in &["l_a", "r_b"] { e.exec(&Stmt::DropIndex { index, table: None }).unwrap(); }
+  }
+  e.exec(&Stmt::DropDb("merge_join")).unwrap();
+}
+
+#[test]
+fn implied() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  // p(id) and q(pid, w), joined on id = pid, only q.pid is indexed
+  let (p, q) = ((0..300).collect::<Vec<i32>>(), (0..2000).map(|_| (rng.gen_range(0, 300), rng.gen_range(0, 10))).collect::<Vec<(i32, i32)>>());
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  let col = |col| ColRef { db: None, table: None, col };
+  let select = |x| Select {
+    ops: None, tables: vec![TableRef { db: None, table: "p", sample: None }, TableRef { db: None, table: "q", sample: None }], order: None, limit: None,
+    where_: vec![Cond::Cmp(CmpOp::Eq, col("id"), Atom::ColRef(col("pid"))), Cond::Cmp(CmpOp::Lt, col("id"), Atom::Lit(lit(x))), Cond::Cmp(CmpOp::Eq, col("w"), Atom::Lit(lit(1)))],
+    offset: 0,
+    derived: vec![],
+  };
+  e.exec(&Stmt::CreateDb("implied")).unwrap();
+  e.exec(&Stmt::UseDb("implied")).unwrap();
+  e.exec(&CreateTable { table: "p", cols: vec![int("id")], cons: vec![] }.into()).unwrap();
+  e.exec(&CreateTable { table: "q", cols: vec![int("pid"), int("w")], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "p", vals: p.iter().map(|&id| vec![lit(id)]).collect(), cols: None })).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "q", vals: q.iter().map(|&(pid, w)| vec![lit(pid), lit(w)]).collect(), cols: None })).unwrap();
+  e.exec(&CreateIndex { index: "q_pid", table: "q", col: "pid", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  for &x in &[0, 10, 200] {
+    // `id < x` is also applied to `pid`, so q is scanned by its index
+    let s = e.exec(&Explain { select: select(x), format: ExplainFormat::Text }.into()).unwrap().into_owned();
+    assert!(s.contains(&format!("q: index scan on pid (-inf, {})", x)), "{}", s);
+    let expect = q.iter().filter(|&&(pid, w)| pid < x && w == 1).count();
+    assert_eq!(e.select(&select(x)).unwrap().row_count(), expect);
+  }
+  e.exec(&Stmt::DropDb("implied")).unwrap();
+}
+
+#[test]
+fn external_sort() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let vals = (0..3000).map(|i| (if rng.gen_range(0, 10) == 0 { None } else { Some(rng.gen_range(0, 500)) }, i)).collect::<Vec<(Option<i32>, i32)>>();
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  let select = |desc| Select { ops: None, tables: vec![TableRef { db: None, table: "s", sample: None }], where_: vec![], order: Some((ColRef { db: None, table: None, col: "a" }, desc)), limit: None, offset: 0, derived: vec![] };
+  e.exec(&Stmt::CreateDb("external_sort")).unwrap();
+  e.exec(&Stmt::UseDb("external_sort")).unwrap();
+  e.exec(&CreateTable { table: "s", cols: vec![int("a"), int("i")], cons: vec![] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "s", vals: vals.iter().map(|&(a, i)| vec![a.map(lit).unwrap_or(CLit::new(Lit::Null)), lit(i)]).collect(), cols: None })).unwrap();
+  for &desc in &[false, true] {
+    let in_memory = e.select(&select(desc)).unwrap().csv();
+    // 3KB, about 100 rows in a run, so there are about 30 runs to merge
+    e.exec_cached("set sort_memory = 3200;", |_| {}).unwrap();
+    let external = e.select(&select(desc)).unwrap().csv();
+    e.exec_cached("set sort_memory = '64MB';", |_| {}).unwrap();
+    // the sort is stable, so even the order of equal keys is the same
+    assert_eq!(in_memory, external);
+    let mut expect = vals.iter().map(|v| v.0).collect::<Vec<_>>();
+    expect.sort_by(|l, r| if desc { r.cmp(l) } else { l.cmp(r) });
+    let expect = expect.iter().map(|a| a.map(|a| a.to_string()).unwrap_or_default()).collect::<Vec<_>>();
+    assert_eq!(external.lines().skip(1).map(|l| l.split(',').next().unwrap()).collect::<Vec<_>>(), expect);
+  }
+  // all runs are removed after merging
+  let prefix = format!("db-sort-{}-", std::process::id());
+  assert!(std::fs::read_dir(std::env::temp_dir()).unwrap().all(|f| !f.unwrap().file_name().to_string_lossy().starts_with(&prefix)));
+  e.exec(&Stmt::DropDb("external_sort")).unwrap();
+}
+
+#[test]
+fn top_k() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  let select = |desc, limit| Select { ops: None, tables: vec![TableRef { db: None, table: "t", sample: None }], where_: vec![], order: Some((ColRef { db: None, table: None, col: "a" }, desc)), limit, offset: 0, derived: vec![] };
+  e.exec(&Stmt::CreateDb("top_k")).unwrap();
+  e.exec(&Stmt::UseDb("top_k")).unwrap();
+  e.exec(&CreateTable { table: "t", cols: vec![int("a"), int("i")], cons: vec![] }.into()).unwrap();
+  let vals = (0..3000).map(|i| vec![if rng.gen_range(0, 10) == 0 { CLit::new(Lit::Null) } else { lit(rng.gen_range(0, 100)) }, lit(i)]).collect();
+  e.exec(&Stmt::Insert(Insert { table: "t", vals, cols: None })).unwrap();
+  for &desc in &[false, true] {
+    let all = e.select(&select(desc, None)).unwrap().csv();
+    for &limit in &[0, 1, 10, 2999, 3000, 5000] {
+      // the first rows of a full (stable) sort, including the order of equal keys
+      let top = e.select(&select(desc, Some(limit))).unwrap().csv();
+      assert_eq!(top.lines().collect::<Vec<_>>(), all.lines().take(limit as usize + 1).collect::<Vec<_>>());
+    }
+  }
+  assert!(e.exec(&Explain { select: select(false, Some(10)), format: ExplainFormat::Text }.into()).unwrap().ends_with("sort by t.a, only keep the first 10"));
+  e.exec(&Stmt::DropDb("top_k")).unwrap();
+}
+
+#[test]
+fn parallel_scan() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  let pad = ColDecl { col: "pad", ty: ColTy::FixTy(FixTy { size: 100, ty: Char }), notnull: false, dft: None };
+  e.exec(&Stmt::CreateDb("parallel_scan")).unwrap();
+  e.exec(&Stmt::UseDb("parallel_scan")).unwrap();
+  e.exec(&CreateTable { table: "p", cols: vec![int("a"), int("i"), pad], cons: vec![] }.into()).unwrap();
+  // about 70 rows in a page, so there are more than `PAR_MIN_PAGES` pages
+  let vals = (0..10000).map(|i| vec![lit(rng.gen_range(0, 1000)), lit(i), CLit::new(Lit::Str("x"))]).collect();
+  e.exec(&Stmt::Insert(Insert { table: "p", vals, cols: None })).unwrap();
+  let agg = |op, col| Agg { op: Some(op), col: ColRef { db: None, table: None, col }, arg: None };
+  let col = |col| Agg { op: None, col: ColRef { db: None, table: None, col }, arg: None };
+  let select = |ops, where_| Select { ops: Some(ops), tables: vec![TableRef { db: None, table: "p", sample: None }], where_, order: None, limit: None, offset: 0, derived: vec![] };
+  let lt = |x| vec![Cond::Cmp(CmpOp::Lt, ColRef { db: None, table: None, col: "a" }, Atom::Lit(lit(x)))];
+  let run = |e: &mut Eval| (
+    e.select(&select(vec![col("a"), col("i")], lt(10))).unwrap().csv(),
+    e.select(&select(vec![agg(AggOp::CountAll, "a"), agg(AggOp::Sum, "a"), agg(AggOp::Avg, "i"), agg(AggOp::Max, "i")], lt(500))).unwrap().csv(),
+    e.select(&select(vec![agg(AggOp::Min, "i")], vec![])).unwrap().csv(),
+  );
+  e.exec_cached("pragma scan_threads = 4;", |_| {}).unwrap();
+  let parallel = run(&mut e);
+  e.exec_cached("pragma scan_threads = 1;", |_| {}).unwrap();
+  let serial = run(&mut e);
+  // rows are merged in the order of data pages, and the partial aggregates of ints are exact, so they are the same as a serial scan
+  assert_eq!(parallel, serial);
+  assert_eq!(parallel.2.lines().nth(1), Some("0"));
+  e.exec(&Stmt::DropDb("parallel_scan")).unwrap();
+}
+
+#[test]
+fn batch_predicate() {
+  let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(19260817);
+  let mut e = Eval::default();
+  let col = |col, ty| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty }), notnull: false, dft: None };
+  e.exec(&Stmt::CreateDb("batch_predicate")).unwrap();
+  e.exec(&Stmt::UseDb("batch_predicate")).unwrap();
+  e.exec(&CreateTable { table: "b", cols: vec![col("i", Int), col("f", Float), col("b", Bool), col("id", Int)], cons: vec![] }.into()).unwrap();
+  let vals = (0..2000).map(|id| {
+    let null = |rng: &mut rand_chacha::ChaCha8Rng| rng.gen_range(0, 8) == 0;
+    let i = if null(&mut rng) { None } else { Some(rng.gen_range(0, 100)) };
+    let f = if null(&mut rng) { None } else { Some(rng.gen_range(0, 100) as f32 / 4.0) };
+    let b = if null(&mut rng) { None } else { Some(rng.gen_range(0, 2) == 0) };
+    (id, i, f, b)
+  }).collect::<Vec<_>>();
+  let null = CLit::new(Lit::Null);
+  e.exec(&Stmt::Insert(Insert {
+    table: "b",
+    vals: vals.iter().map(|&(id, i, f, b)| vec![i.map(lit).unwrap_or(null), f.map(|f| CLit::new(Lit::Number(f as f64))).unwrap_or(null),
+                                                 b.map(|b| CLit::new(Lit::Bool(b))).unwrap_or(null), lit(id)]).collect(),
+    cols: None,
+  })).unwrap();
+  let cmp = |op, col, x| Cond::Cmp(op, ColRef { db: None, table: None, col }, Atom::Lit(x));
+  let ids = |e: &mut Eval, where_| {
+    let res = e.select(&Select { ops: Some(vec![Agg { op: None, col: ColRef { db: None, table: None, col: "id" }, arg: None }]), tables: vec![TableRef { db: None, table: "b", sample: None }], where_, order: None, limit: None, offset: 0, derived: vec![] }).unwrap();
+    let mut ids = res.rows().map(|r| r.get::<i32>(0).unwrap()).collect::<Vec<_>>();
+    (ids.sort(), ids).1
+  };
+  for &op in &[CmpOp::Lt, CmpOp::Le, CmpOp::Gt, CmpOp::Ge, CmpOp::Eq, CmpOp::Ne] {
+    let test = |l: f32, r: f32| match op { CmpOp::Lt => l < r, CmpOp::Le => l <= r, CmpOp::Gt => l > r, CmpOp::Ge => l >= r, CmpOp::Eq => l == r, CmpOp::Ne => l != r };
+    // several conditions in one where, the slots passing all of them are left, and null never passes
+    let where_ = vec![cmp(op, "i", lit(50)), cmp(op, "f", CLit::new(Lit::Number(12.5))), cmp(CmpOp::Eq, "b", CLit::new(Lit::Bool(true)))];
+    let expect = vals.iter().filter(|&&(_, i, f, b)| i.map(|i| test(i as f32, 50.0)).unwrap_or(false) &&
+      f.map(|f| test(f, 12.5)).unwrap_or(false) && b == Some(true)).map(|v| v.0).collect::<Vec<_>>();
+    assert_eq!(ids(&mut e, where_), expect);
+  }
+  // comparing with null rejects everything
+  assert!(ids(&mut e, vec![cmp(CmpOp::Eq, "i", null)]).is_empty());
+  e.exec(&Stmt::DropDb("batch_predicate")).unwrap();
+}
+
+#[test]
+fn table_sample() {
+  use typed_arena::Arena;
+  let mut e = Eval::default();
+  let int = |col| ColDecl { col, ty: ColTy::FixTy(FixTy { size: 0, ty: Int }), notnull: false, dft: None };
+  e.exec(&Stmt::CreateDb("table_sample")).unwrap();
+  e.exec(&Stmt::UseDb("table_sample")).unwrap();
+  e.exec(&CreateTable { table: "s", cols: vec![int("id")], cons: vec![ColCons::Primary(vec!["id"])] }.into()).unwrap();
+  e.exec(&Stmt::Insert(Insert { table: "s", vals: (0..20000).map(|i| vec![lit(i)]).collect(), cols: None })).unwrap();
+  let ids = |e: &Eval, sample| {
+    let s = Select { ops: None, tables: vec![TableRef { db: None, table: "s", sample }], where_: vec![], order: None, limit: None, offset: 0, derived: vec![] };
+    e.select(&s).unwrap().rows().map(|r| r.get::<i32>(0).unwrap()).collect::<BTreeSet<_>>()
+  };
+  let all = ids(&e, None);
+  assert_eq!((ids(&e, Some(100.0)), ids(&e, Some(0.0)).len()), (all.clone(), 0));
+  // whole pages are chosen, about half of all rows, and the same pages every time
+  let part = ids(&e, Some(50.0));
+  assert!(!part.is_empty() && part.len() < all.len() && part.is_subset(&all));
+  assert_eq!(ids(&e, Some(50.0)), part);
+  // the primary key index is not used on a sampled table
+  let alloc = Arena::default();
+  let s = match &syntax::work("select * from s tablesample (50 percent) where id < 100;", &alloc).unwrap()[0] { Stmt::Select(s) => e.select(s).unwrap().row_count(), _ => unreachable!() };
+  assert_eq!(s, part.range(..100).count());
+  assert!(e.select(&Select { ops: None, tables: vec![TableRef { db: None, table: "s", sample: Some(-1.0) }], where_: vec![], order: None, limit: None, offset: 0, derived: vec![] }).is_err());
+  e.exec(&Stmt::DropDb("table_sample")).unwrap();
+}
+
+#[test]
+fn approx_count_distinct() {
+  let mut e = Eval::default();
+  let col = |col, ty, size| ColDecl { col, ty: ColTy::FixTy(FixTy { size, ty }), notnull: false, dft: None };
+  e.exec(&Stmt::CreateDb("approx_count_distinct")).unwrap();
+  e.exec(&Stmt::UseDb("approx_count_distinct")).unwrap();
+  e.exec(&CreateTable { table: "d", cols: vec![col("a", Int, 0), col("s", Char, 8), col("f", Float, 0)], cons: vec![] }.into()).unwrap();
+  let names = (0..20000).map(|i| format!("n{}", i % 10)).collect::<Vec<_>>();
+  let vals = (0..20000).map(|i| vec![if i % 7 == 0 { CLit::new(Lit::Null) } else { lit(i % 5000) }, CLit::new(Lit::Str(&names[i as usize])), lit(i % 3)]).collect();
+  e.exec(&Stmt::Insert(Insert { table: "d", vals, cols: None })).unwrap();
+  let approx = |e: &Eval, col| {
+    let s = Select { ops: Some(vec![Agg { op: Some(AggOp::ApproxCountDistinct), col: ColRef { db: None, table: None, col }, arg: None }]), tables: vec![TableRef { db: None, table: "d", sample: None }], where_: vec![], order: None, limit: None, offset: 0, derived: vec![] };
+    e.select(&s).unwrap().row(0).unwrap().get::<i32>(0).unwrap()
+  };
+  // null is ignored, a few distinct values are counted almost exactly, many distinct values are within the error of HyperLogLog
+  let a = approx(&e, "a");
+  assert!((a - 5000).abs() < 150, "{}", a);
+  assert_eq!((approx(&e, "s"), approx(&e, "f")), (10, 3));
+  // the same from an index only scan, because HyperLogLog doesn't depend on the order of values
+  e.exec(&CreateIndex { index: "d_a", table: "d", col: "a", desc: false, prefix: None, func: None, where_: None, fulltext: false, spatial: None }.into()).unwrap();
+  assert_eq!(approx(&e, "a"), a);
+  e.exec(&Stmt::DropDb("approx_count_distinct")).unwrap();
+}
+
+#[test]
+fn explain_json() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("explain_json")).unwrap();
+  e.exec(&Stmt::UseDb("explain_json")).unwrap();
+  e.exec_cached("create table t (a int, b char(10)); create index t_a on t(a); insert into t values (1, 'x'), (2, 'y');", |_| {}).unwrap();
+  let json = out(&mut e, "explain (format json) select * from t where a >= 1 order by b desc limit 1;");
+  assert!(json.starts_with("{\"tables\": [{\"table\": \"t\", \"method\": \"index scan\", \"index\": \"a\", \"range\": \"[1, +inf)\""), "{}", json);
+  assert!(json.ends_with("\"merge_join\": null, \"rank\": null, \"sort\": {\"col\": \"t.b\", \"desc\": true, \"limit\": 1}}"), "{}", json);
+  // the text form is made from the same plan
+  let text = out(&mut e, "explain select * from t where a >= 1 order by b desc limit 1;");
+  assert!(text.starts_with("t: index scan on a [1, +inf)") && text.ends_with("sort by t.b desc, only keep the first 1"), "{}", text);
+  assert!(e.exec_cached("explain (format xml) select * from t;", |_| {}).is_err());
+  e.exec(&Stmt::DropDb("explain_json")).unwrap();
+}
+
+#[test]
+fn explain_tree() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("explain_tree")).unwrap();
+  e.exec(&Stmt::UseDb("explain_tree")).unwrap();
+  e.exec_cached("create table t (a int, b int); create table u (b int); insert into t values (1, 1), (2, 1), (3, 2); insert into u values (1), (3);", |_| {}).unwrap();
+  let sql = "select * from t, u where t.b = u.b and t.a >= 2 order by t.a";
+  let tree = out(&mut e, &format!("explain (format tree) {};", sql));
+  let lines = tree.lines().collect::<Vec<_>>();
+  assert_eq!(lines.len(), 5, "{}", tree);
+  assert_eq!((lines[0], lines[1], lines[2]), ("result (actual rows = 1)", "└── sort by t.a", "    └── nested loop join"));
+  // t has 2 records with a >= 2, u has no condition of its own
+  let leaf = |table: &str| lines.iter().find(|l| l.contains(&format!("{}: full scan", table))).unwrap().to_owned();
+  assert!(leaf("t").contains("(actual rows = 2)") && leaf("u").contains("(actual rows = 2)"), "{}", tree);
+  assert!(lines[3].starts_with("        ├── ") && lines[4].starts_with("        └── "));
+  let dot = out(&mut e, &format!("explain (format dot) {};", sql));
+  assert!(dot.starts_with("digraph plan {\n  node [shape=box];\n  n0 [label=\"result (actual rows = 1)\"];\n") && dot.ends_with("}"), "{}", dot);
+  // 5 nodes, each except the result feeds another one
+  assert_eq!((dot.matches("[label=").count(), dot.matches(" -> ").count()), (5, 4));
+  assert!(dot.contains("  n1 -> n0;\n"));
+  // the select itself is not affected
+  assert_eq!(out(&mut e, &format!("{};", sql)).lines().count(), 2);
+  e.exec(&Stmt::DropDb("explain_tree")).unwrap();
+}
+
+#[test]
+fn count_all() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("count_all")).unwrap();
+  e.exec(&Stmt::UseDb("count_all")).unwrap();
+  e.exec_cached("create table p (id int, primary key(id)); create table c (pid int, foreign key(pid) references p(id) on delete cascade);", |_| {}).unwrap();
+  e.exec_cached("insert into p values (1), (2), (3); insert into c values (1), (1), (2);", |_| {}).unwrap();
+  assert!(out(&mut e, "explain select count(*) from c;").starts_with("c: row count"));
+  assert!(!out(&mut e, "explain select count(*) from c where pid = 1;").starts_with("c: row count"));
+  // no record is read for the count
+  let read = |e: &mut Eval| out(e, "select rows_read from sys.table_counters where table_name = 'c';");
+  let read0 = read(&mut e);
+  assert_eq!(out(&mut e, "select count(*), count(*) from c;"), "count(*),count(*)\n3,3");
+  assert_eq!(read(&mut e), read0);
+  // the records deleted by `on delete cascade` are also counted
+  e.exec_cached("delete from p where id = 1;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select count(*) from c;"), "count(*)\n1");
+  assert_eq!(out(&mut e, "select count(*) from c limit 0;"), "count(*)");
+  e.exec_cached("insert into c values (3), (null); optimize table c;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select count(*) from c;"), "count(*)\n3");
+  assert_eq!(out(&mut e, "select count(pid) from c;"), "count(pid)\n2");
+  e.exec(&Stmt::DropDb("count_all")).unwrap();
+}
@@ -0,0 +1,135 @@
+use driver::Eval;
+use syntax::ast::*;
+use crate::util::{out, err};
+
+#[test]
+fn row_value() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("row_value")).unwrap();
+  e.exec(&Stmt::UseDb("row_value")).unwrap();
+  e.exec_cached("create table t (id int, a int, b char(5)); insert into t values (1, 1, 'a'), (2, 1, 'b'), (3, 2, 'a'), (4, 2, null), (5, null, 'c');", |_| {}).unwrap();
+  for round in 0..2 {
+    // the first unequal item decides, a null before it makes the result false
+    assert_eq!(out(&mut e, "select id from t where (a, b) > (1, 'a');"), "id\n2\n3\n4");
+    assert_eq!(out(&mut e, "select id from t where (a, b) <= (2, 'a');"), "id\n1\n2\n3");
+    assert_eq!(out(&mut e, "select id from t where (a, b) <> (1, 'a');"), "id\n2\n3\n4\n5");
+    assert_eq!(out(&mut e, "select id from t where (a, b) = (2, 'a');"), "id\n3");
+    assert_eq!(out(&mut e, "select id from t where (a, b) in ((1, 'b'), (2, null), (3, 'x'));"), "id\n2");
+    // keyset pagination: the page after (a, id) = (1, 2)
+    assert_eq!(out(&mut e, "select id from t where (a, id) > (1, 2) and a is not null;"), "id\n3\n4");
+    if round == 0 { e.exec_cached("create index t_a on t(a);", |_| {}).unwrap(); }
+  }
+  // only the first item bounds the index range
+  let text = out(&mut e, "explain select * from t where (a, b) > (1, 'a');");
+  assert!(text.starts_with("t: index scan on a [1, +inf)"), "{}", text);
+  match &syntax::work("select * from t where (a, t.b) >= (1, 'x') and (a) in ((1), (2));", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{:?}", s.where_), "[(a, t.b) >= (1, 'x'), (a) in ((1), (2))]"),
+    _ => panic!(),
+  }
+  assert_eq!(err(&mut e, "select * from t where (a, b) > (1);"), "RowArityMismatch { cols: 2, vals: 1 }");
+  assert_eq!(err(&mut e, "select * from t where (a, b) in ((1, 'a'), (2));"), "RowArityMismatch { cols: 2, vals: 1 }");
+  e.exec(&Stmt::DropDb("row_value")).unwrap();
+}
+
+#[test]
+fn quantified() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("quantified")).unwrap();
+  e.exec(&Stmt::UseDb("quantified")).unwrap();
+  e.exec_cached("create table t (id int, x int); insert into t values (1, 1), (2, 5), (3, 10), (4, null);
+    create table s (y float, tag char(5)); insert into s values (2, 'a'), (5, 'a'), (8, 'b'), (null, 'c');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id from t where x > all (select y from s where tag = 'a');"), "id\n3");
+  assert_eq!(out(&mut e, "select id from t where x < any (select y from s where tag <> 'c');"), "id\n1\n2");
+  assert_eq!(out(&mut e, "select id from t where x = some (select y from s);"), "id\n2");
+  assert_eq!(out(&mut e, "select id from t where x <> all (select y from s where y is not null);"), "id\n1\n3");
+  // a null value makes `all` never true, `all` of an empty subquery is always true
+  assert_eq!(out(&mut e, "select id from t where x <> all (select y from s);"), "id");
+  assert_eq!(out(&mut e, "select id from t where x <= all (select y from s where tag = 'x');"), "id\n1\n2\n3\n4");
+  assert_eq!(out(&mut e, "select id from t where x = any (select y from s where tag = 'x');"), "id");
+  assert_eq!(out(&mut e, "select id from t where x >= all (select max(y) from s);"), "id\n3");
+  assert_eq!(out(&mut e, "delete from t where x < any (select y from s where tag = 'b'); select id from t;"), "id\n3\n4");
+  match &syntax::work("select * from t where x > all (select y from s where tag = 'a' order by y desc limit 1);", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{:?}", s.where_), "[x > all (select y from s where tag == 'a' order by y desc limit 1)]"),
+    _ => panic!(),
+  }
+  assert_eq!(err(&mut e, "select id from t where x > any (select * from s);"), "SubqueryColNum(2)");
+  assert!(err(&mut e, "select id from t where x > any (select tag from s);").starts_with("ColMismatch"));
+  e.exec(&Stmt::DropDb("quantified")).unwrap();
+}
+
+#[test]
+fn correlated() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("correlated")).unwrap();
+  e.exec(&Stmt::UseDb("correlated")).unwrap();
+  e.exec_cached("create table item (id int, cat char(5), price int); insert into item values (1, 'a', 10), (2, 'a', 30), (3, 'b', 5), (4, 'b', 7), (5, null, 1);
+    create table sold (cat char(5), price int); insert into sold values ('a', 20), ('a', 40), ('b', 6), ('c', 100);", |_| {}).unwrap();
+  // not correlated, the first value is used
+  assert_eq!(out(&mut e, "select id from item where price < (select avg(price) from sold);"), "id\n1\n2\n3\n4\n5");
+  assert_eq!(out(&mut e, "select id from item where price >= (select price from sold where cat = 'a' order by price desc);"), "id");
+  assert_eq!(out(&mut e, "select id from item where price > (select price from sold where cat = 'x');"), "id");
+  // correlated by `sold.cat = item.cat`, a null outer value matches nothing
+  assert_eq!(out(&mut e, "select id from item where price > (select avg(price) from sold where sold.cat = item.cat);"), "id\n4");
+  assert_eq!(out(&mut e, "select id from item where price < (select max(price) from sold where item.cat = cat and price > 10);"), "id\n1\n2");
+  assert_eq!(out(&mut e, "select id from item where price <= all (select price from sold where cat = item.cat);"), "id\n1\n3\n5");
+  assert_eq!(out(&mut e, "update item set price = price * 2 where price < any (select price from sold where cat = item.cat); select price from item;"), "price\n20\n60\n10\n7\n1");
+  match &syntax::work("select * from item where price > (select avg(price) from sold where sold.cat = item.cat);", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{:?}", s.where_), "[price > (select avg(price) from sold where sold.cat == item.cat)]"),
+    _ => panic!(),
+  }
+  assert!(err(&mut e, "select id from item where price > (select price from sold where price = item.cat);").starts_with("ColMismatch"));
+  assert_eq!(err(&mut e, "select id from item where price > (select * from sold);"), "SubqueryColNum(2)");
+  // the subquery is executed when a record is checked, and its error is returned by the statement; it is not executed for no record
+  assert_eq!(err(&mut e, "select id from item where price > (select price from sold where nope = 1);"), "NoSuchCol(\"nope\")");
+  assert_eq!(err(&mut e, "delete from item where price > any (select price from sold where nope = 1);"), "NoSuchCol(\"nope\")");
+  e.exec_cached("create table nothing (price int);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select price from nothing where price > (select price from sold where nope = 1);"), "price");
+  // correlated by a date col, whose values are dates rather than strings
+  e.exec_cached("create table ev (id int, d date); insert into ev values (1, '2020-01-01'), (2, '2020-02-01');
+    create table seen (d date, n int); insert into seen values ('2020-01-01', 3), ('2020-01-01', 4), ('2020-02-01', 1);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id from ev where id < (select max(n) from seen where seen.d = ev.d);"), "id\n1");
+  e.exec(&Stmt::DropDb("correlated")).unwrap();
+}
+
+#[test]
+fn derived_table() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("derived_table")).unwrap();
+  e.exec(&Stmt::UseDb("derived_table")).unwrap();
+  e.exec_cached("create table item (id int, cat char(5), price int); insert into item values (1, 'a', 10), (2, 'a', 30), (3, 'b', 5), (4, 'b', 7), (5, null, 1);
+    create table cat (name char(5), descr varchar(20)); insert into cat values ('a', 'apple'), ('b', 'banana');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select * from (select id, price from item where price > 5) as x;"), "id,price\n1,10\n2,30\n4,7");
+  assert_eq!(out(&mut e, "select * from (select id from item where price > 100) x;"), "id");
+  // the cols are renamed by the list, so an aggregation can be used further
+  assert_eq!(out(&mut e, "select max(n) from (select count(*) from item where price > 5) t(n);"), "max(n)\n3");
+  assert_eq!(out(&mut e, "select x.id, descr from (select id, cat from item where price > 20) x, cat where x.cat = cat.name;"), "id,descr\n2,\"apple\"");
+  assert_eq!(out(&mut e, "select * from (select n from (select price from item) a (n) where n > 20) b;"), "n\n30");
+  match &syntax::work("select * from (select id from item) as x(i) where i > 1;", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{}", s), "select * from (select id from item) as x(i) where i > 1"),
+    _ => panic!(),
+  }
+  assert_eq!(err(&mut e, "select * from (select id, id from item) x;"), r#"InvalidDerived { table: "x", reason: "DupCol(\"id\")" }"#);
+  assert!(err(&mut e, "select * from (select id from item) x (a, b);").starts_with("InvalidDerived"));
+  assert!(err(&mut e, "attach 'derived_table' as derived;").starts_with("DupAttach"));
+  e.exec(&Stmt::DropDb("derived_table")).unwrap();
+}
+
+#[test]
+fn values_table() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("values_table")).unwrap();
+  e.exec(&Stmt::UseDb("values_table")).unwrap();
+  e.exec_cached("create table item (id int, cat char(5)); insert into item values (1, 'a'), (2, 'b'), (3, null);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select * from (values (1, 'a'), (2, 'bb')) as v(id, name);"), "id,name\n1,\"a\"\n2,\"bb\"");
+  // the cols are named `column1`, `column2` and so on without the list, their types are decided by the values
+  assert_eq!(out(&mut e, "select * from (values (1.5, true), (null, false)) v;"), "column1,column2\n1.5,true\n,false");
+  assert_eq!(out(&mut e, "select count(*) from item, (values ('a', 'apple'), ('b', 'banana')) v (cat, name) where item.cat = v.cat;"), "count(*)\n2");
+  assert_eq!(out(&mut e, "select item.id, name from item, (values ('a', 'apple'), ('b', 'banana')) v (cat, name) where item.cat = v.cat and name = 'banana';"), "id,name\n2,\"banana\"");
+  match &syntax::work("select * from (values (1, 'a'), (2, 'b')) as v(id, name) where id > 1;", &typed_arena::Arena::default()).unwrap()[0] {
+    Stmt::Select(s) => assert_eq!(format!("{}", s), "select * from (values (1, 'a'), (2, 'b')) as v(id, name) where id > 1"),
+    _ => panic!(),
+  }
+  assert_eq!(err(&mut e, "select * from (values (1, 'a'), (2)) v;"), "RowArityMismatch { cols: 2, vals: 1 }");
+  assert_eq!(err(&mut e, "select * from (values (1), ('x')) v;"), "IncompatibleArgs { func: \"values\", l: Number, r: Str }");
+  e.exec(&Stmt::DropDb("values_table")).unwrap();
+}
@@ -0,0 +1,287 @@
+use driver::Eval;
+use syntax::ast::*;
+use common::*;
+use physics::*;
+use crate::util::{out, err};
+
+#[test]
+fn insert_returning() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("insert_returning")).unwrap();
+  e.exec(&Stmt::UseDb("insert_returning")).unwrap();
+  e.exec_cached("create table item (id int, name char(10) default 'none', price float default 1.5, tag varchar(20));
+    create table t (id int, u char(36) default uuid4());", |_| {}).unwrap();
+  // default values are filled in the returned rows
+  assert_eq!(out(&mut e, "insert into item (id, tag) values (1, 'x'), (2, null) returning *;"), "id,name,price,tag\n1,\"none\",1.5,\"x\"\n2,\"none\",1.5,");
+  assert_eq!(out(&mut e, "insert into item values (3, 'pen', 2, 'y') returning id, price;"), "id,price\n3,2");
+  let u = out(&mut e, "insert into t (id) values (1) returning u;");
+  assert_eq!(u.len(), "u\n".len() + 38);
+  assert_eq!(u, out(&mut e, "select u from t;"));
+  // the cols are checked before any row is inserted
+  assert_eq!(err(&mut e, "insert into item values (4) returning nope;"), "NoSuchCol(\"nope\")");
+  assert_eq!(out(&mut e, "select id from item;"), "id\n1\n2\n3");
+  e.exec(&Stmt::DropDb("insert_returning")).unwrap();
+}
+
+#[test]
+fn update_delete_returning() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("update_delete_returning")).unwrap();
+  e.exec(&Stmt::UseDb("update_delete_returning")).unwrap();
+  e.exec_cached("create table job (id int, state char(10), payload varchar(20));
+    insert into job values (1, 'ready', 'a'), (2, 'ready', 'b'), (3, 'done', 'c');", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "update job set state = 'running' where id = 1 returning id, state;"), "id,state\n1,\"running\"");
+  assert_eq!(out(&mut e, "update job set payload = 'x' where state = 'nope' returning *;"), "id,state,payload");
+  assert_eq!(err(&mut e, "delete from job where id = 2 returning nope;"), "NoSuchCol(\"nope\")");
+  // the removed values are returned, including varchar ones, whose space is freed
+  assert_eq!(out(&mut e, "delete from job where state = 'ready' returning id, payload;"), "id,payload\n2,\"b\"");
+  assert_eq!(out(&mut e, "delete from job returning *;"), "id,state,payload\n1,\"running\",\"a\"\n3,\"done\",\"c\"");
+  assert_eq!(out(&mut e, "select * from job;"), "id,state,payload");
+  e.exec(&Stmt::DropDb("update_delete_returning")).unwrap();
+}
+
+#[test]
+fn temp_table() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("temp_table")).unwrap();
+  e.exec(&Stmt::UseDb("temp_table")).unwrap();
+  e.exec_cached("create table item (id int, name char(10));
+    insert into item values (1, 'pen'), (2, 'ink'), (3, 'cup');
+    create temporary table stage (id int, qty int);
+    insert into stage values (1, 10), (3, 30), (4, 40);
+    update stage set qty = 0 where id = 4;", |_| {}).unwrap();
+  // it is not in the db in use, but can be used like a table in it
+  assert_eq!(out(&mut e, "show tables;").matches("stage").count(), 0);
+  assert_eq!(out(&mut e, "select name, qty from item, stage where item.id = stage.id;"), "name,qty\n\"pen\",10\n\"cup\",30");
+  assert_eq!(out(&mut e, "select qty from temp.stage where id = 4;"), "qty\n0");
+  assert_eq!(out(&mut e, "delete from stage where qty = 0 returning id;"), "id\n4");
+  // it hides a table of the same name until it is dropped
+  e.exec_cached("create temporary table item (id int);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select * from item;"), "id");
+  e.exec_cached("drop table item;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id from item;"), "id\n1\n2\n3");
+  assert_eq!(err(&mut e, "attach database 'temp_table' as temp;"), "DupAttach(\"temp\")");
+  drop(e);
+  // it is gone with the session
+  let mut e = Eval::default();
+  e.exec(&Stmt::UseDb("temp_table")).unwrap();
+  assert_eq!(err(&mut e, "select * from stage;"), "NoSuchTable(\"stage\")");
+  e.exec(&Stmt::DropDb("temp_table")).unwrap();
+}
+
+#[test]
+fn create_table_as() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("create_table_as")).unwrap();
+  e.exec(&Stmt::UseDb("create_table_as")).unwrap();
+  e.exec_cached("create table item (id int not null, name char(10), tag varchar(20), price float);
+    insert into item values (1, 'pen', 'a', 1.5), (2, 'ink', null, 2), (3, 'cup', 'b', 3);
+    create table cheap as select id, name, tag from item where price < 2.5;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select * from cheap;"), "id,name,tag\n1,\"pen\",\"a\"\n2,\"ink\",");
+  // the types are the same as the result cols, and all cols are nullable
+  let desc = out(&mut e, "desc cheap;");
+  assert!(desc.contains("Char(10)") && desc.contains("Varchar(20)") && !desc.contains("notnull"));
+  e.exec_cached("insert into cheap values (null, null, null);", |_| {}).unwrap();
+  // the cols can be renamed, and an empty result makes an empty table
+  e.exec_cached("create table stats (n, total) as select count(*), sum(price) from item;
+    create table nothing as select * from item where id > 10;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select * from stats;"), "n,total\n3,6.5");
+  assert_eq!(out(&mut e, "select * from nothing;"), "id,name,tag,price");
+  assert_eq!(err(&mut e, "create table cheap as select id from item;"), "InvalidCreateAs { table: \"cheap\", reason: \"DupTable(\\\"cheap\\\")\" }");
+  assert_eq!(err(&mut e, "create table t (a) as select id, name from item;"), "InvalidCreateAs { table: \"t\", reason: \"InsertTooLong { max: 2, actual: 1 }\" }");
+  assert_eq!(err(&mut e, "select * from t;"), "NoSuchTable(\"t\")");
+  e.exec(&Stmt::DropDb("create_table_as")).unwrap();
+}
+
+#[test]
+fn create_table_like() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("create_table_like")).unwrap();
+  e.exec(&Stmt::UseDb("create_table_like")).unwrap();
+  e.exec_cached("create table acct (id int, tag char(8) default 'x' comment 'kind', code char(4) not null, parent int, v float, d date default current_date, nm char(6),
+      primary key (id), unique (code), check (tag in ('x', 'y')), foreign key (parent) references acct (id) on delete cascade);
+    comment on table acct is 'accounts';
+    create index tag_idx on acct (tag(3)) where v > 1;
+    create index abcdefghijklm on acct (v desc);
+    create index lower_nm on acct (lower(nm));", |_| {}).unwrap();
+  let desc = out(&mut e, "desc acct;");
+  e.exec_cached("insert into acct values (1, 'y', 'a', null, 2, null); create table acct2 like acct;", |_| {}).unwrap();
+  // everything except the records is the same, and the indexes have new names
+  let expect = desc.replace("`acct", "`acct2").replace("`tag_idx`", "`tag_idx1`").replace("`abcdefghijklm`", "`abcdefghijkl1`").replace("`lower_nm`", "`lower_nm1`");
+  assert_eq!(out(&mut e, "desc acct2;"), expect);
+  assert_eq!(out(&mut e, "select * from acct2;"), "id,tag,code,parent,v,d,nm");
+  e.exec_cached("insert into acct2 (id, code, v) values (1, 'a', 2);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id, tag, code from acct2;"), "id,tag,code\n1,\"x\",\"a\"");
+  assert_eq!(err(&mut e, "insert into acct2 values (2, 'z', 'b', null, 1, null);"), "PutNotInCheck { col: \"tag\", val: 'z' }");
+  assert_eq!(err(&mut e, "insert into acct2 values (2, 'x', 'a', null, 1, null);"), "PutDupOnUnique { col: \"code\", val: 'a' }");
+  // the foreign key refers to the new table itself
+  assert_eq!(err(&mut e, "insert into acct2 values (2, 'x', 'b', 5, 1, null);"), "PutNonexistentForeign { col: \"parent\", val: 5 }");
+  e.exec_cached("insert into acct2 values (2, 'x', 'b', 1, 1, null); delete from acct2 where id = 1;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select count(*) from acct2;"), "count(*)\n0");
+  assert_eq!(out(&mut e, "select count(*) from acct;"), "count(*)\n1");
+  assert_eq!(err(&mut e, "create table acct2 like acct;"), "DupTable(\"acct2\")");
+  assert_eq!(err(&mut e, "create table t like nope;"), "NoSuchTable(\"nope\")");
+  e.exec(&Stmt::DropDb("create_table_like")).unwrap();
+}
+
+#[test]
+fn partition() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("partition")).unwrap();
+  e.exec(&Stmt::UseDb("partition")).unwrap();
+  e.exec_cached("create table ev (id int, ts date, v int) partition by range (ts) ('2020-01-01', '2021-01-01');
+    insert into ev values (1, '2019-05-01', 10), (2, '2020-01-01', 20), (3, '2020-12-31', 30), (4, '2021-03-01', 40), (5, null, 50);
+    insert into ev values (6, '2022-01-01', 60);", |_| {}).unwrap();
+  assert!(out(&mut e, "desc ev;").contains("  - partition by range (`ts`): 3 partitions, bounds = ("));
+  assert_eq!(out(&mut e, "select id from ev where ts >= '2021-01-01';"), "id\n4\n6");
+  assert_eq!(out(&mut e, "select id from ev where ts = '2020-01-01';"), "id\n2");
+  assert_eq!(out(&mut e, "select count(*) from ev where ts is null;"), "count(*)\n1");
+  // only the partitions that may satisfy the where list are scanned
+  assert!(out(&mut e, "explain select * from ev where ts >= '2021-01-01';").contains("ev: full scan on 1 of 3 partitions (2), then filter"));
+  assert!(out(&mut e, "explain select * from ev where ts < '2020-06-01' and v > 1;").contains("on 2 of 3 partitions (0, 1)"));
+  assert!(out(&mut e, "explain select * from ev where ts is null;").contains("on 1 of 3 partitions (0)"));
+  unsafe { // each data page only holds records of one partition
+    let db = e.db().unwrap();
+    let tp = db.get_tp("ev").unwrap().1;
+    for (data, rid) in db.record_iter(tp) { assert_eq!(db.get_page::<DataPage>(rid.page()).part, db.partition_of(tp, data)); }
+  }
+  assert_eq!(err(&mut e, "update ev set ts = '2021-06-01' where id = 1;"), "ModifyPartitioned(\"ts\")");
+  assert_eq!(err(&mut e, "alter table ev add w int;"), "ModifyPartitioned(\"ev\")");
+  assert_eq!(err(&mut e, "alter table ev drop partition 3;"), "NoSuchPartition { table: \"ev\", part: 3 }");
+  // the null one is in partition 0
+  assert_eq!(out(&mut e, "alter table ev drop partition 0;"), "2 column(s) affected");
+  assert_eq!(out(&mut e, "select id from ev;"), "id\n4\n6\n2\n3");
+  e.exec_cached("insert into ev values (7, '2000-01-01', 70);", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select id from ev where ts < '2020-01-01';"), "id\n7");
+
+  let vals = (0..200).map(|i| format!("({}, 'k{}')", i, i % 10)).collect::<Vec<_>>().join(", ");
+  e.exec_cached(&format!("create table h (k int, s char(4)) partition by hash (k) (4); insert into h values {};", vals), |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select s from h where k = 37;"), "s\n\"k7\"");
+  assert_eq!(out(&mut e, "select count(*) from h where k > 100;"), "count(*)\n99");
+  assert!(out(&mut e, "explain select * from h where k = 37;").contains("on 1 of 4 partitions"));
+  assert!(out(&mut e, "explain select * from h where k > 37;").contains("on 4 of 4 partitions (0, 1, 2, 3)"));
+  e.exec_cached("create table h2 like h;", |_| {}).unwrap();
+  assert!(out(&mut e, "desc h2;").contains("  - partition by hash (`k`): 4 partitions\n"));
+
+  assert_eq!(err(&mut e, "create table t (a int) partition by range (a) (5, 3);"), "InvalidPartition { col: \"a\", reason: \"the bounds should be strictly ascending\" }");
+  assert_eq!(err(&mut e, "create table t (a int) partition by hash (a) (0);"), "InvalidPartition { col: \"a\", reason: \"the number of hash partitions should be an integer in [1, MAX_PART]\" }");
+  assert_eq!(err(&mut e, "create table t (a int) partition by hash (b) (2);"), "NoSuchCol(\"b\")");
+  assert_eq!(err(&mut e, "select * from t;"), "NoSuchTable(\"t\")");
+  e.exec(&Stmt::DropDb("partition")).unwrap();
+}
+
+#[test]
+fn wide_row() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("wide_row")).unwrap();
+  e.exec(&Stmt::UseDb("wide_row")).unwrap();
+  let cols = (0..40).map(|i| format!("c{} char(255)", i)).collect::<Vec<_>>().join(", ");
+  e.exec_cached(&format!("create table w (id int, {}, primary key(id));", cols), |_| {}).unwrap();
+  unsafe {
+    let tp = e.db().unwrap().get_tp("w").unwrap().1;
+    assert!(tp.size as usize > MAX_DATA_BYTE);
+    assert_eq!((tp.cap, tp.overflow()), (1, 1));
+  }
+  for i in 0..5 {
+    let vals = (0..40).map(|j| format!("'{}-{}'", i, j)).collect::<Vec<_>>().join(", ");
+    e.exec_cached(&format!("insert into w values ({}, {});", i, vals), |_| {}).unwrap();
+  }
+  // the trailing cols are in the overflow pages
+  assert_eq!(out(&mut e, "select c39 from w where id = 3;"), "c39\n\"3-39\"");
+  e.exec_cached("update w set c38 = 'x' where id = 3; alter table w add z int default 7; delete from w where id < 2;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select c38, c39, z from w where id = 3;"), "c38,c39,z\n\"x\",\"3-39\",7");
+  assert_eq!(out(&mut e, "select count(*) from w;"), "count(*)\n3");
+  // the 2 emptied data pages are released, each with its overflow page
+  assert_eq!(out(&mut e, "optimize table w;"), "4 page(s) released");
+  assert_eq!(out(&mut e, "select id from w where c0 = '4-0';"), "id\n4");
+  e.exec_cached("drop table w;", |_| {}).unwrap();
+  e.exec(&Stmt::DropDb("wide_row")).unwrap();
+}
+
+#[test]
+fn many_cols() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("many_cols")).unwrap();
+  e.exec(&Stmt::UseDb("many_cols")).unwrap();
+  let cols = |range: std::ops::Range<usize>, f: &dyn Fn(usize) -> String| range.map(f).collect::<Vec<_>>().join(", ");
+  e.exec_cached(&format!("create table m ({}, primary key(c0)); create table r (x int, foreign key(x) references m(c0));", cols(0..MAX_INLINE_COL, &|i| format!("c{} int", i))), |_| {}).unwrap();
+  e.exec_cached(&format!("insert into m values ({}); insert into r values (0);", cols(0..MAX_INLINE_COL, &|i| i.to_string())), |_| {}).unwrap();
+  let tp_id = unsafe { e.db().unwrap().get_tp("m").unwrap().0 };
+  // the new col doesn't fit in the table page, so it is moved to a place with a col page after it
+  e.exec_cached("alter table m add c127 int default 127;", |_| {}).unwrap();
+  unsafe {
+    let (tp_id1, tp) = e.db().unwrap().get_tp("m").unwrap();
+    assert_ne!(tp_id1, tp_id);
+    assert_eq!(tp.col_pages(), 1);
+  }
+  assert_eq!(out(&mut e, "select c126, c127 from m where c0 = 0;"), "c126,c127\n126,127");
+  // the foreign key of r refers to the moved table page
+  assert!(err(&mut e, "insert into r values (1);").starts_with("PutNonexistentForeign"));
+  e.exec_cached(&(128..MAX_COL).map(|i| format!("alter table m add c{} int;", i)).collect::<String>(), |_| {}).unwrap();
+  assert_eq!(err(&mut e, "alter table m add c255 int;"), format!("ColTooMany({})", MAX_COL + 1));
+  e.exec_cached("update m set c254 = 254; analyze m;", |_| {}).unwrap();
+  assert_eq!(out(&mut e, "select c0, c127, c254 from m where c254 = 254;"), "c0,c127,c254\n0,127,254");
+  unsafe {
+    let db = e.db().unwrap();
+    let tp = db.get_tp("m").unwrap().1;
+    let sp = db.stats(tp).unwrap();
+    assert_eq!(sp.col_num, MAX_COL as u32);
+    assert_eq!((sp.cols.get_unchecked(MAX_COL - 1).nulls, sp.cols.get_unchecked(MAX_COL - 1).distinct), (0, 1));
+  }
+  e.exec_cached(&(MAX_INLINE_COL..MAX_COL).map(|i| format!("alter table m drop c{};", i)).collect::<String>(), |_| {}).unwrap();
+  unsafe { assert_eq!(e.db().unwrap().get_tp("m").unwrap().1.col_pages(), 0); }
+  assert_eq!(out(&mut e, "select c126 from m;"), "c126\n126");
+  assert_eq!(err(&mut e, &format!("create table w ({});", cols(0..MAX_COL + 1, &|i| format!("c{} int", i)))), format!("ColTooMany({})", MAX_COL + 1));
+  e.exec(&Stmt::DropDb("many_cols")).unwrap();
+}
+
+#[test]
+fn many_tables() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("many_tables")).unwrap();
+  e.exec(&Stmt::UseDb("many_tables")).unwrap();
+  // the last table is the only one in the second `DirPage`
+  let n = MAX_TABLE + DIR_TABLES + 1;
+  e.exec_cached(&(0..n).map(|i| format!("create table t{} (a int);", i)).collect::<String>(), |_| {}).unwrap();
+  let dirs = |e: &mut Eval| unsafe {
+    let db = e.db().unwrap();
+    let (mut page, mut k) = (db.dp().dir, 0);
+    while page != !0 { (page = db.get_page::<DirPage>(page).next, k += 1); }
+    k
+  };
+  assert_eq!((unsafe { e.db().unwrap().tables().count() }, dirs(&mut e)), (n, 2));
+  e.exec_cached(&format!("insert into t{0} values (1); insert into t{1} values (2);", n - 1, MAX_TABLE + 5), |_| {}).unwrap();
+  assert_eq!(out(&mut e, &format!("select a from t{};", MAX_TABLE + 5)), "a\n2");
+  // t{n - 1} takes the place of t0
+  e.exec_cached("drop table t0;", |_| {}).unwrap();
+  assert_eq!((unsafe { e.db().unwrap().tables().count() }, dirs(&mut e)), (n - 1, 1));
+  assert_eq!(out(&mut e, &format!("select a from t{};", n - 1)), "a\n1");
+  e.exec_cached(&format!("create table t0 (a int); drop table t{};", MAX_TABLE + 5), |_| {}).unwrap();
+  assert_eq!(dirs(&mut e), 1);
+  assert!(e.exec_cached(&format!("select * from t{};", MAX_TABLE + 5), |_| {}).is_err());
+  e.exec(&Stmt::DropDb("many_tables")).unwrap();
+}
+
+#[test]
+fn long_names() {
+  let mut e = Eval::default();
+  e.exec(&Stmt::CreateDb("long_names")).unwrap();
+  e.exec(&Stmt::UseDb("long_names")).unwrap();
+  let (table, a, b, c) = ("customer_order_line_item_history", "quantity_of_items_shipped_to_customer", "short", "total_price_after_discounts_and_taxes");
+  e.exec_cached(&format!("create table {} ({} int, {} int, x int); insert into {0} values (1, 2, 3);", table, a, b), |_| {}).unwrap();
+  unsafe { assert_eq!(e.db().unwrap().get_tp(table).unwrap().1.names as usize, table.len() + a.len()); }
+  assert_eq!(out(&mut e, &format!("select {}, {} from {};", a, b, table)), format!("{},{}\n1,2", a, b));
+  e.exec_cached(&format!("alter table {} drop x; alter table {0} add {} int default 4;", table, c), |_| {}).unwrap();
+  assert_eq!(out(&mut e, &format!("select * from {};", table)), format!("{},{},{}\n1,2,4", a, b, c));
+  // the names after the dropped col are still found
+  e.exec_cached(&format!("alter table {} drop {};", table, a), |_| {}).unwrap();
+  assert_eq!(out(&mut e, &format!("select {}, {} from {};", b, c, table)), format!("{},{}\n2,4", b, c));
+  let long = "n".repeat(MAX_NAME);
+  e.exec_cached(&format!("alter table {} rename to {};", table, long), |_| {}).unwrap();
+  assert_eq!(out(&mut e, &format!("select {} from {};", c, long)), format!("{}\n4", c));
+  let too_long = "n".repeat(MAX_NAME + 1);
+  assert_eq!(err(&mut e, &format!("alter table {} rename to {};", long, too_long)), format!("TableNameTooLong({:?})", too_long));
+  assert_eq!(err(&mut e, &format!("alter table {} add {} int;", long, too_long)), format!("ColNameTooLong({:?})", too_long));
+  assert_eq!(err(&mut e, &format!("create table {} (a int);", too_long)), format!("TableNameTooLong({:?})", too_long));
+  e.exec(&Stmt::DropDb("long_names")).unwrap();
+}